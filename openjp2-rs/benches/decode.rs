@@ -0,0 +1,122 @@
+//! Decoding throughput on the conformance files of the non-regression test
+//! suite.
+//!
+//! The files decoded by the `opj_decompress` commands of
+//! `tests/nonregression/test_suite.ctest.in` are read from
+//! `$OPJ_DATA_ROOT/input/conformance` and decoded in full with the safe
+//! `Jp2Codec` API, each `OPJ_BENCH_ITERATIONS` times (5 by default). The
+//! benchmark is skipped when `OPJ_DATA_ROOT` isn't set.
+//!
+//! ```text
+//! OPJ_DATA_ROOT=/path/to/openjpeg-data cargo bench --bench decode
+//! ```
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use openjp2::safe::{decoder_format_from_extension, Jp2Codec};
+
+const DEFAULT_ITERATIONS: u32 = 5;
+
+/// The commands of the test suite, split on whitespace. Comments and the
+/// commands expected to fail (starting with `!`) are skipped.
+fn parse_test_commands(path: &Path) -> std::io::Result<Vec<Vec<String>>> {
+  let suite = std::fs::read_to_string(path)?;
+  Ok(
+    suite
+      .lines()
+      .map(str::trim)
+      .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+      .map(|line| line.split_whitespace().map(String::from).collect())
+      .collect(),
+  )
+}
+
+fn get_input_conformance_dir(data_root: &Path) -> PathBuf {
+  data_root.join("input").join("conformance")
+}
+
+/// Names of the conformance files decoded by the test suite, sorted.
+fn conformance_inputs(commands: &[Vec<String>]) -> BTreeSet<String> {
+  commands
+    .iter()
+    .filter(|command| command.first().map(String::as_str) == Some("opj_decompress"))
+    .filter_map(|command| {
+      let input = command.iter().skip_while(|arg| *arg != "-i").nth(1)?;
+      input.strip_prefix("@INPUT_CONF_PATH@/").map(String::from)
+    })
+    .collect()
+}
+
+/// Decode `data`, returns the number of pixels of the decoded image.
+fn decode(path: &Path, data: Vec<u8>) -> Option<u64> {
+  let format = decoder_format_from_extension(path)?;
+  let image = Jp2Codec::decoder(format, data)?
+    .read_header()?
+    .decode()?
+    .into_image();
+  Some((image.x1 - image.x0) as u64 * (image.y1 - image.y0) as u64)
+}
+
+fn main() {
+  let data_root = match std::env::var_os("OPJ_DATA_ROOT") {
+    Some(data_root) => PathBuf::from(data_root),
+    None => {
+      eprintln!("OPJ_DATA_ROOT is not set, skipping the decoding benchmark");
+      return;
+    }
+  };
+  let iterations = std::env::var("OPJ_BENCH_ITERATIONS")
+    .ok()
+    .and_then(|n| n.parse().ok())
+    .filter(|n| *n > 0)
+    .unwrap_or(DEFAULT_ITERATIONS);
+  let suite =
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/nonregression/test_suite.ctest.in");
+  let commands = parse_test_commands(&suite).expect("readable test suite");
+  let conformance_dir = get_input_conformance_dir(&data_root);
+
+  println!(
+    "{:<16} {:>10} {:>10} {:>10} {:>10}",
+    "file", "bytes", "ms/decode", "MB/s", "MPixels/s"
+  );
+  for name in conformance_inputs(&commands) {
+    let path = conformance_dir.join(&name);
+    let data = match std::fs::read(&path) {
+      Ok(data) => data,
+      Err(_) => {
+        eprintln!("{}: missing, skipped", path.display());
+        continue;
+      }
+    };
+    let mut pixels = 0;
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..iterations {
+      let data = data.clone();
+      let start = Instant::now();
+      match decode(&path, data) {
+        Some(decoded) => pixels = decoded,
+        None => {
+          pixels = 0;
+          break;
+        }
+      }
+      elapsed += start.elapsed();
+    }
+    if pixels == 0 {
+      eprintln!("{}: decoding failed, skipped", path.display());
+      continue;
+    }
+    let secs = elapsed.as_secs_f64();
+    let total = iterations as f64;
+    println!(
+      "{:<16} {:>10} {:>10.2} {:>10.2} {:>10.2}",
+      name,
+      data.len(),
+      secs * 1000.0 / total,
+      data.len() as f64 * total / secs / 1e6,
+      pixels as f64 * total / secs / 1e6,
+    );
+  }
+}
@@ -0,0 +1,114 @@
+//! Decoding many small images with a new codec each, and with one codec
+//! reset between them by `opj_decoder_reset_for_next`.
+//!
+//! A 256x256 RGB image is encoded once and decoded `OPJ_BENCH_ITERATIONS`
+//! times (1000 by default) each way, e.g. like the tiles of a pyramid:
+//!
+//! ```text
+//! cargo bench --bench decoder_reuse
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::time::{Duration, Instant};
+
+use openjp2::image::opj_image;
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+const DEFAULT_ITERATIONS: u32 = 1000;
+const SIZE: u32 = 256;
+
+/// An RGB image with a different gradient in each component.
+fn rgb_image() -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = SIZE;
+  image.y1 = SIZE;
+  image.color_space = OPJ_CLRSPC_SRGB;
+  assert!(image.alloc_comps(3, true));
+  for (compno, comp) in image.comps_mut().unwrap().iter_mut().enumerate() {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = SIZE;
+    comp.h = SIZE;
+    comp.prec = 8;
+    assert!(comp.alloc_data());
+    for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+      *v = ((i * (compno + 1)) % 256) as i32;
+    }
+  }
+  image
+}
+
+fn new_decoder() -> *mut opj_codec_t {
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  unsafe {
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+  }
+  codec
+}
+
+/// Decode the file `path` with `codec`, reading the header with
+/// `opj_read_header` if `first` and `opj_decoder_reset_for_next` otherwise.
+fn decode_with(codec: *mut opj_codec_t, path: &CStr, first: bool) {
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    let stream = opj_stream_create_default_file_stream(path.as_ptr(), 1);
+    if first {
+      assert!(opj_read_header(stream, codec, &mut image) != 0);
+    } else {
+      assert!(opj_decoder_reset_for_next(codec, stream, &mut image) != 0);
+    }
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+}
+
+fn main() {
+  let iterations = std::env::var("OPJ_BENCH_ITERATIONS")
+    .ok()
+    .and_then(|n| n.parse().ok())
+    .filter(|n| *n > 0)
+    .unwrap_or(DEFAULT_ITERATIONS);
+
+  let mut params = opj_cparameters_t::default();
+  let data = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, rgb_image())
+    .and_then(|codec| codec.encode())
+    .expect("encoded image")
+    .into_bytes();
+  let path = std::env::temp_dir().join(format!("openjp2-bench-reuse-{}.j2k", std::process::id()));
+  std::fs::write(&path, &data).expect("written codestream");
+  let c_path = CString::new(path.to_str().expect("UTF-8 path")).unwrap();
+
+  let start = Instant::now();
+  for _ in 0..iterations {
+    let codec = new_decoder();
+    decode_with(codec, &c_path, true);
+    unsafe {
+      opj_destroy_codec(codec);
+    }
+  }
+  let fresh = start.elapsed();
+
+  let codec = new_decoder();
+  let start = Instant::now();
+  for i in 0..iterations {
+    decode_with(codec, &c_path, i == 0);
+  }
+  let reused = start.elapsed();
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  std::fs::remove_file(&path).unwrap();
+
+  let per_image = |elapsed: Duration| elapsed.as_secs_f64() * 1000.0 / iterations as f64;
+  println!("{}x{} RGB, {} bytes", SIZE, SIZE, data.len());
+  println!(
+    "{:.3} ms/image with new codecs, {:.3} ms/image with a reset codec ({:.2}x)",
+    per_image(fresh),
+    per_image(reused),
+    fresh.as_secs_f64() / reused.as_secs_f64()
+  );
+}
@@ -0,0 +1,78 @@
+//! Decoding throughput of a large lossless (5-3 wavelet) image.
+//!
+//! A 2048x2048 greyscale image with a lot of texture is encoded once with
+//! the reversible transform and decoded `OPJ_BENCH_ITERATIONS` times (5 by
+//! default). Most of the time is spent in the MQ decoder, which makes this
+//! benchmark the one to run when changing `mqc.rs` or the T1 passes:
+//!
+//! ```text
+//! cargo bench --bench lossless
+//! ```
+
+use std::time::{Duration, Instant};
+
+use openjp2::image::opj_image;
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+const DEFAULT_ITERATIONS: u32 = 5;
+const SIZE: u32 = 2048;
+
+/// A gradient with pseudo-random noise in the low bits, so most bit-planes
+/// of the code-blocks are coded.
+fn noisy_image() -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = SIZE;
+  image.y1 = SIZE;
+  image.color_space = OPJ_CLRSPC_GRAY;
+  assert!(image.alloc_comps(1, true));
+  let comp = &mut image.comps_mut().unwrap()[0];
+  comp.dx = 1;
+  comp.dy = 1;
+  comp.w = SIZE;
+  comp.h = SIZE;
+  comp.prec = 8;
+  assert!(comp.alloc_data());
+  let mut seed = 0x2545_f491u32;
+  for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+    seed ^= seed << 13;
+    seed ^= seed >> 17;
+    seed ^= seed << 5;
+    let (x, y) = (i as u32 % SIZE, i as u32 / SIZE);
+    *v = (((x + y) / 16 + seed % 64) % 256) as i32;
+  }
+  image
+}
+
+fn main() {
+  let iterations = std::env::var("OPJ_BENCH_ITERATIONS")
+    .ok()
+    .and_then(|n| n.parse().ok())
+    .filter(|n| *n > 0)
+    .unwrap_or(DEFAULT_ITERATIONS);
+
+  let mut params = opj_cparameters_t::default();
+  let data = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, noisy_image())
+    .and_then(|codec| codec.encode())
+    .expect("encoded image")
+    .into_bytes();
+
+  let mut elapsed = Duration::ZERO;
+  for _ in 0..iterations {
+    let data = data.clone();
+    let start = Instant::now();
+    Jp2Codec::decoder(OPJ_CODEC_J2K, data)
+      .and_then(|codec| codec.read_header())
+      .and_then(|codec| codec.decode())
+      .expect("decoded image");
+    elapsed += start.elapsed();
+  }
+  let secs = elapsed.as_secs_f64();
+  let total = iterations as f64;
+  println!("{}x{} grey, {} bytes", SIZE, SIZE, data.len());
+  println!(
+    "{:.2} ms/decode, {:.2} MPixels/s",
+    secs * 1000.0 / total,
+    (SIZE * SIZE) as f64 * total / secs / 1e6,
+  );
+}
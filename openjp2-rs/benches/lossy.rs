@@ -0,0 +1,87 @@
+//! Decoding throughput of a large lossy (9-7 wavelet) image.
+//!
+//! A 4096x4096 RGB image is encoded once with the irreversible transform
+//! and decoded `OPJ_BENCH_ITERATIONS` times (5 by default). Compare the
+//! timings without and with the `simd` feature to measure the AVX version
+//! of the inverse 9-7 transform:
+//!
+//! ```text
+//! cargo bench --bench lossy
+//! cargo bench --bench lossy --features simd
+//! ```
+
+use std::time::{Duration, Instant};
+
+use openjp2::image::opj_image;
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+const DEFAULT_ITERATIONS: u32 = 5;
+const SIZE: u32 = 4096;
+
+/// A smooth RGB gradient with some texture, so the image compresses like
+/// a photograph rather than noise.
+fn photo_image() -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = SIZE;
+  image.y1 = SIZE;
+  image.color_space = OPJ_CLRSPC_SRGB;
+  assert!(image.alloc_comps(3, true));
+  for (compno, comp) in image.comps_mut().unwrap().iter_mut().enumerate() {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = SIZE;
+    comp.h = SIZE;
+    comp.prec = 8;
+    assert!(comp.alloc_data());
+    for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+      let (x, y) = (i as u32 % SIZE, i as u32 / SIZE);
+      let texture = (x * 7 + y * 13 + compno as u32 * 29) % 32;
+      *v = ((x + y) / 32 + texture) as i32 % 256;
+    }
+  }
+  image
+}
+
+fn main() {
+  let iterations = std::env::var("OPJ_BENCH_ITERATIONS")
+    .ok()
+    .and_then(|n| n.parse().ok())
+    .filter(|n| *n > 0)
+    .unwrap_or(DEFAULT_ITERATIONS);
+
+  let mut params = opj_cparameters_t::default();
+  params.irreversible = 1;
+  params.tcp_numlayers = 1;
+  params.tcp_rates[0] = 20.0;
+  params.cp_disto_alloc = 1;
+  let data = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, photo_image())
+    .and_then(|codec| codec.encode())
+    .expect("encoded image")
+    .into_bytes();
+
+  let mut elapsed = Duration::ZERO;
+  for _ in 0..iterations {
+    let data = data.clone();
+    let start = Instant::now();
+    Jp2Codec::decoder(OPJ_CODEC_J2K, data)
+      .and_then(|codec| codec.read_header())
+      .and_then(|codec| codec.decode())
+      .expect("decoded image");
+    elapsed += start.elapsed();
+  }
+  let secs = elapsed.as_secs_f64();
+  let total = iterations as f64;
+  println!(
+    "{}x{} RGB, {} bytes, simd: {}",
+    SIZE,
+    SIZE,
+    data.len(),
+    cfg!(feature = "simd")
+  );
+  println!(
+    "{:.2} ms/decode, {:.2} MPixels/s",
+    secs * 1000.0 / total,
+    (SIZE * SIZE) as f64 * total / secs / 1e6,
+  );
+}
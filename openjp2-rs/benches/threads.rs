@@ -0,0 +1,100 @@
+//! Serial and threaded decoding time of a large single tile image.
+//!
+//! A 2048x2048 greyscale image is encoded once as a single tile and decoded
+//! `OPJ_BENCH_ITERATIONS` times (5 by default) with one thread, then with
+//! one thread per CPU, see `opj_codec_set_threads`. Only the time spent in
+//! `opj_decode` is measured:
+//!
+//! ```text
+//! cargo bench --features threads --bench threads
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::time::{Duration, Instant};
+
+use openjp2::image::opj_image;
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+const DEFAULT_ITERATIONS: u32 = 5;
+const SIZE: u32 = 2048;
+
+/// A gradient with pseudo-random noise in the low bits.
+fn noisy_image() -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = SIZE;
+  image.y1 = SIZE;
+  image.color_space = OPJ_CLRSPC_GRAY;
+  assert!(image.alloc_comps(1, true));
+  let comp = &mut image.comps_mut().unwrap()[0];
+  comp.dx = 1;
+  comp.dy = 1;
+  comp.w = SIZE;
+  comp.h = SIZE;
+  comp.prec = 8;
+  assert!(comp.alloc_data());
+  let mut seed = 0x2545_f491u32;
+  for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+    seed ^= seed << 13;
+    seed ^= seed >> 17;
+    seed ^= seed << 5;
+    let (x, y) = (i as u32 % SIZE, i as u32 / SIZE);
+    *v = (((x + y) / 16 + seed % 64) % 256) as i32;
+  }
+  image
+}
+
+/// Time spent in `opj_decode` by `iterations` decodings of the file `path`
+/// with `num_threads` threads.
+fn decode(path: &CStr, num_threads: i32, iterations: u32) -> Duration {
+  let mut elapsed = Duration::ZERO;
+  for _ in 0..iterations {
+    let mut params = opj_dparameters_t::default();
+    let codec = opj_create_decompress(OPJ_CODEC_J2K);
+    let mut image = std::ptr::null_mut() as *mut opj_image_t;
+    unsafe {
+      let stream = opj_stream_create_default_file_stream(path.as_ptr(), 1);
+      assert!(opj_setup_decoder(codec, &mut params) != 0);
+      assert!(opj_codec_set_threads(codec, num_threads) != 0);
+      assert!(opj_read_header(stream, codec, &mut image) != 0);
+      let start = Instant::now();
+      assert!(opj_decode(codec, stream, image) != 0);
+      elapsed += start.elapsed();
+      assert!(opj_end_decompress(codec, stream) != 0);
+      opj_destroy_codec(codec);
+      opj_stream_destroy(stream);
+    }
+    opj_image_destroy(image);
+  }
+  elapsed
+}
+
+fn main() {
+  let iterations = std::env::var("OPJ_BENCH_ITERATIONS")
+    .ok()
+    .and_then(|n| n.parse().ok())
+    .filter(|n| *n > 0)
+    .unwrap_or(DEFAULT_ITERATIONS);
+
+  let mut params = opj_cparameters_t::default();
+  let data = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, noisy_image())
+    .and_then(|codec| codec.encode())
+    .expect("encoded image")
+    .into_bytes();
+
+  let path = std::env::temp_dir().join(format!("openjp2-bench-threads-{}.j2k", std::process::id()));
+  std::fs::write(&path, &data).expect("written codestream");
+  let c_path = CString::new(path.to_str().expect("UTF-8 path")).unwrap();
+
+  println!("{}x{} grey, {} bytes", SIZE, SIZE, data.len());
+  let num_cpus = opj_get_num_cpus();
+  for num_threads in [1, num_cpus] {
+    let secs = decode(&c_path, num_threads, iterations).as_secs_f64();
+    println!(
+      "{} thread(s): {:.2} ms/decode",
+      num_threads,
+      secs * 1000.0 / iterations as f64,
+    );
+  }
+  std::fs::remove_file(&path).unwrap();
+}
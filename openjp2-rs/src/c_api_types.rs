@@ -107,7 +107,7 @@ pub enum CODEC_FORMAT {
   OPJ_CODEC_UNKNOWN = -1,
   ///< JPEG-2000 codestream : read/write
   OPJ_CODEC_J2K = 0,
-  ///< JPT-stream (JPEG 2000, JPIP) : read only
+  ///< JPT-stream (JPEG 2000, JPIP) : read only, tile data-bins only
   OPJ_CODEC_JPT = 1,
   ///< JP2 file format : read/write
   OPJ_CODEC_JP2 = 2,
@@ -318,6 +318,19 @@ impl opj_cparameters_t {
     *self = Default::default()
   }
 
+  /// Set the origin of the tile grid on the reference grid.
+  ///
+  /// The origin must not be past the image origin and the first tile must
+  /// intersect the image, this is checked by `opj_setup_encoder`.
+  pub fn set_tile_origin(&mut self, tx0: u32, ty0: u32) -> bool {
+    if tx0 > i32::MAX as u32 || ty0 > i32::MAX as u32 {
+      return false;
+    }
+    self.cp_tx0 = tx0 as core::ffi::c_int;
+    self.cp_ty0 = ty0 as core::ffi::c_int;
+    true
+  }
+
   pub fn set_MCT(
     &mut self,
     mut encoding_matrix: &[f32],
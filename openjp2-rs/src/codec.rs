@@ -32,12 +32,16 @@
  */
 use super::j2k::*;
 use super::jp2::*;
+use super::jpt::*;
+use super::layout::*;
+use super::math::*;
 
 use super::c_api_types::*;
 use super::consts::*;
 use super::types::*;
 
 use super::event::*;
+use super::image::UpsampleFilter;
 
 #[cfg(feature = "file-io")]
 use ::libc::FILE;
@@ -56,6 +60,17 @@ pub(crate) enum CodecType {
 pub(crate) struct Codec {
   pub m_codec: CodecType,
   pub m_event_mgr: opj_event_mgr,
+  /// The input is a JPT-stream that needs to be converted to a codestream.
+  pub m_is_jpt: bool,
+  /// Upsample subsampled components of the decoded images with this filter.
+  pub m_chroma_upsampling: Option<UpsampleFilter>,
+  /// Upsample all the components of the decoded images to `dx = dy = 1`.
+  pub m_upsample: bool,
+  /// Convert the decoded images to sRGB.
+  pub m_force_rgb: bool,
+  /// Warn about decoded components whose samples are all the minimum or
+  /// all the maximum value.
+  pub m_saturation_warning: bool,
 }
 pub(crate) type opj_codec_private_t = Codec;
 
@@ -74,12 +89,19 @@ impl Codec {
     Some(Codec {
       m_codec,
       m_event_mgr: Default::default(),
+      m_is_jpt: false,
+      m_chroma_upsampling: None,
+      m_upsample: false,
+      m_force_rgb: false,
+      m_saturation_warning: false,
     })
   }
 
   pub fn new_decoder(format: CODEC_FORMAT) -> Option<Self> {
     let m_codec = match format {
-      OPJ_CODEC_J2K => CodecType::Decoder(CodecFormat::J2K(opj_j2k_create_decompress()?)),
+      OPJ_CODEC_J2K | OPJ_CODEC_JPT => {
+        CodecType::Decoder(CodecFormat::J2K(opj_j2k_create_decompress()?))
+      }
       OPJ_CODEC_JP2 => {
         /* get a JP2 decoder handle */
         CodecType::Decoder(CodecFormat::JP2(opj_jp2_create(1i32)?))
@@ -91,9 +113,20 @@ impl Codec {
     Some(Codec {
       m_codec,
       m_event_mgr: Default::default(),
+      m_is_jpt: format == OPJ_CODEC_JPT,
+      m_chroma_upsampling: None,
+      m_upsample: false,
+      m_force_rgb: false,
+      m_saturation_warning: false,
     })
   }
 
+  /// Reasons why decoding `data` may fail with this build, see
+  /// `opj_explain_unsupported`.
+  pub fn explain_unsupported(data: &[u8]) -> Vec<String> {
+    super::validate::opj_explain_unsupported(data)
+  }
+
   pub fn set_info_handler(
     &mut self,
     mut p_callback: opj_msg_callback,
@@ -123,6 +156,10 @@ impl Codec {
     1i32
   }
 
+  pub fn use_log_crate(&mut self) {
+    self.m_event_mgr.set_log_handlers();
+  }
+
   pub fn set_threads(&mut self, mut num_threads: core::ffi::c_int) -> OPJ_BOOL {
     if num_threads >= 0i32 {
       match &mut self.m_codec {
@@ -161,6 +198,74 @@ impl Codec {
     }
   }
 
+  pub fn comments(&self) -> Vec<Comment> {
+    match &self.m_codec {
+      CodecType::Encoder(CodecFormat::J2K(j2k)) | CodecType::Decoder(CodecFormat::J2K(j2k)) => {
+        opj_j2k_get_comments(j2k)
+      }
+      CodecType::Encoder(CodecFormat::JP2(jp2)) | CodecType::Decoder(CodecFormat::JP2(jp2)) => {
+        opj_jp2_get_comments(jp2)
+      }
+    }
+  }
+
+  pub fn requires_features(&self) -> FeatureSet {
+    match &self.m_codec {
+      CodecType::Encoder(CodecFormat::J2K(j2k)) | CodecType::Decoder(CodecFormat::J2K(j2k)) => {
+        opj_j2k_get_required_features(j2k)
+      }
+      CodecType::Encoder(CodecFormat::JP2(jp2)) | CodecType::Decoder(CodecFormat::JP2(jp2)) => {
+        opj_jp2_get_required_features(jp2)
+      }
+    }
+  }
+
+  pub fn capabilities(&self) -> Capabilities {
+    match &self.m_codec {
+      CodecType::Encoder(CodecFormat::J2K(j2k)) | CodecType::Decoder(CodecFormat::J2K(j2k)) => {
+        opj_j2k_get_capabilities(j2k)
+      }
+      CodecType::Encoder(CodecFormat::JP2(jp2)) | CodecType::Decoder(CodecFormat::JP2(jp2)) => {
+        opj_jp2_get_capabilities(jp2)
+      }
+    }
+  }
+
+  pub fn color_spec(&self) -> Jp2ColorSpec {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::JP2(jp2)) => opj_jp2_get_color_spec(jp2),
+      _ => Jp2ColorSpec::Unspecified,
+    }
+  }
+
+  pub fn ipr(&self) -> Option<&[u8]> {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::JP2(jp2)) => opj_jp2_get_ipr(jp2),
+      _ => None,
+    }
+  }
+
+  pub fn geo_metadata(&self) -> Option<&[u8]> {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::JP2(jp2)) => opj_jp2_get_geo_metadata(jp2),
+      _ => None,
+    }
+  }
+
+  pub fn associations(&self) -> Vec<Association> {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::JP2(jp2)) => opj_jp2_get_associations(jp2),
+      _ => Vec::new(),
+    }
+  }
+
+  pub fn reader_requirements(&self) -> Option<ReaderRequirements> {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::JP2(jp2)) => opj_jp2_get_reader_requirements(jp2),
+      _ => None,
+    }
+  }
+
   pub fn get_cstr_index(&mut self) -> *mut opj_codestream_index_t {
     match &mut self.m_codec {
       CodecType::Encoder(CodecFormat::J2K(j2k)) | CodecType::Decoder(CodecFormat::J2K(j2k)) => {
@@ -221,6 +326,232 @@ impl Codec {
     }
   }
 
+  pub fn decoder_set_partial(&mut self, partial: bool) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_partial function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_decoder_set_partial(dec, partial);
+        true
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decoder_set_partial(dec, partial);
+        true
+      }
+    }
+  }
+
+  pub fn decoder_set_raw_components(&mut self, raw: bool) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_raw_components function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_decoder_set_raw_components(dec, raw);
+        true
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decoder_set_raw_components(dec, raw);
+        true
+      }
+    }
+  }
+
+  pub fn decoder_set_validate_profile(&mut self, validate: bool) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_validate_profile function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_decoder_set_validate_profile(dec, validate);
+        true
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decoder_set_validate_profile(dec, validate);
+        true
+      }
+    }
+  }
+
+  pub fn decoder_set_tile_parts_correction(&mut self, enable: bool) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_tile_parts_correction function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_decoder_set_tile_parts_correction(dec, enable);
+        true
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decoder_set_tile_parts_correction(dec, enable);
+        true
+      }
+    }
+  }
+
+  pub fn decoder_set_error_resilience(&mut self, flags: ErrorResilienceFlags) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_error_resilience function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_decoder_set_error_resilience(dec, flags);
+        true
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decoder_set_error_resilience(dec, flags);
+        true
+      }
+    }
+  }
+
+  pub fn decoder_set_limits(&mut self, limits: DecodeLimits) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_limits function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_decoder_set_limits(dec, limits);
+        true
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decoder_set_limits(dec, limits);
+        true
+      }
+    }
+  }
+
+  pub fn decoder_set_dc_shift(&mut self, compno: u32, shift: Option<i32>) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_dc_shift function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_decoder_set_dc_shift(dec, compno, shift, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decoder_set_dc_shift(dec, compno, shift, &mut self.m_event_mgr)
+      }
+    }
+  }
+
+  pub fn dc_shift(&self, compno: u32) -> Option<i32> {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::J2K(j2k)) => opj_j2k_get_dc_shift(j2k, compno),
+      CodecType::Decoder(CodecFormat::JP2(jp2)) => opj_jp2_get_dc_shift(jp2, compno),
+      CodecType::Encoder(_) => None,
+    }
+  }
+
+  pub fn decoder_capture_boxes(&mut self, capture: bool) -> bool {
+    match &mut self.m_codec {
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decoder_capture_boxes(dec, capture);
+        true
+      }
+      _ => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_capture_boxes function is not a JP2 decompressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn boxes(&self) -> Vec<RawBox> {
+    match &self.m_codec {
+      CodecType::Encoder(CodecFormat::JP2(jp2)) | CodecType::Decoder(CodecFormat::JP2(jp2)) => {
+        opj_jp2_get_boxes(jp2)
+      }
+      _ => Vec::new(),
+    }
+  }
+
+  pub fn decoded_region(&self) -> DecodedRegion {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::J2K(j2k)) => opj_j2k_get_decoded_region(j2k),
+      CodecType::Decoder(CodecFormat::JP2(jp2)) => opj_jp2_get_decoded_region(jp2),
+      CodecType::Encoder(_) => DecodedRegion::default(),
+    }
+  }
+
+  /// Number of tiles decoded by the last `decode`.
+  pub fn get_decoded_tile_count(&self) -> u32 {
+    self.decoded_region().tiles.len() as u32
+  }
+
+  /// Status of the tiles read by the last `decode`, in index order.
+  pub fn tile_status(&self) -> Vec<TileStatus> {
+    self.decoded_region().tile_status
+  }
+
+  /// Index of the tile containing the point `(x, y)` of the reference grid,
+  /// once the header was read or the compression started.
+  pub fn tile_at(&self, x: OPJ_UINT32, y: OPJ_UINT32) -> Option<OPJ_UINT32> {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::J2K(j2k)) | CodecType::Encoder(CodecFormat::J2K(j2k)) => {
+        opj_j2k_tile_at(j2k, x, y)
+      }
+      CodecType::Decoder(CodecFormat::JP2(jp2)) | CodecType::Encoder(CodecFormat::JP2(jp2)) => {
+        opj_jp2_tile_at(jp2, x, y)
+      }
+    }
+  }
+
+  /// Area `(x0, y0, x1, y1)` of a tile on the reference grid, clamped to the
+  /// image for the tiles at the right and bottom edges.
+  pub fn tile_bounds(
+    &self,
+    tile_index: OPJ_UINT32,
+  ) -> Option<(OPJ_UINT32, OPJ_UINT32, OPJ_UINT32, OPJ_UINT32)> {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::J2K(j2k)) | CodecType::Encoder(CodecFormat::J2K(j2k)) => {
+        opj_j2k_tile_bounds(j2k, tile_index)
+      }
+      CodecType::Decoder(CodecFormat::JP2(jp2)) | CodecType::Encoder(CodecFormat::JP2(jp2)) => {
+        opj_jp2_tile_bounds(jp2, tile_index)
+      }
+    }
+  }
+
   pub fn read_header(
     &mut self,
     mut p_stream: &mut Stream,
@@ -235,6 +566,15 @@ impl Codec {
         );
       }
       CodecType::Decoder(dec) => {
+        if self.m_is_jpt {
+          /* Replace the JPT-stream with the reconstructed codestream */
+          let data = p_stream.read_remaining();
+          match opj_jpt_to_codestream(&data, &mut self.m_event_mgr) {
+            Some(codestream) => p_stream.replace_input(codestream),
+            None => return 0,
+          }
+          self.m_is_jpt = false;
+        }
         return match dec {
           CodecFormat::J2K(dec) => {
             opj_j2k_read_header(p_stream, dec, p_image, &mut self.m_event_mgr)
@@ -248,6 +588,36 @@ impl Codec {
     0
   }
 
+  /// Reset the decoder and read the header of the next codestream, see
+  /// `opj_decoder_reset_for_next`.
+  pub fn reset_for_next(
+    &mut self,
+    p_stream: &mut Stream,
+    p_image: *mut *mut opj_image_t,
+  ) -> OPJ_BOOL {
+    let reset = match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_reset_for_next function is not a decompressor handler.\n",
+        );
+        return 0;
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => opj_j2k_reset_decoder(dec),
+      CodecType::Decoder(CodecFormat::JP2(dec)) => opj_jp2_reset_decoder(dec),
+    };
+    if !reset {
+      event_msg!(
+        &mut self.m_event_mgr,
+        EVT_ERROR,
+        "Not enough memory to reset the decoder\n",
+      );
+      return 0;
+    }
+    self.read_header(p_stream, p_image)
+  }
+
   pub fn set_decoded_components(
     &mut self,
     mut components: &[u32],
@@ -286,77 +656,206 @@ impl Codec {
     match &mut self.m_codec {
       CodecType::Encoder(_) => (),
       CodecType::Decoder(dec) => {
-        return match dec {
+        let ret = match dec {
           CodecFormat::J2K(dec) => opj_j2k_decode(dec, p_stream, p_image, &mut self.m_event_mgr),
           CodecFormat::JP2(dec) => opj_jp2_decode(dec, p_stream, p_image, &mut self.m_event_mgr),
         };
+        if ret != 0 {
+          self.postprocess_image(p_image);
+        }
+        return ret;
       }
     }
     0i32
   }
 
-  pub fn end_decompress(&mut self, mut p_stream: &mut Stream) -> OPJ_BOOL {
+  pub fn set_chroma_upsampling(&mut self, filter: Option<UpsampleFilter>) -> bool {
     match &mut self.m_codec {
-      CodecType::Encoder(_) => (),
-      CodecType::Decoder(dec) => {
-        return match dec {
-          CodecFormat::J2K(dec) => opj_j2k_end_decompress(dec, p_stream, &mut self.m_event_mgr),
-          CodecFormat::JP2(dec) => opj_jp2_end_decompress(dec, p_stream, &mut self.m_event_mgr),
-        };
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_chroma_upsampling function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(_) => {
+        self.m_chroma_upsampling = filter;
+        true
       }
     }
-    0
   }
 
-  pub fn set_decode_area(
-    &mut self,
-    mut p_image: &mut opj_image,
-    mut p_start_x: OPJ_INT32,
-    mut p_start_y: OPJ_INT32,
-    mut p_end_x: OPJ_INT32,
-    mut p_end_y: OPJ_INT32,
-  ) -> OPJ_BOOL {
+  pub fn set_upsample(&mut self, upsample: bool) -> bool {
     match &mut self.m_codec {
-      CodecType::Encoder(_) => (),
-      CodecType::Decoder(dec) => {
-        return match dec {
-          CodecFormat::J2K(dec) => opj_j2k_set_decode_area(
-            dec,
-            p_image,
-            p_start_x,
-            p_start_y,
-            p_end_x,
-            p_end_y,
-            &mut self.m_event_mgr,
-          ),
-          CodecFormat::JP2(dec) => opj_jp2_set_decode_area(
-            dec,
-            p_image,
-            p_start_x,
-            p_start_y,
-            p_end_x,
-            p_end_y,
-            &mut self.m_event_mgr,
-          ),
-        };
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_upsample function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(_) => {
+        self.m_upsample = upsample;
+        true
       }
     }
-    0i32
   }
 
-  pub fn read_tile_header(&mut self, mut p_stream: &mut Stream, tile_info: &mut TileInfo) -> bool {
+  pub fn set_saturation_warning(&mut self, enable: bool) -> bool {
     match &mut self.m_codec {
-      CodecType::Encoder(_) => (),
-      CodecType::Decoder(dec) => {
-        return match dec {
-          CodecFormat::J2K(dec) => {
-            opj_j2k_read_tile_header(dec, p_stream, tile_info, &mut self.m_event_mgr)
-          }
-          CodecFormat::JP2(dec) => {
-            opj_jp2_read_tile_header(dec, p_stream, tile_info, &mut self.m_event_mgr)
-          }
-        };
-      }
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_saturation_warning function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(_) => {
+        self.m_saturation_warning = enable;
+        true
+      }
+    }
+  }
+
+  pub fn set_force_rgb(&mut self, force_rgb: bool) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_force_rgb function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(_) => {
+        self.m_force_rgb = force_rgb;
+        true
+      }
+    }
+  }
+
+  /// Upsample and convert a decoded image, as enabled. Raw components are
+  /// left as they are.
+  fn postprocess_image(&mut self, p_image: &mut opj_image) {
+    if p_image.raw_components {
+      return;
+    }
+    self.upsample_chroma(p_image);
+    if self.m_force_rgb && !p_image.convert_to_srgb(self.m_chroma_upsampling.unwrap_or_default()) {
+      event_msg!(
+        &mut self.m_event_mgr,
+        EVT_WARNING,
+        "Decoded image can't be converted to sRGB\n",
+      );
+    }
+    if self.m_saturation_warning {
+      self.warn_saturated_components(p_image);
+    }
+  }
+
+  /// Warn about the components of a decoded image that are entirely
+  /// clipped, a symptom of a wrong precision or DC level shift.
+  fn warn_saturated_components(&mut self, p_image: &opj_image) {
+    for (compno, comp) in p_image.comps().unwrap_or_default().iter().enumerate() {
+      if let Some(value) = comp.saturated_value() {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_WARNING,
+          "All the samples of component %d are %d, the image may be decoded with a wrong precision or DC level shift\n",
+          compno,
+          value,
+        );
+      }
+    }
+  }
+
+  /// Upsample the subsampled components of a decoded image, if enabled.
+  fn upsample_chroma(&mut self, p_image: &mut opj_image) {
+    if self.m_upsample {
+      let filter = self.m_chroma_upsampling.unwrap_or_default();
+      if !p_image.upsample_to_full(filter) {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_WARNING,
+          "Components decoded at different resolutions can't be upsampled\n",
+        );
+      }
+    } else if let Some(filter) = self.m_chroma_upsampling {
+      if !p_image.comps_match() && !p_image.upsample_to_match(filter) {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_WARNING,
+          "Components decoded at different resolutions can't be upsampled\n",
+        );
+      }
+    }
+  }
+
+  pub fn end_decompress(&mut self, mut p_stream: &mut Stream) -> OPJ_BOOL {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => (),
+      CodecType::Decoder(dec) => {
+        return match dec {
+          CodecFormat::J2K(dec) => opj_j2k_end_decompress(dec, p_stream, &mut self.m_event_mgr),
+          CodecFormat::JP2(dec) => opj_jp2_end_decompress(dec, p_stream, &mut self.m_event_mgr),
+        };
+      }
+    }
+    0
+  }
+
+  pub fn set_decode_area(
+    &mut self,
+    mut p_image: &mut opj_image,
+    mut p_start_x: OPJ_INT32,
+    mut p_start_y: OPJ_INT32,
+    mut p_end_x: OPJ_INT32,
+    mut p_end_y: OPJ_INT32,
+  ) -> OPJ_BOOL {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => (),
+      CodecType::Decoder(dec) => {
+        return match dec {
+          CodecFormat::J2K(dec) => opj_j2k_set_decode_area(
+            dec,
+            p_image,
+            p_start_x,
+            p_start_y,
+            p_end_x,
+            p_end_y,
+            &mut self.m_event_mgr,
+          ),
+          CodecFormat::JP2(dec) => opj_jp2_set_decode_area(
+            dec,
+            p_image,
+            p_start_x,
+            p_start_y,
+            p_end_x,
+            p_end_y,
+            &mut self.m_event_mgr,
+          ),
+        };
+      }
+    }
+    0i32
+  }
+
+  pub fn read_tile_header(&mut self, mut p_stream: &mut Stream, tile_info: &mut TileInfo) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => (),
+      CodecType::Decoder(dec) => {
+        return match dec {
+          CodecFormat::J2K(dec) => {
+            opj_j2k_read_tile_header(dec, p_stream, tile_info, &mut self.m_event_mgr)
+          }
+          CodecFormat::JP2(dec) => {
+            opj_jp2_read_tile_header(dec, p_stream, tile_info, &mut self.m_event_mgr)
+          }
+        };
+      }
     }
     false
   }
@@ -392,7 +891,7 @@ impl Codec {
     match &mut self.m_codec {
       CodecType::Encoder(_) => (),
       CodecType::Decoder(dec) => {
-        return match dec {
+        let ret = match dec {
           CodecFormat::J2K(dec) => {
             opj_j2k_get_tile(dec, p_stream, p_image, &mut self.m_event_mgr, tile_index)
           }
@@ -400,80 +899,918 @@ impl Codec {
             opj_jp2_get_tile(dec, p_stream, p_image, &mut self.m_event_mgr, tile_index)
           }
         };
+        if ret != 0 {
+          self.postprocess_image(p_image);
+        }
+        return ret;
       }
     }
     0
   }
 
-  pub fn set_decoded_resolution_factor(&mut self, mut res_factor: OPJ_UINT32) -> OPJ_BOOL {
+  pub fn decode_tile(
+    &mut self,
+    p_stream: &mut Stream,
+    tile_index: OPJ_UINT32,
+  ) -> Option<Box<opj_image>> {
     match &mut self.m_codec {
-      CodecType::Encoder(_) => 0,
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decode_tile function is not a decompressor handler.\n",
+        );
+        None
+      }
       CodecType::Decoder(CodecFormat::J2K(dec)) => {
-        opj_j2k_set_decoded_resolution_factor(dec, res_factor, &mut self.m_event_mgr)
+        opj_j2k_decode_tile_image(dec, p_stream, tile_index, &mut self.m_event_mgr)
       }
       CodecType::Decoder(CodecFormat::JP2(dec)) => {
-        opj_jp2_set_decoded_resolution_factor(dec, res_factor, &mut self.m_event_mgr)
+        opj_jp2_decode_tile_image(dec, p_stream, tile_index, &mut self.m_event_mgr)
       }
     }
+    .map(|mut image| {
+      self.postprocess_image(&mut image);
+      image
+    })
   }
-}
 
-// Encoder
-impl Codec {
-  pub fn setup_encoder(
-    &mut self,
-    mut parameters: &mut opj_cparameters_t,
-    mut p_image: &mut opj_image,
-  ) -> OPJ_BOOL {
+  pub fn packet_order(&mut self, tile_index: OPJ_UINT32) -> Option<Vec<PacketCoord>> {
     match &mut self.m_codec {
-      CodecType::Encoder(enc) => {
-        return match enc {
-          CodecFormat::J2K(enc) => {
-            opj_j2k_setup_encoder(enc, parameters, p_image, &mut self.m_event_mgr)
-          }
-          CodecFormat::JP2(enc) => {
-            opj_jp2_setup_encoder(enc, parameters, p_image, &mut self.m_event_mgr)
-          }
-        };
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_get_packet_order function is not a decompressor handler.\n",
+        );
+        None
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_get_packet_order(dec, tile_index, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_get_packet_order(dec, tile_index, &mut self.m_event_mgr)
       }
-      CodecType::Decoder(_) => (),
     }
-    0i32
   }
 
-  pub fn encoder_set_extra_options(&mut self, options: &[&str]) -> bool {
+  pub fn packet_count(&mut self, tile_index: OPJ_UINT32) -> Option<OPJ_UINT32> {
     match &mut self.m_codec {
-      CodecType::Encoder(enc) => match enc {
-        CodecFormat::J2K(enc) => {
-          opj_j2k_encoder_set_extra_options(enc, options, &mut self.m_event_mgr)
-        }
-        CodecFormat::JP2(enc) => {
-          opj_jp2_encoder_set_extra_options(enc, options, &mut self.m_event_mgr)
-        }
-      },
-      CodecType::Decoder(_) => false,
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_get_packet_count function is not a decompressor handler.\n",
+        );
+        None
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_get_packet_count(dec, tile_index, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_get_packet_count(dec, tile_index, &mut self.m_event_mgr)
+      }
     }
   }
 
-  pub fn start_compress(
+  pub fn layer_sizes(&mut self, tile_index: OPJ_UINT32) -> Option<Vec<u64>> {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_get_layer_sizes function is not a decompressor handler.\n",
+        );
+        None
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_get_layer_sizes(dec, tile_index, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_get_layer_sizes(dec, tile_index, &mut self.m_event_mgr)
+      }
+    }
+  }
+
+  pub fn build_packet_index(&mut self, p_stream: &mut Stream) -> Option<PacketIndex> {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_build_packet_index function is not a decompressor handler.\n",
+        );
+        None
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_build_packet_index(dec, p_stream, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_build_packet_index(dec, p_stream, &mut self.m_event_mgr)
+      }
+    }
+  }
+
+  pub fn codestream_byte_ranges(&mut self) -> Option<CodestreamByteRanges> {
+    match &self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_get_codestream_byte_ranges function is not a decompressor handler.\n",
+        );
+        None
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_get_codestream_byte_ranges(dec, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_get_codestream_byte_ranges(dec, &mut self.m_event_mgr)
+      }
+    }
+  }
+
+  pub fn verify_embedded_md5(&self, p_image: &opj_image) -> Option<bool> {
+    match &self.m_codec {
+      CodecType::Decoder(CodecFormat::J2K(dec)) => opj_j2k_verify_embedded_md5(dec, p_image),
+      CodecType::Decoder(CodecFormat::JP2(dec)) => opj_jp2_verify_embedded_md5(dec, p_image),
+      CodecType::Encoder(_) => None,
+    }
+  }
+
+  pub fn decode_precinct(
     &mut self,
-    mut p_image: &mut opj_image,
-    mut p_stream: &mut Stream,
-  ) -> OPJ_BOOL {
+    p_stream: &mut Stream,
+    p_image: &mut opj_image,
+    tile_index: OPJ_UINT32,
+    compno: OPJ_UINT32,
+    resno: OPJ_UINT32,
+    precno: OPJ_UINT32,
+  ) -> Option<PrecinctData> {
+    let precinct = PrecinctIndex {
+      tile_index,
+      compno,
+      resno,
+      precno,
+    };
     match &mut self.m_codec {
-      CodecType::Encoder(enc) => {
-        return match enc {
-          CodecFormat::J2K(enc) => {
-            opj_j2k_start_compress(enc, p_stream, p_image, &mut self.m_event_mgr)
-          }
-          CodecFormat::JP2(enc) => {
-            opj_jp2_start_compress(enc, p_stream, p_image, &mut self.m_event_mgr)
-          }
-        };
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decode_precinct function is not a decompressor handler.\n",
+        );
+        None
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_decode_precinct(dec, p_stream, p_image, precinct, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_decode_precinct(dec, p_stream, p_image, precinct, &mut self.m_event_mgr)
+      }
+    }
+  }
+
+  /// Decode all tiles straight into `out`, see `opj_decode_into`.
+  pub fn decode_into(
+    &mut self,
+    p_stream: &mut Stream,
+    p_image: &opj_image,
+    out: &mut [u8],
+    layout: &PixelLayout,
+  ) -> bool {
+    if let CodecType::Encoder(_) = self.m_codec {
+      event_msg!(
+        &mut self.m_event_mgr,
+        EVT_ERROR,
+        "Codec provided to the opj_decode_into function is not a decompressor handler.\n",
+      );
+      return false;
+    }
+    let (decoded, decode_area) = match &self.m_codec {
+      CodecType::Decoder(CodecFormat::J2K(dec)) => (
+        opj_j2k_decoded_components(dec),
+        opj_j2k_has_decode_area(dec),
+      ),
+      CodecType::Decoder(CodecFormat::JP2(dec)) => (
+        opj_jp2_decoded_components(dec),
+        opj_jp2_has_decode_area(dec),
+      ),
+      CodecType::Encoder(_) => (None, false),
+    };
+    if decode_area {
+      event_msg!(
+        &mut self.m_event_mgr,
+        EVT_ERROR,
+        "opj_decode_into doesn't support decode areas\n",
+      );
+      return false;
+    }
+    let comps = match p_image.comps() {
+      Some(comps) => comps,
+      None => return false,
+    };
+    /* Index in `comps` of each decoded component */
+    let decoded = decoded.unwrap_or_else(|| (0..p_image.numcomps).collect());
+    let channels = layout.channels(decoded.len() as u32);
+    if channels.is_empty() {
+      return false;
+    }
+    let factor = comps[decoded[0] as usize].factor;
+    for &compno in &channels {
+      let comp = match decoded
+        .get(compno as usize)
+        .and_then(|&i| comps.get(i as usize))
+      {
+        Some(comp) => comp,
+        None => {
+          event_msg!(
+            &mut self.m_event_mgr,
+            EVT_ERROR,
+            "Invalid component index: %u\n",
+            compno,
+          );
+          return false;
+        }
+      };
+      if comp.dx != 1 || comp.dy != 1 || comp.factor != factor || !(1..=31).contains(&comp.prec) {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "opj_decode_into doesn't support subsampled components or a precision of %d bits\n",
+          comp.prec,
+        );
+        return false;
+      }
+    }
+    let img_x0 = opj_uint_ceildivpow2(p_image.x0, factor) as usize;
+    let img_y0 = opj_uint_ceildivpow2(p_image.y0, factor) as usize;
+    let width = opj_uint_ceildivpow2(p_image.x1, factor) as usize - img_x0;
+    let height = opj_uint_ceildivpow2(p_image.y1, factor) as usize - img_y0;
+    let nb_channels = channels.len();
+    let (stride, size) =
+      match layout
+        .stride(width, nb_channels)
+        .zip(layout.buffer_size(width, height, nb_channels))
+      {
+        Some(res) => res,
+        None => {
+          event_msg!(
+            &mut self.m_event_mgr,
+            EVT_ERROR,
+            "Invalid row stride for an image width of %d\n",
+            width,
+          );
+          return false;
+        }
+      };
+    if out.len() < size {
+      event_msg!(
+        &mut self.m_event_mgr,
+        EVT_ERROR,
+        "Output buffer too small: %d bytes, need %d bytes\n",
+        out.len(),
+        size,
+      );
+      return false;
+    }
+
+    /* Only a single tile is held in memory, not the whole image */
+    let mut tile_data = Vec::new();
+    loop {
+      let mut tile_info = TileInfo {
+        data_size: Some(0),
+        ..Default::default()
+      };
+      if !self.read_tile_header(p_stream, &mut tile_info) {
+        return false;
+      }
+      if !tile_info.go_on {
+        break;
+      }
+      tile_data.resize(tile_info.data_size.unwrap_or_default() as usize, 0u8);
+      if self.decode_tile_data(p_stream, tile_info.index, Some(&mut tile_data)) == 0 {
+        return false;
+      }
+      let tile_x0 = opj_uint_ceildivpow2(tile_info.x0 as u32, factor) as usize;
+      let tile_y0 = opj_uint_ceildivpow2(tile_info.y0 as u32, factor) as usize;
+      let tile_w = opj_uint_ceildivpow2(tile_info.x1 as u32, factor) as usize - tile_x0;
+      let tile_h = opj_uint_ceildivpow2(tile_info.y1 as u32, factor) as usize - tile_y0;
+      /* The tile data holds one plane per decoded component, in index
+       * order, see opj_tcd_update_tile_data() */
+      let mut planes = vec![(0, 0); comps.len()];
+      let mut offset = 0;
+      for (compno, comp) in comps.iter().enumerate() {
+        if !decoded.contains(&(compno as u32)) {
+          continue;
+        }
+        let nbytes = match (comp.prec + 7) >> 3 {
+          1 => 1,
+          2 => 2,
+          _ => 4,
+        };
+        planes[compno] = (offset, nbytes);
+        offset += tile_w * tile_h * nbytes;
+      }
+      if offset > tile_data.len() {
+        return false;
+      }
+      for (channel, &compno) in channels.iter().enumerate() {
+        let compno = decoded[compno as usize] as usize;
+        let comp = &comps[compno];
+        let sgnd = comp.sgnd != 0;
+        let (plane, nbytes) = planes[compno];
+        for y in 0..tile_h {
+          for x in 0..tile_w {
+            let src = &tile_data[plane + (y * tile_w + x) * nbytes..][..nbytes];
+            let value = match (nbytes, sgnd) {
+              (1, true) => src[0] as i8 as i32,
+              (1, false) => src[0] as i32,
+              (2, true) => i16::from_ne_bytes([src[0], src[1]]) as i32,
+              (2, false) => u16::from_ne_bytes([src[0], src[1]]) as i32,
+              _ => i32::from_ne_bytes([src[0], src[1], src[2], src[3]]),
+            };
+            let (out_x, out_y) = (tile_x0 - img_x0 + x, tile_y0 - img_y0 + y);
+            let dst = layout.offset(out_x, out_y, channel, stride, height, nb_channels);
+            let pixel = out_y * width + out_x;
+            layout.write_sample(out, dst, pixel, value, comp.prec, sgnd);
+          }
+        }
+      }
+    }
+    true
+  }
+
+  pub fn set_decoded_resolution_factor(&mut self, mut res_factor: OPJ_UINT32) -> OPJ_BOOL {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => 0,
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_set_decoded_resolution_factor(dec, res_factor, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_set_decoded_resolution_factor(dec, res_factor, &mut self.m_event_mgr)
+      }
+    }
+  }
+
+  pub fn set_component_reduce(&mut self, factors: &[(OPJ_UINT32, OPJ_UINT32)]) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_set_component_reduce function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_set_component_reduce(dec, factors, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_set_component_reduce(dec, factors, &mut self.m_event_mgr)
+      }
+    }
+  }
+
+  pub fn set_layer_range(&mut self, start: OPJ_UINT32, end: OPJ_UINT32) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_decoder_set_layer_range function is not a decompressor handler.\n",
+        );
+        false
+      }
+      CodecType::Decoder(CodecFormat::J2K(dec)) => {
+        opj_j2k_set_layer_range(dec, start, end, &mut self.m_event_mgr)
+      }
+      CodecType::Decoder(CodecFormat::JP2(dec)) => {
+        opj_jp2_set_layer_range(dec, start, end, &mut self.m_event_mgr)
+      }
+    }
+  }
+}
+
+// Encoder
+impl Codec {
+  pub fn setup_encoder(
+    &mut self,
+    mut parameters: &mut opj_cparameters_t,
+    mut p_image: &mut opj_image,
+  ) -> OPJ_BOOL {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => {
+        return match enc {
+          CodecFormat::J2K(enc) => {
+            opj_j2k_setup_encoder(enc, parameters, p_image, &mut self.m_event_mgr)
+          }
+          CodecFormat::JP2(enc) => {
+            opj_jp2_setup_encoder(enc, parameters, p_image, &mut self.m_event_mgr)
+          }
+        };
+      }
+      CodecType::Decoder(_) => (),
+    }
+    0i32
+  }
+
+  pub fn encoder_set_extra_options(&mut self, options: &[&str]) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_extra_options(enc, options, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_extra_options(enc, options, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => false,
+    }
+  }
+
+  pub fn set_resolutions(&mut self, numresolutions: u32) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_resolutions(enc, numresolutions, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_resolutions(enc, numresolutions, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_resolutions function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_quantization(&mut self, style: &QuantStyle) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_quantization(enc, style, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_quantization(enc, style, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_quantization function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_near_lossless(&mut self, max_error: OPJ_UINT32, step_shift: OPJ_UINT32) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_near_lossless(enc, max_error, step_shift, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_near_lossless(enc, max_error, step_shift, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_near_lossless function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_guard_bits(&mut self, numgbits: u32) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_guard_bits(enc, numgbits, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_guard_bits(enc, numgbits, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_guard_bits function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_mode_switches(&mut self, modes: ModeSwitches) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_mode_switches(enc, modes, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_mode_switches(enc, modes, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_mode_switches function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_coding_style(&mut self, style: &CodingStyle) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_coding_style(enc, style, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_coding_style(enc, style, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_coding_style function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn coding_style(&self) -> Option<CodingStyle> {
+    match &self.m_codec {
+      CodecType::Encoder(CodecFormat::J2K(enc)) => opj_j2k_encoder_get_coding_style(enc),
+      CodecType::Encoder(CodecFormat::JP2(enc)) => opj_jp2_encoder_get_coding_style(enc),
+      CodecType::Decoder(_) => None,
+    }
+  }
+
+  pub fn set_num_layers(&mut self, numlayers: u32) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_num_layers(enc, numlayers, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_num_layers(enc, numlayers, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_num_layers function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_layer_rates(&mut self, rates: &[f32]) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => opj_j2k_encoder_set_layer_rates(enc, rates, &mut self.m_event_mgr),
+        CodecFormat::JP2(enc) => opj_jp2_encoder_set_layer_rates(enc, rates, &mut self.m_event_mgr),
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_layer_rates function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_layer_psnr(&mut self, psnr: &[f32]) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => opj_j2k_encoder_set_layer_psnr(enc, psnr, &mut self.m_event_mgr),
+        CodecFormat::JP2(enc) => opj_jp2_encoder_set_layer_psnr(enc, psnr, &mut self.m_event_mgr),
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_layer_psnr function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn add_comment(&mut self, comment: &str) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => opj_j2k_encoder_add_comment(enc, comment, &mut self.m_event_mgr),
+        CodecFormat::JP2(enc) => opj_jp2_encoder_add_comment(enc, comment, &mut self.m_event_mgr),
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_add_comment function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_writer_signature(&mut self, signature: Option<&str>) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_writer_signature(enc, signature, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_writer_signature(enc, signature, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_writer_signature function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn preserve_boxes(&mut self, boxes: Vec<RawBox>) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::JP2(enc)) => {
+        opj_jp2_encoder_preserve_boxes(enc, boxes, &mut self.m_event_mgr)
+      }
+      _ => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_preserve_boxes function is not a JP2 compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn add_uuid_box(&mut self, uuid: [u8; 16], data: Vec<u8>) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::JP2(enc)) => {
+        opj_jp2_encoder_add_uuid_box(enc, uuid, data);
+        true
+      }
+      _ => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_add_uuid_box function is not a JP2 compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_geo_metadata(&mut self, data: &[u8]) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::JP2(enc)) => {
+        opj_jp2_encoder_set_geo_metadata(enc, data);
+        true
+      }
+      _ => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_geo_metadata function is not a JP2 compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_brands(&mut self, brand: [u8; 4], compatible: Vec<[u8; 4]>) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::JP2(enc)) => opj_jp2_encoder_set_brands(
+        enc,
+        u32::from_be_bytes(brand),
+        compatible.into_iter().map(u32::from_be_bytes).collect(),
+        &mut self.m_event_mgr,
+      ),
+      _ => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_brands function is not a JP2 compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_colorspace_unknown(&mut self, unknown: bool) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::JP2(enc)) => {
+        opj_jp2_encoder_set_colorspace_unknown(enc, unknown);
+        true
+      }
+      _ => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_colorspace_unknown function is not a JP2 compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_ipr(&mut self, ipr: Option<Vec<u8>>) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::JP2(enc)) => {
+        opj_jp2_encoder_set_ipr(enc, ipr);
+        true
+      }
+      _ => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_ipr function is not a JP2 compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_reader_requirements(&mut self, rreq: Option<ReaderRequirements>) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::JP2(enc)) => {
+        opj_jp2_encoder_set_reader_requirements(enc, rreq, &mut self.m_event_mgr)
+      }
+      _ => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_reader_requirements function is not a JP2 compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_capability(&mut self, part: u32, ccap: u16) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_capability(enc, part, ccap, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_capability(enc, part, ccap, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_capability function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn set_codestream_layout(&mut self, layout: CodestreamLayout) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::J2K(enc)) => {
+        opj_j2k_encoder_set_codestream_layout(enc, layout)
+      }
+      CodecType::Encoder(CodecFormat::JP2(enc)) => {
+        opj_jp2_encoder_set_codestream_layout(enc, layout)
+      }
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_codestream_layout function is not a compressor handler.\n",
+        );
+        return false;
+      }
+    }
+    true
+  }
+
+  pub fn embed_decoded_md5(&mut self, embed: bool) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(CodecFormat::J2K(enc)) => opj_j2k_encoder_embed_md5(enc, embed),
+      CodecType::Encoder(CodecFormat::JP2(enc)) => opj_jp2_encoder_embed_md5(enc, embed),
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_embed_decoded_md5 function is not a compressor handler.\n",
+        );
+        return false;
+      }
+    }
+    true
+  }
+
+  pub fn progression(&mut self, tile_index: OPJ_UINT32) -> Option<Progression> {
+    match &self.m_codec {
+      CodecType::Encoder(CodecFormat::J2K(codec)) | CodecType::Decoder(CodecFormat::J2K(codec)) => {
+        opj_j2k_get_progression(codec, tile_index, &mut self.m_event_mgr)
+      }
+      CodecType::Encoder(CodecFormat::JP2(codec)) | CodecType::Decoder(CodecFormat::JP2(codec)) => {
+        opj_jp2_get_progression(codec, tile_index, &mut self.m_event_mgr)
+      }
+    }
+  }
+
+  pub fn set_progression(&mut self, progression: Progression) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => {
+          opj_j2k_encoder_set_progression(enc, progression, &mut self.m_event_mgr)
+        }
+        CodecFormat::JP2(enc) => {
+          opj_jp2_encoder_set_progression(enc, progression, &mut self.m_event_mgr)
+        }
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_set_progression function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn add_progression_change(&mut self, poc: &PocSpec) -> bool {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => match enc {
+        CodecFormat::J2K(enc) => opj_j2k_encoder_add_poc(enc, poc, &mut self.m_event_mgr),
+        CodecFormat::JP2(enc) => opj_jp2_encoder_add_poc(enc, poc, &mut self.m_event_mgr),
+      },
+      CodecType::Decoder(_) => {
+        event_msg!(
+          &mut self.m_event_mgr,
+          EVT_ERROR,
+          "Codec provided to the opj_encoder_add_progression_change function is not a compressor handler.\n",
+        );
+        false
+      }
+    }
+  }
+
+  pub fn start_compress(
+    &mut self,
+    mut p_image: &mut opj_image,
+    mut p_stream: &mut Stream,
+  ) -> OPJ_BOOL {
+    match &mut self.m_codec {
+      CodecType::Encoder(enc) => {
+        return match enc {
+          CodecFormat::J2K(enc) => {
+            opj_j2k_start_compress(enc, p_stream, p_image, &mut self.m_event_mgr)
+          }
+          CodecFormat::JP2(enc) => {
+            opj_jp2_start_compress(enc, p_stream, p_image, &mut self.m_event_mgr)
+          }
+        };
+      }
+      CodecType::Decoder(_) => (),
+    }
+    0i32
+  }
+
+  /// Forget the samples moved into the codec by `start_compress`, when
+  /// they are borrowed and must not be freed with the codec.
+  pub(crate) fn detach_image_data(&mut self) {
+    if let CodecType::Encoder(enc) = &mut self.m_codec {
+      match enc {
+        CodecFormat::J2K(enc) => opj_j2k_encoder_detach_image_data(enc),
+        CodecFormat::JP2(enc) => opj_jp2_encoder_detach_image_data(enc),
       }
-      CodecType::Decoder(_) => (),
     }
-    0i32
   }
 
   pub fn encode(&mut self, mut p_stream: &mut Stream) -> OPJ_BOOL {
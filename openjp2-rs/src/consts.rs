@@ -118,6 +118,10 @@ pub mod j2k {
   pub const J2K_CP_CSTY_PRT: u32 = 0x01;
   pub const J2K_CP_CSTY_SOP: u32 = 0x02;
   pub const J2K_CP_CSTY_EPH: u32 = 0x04;
+  /**< Precinct and code-block partitions start at x = 1 (Part 2) */
+  pub const J2K_CP_CSTY_ORIGIN_X: u32 = 0x08;
+  /**< Precinct and code-block partitions start at y = 1 (Part 2) */
+  pub const J2K_CP_CSTY_ORIGIN_Y: u32 = 0x10;
   pub const J2K_CCP_CSTY_PRT: u32 = 0x01;
   /**< Selective arithmetic coding bypass */
   pub const J2K_CCP_CBLKSTY_LAZY: u32 = 0x01;
@@ -212,6 +216,7 @@ pub mod j2k {
 }
 
 #[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Jp2BoxType {
   /// No box type
   #[default]
@@ -254,6 +259,12 @@ pub enum Jp2BoxType {
   UUID,
   /// UUID list box
   UINF,
+  /// Reader requirements box
+  RREQ,
+  /// Association box (super-box)
+  ASOC,
+  /// Label box
+  LBL,
   /// Unknown box type
   Unknown(u32),
 }
@@ -278,8 +289,11 @@ impl From<u32> for Jp2BoxType {
       0x72657320 => Jp2BoxType::RES,
       0x6a703269 => Jp2BoxType::JP2I,
       0x786d6c20 => Jp2BoxType::XML,
-      0x75756994 => Jp2BoxType::UUID,
+      0x75756964 => Jp2BoxType::UUID,
       0x75696e66 => Jp2BoxType::UINF,
+      0x72726571 => Jp2BoxType::RREQ,
+      0x61736f63 => Jp2BoxType::ASOC,
+      0x6c626c20 => Jp2BoxType::LBL,
       _ => Jp2BoxType::Unknown(value),
     }
   }
@@ -328,8 +342,11 @@ impl Jp2BoxType {
       Jp2BoxType::RES => Some(0x72657320),
       Jp2BoxType::JP2I => Some(0x6a703269),
       Jp2BoxType::XML => Some(0x786d6c20),
-      Jp2BoxType::UUID => Some(0x75756994),
+      Jp2BoxType::UUID => Some(0x75756964),
       Jp2BoxType::UINF => Some(0x75696e66),
+      Jp2BoxType::RREQ => Some(0x72726571),
+      Jp2BoxType::ASOC => Some(0x61736f63),
+      Jp2BoxType::LBL => Some(0x6c626c20),
       Jp2BoxType::Unknown(value) => Some(*value),
     }
   }
@@ -675,6 +692,9 @@ mod tests {
       Jp2BoxType::XML,
       Jp2BoxType::UUID,
       Jp2BoxType::UINF,
+      Jp2BoxType::RREQ,
+      Jp2BoxType::ASOC,
+      Jp2BoxType::LBL,
       Jp2BoxType::Unknown(123),
     ];
 
@@ -0,0 +1,155 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! Converters between `opj_image` and simple image formats.
+
+#[cfg(feature = "file-io")]
+use std::fs::File;
+#[cfg(feature = "file-io")]
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+#[cfg(feature = "file-io")]
+use crate::openjpeg::opj_image;
+use crate::openjpeg::RoundingMode;
+
+pub mod pgx;
+pub mod pnm;
+pub mod raw;
+#[cfg(feature = "tiff")]
+pub mod tiff;
+
+/// Uncompressed image formats that `opj_image::save` can write.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFormat {
+  /// `.pgx`: one file per component.
+  Pgx,
+  /// `.pnm`: PPM for 3 or more components, PGM otherwise.
+  Pnm,
+  /// `.pgm`: the first component.
+  Pgm,
+  /// `.ppm`: the first three components.
+  Ppm,
+  /// `.raw` and `.yuv`: big-endian raw samples.
+  Raw,
+  /// `.rawl`: little-endian raw samples.
+  RawL,
+  /// `.tif` and `.tiff`: uncompressed TIFF, see `tiff::TiffOptions` for
+  /// the other layouts. Needs the `tiff` feature.
+  #[cfg(feature = "tiff")]
+  Tif,
+}
+
+/// How signed components are written to formats of unsigned samples, PGM
+/// and PPM. PGX and raw files keep signed samples as they are.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SignedHandling {
+  /// Add `2^(prec-1)`, the DC level shift: the full signed range maps to
+  /// the full unsigned range.
+  #[default]
+  Offset,
+  /// Clip negative samples to zero and keep the others as they are.
+  ClampToZero,
+  /// Map the minimum and maximum samples of the written components to the
+  /// full unsigned range, to keep the detail of data using a small part of
+  /// its range.
+  Rescale,
+}
+
+/// Options of `opj_image::save_with_options`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SaveOptions {
+  /// How signed components are written to formats of unsigned samples.
+  pub signed_handling: SignedHandling,
+  /// Rounding of the samples scaled down to the depth of the format, e.g.
+  /// PGM and PPM samples deeper than 16 bits.
+  pub rounding: RoundingMode,
+}
+
+/// Detect the image format from the extension of `path`, ignoring case.
+///
+/// Uses the same extensions as `opj_decompress` for the formats supported
+/// by the library.
+pub fn detect_format_from_extension<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+  let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+  Some(match ext.as_str() {
+    "pgx" => ImageFormat::Pgx,
+    "pnm" => ImageFormat::Pnm,
+    "pgm" => ImageFormat::Pgm,
+    "ppm" => ImageFormat::Ppm,
+    "raw" | "yuv" => ImageFormat::Raw,
+    "rawl" => ImageFormat::RawL,
+    #[cfg(feature = "tiff")]
+    "tif" | "tiff" => ImageFormat::Tif,
+    _ => return None,
+  })
+}
+
+/// Create the file `path` and write it with `write`.
+#[cfg(feature = "file-io")]
+fn write_file<F>(path: &Path, write: F) -> Result<()>
+where
+  F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+  let mut out = BufWriter::new(File::create(path)?);
+  write(&mut out)?;
+  out.flush()
+}
+
+#[cfg(feature = "file-io")]
+impl opj_image {
+  /// Save the image to `path` in the format given by its extension, see
+  /// `detect_format_from_extension`.
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    self.save_with_options(path, &SaveOptions::default())
+  }
+
+  /// Save the image to `path` in the format given by its extension, with
+  /// `options`.
+  pub fn save_with_options<P: AsRef<Path>>(&self, path: P, options: &SaveOptions) -> Result<()> {
+    let path = path.as_ref();
+    let format = detect_format_from_extension(path).ok_or_else(|| {
+      Error::new(
+        ErrorKind::InvalidInput,
+        format!("Unsupported output format: {}", path.display()),
+      )
+    })?;
+    match format {
+      ImageFormat::Pgx => pgx::save_pgx_image(self, path, pgx::PgxByteOrder::BigEndian),
+      ImageFormat::Pnm => pnm::save_pnm_image_with_options(self, path, options),
+      ImageFormat::Pgm => write_file(path, |out| self.write_pgm_with_options(options, out)),
+      ImageFormat::Ppm => write_file(path, |out| self.write_ppm_with_options(options, out)),
+      ImageFormat::Raw => raw::save_raw_image(self, path, raw::RawByteOrder::BigEndian),
+      ImageFormat::RawL => raw::save_raw_image(self, path, raw::RawByteOrder::LittleEndian),
+      #[cfg(feature = "tiff")]
+      ImageFormat::Tif => tiff::save_tiff_image(self, path),
+    }
+  }
+}
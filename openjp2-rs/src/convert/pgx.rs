@@ -0,0 +1,287 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! PGX, the raw single component format used by the JPEG 2000 conformance
+//! test suite.
+//!
+//! The header is `PG <order> <sign> <depth> <width> <height>\n` followed by
+//! the samples. `ML` means the most significant byte comes first (big-endian)
+//! and `LM` the least significant byte first (little-endian). Samples use 1,
+//! 2 or 4 bytes depending on the depth.
+
+use std::io::{BufRead, Error, ErrorKind, Result, Write};
+
+#[cfg(feature = "file-io")]
+use std::{fs::File, io::BufReader, io::BufWriter, path::Path};
+
+use crate::openjpeg::{opj_image, opj_image_comp, OPJ_CLRSPC_GRAY};
+
+/// Byte order of the PGX samples.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PgxByteOrder {
+  /// `ML`: most significant byte first.
+  #[default]
+  BigEndian,
+  /// `LM`: least significant byte first.
+  LittleEndian,
+}
+
+impl PgxByteOrder {
+  fn token(&self) -> &'static str {
+    match self {
+      Self::BigEndian => "ML",
+      Self::LittleEndian => "LM",
+    }
+  }
+}
+
+/// Number of bytes used for each sample of the given depth.
+fn sample_bytes(prec: u32) -> usize {
+  match prec {
+    0..=8 => 1,
+    9..=16 => 2,
+    _ => 4,
+  }
+}
+
+/// Clamp a sample to the range of the component depth.
+fn clamp(value: i32, prec: u32, sgnd: bool) -> i32 {
+  let (min, max) = if sgnd {
+    (-(1i64 << (prec - 1)), (1i64 << (prec - 1)) - 1)
+  } else {
+    (0, (1i64 << prec) - 1)
+  };
+  (value as i64).clamp(min, max) as i32
+}
+
+fn invalid_data(msg: &str) -> Error {
+  Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Write one image component as a PGX file.
+pub fn write_pgx_component<W: Write>(
+  comp: &opj_image_comp,
+  order: PgxByteOrder,
+  out: &mut W,
+) -> Result<()> {
+  let data = comp
+    .data()
+    .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Component has no data"))?;
+  if comp.prec == 0 || comp.prec > 31 {
+    return Err(Error::new(
+      ErrorKind::InvalidInput,
+      "PGX supports depths from 1 to 31 bits",
+    ));
+  }
+  let sgnd = comp.sgnd != 0;
+  writeln!(
+    out,
+    "PG {} {} {} {} {}",
+    order.token(),
+    if sgnd { '-' } else { '+' },
+    comp.prec,
+    comp.w,
+    comp.h
+  )?;
+  let nbytes = sample_bytes(comp.prec);
+  let mut line = Vec::with_capacity(comp.w as usize * nbytes);
+  for row in data.chunks(comp.w.max(1) as usize) {
+    line.clear();
+    for &v in row {
+      let v = clamp(v, comp.prec, sgnd) as u32;
+      let bytes = v.to_be_bytes();
+      let bytes = &bytes[4 - nbytes..];
+      match order {
+        PgxByteOrder::BigEndian => line.extend_from_slice(bytes),
+        PgxByteOrder::LittleEndian => line.extend(bytes.iter().rev()),
+      }
+    }
+    out.write_all(&line)?;
+  }
+  Ok(())
+}
+
+/// Read a header token, skipping leading whitespace.
+fn read_token<R: BufRead>(reader: &mut R) -> Result<String> {
+  let mut token = String::new();
+  let mut byte = [0u8; 1];
+  loop {
+    if reader.read(&mut byte)? == 0 {
+      return Err(invalid_data("Bad pgx header: truncated"));
+    }
+    let c = byte[0] as char;
+    if c.is_ascii_whitespace() {
+      if token.is_empty() {
+        continue;
+      }
+      return Ok(token);
+    }
+    // The sign may be written directly in front of the depth.
+    if token.is_empty() && (c == '+' || c == '-') {
+      return Ok(c.to_string());
+    }
+    token.push(c);
+    if token.len() > 32 {
+      return Err(invalid_data("Bad pgx header: token too long"));
+    }
+  }
+}
+
+fn parse_header_u32(token: &str, what: &str) -> Result<u32> {
+  token
+    .parse::<u32>()
+    .map_err(|_| invalid_data(&format!("Bad pgx header: invalid {what} `{token}`")))
+}
+
+/// Read a PGX file into a single component greyscale image.
+///
+/// Both the `ML` (big-endian) and `LM` (little-endian) byte orders are
+/// supported. The depth and signedness from the header are kept as is.
+pub fn read_pgx<R: BufRead>(reader: &mut R) -> Result<Box<opj_image>> {
+  if read_token(reader)? != "PG" {
+    return Err(invalid_data("Bad pgx header: missing `PG` magic"));
+  }
+  let order = match read_token(reader)?.as_str() {
+    "ML" => PgxByteOrder::BigEndian,
+    "LM" => PgxByteOrder::LittleEndian,
+    token => {
+      return Err(invalid_data(&format!(
+        "Bad pgx header: unknown byte order `{token}`"
+      )))
+    }
+  };
+  let mut token = read_token(reader)?;
+  let sgnd = match token.as_str() {
+    "+" | "-" => {
+      let sgnd = token == "-";
+      token = read_token(reader)?;
+      sgnd
+    }
+    _ => false,
+  };
+  let prec = parse_header_u32(&token, "depth")?;
+  let w = parse_header_u32(&read_token(reader)?, "width")?;
+  let h = parse_header_u32(&read_token(reader)?, "height")?;
+  if w == 0 || h == 0 || prec == 0 || prec > 31 {
+    return Err(invalid_data("Bad pgx header: invalid dimensions or depth"));
+  }
+  let nbytes = sample_bytes(prec);
+  if (w as usize).checked_mul(h as usize * nbytes).is_none() {
+    return Err(invalid_data("Bad pgx header: image too large"));
+  }
+
+  let mut image = opj_image::new();
+  image.color_space = OPJ_CLRSPC_GRAY;
+  image.x1 = w;
+  image.y1 = h;
+  if !image.alloc_comps(1, true) {
+    return Err(Error::new(
+      ErrorKind::OutOfMemory,
+      "Failed to allocate image",
+    ));
+  }
+  let comp = &mut image.comps_mut().unwrap()[0];
+  comp.dx = 1;
+  comp.dy = 1;
+  comp.w = w;
+  comp.h = h;
+  comp.prec = prec;
+  comp.sgnd = sgnd as u32;
+  if !comp.alloc_data() {
+    return Err(Error::new(
+      ErrorKind::OutOfMemory,
+      "Failed to allocate image",
+    ));
+  }
+  let data = comp.data_mut().unwrap();
+  let mut line = vec![0u8; w as usize * nbytes];
+  for row in data.chunks_mut(w as usize) {
+    reader.read_exact(&mut line)?;
+    for (v, bytes) in row.iter_mut().zip(line.chunks(nbytes)) {
+      let mut raw = [0u8; 4];
+      match order {
+        PgxByteOrder::BigEndian => raw[4 - nbytes..].copy_from_slice(bytes),
+        PgxByteOrder::LittleEndian => {
+          for (dst, src) in raw[4 - nbytes..].iter_mut().zip(bytes.iter().rev()) {
+            *dst = *src;
+          }
+        }
+      }
+      let raw = u32::from_be_bytes(raw);
+      *v = if sgnd && nbytes < 4 {
+        // Sign extend from the sample width.
+        let shift = 32 - nbytes * 8;
+        ((raw << shift) as i32) >> shift
+      } else {
+        raw as i32
+      };
+    }
+  }
+  Ok(image)
+}
+
+/// Save each component of `image` as a PGX file.
+///
+/// A single component is written to `path`. Several components are written
+/// to `<stem>_<index>.pgx` next to `path`, like `imagetopgx`, which is the
+/// naming used by the conformance MD5 test harness.
+#[cfg(feature = "file-io")]
+pub fn save_pgx_image<P: AsRef<Path>>(
+  image: &opj_image,
+  path: P,
+  order: PgxByteOrder,
+) -> Result<()> {
+  let path = path.as_ref();
+  let stem = path
+    .file_stem()
+    .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Missing output file name"))?
+    .to_string_lossy();
+  let comps = image
+    .comps()
+    .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Image has no components"))?;
+  for (compno, comp) in comps.iter().enumerate() {
+    let name = if comps.len() > 1 {
+      path.with_file_name(format!("{stem}_{compno}.pgx"))
+    } else {
+      path.to_path_buf()
+    };
+    let mut out = BufWriter::new(File::create(name)?);
+    write_pgx_component(comp, order, &mut out)?;
+    out.flush()?;
+  }
+  Ok(())
+}
+
+/// Load a PGX file into a single component greyscale image.
+#[cfg(feature = "file-io")]
+pub fn load_pgx_image<P: AsRef<Path>>(path: P) -> Result<Box<opj_image>> {
+  let mut reader = BufReader::new(File::open(path)?);
+  read_pgx(&mut reader)
+}
@@ -0,0 +1,320 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! Binary PNM: PGM (`P5`) for greyscale and PPM (`P6`) for RGB.
+//!
+//! Samples of up to 8 bits are written as single bytes, deeper samples as
+//! 16-bit big-endian values. The maxval is `2^prec - 1`; samples deeper
+//! than 16 bits are scaled down to 16 bits, rounded as set by
+//! `SaveOptions::rounding`. Signed components are mapped
+//! to the unsigned range as set by `SignedHandling`, shifted by default.
+//!
+//! Reading streams the raster a row at a time into the components, so that
+//! large files don't need a second copy of their samples in memory.
+
+use std::io::{BufRead, Error, ErrorKind, Result, Write};
+
+#[cfg(feature = "file-io")]
+use std::{fs::File, io::BufReader, io::BufWriter, path::Path};
+
+use super::{SaveOptions, SignedHandling};
+use crate::openjpeg::{opj_image, opj_image_comp, OPJ_CLRSPC_GRAY, OPJ_CLRSPC_SRGB};
+
+fn invalid_input(msg: &str) -> Error {
+  Error::new(ErrorKind::InvalidInput, msg)
+}
+
+fn invalid_data(msg: &str) -> Error {
+  Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Check that the components can be written together and return their
+/// samples and the precision used for the output.
+fn pnm_components(comps: &[opj_image_comp]) -> Result<(Vec<&[i32]>, u32)> {
+  let first = &comps[0];
+  if first.prec == 0 || first.prec > 31 {
+    return Err(invalid_input("PNM supports depths from 1 to 31 bits"));
+  }
+  let mut data = Vec::with_capacity(comps.len());
+  for comp in comps {
+    if (comp.w, comp.h, comp.dx, comp.dy, comp.prec, comp.sgnd)
+      != (first.w, first.h, first.dx, first.dy, first.prec, first.sgnd)
+    {
+      return Err(invalid_input(
+        "PNM requires components with the same size and depth",
+      ));
+    }
+    let samples = comp
+      .data()
+      .ok_or_else(|| invalid_input("Component has no data"))?;
+    data.push(samples);
+  }
+  Ok((data, first.prec))
+}
+
+/// Map the samples of `data` to `[0, 2^prec - 1]`, see `SignedHandling`.
+fn unsigned_mapping(
+  data: &[&[i32]],
+  prec: u32,
+  sgnd: bool,
+  signed: SignedHandling,
+) -> impl Fn(i32) -> i64 {
+  let max = (1i64 << prec) - 1;
+  // `v -> (v - min) * scale / range`, rounded.
+  let (min, scale, range) = match (sgnd, signed) {
+    (false, _) | (true, SignedHandling::ClampToZero) => (0, 1, 1),
+    (true, SignedHandling::Offset) => (-(1i64 << (prec - 1)), 1, 1),
+    (true, SignedHandling::Rescale) => {
+      let samples = data.iter().flat_map(|samples| samples.iter());
+      let min = samples.clone().min().copied().unwrap_or(0) as i64;
+      let max_sample = samples.max().copied().unwrap_or(0) as i64;
+      if max_sample > min {
+        (min, max, max_sample - min)
+      } else {
+        (min, 0, 1)
+      }
+    }
+  };
+  // 128 bits: the product overflows 64 bits for 31-bit samples.
+  move |v| (((v as i64 - min) as i128 * scale as i128 + range as i128 / 2) / range as i128) as i64
+}
+
+/// Write the header and the interleaved samples of `comps`.
+fn write_pnm<W: Write>(
+  magic: &str,
+  comps: &[opj_image_comp],
+  options: &SaveOptions,
+  out: &mut W,
+) -> Result<()> {
+  let (data, prec) = pnm_components(comps)?;
+  let first = &comps[0];
+  let out_prec = prec.min(16);
+  let shift = prec - out_prec;
+  let map = unsigned_mapping(&data, prec, first.sgnd != 0, options.signed_handling);
+  let max = (1i64 << prec) - 1;
+  let out_max = (1i64 << out_prec) - 1;
+  writeln!(out, "{magic}\n{} {}\n{}", first.w, first.h, out_max)?;
+
+  let nbytes = if out_prec > 8 { 2 } else { 1 };
+  let width = first.w as usize;
+  let mut line = Vec::with_capacity(width * data.len() * nbytes);
+  for y in 0..first.h as usize {
+    line.clear();
+    for x in 0..width {
+      let idx = y * width + x;
+      for samples in &data {
+        let v = map(samples[idx]).clamp(0, max);
+        let v = options
+          .rounding
+          .scale(v, 1, 1 << shift, idx as u64)
+          .min(out_max) as u16;
+        if nbytes == 2 {
+          line.extend_from_slice(&v.to_be_bytes());
+        } else {
+          line.push(v as u8);
+        }
+      }
+    }
+    out.write_all(&line)?;
+  }
+  Ok(())
+}
+
+impl opj_image {
+  /// Write the first three components as a binary PPM (`P6`).
+  pub fn write_ppm<W: Write>(&self, out: &mut W) -> Result<()> {
+    self.write_ppm_with_options(&SaveOptions::default(), out)
+  }
+
+  /// Write the first three components as a binary PPM (`P6`), with
+  /// `options`.
+  pub fn write_ppm_with_options<W: Write>(&self, options: &SaveOptions, out: &mut W) -> Result<()> {
+    let comps = self
+      .comps()
+      .filter(|comps| comps.len() >= 3)
+      .ok_or_else(|| invalid_input("PPM requires at least 3 components"))?;
+    write_pnm("P6", &comps[..3], options, out)
+  }
+
+  /// Write the first component as a binary PGM (`P5`).
+  pub fn write_pgm<W: Write>(&self, out: &mut W) -> Result<()> {
+    self.write_pgm_with_options(&SaveOptions::default(), out)
+  }
+
+  /// Write the first component as a binary PGM (`P5`), with `options`.
+  pub fn write_pgm_with_options<W: Write>(&self, options: &SaveOptions, out: &mut W) -> Result<()> {
+    let comps = self
+      .comps()
+      .filter(|comps| !comps.is_empty())
+      .ok_or_else(|| invalid_input("Image has no components"))?;
+    write_pnm("P5", &comps[..1], options, out)
+  }
+}
+
+/// Read a header token, skipping whitespace and `#` comments before it.
+/// The single whitespace ending the token is consumed.
+fn read_token<R: BufRead>(reader: &mut R) -> Result<String> {
+  let mut token = String::new();
+  let mut in_comment = false;
+  let mut byte = [0u8; 1];
+  loop {
+    if reader.read(&mut byte)? == 0 {
+      return Err(invalid_data("Bad pnm header: truncated"));
+    }
+    let c = byte[0] as char;
+    if in_comment {
+      in_comment = c != '\n' && c != '\r';
+    } else if c == '#' && token.is_empty() {
+      in_comment = true;
+    } else if c.is_ascii_whitespace() {
+      if !token.is_empty() {
+        return Ok(token);
+      }
+    } else {
+      token.push(c);
+      if token.len() > 16 {
+        return Err(invalid_data("Bad pnm header: token too long"));
+      }
+    }
+  }
+}
+
+fn parse_header_u32(token: &str, what: &str) -> Result<u32> {
+  token
+    .parse::<u32>()
+    .map_err(|_| invalid_data(&format!("Bad pnm header: invalid {what} `{token}`")))
+}
+
+/// Read a binary PGM (`P5`) or PPM (`P6`) into a greyscale or sRGB image.
+///
+/// The precision of the components is the number of bits of the maxval,
+/// up to 16. The raster is read row by row: besides the image, only one
+/// row of the file is buffered, and the reader is left at the end of the
+/// raster.
+pub fn read_pnm<R: BufRead>(reader: &mut R) -> Result<Box<opj_image>> {
+  let (numcomps, color_space) = match read_token(reader)?.as_str() {
+    "P5" => (1, OPJ_CLRSPC_GRAY),
+    "P6" => (3, OPJ_CLRSPC_SRGB),
+    token => {
+      return Err(invalid_data(&format!(
+        "Bad pnm header: unsupported magic `{token}`, only binary PGM and PPM are read"
+      )))
+    }
+  };
+  let w = parse_header_u32(&read_token(reader)?, "width")?;
+  let h = parse_header_u32(&read_token(reader)?, "height")?;
+  let maxval = parse_header_u32(&read_token(reader)?, "maxval")?;
+  if w == 0 || h == 0 || maxval == 0 || maxval > 65535 {
+    return Err(invalid_data("Bad pnm header: invalid dimensions or maxval"));
+  }
+  let prec = 32 - maxval.leading_zeros();
+  let nbytes = if maxval > 255 { 2 } else { 1 };
+  let line_len = (w as usize)
+    .checked_mul(numcomps as usize * nbytes)
+    .filter(|len| len.checked_mul(h as usize).is_some())
+    .ok_or_else(|| invalid_data("Bad pnm header: image too large"))?;
+
+  let mut image = opj_image::new();
+  image.color_space = color_space;
+  image.x1 = w;
+  image.y1 = h;
+  if !image.alloc_comps(numcomps, true) {
+    return Err(Error::new(
+      ErrorKind::OutOfMemory,
+      "Failed to allocate image",
+    ));
+  }
+  for comp in image.comps_mut().unwrap() {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = w;
+    comp.h = h;
+    comp.prec = prec;
+    if !comp.alloc_data() {
+      return Err(Error::new(
+        ErrorKind::OutOfMemory,
+        "Failed to allocate image",
+      ));
+    }
+  }
+  let mut rows: Vec<_> = image
+    .comps_mut()
+    .unwrap()
+    .iter_mut()
+    .map(|comp| comp.data_mut().unwrap().chunks_mut(w as usize))
+    .collect();
+  let mut line = vec![0u8; line_len];
+  for _ in 0..h {
+    reader.read_exact(&mut line)?;
+    for (compno, row) in rows.iter_mut().enumerate() {
+      let row = row.next().unwrap();
+      let pixels = line.chunks(numcomps as usize * nbytes);
+      for (v, pixel) in row.iter_mut().zip(pixels) {
+        let bytes = &pixel[compno * nbytes..(compno + 1) * nbytes];
+        *v = match bytes {
+          [b] => *b as i32,
+          [hi, lo] => u16::from_be_bytes([*hi, *lo]) as i32,
+          _ => unreachable!(),
+        };
+      }
+    }
+  }
+  Ok(image)
+}
+
+/// Save `image` as a PPM if it has at least 3 components, otherwise as a PGM.
+#[cfg(feature = "file-io")]
+pub fn save_pnm_image<P: AsRef<Path>>(image: &opj_image, path: P) -> Result<()> {
+  save_pnm_image_with_options(image, path, &SaveOptions::default())
+}
+
+/// Save `image` as a PPM if it has at least 3 components, otherwise as a
+/// PGM, with `options`.
+#[cfg(feature = "file-io")]
+pub fn save_pnm_image_with_options<P: AsRef<Path>>(
+  image: &opj_image,
+  path: P,
+  options: &SaveOptions,
+) -> Result<()> {
+  let mut out = BufWriter::new(File::create(path)?);
+  if image.numcomps >= 3 {
+    image.write_ppm_with_options(options, &mut out)?;
+  } else {
+    image.write_pgm_with_options(options, &mut out)?;
+  }
+  out.flush()
+}
+
+/// Load a binary PGM or PPM file, see `read_pnm`.
+#[cfg(feature = "file-io")]
+pub fn load_pnm_image<P: AsRef<Path>>(path: P) -> Result<Box<opj_image>> {
+  let mut reader = BufReader::new(File::open(path)?);
+  read_pnm(&mut reader)
+}
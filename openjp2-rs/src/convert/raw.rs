@@ -0,0 +1,225 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! Raw images: the samples of each component one after the other, without
+//! any header.
+//!
+//! Samples use `ceil(prec / 8)` bytes, from 1 to 4, in the chosen byte
+//! order. Signed samples are stored in two's complement. Samples are
+//! clamped to the range of their depth when written.
+
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+#[cfg(feature = "file-io")]
+use std::{fs::File, io::BufReader, io::BufWriter, path::Path};
+
+use crate::openjpeg::{opj_image, OPJ_CLRSPC_GRAY, OPJ_CLRSPC_SRGB, OPJ_CLRSPC_UNKNOWN};
+
+/// Byte order of the multi-byte raw samples.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum RawByteOrder {
+  /// Most significant byte first (`.raw`).
+  #[default]
+  BigEndian,
+  /// Least significant byte first (`.rawl`).
+  LittleEndian,
+}
+
+/// Layout of a raw image, which isn't stored in the file.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RawImageInfo {
+  pub width: u32,
+  pub height: u32,
+  pub numcomps: u32,
+  /// Depth of the samples, from 1 to 31 bits.
+  pub prec: u32,
+  pub sgnd: bool,
+}
+
+fn invalid_input(msg: &str) -> Error {
+  Error::new(ErrorKind::InvalidInput, msg)
+}
+
+/// Number of bytes used for each sample of the given depth.
+fn sample_bytes(prec: u32) -> usize {
+  prec.div_ceil(8) as usize
+}
+
+/// Range of the samples of the given depth.
+fn sample_range(prec: u32, sgnd: bool) -> (i64, i64) {
+  if sgnd {
+    (-(1i64 << (prec - 1)), (1i64 << (prec - 1)) - 1)
+  } else {
+    (0, (1i64 << prec) - 1)
+  }
+}
+
+impl opj_image {
+  /// Write the samples of all components as raw data.
+  ///
+  /// All components must have the same subsampling, depth and sign.
+  pub fn write_raw<W: Write>(&self, order: RawByteOrder, out: &mut W) -> Result<()> {
+    let comps = self
+      .comps()
+      .filter(|comps| !comps.is_empty())
+      .ok_or_else(|| invalid_input("Image has no components"))?;
+    let first = &comps[0];
+    if first.prec == 0 || first.prec > 31 {
+      return Err(invalid_input(
+        "Raw output supports depths from 1 to 31 bits",
+      ));
+    }
+    if comps
+      .iter()
+      .any(|c| (c.dx, c.dy, c.prec, c.sgnd) != (first.dx, first.dy, first.prec, first.sgnd))
+    {
+      return Err(invalid_input(
+        "Raw output requires components with the same subsampling and depth",
+      ));
+    }
+    let (min, max) = sample_range(first.prec, first.sgnd != 0);
+    let nbytes = sample_bytes(first.prec);
+    for comp in comps {
+      let samples = comp
+        .data()
+        .ok_or_else(|| invalid_input("Component has no data"))?;
+      let mut buf = Vec::with_capacity(samples.len() * nbytes);
+      for sample in samples {
+        let v = (*sample as i64).clamp(min, max) as u32;
+        match order {
+          RawByteOrder::BigEndian => buf.extend_from_slice(&v.to_be_bytes()[4 - nbytes..]),
+          RawByteOrder::LittleEndian => buf.extend_from_slice(&v.to_le_bytes()[..nbytes]),
+        }
+      }
+      out.write_all(&buf)?;
+    }
+    Ok(())
+  }
+}
+
+/// Read a raw image with the layout `info`.
+///
+/// One and three component images are tagged as greyscale and sRGB.
+pub fn read_raw<R: Read>(
+  reader: &mut R,
+  info: &RawImageInfo,
+  order: RawByteOrder,
+) -> Result<Box<opj_image>> {
+  let RawImageInfo {
+    width: w,
+    height: h,
+    numcomps,
+    prec,
+    sgnd,
+  } = *info;
+  if w == 0 || h == 0 || numcomps == 0 || prec == 0 || prec > 31 {
+    return Err(invalid_input("Invalid raw image dimensions or depth"));
+  }
+  let nbytes = sample_bytes(prec);
+  if (w as usize).checked_mul(h as usize * nbytes).is_none() {
+    return Err(invalid_input("Raw image too large"));
+  }
+
+  let mut image = opj_image::new();
+  image.color_space = match numcomps {
+    1 => OPJ_CLRSPC_GRAY,
+    3 => OPJ_CLRSPC_SRGB,
+    _ => OPJ_CLRSPC_UNKNOWN,
+  };
+  image.x1 = w;
+  image.y1 = h;
+  if !image.alloc_comps(numcomps, true) {
+    return Err(Error::new(
+      ErrorKind::OutOfMemory,
+      "Failed to allocate image",
+    ));
+  }
+  let mut line = vec![0u8; w as usize * nbytes];
+  for comp in image.comps_mut().unwrap() {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = w;
+    comp.h = h;
+    comp.prec = prec;
+    comp.sgnd = sgnd as u32;
+    if !comp.alloc_data() {
+      return Err(Error::new(
+        ErrorKind::OutOfMemory,
+        "Failed to allocate image",
+      ));
+    }
+    for row in comp.data_mut().unwrap().chunks_mut(w as usize) {
+      reader.read_exact(&mut line)?;
+      for (v, bytes) in row.iter_mut().zip(line.chunks(nbytes)) {
+        let mut raw = [0u8; 4];
+        match order {
+          RawByteOrder::BigEndian => raw[4 - nbytes..].copy_from_slice(bytes),
+          RawByteOrder::LittleEndian => {
+            for (dst, src) in raw[4 - nbytes..].iter_mut().zip(bytes.iter().rev()) {
+              *dst = *src;
+            }
+          }
+        }
+        let raw = u32::from_be_bytes(raw);
+        *v = if sgnd && nbytes < 4 {
+          // Sign extend from the sample width.
+          let shift = 32 - nbytes * 8;
+          ((raw << shift) as i32) >> shift
+        } else {
+          raw as i32
+        };
+      }
+    }
+  }
+  Ok(image)
+}
+
+/// Save all components of `image` as a raw file.
+#[cfg(feature = "file-io")]
+pub fn save_raw_image<P: AsRef<Path>>(
+  image: &opj_image,
+  path: P,
+  order: RawByteOrder,
+) -> Result<()> {
+  let mut out = BufWriter::new(File::create(path)?);
+  image.write_raw(order, &mut out)?;
+  out.flush()
+}
+
+/// Load a raw file with the layout `info`.
+#[cfg(feature = "file-io")]
+pub fn load_raw_image<P: AsRef<Path>>(
+  path: P,
+  info: &RawImageInfo,
+  order: RawByteOrder,
+) -> Result<Box<opj_image>> {
+  let mut reader = BufReader::new(File::open(path)?);
+  read_raw(&mut reader, info, order)
+}
@@ -0,0 +1,390 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! Baseline TIFF writer, without libtiff. Needs the `tiff` feature.
+//!
+//! Images are written as little-endian TIFF with contiguous samples in
+//! strips, uncompressed or with Deflate. The layout follows the one of
+//! libtiff as used by `opj_decompress`: the strips come first, from offset
+//! 8, then the directory and the values that don't fit in its entries.
+//!
+//! Like `opj_decompress`, up to 4 components are written: greyscale for 1
+//! or 2 components, RGB for 3 or 4 and CMYK for `OPJ_CLRSPC_CMYK` images.
+//! Samples of 1 to 16 bits are supported. Samples of less than 16 bits are
+//! packed most significant bit first, 16-bit samples are little-endian.
+//! Signed components are shifted by `2^(prec-1)` by default, or kept as
+//! two's complement samples with `TiffOptions::keep_signed`.
+
+use std::io::{Error, ErrorKind, Result, Write};
+
+#[cfg(feature = "file-io")]
+use std::{fs::File, io::BufWriter, path::Path};
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+
+use crate::openjpeg::{opj_image, OPJ_CLRSPC_CMYK};
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC: u16 = 262;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_ORIENTATION: u16 = 274;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_PLANAR_CONFIG: u16 = 284;
+const TAG_SAMPLE_FORMAT: u16 = 339;
+
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+
+const PHOTOMETRIC_MINISBLACK: u16 = 1;
+const PHOTOMETRIC_RGB: u16 = 2;
+const PHOTOMETRIC_SEPARATED: u16 = 5;
+
+const SAMPLE_FORMAT_UINT: u16 = 1;
+const SAMPLE_FORMAT_INT: u16 = 2;
+
+/// Compression of the TIFF strips.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TiffCompression {
+  /// Uncompressed strips (`Compression` 1).
+  #[default]
+  None,
+  /// zlib streams (`Compression` 8, Adobe Deflate).
+  Deflate,
+}
+
+impl TiffCompression {
+  fn tag_value(self) -> u16 {
+    match self {
+      Self::None => 1,
+      Self::Deflate => 8,
+    }
+  }
+}
+
+/// Options of `opj_image::write_tiff`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TiffOptions {
+  pub compression: TiffCompression,
+  /// Number of rows of each strip, the last one may have less. Defaults
+  /// to 1 like `opj_decompress`.
+  pub rows_per_strip: u32,
+  /// Write signed components as two's complement samples, tagged with the
+  /// signed integer `SampleFormat`, instead of shifting them to the
+  /// unsigned range.
+  pub keep_signed: bool,
+}
+
+impl Default for TiffOptions {
+  fn default() -> Self {
+    Self {
+      compression: TiffCompression::None,
+      rows_per_strip: 1,
+      keep_signed: false,
+    }
+  }
+}
+
+fn invalid_input(msg: &str) -> Error {
+  Error::new(ErrorKind::InvalidInput, msg)
+}
+
+/// Packs samples of `bits` bits, most significant bit first.
+struct BitPacker {
+  out: Vec<u8>,
+  acc: u32,
+  nbits: u32,
+}
+
+impl BitPacker {
+  fn put(&mut self, value: u32, bits: u32) {
+    self.acc = self.acc << bits | value;
+    self.nbits += bits;
+    while self.nbits >= 8 {
+      self.nbits -= 8;
+      self.out.push((self.acc >> self.nbits) as u8);
+    }
+    self.acc &= (1 << self.nbits) - 1;
+  }
+
+  /// Rows start on a byte boundary.
+  fn flush(&mut self) {
+    if self.nbits > 0 {
+      self.put(0, 8 - self.nbits);
+    }
+  }
+}
+
+/// A directory entry, with its values as little-endian bytes.
+struct Entry {
+  tag: u16,
+  kind: u16,
+  count: u32,
+  values: Vec<u8>,
+}
+
+impl Entry {
+  fn shorts(tag: u16, values: &[u16]) -> Self {
+    Self {
+      tag,
+      kind: TYPE_SHORT,
+      count: values.len() as u32,
+      values: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+    }
+  }
+
+  fn longs(tag: u16, values: &[u32]) -> Self {
+    Self {
+      tag,
+      kind: TYPE_LONG,
+      count: values.len() as u32,
+      values: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+    }
+  }
+
+  /// SHORT values if they all fit, LONG otherwise, like libtiff.
+  fn shorts_or_longs(tag: u16, values: &[u32]) -> Self {
+    if values.iter().all(|&v| v <= u16::MAX as u32) {
+      let values = values.iter().map(|&v| v as u16).collect::<Vec<_>>();
+      Self::shorts(tag, &values)
+    } else {
+      Self::longs(tag, values)
+    }
+  }
+}
+
+impl opj_image {
+  /// Write the image as a TIFF file.
+  ///
+  /// The written components must have the same size, subsampling, depth
+  /// and sign.
+  pub fn write_tiff<W: Write>(&self, options: &TiffOptions, out: &mut W) -> Result<()> {
+    let comps = self
+      .comps()
+      .filter(|comps| !comps.is_empty())
+      .ok_or_else(|| invalid_input("Image has no components"))?;
+    let (photometric, numcomps) = if self.color_space == OPJ_CLRSPC_CMYK {
+      if comps.len() < 4 {
+        return Err(invalid_input(
+          "CMYK images shall be composed of at least 4 planes",
+        ));
+      }
+      (PHOTOMETRIC_SEPARATED, 4)
+    } else if comps.len() > 2 {
+      (PHOTOMETRIC_RGB, comps.len().min(4))
+    } else {
+      (PHOTOMETRIC_MINISBLACK, comps.len())
+    };
+    let comps = &comps[..numcomps];
+    let first = &comps[0];
+    if comps.iter().any(|c| {
+      (c.w, c.h, c.dx, c.dy, c.prec, c.sgnd)
+        != (first.w, first.h, first.dx, first.dy, first.prec, first.sgnd)
+    }) {
+      return Err(invalid_input(
+        "All components shall have the same subsampling, same bit depth",
+      ));
+    }
+    let bps = first.prec;
+    if bps == 0 || bps > 16 {
+      return Err(invalid_input(
+        "TIFF output supports depths from 1 to 16 bits",
+      ));
+    }
+    let (width, height) = (first.w, first.h);
+    if width == 0 || height == 0 {
+      return Err(invalid_input("Image is empty"));
+    }
+    let planes = comps
+      .iter()
+      .map(|comp| {
+        comp
+          .data()
+          .ok_or_else(|| invalid_input("Component has no data"))
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    let sgnd = first.sgnd != 0;
+    let keep_signed = sgnd && options.keep_signed;
+    let (min, max) = if sgnd {
+      (-(1i32 << (bps - 1)), (1i32 << (bps - 1)) - 1)
+    } else {
+      (0, (1i32 << bps) - 1)
+    };
+    let adjust = if sgnd && !keep_signed {
+      1 << (bps - 1)
+    } else {
+      0
+    };
+    let mask = ((1u32 << bps) - 1) as i32;
+
+    // Strips.
+    let rows_per_strip = options.rows_per_strip.clamp(1, height);
+    let mut strips = Vec::new();
+    for first_row in (0..height).step_by(rows_per_strip as usize) {
+      let mut packer = BitPacker {
+        out: Vec::new(),
+        acc: 0,
+        nbits: 0,
+      };
+      for y in first_row..height.min(first_row + rows_per_strip) {
+        let row = y as usize * width as usize;
+        for x in row..row + width as usize {
+          for plane in &planes {
+            let v = ((plane[x].clamp(min, max) + adjust) & mask) as u32;
+            if bps == 16 {
+              packer.out.extend_from_slice(&(v as u16).to_le_bytes());
+            } else {
+              packer.put(v, bps);
+            }
+          }
+        }
+        packer.flush();
+      }
+      strips.push(match options.compression {
+        TiffCompression::None => packer.out,
+        // The default level of zlib, used by libtiff.
+        TiffCompression::Deflate => compress_to_vec_zlib(&packer.out, 6),
+      });
+    }
+
+    // The strips follow the header, then the directory on a word boundary.
+    let mut offset = 8u64;
+    let mut strip_offsets = Vec::with_capacity(strips.len());
+    let mut strip_byte_counts = Vec::with_capacity(strips.len());
+    for strip in &strips {
+      strip_offsets.push(offset as u32);
+      strip_byte_counts.push(strip.len() as u32);
+      offset += strip.len() as u64;
+    }
+    let dir_offset = (offset + 1) & !1;
+
+    // The values that don't fit in the entries are stored after the
+    // directory, in this order. They are arrays of SHORT or LONG, so they
+    // stay on word boundaries.
+    let sample_format = if keep_signed {
+      SAMPLE_FORMAT_INT
+    } else {
+      SAMPLE_FORMAT_UINT
+    };
+    let mut entries = vec![
+      Entry::shorts_or_longs(TAG_IMAGE_WIDTH, &[width]),
+      Entry::shorts_or_longs(TAG_IMAGE_LENGTH, &[height]),
+      Entry::shorts(TAG_BITS_PER_SAMPLE, &vec![bps as u16; numcomps]),
+      Entry::shorts(TAG_COMPRESSION, &[options.compression.tag_value()]),
+      Entry::shorts(TAG_PHOTOMETRIC, &[photometric]),
+      // Top left.
+      Entry::shorts(TAG_ORIENTATION, &[1]),
+      Entry::shorts(TAG_SAMPLES_PER_PIXEL, &[numcomps as u16]),
+      Entry::shorts_or_longs(TAG_ROWS_PER_STRIP, &[rows_per_strip]),
+      // Contiguous samples.
+      Entry::shorts(TAG_PLANAR_CONFIG, &[1]),
+      Entry::shorts_or_longs(TAG_STRIP_BYTE_COUNTS, &strip_byte_counts),
+      Entry::longs(TAG_STRIP_OFFSETS, &strip_offsets),
+    ];
+    if keep_signed {
+      entries.push(Entry::shorts(
+        TAG_SAMPLE_FORMAT,
+        &vec![sample_format; numcomps],
+      ));
+    }
+    let mut data_offset = dir_offset + 2 + 12 * entries.len() as u64 + 4;
+    let mut value_offsets = Vec::with_capacity(entries.len());
+    for entry in &entries {
+      if entry.values.len() > 4 {
+        value_offsets.push(Some(data_offset));
+        data_offset += entry.values.len() as u64;
+      } else {
+        value_offsets.push(None);
+      }
+    }
+    if data_offset > u32::MAX as u64 {
+      return Err(invalid_input("Image too large for TIFF"));
+    }
+
+    let mut directory = Vec::new();
+    directory.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    let mut order = (0..entries.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| entries[i].tag);
+    for &i in &order {
+      let entry = &entries[i];
+      directory.extend_from_slice(&entry.tag.to_le_bytes());
+      directory.extend_from_slice(&entry.kind.to_le_bytes());
+      directory.extend_from_slice(&entry.count.to_le_bytes());
+      match value_offsets[i] {
+        Some(offset) => directory.extend_from_slice(&(offset as u32).to_le_bytes()),
+        None => {
+          let mut value = [0u8; 4];
+          value[..entry.values.len()].copy_from_slice(&entry.values);
+          directory.extend_from_slice(&value);
+        }
+      }
+    }
+    // No next directory.
+    directory.extend_from_slice(&[0; 4]);
+    for entry in &entries {
+      if entry.values.len() > 4 {
+        directory.extend_from_slice(&entry.values);
+      }
+    }
+
+    out.write_all(b"II\x2a\x00")?;
+    out.write_all(&(dir_offset as u32).to_le_bytes())?;
+    for strip in &strips {
+      out.write_all(strip)?;
+    }
+    if offset & 1 != 0 {
+      out.write_all(&[0])?;
+    }
+    out.write_all(&directory)
+  }
+}
+
+/// Save `image` as an uncompressed TIFF file.
+#[cfg(feature = "file-io")]
+pub fn save_tiff_image<P: AsRef<Path>>(image: &opj_image, path: P) -> Result<()> {
+  save_tiff_image_with_options(image, path, &TiffOptions::default())
+}
+
+/// Save `image` as a TIFF file, with `options`.
+#[cfg(feature = "file-io")]
+pub fn save_tiff_image_with_options<P: AsRef<Path>>(
+  image: &opj_image,
+  path: P,
+  options: &TiffOptions,
+) -> Result<()> {
+  let mut out = BufWriter::new(File::create(path)?);
+  image.write_tiff(options, &mut out)?;
+  out.flush()
+}
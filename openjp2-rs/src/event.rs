@@ -94,6 +94,38 @@ impl opj_event_mgr {
     self.m_error_data = p_user_data;
   }
 
+  /// Route messages to the `log` crate with the `OPJ_LOG_TARGET` target:
+  /// errors to `error!`, warnings to `warn!` and infos to `info!`.
+  pub fn set_log_handlers(&mut self) {
+    self.set_error_handler(Some(opj_log_error_handler), std::ptr::null_mut());
+    self.set_warning_handler(Some(opj_log_warning_handler), std::ptr::null_mut());
+    self.set_info_handler(Some(opj_log_info_handler), std::ptr::null_mut());
+  }
+
+  /// Event manager for a worker thread, queueing the messages that `self`
+  /// would handle into `queue`. The handlers of the user aren't thread
+  /// safe, the queued messages are passed to them by `replay()`.
+  #[cfg(feature = "threads")]
+  pub(crate) fn queued(&self, queue: *mut opj_event_queue) -> Self {
+    let data = queue as *mut core::ffi::c_void;
+    Self {
+      m_error_data: data,
+      m_warning_data: data,
+      m_info_data: data,
+      error_handler: self.error_handler.and(Some(opj_queue_error_handler)),
+      warning_handler: self.warning_handler.and(Some(opj_queue_warning_handler)),
+      info_handler: self.info_handler.and(Some(opj_queue_info_handler)),
+    }
+  }
+
+  /// Pass the messages queued by a `queued()` event manager to the handlers.
+  #[cfg(feature = "threads")]
+  pub(crate) fn replay(&self, queue: opj_event_queue) {
+    for (event_type, msg) in queue {
+      self.msg_write(event_type, &msg);
+    }
+  }
+
   pub fn get_handler(
     &self,
     event_type: EventType,
@@ -125,6 +157,81 @@ impl opj_event_mgr {
   }
 }
 
+/// Target of the records logged by `opj_event_mgr::set_log_handlers`.
+pub const OPJ_LOG_TARGET: &str = "openjp2";
+
+/// Log a message that was already formatted by `event_msg!`.
+fn opj_log_msg(level: log::Level, msg: *const core::ffi::c_char) {
+  if msg.is_null() {
+    return;
+  }
+  let msg = unsafe { core::ffi::CStr::from_ptr(msg) }.to_string_lossy();
+  log::log!(target: OPJ_LOG_TARGET, level, "{}", msg.trim_end());
+}
+
+unsafe extern "C" fn opj_log_error_handler(
+  msg: *const core::ffi::c_char,
+  _client_data: *mut core::ffi::c_void,
+) {
+  opj_log_msg(log::Level::Error, msg);
+}
+
+unsafe extern "C" fn opj_log_warning_handler(
+  msg: *const core::ffi::c_char,
+  _client_data: *mut core::ffi::c_void,
+) {
+  opj_log_msg(log::Level::Warn, msg);
+}
+
+unsafe extern "C" fn opj_log_info_handler(
+  msg: *const core::ffi::c_char,
+  _client_data: *mut core::ffi::c_void,
+) {
+  opj_log_msg(log::Level::Info, msg);
+}
+
+/// Messages of a worker thread, see `opj_event_mgr::queued`.
+#[cfg(feature = "threads")]
+pub(crate) type opj_event_queue = Vec<(EventType, String)>;
+
+#[cfg(feature = "threads")]
+fn opj_queue_msg(
+  event_type: EventType,
+  msg: *const core::ffi::c_char,
+  queue: *mut core::ffi::c_void,
+) {
+  if msg.is_null() || queue.is_null() {
+    return;
+  }
+  let msg = unsafe { core::ffi::CStr::from_ptr(msg) }.to_string_lossy();
+  let queue = unsafe { &mut *(queue as *mut opj_event_queue) };
+  queue.push((event_type, msg.into_owned()));
+}
+
+#[cfg(feature = "threads")]
+unsafe extern "C" fn opj_queue_error_handler(
+  msg: *const core::ffi::c_char,
+  client_data: *mut core::ffi::c_void,
+) {
+  opj_queue_msg(EventType::Error, msg, client_data);
+}
+
+#[cfg(feature = "threads")]
+unsafe extern "C" fn opj_queue_warning_handler(
+  msg: *const core::ffi::c_char,
+  client_data: *mut core::ffi::c_void,
+) {
+  opj_queue_msg(EventType::Warning, msg, client_data);
+}
+
+#[cfg(feature = "threads")]
+unsafe extern "C" fn opj_queue_info_handler(
+  msg: *const core::ffi::c_char,
+  client_data: *mut core::ffi::c_void,
+) {
+  opj_queue_msg(EventType::Info, msg, client_data);
+}
+
 macro_rules! event_msg {
   ($event_mgr:expr, $event_type:expr, $fmt:expr) => {
     $event_mgr.msg_write($event_type, $fmt)
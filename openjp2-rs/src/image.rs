@@ -64,13 +64,16 @@ impl opj_image_comp {
     }
   }
 
+  /// Allocate zeroed samples for the component. A component without
+  /// samples, of zero width or height, has no buffer.
   pub fn alloc_data(&mut self) -> bool {
     self.clear_data();
-    let data_len = (self.w as usize)
-      .checked_mul(self.h as usize)
+    let data_len = self
+      .data_len()
       .and_then(|len| len.checked_mul(core::mem::size_of::<OPJ_INT32>()));
     match data_len {
       None => false,
+      Some(0) => true,
       Some(data_len) => {
         unsafe {
           self.data = opj_image_data_alloc(data_len) as *mut OPJ_INT32;
@@ -86,31 +89,261 @@ impl opj_image_comp {
     }
   }
 
+  /// Number of samples of the component, `None` if it overflows.
+  fn data_len(&self) -> Option<usize> {
+    (self.w as usize).checked_mul(self.h as usize)
+  }
+
+  /// The `w * h` samples of the component, empty for a component of zero
+  /// width or height. Returns `None` if the samples weren't allocated.
   pub fn data(&self) -> Option<&[i32]> {
-    if self.data.is_null() {
-      None
-    } else {
-      unsafe {
-        Some(std::slice::from_raw_parts(
-          self.data,
-          self.w as usize * self.h as usize,
-        ))
-      }
+    match self.data_len()? {
+      0 => Some(&[]),
+      _ if self.data.is_null() => None,
+      len => unsafe { Some(std::slice::from_raw_parts(self.data, len)) },
     }
   }
 
   pub fn data_mut(&mut self) -> Option<&mut [i32]> {
-    if self.data.is_null() {
-      None
+    match self.data_len()? {
+      0 => Some(&mut []),
+      _ if self.data.is_null() => None,
+      len => unsafe { Some(std::slice::from_raw_parts_mut(self.data, len)) },
+    }
+  }
+
+  /// The value of all the samples when they are the minimum or the maximum
+  /// of the precision of the component, e.g. an all-black or all-white
+  /// component. Stops at the first other sample.
+  pub fn saturated_value(&self) -> Option<i32> {
+    if !(1..=31).contains(&self.prec) {
+      return None;
+    }
+    let (min, max) = if self.sgnd != 0 {
+      (-(1i64 << (self.prec - 1)), (1i64 << (self.prec - 1)) - 1)
     } else {
-      unsafe {
-        Some(std::slice::from_raw_parts_mut(
-          self.data,
-          self.w as usize * self.h as usize,
-        ))
+      (0, (1i64 << self.prec) - 1)
+    };
+    let data = self.data()?;
+    let first = *data.first()?;
+    if (first as i64 != min && first as i64 != max) || data.iter().any(|&v| v != first) {
+      return None;
+    }
+    Some(first)
+  }
+}
+
+/// Bounds-checked view of an image component and its samples.
+#[derive(Debug, Copy, Clone)]
+pub struct ComponentView<'a> {
+  pub width: u32,
+  pub height: u32,
+  pub dx: u32,
+  pub dy: u32,
+  pub prec: u32,
+  pub sgnd: bool,
+  /// Alpha channel type: 0 for a colour channel, 1 for opacity and 2 for
+  /// premultiplied opacity.
+  pub alpha: u16,
+  /// `width * height` samples, empty if the component has no data.
+  pub data: &'a [i32],
+}
+
+/// Mutable view of an image component, see `ComponentView`.
+#[derive(Debug)]
+pub struct ComponentViewMut<'a> {
+  pub width: u32,
+  pub height: u32,
+  pub dx: u32,
+  pub dy: u32,
+  pub prec: u32,
+  pub sgnd: bool,
+  pub alpha: u16,
+  pub data: &'a mut [i32],
+}
+
+/// Component samples borrowed from the caller, to encode them without a
+/// copy, see `Jp2Codec::encode_borrowed`.
+#[derive(Debug)]
+pub struct ComponentRef<'a> {
+  /// `ceil(width / dx) * ceil(height / dy)` samples, row by row. They are
+  /// overwritten by the encoder.
+  pub data: &'a mut [i32],
+  pub prec: u32,
+  pub sgnd: bool,
+  pub dx: u32,
+  pub dy: u32,
+}
+
+impl opj_image_comp {
+  pub fn view(&self) -> ComponentView<'_> {
+    ComponentView {
+      width: self.w,
+      height: self.h,
+      dx: self.dx,
+      dy: self.dy,
+      prec: self.prec,
+      sgnd: self.sgnd != 0,
+      alpha: self.alpha,
+      data: self.data().unwrap_or_default(),
+    }
+  }
+
+  pub fn view_mut(&mut self) -> ComponentViewMut<'_> {
+    ComponentViewMut {
+      width: self.w,
+      height: self.h,
+      dx: self.dx,
+      dy: self.dy,
+      prec: self.prec,
+      sgnd: self.sgnd != 0,
+      alpha: self.alpha,
+      data: self.data_mut().unwrap_or_default(),
+    }
+  }
+}
+
+/// Samples of a component owned by a `Vec`, see `opj_image::into_planes`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plane {
+  pub width: u32,
+  pub height: u32,
+  pub dx: u32,
+  pub dy: u32,
+  pub prec: u32,
+  pub sgnd: bool,
+  /// `width * height` samples, empty if the component had no data.
+  pub data: Vec<i32>,
+}
+
+/// An image owning its samples and ICC profile in `Vec`s, independent of
+/// the allocator of the library, see `opj_image::into_owned`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnedImage {
+  pub x0: u32,
+  pub y0: u32,
+  pub width: u32,
+  pub height: u32,
+  pub color_space: OPJ_COLOR_SPACE,
+  /// Empty if the image has no ICC profile.
+  pub icc_profile: Vec<u8>,
+  pub components: Vec<Plane>,
+}
+
+/// Interpolation used by `opj_image::upsample_to_match`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum UpsampleFilter {
+  /// Repeat the nearest sample.
+  #[default]
+  Nearest,
+  /// Interpolate linearly between the 4 nearest samples.
+  Bilinear,
+}
+
+/// Sampling of a component along one axis: the position of its first
+/// sample and its number of samples, in units of `step` on the reference
+/// grid.
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Axis {
+  origin: u32,
+  len: u32,
+  step: u64,
+}
+
+impl Axis {
+  /// Position, in samples of `self`, of each sample of `target`.
+  fn positions(&self, target: &Axis) -> impl Iterator<Item = f64> + '_ {
+    let target = *target;
+    (0..target.len).map(move |i| {
+      /* Centre of the target sample on the reference grid */
+      let centre = (2 * (target.origin + i) as u64 + 1) * target.step;
+      centre as f64 / (2 * self.step) as f64 - 0.5 - self.origin as f64
+    })
+  }
+
+  /// Source sample indices and weight of the second one for each sample of
+  /// `target`.
+  fn taps(&self, target: &Axis, filter: UpsampleFilter) -> Vec<(usize, usize, f64)> {
+    let last = self.len as usize - 1;
+    self
+      .positions(target)
+      .map(|pos| {
+        let pos = pos.clamp(0.0, last as f64);
+        match filter {
+          UpsampleFilter::Nearest => {
+            let i = (pos.round() as usize).min(last);
+            (i, i, 0.0)
+          }
+          UpsampleFilter::Bilinear => {
+            let i = pos.floor() as usize;
+            (i, (i + 1).min(last), pos - i as f64)
+          }
+        }
+      })
+      .collect()
+  }
+}
+
+/// Resample the components decoded with the resolution reduction `factor`
+/// to the subsampling, position and size `(dx, x0, w)` and `(dy, y0, h)`.
+fn opj_image_resample_comps(
+  comps: &mut [opj_image_comp],
+  factor: u32,
+  filter: UpsampleFilter,
+  (dx, x0, w): (u32, u32, u32),
+  (dy, y0, h): (u32, u32, u32),
+) -> bool {
+  let axes = |dx: u32, dy: u32, x0: u32, y0: u32, w: u32, h: u32| {
+    (
+      Axis {
+        origin: opj_uint_ceildivpow2(x0, factor),
+        len: w,
+        step: (dx as u64) << factor,
+      },
+      Axis {
+        origin: opj_uint_ceildivpow2(y0, factor),
+        len: h,
+        step: (dy as u64) << factor,
+      },
+    )
+  };
+  let (target_x, target_y) = axes(dx, dy, x0, y0, w, h);
+  for comp in comps.iter_mut() {
+    let (axis_x, axis_y) = axes(comp.dx, comp.dy, comp.x0, comp.y0, comp.w, comp.h);
+    if axis_x == target_x && axis_y == target_y {
+      continue;
+    }
+    let taps_x = axis_x.taps(&target_x, filter);
+    let taps_y = axis_y.taps(&target_y, filter);
+    let src = comp.data().unwrap();
+    let src_w = comp.w as usize;
+    let mut data = Vec::with_capacity(w as usize * h as usize);
+    for &(y0, y1, fy) in &taps_y {
+      let (row0, row1) = (&src[y0 * src_w..], &src[y1 * src_w..]);
+      for &(x0, x1, fx) in &taps_x {
+        let v = match filter {
+          UpsampleFilter::Nearest => row0[x0],
+          UpsampleFilter::Bilinear => {
+            let top = row0[x0] as f64 * (1.0 - fx) + row0[x1] as f64 * fx;
+            let bottom = row1[x0] as f64 * (1.0 - fx) + row1[x1] as f64 * fx;
+            (top * (1.0 - fy) + bottom * fy).round() as i32
+          }
+        };
+        data.push(v);
       }
     }
+    comp.dx = dx;
+    comp.dy = dy;
+    comp.x0 = x0;
+    comp.y0 = y0;
+    comp.w = w;
+    comp.h = h;
+    if !comp.alloc_data() {
+      return false;
+    }
+    comp.data_mut().unwrap().copy_from_slice(&data);
   }
+  true
 }
 
 impl Clone for opj_image_comp {
@@ -169,6 +402,10 @@ pub struct opj_image {
   pub comps: *mut opj_image_comp_t,
   pub icc_profile_buf: *mut OPJ_BYTE,
   pub icc_profile_len: OPJ_UINT32,
+  /// The samples are the components of the codestream, without the
+  /// multiple component transform and the DC level shift, see
+  /// `opj_decoder_set_raw_components`.
+  pub raw_components: bool,
 }
 pub type opj_image_t = opj_image;
 
@@ -184,6 +421,7 @@ impl Default for opj_image {
       comps: std::ptr::null_mut(),
       icc_profile_buf: std::ptr::null_mut(),
       icc_profile_len: 0,
+      raw_components: false,
     }
   }
 }
@@ -197,6 +435,7 @@ impl Clone for opj_image {
     image.y1 = self.y1;
     image.numcomps = self.numcomps;
     image.color_space = self.color_space;
+    image.raw_components = self.raw_components;
     if let Some(comps) = self.comps() {
       if !image.alloc_comps(comps.len() as u32, true) {
         return image;
@@ -229,6 +468,7 @@ impl opj_image {
     image.y1 = self.y1;
     image.numcomps = self.numcomps;
     image.color_space = self.color_space;
+    image.raw_components = self.raw_components;
     if !self.comps.is_null() {
       image.comps = self.comps;
       image.numcomps = self.numcomps;
@@ -238,6 +478,605 @@ impl opj_image {
     image
   }
 
+  /// Move the image area on the reference grid so that it starts at
+  /// `(x0, y0)`, keeping its size.
+  ///
+  /// Fails if the new area doesn't fit on the reference grid or if the
+  /// component sizes would change because of their subsampling.
+  pub fn set_image_offset(&mut self, x0: u32, y0: u32) -> bool {
+    let x1 = match x0.checked_add(self.x1.wrapping_sub(self.x0)) {
+      Some(x1) => x1,
+      None => return false,
+    };
+    let y1 = match y0.checked_add(self.y1.wrapping_sub(self.y0)) {
+      Some(y1) => y1,
+      None => return false,
+    };
+    if let Some(comps) = self.comps() {
+      let same_size = comps.iter().all(|comp| {
+        opj_uint_ceildiv(x1, comp.dx) - opj_uint_ceildiv(x0, comp.dx) == comp.w
+          && opj_uint_ceildiv(y1, comp.dy) - opj_uint_ceildiv(y0, comp.dy) == comp.h
+      });
+      if !same_size {
+        return false;
+      }
+    }
+    self.x0 = x0;
+    self.y0 = y0;
+    self.x1 = x1;
+    self.y1 = y1;
+    if let Some(comps) = self.comps_mut() {
+      for comp in comps {
+        comp.x0 = opj_uint_ceildiv(x0, comp.dx);
+        comp.y0 = opj_uint_ceildiv(y0, comp.dy);
+      }
+    }
+    true
+  }
+
+  /// Convert the components to planes owning their samples, for images with
+  /// any number of components.
+  ///
+  /// The component buffers use the aligned allocator of the library, so the
+  /// samples are copied and each buffer is freed as soon as it was copied.
+  pub fn into_planes(mut self) -> Vec<Plane> {
+    let mut planes = Vec::with_capacity(self.numcomps as usize);
+    for comp in self.comps_mut().unwrap_or_default() {
+      planes.push(Plane {
+        width: comp.w,
+        height: comp.h,
+        dx: comp.dx,
+        dy: comp.dy,
+        prec: comp.prec,
+        sgnd: comp.sgnd != 0,
+        data: comp.data().map(|data| data.to_vec()).unwrap_or_default(),
+      });
+      comp.clear_data();
+    }
+    planes
+  }
+
+  /// Convert the image to an `OwnedImage`, see `into_planes`.
+  pub fn into_owned(self) -> OwnedImage {
+    let icc_profile = self
+      .icc_profile()
+      .map(|icc| icc.to_vec())
+      .unwrap_or_default();
+    OwnedImage {
+      x0: self.x0,
+      y0: self.y0,
+      width: self.x1 - self.x0,
+      height: self.y1 - self.y0,
+      color_space: self.color_space,
+      icc_profile,
+      components: self.into_planes(),
+    }
+  }
+
+  /// Do all components have the same subsampling and size?
+  pub fn comps_match(&self) -> bool {
+    match self.comps() {
+      Some(comps) if !comps.is_empty() => {
+        let first = &comps[0];
+        comps.iter().all(|comp| {
+          (
+            comp.dx,
+            comp.dy,
+            comp.w,
+            comp.h,
+            comp.x0,
+            comp.y0,
+            comp.factor,
+          ) == (
+            first.dx,
+            first.dy,
+            first.w,
+            first.h,
+            first.x0,
+            first.y0,
+            first.factor,
+          )
+        })
+      }
+      _ => true,
+    }
+  }
+
+  /// Upsample the components to the finest subsampling of the image, so
+  /// that `comps_match()` is true afterwards.
+  ///
+  /// The precision and sign of the components are kept. Fails if a
+  /// component is empty or has no data, or if the components were decoded
+  /// at different resolutions.
+  pub fn upsample_to_match(&mut self, filter: UpsampleFilter) -> bool {
+    let comps = match self.comps_mut() {
+      Some(comps) if !comps.is_empty() => comps,
+      _ => return true,
+    };
+    let factor = comps[0].factor;
+    if comps
+      .iter()
+      .any(|comp| comp.factor != factor || comp.data.is_null() || comp.w == 0 || comp.h == 0)
+    {
+      return false;
+    }
+    let finest_x = comps.iter().min_by_key(|comp| comp.dx).unwrap();
+    let target_x = (finest_x.dx, finest_x.x0, finest_x.w);
+    let finest_y = comps.iter().min_by_key(|comp| comp.dy).unwrap();
+    let target_y = (finest_y.dy, finest_y.y0, finest_y.h);
+    opj_image_resample_comps(comps, factor, filter, target_x, target_y)
+  }
+
+  /// Upsample the components to the full resolution of the image, with
+  /// `dx = dy = 1`, so that each component covers the image area.
+  ///
+  /// With a resolution reduction factor, the components are upsampled to
+  /// the reduced image size. Fails like `upsample_to_match()`.
+  pub fn upsample_to_full(&mut self, filter: UpsampleFilter) -> bool {
+    let (x0, y0, x1, y1) = (self.x0, self.y0, self.x1, self.y1);
+    let comps = match self.comps_mut() {
+      Some(comps) if !comps.is_empty() => comps,
+      _ => return true,
+    };
+    let factor = comps[0].factor;
+    if comps
+      .iter()
+      .any(|comp| comp.factor != factor || comp.data.is_null() || comp.w == 0 || comp.h == 0)
+    {
+      return false;
+    }
+    let w = opj_uint_ceildivpow2(x1, factor) - opj_uint_ceildivpow2(x0, factor);
+    let h = opj_uint_ceildivpow2(y1, factor) - opj_uint_ceildivpow2(y0, factor);
+    opj_image_resample_comps(comps, factor, filter, (1, x0, w), (1, y0, h))
+  }
+
+  /// Convert the image to sRGB, like the `-force-rgb` option of
+  /// `opj_decompress`: YCC and CMYK colours are converted, grey is copied
+  /// to the 3 channels. An image that is already RGB is left as is, 3 or
+  /// more components in an unspecified colour space being taken as RGB.
+  ///
+  /// Components after the colour channels, e.g. opacity, follow the RGB
+  /// components: grey with alpha and CMYK with alpha become RGBA.
+  /// Subsampled components are first upsampled with `filter`, see
+  /// `upsample_to_match()`. Fails for other colour spaces, e.g. CIELab, or
+  /// components without samples. JP2 palettes are expanded by the decoder.
+  pub fn convert_to_srgb(&mut self, filter: UpsampleFilter) -> bool {
+    let numcomps = self.numcomps;
+    if numcomps == 0
+      || self
+        .comps()
+        .is_none_or(|comps| comps.iter().any(|comp| comp.data.is_null()))
+    {
+      return false;
+    }
+    let converted = match self.color_space {
+      OPJ_CLRSPC_SRGB | OPJ_CLRSPC_UNSPECIFIED if numcomps >= 3 => true,
+      OPJ_CLRSPC_GRAY | OPJ_CLRSPC_UNSPECIFIED => self.grey_to_rgb(),
+      OPJ_CLRSPC_SYCC | OPJ_CLRSPC_EYCC if numcomps >= 3 => {
+        (self.comps_match() || self.upsample_to_match(filter)) && self.ycc_to_rgb()
+      }
+      OPJ_CLRSPC_CMYK if numcomps >= 4 => {
+        (self.comps_match() || self.upsample_to_match(filter)) && self.cmyk_to_rgb()
+      }
+      _ => false,
+    };
+    if converted {
+      self.color_space = OPJ_CLRSPC_SRGB;
+    }
+    converted
+  }
+
+  /// Copy the grey component to the 3 RGB components.
+  fn grey_to_rgb(&mut self) -> bool {
+    let comps = match self.comps() {
+      Some(comps) => comps,
+      None => return false,
+    };
+    let mut rgb: Vec<_> = std::iter::repeat_n(&comps[0], 3)
+      .chain(&comps[1..])
+      .cloned()
+      .collect();
+    if rgb.iter().any(|comp| comp.data.is_null()) {
+      for comp in &mut rgb {
+        comp.clear_data();
+      }
+      return false;
+    }
+    if !self.alloc_comps(rgb.len() as u32, true) {
+      for comp in &mut rgb {
+        comp.clear_data();
+      }
+      return false;
+    }
+    for (dest, comp) in self.comps_mut().unwrap().iter_mut().zip(rgb) {
+      *dest = comp;
+    }
+    true
+  }
+
+  /// Convert the first 3 components from sYCC or e-sYCC to RGB, with the
+  /// coefficients of `color.c` of OpenJPEG.
+  fn ycc_to_rgb(&mut self) -> bool {
+    let extended = self.color_space == OPJ_CLRSPC_EYCC;
+    let comps = match self.comps_mut() {
+      Some(comps) => comps,
+      None => return false,
+    };
+    let prec = comps[0].prec;
+    if !(1..=31).contains(&prec) {
+      return false;
+    }
+    let offset = 1i32 << (prec - 1);
+    let max = ((1u32 << prec) - 1) as i32;
+    let (cb_signed, cr_signed) = (comps[1].sgnd != 0, comps[2].sgnd != 0);
+    let (y, rest) = comps.split_at_mut(1);
+    let (cb, cr) = rest.split_at_mut(1);
+    let y = y[0].data_mut().unwrap();
+    let cb = cb[0].data_mut().unwrap();
+    let cr = cr[0].data_mut().unwrap();
+    for ((y, cb), cr) in y.iter_mut().zip(cb.iter_mut()).zip(cr.iter_mut()) {
+      let (luma, mut blue, mut red) = (*y, *cb, *cr);
+      let (r, g, b) = if extended {
+        if !cb_signed {
+          blue -= offset;
+        }
+        if !cr_signed {
+          red -= offset;
+        }
+        let (luma, blue, red) = (luma as f32, blue as f32, red as f32);
+        (
+          (luma - 0.0000368 * blue + 1.40199 * red + 0.5) as i32,
+          (1.0003 * luma - 0.344125 * blue - 0.7141128 * red + 0.5) as i32,
+          (0.999823 * luma + 1.77204 * blue - 0.000008 * red + 0.5) as i32,
+        )
+      } else {
+        blue -= offset;
+        red -= offset;
+        (
+          luma + (1.402 * red as f32) as i32,
+          luma - (0.344 * blue as f32 + 0.714 * red as f32) as i32,
+          luma + (1.772 * blue as f32) as i32,
+        )
+      };
+      *y = r.clamp(0, max);
+      *cb = g.clamp(0, max);
+      *cr = b.clamp(0, max);
+    }
+    for comp in &mut comps[..3] {
+      comp.prec = prec;
+      comp.sgnd = 0;
+    }
+    true
+  }
+
+  /// Convert the first 4 components from CMYK to 8-bit RGB, the K
+  /// component being removed.
+  fn cmyk_to_rgb(&mut self) -> bool {
+    let comps = match self.comps_mut() {
+      Some(comps) => comps,
+      None => return false,
+    };
+    if comps[..4].iter().any(|comp| !(1..=31).contains(&comp.prec)) {
+      return false;
+    }
+    let scale = |comp: &opj_image_comp| 1.0 / ((1u32 << comp.prec) - 1) as f32;
+    let (sc, sm, sy, sk) = (
+      scale(&comps[0]),
+      scale(&comps[1]),
+      scale(&comps[2]),
+      scale(&comps[3]),
+    );
+    let (cmy, rest) = comps.split_at_mut(3);
+    let k = rest[0].data().unwrap();
+    let (c, my) = cmy.split_at_mut(1);
+    let (m, y) = my.split_at_mut(1);
+    let c = c[0].data_mut().unwrap();
+    let m = m[0].data_mut().unwrap();
+    let y = y[0].data_mut().unwrap();
+    for (((c, m), y), k) in c.iter_mut().zip(m.iter_mut()).zip(y.iter_mut()).zip(k) {
+      /* CMYK values from 0 to 1 */
+      let k = 1.0 - *k as f32 * sk;
+      *c = (255.0 * (1.0 - *c as f32 * sc) * k) as i32;
+      *m = (255.0 * (1.0 - *m as f32 * sm) * k) as i32;
+      *y = (255.0 * (1.0 - *y as f32 * sy) * k) as i32;
+    }
+    for comp in &mut comps[..3] {
+      comp.prec = 8;
+      comp.sgnd = 0;
+    }
+    /* Remove K, the components array keeps its size */
+    comps[3].clear_data();
+    comps[3..].rotate_left(1);
+    self.numcomps -= 1;
+    true
+  }
+
+  /// Convert the image to interleaved 8-bit RGBA samples, row by row.
+  ///
+  /// 1 or 2 components are a grey image and 3 or 4 an RGB image. The
+  /// opacity is the last of 2 or 4 components, or `255` for the others.
+  /// Samples are scaled from the precision of their component, so bilevel
+  /// (1-bit) samples become `0` and `255`. Returns
+  /// `None` for YCC and CMYK images, components without samples or of
+  /// different sizes (see `upsample_to_match()`).
+  pub fn as_rgba8(&self) -> Option<Vec<u8>> {
+    self.as_rgba8_with_rounding(RoundingMode::default())
+  }
+
+  /// Convert the image to interleaved 8-bit RGBA samples like `as_rgba8()`,
+  /// rounding the samples deeper than 8 bits with `rounding`.
+  pub fn as_rgba8_with_rounding(&self, rounding: RoundingMode) -> Option<Vec<u8>> {
+    let comps = self.comps()?;
+    if !(1..=4).contains(&comps.len())
+      || !self.comps_match()
+      || matches!(
+        self.color_space,
+        OPJ_CLRSPC_SYCC | OPJ_CLRSPC_EYCC | OPJ_CLRSPC_CMYK
+      )
+      || comps.iter().any(|comp| !(1..=31).contains(&comp.prec))
+    {
+      return None;
+    }
+    let data = comps
+      .iter()
+      .map(|comp| comp.data())
+      .collect::<Option<Vec<_>>>()?;
+    /* Components of the RGB channels and of the opacity */
+    let (rgb, alpha) = match comps.len() {
+      1 => ([0, 0, 0], None),
+      2 => ([0, 0, 0], Some(1)),
+      3 => ([0, 1, 2], None),
+      _ => ([0, 1, 2], Some(3)),
+    };
+    let layout = PixelLayout {
+      rounding,
+      ..Default::default()
+    };
+    let mut out = vec![u8::MAX; data[0].len().checked_mul(4)?];
+    for (channel, &compno) in rgb.iter().chain(alpha.iter()).enumerate() {
+      let comp = &comps[compno];
+      for (i, &value) in data[compno].iter().enumerate() {
+        layout.write_sample(
+          &mut out,
+          i * 4 + channel,
+          i,
+          value,
+          comp.prec,
+          comp.sgnd != 0,
+        );
+      }
+    }
+    Some(out)
+  }
+
+  /// Convert the samples of each component to `f32`, e.g. for HDR or
+  /// linear workflows.
+  ///
+  /// With `normalize`, samples are divided by the largest value of the
+  /// precision of their component: unsigned samples map to [0, 1], `0` and
+  /// `2^prec - 1` to `0.0` and `1.0`, and signed ones to [-1, 1]. Otherwise
+  /// the values are kept. A component without samples gives an empty plane.
+  ///
+  /// The decoder only produces integer samples, even when a multiple
+  /// component transform with floating point elements was applied.
+  pub fn as_f32_planes(&self, normalize: bool) -> Vec<Vec<f32>> {
+    let comps = self.comps().unwrap_or_default();
+    comps
+      .iter()
+      .map(|comp| {
+        let data = comp.data().unwrap_or_default();
+        if !normalize || comp.prec == 0 {
+          return data.iter().map(|&v| v as f32).collect();
+        }
+        let bits = if comp.sgnd != 0 {
+          comp.prec - 1
+        } else {
+          comp.prec
+        };
+        let max = (((1u64 << bits.min(63)) - 1) as f64).max(1.0);
+        data
+          .iter()
+          .map(|&v| (v as f64 / max).clamp(-1.0, 1.0) as f32)
+          .collect()
+      })
+      .collect()
+  }
+
+  /// Is the image grey stored as RGB: 3 matching components, in the sRGB
+  /// or an unspecified colour space without ICC profile, whose samples are
+  /// all equal? Stops at the first differing sample.
+  pub fn is_grey_rgb(&self) -> bool {
+    if !matches!(self.color_space, OPJ_CLRSPC_SRGB | OPJ_CLRSPC_UNSPECIFIED)
+      || self.icc_profile().is_some()
+      || !self.comps_match()
+    {
+      return false;
+    }
+    let (r, g, b) = match self.comps() {
+      Some([r, g, b])
+        if (r.prec, r.sgnd) == (g.prec, g.sgnd) && (r.prec, r.sgnd) == (b.prec, b.sgnd) =>
+      {
+        (r, g, b)
+      }
+      _ => return false,
+    };
+    match (r.data(), g.data(), b.data()) {
+      (Some(r), Some(g), Some(b)) => r.iter().zip(g).zip(b).all(|((r, g), b)| r == g && r == b),
+      _ => false,
+    }
+  }
+
+  /// Collapse a grey image stored as RGB, see `is_grey_rgb()`, to a single
+  /// greyscale component. Returns whether the image was changed.
+  pub fn collapse_grey_rgb(&mut self) -> bool {
+    if !self.is_grey_rgb() {
+      return false;
+    }
+    if let Some(comps) = self.comps_mut() {
+      for comp in &mut comps[1..] {
+        comp.clear_data();
+      }
+    }
+    /* The components array keeps its size, it is freed as a whole */
+    self.numcomps = 1;
+    self.color_space = OPJ_CLRSPC_GRAY;
+    true
+  }
+
+  /// Size in bytes of the samples of all the components, at their
+  /// precision, rounded up to a whole byte. Compression ratios, e.g. of
+  /// `opj_encoder_set_layer_rates`, are relative to this size.
+  pub fn uncompressed_size(&self) -> u64 {
+    let bits: u64 = self
+      .comps()
+      .unwrap_or_default()
+      .iter()
+      .filter(|comp| comp.dx != 0 && comp.dy != 0)
+      .map(|comp| {
+        let w = opj_uint_ceildiv(self.x1, comp.dx) - opj_uint_ceildiv(self.x0, comp.dx);
+        let h = opj_uint_ceildiv(self.y1, comp.dy) - opj_uint_ceildiv(self.y0, comp.dy);
+        w as u64 * h as u64 * comp.prec as u64
+      })
+      .sum();
+    bits.div_ceil(8)
+  }
+
+  /// The largest compression ratio, which leaves a single byte of data.
+  pub fn max_compression_ratio(&self) -> f32 {
+    self.uncompressed_size() as f32
+  }
+
+  /// Check the compression ratios of quality layers, from the best quality
+  /// layer, and return them in the order of `opj_encoder_set_layer_rates`:
+  /// the most compressed layer first.
+  ///
+  /// Returns `None` if the ratios aren't strictly increasing, greater than 1
+  /// and up to `max_compression_ratio()`.
+  pub fn recommended_rates(&self, target_ratios: &[f32]) -> Option<Vec<f32>> {
+    let max_ratio = self.max_compression_ratio();
+    let valid = !target_ratios.is_empty()
+      && target_ratios
+        .iter()
+        .all(|&ratio| ratio > 1.0 && ratio <= max_ratio)
+      && target_ratios.windows(2).all(|pair| pair[0] < pair[1]);
+    if !valid {
+      return None;
+    }
+    Some(target_ratios.iter().rev().copied().collect())
+  }
+
+  /// Copy the area `x0..x1` x `y0..y1` of the reference grid, which must be
+  /// within the image. Components keep their subsampling and reduction
+  /// factor. Returns `None` for an empty area or if the samples can't be
+  /// allocated.
+  pub fn crop(&self, x0: u32, y0: u32, x1: u32, y1: u32) -> Option<Box<opj_image>> {
+    if x0 >= x1 || y0 >= y1 || x0 < self.x0 || y0 < self.y0 || x1 > self.x1 || y1 > self.y1 {
+      return None;
+    }
+    let mut image = opj_image::new();
+    opj_copy_image_header(self, &mut *image);
+    if image.numcomps != self.numcomps {
+      return None;
+    }
+    image.x0 = x0;
+    image.y0 = y0;
+    image.x1 = x1;
+    image.y1 = y1;
+    let src_comps = self.comps().unwrap_or_default();
+    let dest_comps = image.comps_mut().unwrap_or_default();
+    for (src, dest) in src_comps.iter().zip(dest_comps) {
+      let to_comp = |v: u32, d: u32| opj_uint_ceildivpow2(opj_uint_ceildiv(v, d), src.factor);
+      let (cx0, cx1) = (to_comp(x0, src.dx), to_comp(x1, src.dx));
+      let (cy0, cy1) = (to_comp(y0, src.dy), to_comp(y1, src.dy));
+      if cx0 < src.x0 || cy0 < src.y0 || cx1 > src.x0 + src.w || cy1 > src.y0 + src.h {
+        return None;
+      }
+      dest.x0 = cx0;
+      dest.y0 = cy0;
+      dest.w = cx1 - cx0;
+      dest.h = cy1 - cy0;
+      let src_data = match src.data() {
+        Some(data) => data,
+        None => continue,
+      };
+      if !dest.alloc_data() {
+        return None;
+      }
+      let (w, src_w) = (dest.w as usize, src.w as usize);
+      let (off_x, off_y) = ((cx0 - src.x0) as usize, (cy0 - src.y0) as usize);
+      if let Some(dest_data) = dest.data_mut().filter(|_| w > 0) {
+        for (y, row) in dest_data.chunks_exact_mut(w).enumerate() {
+          let start = (off_y + y) * src_w + off_x;
+          row.copy_from_slice(&src_data[start..start + w]);
+        }
+      }
+    }
+    Some(image)
+  }
+
+  /// Rotate the image clockwise by `quarter_turns` quarter turns. On an odd
+  /// number of turns the axes of the reference grid are swapped, along with
+  /// the size, offset and subsampling of every component. A multiple of 4
+  /// turns leaves the image unchanged.
+  pub fn rotate90(&mut self, quarter_turns: u8) {
+    match quarter_turns % 4 {
+      0 => (),
+      2 => {
+        self.flip_horizontal();
+        self.flip_vertical();
+      }
+      turns => {
+        core::mem::swap(&mut self.x0, &mut self.y0);
+        core::mem::swap(&mut self.x1, &mut self.y1);
+        for comp in self.comps_mut().unwrap_or_default() {
+          let (w, h) = (comp.w as usize, comp.h as usize);
+          core::mem::swap(&mut comp.w, &mut comp.h);
+          core::mem::swap(&mut comp.x0, &mut comp.y0);
+          core::mem::swap(&mut comp.dx, &mut comp.dy);
+          // The rotated component has the same number of samples, so the
+          // buffer is reused: `h` samples per row, `w` rows.
+          if let Some(data) = comp.data_mut().filter(|_| w > 0 && h > 0) {
+            let src = data.to_vec();
+            for (y, row) in data.chunks_exact_mut(h).enumerate() {
+              for (x, v) in row.iter_mut().enumerate() {
+                *v = if turns == 1 {
+                  src[(h - 1 - x) * w + y]
+                } else {
+                  src[x * w + (w - 1 - y)]
+                };
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+
+  /// Mirror every component left to right.
+  pub fn flip_horizontal(&mut self) {
+    for comp in self.comps_mut().unwrap_or_default() {
+      let w = comp.w as usize;
+      if let Some(data) = comp.data_mut().filter(|_| w > 0) {
+        for row in data.chunks_exact_mut(w) {
+          row.reverse();
+        }
+      }
+    }
+  }
+
+  /// Mirror every component top to bottom.
+  pub fn flip_vertical(&mut self) {
+    for comp in self.comps_mut().unwrap_or_default() {
+      let (w, h) = (comp.w as usize, comp.h as usize);
+      if let Some(data) = comp.data_mut().filter(|_| w > 0) {
+        for y in 0..h / 2 {
+          let (top, bottom) = data.split_at_mut((h - 1 - y) * w);
+          top[y * w..(y + 1) * w].swap_with_slice(&mut bottom[..w]);
+        }
+      }
+    }
+  }
+
   pub fn comps(&self) -> Option<&[opj_image_comp]> {
     if self.comps.is_null() {
       None
@@ -264,6 +1103,24 @@ impl opj_image {
     }
   }
 
+  /// Iterate over the components with their metadata.
+  pub fn components(&self) -> impl Iterator<Item = ComponentView<'_>> {
+    self
+      .comps()
+      .unwrap_or_default()
+      .iter()
+      .map(|comp| comp.view())
+  }
+
+  /// Iterate over the components with mutable access to their samples.
+  pub fn components_mut(&mut self) -> impl Iterator<Item = ComponentViewMut<'_>> {
+    self
+      .comps_mut()
+      .unwrap_or_default()
+      .iter_mut()
+      .map(|comp| comp.view_mut())
+  }
+
   pub fn clear_comps(&mut self) {
     unsafe {
       if let Some(comps) = self.comps() {
@@ -359,6 +1216,162 @@ impl opj_image {
   }
 }
 
+/// Magic of the cache format of `opj_image::to_cache_bytes`.
+const CACHE_MAGIC: &[u8; 8] = b"OPJIMG\x00\x01";
+
+/// Reads the fields of the cache format.
+struct CacheReader<'a>(&'a [u8]);
+
+impl<'a> CacheReader<'a> {
+  fn take(&mut self, len: usize) -> std::io::Result<&'a [u8]> {
+    if len > self.0.len() {
+      return Err(cache_error("Truncated image cache"));
+    }
+    let (head, tail) = self.0.split_at(len);
+    self.0 = tail;
+    Ok(head)
+  }
+
+  fn u32(&mut self) -> std::io::Result<u32> {
+    let bytes = self.take(4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+}
+
+fn cache_error(msg: &str) -> std::io::Error {
+  std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+impl opj_image {
+  /// Serialize the image to an uncompressed cache format: a header with the
+  /// image area, colour space, ICC profile and component parameters,
+  /// followed by the little-endian samples of each component.
+  ///
+  /// The format is only meant to be read back by `from_cache_bytes`, it is
+  /// not a stable interchange format.
+  pub fn to_cache_bytes(&self) -> Vec<u8> {
+    let comps = self.comps().unwrap_or_default();
+    let icc_profile = self.icc_profile().unwrap_or_default();
+    let samples: usize = comps
+      .iter()
+      .map(|comp| comp.data().map_or(0, |data| data.len()))
+      .sum();
+    let mut out = Vec::with_capacity(64 + icc_profile.len() + comps.len() * 48 + samples * 4);
+    out.extend_from_slice(CACHE_MAGIC);
+    for v in [self.x0, self.y0, self.x1, self.y1] {
+      out.extend_from_slice(&v.to_le_bytes());
+    }
+    out.extend_from_slice(&(self.color_space as i32).to_le_bytes());
+    out.extend_from_slice(&(icc_profile.len() as u32).to_le_bytes());
+    out.extend_from_slice(icc_profile);
+    out.extend_from_slice(&(comps.len() as u32).to_le_bytes());
+    for comp in comps {
+      let has_data = comp.data().is_some() as u32;
+      for v in [
+        comp.dx,
+        comp.dy,
+        comp.w,
+        comp.h,
+        comp.x0,
+        comp.y0,
+        comp.prec,
+        comp.sgnd,
+        comp.resno_decoded,
+        comp.factor,
+        comp.alpha as u32,
+        has_data,
+      ] {
+        out.extend_from_slice(&v.to_le_bytes());
+      }
+    }
+    for comp in comps {
+      for v in comp.data().unwrap_or_default() {
+        out.extend_from_slice(&v.to_le_bytes());
+      }
+    }
+    out
+  }
+
+  /// Read back an image serialized by `to_cache_bytes`. The magic and the
+  /// sizes are checked before allocating the samples.
+  pub fn from_cache_bytes(bytes: &[u8]) -> std::io::Result<Box<opj_image>> {
+    let mut reader = CacheReader(bytes);
+    if reader.take(CACHE_MAGIC.len())? != CACHE_MAGIC {
+      return Err(cache_error("Not an image cache"));
+    }
+    let mut image = opj_image::new();
+    image.x0 = reader.u32()?;
+    image.y0 = reader.u32()?;
+    image.x1 = reader.u32()?;
+    image.y1 = reader.u32()?;
+    image.color_space = match reader.u32()? as i32 {
+      -1 => OPJ_CLRSPC_UNKNOWN,
+      0 => OPJ_CLRSPC_UNSPECIFIED,
+      1 => OPJ_CLRSPC_SRGB,
+      2 => OPJ_CLRSPC_GRAY,
+      3 => OPJ_CLRSPC_SYCC,
+      4 => OPJ_CLRSPC_EYCC,
+      5 => OPJ_CLRSPC_CMYK,
+      _ => return Err(cache_error("Invalid colour space")),
+    };
+    let icc_len = reader.u32()? as usize;
+    let icc_profile = reader.take(icc_len)?;
+    if !icc_profile.is_empty() && !image.copy_icc_profile(icc_profile) {
+      return Err(cache_error("Failed to allocate the ICC profile"));
+    }
+    let numcomps = reader.u32()?;
+    /* Each component has a 48 bytes header */
+    if numcomps as u64 * 48 > reader.0.len() as u64 {
+      return Err(cache_error("Truncated image cache"));
+    }
+    let mut params = Vec::with_capacity(numcomps as usize);
+    let mut samples = 0u64;
+    for _ in 0..numcomps {
+      let mut fields = [0u32; 12];
+      for field in &mut fields {
+        *field = reader.u32()?;
+      }
+      if fields[11] != 0 {
+        samples += fields[2] as u64 * fields[3] as u64;
+      }
+      params.push(fields);
+    }
+    if samples * 4 != reader.0.len() as u64 {
+      return Err(cache_error("Invalid size of the image cache samples"));
+    }
+    if !image.alloc_comps(numcomps, true) {
+      return Err(cache_error("Failed to allocate the image"));
+    }
+    for (comp, fields) in image.comps_mut().unwrap().iter_mut().zip(&params) {
+      let [dx, dy, w, h, x0, y0, prec, sgnd, resno_decoded, factor, alpha, has_data] = *fields;
+      comp.dx = dx;
+      comp.dy = dy;
+      comp.w = w;
+      comp.h = h;
+      comp.x0 = x0;
+      comp.y0 = y0;
+      comp.prec = prec;
+      comp.sgnd = sgnd;
+      comp.resno_decoded = resno_decoded;
+      comp.factor = factor;
+      comp.alpha = alpha as u16;
+      if has_data == 0 {
+        continue;
+      }
+      if !comp.alloc_data() {
+        return Err(cache_error("Failed to allocate the image"));
+      }
+      let data = reader.take(w as usize * h as usize * 4)?;
+      if let Some(dest) = comp.data_mut() {
+        for (v, src) in dest.iter_mut().zip(data.chunks_exact(4)) {
+          *v = i32::from_le_bytes([src[0], src[1], src[2], src[3]]);
+        }
+      }
+    }
+    Ok(image)
+  }
+}
+
 impl Drop for opj_image {
   fn drop(&mut self) {
     self.clear_comps();
@@ -527,3 +1540,184 @@ pub fn opj_image_tile_create(
   }
   Box::into_raw(image)
 }
+
+/// Conversions between `opj_image` and `DynamicImage` of the `image` crate.
+#[cfg(feature = "dynamic-image")]
+mod dynamic_image {
+  use core::convert::TryFrom;
+  use std::io::{Error, ErrorKind};
+
+  use ::image::{DynamicImage, ImageBuffer, Pixel};
+
+  use super::*;
+
+  fn invalid_input(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+  }
+
+  /// Create an image from a buffer of unsigned `prec` bits samples, a
+  /// component per channel.
+  fn from_buffer<P: Pixel>(
+    buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+    prec: u32,
+    color_space: OPJ_COLOR_SPACE,
+  ) -> Result<Box<opj_image>, Error>
+  where
+    P::Subpixel: Into<i32>,
+  {
+    let numcomps = P::CHANNEL_COUNT as u32;
+    let (w, h) = buffer.dimensions();
+    let mut cmptparm = vec![
+      opj_image_cmptparm_t {
+        dx: 1,
+        dy: 1,
+        w,
+        h,
+        prec,
+        ..Default::default()
+      };
+      numcomps as usize
+    ];
+    let image = opj_image_create(numcomps, cmptparm.as_mut_ptr(), color_space);
+    if image.is_null() {
+      return Err(Error::other("Failed to allocate the image"));
+    }
+    let mut image = unsafe { Box::from_raw(image) };
+    image.x1 = w;
+    image.y1 = h;
+    let samples = buffer.as_raw();
+    let comps = image.comps_mut().unwrap();
+    for (compno, comp) in comps.iter_mut().enumerate() {
+      let data = comp.data_mut().unwrap();
+      let channel = samples.iter().skip(compno).step_by(numcomps as usize);
+      for (dst, src) in data.iter_mut().zip(channel) {
+        *dst = (*src).into();
+      }
+    }
+    /* LumaA and Rgba: the last channel is the opacity */
+    if numcomps.is_multiple_of(2) {
+      comps[numcomps as usize - 1].alpha = 1;
+    }
+    Ok(image)
+  }
+
+  /// Wrap interleaved samples in an image buffer.
+  fn to_buffer<P: Pixel>(
+    w: u32,
+    h: u32,
+    samples: Vec<P::Subpixel>,
+  ) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, Error> {
+    ImageBuffer::from_raw(w, h, samples).ok_or_else(|| invalid_input("Invalid image size"))
+  }
+
+  /// Interleave the samples of the components, rescaled to `[0, max]`.
+  /// Signed samples are shifted to unsigned ones first.
+  fn interleave(comps: &[opj_image_comp], data: &[&[i32]], max: u64) -> Vec<u64> {
+    let prec = comps[0].prec;
+    let in_max = (1u64 << prec) - 1;
+    let mut samples = Vec::with_capacity(data[0].len() * comps.len());
+    for i in 0..data[0].len() {
+      for (comp, data) in comps.iter().zip(data) {
+        let mut v = data[i] as i64;
+        if comp.sgnd != 0 {
+          v += 1 << (prec - 1);
+        }
+        let v = v.clamp(0, in_max as i64) as u64;
+        samples.push((v * max + in_max / 2) / in_max);
+      }
+    }
+    samples
+  }
+
+  /// Grey images with 1 or 2 (opacity) components and sRGB images with 3
+  /// or 4 components of 8 or 16 bits.
+  impl TryFrom<&DynamicImage> for Box<opj_image> {
+    type Error = Error;
+
+    fn try_from(image: &DynamicImage) -> Result<Self, Error> {
+      match image {
+        DynamicImage::ImageLuma8(buffer) => from_buffer(buffer, 8, OPJ_CLRSPC_GRAY),
+        DynamicImage::ImageLumaA8(buffer) => from_buffer(buffer, 8, OPJ_CLRSPC_GRAY),
+        DynamicImage::ImageRgb8(buffer) => from_buffer(buffer, 8, OPJ_CLRSPC_SRGB),
+        DynamicImage::ImageRgba8(buffer) => from_buffer(buffer, 8, OPJ_CLRSPC_SRGB),
+        DynamicImage::ImageLuma16(buffer) => from_buffer(buffer, 16, OPJ_CLRSPC_GRAY),
+        DynamicImage::ImageLumaA16(buffer) => from_buffer(buffer, 16, OPJ_CLRSPC_GRAY),
+        DynamicImage::ImageRgb16(buffer) => from_buffer(buffer, 16, OPJ_CLRSPC_SRGB),
+        DynamicImage::ImageRgba16(buffer) => from_buffer(buffer, 16, OPJ_CLRSPC_SRGB),
+        _ => Err(invalid_input("Only 8 and 16 bits images can be converted")),
+      }
+    }
+  }
+
+  impl TryFrom<DynamicImage> for Box<opj_image> {
+    type Error = Error;
+
+    fn try_from(image: DynamicImage) -> Result<Self, Error> {
+      Self::try_from(&image)
+    }
+  }
+
+  /// Images of 1 to 4 components of the same size and precision, up to 16
+  /// bits. Samples of up to 8 bits give an 8 bits image, others a 16 bits
+  /// image, rescaled to the full range. 1 or 2 components give a grey image
+  /// and 3 or 4 an RGB image: YCC and CMYK images must be converted first.
+  impl TryFrom<&opj_image> for DynamicImage {
+    type Error = Error;
+
+    fn try_from(image: &opj_image) -> Result<Self, Error> {
+      let comps = match image.comps() {
+        Some(comps) if (1..=4).contains(&comps.len()) => comps,
+        _ => {
+          return Err(invalid_input(
+            "Only images of 1 to 4 components can be converted",
+          ))
+        }
+      };
+      if !image.comps_match() {
+        return Err(invalid_input(
+          "Components have different sizes, see opj_image::upsample_to_match()",
+        ));
+      }
+      if matches!(
+        image.color_space,
+        OPJ_CLRSPC_SYCC | OPJ_CLRSPC_EYCC | OPJ_CLRSPC_CMYK
+      ) {
+        return Err(invalid_input("Only grey and sRGB images can be converted"));
+      }
+      let prec = comps[0].prec;
+      if !(1..=16).contains(&prec) || comps.iter().any(|comp| comp.prec != prec) {
+        return Err(invalid_input(
+          "Components must have the same precision, of up to 16 bits",
+        ));
+      }
+      let data = comps
+        .iter()
+        .map(|comp| {
+          comp
+            .data()
+            .ok_or_else(|| invalid_input("Image without samples"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+      let (w, h) = (comps[0].w, comps[0].h);
+      if prec <= 8 {
+        let samples = interleave(comps, &data, u8::MAX as u64);
+        let samples: Vec<u8> = samples.into_iter().map(|v| v as u8).collect();
+        Ok(match comps.len() {
+          1 => DynamicImage::ImageLuma8(to_buffer(w, h, samples)?),
+          2 => DynamicImage::ImageLumaA8(to_buffer(w, h, samples)?),
+          3 => DynamicImage::ImageRgb8(to_buffer(w, h, samples)?),
+          _ => DynamicImage::ImageRgba8(to_buffer(w, h, samples)?),
+        })
+      } else {
+        let samples = interleave(comps, &data, u16::MAX as u64);
+        let samples: Vec<u16> = samples.into_iter().map(|v| v as u16).collect();
+        Ok(match comps.len() {
+          1 => DynamicImage::ImageLuma16(to_buffer(w, h, samples)?),
+          2 => DynamicImage::ImageLumaA16(to_buffer(w, h, samples)?),
+          3 => DynamicImage::ImageRgb16(to_buffer(w, h, samples)?),
+          _ => DynamicImage::ImageRgba16(to_buffer(w, h, samples)?),
+        })
+      }
+    }
+  }
+}
@@ -6,6 +6,7 @@ use super::image::*;
 use super::invert::*;
 use super::math::*;
 use super::mct::*;
+use super::md5::{md5_to_hex, opj_image_md5};
 use super::openjpeg::*;
 use super::pi::*;
 use super::stream::*;
@@ -163,10 +164,12 @@ impl ProgressionStep {
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) enum ProgressionOrder {
   Unknown = 0,
   CPRL,
   PCRL,
+  RPCL,
   RLCP,
   LRCP,
 }
@@ -176,6 +179,7 @@ impl ProgressionOrder {
     match enum_prog {
       OPJ_CPRL => Self::CPRL,
       OPJ_PCRL => Self::PCRL,
+      OPJ_RPCL => Self::RPCL,
       OPJ_RLCP => Self::RLCP,
       OPJ_LRCP => Self::LRCP,
       _ => Self::Unknown,
@@ -187,6 +191,7 @@ impl ProgressionOrder {
     match self {
       Self::CPRL => &[Component, Precinct, Resolution, Layer],
       Self::PCRL => &[Precinct, Component, Resolution, Layer],
+      Self::RPCL => &[Resolution, Precinct, Component, Layer],
       Self::RLCP => &[Resolution, Layer, Component, Precinct],
       Self::LRCP => &[Layer, Resolution, Component, Precinct],
       Self::Unknown => &[],
@@ -197,6 +202,7 @@ impl ProgressionOrder {
     match self {
       Self::CPRL => "CPRL",
       Self::PCRL => "PCRL",
+      Self::RPCL => "RPCL",
       Self::RLCP => "RLCP",
       Self::LRCP => "LRCP",
       Self::Unknown => "",
@@ -930,7 +936,6 @@ fn opj_j2k_get_num_tp(
   mut tileno: OPJ_UINT32,
 ) -> OPJ_UINT32 {
   unsafe {
-    let mut i: OPJ_INT32 = 0;
     let mut tpnum = 1 as OPJ_UINT32;
     let mut tcp = std::ptr::null_mut::<opj_tcp_t>();
     let mut l_current_poc = std::ptr::null_mut::<opj_poc_t>();
@@ -952,7 +957,7 @@ fn opj_j2k_get_num_tp(
     let prog = opj_j2k_convert_progression_order((*tcp).prg);
     assert!(prog != ProgressionOrder::Unknown);
     if (*cp).m_specific_param.m_enc.m_tp_on {
-      for step in prog.get_order() {
+      for (i, step) in prog.get_order().iter().enumerate() {
         match step {
           ProgressionStep::Component => {
             /* component wise */
@@ -974,7 +979,7 @@ fn opj_j2k_get_num_tp(
         }
         /* would we split here ? */
         if (*cp).m_specific_param.m_enc.m_tp_flag == *step as u8 {
-          (*cp).m_specific_param.m_enc.m_tp_pos = i;
+          (*cp).m_specific_param.m_enc.m_tp_pos = i as OPJ_INT32;
           break;
         }
       }
@@ -1292,8 +1297,6 @@ fn opj_j2k_read_siz(
     let mut l_tmp: OPJ_UINT32 = 0;
     let mut l_tx1: OPJ_UINT32 = 0;
     let mut l_ty1: OPJ_UINT32 = 0;
-    let mut l_prec0: OPJ_UINT32 = 0;
-    let mut l_sgnd0: OPJ_UINT32 = 0;
     let mut l_image = std::ptr::null_mut::<opj_image_t>();
     let mut l_cp = std::ptr::null_mut::<opj_cp_t>();
     let mut l_img_comp = std::ptr::null_mut::<opj_image_comp_t>();
@@ -1383,6 +1386,16 @@ fn opj_j2k_read_siz(
       );
       return 0i32;
     }
+    if (*l_image).numcomps > (*l_cp).limits.max_components {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Error with SIZ marker: %d components exceed the max_components limit (%d)\n",
+        (*l_image).numcomps,
+        (*l_cp).limits.max_components,
+      );
+      return 0i32;
+    }
     if (*l_image).numcomps != l_nb_comp {
       event_msg!(p_manager, EVT_ERROR,
                       "Error with SIZ marker: number of component is not compatible with the remaining number of parameters ( %d vs %d)\n",
@@ -1464,8 +1477,6 @@ fn opj_j2k_read_siz(
       return 0i32;
     }
     l_img_comp = (*l_image).comps;
-    l_prec0 = 0 as OPJ_UINT32;
-    l_sgnd0 = 0 as OPJ_UINT32;
     /* Read the component information */
     i = 0 as OPJ_UINT32; /* Ssiz_i */
     while i < (*l_image).numcomps {
@@ -1474,20 +1485,9 @@ fn opj_j2k_read_siz(
       p_header_data = p_header_data.offset(1);
       (*l_img_comp).prec = (tmp & 0x7fu32).wrapping_add(1u32);
       (*l_img_comp).sgnd = tmp >> 7i32;
-      if p_j2k.dump_state == 0u32 {
-        if i == 0u32 {
-          l_prec0 = (*l_img_comp).prec;
-          l_sgnd0 = (*l_img_comp).sgnd
-        } else if !(*l_cp).allow_different_bit_depth_sign
-          && ((*l_img_comp).prec != l_prec0 || (*l_img_comp).sgnd != l_sgnd0)
-        {
-          event_msg!(p_manager, EVT_WARNING,
-                              "Despite JP2 BPC!=255, precision and/or sgnd values for comp[%d] is different than comp[0]:\n        [0] prec(%d) sgnd(%d) [%d] prec(%d) sgnd(%d)\n", i,
-                              l_prec0, l_sgnd0, i, (*l_img_comp).prec,
-                              (*l_img_comp).sgnd);
-        }
-        /* TODO: we should perhaps also check against JP2 BPCC values */
-      } /* XRsiz_i */
+      /* JP2 IHDR and BPCC values are checked by opj_jp2_read_header(), */
+      /* the codestream ones take precedence */
+      /* XRsiz_i */
       opj_read_bytes(p_header_data, &mut tmp, 1 as OPJ_UINT32); /* should be between 1 and 255 */
       p_header_data = p_header_data.offset(1); /* YRsiz_i */
       (*l_img_comp).dx = tmp; /* should be between 1 and 255 */
@@ -1539,6 +1539,16 @@ fn opj_j2k_read_siz(
       return 0i32;
     }
     l_nb_tiles = (*l_cp).tw.wrapping_mul((*l_cp).th);
+    if l_nb_tiles > (*l_cp).limits.max_tiles {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Error with SIZ marker: %u tiles exceed the max_tiles limit (%u)\n",
+        l_nb_tiles,
+        (*l_cp).limits.max_tiles,
+      );
+      return 0i32;
+    }
     /* Define the tiles which will be decoded */
     if p_j2k.m_specific_param.m_decoder.m_discard_tiles {
       p_j2k.m_specific_param.m_decoder.m_start_tile_x = p_j2k
@@ -1675,18 +1685,72 @@ fn opj_j2k_write_com(
   mut p_j2k: &mut opj_j2k,
   mut p_stream: &mut Stream,
   mut p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  unsafe {
+    if !p_j2k.m_cp.comment.is_null() {
+      let l_comment = core::slice::from_raw_parts(
+        p_j2k.m_cp.comment as *const OPJ_BYTE,
+        strlen(p_j2k.m_cp.comment),
+      );
+      if opj_j2k_write_com_marker(p_j2k, 1, l_comment, p_stream, p_manager) == 0 {
+        return 0i32;
+      }
+    }
+    /* Comments added with opj_encoder_add_comment() */
+    let l_comments = core::mem::take(&mut p_j2k.m_comments);
+    for (l_rcom, l_data) in &l_comments {
+      if opj_j2k_write_com_marker(p_j2k, *l_rcom, l_data, p_stream, p_manager) == 0 {
+        p_j2k.m_comments = l_comments;
+        return 0i32;
+      }
+    }
+    p_j2k.m_comments = l_comments;
+    if p_j2k.m_embed_md5 {
+      /* the digest is written by opj_j2k_write_decoded_md5() */
+      let l_text = format!("{}{}", MD5_COMMENT_PREFIX, MD5_PLACEHOLDER);
+      let l_offset = opj_stream_tell(p_stream) as usize + 6 + MD5_COMMENT_PREFIX.len();
+      if opj_j2k_write_com_marker(p_j2k, 1, l_text.as_bytes(), p_stream, p_manager) == 0 {
+        return 0i32;
+      }
+      p_j2k.m_md5_offset = l_offset;
+    }
+    1i32
+  }
+}
+
+/* *
+ * Writes a single COM marker.
+ *
+ * @param       p_j2k           J2K codec.
+ * @param       p_rcom          the registration value of the comment.
+ * @param       p_comment       the comment data.
+ * @param       p_stream        the stream to write data to.
+ * @param       p_manager       the user event manager.
+*/
+fn opj_j2k_write_com_marker(
+  mut p_j2k: &mut opj_j2k,
+  mut p_rcom: u16,
+  mut p_comment: &[u8],
+  mut p_stream: &mut Stream,
+  mut p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
   unsafe {
     let mut l_comment_size: OPJ_UINT32 = 0;
     let mut l_total_com_size: OPJ_UINT32 = 0;
-    let mut l_comment = std::ptr::null::<OPJ_CHAR>();
     let mut l_current_ptr = std::ptr::null_mut::<OPJ_BYTE>();
     /* preconditions */
     /* L_COM */
 
-    l_comment = p_j2k.m_cp.comment;
-    l_comment_size = strlen(l_comment) as OPJ_UINT32;
+    l_comment_size = p_comment.len() as OPJ_UINT32;
     l_total_com_size = l_comment_size.wrapping_add(6u32);
+    if l_total_com_size > 65537u32 {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Comment is too long to fit in a COM marker\n",
+      );
+      return 0i32;
+    }
     if l_total_com_size > p_j2k.m_specific_param.m_encoder.m_header_tile_data_size {
       let mut new_header_tile_data = opj_realloc(
         p_j2k.m_specific_param.m_encoder.m_header_tile_data as *mut core::ffi::c_void,
@@ -1715,11 +1779,11 @@ fn opj_j2k_write_com(
       2 as OPJ_UINT32,
     );
     l_current_ptr = l_current_ptr.offset(2);
-    opj_write_bytes(l_current_ptr, 1 as OPJ_UINT32, 2 as OPJ_UINT32);
+    opj_write_bytes(l_current_ptr, p_rcom as OPJ_UINT32, 2 as OPJ_UINT32);
     l_current_ptr = l_current_ptr.offset(2);
     memcpy(
       l_current_ptr as *mut core::ffi::c_void,
-      l_comment as *const core::ffi::c_void,
+      p_comment.as_ptr() as *const core::ffi::c_void,
       l_comment_size as usize,
     );
     if opj_stream_write_data(
@@ -1750,15 +1814,28 @@ fn opj_j2k_write_com(
  * @param       p_manager               the user event manager.
 */
 fn opj_j2k_read_com(
-  mut _p_j2k: &mut opj_j2k,
+  mut p_j2k: &mut opj_j2k,
   mut p_header_data: *mut OPJ_BYTE,
-  mut _p_header_size: OPJ_UINT32,
-  mut _p_manager: &mut opj_event_mgr,
+  mut p_header_size: OPJ_UINT32,
+  mut p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
-  /* preconditions */
+  unsafe {
+    let mut l_rcom: OPJ_UINT32 = 0;
+    /* preconditions */
 
-  assert!(!p_header_data.is_null());
-  1i32
+    assert!(!p_header_data.is_null());
+    if p_header_size < 2u32 {
+      event_msg!(p_manager, EVT_ERROR, "Error reading COM marker\n",);
+      return 0i32;
+    }
+    opj_read_bytes(p_header_data, &mut l_rcom, 2 as OPJ_UINT32); /* Rcom */
+    let l_data = core::slice::from_raw_parts(
+      p_header_data.offset(2) as *const OPJ_BYTE,
+      p_header_size.wrapping_sub(2u32) as usize,
+    );
+    p_j2k.m_comments.push((l_rcom as u16, l_data.to_vec()));
+    1i32
+  }
 }
 
 /* *
@@ -1898,7 +1975,7 @@ fn opj_j2k_read_cod(
     opj_read_bytes(p_header_data, &mut (*l_tcp).csty, 1 as OPJ_UINT32);
     p_header_data = p_header_data.offset(1);
     /* Make sure we know how to decode this */
-    if (*l_tcp).csty & !((0x1i32 | 0x2i32 | 0x4i32) as OPJ_UINT32) != 0u32 {
+    if (*l_tcp).csty & !((0x1i32 | 0x2i32 | 0x4i32 | 0x8i32 | 0x10i32) as OPJ_UINT32) != 0u32 {
       event_msg!(p_manager, EVT_ERROR, "Unknown Scod value in COD marker\n",); /* SGcod (A) */
       return 0i32;
     }
@@ -1925,6 +2002,16 @@ fn opj_j2k_read_cod(
       );
       return 0i32;
     }
+    if (*l_tcp).numlayers > (*l_cp).limits.max_layers {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Error with COD marker: %d layers exceed the max_layers limit (%d)\n",
+        (*l_tcp).numlayers,
+        (*l_cp).limits.max_layers,
+      );
+      return 0i32;
+    }
     /* If user didn't set a number layer to decode take the max specify in the codestream. */
     if (*l_cp).m_specific_param.m_dec.m_layer != 0 {
       (*l_tcp).num_layers_to_decode = (*l_cp).m_specific_param.m_dec.m_layer
@@ -3193,7 +3280,7 @@ fn opj_j2k_read_plm(
  * @param       p_manager               the user event manager.
 */
 fn opj_j2k_read_plt(
-  mut _p_j2k: &mut opj_j2k,
+  mut p_j2k: &mut opj_j2k,
   mut p_header_data: *mut OPJ_BYTE,
   mut p_header_size: OPJ_UINT32,
   mut p_manager: &mut opj_event_mgr,
@@ -3214,6 +3301,10 @@ fn opj_j2k_read_plt(
     opj_read_bytes(p_header_data, &mut l_Zplt, 1 as OPJ_UINT32);
     p_header_data = p_header_data.offset(1);
     p_header_size = p_header_size.wrapping_sub(1);
+    let l_lengths = p_j2k
+      .m_packet_lengths
+      .entry(p_j2k.m_current_tile_number)
+      .or_default();
     i = 0 as OPJ_UINT32;
     while i < p_header_size {
       opj_read_bytes(p_header_data, &mut l_tmp, 1 as OPJ_UINT32);
@@ -3224,6 +3315,7 @@ fn opj_j2k_read_plt(
         l_packet_len <<= 7i32
       } else {
         /* store packet length and proceed to next packet */
+        l_lengths.push(l_packet_len);
         l_packet_len = 0 as OPJ_UINT32
       }
       i += 1;
@@ -3999,6 +4091,10 @@ fn opj_j2k_read_sot(
       }
     }
     (*l_tcp).m_current_tile_part_number = l_current_part as OPJ_INT32;
+    /* The packet lengths of a tile are read again with its first tile-part */
+    if l_current_part == 0 {
+      p_j2k.m_packet_lengths.remove(&p_j2k.m_current_tile_number);
+    }
     /* USE_JPWL */
     /* look for the tile in the list of already processed tile (in parts). */
     /* Optimization possible here with a more complex data structure and with the removing of tiles */
@@ -4550,12 +4646,9 @@ fn opj_j2k_read_sod(
 
     l_tcp = &mut *p_j2k.m_cp.tcps.offset(p_j2k.m_current_tile_number as isize) as *mut opj_tcp_t;
     if p_j2k.m_specific_param.m_decoder.m_last_tile_part != 0 {
-      /* opj_stream_get_number_byte_left returns OPJ_OFF_T
-      // but we are in the last tile part,
-      // so its result will fit on OPJ_UINT32 unless we find
-      // a file with a single tile part of more than 4 GB...*/
-      p_j2k.m_specific_param.m_decoder.m_sot_length =
-        (opj_stream_get_number_byte_left(p_stream) - 2i64) as OPJ_UINT32
+      if !opj_j2k_last_tile_part_length(p_j2k, p_stream, p_manager) {
+        return 0i32;
+      }
     } else if p_j2k.m_specific_param.m_decoder.m_sot_length >= 2u32 {
       p_j2k.m_specific_param.m_decoder.m_sot_length = (p_j2k.m_specific_param.m_decoder.m_sot_length
         as core::ffi::c_uint)
@@ -4693,10 +4786,30 @@ fn opj_j2k_read_sod(
         (*l_current_data).offset(*l_tile_len as isize),
         p_j2k.m_specific_param.m_decoder.m_sot_length as OPJ_SIZE_T,
         p_manager,
-      )
+      );
+      /* The stream ended right at the start of the tile data: nothing read */
+      if l_current_read_size == -1i32 as OPJ_SIZE_T {
+        l_current_read_size = 0 as OPJ_SIZE_T
+      }
     } else {
       l_current_read_size = 0 as OPJ_SIZE_T
     }
+    if p_j2k.m_specific_param.m_decoder.m_last_tile_part != 0
+      && l_current_read_size != 0
+      && opj_j2k_contains_sot(std::slice::from_raw_parts(
+        (*l_current_data).offset(*l_tile_len as isize),
+        l_current_read_size,
+      ))
+    {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Psot=0 in tile-part %d of tile %d, but it isn't the last tile-part of the codestream\n",
+        (*l_tcp).m_current_tile_part_number,
+        p_j2k.m_current_tile_number,
+      );
+      return 0i32;
+    }
     if l_current_read_size != p_j2k.m_specific_param.m_decoder.m_sot_length as usize {
       p_j2k.m_specific_param.m_decoder.m_state = J2KState::NEOC
     } else {
@@ -4707,6 +4820,39 @@ fn opj_j2k_read_sod(
     1i32
   }
 }
+/**
+ * Sets the length left in a tile-part with Psot=0 from the stream: the
+ * tile-part runs up to the EOC marker ending the codestream.
+ */
+fn opj_j2k_last_tile_part_length(
+  p_j2k: &mut opj_j2k,
+  p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let l_length = (opj_stream_get_number_byte_left(p_stream) - 2).max(0);
+  if l_length > (u32::MAX - 2) as OPJ_OFF_T {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Tile-part with Psot=0 is too large: %d bytes left in the stream\n",
+      l_length,
+    );
+    return false;
+  }
+  p_j2k.m_specific_param.m_decoder.m_sot_length = l_length as OPJ_UINT32;
+  true
+}
+
+/**
+ * Checks if tile-part data holds a SOT marker segment (0xFF90 followed by
+ * Lsot=10). The coded data never holds marker codes from 0xFF90 on, so a
+ * tile-part with Psot=0 followed by other tile-parts gets caught reading
+ * them as its own data.
+ */
+fn opj_j2k_contains_sot(p_data: &[OPJ_BYTE]) -> bool {
+  p_data.windows(4).any(|w| w == [0xff, 0x90, 0x00, 0x0a])
+}
+
 /* *
  * Writes the RGN marker (Region Of Interest)
  *
@@ -5345,7 +5491,7 @@ fn opj_j2k_read_unk(
           let mut res = opj_j2k_add_mhmarker(
             p_j2k.cstr_index,
             J2KMarker::UNK(0),
-            (opj_stream_tell(p_stream) as OPJ_UINT32).wrapping_sub(l_size_unk) as OPJ_OFF_T,
+            opj_stream_tell(p_stream) - l_size_unk as OPJ_OFF_T,
             l_size_unk,
           );
           if res == 0i32 {
@@ -6378,16 +6524,7 @@ fn opj_j2k_read_cbd(
   }
 }
 /* *
- * Reads a CAP marker (extended capabilities definition). Empty implementation.
- * Found in HTJ2K files
- *
- * @param       p_header_data   the data contained in the CAP box.
- * @param       p_j2k                   the jpeg2000 codec.
- * @param       p_header_size   the size of the data contained in the CAP marker.
- * @param       p_manager               the user event manager.
-*/
-/* *
- * Reads a CAP marker (extended capabilities definition). Empty implementation.
+ * Reads a CAP marker (extended capabilities definition).
  * Found in HTJ2K files.
  *
  * @param       p_header_data   the data contained in the CAP box.
@@ -6396,14 +6533,72 @@ fn opj_j2k_read_cbd(
  * @param       p_manager               the user event manager.
 */
 fn opj_j2k_read_cap(
-  mut _p_j2k: &mut opj_j2k,
+  mut p_j2k: &mut opj_j2k,
   mut p_header_data: *mut OPJ_BYTE,
-  mut _p_header_size: OPJ_UINT32,
-  mut _p_manager: &mut opj_event_mgr,
+  mut p_header_size: OPJ_UINT32,
+  mut p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
-  /* preconditions */
+  unsafe {
+    let mut l_pcap: OPJ_UINT32 = 0;
+    let mut l_ccap: OPJ_UINT32 = 0;
+    /* preconditions */
 
-  assert!(!p_header_data.is_null());
+    assert!(!p_header_data.is_null());
+    if p_header_size < 4u32 {
+      event_msg!(p_manager, EVT_ERROR, "Error reading CAP marker\n",);
+      return 0i32;
+    }
+    opj_read_bytes(p_header_data, &mut l_pcap, 4 as OPJ_UINT32); /* Pcap */
+    p_header_data = p_header_data.offset(4);
+    /* One Ccap per part used */
+    if p_header_size != 4u32 + 2u32 * l_pcap.count_ones() {
+      event_msg!(p_manager, EVT_ERROR, "Error reading CAP marker\n",);
+      return 0i32;
+    }
+    if p_j2k.m_cp.rsiz as core::ffi::c_int & 0x4000i32 == 0 {
+      event_msg!(
+        p_manager,
+        EVT_WARNING,
+        "CAP marker found but Rsiz doesn't signal extended capabilities\n",
+      );
+    }
+    let l_capabilities = &mut p_j2k.m_capabilities;
+    l_capabilities.pcap = l_pcap;
+    l_capabilities.ccap.clear();
+    for _ in 0..l_pcap.count_ones() {
+      opj_read_bytes(p_header_data, &mut l_ccap, 2 as OPJ_UINT32); /* Ccap_i */
+      p_header_data = p_header_data.offset(2);
+      l_capabilities.ccap.push(l_ccap as u16);
+    }
+    1i32
+  }
+}
+
+/* *
+ * Writes the CAP marker (extended capabilities).
+ *
+ * @param       p_j2k           J2K codec.
+ * @param       p_stream        the stream to write data to.
+ * @param       p_manager       the user event manager.
+*/
+fn opj_j2k_write_cap(
+  mut p_j2k: &mut opj_j2k,
+  mut p_stream: &mut Stream,
+  mut p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  let l_capabilities = &p_j2k.m_capabilities;
+  /* Lcap includes itself and Pcap */
+  let l_lcap = 6 + 2 * l_capabilities.ccap.len();
+  let mut l_data = Vec::with_capacity(2 + l_lcap);
+  l_data.extend_from_slice(&(J2KMarker::CAP.as_u32() as u16).to_be_bytes());
+  l_data.extend_from_slice(&(l_lcap as u16).to_be_bytes());
+  l_data.extend_from_slice(&l_capabilities.pcap.to_be_bytes());
+  for l_ccap in &l_capabilities.ccap {
+    l_data.extend_from_slice(&l_ccap.to_be_bytes());
+  }
+  if opj_stream_write_data(p_stream, l_data.as_ptr(), l_data.len(), p_manager) != l_data.len() {
+    return 0i32;
+  }
   1i32
 }
 
@@ -6422,14 +6617,60 @@ fn opj_j2k_read_cap(
  * @param       p_manager               the user event manager.
 */
 fn opj_j2k_read_cpf(
-  mut _p_j2k: &mut opj_j2k,
+  mut p_j2k: &mut opj_j2k,
   mut p_header_data: *mut OPJ_BYTE,
-  mut _p_header_size: OPJ_UINT32,
-  mut _p_manager: &mut opj_event_mgr,
+  mut p_header_size: OPJ_UINT32,
+  mut p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
-  /* preconditions */
+  unsafe {
+    let mut l_pcpf: OPJ_UINT32 = 0;
+    /* preconditions */
 
-  assert!(!p_header_data.is_null());
+    assert!(!p_header_data.is_null());
+    /* One or more Pcpf */
+    if p_header_size == 0 || !p_header_size.is_multiple_of(2) {
+      event_msg!(p_manager, EVT_ERROR, "Error reading CPF marker\n",);
+      return 0i32;
+    }
+    let l_cpf = &mut p_j2k.m_capabilities.cpf;
+    l_cpf.clear();
+    for _ in 0..p_header_size / 2 {
+      opj_read_bytes(p_header_data, &mut l_pcpf, 2 as OPJ_UINT32); /* Pcpf_i */
+      p_header_data = p_header_data.offset(2);
+      l_cpf.push(l_pcpf as u16);
+    }
+    1i32
+  }
+}
+
+/* *
+ * Writes the CPF marker (corresponding profile) of a HTJ2K codestream.
+ *
+ * @param       p_j2k           J2K codec.
+ * @param       p_stream        the stream to write data to.
+ * @param       p_manager       the user event manager.
+*/
+fn opj_j2k_write_cpf(
+  mut p_j2k: &mut opj_j2k,
+  mut p_stream: &mut Stream,
+  mut p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  /* The corresponding codestream has the same Rsiz, without the CAP marker */
+  let l_cpf = match p_j2k.m_capabilities.cpf.as_slice() {
+    [] => vec![p_j2k.m_cp.rsiz & !0x4000],
+    l_cpf => l_cpf.to_vec(),
+  };
+  /* Lcpf includes itself */
+  let l_lcpf = 2 + 2 * l_cpf.len();
+  let mut l_data = Vec::with_capacity(2 + l_lcpf);
+  l_data.extend_from_slice(&(J2KMarker::CPF.as_u32() as u16).to_be_bytes());
+  l_data.extend_from_slice(&(l_lcpf as u16).to_be_bytes());
+  for l_pcpf in &l_cpf {
+    l_data.extend_from_slice(&l_pcpf.to_be_bytes());
+  }
+  if opj_stream_write_data(p_stream, l_data.as_ptr(), l_data.len(), p_manager) != l_data.len() {
+    return 0i32;
+  }
   1i32
 }
 
@@ -6446,6 +6687,144 @@ pub(crate) fn opj_j2k_decoder_set_strict_mode(mut j2k: &mut opj_j2k, mut strict:
   j2k.m_cp.strict = strict
 }
 
+pub(crate) fn opj_j2k_decoder_set_partial(j2k: &mut opj_j2k, partial: bool) {
+  j2k.m_cp.partial = partial
+}
+
+pub(crate) fn opj_j2k_decoder_set_error_resilience(j2k: &mut opj_j2k, flags: ErrorResilienceFlags) {
+  j2k.m_cp.error_resilience = flags
+}
+
+pub(crate) fn opj_j2k_decoder_set_validate_profile(j2k: &mut opj_j2k, validate: bool) {
+  j2k.m_cp.validate_profile = validate
+}
+
+pub(crate) fn opj_j2k_decoder_set_tile_parts_correction(j2k: &mut opj_j2k, enable: bool) {
+  j2k.m_cp.tile_parts_correction = enable
+}
+
+pub(crate) fn opj_j2k_decoder_set_raw_components(j2k: &mut opj_j2k, raw: bool) {
+  j2k.m_cp.raw_components = raw
+}
+
+pub(crate) fn opj_j2k_decoder_set_limits(j2k: &mut opj_j2k, limits: DecodeLimits) {
+  j2k.m_cp.limits = limits
+}
+
+pub(crate) fn opj_j2k_get_decoded_region(j2k: &opj_j2k) -> DecodedRegion {
+  j2k.m_decoded_region.clone()
+}
+
+/// Index of the tile containing the point `(x, y)` of the reference grid,
+/// `None` outside of the image or before the tile grid is known.
+pub(crate) fn opj_j2k_tile_at(j2k: &opj_j2k, x: OPJ_UINT32, y: OPJ_UINT32) -> Option<OPJ_UINT32> {
+  let image = unsafe { j2k.m_private_image.as_ref() }?;
+  let cp = &j2k.m_cp;
+  if cp.tdx == 0 || cp.tdy == 0 || x < image.x0 || y < image.y0 || x >= image.x1 || y >= image.y1 {
+    return None;
+  }
+  /* Same grid as opj_j2k_read_siz(): the first tile starts at (tx0, ty0) */
+  let p = x.checked_sub(cp.tx0)? / cp.tdx;
+  let q = y.checked_sub(cp.ty0)? / cp.tdy;
+  if p >= cp.tw || q >= cp.th {
+    return None;
+  }
+  Some(q * cp.tw + p)
+}
+
+/// Area `(x0, y0, x1, y1)` of the tile `tile_index` on the reference grid,
+/// clamped to the image at the edges of the grid.
+pub(crate) fn opj_j2k_tile_bounds(
+  j2k: &opj_j2k,
+  tile_index: OPJ_UINT32,
+) -> Option<(OPJ_UINT32, OPJ_UINT32, OPJ_UINT32, OPJ_UINT32)> {
+  let image = unsafe { j2k.m_private_image.as_ref() }?;
+  let cp = &j2k.m_cp;
+  if cp.tw == 0 || tile_index as u64 >= cp.tw as u64 * cp.th as u64 {
+    return None;
+  }
+  let (p, q) = ((tile_index % cp.tw) as u64, (tile_index / cp.tw) as u64);
+  let (tx0, ty0) = (cp.tx0 as u64, cp.ty0 as u64);
+  let (tdx, tdy) = (cp.tdx as u64, cp.tdy as u64);
+  Some((
+    (tx0 + p * tdx).max(image.x0 as u64) as OPJ_UINT32,
+    (ty0 + q * tdy).max(image.y0 as u64) as OPJ_UINT32,
+    (tx0 + (p + 1) * tdx).min(image.x1 as u64) as OPJ_UINT32,
+    (ty0 + (q + 1) * tdy).min(image.y1 as u64) as OPJ_UINT32,
+  ))
+}
+
+pub(crate) fn opj_j2k_decoder_set_dc_shift(
+  p_j2k: &mut opj_j2k,
+  compno: OPJ_UINT32,
+  shift: Option<OPJ_INT32>,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let numcomps = unsafe { p_j2k.m_private_image.as_ref() }.map(|image| image.numcomps);
+  match numcomps {
+    None => {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "opj_read_header() should be called before opj_decoder_set_dc_shift().\n",
+      );
+      false
+    }
+    Some(numcomps) if compno >= numcomps => {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Invalid component index: %u\n",
+        compno,
+      );
+      false
+    }
+    Some(numcomps) => {
+      p_j2k.m_dc_shift_overrides.resize(numcomps as usize, None);
+      p_j2k.m_dc_shift_overrides[compno as usize] = shift;
+      true
+    }
+  }
+}
+
+pub(crate) fn opj_j2k_get_dc_shift(p_j2k: &opj_j2k, compno: OPJ_UINT32) -> Option<OPJ_INT32> {
+  unsafe {
+    if p_j2k.m_is_decoder == 0 {
+      return None;
+    }
+    let image = p_j2k.m_private_image.as_ref()?;
+    let tcp = p_j2k.m_specific_param.m_decoder.m_default_tcp.as_ref()?;
+    if compno >= image.numcomps || tcp.tccps.is_null() {
+      return None;
+    }
+    Some((*tcp.tccps.add(compno as usize)).m_dc_level_shift)
+  }
+}
+
+pub(crate) fn opj_j2k_get_capabilities(j2k: &opj_j2k) -> Capabilities {
+  Capabilities {
+    rsiz: j2k.m_cp.rsiz,
+    ..j2k.m_capabilities.clone()
+  }
+}
+
+pub(crate) fn opj_j2k_get_required_features(j2k: &opj_j2k) -> FeatureSet {
+  let mut features = j2k.m_required_features;
+  if j2k.m_capabilities.is_htj2k() {
+    features |= FeatureSet::HTJ2K;
+  }
+  features
+}
+
+/// Tiles are decoded one at a time, so all the threads go to the code-blocks
+/// of the current tile component and are not shared with a tile level pool.
+#[cfg(feature = "threads")]
+pub(crate) fn opj_j2k_set_threads(mut j2k: &mut opj_j2k, mut num_threads: OPJ_UINT32) -> OPJ_BOOL {
+  j2k.m_cp.num_threads = num_threads;
+  1i32
+}
+
+#[cfg(not(feature = "threads"))]
 pub(crate) fn opj_j2k_set_threads(
   mut _j2k: &mut opj_j2k,
   mut _num_threads: OPJ_UINT32,
@@ -7293,6 +7672,14 @@ pub(crate) fn opj_j2k_setup_encoder(
     }
 
     p_j2k.m_specific_param.m_encoder.m_nb_comps = image.numcomps;
+    p_j2k.m_comp_prec = image
+      .comps()
+      .map(|comps| comps.iter().map(|comp| comp.prec).collect())
+      .unwrap_or_default();
+    p_j2k.m_comp_subsampling = image
+      .comps()
+      .map(|comps| comps.iter().map(|comp| (comp.dx, comp.dy)).collect())
+      .unwrap_or_default();
     /* keep a link to cp so that we can destroy it later in j2k_destroy_compress */
     cp = &mut p_j2k.m_cp;
     /* set default values for cp */
@@ -7628,9 +8015,8 @@ pub(crate) fn opj_j2k_setup_encoder(
     } else {
       /* Create default comment for codestream */
       let comment = format!("Created by OpenJPEG version {}", OPJ_VERSION);
-      let c_comment = alloc::ffi::CString::new(comment).unwrap();
       /* UniPG>> */
-      (*cp).comment = c_comment.into_raw();
+      (*cp).comment = opj_j2k_alloc_comment(comment.as_bytes());
       if (*cp).comment.is_null() {
         event_msg!(
           p_manager,
@@ -7643,6 +8029,32 @@ pub(crate) fn opj_j2k_setup_encoder(
     /*
     calculate other encoding parameters
     */
+    /* Same tile offset constraints as checked by opj_j2k_read_siz() */
+    if (*cp).tx0 > image.x0 || (*cp).ty0 > image.y0 {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Invalid tile origin (%u,%u): it must not be past the image origin (%u,%u)\n",
+        (*cp).tx0,
+        (*cp).ty0,
+        image.x0,
+        image.y0,
+      );
+      return 0i32;
+    }
+    if parameters.tile_size_on != 0
+      && (opj_uint_adds((*cp).tx0, (*cp).tdx) <= image.x0
+        || opj_uint_adds((*cp).ty0, (*cp).tdy) <= image.y0)
+    {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Invalid tile origin (%u,%u): the first tile doesn't intersect the image\n",
+        (*cp).tx0,
+        (*cp).ty0,
+      );
+      return 0i32;
+    }
     if parameters.tile_size_on != 0 {
       if (*cp).tdx == 0u32 {
         event_msg!(p_manager, EVT_ERROR, "Invalid tile width\n",);
@@ -8226,12 +8638,15 @@ pub(crate) fn opj_j2k_read_header(
  * Sets up the procedures to do on reading header. Developers wanting to extend the library can add their own reading procedures.
  */
 fn opj_j2k_setup_header_reading(
-  _p_j2k: &mut opj_j2k,
+  p_j2k: &mut opj_j2k,
   list: &mut opj_j2k_proc_list_t,
   _p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
   list.add(opj_j2k_read_header_procedure);
   /* DEVELOPER CORNER, add your custom procedures */
+  if p_j2k.m_cp.validate_profile {
+    list.add(opj_j2k_check_profile);
+  }
   list.add(opj_j2k_copy_default_tcp_and_create_tcd);
   1i32
 }
@@ -8583,13 +8998,62 @@ fn opj_j2k_encoding_validation(
   }
 }
 /* *
- * The default decoding validation procedure without any extension.
+ * Checks that the progression order changes of each tile cover all its
+ * packets.
  *
  * @param       p_j2k                   the jpeg2000 codec to validate.
- * @param       p_stream                                the input stream to validate.
+ * @param       p_stream                the input stream to validate.
  * @param       p_manager               the user event manager.
  *
- * @return true if the parameters are correct.
+ * @return true if the progression order changes are valid.
+ */
+fn opj_j2k_poc_validation(
+  p_j2k: &mut opj_j2k,
+  _p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  unsafe {
+    let l_nb_comps = (*p_j2k.m_private_image).numcomps;
+    for tileno in 0..p_j2k.m_cp.tw * p_j2k.m_cp.th {
+      let l_tcp = &*p_j2k.m_cp.tcps.add(tileno as usize);
+      if !l_tcp.POC {
+        continue;
+      }
+      let l_pocs = &l_tcp.pocs[..=l_tcp.numpocs as usize];
+      if l_pocs.iter().any(|poc| poc.compno0 >= l_nb_comps) {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Invalid compno0 for a POC of tile %u\n",
+          tileno,
+        );
+        return 0i32;
+      }
+      if opj_j2k_check_poc_val(
+        l_pocs.as_ptr(),
+        tileno,
+        l_pocs.len() as OPJ_UINT32,
+        (*l_tcp.tccps).numresolutions,
+        l_nb_comps,
+        l_tcp.numlayers,
+        p_manager,
+      ) == 0
+      {
+        return 0i32;
+      }
+    }
+    1i32
+  }
+}
+
+/* *
+ * The default decoding validation procedure without any extension.
+ *
+ * @param       p_j2k                   the jpeg2000 codec to validate.
+ * @param       p_stream                                the input stream to validate.
+ * @param       p_manager               the user event manager.
+ *
+ * @return true if the parameters are correct.
  */
 fn opj_j2k_decoding_validation(
   mut p_j2k: &mut opj_j2k,
@@ -8655,6 +9119,7 @@ fn opj_j2k_read_header_procedure(
         );
         return 0i32;
       }
+      p_j2k.m_required_features |= FeatureSet::from_marker(l_current_marker.as_u32());
       /* Get the marker handler from the marker ID */
       let mut l_marker_handler = l_current_marker;
       /* Manage case where marker is unknown */
@@ -8762,9 +9227,7 @@ fn opj_j2k_read_header_procedure(
         == opj_j2k_add_mhmarker(
           p_j2k.cstr_index,
           l_marker_handler,
-          (opj_stream_tell(p_stream) as OPJ_UINT32)
-            .wrapping_sub(l_marker_size)
-            .wrapping_sub(4u32) as OPJ_OFF_T,
+          opj_stream_tell(p_stream) - l_marker_size as OPJ_OFF_T - 4,
           l_marker_size.wrapping_add(4u32),
         )
       {
@@ -8819,13 +9282,184 @@ fn opj_j2k_read_header_procedure(
       "Main header has been correctly decoded.\n",
     );
     /* Position of the last element if the main header */
-    (*p_j2k.cstr_index).main_head_end =
-      (opj_stream_tell(p_stream) as OPJ_UINT32).wrapping_sub(2u32) as OPJ_OFF_T;
+    (*p_j2k.cstr_index).main_head_end = opj_stream_tell(p_stream) - 2;
     /* Next step: read a tile-part header */
     p_j2k.m_specific_param.m_decoder.m_state = J2KState::TPHSOT;
     1i32
   }
 }
+/// Check the main header of a codestream against the digital cinema or IMF
+/// profile claimed by its `Rsiz`. Violations are warnings, or errors that
+/// fail the header reading in strict mode.
+fn opj_j2k_check_profile(
+  p_j2k: &mut opj_j2k,
+  _p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  let violations = opj_j2k_profile_violations(p_j2k);
+  let event_type = if p_j2k.m_cp.strict != 0 {
+    EVT_ERROR
+  } else {
+    EVT_WARNING
+  };
+  for violation in &violations {
+    event_msg!(p_manager, event_type, "%s\n", violation.as_str());
+  }
+  (violations.is_empty() || p_j2k.m_cp.strict == 0) as OPJ_BOOL
+}
+
+/// List how the main header breaks the cinema or IMF profile of its `Rsiz`,
+/// following the checks of the encoder (`opj_j2k_is_cinema_compliant`,
+/// `opj_j2k_is_imf_compliant`).
+fn opj_j2k_profile_violations(p_j2k: &opj_j2k) -> Vec<String> {
+  let cp = &p_j2k.m_cp;
+  let rsiz = cp.rsiz as u32;
+  let profile = rsiz & 0xff00;
+  let (name, cinema) = match rsiz {
+    OPJ_PROFILE_CINEMA_2K => ("2K digital cinema", true),
+    OPJ_PROFILE_CINEMA_4K => ("4K digital cinema", true),
+    _ => match profile {
+      OPJ_PROFILE_IMF_2K => ("IMF 2K", false),
+      OPJ_PROFILE_IMF_4K => ("IMF 4K", false),
+      OPJ_PROFILE_IMF_8K => ("IMF 8K", false),
+      OPJ_PROFILE_IMF_2K_R => ("IMF 2K_R", false),
+      OPJ_PROFILE_IMF_4K_R => ("IMF 4K_R", false),
+      OPJ_PROFILE_IMF_8K_R => ("IMF 8K_R", false),
+      _ => return Vec::new(),
+    },
+  };
+  let image = match unsafe { p_j2k.m_private_image.as_ref() } {
+    Some(image) => image,
+    None => return Vec::new(),
+  };
+  let comps = image.comps().unwrap_or_default();
+  let tcp = unsafe { &*p_j2k.m_specific_param.m_decoder.m_default_tcp };
+  let tccps: &[opj_tccp_t] = if tcp.tccps.is_null() {
+    &[]
+  } else {
+    unsafe { std::slice::from_raw_parts(tcp.tccps, comps.len()) }
+  };
+  let mut violations = Vec::new();
+  let mut violation = |msg: String| violations.push(format!("{} profile: {}", name, msg));
+
+  /* Components */
+  if cinema && comps.len() != 3 {
+    violation(format!("requires 3 components, not {}", comps.len()));
+  } else if !cinema && comps.len() > 3 {
+    violation(format!(
+      "requires at most 3 components, not {}",
+      comps.len()
+    ));
+  }
+  for (compno, comp) in comps.iter().enumerate() {
+    let valid_prec = if cinema {
+      comp.prec == 12
+    } else {
+      (8..=16).contains(&comp.prec)
+    };
+    if !valid_prec || comp.sgnd != 0 {
+      violation(format!(
+        "component {} has an invalid precision ({} bits, {})",
+        compno,
+        comp.prec,
+        if comp.sgnd != 0 { "signed" } else { "unsigned" }
+      ));
+    }
+  }
+  /* Image size */
+  let (max_w, max_h) = match (rsiz, profile) {
+    (OPJ_PROFILE_CINEMA_2K, _) => (2048, 1080),
+    (OPJ_PROFILE_CINEMA_4K, _) => (4096, 2160),
+    (_, OPJ_PROFILE_IMF_2K) | (_, OPJ_PROFILE_IMF_2K_R) => (2048, 1556),
+    (_, OPJ_PROFILE_IMF_4K) | (_, OPJ_PROFILE_IMF_4K_R) => (4096, 3112),
+    _ => (8192, 6224),
+  };
+  let (w, h) = (image.x1 - image.x0, image.y1 - image.y0);
+  if w > max_w || h > max_h {
+    violation(format!(
+      "requires an image of at most {}x{}, not {}x{}",
+      max_w, max_h, w, h
+    ));
+  }
+  if image.x0 != 0 || image.y0 != 0 || cp.tx0 != 0 || cp.ty0 != 0 {
+    violation("requires the image and tile origins to be at 0,0".to_string());
+  }
+  /* Tiles */
+  let single_tile = cinema
+    || profile == OPJ_PROFILE_IMF_2K
+    || profile == OPJ_PROFILE_IMF_4K
+    || profile == OPJ_PROFILE_IMF_8K;
+  if single_tile && cp.tw * cp.th != 1 {
+    violation(format!("requires a single tile, not {}", cp.tw * cp.th));
+  } else if !single_tile && cp.tw * cp.th != 1 {
+    let valid_tile = match (cp.tdx, cp.tdy) {
+      (1024, 1024) => true,
+      (2048, 2048) => profile != OPJ_PROFILE_IMF_2K_R,
+      (4096, 4096) => profile == OPJ_PROFILE_IMF_8K_R,
+      _ => false,
+    };
+    if !valid_tile {
+      violation(format!("doesn't allow {}x{} tiles", cp.tdx, cp.tdy));
+    }
+  }
+  /* Coding style */
+  if tcp.prg != OPJ_CPRL {
+    violation(format!(
+      "requires the CPRL progression order, not {}",
+      tcp.prg
+    ));
+  }
+  if tcp.numpocs != 0 && rsiz != OPJ_PROFILE_CINEMA_4K {
+    violation("forbids progression order changes".to_string());
+  }
+  let irreversible = cinema || single_tile;
+  let max_resolutions = match rsiz {
+    OPJ_PROFILE_CINEMA_2K => 6,
+    OPJ_PROFILE_CINEMA_4K => 7,
+    _ => 33,
+  };
+  for (compno, tccp) in tccps.iter().enumerate() {
+    if tccp.cblkw != 5 || tccp.cblkh != 5 {
+      violation(format!(
+        "requires 32x32 code-blocks, not {}x{} for component {}",
+        1u32 << tccp.cblkw.min(31),
+        1u32 << tccp.cblkh.min(31),
+        compno
+      ));
+    }
+    if tccp.cblksty != 0 {
+      violation(format!(
+        "forbids code-block mode switches, set for component {}",
+        compno
+      ));
+    }
+    if irreversible && tccp.qmfbid != 0 {
+      violation(format!(
+        "requires the 9-7 irreversible transform for component {}",
+        compno
+      ));
+    } else if !irreversible && tccp.qmfbid != 1 {
+      violation(format!(
+        "requires the 5-3 reversible transform for component {}",
+        compno
+      ));
+    }
+    if tccp.numresolutions > max_resolutions {
+      violation(format!(
+        "allows at most {} resolutions, not {} for component {}",
+        max_resolutions, tccp.numresolutions, compno
+      ));
+    }
+    if tccp.roishift != 0 {
+      violation(format!(
+        "forbids regions of interest, set for component {}",
+        compno
+      ));
+    }
+  }
+  violations
+}
+
 /* *
  * Executes the given procedures on the given codec.
  *
@@ -9006,6 +9640,13 @@ fn opj_j2k_copy_default_tcp_and_create_tcd(
       l_tcp = l_tcp.offset(1);
       i += 1;
     }
+    /* Reuse the tile decoder of the previous codestream, see opj_j2k_reset_decoder() */
+    if !p_j2k.m_tcd.is_null() {
+      if opj_tcd_reinit(p_j2k.m_tcd, l_image, &mut p_j2k.m_cp) {
+        return 1i32;
+      }
+      opj_tcd_destroy(p_j2k.m_tcd);
+    }
     /* Create the current tile decoder*/
     p_j2k.m_tcd = opj_tcd_create(1i32);
     if p_j2k.m_tcd.is_null() {
@@ -9339,8 +9980,11 @@ fn opj_j2k_need_nb_tile_parts_correction(
         p_manager,
       ) != 2
       {
-        event_msg!(p_manager, EVT_ERROR, "Stream too short\n",);
-        return 0i32;
+        /* truncated SOT marker segment, it is reported when the tile is read */
+        if opj_stream_seek(p_stream, l_stream_pos_backup, p_manager) == 0 {
+          return 0i32;
+        }
+        return 1i32;
       }
       /* Read 2 bytes from the buffer as the marker size */
       opj_read_bytes(
@@ -9361,8 +10005,11 @@ fn opj_j2k_need_nb_tile_parts_correction(
         p_manager,
       ) != l_marker_size as usize
       {
-        event_msg!(p_manager, EVT_ERROR, "Stream too short\n",);
-        return 0i32;
+        /* truncated SOT marker segment, it is reported when the tile is read */
+        if opj_stream_seek(p_stream, l_stream_pos_backup, p_manager) == 0 {
+          return 0i32;
+        }
+        return 1i32;
       }
       if opj_j2k_get_sot_values(
         l_header_data.as_mut_ptr(),
@@ -9541,15 +10188,20 @@ pub(crate) fn opj_j2k_read_tile_header(
               );
               return false;
             }
+            /* Psot=0: the tile-part runs up to the EOC marker ending the codestream */
+            if l_marker_handler == J2KMarker::SOT
+              && p_j2k.m_specific_param.m_decoder.m_last_tile_part != 0
+              && !opj_j2k_last_tile_part_length(p_j2k, p_stream, p_manager)
+            {
+              return false;
+            }
             /* Add the marker to the codestream index*/
             if 0i32
               == opj_j2k_add_tlmarker(
                 p_j2k.m_current_tile_number,
                 p_j2k.cstr_index,
                 l_marker_handler,
-                (opj_stream_tell(p_stream) as OPJ_UINT32)
-                  .wrapping_sub(l_marker_size)
-                  .wrapping_sub(4u32) as OPJ_OFF_T,
+                opj_stream_tell(p_stream) - l_marker_size as OPJ_OFF_T - 4,
                 l_marker_size.wrapping_add(4u32),
               )
             {
@@ -9558,11 +10210,9 @@ pub(crate) fn opj_j2k_read_tile_header(
             }
             /* Keep the position of the last SOT marker read */
             if l_marker_handler == J2KMarker::SOT {
-              let mut sot_pos = (opj_stream_tell(p_stream) as OPJ_UINT32)
-                .wrapping_sub(l_marker_size)
-                .wrapping_sub(4u32);
-              if sot_pos as i64 > p_j2k.m_specific_param.m_decoder.m_last_sot_read_pos {
-                p_j2k.m_specific_param.m_decoder.m_last_sot_read_pos = sot_pos as OPJ_OFF_T
+              let sot_pos = opj_stream_tell(p_stream) - l_marker_size as OPJ_OFF_T - 4;
+              if sot_pos > p_j2k.m_specific_param.m_decoder.m_last_sot_read_pos {
+                p_j2k.m_specific_param.m_decoder.m_last_sot_read_pos = sot_pos
               }
             }
             if p_j2k.m_specific_param.m_decoder.m_skip_data {
@@ -9593,6 +10243,16 @@ pub(crate) fn opj_j2k_read_tile_header(
               /* Read 2 bytes from the buffer as the new marker ID */
               l_current_marker =
                 J2KMarker::from_buffer(p_j2k.m_specific_param.m_decoder.m_header_data);
+              /* An empty tile-part (Psot=12, written by kakadu) has no SOD: */
+              /* the marker read is the one following the tile-part. */
+              if l_marker_handler == J2KMarker::SOT
+                && p_j2k.m_specific_param.m_decoder.m_last_tile_part == 0
+                && p_j2k.m_specific_param.m_decoder.m_sot_length == 0
+                && l_current_marker != J2KMarker::SOD
+              {
+                p_j2k.m_specific_param.m_decoder.m_next_marker = l_current_marker.as_u32();
+                l_current_marker = J2KMarker::SOD
+              }
             }
           }
         }
@@ -9608,7 +10268,10 @@ pub(crate) fn opj_j2k_read_tile_header(
         if opj_j2k_read_sod(p_j2k, p_stream, p_manager) == 0 {
           return false;
         }
-        if p_j2k.m_specific_param.m_decoder.m_can_decode
+        /* The check reads from the next SOT marker, not read yet */
+        if p_j2k.m_cp.tile_parts_correction
+          && p_j2k.m_specific_param.m_decoder.m_can_decode
+          && p_j2k.m_specific_param.m_decoder.m_next_marker == 0
           && !p_j2k
             .m_specific_param
             .m_decoder
@@ -9664,7 +10327,10 @@ pub(crate) fn opj_j2k_read_tile_header(
         continue;
       }
       /* Try to read 2 bytes (the next marker ID) from stream and copy them into the buffer */
-      if opj_stream_read_data(
+      if p_j2k.m_specific_param.m_decoder.m_next_marker != 0 {
+        l_current_marker = J2KMarker::from(p_j2k.m_specific_param.m_decoder.m_next_marker);
+        p_j2k.m_specific_param.m_decoder.m_next_marker = 0;
+      } else if opj_stream_read_data(
         p_stream,
         p_j2k.m_specific_param.m_decoder.m_header_data,
         2 as OPJ_SIZE_T,
@@ -9761,6 +10427,62 @@ pub(crate) fn opj_j2k_read_tile_header(
   }
 }
 
+/// Check that the multiple component transform of a tile can be applied:
+/// it needs at least 3 components, the first 3 having the same
+/// sub-sampling and wavelet. An incompatible transform is an error in
+/// strict mode, otherwise it is disabled with a warning.
+fn opj_j2k_check_mct(
+  p_j2k: &opj_j2k,
+  l_tcp: &mut opj_tcp,
+  p_tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  /* Only components 0 to 2 take part in the standard transform, and it is
+   * skipped when decoding a subset of the components or raw components */
+  let numcomps_to_decode = unsafe { p_j2k.m_specific_param.m_decoder.m_numcomps_to_decode };
+  if l_tcp.mct != 1 || numcomps_to_decode != 0 || p_j2k.m_cp.raw_components {
+    return true;
+  }
+  let l_comps = unsafe { (*p_j2k.m_private_image).comps().unwrap_or_default() };
+  let reason = if l_comps.len() < 3 {
+    format!("the image has {} components", l_comps.len())
+  } else if l_comps[1..3]
+    .iter()
+    .any(|comp| (comp.dx, comp.dy) != (l_comps[0].dx, l_comps[0].dy))
+  {
+    "the first 3 components have different sub-sampling".to_string()
+  } else {
+    let l_tccps = unsafe { core::slice::from_raw_parts(l_tcp.tccps, 3) };
+    if l_tccps[1..]
+      .iter()
+      .any(|tccp| tccp.qmfbid != l_tccps[0].qmfbid)
+    {
+      "the first 3 components use different wavelets".to_string()
+    } else {
+      return true;
+    }
+  };
+  if p_j2k.m_cp.strict != 0 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Tile %d uses a multiple component transform, but %s\n",
+      p_tile_index,
+      reason.as_str(),
+    );
+    return false;
+  }
+  event_msg!(
+    p_manager,
+    EVT_WARNING,
+    "Tile %d uses a multiple component transform, but %s. Skipping it.\n",
+    p_tile_index,
+    reason.as_str(),
+  );
+  l_tcp.mct = 0;
+  true
+}
+
 pub(crate) fn opj_j2k_decode_tile(
   mut p_j2k: &mut opj_j2k,
   mut p_tile_index: OPJ_UINT32,
@@ -9785,6 +10507,17 @@ pub(crate) fn opj_j2k_decode_tile(
       opj_j2k_tcp_destroy(l_tcp);
       return 0i32;
     }
+    /* DC level shifts set by the user take precedence over the codestream */
+    for (compno, shift) in p_j2k.m_dc_shift_overrides.iter().enumerate() {
+      if let Some(shift) = shift {
+        (*(*l_tcp).tccps.add(compno)).m_dc_level_shift = *shift;
+      }
+    }
+    if !opj_j2k_check_mct(p_j2k, &mut *l_tcp, p_tile_index, p_manager) {
+      opj_j2k_tcp_destroy(l_tcp);
+      p_j2k.m_specific_param.m_decoder.m_state |= J2KState::ERR;
+      return 0i32;
+    }
     /* When using the opj_read_tile_header / opj_decode_tile_data API */
     /* such as in test_tile_decoder, m_output_image is NULL, so fall back */
     /* to the full image dimension. This is a bit surprising that */
@@ -9836,11 +10569,23 @@ pub(crate) fn opj_j2k_decode_tile(
       return 1i32;
     }
     if p_j2k.m_specific_param.m_decoder.m_state != J2KState::EOC {
-      if opj_stream_read_data(p_stream, l_data.as_mut_ptr(), 2 as OPJ_SIZE_T, p_manager) != 2 {
-        event_msg!(p_manager, EVT_ERROR, "Stream too short\n",);
-        return 0i32;
+      if p_j2k.m_specific_param.m_decoder.m_next_marker != 0 {
+        /* Already read after an empty tile-part */
+        l_current_marker = J2KMarker::from(p_j2k.m_specific_param.m_decoder.m_next_marker);
+        p_j2k.m_specific_param.m_decoder.m_next_marker = 0;
+      } else {
+        if opj_stream_get_number_byte_left(p_stream) == 0i64 {
+          /* The codestream ends right after the data of this tile */
+          p_j2k.m_specific_param.m_decoder.m_state = J2KState::NEOC;
+          event_msg!(p_manager, EVT_WARNING, "Stream does not end with EOC\n",);
+          return 1i32;
+        }
+        if opj_stream_read_data(p_stream, l_data.as_mut_ptr(), 2 as OPJ_SIZE_T, p_manager) != 2 {
+          event_msg!(p_manager, EVT_ERROR, "Stream too short\n",);
+          return 0i32;
+        }
+        l_current_marker = J2KMarker::from_buffer(l_data.as_mut_ptr());
       }
-      l_current_marker = J2KMarker::from_buffer(l_data.as_mut_ptr());
       if l_current_marker == J2KMarker::EOC {
         p_j2k.m_current_tile_number = 0 as OPJ_UINT32;
         p_j2k.m_specific_param.m_decoder.m_state = J2KState::EOC
@@ -10216,6 +10961,30 @@ pub(crate) fn opj_j2k_set_decoded_components(
   }
 }
 
+/// Indices of the components selected by `opj_j2k_set_decoded_components`,
+/// in their decoded order, `None` when all the components are decoded.
+pub(crate) fn opj_j2k_decoded_components(p_j2k: &opj_j2k) -> Option<Vec<OPJ_UINT32>> {
+  let l_decoder = unsafe { &p_j2k.m_specific_param.m_decoder };
+  if l_decoder.m_numcomps_to_decode == 0 || l_decoder.m_comps_indices_to_decode.is_null() {
+    return None;
+  }
+  Some(
+    unsafe {
+      core::slice::from_raw_parts(
+        l_decoder.m_comps_indices_to_decode,
+        l_decoder.m_numcomps_to_decode as usize,
+      )
+    }
+    .to_vec(),
+  )
+}
+
+/// Whether `opj_j2k_set_decode_area` restricted the decoding to a part of
+/// the image.
+pub(crate) fn opj_j2k_has_decode_area(p_j2k: &opj_j2k) -> bool {
+  unsafe { p_j2k.m_specific_param.m_decoder.m_discard_tiles }
+}
+
 pub(crate) fn opj_j2k_set_decode_area(
   mut p_j2k: &mut opj_j2k,
   mut p_image: &mut opj_image,
@@ -10245,10 +11014,11 @@ pub(crate) fn opj_j2k_set_decode_area(
       }
     }
     /* Update the comps[].factor member of the output image with the one */
-    /* of m_reduce */
+    /* of the codec, which can differ between components */
     it_comp = 0 as OPJ_UINT32;
     while it_comp < p_image.numcomps {
-      (*p_image.comps.offset(it_comp as isize)).factor = p_j2k.m_cp.m_specific_param.m_dec.m_reduce;
+      (*p_image.comps.offset(it_comp as isize)).factor =
+        (*(*l_image).comps.offset(it_comp as isize)).factor;
       it_comp += 1;
     }
     if p_start_x == 0 && p_start_y == 0 && p_end_x == 0 && p_end_y == 0 {
@@ -10437,6 +11207,19 @@ impl opj_j2k {
         ihdr_w: 0,
         ihdr_h: 0,
         dump_state: 0,
+        m_comments: Vec::new(),
+        m_comp_prec: Vec::new(),
+        m_comp_subsampling: Vec::new(),
+        m_decoded_region: DecodedRegion::default(),
+        m_capabilities: Capabilities::default(),
+        m_required_features: FeatureSet::empty(),
+        m_dc_shift_overrides: Vec::new(),
+        m_embed_md5: false,
+        m_md5_codestream: None,
+        m_md5_offset: 0,
+        m_packet_lengths: Default::default(),
+        m_layout: CodestreamLayout::TileFirst,
+        m_tile_parts: Vec::new(),
       }
     }
   }
@@ -10445,12 +11228,11 @@ impl opj_j2k {
 pub(crate) fn opj_j2k_create_decompress() -> Option<opj_j2k> {
   let mut l_j2k = opj_j2k::new(1);
   l_j2k.m_cp.m_is_decoder = true;
-  /* in the absence of JP2 boxes, consider different bit depth / sign */
-  /* per component is allowed */
   unsafe {
-    l_j2k.m_cp.allow_different_bit_depth_sign = true;
     /* Default to using strict mode. */
     l_j2k.m_cp.strict = 1i32;
+    l_j2k.m_cp.limits = DecodeLimits::default();
+    l_j2k.m_cp.tile_parts_correction = true;
     l_j2k.m_specific_param.m_decoder.m_default_tcp =
       opj_calloc(1i32 as size_t, core::mem::size_of::<opj_tcp_t>()) as *mut opj_tcp_t;
     if l_j2k.m_specific_param.m_decoder.m_default_tcp.is_null() {
@@ -10473,6 +11255,32 @@ pub(crate) fn opj_j2k_create_decompress() -> Option<opj_j2k> {
   Some(l_j2k)
 }
 
+/// Reset a decoder to read a new codestream.
+///
+/// The settings of `opj_j2k_setup_decoder()`, the strict, partial and
+/// error resilience modes and the number of threads are kept, as is the
+/// tile decoder: its buffers are reused by the next codestream when its
+/// tiles have as many components, and only grown when they are larger.
+pub(crate) fn opj_j2k_reset_decoder(p_j2k: &mut opj_j2k) -> bool {
+  let mut l_j2k = match opj_j2k_create_decompress() {
+    Some(l_j2k) => l_j2k,
+    None => return false,
+  };
+  l_j2k.m_cp.m_specific_param.m_dec = unsafe { p_j2k.m_cp.m_specific_param.m_dec };
+  l_j2k.m_cp.strict = p_j2k.m_cp.strict;
+  l_j2k.m_cp.partial = p_j2k.m_cp.partial;
+  l_j2k.m_cp.error_resilience = p_j2k.m_cp.error_resilience;
+  l_j2k.m_cp.limits = p_j2k.m_cp.limits;
+  l_j2k.m_cp.validate_profile = p_j2k.m_cp.validate_profile;
+  l_j2k.m_cp.tile_parts_correction = p_j2k.m_cp.tile_parts_correction;
+  l_j2k.m_cp.raw_components = p_j2k.m_cp.raw_components;
+  l_j2k.m_cp.num_threads = p_j2k.m_cp.num_threads;
+  l_j2k.dump_state = p_j2k.dump_state;
+  l_j2k.m_tcd = std::mem::replace(&mut p_j2k.m_tcd, std::ptr::null_mut());
+  *p_j2k = l_j2k;
+  true
+}
+
 fn opj_j2k_create_cstr_index() -> *mut opj_codestream_index_t {
   unsafe {
     let mut cstr_index = opj_calloc(
@@ -10719,12 +11527,23 @@ fn opj_j2k_read_SPCod_SPCoc(
       );
       return 0i32;
     }
+    if (*l_tccp).numresolutions > (*l_cp).limits.max_resolutions {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Error with SPCod SPCoc element: %d resolutions exceed the max_resolutions limit (%d)\n",
+        (*l_tccp).numresolutions,
+        (*l_cp).limits.max_resolutions,
+      );
+      return 0i32;
+    }
     l_current_ptr = l_current_ptr.offset(1);
     /* If user wants to remove more resolutions than the codestream contains, return error */
-    if (*l_cp).m_specific_param.m_dec.m_reduce >= (*l_tccp).numresolutions {
+    let l_reduce = (*(*p_j2k.m_private_image).comps.add(compno as usize)).factor;
+    if l_reduce >= (*l_tccp).numresolutions {
       event_msg!(p_manager, EVT_ERROR,
                       "Error decoding component %d.\nThe number of resolutions to remove (%d) is greater or equal than the number of resolutions of this component (%d)\nModify the cp_reduce parameter.\n\n", compno,
-                      (*l_cp).m_specific_param.m_dec.m_reduce,
+                      l_reduce,
                       (*l_tccp).numresolutions);
       p_j2k.m_specific_param.m_decoder.m_state |= J2KState::ERR;
       return 0i32;
@@ -11652,12 +12471,12 @@ pub(crate) fn j2k_get_cstr_info(mut p_j2k: &mut opj_j2k) -> *mut opj_codestream_
         memcpy(
           (*l_tccp_info).prch.as_mut_ptr() as *mut core::ffi::c_void,
           (*l_tccp).prch.as_mut_ptr() as *const core::ffi::c_void,
-          (*l_tccp).numresolutions as usize,
+          ((*l_tccp).numresolutions as usize).wrapping_mul(core::mem::size_of::<OPJ_UINT32>()),
         );
         memcpy(
           (*l_tccp_info).prcw.as_mut_ptr() as *mut core::ffi::c_void,
           (*l_tccp).prcw.as_mut_ptr() as *const core::ffi::c_void,
-          (*l_tccp).numresolutions as usize,
+          ((*l_tccp).numresolutions as usize).wrapping_mul(core::mem::size_of::<OPJ_UINT32>()),
         );
       }
       /* quantization style*/
@@ -11927,6 +12746,89 @@ fn opj_j2k_are_all_used_components_decoded(
     1i32
   }
 }
+/* *
+ * Stops decoding after a tile failed to be read or decoded.
+ *
+ * In partial mode, the image is returned with the tiles decoded so far and
+ * zeros elsewhere.
+ */
+fn opj_j2k_end_partial_decoding(p_j2k: &mut opj_j2k, p_manager: &mut opj_event_mgr) -> OPJ_BOOL {
+  for status in p_j2k
+    .m_decoded_region
+    .tile_status
+    .iter_mut()
+    .filter(|status| !status.decoded && status.error.is_none())
+  {
+    status.error = Some("Codestream is truncated or corrupted".into());
+  }
+  if !p_j2k.m_cp.partial {
+    return 0i32;
+  }
+  unsafe {
+    /* Free the compressed data of the incomplete tile and of tiles that
+     * won't be decoded */
+    let l_nb_tiles = p_j2k.m_cp.tw.wrapping_mul(p_j2k.m_cp.th) as usize;
+    for tileno in 0..l_nb_tiles {
+      opj_j2k_tcp_data_destroy(p_j2k.m_cp.tcps.add(tileno));
+    }
+    let output_image = &mut *p_j2k.m_output_image;
+    if let Some(comps) = output_image.comps_mut() {
+      for comp in comps.iter_mut().filter(|comp| comp.data.is_null()) {
+        if !comp.alloc_data() {
+          event_msg!(p_manager, EVT_ERROR, "Not enough memory to decode image\n",);
+          return 0i32;
+        }
+      }
+    }
+  }
+  p_j2k.m_decoded_region.truncated = true;
+  event_msg!(
+    p_manager,
+    EVT_WARNING,
+    "Codestream is truncated or corrupted, only %d of %d tiles were decoded\n",
+    p_j2k.m_decoded_region.tiles.len(),
+    p_j2k.m_decoded_region.nb_tiles,
+  );
+  1i32
+}
+
+/* *
+ * Records the size of the compressed data of a tile about to be decoded.
+ */
+fn opj_j2k_start_tile_status(p_j2k: &mut opj_j2k, tile_index: OPJ_UINT32) {
+  let bytes = unsafe { (*p_j2k.m_cp.tcps.add(tile_index as usize)).m_data_size };
+  if let Some(status) = opj_j2k_tile_status_mut(p_j2k, tile_index) {
+    status.bytes = bytes as u64;
+  }
+}
+
+/* *
+ * Marks a tile as decoded.
+ */
+fn opj_j2k_end_tile_status(p_j2k: &mut opj_j2k, tile_index: OPJ_UINT32) {
+  p_j2k.m_decoded_region.tiles.push(tile_index);
+  if let Some(status) = opj_j2k_tile_status_mut(p_j2k, tile_index) {
+    status.decoded = true;
+  }
+}
+
+/* *
+ * Marks a tile as failed to decode.
+ */
+fn opj_j2k_fail_tile_status(p_j2k: &mut opj_j2k, tile_index: OPJ_UINT32) {
+  if let Some(status) = opj_j2k_tile_status_mut(p_j2k, tile_index) {
+    status.error = Some("Failed to decode tile".into());
+  }
+}
+
+fn opj_j2k_tile_status_mut(p_j2k: &mut opj_j2k, tile_index: OPJ_UINT32) -> Option<&mut TileStatus> {
+  p_j2k
+    .m_decoded_region
+    .tile_status
+    .iter_mut()
+    .find(|status| status.index == tile_index)
+}
+
 /* *
  * Reads the tiles.
  */
@@ -11938,6 +12840,21 @@ fn opj_j2k_decode_tiles(
   unsafe {
     let mut tile_info = TileInfo::default();
     let mut nr_tiles = 0 as OPJ_UINT32;
+    /* Only the tiles intersecting the decoded area are read */
+    let l_decoder = &p_j2k.m_specific_param.m_decoder;
+    let tw = p_j2k.m_cp.tw;
+    p_j2k.m_decoded_region = DecodedRegion {
+      nb_tiles: (l_decoder.m_end_tile_x - l_decoder.m_start_tile_x)
+        * (l_decoder.m_end_tile_y - l_decoder.m_start_tile_y),
+      tile_status: (l_decoder.m_start_tile_y..l_decoder.m_end_tile_y)
+        .flat_map(|y| (l_decoder.m_start_tile_x..l_decoder.m_end_tile_x).map(move |x| y * tw + x))
+        .map(|index| TileStatus {
+          index,
+          ..Default::default()
+        })
+        .collect(),
+      ..Default::default()
+    };
     /* Particular case for whole single tile decoding */
     /* We can avoid allocating intermediate tile buffers */
     if p_j2k.m_cp.tw == 1u32
@@ -11951,12 +12868,15 @@ fn opj_j2k_decode_tiles(
     {
       let mut i: OPJ_UINT32 = 0;
       if !opj_j2k_read_tile_header(p_j2k, p_stream, &mut tile_info, p_manager) {
-        return 0i32;
+        return opj_j2k_end_partial_decoding(p_j2k, p_manager);
       }
+      opj_j2k_start_tile_status(p_j2k, tile_info.index);
       if opj_j2k_decode_tile(p_j2k, tile_info.index, None, p_stream, p_manager) == 0 {
         event_msg!(p_manager, EVT_ERROR, "Failed to decode tile 1/1\n",);
-        return 0i32;
+        opj_j2k_fail_tile_status(p_j2k, tile_info.index);
+        return opj_j2k_end_partial_decoding(p_j2k, p_manager);
       }
+      opj_j2k_end_tile_status(p_j2k, tile_info.index);
       /* Transfer TCD data to output image data */
       i = 0 as OPJ_UINT32;
       while i < (*p_j2k.m_output_image).numcomps {
@@ -11989,12 +12909,13 @@ fn opj_j2k_decode_tiles(
         p_j2k.m_specific_param.m_decoder.m_state |= J2KState::DATA
       } else {
         if !opj_j2k_read_tile_header(p_j2k, p_stream, &mut tile_info, p_manager) {
-          return 0i32;
+          return opj_j2k_end_partial_decoding(p_j2k, p_manager);
         }
         if !tile_info.go_on {
           break;
         }
       }
+      opj_j2k_start_tile_status(p_j2k, tile_info.index);
       if opj_j2k_decode_tile(p_j2k, tile_info.index, None, p_stream, p_manager) == 0 {
         event_msg!(
           p_manager,
@@ -12003,7 +12924,8 @@ fn opj_j2k_decode_tiles(
           tile_info.index.wrapping_add(1u32),
           p_j2k.m_cp.th.wrapping_mul(p_j2k.m_cp.tw),
         );
-        return 0i32;
+        opj_j2k_fail_tile_status(p_j2k, tile_info.index);
+        return opj_j2k_end_partial_decoding(p_j2k, p_manager);
       }
       event_msg!(
         p_manager,
@@ -12015,6 +12937,7 @@ fn opj_j2k_decode_tiles(
       if opj_j2k_update_image_data(p_j2k.m_tcd, &mut *p_j2k.m_output_image) == 0 {
         return 0i32;
       }
+      opj_j2k_end_tile_status(p_j2k, tile_info.index);
       if !(p_j2k.m_cp.tw == 1u32
         && p_j2k.m_cp.th == 1u32
         && !((*p_j2k.m_output_image).x0 == (*p_j2k.m_private_image).x0
@@ -12040,6 +12963,12 @@ fn opj_j2k_decode_tiles(
         break;
       }
     }
+    if p_j2k.m_cp.partial
+      && (p_j2k.m_decoded_region.tiles.len() as OPJ_UINT32) < p_j2k.m_decoded_region.nb_tiles
+    {
+      /* The codestream ended before all tiles were found */
+      return opj_j2k_end_partial_decoding(p_j2k, p_manager);
+    }
     if opj_j2k_are_all_used_components_decoded(p_j2k, p_manager) == 0 {
       return 0i32;
     }
@@ -12119,6 +13048,7 @@ fn opj_j2k_decode_one_tile(
       if p_j2k.m_specific_param.m_decoder.m_state == J2KState::EOC {
         p_j2k.m_specific_param.m_decoder.m_state = J2KState::TPHSOT
       }
+      p_j2k.m_specific_param.m_decoder.m_next_marker = 0;
     }
     /* Reset current tile part number for all tiles, and not only the one */
     /* of interest. */
@@ -12276,6 +13206,7 @@ fn opj_j2k_move_data_from_codec_to_output_image(
         compno += 1;
       }
     }
+    p_image.raw_components = p_j2k.m_cp.raw_components;
     1i32
   }
 }
@@ -12288,25 +13219,25 @@ pub(crate) fn opj_j2k_decode(
 ) -> OPJ_BOOL {
   unsafe {
     /* Heuristics to detect sequence opj_read_header(), opj_set_decoded_resolution_factor() */
-    /* and finally opj_decode_image() without manual setting of comps[].factor */
+    /* (or opj_set_component_reduce()) and finally opj_decode_image() without manual */
+    /* setting of comps[].factor */
     /* We could potentially always execute it, if we don't allow people to do */
     /* opj_read_header(), modify x0,y0,x1,y1 of returned image an call opj_decode_image() */
-    if p_j2k.m_cp.m_specific_param.m_dec.m_reduce > 0u32
-      && !p_j2k.m_private_image.is_null()
-      && (*p_j2k.m_private_image).numcomps > 0u32
-      && (*(*p_j2k.m_private_image).comps.offset(0)).factor
-        == p_j2k.m_cp.m_specific_param.m_dec.m_reduce
-      && p_image.numcomps > 0u32
+    if !p_j2k.m_private_image.is_null()
+      && (*p_j2k.m_private_image)
+        .comps()
+        .is_some_and(|comps| comps.iter().any(|comp| comp.factor > 0))
+      && (*p_j2k.m_private_image).numcomps == p_image.numcomps
       && (*p_image.comps.offset(0)).factor == 0u32
       && (*p_image.comps.offset(0)).data.is_null()
     {
       let mut it_comp: OPJ_UINT32 = 0;
       /* Update the comps[].factor member of the output image with the one */
-      /* of m_reduce */
+      /* of the codec */
       it_comp = 0 as OPJ_UINT32;
       while it_comp < p_image.numcomps {
         (*p_image.comps.offset(it_comp as isize)).factor =
-          p_j2k.m_cp.m_specific_param.m_dec.m_reduce;
+          (*(*p_j2k.m_private_image).comps.offset(it_comp as isize)).factor;
         it_comp += 1;
       }
       if opj_j2k_update_image_dimensions(p_image, p_manager) == 0 {
@@ -12336,25 +13267,482 @@ pub(crate) fn opj_j2k_decode(
   }
 }
 
-pub(crate) fn opj_j2k_get_tile(
-  mut p_j2k: &mut opj_j2k,
-  mut p_stream: &mut Stream,
-  mut p_image: &mut opj_image,
-  mut p_manager: &mut opj_event_mgr,
-  mut tile_index: OPJ_UINT32,
-) -> OPJ_BOOL {
+/* *
+ * Area of precinct `precno` of resolution `resno` of component `compno` of
+ * a tile, in coordinates of the resolution level, and the area of the
+ * reference grid it is decoded from. Same partition as opj_tcd_init_tile().
+ */
+fn opj_j2k_get_precinct_area(
+  p_j2k: &opj_j2k,
+  tile_index: OPJ_UINT32,
+  compno: OPJ_UINT32,
+  resno: OPJ_UINT32,
+  precno: OPJ_UINT32,
+) -> Option<([OPJ_UINT32; 4], [OPJ_UINT32; 4])> {
+  let l_cp = &p_j2k.m_cp;
   unsafe {
-    let mut compno: OPJ_UINT32 = 0;
-    let mut l_tile_x: OPJ_UINT32 = 0;
-    let mut l_tile_y: OPJ_UINT32 = 0;
-    let mut l_img_comp = std::ptr::null_mut::<opj_image_comp_t>();
-    if p_image.numcomps < (*p_j2k.m_private_image).numcomps {
+    let l_image = &*p_j2k.m_private_image;
+    let l_comp = &*l_image.comps.add(compno as usize);
+    let l_tcp = &*l_cp.tcps.add(tile_index as usize);
+    if l_tcp.tccps.is_null() {
+      return None;
+    }
+    let l_tccp = &*l_tcp.tccps.add(compno as usize);
+    if resno >= l_tccp.numresolutions {
+      return None;
+    }
+    let p = tile_index % l_cp.tw;
+    let q = tile_index / l_cp.tw;
+    let tx0 = opj_uint_max(
+      opj_uint_adds(l_cp.tx0, p.wrapping_mul(l_cp.tdx)),
+      l_image.x0,
+    );
+    let ty0 = opj_uint_max(
+      opj_uint_adds(l_cp.ty0, q.wrapping_mul(l_cp.tdy)),
+      l_image.y0,
+    );
+    let tx1 = opj_uint_min(
+      opj_uint_adds(l_cp.tx0, (p + 1).wrapping_mul(l_cp.tdx)),
+      l_image.x1,
+    );
+    let ty1 = opj_uint_min(
+      opj_uint_adds(l_cp.ty0, (q + 1).wrapping_mul(l_cp.tdy)),
+      l_image.y1,
+    );
+    let levelno = l_tccp.numresolutions - 1 - resno;
+    let rx0 = opj_uint_ceildivpow2(opj_uint_ceildiv(tx0, l_comp.dx), levelno);
+    let ry0 = opj_uint_ceildivpow2(opj_uint_ceildiv(ty0, l_comp.dy), levelno);
+    let rx1 = opj_uint_ceildivpow2(opj_uint_ceildiv(tx1, l_comp.dx), levelno);
+    let ry1 = opj_uint_ceildivpow2(opj_uint_ceildiv(ty1, l_comp.dy), levelno);
+    let pdx = l_tccp.prcw[resno as usize];
+    let pdy = l_tccp.prch[resno as usize];
+    /* The partitions start at the origin, 0 or 1. Positions are shifted
+     * by a precinct to stay unsigned before it. */
+    let (ox, oy) = l_tcp.partition_origin();
+    let (sx, sy) = ((1u64 << pdx) - ox as u64, (1u64 << pdy) - oy as u64);
+    let prc_x_start = ((rx0 as u64 + sx) >> pdx) << pdx;
+    let prc_y_start = ((ry0 as u64 + sy) >> pdy) << pdy;
+    let pw = if rx0 == rx1 {
+      0
+    } else {
+      ((((rx1 as u64 + sx + (1 << pdx) - 1) >> pdx) << pdx) - prc_x_start) >> pdx
+    };
+    let ph = if ry0 == ry1 {
+      0
+    } else {
+      ((((ry1 as u64 + sy + (1 << pdy) - 1) >> pdy) << pdy) - prc_y_start) >> pdy
+    };
+    if precno as u64 >= pw * ph {
+      return None;
+    }
+    let pw = pw as OPJ_UINT32;
+    let x0 = opj_uint_max(
+      (prc_x_start + ((precno % pw) << pdx) as u64).saturating_sub(sx) as u32,
+      rx0,
+    );
+    let y0 = opj_uint_max(
+      (prc_y_start + ((precno / pw) << pdy) as u64).saturating_sub(sy) as u32,
+      ry0,
+    );
+    let x1 = opj_uint_min(
+      (prc_x_start + ((precno % pw + 1) << pdx) as u64).saturating_sub(sx) as u32,
+      rx1,
+    );
+    let y1 = opj_uint_min(
+      (prc_y_start + ((precno / pw + 1) << pdy) as u64).saturating_sub(sy) as u32,
+      ry1,
+    );
+    /* Samples x0..x1 of the resolution are decoded from the reference grid
+     * area (x0 << levelno) * dx.., inside the tile */
+    let area = [
+      opj_uint_max((x0 << levelno) * l_comp.dx, tx0),
+      opj_uint_max((y0 << levelno) * l_comp.dy, ty0),
+      opj_uint_min((x1 << levelno) * l_comp.dx, tx1),
+      opj_uint_min((y1 << levelno) * l_comp.dy, ty1),
+    ];
+    Some(([x0, y0, x1, y1], area))
+  }
+}
+
+/// Decode the samples of the area covered by `precinct`.
+///
+/// The tile is decoded with `opj_j2k_decode()` for this component only, up
+/// to the resolution of the precinct and over the area of the reference
+/// grid the precinct is decoded from.
+pub(crate) fn opj_j2k_decode_precinct(
+  p_j2k: &mut opj_j2k,
+  p_stream: &mut Stream,
+  p_image: &mut opj_image,
+  precinct: PrecinctIndex,
+  p_manager: &mut opj_event_mgr,
+) -> Option<PrecinctData> {
+  let PrecinctIndex {
+    tile_index,
+    compno,
+    resno,
+    precno,
+  } = precinct;
+  if p_j2k.m_private_image.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_read_header() should be called before opj_decode_precinct().\n",
+    );
+    return None;
+  }
+  if !p_stream.has_seek() {
+    /* Tiles are located with the codestream index or by going back to the
+     * last SOT read, both need to seek */
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_decode_precinct() needs a seekable stream\n",
+    );
+    return None;
+  }
+  if tile_index >= p_j2k.m_cp.tw.wrapping_mul(p_j2k.m_cp.th) {
+    event_msg!(p_manager, EVT_ERROR, "Invalid tile index: %u\n", tile_index,);
+    return None;
+  }
+  if opj_j2k_set_decoded_components(p_j2k, &[compno], p_manager) == 0 {
+    return None;
+  }
+  let numres = unsafe {
+    (*(*p_j2k.m_cp.tcps.add(tile_index as usize))
+      .tccps
+      .add(compno as usize))
+    .numresolutions
+  };
+  if resno >= numres {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid resolution %u, component %u has %u resolutions\n",
+      resno,
+      compno,
+      numres,
+    );
+    return None;
+  }
+  let (prc, area) = match opj_j2k_get_precinct_area(p_j2k, tile_index, compno, resno, precno) {
+    Some(area) => area,
+    None => {
       event_msg!(
         p_manager,
         EVT_ERROR,
-        "Image has less components than codestream.\n",
+        "Invalid precinct %u of resolution %u of tile %u\n",
+        precno,
+        resno,
+        tile_index,
       );
-      return 0i32;
+      return None;
+    }
+  };
+  if opj_j2k_set_decoded_resolution_factor(p_j2k, numres - 1 - resno, p_manager) == 0 {
+    return None;
+  }
+  /* Only the code-blocks contributing to the area of the precinct are
+   * decoded, see opj_tcd_is_subband_area_of_interest() */
+  if opj_j2k_set_decode_area(
+    p_j2k,
+    p_image,
+    area[0] as OPJ_INT32,
+    area[1] as OPJ_INT32,
+    area[2] as OPJ_INT32,
+    area[3] as OPJ_INT32,
+    p_manager,
+  ) == 0
+  {
+    return None;
+  }
+  if opj_j2k_decode(p_j2k, p_stream, p_image, p_manager) == 0 {
+    return None;
+  }
+  /* A COD or COC marker of the tile header can change the partition */
+  if opj_j2k_get_precinct_area(p_j2k, tile_index, compno, resno, precno) != Some((prc, area)) {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "The precinct partition of tile %u differs from the main header\n",
+      tile_index,
+    );
+    return None;
+  }
+  let [x0, y0, x1, y1] = prc;
+  /* The image has the decoded component at the resolution of the precinct */
+  let comp = &p_image.comps()?[0];
+  let data = comp.data()?;
+  let comp_x0 = opj_uint_ceildivpow2(comp.x0, comp.factor);
+  let comp_y0 = opj_uint_ceildivpow2(comp.y0, comp.factor);
+  if comp_x0 > x0 || comp_y0 > y0 || comp_x0 + comp.w < x1 || comp_y0 + comp.h < y1 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Decoded area of tile %u doesn't match resolution %u\n",
+      tile_index,
+      resno,
+    );
+    return None;
+  }
+  let mut samples = Vec::with_capacity(((x1 - x0) * (y1 - y0)) as usize);
+  for y in y0..y1 {
+    let start = ((y - comp_y0) * comp.w + (x0 - comp_x0)) as usize;
+    samples.extend_from_slice(&data[start..start + (x1 - x0) as usize]);
+  }
+  Some(PrecinctData {
+    x0,
+    y0,
+    x1,
+    y1,
+    prec: comp.prec,
+    sgnd: comp.sgnd != 0,
+    data: samples,
+  })
+}
+
+pub(crate) fn opj_j2k_get_packet_order(
+  p_j2k: &mut opj_j2k,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Vec<PacketCoord>> {
+  if p_j2k.m_is_decoder == 0 || p_j2k.m_private_image.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_read_header() should be called before opj_get_packet_order().\n",
+    );
+    return None;
+  }
+  if tile_index >= p_j2k.m_cp.tw.wrapping_mul(p_j2k.m_cp.th) {
+    event_msg!(p_manager, EVT_ERROR, "Invalid tile index: %u\n", tile_index,);
+    return None;
+  }
+  unsafe {
+    let l_tcp = &*p_j2k.m_cp.tcps.add(tile_index as usize);
+    if l_tcp.tccps.is_null() {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Coding parameters of tile %u are not available\n",
+        tile_index,
+      );
+      return None;
+    }
+    if l_tcp.m_current_tile_part_number < 0 {
+      event_msg!(
+        p_manager,
+        EVT_WARNING,
+        "Tile %u hasn't been read, its COD and POC markers aren't taken into account\n",
+        tile_index,
+      );
+    }
+    let l_nb_pocs = l_tcp.numpocs + 1;
+    let l_pi = opj_pi_create_decode(
+      p_j2k.m_private_image,
+      &mut p_j2k.m_cp,
+      tile_index,
+      p_manager,
+    );
+    if l_pi.is_null() {
+      return None;
+    }
+    /* Same traversal as opj_t2_decode_packets(): the progression of each
+     * POC in turn, packets already visited being skipped */
+    let mut packets = Vec::new();
+    for pino in 0..l_nb_pocs {
+      let l_current_pi = l_pi.add(pino as usize);
+      if (*l_current_pi).poc.prg as core::ffi::c_int == OPJ_PROG_UNKNOWN as core::ffi::c_int {
+        opj_pi_destroy(l_pi, l_nb_pocs);
+        event_msg!(p_manager, EVT_ERROR, "Unknown progression order\n",);
+        return None;
+      }
+      while opj_pi_next(l_current_pi) != 0 {
+        packets.push(PacketCoord {
+          layer: (*l_current_pi).layno,
+          resolution: (*l_current_pi).resno,
+          component: (*l_current_pi).compno,
+          precinct: (*l_current_pi).precno,
+        });
+      }
+    }
+    opj_pi_destroy(l_pi, l_nb_pocs);
+    Some(packets)
+  }
+}
+
+pub(crate) fn opj_j2k_get_packet_count(
+  p_j2k: &mut opj_j2k,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<OPJ_UINT32> {
+  opj_j2k_get_packet_order(p_j2k, tile_index, p_manager).map(|packets| packets.len() as OPJ_UINT32)
+}
+
+/// Cumulative size of the packets of the layers of a tile, from the
+/// packet lengths of its PLT markers.
+pub(crate) fn opj_j2k_get_layer_sizes(
+  p_j2k: &mut opj_j2k,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Vec<u64>> {
+  let packets = opj_j2k_get_packet_order(p_j2k, tile_index, p_manager)?;
+  let lengths = match p_j2k.m_packet_lengths.get(&tile_index) {
+    Some(lengths) if lengths.len() == packets.len() => lengths,
+    _ => {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Packet lengths of tile %u are unknown: the tile has no PLT markers or wasn't read\n",
+        tile_index,
+      );
+      return None;
+    }
+  };
+  let numlayers = unsafe { (*p_j2k.m_cp.tcps.add(tile_index as usize)).numlayers };
+  let mut sizes = vec![0u64; numlayers as usize];
+  for (packet, len) in packets.iter().zip(lengths) {
+    sizes[packet.layer as usize] += *len as u64;
+  }
+  for layno in 1..sizes.len() {
+    sizes[layno] += sizes[layno - 1];
+  }
+  Some(sizes)
+}
+
+/// Read the packet headers of all the tiles, without decoding the
+/// code-blocks, and record the location of each packet in the stream.
+pub(crate) fn opj_j2k_build_packet_index(
+  p_j2k: &mut opj_j2k,
+  p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> Option<PacketIndex> {
+  if p_j2k.m_is_decoder == 0 || p_j2k.m_private_image.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_read_header() should be called before opj_build_packet_index().\n",
+    );
+    return None;
+  }
+  unsafe {
+    let mut index = PacketIndex::default();
+    let mut tile_info = TileInfo::default();
+    let l_nb_tiles = p_j2k.m_cp.tw.wrapping_mul(p_j2k.m_cp.th);
+    for _ in 0..l_nb_tiles {
+      if !opj_j2k_read_tile_header(p_j2k, p_stream, &mut tile_info, p_manager) {
+        return None;
+      }
+      if !tile_info.go_on {
+        break;
+      }
+      let mut l_packets = Vec::new();
+      (*p_j2k.m_tcd).packet_index = &mut l_packets;
+      let l_ret = opj_j2k_decode_tile(p_j2k, tile_info.index, None, p_stream, p_manager);
+      (*p_j2k.m_tcd).packet_index = std::ptr::null_mut();
+      if l_ret == 0 {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Failed to read the packet headers of tile %d/%d\n",
+          tile_info.index.wrapping_add(1u32),
+          l_nb_tiles,
+        );
+        return None;
+      }
+      opj_j2k_tcp_data_destroy(&mut *p_j2k.m_cp.tcps.add(tile_info.index as usize));
+      /* Packet offsets are relative to the data of the tile, the
+       * concatenation of the data of its tile-parts */
+      let l_tile_index = &*(*p_j2k.cstr_index).tile_index.add(tile_info.index as usize);
+      let l_parts = core::slice::from_raw_parts(
+        l_tile_index.tp_index,
+        l_tile_index.current_tpsno as usize + 1,
+      );
+      for mut packet in l_packets {
+        let mut l_part_start = 0u64;
+        for (partno, part) in l_parts.iter().enumerate() {
+          /* end_header is the position of the SOD marker */
+          let l_part_len = (part.end_pos - part.end_header - 2) as u64;
+          if packet.offset < l_part_start + l_part_len || partno + 1 == l_parts.len() {
+            packet.offset = (part.end_header + 2) as u64 + packet.offset - l_part_start;
+            break;
+          }
+          l_part_start += l_part_len;
+        }
+        index.packets.push(packet);
+      }
+      if opj_stream_get_number_byte_left(p_stream) == 0i64
+        && p_j2k.m_specific_param.m_decoder.m_state == J2KState::NEOC
+      {
+        break;
+      }
+    }
+    Some(index)
+  }
+}
+
+/// Byte ranges of the main header and of the tile-parts read so far, from
+/// the codestream index.
+pub(crate) fn opj_j2k_get_codestream_byte_ranges(
+  p_j2k: &opj_j2k,
+  p_manager: &mut opj_event_mgr,
+) -> Option<CodestreamByteRanges> {
+  if p_j2k.m_is_decoder == 0 || p_j2k.m_private_image.is_null() || p_j2k.cstr_index.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_read_header() should be called before opj_get_codestream_byte_ranges().\n",
+    );
+    return None;
+  }
+  unsafe {
+    let l_cstr_index = &*p_j2k.cstr_index;
+    let mut ranges = CodestreamByteRanges {
+      main_header: l_cstr_index.main_head_start as u64..l_cstr_index.main_head_end as u64,
+      tiles: Vec::new(),
+    };
+    if l_cstr_index.tile_index.is_null() {
+      return Some(ranges);
+    }
+    let l_tiles =
+      core::slice::from_raw_parts(l_cstr_index.tile_index, l_cstr_index.nb_of_tiles as usize);
+    for (tileno, l_tile) in l_tiles.iter().enumerate() {
+      if l_tile.tp_index.is_null() || l_tile.current_nb_tps == 0 {
+        continue;
+      }
+      let l_nb_parts = (l_tile.current_tpsno + 1).min(l_tile.current_nb_tps);
+      let l_parts = core::slice::from_raw_parts(l_tile.tp_index, l_nb_parts as usize);
+      ranges.tiles.push(TileByteRange {
+        tile: tileno as u32,
+        /* Tile-parts without a SOD marker have no end position */
+        tile_parts: l_parts
+          .iter()
+          .map(|part| part.start_pos as u64..part.end_pos.max(part.start_pos) as u64)
+          .collect(),
+      });
+    }
+    Some(ranges)
+  }
+}
+
+pub(crate) fn opj_j2k_get_tile(
+  mut p_j2k: &mut opj_j2k,
+  mut p_stream: &mut Stream,
+  mut p_image: &mut opj_image,
+  mut p_manager: &mut opj_event_mgr,
+  mut tile_index: OPJ_UINT32,
+) -> OPJ_BOOL {
+  unsafe {
+    let mut compno: OPJ_UINT32 = 0;
+    let mut l_tile_x: OPJ_UINT32 = 0;
+    let mut l_tile_y: OPJ_UINT32 = 0;
+    let mut l_img_comp = std::ptr::null_mut::<opj_image_comp_t>();
+    if p_image.numcomps < (*p_j2k.m_private_image).numcomps {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Image has less components than codestream.\n",
+      );
+      return 0i32;
     }
     if tile_index >= p_j2k.m_cp.tw.wrapping_mul(p_j2k.m_cp.th) {
       event_msg!(
@@ -12459,117 +13847,1375 @@ pub(crate) fn opj_j2k_get_tile(
       p_j2k.m_private_image = std::ptr::null_mut::<opj_image_t>();
       return 0i32;
     }
-    /* Move data and copy one information from codec to output image*/
-    opj_j2k_move_data_from_codec_to_output_image(p_j2k, p_image)
+    /* Move data and copy one information from codec to output image*/
+    opj_j2k_move_data_from_codec_to_output_image(p_j2k, p_image)
+  }
+}
+
+/// Create the image of a single tile, to be filled by `opj_j2k_get_tile`.
+pub(crate) fn opj_j2k_create_tile_image(
+  p_j2k: &opj_j2k,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Box<opj_image>> {
+  if p_j2k.m_private_image.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_decode_tile must be called after opj_read_header\n",
+    );
+    return None;
+  }
+  let nb_tiles = p_j2k.m_cp.tw.wrapping_mul(p_j2k.m_cp.th);
+  if tile_index >= nb_tiles {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Tile index provided by the user is incorrect %d (max = %d) \n",
+      tile_index,
+      nb_tiles.wrapping_sub(1),
+    );
+    return None;
+  }
+  let mut image = opj_image::new();
+  opj_copy_image_header(p_j2k.m_private_image, &mut *image);
+  Some(image)
+}
+
+/// Decode a single tile into a new image, sized to the tile.
+pub(crate) fn opj_j2k_decode_tile_image(
+  p_j2k: &mut opj_j2k,
+  p_stream: &mut Stream,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Box<opj_image>> {
+  let mut image = opj_j2k_create_tile_image(p_j2k, tile_index, p_manager)?;
+  if opj_j2k_get_tile(p_j2k, p_stream, &mut image, p_manager, tile_index) == 0 {
+    return None;
+  }
+  Some(image)
+}
+
+pub(crate) fn opj_j2k_set_decoded_resolution_factor(
+  mut p_j2k: &mut opj_j2k,
+  mut res_factor: OPJ_UINT32,
+  mut p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  unsafe {
+    let mut it_comp: OPJ_UINT32 = 0;
+    p_j2k.m_cp.m_specific_param.m_dec.m_reduce = res_factor;
+    if !p_j2k.m_private_image.is_null()
+      && !(*p_j2k.m_private_image).comps.is_null()
+      && !p_j2k.m_specific_param.m_decoder.m_default_tcp.is_null()
+      && !(*p_j2k.m_specific_param.m_decoder.m_default_tcp)
+        .tccps
+        .is_null()
+    {
+      it_comp = 0 as OPJ_UINT32;
+      while it_comp < (*p_j2k.m_private_image).numcomps {
+        let mut max_res = (*(*p_j2k.m_specific_param.m_decoder.m_default_tcp)
+          .tccps
+          .offset(it_comp as isize))
+        .numresolutions;
+        if res_factor >= max_res {
+          event_msg!(
+            p_manager,
+            EVT_ERROR,
+            "Resolution factor is greater than the maximum resolution in the component.\n",
+          );
+          return 0i32;
+        }
+        (*(*p_j2k.m_private_image).comps.offset(it_comp as isize)).factor = res_factor;
+        it_comp += 1;
+      }
+      return 1i32;
+    }
+    0i32
+  }
+}
+
+pub(crate) fn opj_j2k_set_component_reduce(
+  p_j2k: &mut opj_j2k,
+  factors: &[(OPJ_UINT32, OPJ_UINT32)],
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  unsafe {
+    let l_default_tcp = p_j2k.m_specific_param.m_decoder.m_default_tcp;
+    if p_j2k.m_private_image.is_null()
+      || l_default_tcp.is_null()
+      || (*l_default_tcp).tccps.is_null()
+    {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "opj_read_header() should be called before opj_set_component_reduce().\n",
+      );
+      return false;
+    }
+    let l_comps = match (*p_j2k.m_private_image).comps_mut() {
+      Some(comps) => comps,
+      None => return false,
+    };
+    let mut l_factors: Vec<OPJ_UINT32> = l_comps.iter().map(|comp| comp.factor).collect();
+    for &(compno, factor) in factors {
+      if compno as usize >= l_factors.len() {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Invalid component index: %u\n",
+          compno,
+        );
+        return false;
+      }
+      let l_numres = (*(*l_default_tcp).tccps.add(compno as usize)).numresolutions;
+      if factor >= l_numres {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Resolution factor %u is greater or equal than the number of resolutions of component %u (%u)\n",
+          factor,
+          compno,
+          l_numres,
+        );
+        return false;
+      }
+      l_factors[compno as usize] = factor;
+    }
+    /* The multiple component transform needs components of the same size */
+    if (*l_default_tcp).mct != 0
+      && l_factors.len() >= 3
+      && (l_factors[0] != l_factors[1] || l_factors[0] != l_factors[2])
+    {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "The first three components must have the same resolution factor when a multiple component transform is used\n",
+      );
+      return false;
+    }
+    for (comp, factor) in l_comps.iter_mut().zip(l_factors) {
+      comp.factor = factor;
+    }
+  }
+  true
+}
+
+pub(crate) fn opj_j2k_set_layer_range(
+  p_j2k: &mut opj_j2k,
+  start: OPJ_UINT32,
+  end: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  unsafe {
+    let l_default_tcp = p_j2k.m_specific_param.m_decoder.m_default_tcp;
+    if p_j2k.m_private_image.is_null() || l_default_tcp.is_null() {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "opj_read_header() should be called before opj_decoder_set_layer_range().\n",
+      );
+      return false;
+    }
+    let l_numlayers = (*l_default_tcp).numlayers;
+    if start >= end || end > l_numlayers {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Invalid layer range [%u, %u) for a codestream of %u layers\n",
+        start,
+        end,
+        l_numlayers,
+      );
+      return false;
+    }
+    /* The layers below start are the base of the band: decode 0..end.
+     * Tile-part COD markers read later also use m_layer. */
+    p_j2k.m_cp.m_specific_param.m_dec.m_layer = end;
+    (*l_default_tcp).num_layers_to_decode = end;
+    if !p_j2k.m_cp.tcps.is_null() {
+      let l_nb_tiles = p_j2k.m_cp.tw.wrapping_mul(p_j2k.m_cp.th) as usize;
+      for l_tcp in core::slice::from_raw_parts_mut(p_j2k.m_cp.tcps, l_nb_tiles) {
+        l_tcp.num_layers_to_decode = end;
+      }
+    }
+    true
+  }
+}
+
+pub(crate) fn opj_j2k_encoder_set_extra_options(
+  p_j2k: &mut opj_j2k,
+  options: &[&str],
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  for option in options {
+    if option.starts_with("PLT=") {
+      if *option == "PLT=YES" {
+        p_j2k.m_specific_param.m_encoder.m_PLT = 1i32
+      } else if *option == "PLT=NO" {
+        p_j2k.m_specific_param.m_encoder.m_PLT = 0i32
+      } else {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Invalid value for option: %s.\n",
+          *option,
+        );
+        return false;
+      }
+    } else if option.starts_with("TLM=") {
+      if *option == "TLM=YES" {
+        p_j2k.m_specific_param.m_encoder.m_TLM = 1i32
+      } else if *option == "TLM=NO" {
+        p_j2k.m_specific_param.m_encoder.m_TLM = 0i32
+      } else {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Invalid value for option: %s.\n",
+          *option,
+        );
+        return false;
+      }
+    } else if let Some(value) = option.strip_prefix("GUARD_BITS=") {
+      let numgbits = value.parse::<i32>().unwrap_or_default();
+      if !(0..=7).contains(&numgbits) {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Invalid value for option: %s. Should be in [0,7]\n",
+          *option,
+        );
+        return false;
+      }
+      if !opj_j2k_encoder_set_guard_bits(p_j2k, numgbits as OPJ_UINT32, p_manager) {
+        return false;
+      }
+    } else {
+      event_msg!(p_manager, EVT_ERROR, "Invalid option: %s.\n", *option);
+      return false;
+    }
+  }
+  true
+}
+
+/// Check a number of resolutions against the tile size and the layer
+/// allocation of an encoder.
+fn opj_j2k_check_resolutions(
+  cp: &opj_cp_t,
+  numresolutions: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  /* ISO 15444-1 allows at most 32 decomposition levels */
+  if !(1..=33).contains(&numresolutions) {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid number of resolutions : %d not in range [1,%d]\n",
+      numresolutions,
+      33,
+    );
+    return false;
+  }
+  /* The lowest resolution of a tile must be at least 1 pixel */
+  let min_size = 1u64 << (numresolutions - 1);
+  if (cp.tdx as u64) < min_size || (cp.tdy as u64) < min_size {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Number of resolutions %d is too high for the tile size %dx%d, the lowest resolution would be empty\n",
+      numresolutions,
+      cp.tdx,
+      cp.tdy,
+    );
+    return false;
+  }
+  if unsafe { cp.m_specific_param.m_enc.m_quality_layer_alloc_strategy }
+    == J2K_QUALITY_LAYER_ALLOCATION_STRATEGY::FIXED_LAYER
+    && numresolutions as i32 > j2k::J2K_TCD_MATRIX_MAX_RESOLUTION_COUNT
+  {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "numresolution when cp_fixed_alloc set should not exceed %d\n",
+      j2k::J2K_TCD_MATRIX_MAX_RESOLUTION_COUNT
+    );
+    return false;
+  }
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_resolutions(
+  p_j2k: &mut opj_j2k,
+  numresolutions: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let nb_comps = unsafe { p_j2k.m_specific_param.m_encoder.m_nb_comps } as usize;
+  let cp = &mut p_j2k.m_cp;
+  if cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_resolutions must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  if !opj_j2k_check_resolutions(cp, numresolutions, p_manager) {
+    return false;
+  }
+  let nb_tiles = cp.tw.wrapping_mul(cp.th) as usize;
+  let tcps = unsafe { core::slice::from_raw_parts_mut(cp.tcps, nb_tiles) };
+  /* Validate all tiles before changing any of them */
+  for tcp in tcps.iter() {
+    let tccps = unsafe { core::slice::from_raw_parts(tcp.tccps, nb_comps) };
+    for tccp in tccps {
+      if tccp.csty & j2k::J2K_CCP_CSTY_PRT == 0 || tccp.numresolutions >= numresolutions {
+        continue;
+      }
+      /* New lower resolutions get precincts halved from the lowest specified size */
+      let extra = numresolutions - tccp.numresolutions;
+      if tccp.prcw[0] <= extra || tccp.prch[0] <= extra {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Number of resolutions %d is incompatible with the precinct size %dx%d of the lowest resolution\n",
+          numresolutions,
+          1u32 << tccp.prcw[0],
+          1u32 << tccp.prch[0],
+        );
+        return false;
+      }
+    }
+  }
+  for tcp in tcps.iter_mut() {
+    let tccps = unsafe { core::slice::from_raw_parts_mut(tcp.tccps, nb_comps) };
+    for (compno, tccp) in tccps.iter_mut().enumerate() {
+      let old_numres = tccp.numresolutions;
+      if tccp.csty & j2k::J2K_CCP_CSTY_PRT != 0 {
+        /* Precinct sizes are specified from the highest resolution down */
+        let (old_prcw, old_prch) = (tccp.prcw, tccp.prch);
+        for p in 0..numresolutions {
+          let resno = (numresolutions - 1 - p) as usize;
+          if p < old_numres {
+            let old_resno = (old_numres - 1 - p) as usize;
+            tccp.prcw[resno] = old_prcw[old_resno];
+            tccp.prch[resno] = old_prch[old_resno];
+          } else {
+            let shift = p - (old_numres - 1);
+            tccp.prcw[resno] = old_prcw[0] - shift;
+            tccp.prch[resno] = old_prch[0] - shift;
+          }
+        }
+      } else {
+        for resno in 0..numresolutions as usize {
+          tccp.prcw[resno] = 15;
+          tccp.prch[resno] = 15;
+        }
+      }
+      tccp.numresolutions = numresolutions;
+      let prec = p_j2k.m_comp_prec.get(compno).copied().unwrap_or(8);
+      unsafe {
+        opj_dwt_calc_explicit_stepsizes(tccp, prec);
+      }
+    }
+  }
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_quantization(
+  p_j2k: &mut opj_j2k,
+  style: &QuantStyle,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let nb_comps = unsafe { p_j2k.m_specific_param.m_encoder.m_nb_comps } as usize;
+  let cp = &mut p_j2k.m_cp;
+  if cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_quantization must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  let nb_tiles = cp.tw.wrapping_mul(cp.th) as usize;
+  let tcps = unsafe { core::slice::from_raw_parts_mut(cp.tcps, nb_tiles) };
+  /* Validate all tiles before changing any of them */
+  for tcp in tcps.iter() {
+    let tccps = unsafe { core::slice::from_raw_parts(tcp.tccps, nb_comps) };
+    for tccp in tccps {
+      if tccp.qmfbid != 0 {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Quantization step sizes can only be set with the irreversible wavelet\n",
+        );
+        return false;
+      }
+      if let QuantStyle::Explicit { steps } = style {
+        let numbands = tccp.numresolutions * 3 - 2;
+        if steps.len() != numbands as usize {
+          event_msg!(
+            p_manager,
+            EVT_ERROR,
+            "Expected %d quantization step sizes for %d resolutions, got %d\n",
+            numbands,
+            tccp.numresolutions,
+            steps.len(),
+          );
+          return false;
+        }
+      }
+    }
+  }
+  if let QuantStyle::Explicit { steps } = style {
+    if steps
+      .iter()
+      .any(|(mant, expn)| *mant >= 1 << 11 || *expn >= 1 << 5)
+    {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Quantization step size mantissas must fit in 11 bits and exponents in 5 bits\n",
+      );
+      return false;
+    }
+  }
+  for tcp in tcps.iter_mut() {
+    let tccps = unsafe { core::slice::from_raw_parts_mut(tcp.tccps, nb_comps) };
+    for (compno, tccp) in tccps.iter_mut().enumerate() {
+      let numbands = (tccp.numresolutions * 3 - 2) as usize;
+      match style {
+        QuantStyle::Derived => {
+          let prec = p_j2k.m_comp_prec.get(compno).copied().unwrap_or(8);
+          unsafe {
+            opj_dwt_calc_explicit_stepsizes(&mut *tccp, prec);
+          }
+          tccp.qntsty = j2k::J2K_CCP_QNTSTY_SIQNT;
+          /* Same derivation as opj_j2k_read_SQcd_SQcc() */
+          let base = tccp.stepsizes[0];
+          for bandno in 1..numbands {
+            let stepsize = &mut tccp.stepsizes[bandno];
+            stepsize.expn = (base.expn - ((bandno - 1) / 3) as OPJ_INT32).max(0);
+            stepsize.mant = base.mant;
+          }
+        }
+        QuantStyle::Explicit { steps } => {
+          tccp.qntsty = j2k::J2K_CCP_QNTSTY_SEQNT;
+          for (stepsize, (mant, expn)) in tccp.stepsizes.iter_mut().zip(steps) {
+            stepsize.mant = *mant as OPJ_INT32;
+            stepsize.expn = *expn as OPJ_INT32;
+          }
+        }
+      }
+    }
+  }
+  true
+}
+
+/// Switch to the irreversible wavelet with step sizes keeping the decoded
+/// samples within `max_error` of the source samples.
+///
+/// The step sizes are derived from the subband norms, so the bound holds
+/// statistically: the error of each sample sums the quantization errors of
+/// many coefficients.  A guaranteed bound would need step sizes computed
+/// per subband from the synthesis filters.  Each `step_shift` halves the
+/// step sizes, to encode again an image whose error exceeded the bound.
+pub(crate) fn opj_j2k_encoder_set_near_lossless(
+  p_j2k: &mut opj_j2k,
+  max_error: OPJ_UINT32,
+  step_shift: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let nb_comps = unsafe { p_j2k.m_specific_param.m_encoder.m_nb_comps } as usize;
+  let cp = &mut p_j2k.m_cp;
+  if cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_near_lossless must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  if max_error == 0 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Lossless coding needs the reversible wavelet, not a near-lossless error of 0\n",
+    );
+    return false;
+  }
+  for (compno, prec) in p_j2k.m_comp_prec.iter().enumerate() {
+    if max_error as u64 >= 1u64 << ((*prec).max(1) - 1) {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "The near-lossless error %d is too large for the %d-bit component %d\n",
+        max_error,
+        prec,
+        compno,
+      );
+      return false;
+    }
+  }
+  let nb_tiles = cp.tw.wrapping_mul(cp.th) as usize;
+  let tcps = unsafe { core::slice::from_raw_parts_mut(cp.tcps, nb_tiles) };
+  if tcps.iter().any(|tcp| tcp.mct == 2) {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Near-lossless coding doesn't support a custom multiple component transform\n",
+    );
+    return false;
+  }
+  let last_layer_lossy = tcps.iter().any(|tcp| {
+    let last = tcp.numlayers.max(1) as usize - 1;
+    tcp.rates[last] > 0.0 || tcp.distoratio[last] > 0.0
+  });
+  if last_layer_lossy {
+    event_msg!(
+      p_manager,
+      EVT_WARNING,
+      "The last quality layer isn't lossless, the error may exceed %d\n",
+      max_error,
+    );
+  }
+  for tcp in tcps.iter_mut() {
+    /* A quantization error of up to one step in the dead zone, 4 times
+     * below the rounding threshold of the decoded samples */
+    let mut scale = (max_error as OPJ_FLOAT64 + 0.5) / 4.0;
+    scale *= (0.5 as OPJ_FLOAT64).powi(step_shift.min(32) as i32);
+    if tcp.mct == 1 {
+      /* The inverse ICT doubles the error of the blue component */
+      scale /= 2.0;
+    }
+    let tccps = unsafe { core::slice::from_raw_parts_mut(tcp.tccps, nb_comps) };
+    for (compno, tccp) in tccps.iter_mut().enumerate() {
+      tccp.qmfbid = 0;
+      tccp.qntsty = j2k::J2K_CCP_QNTSTY_SEQNT;
+      let prec = p_j2k.m_comp_prec.get(compno).copied().unwrap_or(8);
+      unsafe {
+        opj_dwt_calc_scaled_stepsizes(tccp, prec, scale);
+      }
+    }
+  }
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_guard_bits(
+  p_j2k: &mut opj_j2k,
+  numgbits: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let nb_comps = unsafe { p_j2k.m_specific_param.m_encoder.m_nb_comps } as usize;
+  let cp = &mut p_j2k.m_cp;
+  if cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_guard_bits must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  /* Guard bits are coded on 3 bits of Sqcd/Sqcc */
+  if numgbits > 7 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid number of guard bits : %d not in range [0,7]\n",
+      numgbits,
+    );
+    return false;
+  }
+  let nb_tiles = cp.tw.wrapping_mul(cp.th) as usize;
+  let tcps = unsafe { core::slice::from_raw_parts_mut(cp.tcps, nb_tiles) };
+  for tcp in tcps.iter_mut() {
+    let tccps = unsafe { core::slice::from_raw_parts_mut(tcp.tccps, nb_comps) };
+    for tccp in tccps {
+      tccp.numgbits = numgbits;
+    }
+  }
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_mode_switches(
+  p_j2k: &mut opj_j2k,
+  modes: ModeSwitches,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let nb_comps = unsafe { p_j2k.m_specific_param.m_encoder.m_nb_comps } as usize;
+  let cp = &mut p_j2k.m_cp;
+  if cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_mode_switches must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  let nb_tiles = cp.tw.wrapping_mul(cp.th) as usize;
+  let tcps = unsafe { core::slice::from_raw_parts_mut(cp.tcps, nb_tiles) };
+  for tcp in tcps.iter_mut() {
+    let tccps = unsafe { core::slice::from_raw_parts_mut(tcp.tccps, nb_comps) };
+    for tccp in tccps {
+      /* Keep the HT block coder bits */
+      tccp.cblksty = (tccp.cblksty & (J2K_CCP_CBLKSTY_HT | J2K_CCP_CBLKSTY_HTMIXED)) | modes.bits();
+    }
+  }
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_coding_style(
+  p_j2k: &mut opj_j2k,
+  style: &CodingStyle,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let nb_comps = unsafe { p_j2k.m_specific_param.m_encoder.m_nb_comps } as usize;
+  let cp = &mut p_j2k.m_cp;
+  if cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_coding_style must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  /* Validate the whole style before changing any tile */
+  if !opj_j2k_check_resolutions(cp, style.resolutions, p_manager) {
+    return false;
+  }
+  let (cblkw, cblkh) = style.codeblock;
+  for size in [cblkw, cblkh] {
+    if !size.is_power_of_two() || !(4..=1024).contains(&size) {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Invalid code-block size %dx%d: not powers of 2 in range [4,1024]\n",
+        cblkw,
+        cblkh,
+      );
+      return false;
+    }
+  }
+  if cblkw * cblkh > 4096 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid code-block size %dx%d: the area should be <= 4096\n",
+      cblkw,
+      cblkh,
+    );
+    return false;
+  }
+  if style.precincts.len() > style.resolutions as usize {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "%d precinct sizes given for %d resolutions\n",
+      style.precincts.len(),
+      style.resolutions,
+    );
+    return false;
+  }
+  for &(prcw, prch) in &style.precincts {
+    for size in [prcw, prch] {
+      if !size.is_power_of_two() || !(2..=32768).contains(&size) {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Invalid precinct size %dx%d: not powers of 2 in range [2,32768]\n",
+          prcw,
+          prch,
+        );
+        return false;
+      }
+    }
+  }
+  let (origin_x, origin_y) = style.partition_origin;
+  if origin_x > 1 || origin_y > 1 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid partition origin %d,%d: not 0 or 1\n",
+      origin_x,
+      origin_y,
+    );
+    return false;
+  }
+  if style.guard_bits > 7 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid number of guard bits : %d not in range [0,7]\n",
+      style.guard_bits,
+    );
+    return false;
+  }
+  if style.mct {
+    let subsampling = &p_j2k.m_comp_subsampling;
+    if subsampling.len() < 3 {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "The multiple component transform needs 3 components, the image has %d\n",
+        subsampling.len(),
+      );
+      return false;
+    }
+    if subsampling[1] != subsampling[0] || subsampling[2] != subsampling[0] {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Cannot perform MCT on components with different sizes\n",
+      );
+      return false;
+    }
+  }
+  let nb_tiles = cp.tw.wrapping_mul(cp.th) as usize;
+  let tcps = unsafe { core::slice::from_raw_parts_mut(cp.tcps, nb_tiles) };
+  if tcps.iter().any(|tcp| tcp.mct == 2) {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "The coding style can't replace a custom multiple component transform\n",
+    );
+    return false;
+  }
+  let precincts = !style.precincts.is_empty();
+  let mut csty = 0;
+  if precincts {
+    csty |= j2k::J2K_CP_CSTY_PRT;
+  }
+  if style.sop {
+    csty |= j2k::J2K_CP_CSTY_SOP;
+  }
+  if style.eph {
+    csty |= j2k::J2K_CP_CSTY_EPH;
+  }
+  if origin_x != 0 {
+    csty |= j2k::J2K_CP_CSTY_ORIGIN_X;
+  }
+  if origin_y != 0 {
+    csty |= j2k::J2K_CP_CSTY_ORIGIN_Y;
+  }
+  let irreversible = style.wavelet == Wavelet::Irreversible97;
+  for tcp in tcps.iter_mut() {
+    tcp.csty = csty;
+    tcp.mct = style.mct as OPJ_UINT32;
+    let tccps = unsafe { core::slice::from_raw_parts_mut(tcp.tccps, nb_comps) };
+    for (compno, tccp) in tccps.iter_mut().enumerate() {
+      tccp.csty = if precincts { j2k::J2K_CCP_CSTY_PRT } else { 0 };
+      tccp.numresolutions = style.resolutions;
+      tccp.cblkw = cblkw.trailing_zeros();
+      tccp.cblkh = cblkh.trailing_zeros();
+      /* Keep the HT block coder bits */
+      tccp.cblksty = (tccp.cblksty & (J2K_CCP_CBLKSTY_HT | J2K_CCP_CBLKSTY_HTMIXED))
+        | style.mode_switches.bits();
+      tccp.qmfbid = if irreversible { 0 } else { 1 };
+      tccp.qntsty = if irreversible {
+        j2k::J2K_CCP_QNTSTY_SEQNT
+      } else {
+        j2k::J2K_CCP_QNTSTY_NOQNT
+      };
+      tccp.numgbits = style.guard_bits;
+      /* Precinct sizes are given from the highest resolution down */
+      for p in 0..style.resolutions as usize {
+        let resno = style.resolutions as usize - 1 - p;
+        let (prcw, prch) = match style.precincts.get(p).or_else(|| style.precincts.last()) {
+          Some(&(prcw, prch)) => {
+            let shift = p.saturating_sub(style.precincts.len() - 1) as u32;
+            (
+              prcw.trailing_zeros().saturating_sub(shift).max(1),
+              prch.trailing_zeros().saturating_sub(shift).max(1),
+            )
+          }
+          None => (15, 15),
+        };
+        tccp.prcw[resno] = prcw;
+        tccp.prch[resno] = prch;
+      }
+      let prec = p_j2k.m_comp_prec.get(compno).copied().unwrap_or(8);
+      unsafe {
+        opj_dwt_calc_explicit_stepsizes(tccp, prec);
+      }
+    }
+  }
+  true
+}
+
+/// The coding style of the first component of the first tile.
+pub(crate) fn opj_j2k_encoder_get_coding_style(p_j2k: &opj_j2k) -> Option<CodingStyle> {
+  let cp = &p_j2k.m_cp;
+  if cp.tcps.is_null() || unsafe { p_j2k.m_specific_param.m_encoder.m_nb_comps } == 0 {
+    return None;
+  }
+  let tcp = unsafe { &*cp.tcps };
+  let tccp = unsafe { &*tcp.tccps };
+  let precincts = if tccp.csty & j2k::J2K_CCP_CSTY_PRT != 0 {
+    (0..tccp.numresolutions as usize)
+      .rev()
+      .map(|resno| (1 << tccp.prcw[resno], 1 << tccp.prch[resno]))
+      .collect()
+  } else {
+    Vec::new()
+  };
+  Some(CodingStyle {
+    resolutions: tccp.numresolutions,
+    codeblock: (1 << tccp.cblkw, 1 << tccp.cblkh),
+    precincts,
+    mode_switches: ModeSwitches::from_bits_truncate(tccp.cblksty),
+    sop: tcp.csty & j2k::J2K_CP_CSTY_SOP != 0,
+    eph: tcp.csty & j2k::J2K_CP_CSTY_EPH != 0,
+    partition_origin: tcp.partition_origin(),
+    guard_bits: tccp.numgbits,
+    mct: tcp.mct == 1,
+    wavelet: if tccp.qmfbid == 1 {
+      Wavelet::Reversible53
+    } else {
+      Wavelet::Irreversible97
+    },
+  })
+}
+
+/// Tile coding parameters of an encoder set up by `opj_setup_encoder`, for
+/// the quality layer setters, which don't support the fixed layer
+/// allocation.
+fn opj_j2k_layer_tcps<'a>(
+  p_j2k: &'a mut opj_j2k,
+  function: &str,
+  p_manager: &mut opj_event_mgr,
+) -> Option<&'a mut [opj_tcp_t]> {
+  let cp = &mut p_j2k.m_cp;
+  if cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "%s must be called after opj_setup_encoder\n",
+      function,
+    );
+    return None;
+  }
+  if unsafe { cp.m_specific_param.m_enc.m_quality_layer_alloc_strategy }
+    == J2K_QUALITY_LAYER_ALLOCATION_STRATEGY::FIXED_LAYER
+  {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "%s can't be used with cp_fixed_alloc\n",
+      function,
+    );
+    return None;
+  }
+  let nb_tiles = cp.tw.wrapping_mul(cp.th) as usize;
+  Some(unsafe { core::slice::from_raw_parts_mut(cp.tcps, nb_tiles) })
+}
+
+pub(crate) fn opj_j2k_encoder_set_num_layers(
+  p_j2k: &mut opj_j2k,
+  numlayers: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let tcps = match opj_j2k_layer_tcps(p_j2k, "opj_encoder_set_num_layers", p_manager) {
+    Some(tcps) => tcps,
+    None => return false,
+  };
+  if numlayers == 0 || numlayers as usize > tcps[0].rates.len() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid number of layers : %d not in range [1,%d]\n",
+      numlayers,
+      tcps[0].rates.len(),
+    );
+    return false;
+  }
+  for tcp in tcps.iter() {
+    let nb_pocs = if tcp.POC { tcp.numpocs as usize + 1 } else { 0 };
+    if tcp.pocs[..nb_pocs].iter().any(|poc| poc.layno1 > numlayers) {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Number of layers %d is lower than the layers of a progression order change\n",
+        numlayers,
+      );
+      return false;
+    }
+  }
+  for tcp in tcps.iter_mut() {
+    /* Each layer includes all the remaining data until targets are set */
+    tcp.numlayers = numlayers;
+    tcp.rates = [0.0; 100];
+    tcp.distoratio = [0.0; 100];
+  }
+  true
+}
+
+/// Check the number of layer targets, and that they are strictly decreasing
+/// (`increasing == false`) or increasing, except for a last lossless 0.
+fn opj_j2k_check_layer_targets(
+  tcps: &[opj_tcp_t],
+  targets: &[f32],
+  increasing: bool,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  if targets.len() != tcps[0].numlayers as usize {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Expected %d layer targets, got %d\n",
+      tcps[0].numlayers,
+      targets.len(),
+    );
+    return false;
+  }
+  for (i, target) in targets.iter().enumerate() {
+    let last_lossless = i + 1 == targets.len() && *target == 0.0;
+    if !target.is_finite() || *target < 0.0 {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Invalid target of layer %d: %f\n",
+        i,
+        *target as f64,
+      );
+      return false;
+    }
+    if i > 0 && !last_lossless && (targets[i - 1] < *target) != increasing {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Target of layer %d (%f) should be strictly %s than the one of layer %d (%f)\n",
+        i,
+        *target as f64,
+        if increasing { "greater" } else { "lesser" },
+        i - 1,
+        targets[i - 1] as f64,
+      );
+      return false;
+    }
+  }
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_layer_rates(
+  p_j2k: &mut opj_j2k,
+  rates: &[f32],
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let tcps = match opj_j2k_layer_tcps(p_j2k, "opj_encoder_set_layer_rates", p_manager) {
+    Some(tcps) => tcps,
+    None => return false,
+  };
+  if !opj_j2k_check_layer_targets(tcps, rates, false, p_manager) {
+    return false;
+  }
+  for tcp in tcps.iter_mut() {
+    tcp.distoratio = [0.0; 100];
+    for (rate, target) in tcp.rates.iter_mut().zip(rates) {
+      /* force lossless */
+      *rate = if *target <= 1.0 { 0.0 } else { *target };
+    }
+  }
+  p_j2k
+    .m_cp
+    .m_specific_param
+    .m_enc
+    .m_quality_layer_alloc_strategy = J2K_QUALITY_LAYER_ALLOCATION_STRATEGY::RATE_DISTORTION_RATIO;
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_layer_psnr(
+  p_j2k: &mut opj_j2k,
+  psnr: &[f32],
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let tcps = match opj_j2k_layer_tcps(p_j2k, "opj_encoder_set_layer_psnr", p_manager) {
+    Some(tcps) => tcps,
+    None => return false,
+  };
+  if !opj_j2k_check_layer_targets(tcps, psnr, true, p_manager) {
+    return false;
+  }
+  for tcp in tcps.iter_mut() {
+    tcp.rates = [0.0; 100];
+    tcp.distoratio[..psnr.len()].copy_from_slice(psnr);
+  }
+  p_j2k
+    .m_cp
+    .m_specific_param
+    .m_enc
+    .m_quality_layer_alloc_strategy = J2K_QUALITY_LAYER_ALLOCATION_STRATEGY::FIXED_DISTORTION_RATIO;
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_add_comment(
+  p_j2k: &mut opj_j2k,
+  comment: &str,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let data = match Comment::encode_text(comment) {
+    Some(data) => data,
+    None => {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Comment can't be encoded as ISO/IEC 8859-15 text\n",
+      );
+      return false;
+    }
+  };
+  /* Lcom is 16 bits and includes itself and Rcom */
+  if data.len() > 65531 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Comment is too long to fit in a COM marker\n",
+    );
+    return false;
   }
+  p_j2k.m_comments.push((1, data));
+  true
 }
 
-pub(crate) fn opj_j2k_set_decoded_resolution_factor(
-  mut p_j2k: &mut opj_j2k,
-  mut res_factor: OPJ_UINT32,
-  mut p_manager: &mut opj_event_mgr,
-) -> OPJ_BOOL {
+/// Copy `comment` in a NUL terminated string allocated with `opj_malloc`,
+/// as freed with the coding parameters.
+fn opj_j2k_alloc_comment(comment: &[u8]) -> *mut OPJ_CHAR {
   unsafe {
-    let mut it_comp: OPJ_UINT32 = 0;
-    p_j2k.m_cp.m_specific_param.m_dec.m_reduce = res_factor;
-    if !p_j2k.m_private_image.is_null()
-      && !(*p_j2k.m_private_image).comps.is_null()
-      && !p_j2k.m_specific_param.m_decoder.m_default_tcp.is_null()
-      && !(*p_j2k.m_specific_param.m_decoder.m_default_tcp)
-        .tccps
-        .is_null()
-    {
-      it_comp = 0 as OPJ_UINT32;
-      while it_comp < (*p_j2k.m_private_image).numcomps {
-        let mut max_res = (*(*p_j2k.m_specific_param.m_decoder.m_default_tcp)
-          .tccps
-          .offset(it_comp as isize))
-        .numresolutions;
-        if res_factor >= max_res {
-          event_msg!(
-            p_manager,
-            EVT_ERROR,
-            "Resolution factor is greater than the maximum resolution in the component.\n",
-          );
-          return 0i32;
-        }
-        (*(*p_j2k.m_private_image).comps.offset(it_comp as isize)).factor = res_factor;
-        it_comp += 1;
-      }
-      return 1i32;
+    let l_comment = opj_malloc(comment.len() + 1) as *mut OPJ_CHAR;
+    if !l_comment.is_null() {
+      core::ptr::copy_nonoverlapping(comment.as_ptr(), l_comment as *mut u8, comment.len());
+      *l_comment.add(comment.len()) = 0;
     }
-    0i32
+    l_comment
   }
 }
 
-pub(crate) fn opj_j2k_encoder_set_extra_options(
+pub(crate) fn opj_j2k_encoder_set_writer_signature(
   p_j2k: &mut opj_j2k,
-  options: &[&str],
+  signature: Option<&str>,
   p_manager: &mut opj_event_mgr,
 ) -> bool {
-  for option in options {
-    if option.starts_with("PLT=") {
-      if *option == "PLT=YES" {
-        p_j2k.m_specific_param.m_encoder.m_PLT = 1i32
-      } else if *option == "PLT=NO" {
-        p_j2k.m_specific_param.m_encoder.m_PLT = 0i32
-      } else {
+  if p_j2k.m_cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_writer_signature must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  let l_comment = match signature {
+    Some(signature) => {
+      let data = match Comment::encode_text(signature) {
+        Some(data) if !data.contains(&0) => data,
+        _ => {
+          event_msg!(
+            p_manager,
+            EVT_ERROR,
+            "Writer signature can't be encoded as ISO/IEC 8859-15 text\n",
+          );
+          return false;
+        }
+      };
+      /* Lcom is 16 bits and includes itself and Rcom */
+      if data.len() > 65531 {
         event_msg!(
           p_manager,
           EVT_ERROR,
-          "Invalid value for option: %s.\n",
-          *option,
+          "Writer signature is too long to fit in a COM marker\n",
         );
         return false;
       }
-    } else if option.starts_with("TLM=") {
-      if *option == "TLM=YES" {
-        p_j2k.m_specific_param.m_encoder.m_TLM = 1i32
-      } else if *option == "TLM=NO" {
-        p_j2k.m_specific_param.m_encoder.m_TLM = 0i32
-      } else {
+      let l_comment = opj_j2k_alloc_comment(&data);
+      if l_comment.is_null() {
         event_msg!(
           p_manager,
           EVT_ERROR,
-          "Invalid value for option: %s.\n",
-          *option,
+          "Not enough memory to allocate comment string\n",
         );
         return false;
       }
-    } else if option.starts_with("GUARD_BITS=") {
-      let mut tileno: OPJ_UINT32 = 0;
-      let mut cp = &mut p_j2k.m_cp;
-      let mut numgbits = option[11..].parse::<i32>().unwrap_or_default();
-      if !(0..=7).contains(&numgbits) {
+      l_comment
+    }
+    None => core::ptr::null_mut(),
+  };
+  unsafe {
+    opj_free(p_j2k.m_cp.comment as *mut core::ffi::c_void);
+  }
+  p_j2k.m_cp.comment = l_comment;
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_capability(
+  p_j2k: &mut opj_j2k,
+  part: OPJ_UINT32,
+  ccap: u16,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  if p_j2k.m_cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_capability must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  if !p_j2k.m_capabilities.set(part, ccap) {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid part for the CAP marker : %d not in range [1,32]\n",
+      part,
+    );
+    return false;
+  }
+  /* Rsiz signals the CAP marker */
+  p_j2k.m_cp.rsiz |= 0x4000;
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_add_poc(
+  p_j2k: &mut opj_j2k,
+  poc: &PocSpec,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  if p_j2k.m_cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_add_progression_change must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  if !(OPJ_LRCP..=OPJ_CPRL).contains(&poc.order) {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Invalid progression order: %d\n",
+      poc.order,
+    );
+    return false;
+  }
+  if poc.res_start >= poc.res_end || poc.comp_start >= poc.comp_end || poc.layer_end == 0 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Progression order change without any packet\n",
+    );
+    return false;
+  }
+  let l_nb_tiles = p_j2k.m_cp.tw * p_j2k.m_cp.th;
+  unsafe {
+    /* Check every tile before changing any */
+    for tileno in 0..l_nb_tiles {
+      let l_tcp = &*p_j2k.m_cp.tcps.add(tileno as usize);
+      let l_numres = (*l_tcp.tccps).numresolutions;
+      if poc.res_end > l_numres || poc.layer_end > l_tcp.numlayers {
         event_msg!(
           p_manager,
           EVT_ERROR,
-          "Invalid value for option: %s. Should be in [0,7]\n",
-          *option,
+          "Progression order change out of range: tile %u has %u resolutions and %u layers\n",
+          tileno,
+          l_numres,
+          l_tcp.numlayers,
         );
         return false;
       }
-      unsafe {
-        tileno = 0 as OPJ_UINT32;
-        while tileno < cp.tw.wrapping_mul(cp.th) {
-          let mut i: OPJ_UINT32 = 0;
-          let mut tcp: *mut opj_tcp_t = &mut *cp.tcps.offset(tileno as isize) as *mut opj_tcp_t;
-          i = 0 as OPJ_UINT32;
-          while i < p_j2k.m_specific_param.m_encoder.m_nb_comps {
-            let mut tccp: *mut opj_tccp_t =
-              &mut *(*tcp).tccps.offset(i as isize) as *mut opj_tccp_t;
-            (*tccp).numgbits = numgbits as OPJ_UINT32;
-            i += 1;
-          }
-          tileno += 1;
-        }
+      if l_tcp.POC && l_tcp.numpocs as usize + 1 >= l_tcp.pocs.len() {
+        event_msg!(p_manager, EVT_ERROR, "Too many progression order changes\n",);
+        return false;
       }
+    }
+    for tileno in 0..l_nb_tiles {
+      let l_tcp = &mut *p_j2k.m_cp.tcps.add(tileno as usize);
+      let pino = if l_tcp.POC { l_tcp.numpocs + 1 } else { 0 };
+      l_tcp.pocs[pino as usize] = opj_poc_t {
+        resno0: poc.res_start,
+        compno0: poc.comp_start,
+        layno1: poc.layer_end,
+        resno1: poc.res_end,
+        compno1: poc.comp_end,
+        prg1: poc.order,
+        prg: poc.order,
+        tile: tileno + 1,
+        ..Default::default()
+      };
+      l_tcp.numpocs = pino;
+      l_tcp.POC = true;
+    }
+  }
+  true
+}
+
+/// Progression order of the COD marker of a tile, read from the tile
+/// coding parameters of a decoder or of an encoder.
+pub(crate) fn opj_j2k_get_progression(
+  p_j2k: &opj_j2k,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Progression> {
+  if p_j2k.m_cp.tcps.is_null() || (p_j2k.m_is_decoder != 0 && p_j2k.m_private_image.is_null()) {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_read_header() or opj_setup_encoder() should be called before opj_get_progression().\n",
+    );
+    return None;
+  }
+  if tile_index >= p_j2k.m_cp.tw.wrapping_mul(p_j2k.m_cp.th) {
+    event_msg!(p_manager, EVT_ERROR, "Invalid tile index: %u\n", tile_index,);
+    return None;
+  }
+  let prg = unsafe { (*p_j2k.m_cp.tcps.add(tile_index as usize)).prg };
+  let progression = Progression::from_c_enum(prg);
+  if progression.is_none() {
+    event_msg!(p_manager, EVT_ERROR, "Unknown progression order\n",);
+  }
+  progression
+}
+
+pub(crate) fn opj_j2k_encoder_set_progression(
+  p_j2k: &mut opj_j2k,
+  progression: Progression,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let cp = &mut p_j2k.m_cp;
+  if cp.tcps.is_null() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "opj_encoder_set_progression must be called after opj_setup_encoder\n",
+    );
+    return false;
+  }
+  let nb_tiles = cp.tw.wrapping_mul(cp.th) as usize;
+  let tcps = unsafe { core::slice::from_raw_parts_mut(cp.tcps, nb_tiles) };
+  for tcp in tcps {
+    tcp.prg = progression.to_c_enum();
+  }
+  true
+}
+
+pub(crate) fn opj_j2k_encoder_set_codestream_layout(p_j2k: &mut opj_j2k, layout: CodestreamLayout) {
+  p_j2k.m_layout = layout;
+}
+
+/// Prefix of the COM marker holding the MD5 of the image samples.
+const MD5_COMMENT_PREFIX: &str = "openjp2 samples MD5: ";
+/// Written in place of the digest until the codestream can be decoded.
+const MD5_PLACEHOLDER: &str = "00000000000000000000000000000000";
+
+pub(crate) fn opj_j2k_encoder_embed_md5(p_j2k: &mut opj_j2k, embed: bool) {
+  p_j2k.m_embed_md5 = embed;
+}
+
+/// Run `f` on the output of the encoder: `p_stream`, or the in-memory
+/// codestream while the MD5 of the decoded samples is embedded.
+fn opj_j2k_with_output<F>(
+  p_j2k: &mut opj_j2k,
+  p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+  f: F,
+) -> OPJ_BOOL
+where
+  F: FnOnce(&mut opj_j2k, &mut Stream, &mut opj_event_mgr) -> OPJ_BOOL,
+{
+  let mut l_stream = match &p_j2k.m_md5_codestream {
+    Some(output) => Stream::new_memory_appender(output.clone()),
+    None => return f(p_j2k, p_stream, p_manager),
+  };
+  if f(p_j2k, &mut l_stream, p_manager) == 0 {
+    return 0i32;
+  }
+  opj_stream_flush(&mut l_stream, p_manager)
+}
+
+/// MD5 of the samples `codestream` decodes to, at full resolution.
+fn opj_j2k_decoded_md5(codestream: Vec<u8>) -> Option<[u8; 16]> {
+  let mut l_manager = opj_event_mgr::default();
+  let mut l_parameters = opj_dparameters_t::default();
+  let mut l_j2k = opj_j2k_create_decompress()?;
+  opj_j2k_setup_decoder(&mut l_j2k, &mut l_parameters);
+  let mut l_stream = Stream::new_memory(codestream);
+  let mut l_image = std::ptr::null_mut::<opj_image_t>();
+  if opj_j2k_read_header(&mut l_stream, &mut l_j2k, &mut l_image, &mut l_manager) == 0 {
+    opj_image_destroy(l_image);
+    return None;
+  }
+  let digest = unsafe {
+    if opj_j2k_decode(&mut l_j2k, &mut l_stream, &mut *l_image, &mut l_manager) != 0 {
+      opj_image_md5(&*l_image)
     } else {
-      event_msg!(p_manager, EVT_ERROR, "Invalid option: %s.\n", *option);
+      None
+    }
+  };
+  opj_image_destroy(l_image);
+  digest
+}
+
+/// Decode the codestream written in memory, replace the placeholder of the
+/// MD5 COM marker with the digest of the decoded samples and write the
+/// codestream to `p_stream`. As the samples are the ones a decoder
+/// reconstructs, the digest also matches lossy codestreams.
+fn opj_j2k_write_decoded_md5(
+  p_j2k: &mut opj_j2k,
+  p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  let mut l_codestream = match p_j2k.m_md5_codestream.take() {
+    Some(output) => output.take(),
+    None => return false,
+  };
+  let digest = match opj_j2k_decoded_md5(l_codestream.clone()) {
+    Some(digest) => digest,
+    None => {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Failed to decode the codestream to compute the MD5 of its samples\n",
+      );
       return false;
     }
+  };
+  let l_offset = p_j2k.m_md5_offset;
+  l_codestream[l_offset..l_offset + MD5_PLACEHOLDER.len()]
+    .copy_from_slice(md5_to_hex(&digest).as_bytes());
+  opj_stream_write_data(
+    p_stream,
+    l_codestream.as_ptr(),
+    l_codestream.len(),
+    p_manager,
+  ) == l_codestream.len()
+}
+
+pub(crate) fn opj_j2k_verify_embedded_md5(p_j2k: &opj_j2k, p_image: &opj_image) -> Option<bool> {
+  let embedded =
+    p_j2k
+      .m_comments
+      .iter()
+      .find_map(|(rcom, data)| match Comment::from_marker(*rcom, data) {
+        Comment::Text(text) => text.strip_prefix(MD5_COMMENT_PREFIX).map(String::from),
+        Comment::Binary(_) => None,
+      })?;
+  /* The digest is the one of the whole image at full resolution */
+  let l_private_image = unsafe { p_j2k.m_private_image.as_ref()? };
+  let l_comps = p_image.comps()?;
+  if p_image.x0 != l_private_image.x0
+    || p_image.y0 != l_private_image.y0
+    || p_image.x1 != l_private_image.x1
+    || p_image.y1 != l_private_image.y1
+    || p_image.numcomps != l_private_image.numcomps
+    || l_comps.iter().any(|comp| comp.factor != 0)
+  {
+    return None;
   }
-  true
+  let digest = opj_image_md5(p_image)?;
+  Some(embedded.trim().eq_ignore_ascii_case(&md5_to_hex(&digest)))
+}
+
+pub(crate) fn opj_j2k_get_comments(p_j2k: &opj_j2k) -> Vec<Comment> {
+  p_j2k
+    .m_comments
+    .iter()
+    .map(|(rcom, data)| Comment::from_marker(*rcom, data))
+    .collect()
 }
 
 pub(crate) fn opj_j2k_encode(
@@ -12667,7 +15313,7 @@ pub(crate) fn opj_j2k_encode(
           return 0i32;
         }
       }
-      if opj_j2k_post_write_tile(p_j2k, p_stream, p_manager) == 0 {
+      if opj_j2k_with_output(p_j2k, p_stream, p_manager, opj_j2k_post_write_tile) == 0 {
         if !l_current_data.is_null() {
           opj_free(l_current_data as *mut core::ffi::c_void);
         }
@@ -12692,7 +15338,13 @@ pub(crate) fn opj_j2k_end_compress(
   if opj_j2k_setup_end_compress(p_j2k, &mut procedure_list, p_manager) == 0 {
     return 0i32;
   }
-  if opj_j2k_exec(p_j2k, &mut procedure_list, p_stream, p_manager) == 0 {
+  let l_written = opj_j2k_with_output(p_j2k, p_stream, p_manager, |p_j2k, p_stream, p_manager| {
+    opj_j2k_exec(p_j2k, &mut procedure_list, p_stream, p_manager)
+  });
+  if l_written == 0 {
+    return 0i32;
+  }
+  if p_j2k.m_embed_md5 && !opj_j2k_write_decoded_md5(p_j2k, p_stream, p_manager) {
     return 0i32;
   }
   1i32
@@ -12729,6 +15381,12 @@ pub(crate) fn opj_j2k_start_compress(
         it_comp += 1;
       }
     }
+    /* the codestream is kept in memory until the MD5 of its samples is known */
+    p_j2k.m_md5_codestream = if p_j2k.m_embed_md5 {
+      Some(MemoryWriter::default())
+    } else {
+      None
+    };
     /* customization of the validation */
     if opj_j2k_setup_encoding_validation(p_j2k, &mut validation_list, p_manager) == 0 {
       return 0i32;
@@ -12742,10 +15400,19 @@ pub(crate) fn opj_j2k_start_compress(
       return 0i32;
     }
     /* write header */
-    if opj_j2k_exec(p_j2k, &mut procedure_list, p_stream, p_manager) == 0 {
-      return 0i32;
+    opj_j2k_with_output(p_j2k, p_stream, p_manager, |p_j2k, p_stream, p_manager| {
+      opj_j2k_exec(p_j2k, &mut procedure_list, p_stream, p_manager)
+    })
+  }
+}
+
+/// Forget the samples given to `opj_j2k_start_compress`, so that they
+/// aren't freed with the codec. Used when the samples are borrowed.
+pub(crate) fn opj_j2k_encoder_detach_image_data(p_j2k: &mut opj_j2k) {
+  if let Some(image) = unsafe { p_j2k.m_private_image.as_mut() } {
+    for comp in image.comps_mut().unwrap_or_default() {
+      comp.data = std::ptr::null_mut();
     }
-    1i32
   }
 }
 
@@ -12976,7 +15643,32 @@ fn opj_j2k_post_write_tile(
     l_available_data =
       (l_available_data as core::ffi::c_uint).wrapping_sub(l_nb_bytes_written) as OPJ_UINT32;
     l_nb_bytes_written = l_tile_size.wrapping_sub(l_available_data);
-    if opj_stream_write_data(
+    if p_j2k.m_layout != CodestreamLayout::TileFirst {
+      /* Keep the tile-parts, split by their Psot, to interleave them */
+      let data = std::slice::from_raw_parts(
+        p_j2k.m_specific_param.m_encoder.m_encoded_tile_data,
+        l_nb_bytes_written as usize,
+      );
+      let mut tile_parts = Vec::new();
+      let mut offset = 0usize;
+      while offset + 10 <= data.len() {
+        let psot = u32::from_be_bytes([
+          data[offset + 6],
+          data[offset + 7],
+          data[offset + 8],
+          data[offset + 9],
+        ]) as usize;
+        if psot < 12 || offset + psot > data.len() {
+          event_msg!(p_manager, EVT_ERROR, "Invalid tile-part length\n",);
+          return 0i32;
+        }
+        tile_parts.push(data[offset..offset + psot].to_vec());
+        offset += psot;
+      }
+      p_j2k
+        .m_tile_parts
+        .push((p_j2k.m_current_tile_number, tile_parts));
+    } else if opj_stream_write_data(
       p_stream,
       p_j2k.m_specific_param.m_encoder.m_encoded_tile_data,
       l_nb_bytes_written as OPJ_SIZE_T,
@@ -13000,6 +15692,9 @@ fn opj_j2k_setup_end_compress(
   _p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
   /* DEVELOPER CORNER, insert your custom procedures */
+  if p_j2k.m_layout != CodestreamLayout::TileFirst {
+    list.add(opj_j2k_write_interleaved_tile_parts);
+  }
   list.add(opj_j2k_write_eoc);
   if unsafe { p_j2k.m_specific_param.m_encoder.m_TLM } != 0 {
     list.add(opj_j2k_write_updated_tlm);
@@ -13015,7 +15710,7 @@ fn opj_j2k_setup_end_compress(
  * are valid. Developers wanting to extend the library can add their own validation procedures.
  */
 fn opj_j2k_setup_encoding_validation(
-  _p_j2k: &mut opj_j2k,
+  p_j2k: &mut opj_j2k,
   list: &mut opj_j2k_proc_list_t,
   _p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
@@ -13023,6 +15718,97 @@ fn opj_j2k_setup_encoding_validation(
   list.add(opj_j2k_encoding_validation);
   /* DEVELOPER CORNER, add your custom validation procedure */
   list.add(opj_j2k_mct_validation);
+  list.add(opj_j2k_poc_validation);
+  if p_j2k.m_layout != CodestreamLayout::TileFirst {
+    list.add(opj_j2k_layout_validation);
+  }
+  1i32
+}
+
+/// Split the tiles into one tile-part per layer or resolution for the
+/// codestream layout, and make the progression orders start with it.
+fn opj_j2k_layout_validation(
+  p_j2k: &mut opj_j2k,
+  _p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  let (tp_flag, name) = match p_j2k.m_layout {
+    CodestreamLayout::TileFirst => return 1i32,
+    CodestreamLayout::LayerFirst => (b'L', "layer"),
+    CodestreamLayout::ResolutionFirst => (b'R', "resolution"),
+  };
+  let layout = p_j2k.m_layout;
+  let starts_with_layout = |prg: OPJ_PROG_ORDER| match layout {
+    CodestreamLayout::LayerFirst => prg == OPJ_LRCP,
+    _ => prg == OPJ_RLCP || prg == OPJ_RPCL,
+  };
+  let l_nb_tiles = p_j2k.m_cp.tw * p_j2k.m_cp.th;
+  unsafe {
+    for tileno in 0..l_nb_tiles {
+      let l_tcp = &mut *p_j2k.m_cp.tcps.add(tileno as usize);
+      /* The packets of each progression order change are in their own tile-parts */
+      if l_tcp.POC
+        && l_tcp.pocs[..=l_tcp.numpocs as usize]
+          .iter()
+          .any(|poc| !starts_with_layout(poc.prg1))
+      {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "The %s-first codestream layout needs progression order changes starting with %ss\n",
+          name,
+          name,
+        );
+        return 0i32;
+      }
+      /* The number of tile-parts is computed from the tile progression order */
+      if !starts_with_layout(l_tcp.prg) {
+        l_tcp.prg = if tp_flag == b'L' { OPJ_LRCP } else { OPJ_RLCP };
+      }
+    }
+    p_j2k.m_cp.m_specific_param.m_enc.m_tp_on = true;
+    p_j2k.m_cp.m_specific_param.m_enc.m_tp_flag = tp_flag;
+  }
+  1i32
+}
+
+/* *
+ * Writes the tile-parts kept by the encoder, the first tile-part of every
+ * tile, then the second one...
+ *
+ * @param       p_j2k                   J2K codec.
+ * @param       p_stream                the stream to write data to.
+ * @param       p_manager               the user event manager.
+*/
+fn opj_j2k_write_interleaved_tile_parts(
+  p_j2k: &mut opj_j2k,
+  p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  let tiles = core::mem::take(&mut p_j2k.m_tile_parts);
+  let l_current_tile_number = p_j2k.m_current_tile_number;
+  let max_tile_parts = tiles
+    .iter()
+    .map(|(_, parts)| parts.len())
+    .max()
+    .unwrap_or(0);
+  for tilepartno in 0..max_tile_parts {
+    for (tileno, parts) in &tiles {
+      let data = match parts.get(tilepartno) {
+        Some(data) => data,
+        None => continue,
+      };
+      if unsafe { p_j2k.m_specific_param.m_encoder.m_TLM } != 0 {
+        p_j2k.m_current_tile_number = *tileno;
+        opj_j2k_update_tlm(p_j2k, data.len() as OPJ_UINT32);
+      }
+      if opj_stream_write_data(p_stream, data.as_ptr(), data.len(), p_manager) != data.len() {
+        event_msg!(p_manager, EVT_ERROR, "Error while writing tile-parts\n",);
+        return 0i32;
+      }
+    }
+  }
+  p_j2k.m_current_tile_number = l_current_tile_number;
   1i32
 }
 
@@ -13038,6 +15824,13 @@ fn opj_j2k_setup_header_writing(
   list.add(opj_j2k_init_info);
   list.add(opj_j2k_write_soc);
   list.add(opj_j2k_write_siz);
+  if p_j2k.m_capabilities.pcap != 0 {
+    list.add(opj_j2k_write_cap);
+  }
+  /* HTJ2K codestreams signal their corresponding profile */
+  if p_j2k.m_capabilities.is_htj2k() {
+    list.add(opj_j2k_write_cpf);
+  }
   list.add(opj_j2k_write_cod);
   list.add(opj_j2k_write_qcd);
   list.add(opj_j2k_write_all_coc);
@@ -13049,7 +15842,7 @@ fn opj_j2k_setup_header_writing(
     }
   }
   list.add(opj_j2k_write_regions);
-  if !p_j2k.m_cp.comment.is_null() {
+  if !p_j2k.m_cp.comment.is_null() || !p_j2k.m_comments.is_empty() || p_j2k.m_embed_md5 {
     list.add(opj_j2k_write_com);
   }
   /* DEVELOPER CORNER, insert your custom procedures */
@@ -13137,7 +15930,9 @@ fn opj_j2k_write_first_tile_part(
     *p_data_written = l_nb_bytes_written;
     /* Writing Psot in SOT marker */
     opj_write_bytes(l_begin_data.offset(6), l_nb_bytes_written, 4 as OPJ_UINT32); /* PSOT */
-    if p_j2k.m_specific_param.m_encoder.m_TLM != 0 {
+    /* Interleaved tile-parts are added to the TLM when written */
+    if p_j2k.m_specific_param.m_encoder.m_TLM != 0 && p_j2k.m_layout == CodestreamLayout::TileFirst
+    {
       opj_j2k_update_tlm(p_j2k, l_nb_bytes_written);
     }
     1i32
@@ -13225,7 +16020,9 @@ fn opj_j2k_write_all_tile_parts(
         .wrapping_add(l_current_nb_bytes_written) as OPJ_UINT32;
       /* Writing Psot in SOT marker */
       opj_write_bytes(l_begin_data.offset(6), l_part_tile_size, 4 as OPJ_UINT32); /* PSOT */
-      if p_j2k.m_specific_param.m_encoder.m_TLM != 0 {
+      if p_j2k.m_specific_param.m_encoder.m_TLM != 0
+        && p_j2k.m_layout == CodestreamLayout::TileFirst
+      {
         opj_j2k_update_tlm(p_j2k, l_part_tile_size);
       }
       p_j2k.m_specific_param.m_encoder.m_current_tile_part_number = p_j2k
@@ -13291,7 +16088,9 @@ fn opj_j2k_write_all_tile_parts(
           .wrapping_add(l_current_nb_bytes_written) as OPJ_UINT32;
         /* Writing Psot in SOT marker */
         opj_write_bytes(l_begin_data.offset(6), l_part_tile_size, 4 as OPJ_UINT32); /* PSOT */
-        if p_j2k.m_specific_param.m_encoder.m_TLM != 0 {
+        if p_j2k.m_specific_param.m_encoder.m_TLM != 0
+          && p_j2k.m_layout == CodestreamLayout::TileFirst
+        {
           opj_j2k_update_tlm(p_j2k, l_part_tile_size);
         }
         p_j2k.m_specific_param.m_encoder.m_current_tile_part_number = p_j2k
@@ -13572,7 +16371,7 @@ pub(crate) fn opj_j2k_write_tile(
         );
         return 0i32;
       }
-      if opj_j2k_post_write_tile(p_j2k, p_stream, p_manager) == 0 {
+      if opj_j2k_with_output(p_j2k, p_stream, p_manager, opj_j2k_post_write_tile) == 0 {
         event_msg!(
           p_manager,
           EVT_ERROR,
@@ -22,9 +22,9 @@ pub const JP2_IMG_STATE_NONE: C2RustUnnamed_3 = 0;
 
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Jp2BoxHeader {
-  pub length: u32,
+  pub length: u64,
   pub ty: Jp2BoxType,
-  pub header_length: u32,
+  pub header_length: u64,
 }
 
 impl Jp2BoxHeader {
@@ -50,7 +50,7 @@ impl Jp2BoxHeader {
     self.ty.to_u32().unwrap_or_default()
   }
 
-  pub fn content_length(&self) -> u32 {
+  pub fn content_length(&self) -> u64 {
     self.length - self.header_length
   }
 
@@ -58,7 +58,7 @@ impl Jp2BoxHeader {
   fn read<R: Read + ?Sized>(&mut self, reader: &mut R, max_size: usize) -> Result<(), String> {
     self.length = reader
       .read_u32::<BigEndian>()
-      .map_err(|e| format!("Truncated JP2 Box header: {e:?}"))?;
+      .map_err(|e| format!("Truncated JP2 Box header: {e:?}"))? as u64;
     self.ty = reader
       .read_u32::<BigEndian>()
       .map_err(|e| format!("Truncated JP2 Box header: {e:?}"))?
@@ -69,36 +69,40 @@ impl Jp2BoxHeader {
         return Err(format!("Can't handle box of undefined size."));
       }
       /* last box */
-      if max_size > u32::MAX as usize {
-        // TODO: Handle large boxes?
-        return Err(format!("Cannot handle box sizes higher than 2^32"));
-      }
-      self.length = max_size as u32;
-      assert!(self.length as usize == max_size);
+      self.length = max_size as u64;
       return Ok(());
     }
     /* do we have a "special very large box ?" */
     if self.length == 1 {
       /* read then the XLBox */
-      let xl_part_size = reader
-        .read_u32::<BigEndian>()
-        .map_err(|e| format!("Truncated JP2 XLBox header: {e:?}"))?;
-      let length = reader
-        .read_u32::<BigEndian>()
+      self.length = reader
+        .read_u64::<BigEndian>()
         .map_err(|e| format!("Truncated JP2 XLBox header: {e:?}"))?;
       self.header_length += 8;
-      if xl_part_size != 0 {
-        // TODO: Handle large boxes?
-        return Err(format!("Cannot handle box sizes higher than 2^32"));
+      if self.length > i64::MAX as u64 {
+        return Err(format!("Invalid JP2 XLBox length: {}", self.length));
       }
-      self.length = length;
     }
     Ok(())
   }
 
+  /// Use the XLBox form of the header, for boxes of 4GB or more.
+  fn set_xl(&mut self) {
+    if self.header_length == 8 {
+      self.header_length = 16;
+      self.length += 8;
+    }
+  }
+
   fn write<W: Write>(&self, writer: &mut W) -> bool {
-    writer.write_u32::<BigEndian>(self.length).is_ok()
-      && writer.write_u32::<BigEndian>(self.ty_u32()).is_ok()
+    if self.header_length == 16 {
+      writer.write_u32::<BigEndian>(1).is_ok()
+        && writer.write_u32::<BigEndian>(self.ty_u32()).is_ok()
+        && writer.write_u64::<BigEndian>(self.length).is_ok()
+    } else {
+      writer.write_u32::<BigEndian>(self.length as u32).is_ok()
+        && writer.write_u32::<BigEndian>(self.ty_u32()).is_ok()
+    }
   }
 
   fn read_content(
@@ -149,9 +153,9 @@ impl HeaderWriter {
     }
   }
 
-  fn run(&mut self, jp2: &mut opj_jp2) -> Option<u32> {
+  fn run(&mut self, jp2: &mut opj_jp2) -> Option<u64> {
     if (self.handler)(jp2, &mut self.data) {
-      Some(self.data.len() as u32)
+      Some(self.data.len() as u64)
     } else {
       None
     }
@@ -242,7 +246,6 @@ fn opj_jp2_read_ihdr(jp2: &mut opj_jp2, mut buf: &[u8], p_manager: &mut opj_even
   // IPR
   jp2.IPR = buf.read_u8().expect("Buffer should have enough data") as u32;
 
-  jp2.j2k.m_cp.allow_different_bit_depth_sign = jp2.bpc == 255;
   jp2.j2k.ihdr_w = jp2.w;
   jp2.j2k.ihdr_h = jp2.h;
   jp2.has_ihdr = 1 as OPJ_BYTE;
@@ -281,7 +284,7 @@ fn opj_jp2_write_ihdr(mut jp2: &mut opj_jp2, buf: &mut Vec<u8>) -> bool {
 */
 fn opj_jp2_write_bpcc(mut jp2: &mut opj_jp2, buf: &mut Vec<u8>) -> bool {
   let mut header = Jp2BoxHeader::new(Jp2BoxType::BPCC);
-  header.length += jp2.comps.len() as u32;
+  header.length += jp2.comps.len() as u64;
   header.write(buf);
   for comp in &jp2.comps {
     buf.push(comp.bpcc as u8);
@@ -314,9 +317,48 @@ fn opj_jp2_read_bpcc(jp2: &mut opj_jp2, mut buf: &[u8], p_manager: &mut opj_even
     /* read each BPCC component */
     comp.bpcc = buf.read_u8().expect("Buffer should have enough data") as u32;
   }
+  jp2.has_bpcc = 1 as OPJ_BYTE;
   1
 }
 
+/// Check the bit depths given by the IHDR or BPCC box against the ones of
+/// the codestream. The codestream takes precedence: the components keep
+/// its precision and signedness, and a single warning reports the
+/// mismatches.
+fn opj_jp2_check_bit_depths(jp2: &opj_jp2, image: &opj_image, p_manager: &mut opj_event_mgr) {
+  if jp2.bpc == 255 && jp2.has_bpcc == 0 {
+    /* Already reported when reading the JP2 Header box */
+    return;
+  }
+  let comps = match image.comps() {
+    Some(comps) => comps,
+    None => return,
+  };
+  let mut mismatches = jp2
+    .comps
+    .iter()
+    .zip(comps)
+    .enumerate()
+    .map(|(compno, (j_comp, comp))| {
+      let bpcc = if jp2.bpc == 255 { j_comp.bpcc } else { jp2.bpc };
+      (compno, bpcc, comp)
+    })
+    .filter(|(_, bpcc, comp)| *bpcc != comp.prec.wrapping_sub(1).wrapping_add(comp.sgnd << 7));
+  if let Some((compno, bpcc, comp)) = mismatches.next() {
+    event_msg!(
+      p_manager,
+      EVT_WARNING,
+      "JP2 bit depths of %d component(s) differ from the codestream ones, using the latter (component %d: prec(%d) sgnd(%d) instead of prec(%d) sgnd(%d))\n",
+      mismatches.count() + 1,
+      compno,
+      comp.prec,
+      comp.sgnd,
+      (bpcc & 0x7f) + 1,
+      bpcc >> 7,
+    );
+  }
+}
+
 /* *
  * Writes the Channel Definition box.
  *
@@ -330,7 +372,7 @@ fn opj_jp2_write_cdef(mut jp2: &mut opj_jp2, buf: &mut Vec<u8>) -> bool {
   let len = info.len() as u32;
   assert!(info.len() > 0);
   let mut header = Jp2BoxHeader::new(Jp2BoxType::CDEF);
-  header.length += 2 + (len * 6);
+  header.length += 2 + (len as u64 * 6);
   header.write(buf);
   buf.write_u16::<BigEndian>(len as u16).unwrap();
   for info in info {
@@ -355,7 +397,7 @@ fn opj_jp2_write_colr(mut jp2: &mut opj_jp2, buf: &mut Vec<u8>) -> bool {
     1 => 4,
     2 => {
       assert!(jp2.color.icc_profile_len != 0);
-      jp2.color.icc_profile_len
+      jp2.color.icc_profile_len as u64
     }
     _ => return false,
   };
@@ -1019,7 +1061,7 @@ pub(crate) fn opj_jp2_apply_color_postprocessing(
   p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
   unsafe {
-    if jp2.j2k.m_specific_param.m_decoder.m_numcomps_to_decode != 0 {
+    if jp2.j2k.m_specific_param.m_decoder.m_numcomps_to_decode != 0 || jp2.j2k.m_cp.raw_components {
       /* Bypass all JP2 component transforms */
       return 1;
     }
@@ -1144,7 +1186,7 @@ fn opj_jp2_write_ftyp(
 ) -> OPJ_BOOL {
   /* FTYP */
   let mut header = Jp2BoxHeader::new(Jp2BoxType::FTYP);
-  header.length += 8 + (4 * jp2.cl.len() as u32);
+  header.length += 8 + (4 * jp2.cl.len() as u64);
   let mut buf = Vec::with_capacity(header.length as usize);
   header.write(&mut buf);
 
@@ -1165,6 +1207,258 @@ fn opj_jp2_write_ftyp(
   1
 }
 
+/* *
+ * Writes a RREQ box - Reader requirements box
+ *
+ * @param   stream         the stream to write data to.
+ * @param   jp2         the jpeg2000 file codec.
+ * @param   p_manager   the user event manager.
+ *
+ * @return  true if writing was successful.
+ */
+fn opj_jp2_write_rreq(
+  jp2: &mut opj_jp2,
+  stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  let rreq = match &jp2.reader_requirements {
+    Some(rreq) => rreq,
+    None => return 1,
+  };
+  /* Use the smallest mask length that holds all the masks */
+  let all_masks = rreq
+    .standard_features
+    .iter()
+    .map(|(_, sm)| *sm)
+    .chain(rreq.vendor_features.iter().map(|(_, vm)| *vm))
+    .fold(rreq.fuam | rreq.dcm, |acc, mask| acc | mask);
+  let ml = (64 - all_masks.leading_zeros() as usize).div_ceil(8).max(1);
+
+  let mut header = Jp2BoxHeader::new(Jp2BoxType::RREQ);
+  header.length += (1
+    + 2 * ml
+    + 2
+    + rreq.standard_features.len() * (2 + ml)
+    + 2
+    + rreq.vendor_features.len() * (16 + ml)) as u64;
+  let mut buf = Vec::with_capacity(header.length as usize);
+  header.write(&mut buf);
+
+  buf.write_u8(ml as u8).unwrap();
+  buf.write_uint::<BigEndian>(rreq.fuam, ml).unwrap();
+  buf.write_uint::<BigEndian>(rreq.dcm, ml).unwrap();
+  buf
+    .write_u16::<BigEndian>(rreq.standard_features.len() as u16)
+    .unwrap();
+  for (sf, sm) in &rreq.standard_features {
+    buf.write_u16::<BigEndian>(*sf).unwrap();
+    buf.write_uint::<BigEndian>(*sm, ml).unwrap();
+  }
+  buf
+    .write_u16::<BigEndian>(rreq.vendor_features.len() as u16)
+    .unwrap();
+  for (vf, vm) in &rreq.vendor_features {
+    buf.extend_from_slice(vf);
+    buf.write_uint::<BigEndian>(*vm, ml).unwrap();
+  }
+  if stream.write_all(buf.as_slice()).is_err() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Error while writing rreq data to stream\n",
+    );
+    return 0;
+  }
+  1
+}
+
+/* *
+ * Writes the intellectual property box, set by the encoder.
+ *
+ * @param   jp2         the jpeg2000 file codec.
+ * @param   stream      the stream to write data to.
+ * @param   p_manager   the user event manager.
+ *
+ * @return  true if writing was successful.
+ */
+fn opj_jp2_write_jp2i(
+  jp2: &mut opj_jp2,
+  stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  let ipr = match &jp2.ipr {
+    Some(ipr) => ipr,
+    None => return 1,
+  };
+  let mut header = Jp2BoxHeader::new(Jp2BoxType::JP2I);
+  header.length += ipr.len() as u64;
+  if header.length > u32::MAX as u64 {
+    header.set_xl();
+  }
+  if !header.write(stream) || stream.write_all(ipr).is_err() {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Error while writing jp2i data to stream\n",
+    );
+    return 0;
+  }
+  1
+}
+
+/// Parse the contents of a reader requirements box.
+fn opj_jp2_parse_rreq(mut buf: &[u8]) -> Option<ReaderRequirements> {
+  let ml = buf.read_u8().ok()? as usize;
+  if ml == 0 || ml > 8 {
+    return None;
+  }
+  let fuam = buf.read_uint::<BigEndian>(ml).ok()?;
+  let dcm = buf.read_uint::<BigEndian>(ml).ok()?;
+  let nsf = buf.read_u16::<BigEndian>().ok()?;
+  let mut standard_features = Vec::new();
+  for _ in 0..nsf {
+    let sf = buf.read_u16::<BigEndian>().ok()?;
+    let sm = buf.read_uint::<BigEndian>(ml).ok()?;
+    standard_features.push((sf, sm));
+  }
+  let nvf = buf.read_u16::<BigEndian>().ok()?;
+  let mut vendor_features = Vec::new();
+  for _ in 0..nvf {
+    let mut vf = [0u8; 16];
+    buf.read_exact(&mut vf).ok()?;
+    let vm = buf.read_uint::<BigEndian>(ml).ok()?;
+    vendor_features.push((vf, vm));
+  }
+  Some(ReaderRequirements {
+    fuam,
+    dcm,
+    standard_features,
+    vendor_features,
+  })
+}
+
+/* *
+ * Reads a RREQ box - Reader requirements box
+ *
+ * @param   jp2         the jpeg2000 file codec.
+ * @param   p_header_data   the data contained in the RREQ box.
+ * @param   p_manager   the user event manager.
+ */
+fn opj_jp2_read_rreq(jp2: &mut opj_jp2, p_header_data: &[u8], p_manager: &mut opj_event_mgr) {
+  jp2.reader_requirements = opj_jp2_parse_rreq(p_header_data);
+  if jp2.reader_requirements.is_none() {
+    event_msg!(
+      p_manager,
+      EVT_WARNING,
+      "Invalid reader requirements box, ignoring it\n",
+    );
+  }
+}
+
+/// Maximum nesting of association boxes, deeper ones are ignored.
+const OPJ_JP2_MAX_ASOC_DEPTH: usize = 32;
+
+/// Split the contents of a super-box into its boxes, `None` if a box length
+/// is invalid.
+fn opj_jp2_split_boxes(mut data: &[u8]) -> Option<Vec<(u32, &[u8])>> {
+  let mut boxes = Vec::new();
+  while !data.is_empty() {
+    if data.len() < 8 {
+      return None;
+    }
+    let mut length = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as u64;
+    let ty = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    let mut header_length = 8u64;
+    if length == 0 {
+      /* last box */
+      length = data.len() as u64;
+    } else if length == 1 {
+      if data.len() < 16 {
+        return None;
+      }
+      let mut xl_length = [0u8; 8];
+      xl_length.copy_from_slice(&data[8..16]);
+      length = u64::from_be_bytes(xl_length);
+      header_length = 16;
+    }
+    if length < header_length || length > data.len() as u64 {
+      return None;
+    }
+    boxes.push((ty, &data[header_length as usize..length as usize]));
+    data = &data[length as usize..];
+  }
+  Some(boxes)
+}
+
+/* *
+ * Reads an ASOC box - Association box
+ *
+ * The nested association boxes are read with an explicit work list and
+ * their depth is bounded, so that crafted files can't overflow the stack.
+ *
+ * @param   jp2         the jpeg2000 file codec.
+ * @param   p_header_data   the data contained in the ASOC box.
+ * @param   after_codestream    is the ASOC box after the codestream box?
+ * @param   p_manager   the user event manager.
+ */
+fn opj_jp2_read_asoc(
+  jp2: &mut opj_jp2,
+  p_header_data: &[u8],
+  after_codestream: bool,
+  p_manager: &mut opj_event_mgr,
+) {
+  /* Associations with the index of their parent, parents come first */
+  let mut nodes = vec![(Association::default(), None)];
+  let mut work = vec![(0usize, p_header_data, 1usize)];
+  while let Some((node, data, depth)) = work.pop() {
+    let boxes = match opj_jp2_split_boxes(data) {
+      Some(boxes) => boxes,
+      None => {
+        event_msg!(
+          p_manager,
+          EVT_WARNING,
+          "Invalid box size in association box, ignoring its contents\n",
+        );
+        continue;
+      }
+    };
+    let mut children = Vec::new();
+    for (ty, content) in boxes {
+      match Jp2BoxType::from(ty) {
+        Jp2BoxType::ASOC if depth >= OPJ_JP2_MAX_ASOC_DEPTH => {
+          event_msg!(
+            p_manager,
+            EVT_WARNING,
+            "Association boxes nested deeper than %d levels are ignored\n",
+            OPJ_JP2_MAX_ASOC_DEPTH,
+          );
+        }
+        Jp2BoxType::ASOC => {
+          nodes.push((Association::default(), Some(node)));
+          children.push((nodes.len() - 1, content, depth + 1));
+        }
+        Jp2BoxType::LBL if nodes[node].0.label.is_none() => {
+          nodes[node].0.label = Some(String::from_utf8_lossy(content).into_owned());
+        }
+        _ => nodes[node].0.raw_boxes.push(RawBox {
+          ty: ty.to_be_bytes(),
+          data: content.to_vec(),
+          after_codestream,
+        }),
+      }
+    }
+    /* Read the children in file order */
+    work.extend(children.into_iter().rev());
+  }
+  /* Move the children into their parents, last ones first */
+  while let Some((association, parent)) = nodes.pop() {
+    match parent {
+      Some(parent) => nodes[parent].0.children.insert(0, association),
+      None => jp2.associations.push(association),
+    }
+  }
+}
+
 /* *
  * Writes the Jpeg2000 codestream Header box - JP2C Header box. This function must be called AFTER the coding has been done.
  *
@@ -1185,7 +1479,17 @@ fn opj_jp2_write_jp2c(
   }
   let j2k_codestream_exit = opj_stream_tell(stream);
   let mut header = Jp2BoxHeader::new(Jp2BoxType::JP2C);
-  header.length = (j2k_codestream_exit - jp2.j2k_codestream_offset) as u32;
+  header.length = (j2k_codestream_exit - jp2.j2k_codestream_offset) as u64;
+  if jp2.jp2c_xlbox {
+    header.header_length = 16;
+  } else if header.length > u32::MAX as u64 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Codestream too large for the 32-bit length of its box, use the JP2C_XLBOX=YES option\n",
+    );
+    return 0;
+  }
   if opj_stream_seek(stream, jp2.j2k_codestream_offset, p_manager) == 0 {
     event_msg!(p_manager, EVT_ERROR, "Failed to seek in the stream.\n",);
     return 0;
@@ -1205,6 +1509,73 @@ fn opj_jp2_write_jp2c(
   1
 }
 
+/* *
+ * Writes the preserved boxes that precede the codestream box.
+ *
+ * @param   jp2         the jpeg2000 file codec.
+ * @param   stream      the stream to write data to.
+ * @param   p_manager   user event manager.
+ *
+ * @return true if writing was successful.
+ */
+fn opj_jp2_write_boxes_before_jp2c(
+  jp2: &mut opj_jp2,
+  stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  opj_jp2_write_preserved_boxes(jp2, false, stream, p_manager)
+}
+
+/* *
+ * Writes the preserved boxes that follow the codestream box.
+ *
+ * @param   jp2         the jpeg2000 file codec.
+ * @param   stream      the stream to write data to.
+ * @param   p_manager   user event manager.
+ *
+ * @return true if writing was successful.
+ */
+fn opj_jp2_write_boxes_after_jp2c(
+  jp2: &mut opj_jp2,
+  stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  opj_jp2_write_preserved_boxes(jp2, true, stream, p_manager)
+}
+
+fn opj_jp2_write_preserved_boxes(
+  jp2: &mut opj_jp2,
+  after_codestream: bool,
+  stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  /* Preserved rreq and jp2i boxes are replaced by the encoder's reader
+   * requirements and intellectual property rights */
+  let skip_rreq = jp2.reader_requirements.is_some();
+  let skip_jp2i = jp2.ipr.is_some();
+  for raw_box in jp2.boxes.iter().chain(&jp2.uuid_boxes).filter(|b| {
+    b.after_codestream == after_codestream
+      && !(skip_rreq && &b.ty == b"rreq")
+      && !(skip_jp2i && &b.ty == b"jp2i")
+  }) {
+    let mut header = Jp2BoxHeader::new(u32::from_be_bytes(raw_box.ty).into());
+    header.length += raw_box.data.len() as u64;
+    /* Larger boxes have LBox set to 1 and their length in the XLBox */
+    if header.length > u32::MAX as u64 {
+      header.set_xl();
+    }
+    if !header.write(stream) || stream.write_all(&raw_box.data).is_err() {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Error while writing preserved box to stream\n",
+      );
+      return 0;
+    }
+  }
+  1
+}
+
 /* *
  * Writes a jpeg2000 file signature box.
  *
@@ -1249,10 +1620,165 @@ pub(crate) fn opj_jp2_setup_decoder(mut jp2: &mut opj_jp2, mut parameters: &mut
   jp2.ignore_pclr_cmap_cdef = (parameters.flags & 0x1u32) as OPJ_BOOL;
 }
 
+/// Reset a decoder to read a new file, see `opj_j2k_reset_decoder()`.
+pub(crate) fn opj_jp2_reset_decoder(jp2: &mut opj_jp2) -> bool {
+  let mut l_jp2 = match opj_jp2_create(1i32) {
+    Some(l_jp2) => l_jp2,
+    None => return false,
+  };
+  if !opj_j2k_reset_decoder(&mut jp2.j2k) {
+    return false;
+  }
+  std::mem::swap(&mut l_jp2.j2k, &mut jp2.j2k);
+  l_jp2.ignore_pclr_cmap_cdef = jp2.ignore_pclr_cmap_cdef;
+  *jp2 = l_jp2;
+  true
+}
+
 pub(crate) fn opj_jp2_decoder_set_strict_mode(mut jp2: &mut opj_jp2, mut strict: OPJ_BOOL) {
   opj_j2k_decoder_set_strict_mode(&mut jp2.j2k, strict);
 }
 
+pub(crate) fn opj_jp2_decoder_set_partial(jp2: &mut opj_jp2, partial: bool) {
+  opj_j2k_decoder_set_partial(&mut jp2.j2k, partial);
+}
+
+pub(crate) fn opj_jp2_decoder_set_raw_components(jp2: &mut opj_jp2, raw: bool) {
+  opj_j2k_decoder_set_raw_components(&mut jp2.j2k, raw);
+}
+
+pub(crate) fn opj_jp2_decoder_set_validate_profile(jp2: &mut opj_jp2, validate: bool) {
+  opj_j2k_decoder_set_validate_profile(&mut jp2.j2k, validate);
+}
+
+pub(crate) fn opj_jp2_decoder_set_tile_parts_correction(jp2: &mut opj_jp2, enable: bool) {
+  opj_j2k_decoder_set_tile_parts_correction(&mut jp2.j2k, enable);
+}
+
+pub(crate) fn opj_jp2_decoder_set_error_resilience(jp2: &mut opj_jp2, flags: ErrorResilienceFlags) {
+  opj_j2k_decoder_set_error_resilience(&mut jp2.j2k, flags);
+}
+
+pub(crate) fn opj_jp2_decoder_set_limits(jp2: &mut opj_jp2, limits: DecodeLimits) {
+  opj_j2k_decoder_set_limits(&mut jp2.j2k, limits);
+}
+
+pub(crate) fn opj_jp2_decoder_set_dc_shift(
+  jp2: &mut opj_jp2,
+  compno: OPJ_UINT32,
+  shift: Option<OPJ_INT32>,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_decoder_set_dc_shift(&mut jp2.j2k, compno, shift, p_manager)
+}
+
+pub(crate) fn opj_jp2_get_dc_shift(jp2: &opj_jp2, compno: OPJ_UINT32) -> Option<OPJ_INT32> {
+  opj_j2k_get_dc_shift(&jp2.j2k, compno)
+}
+
+pub(crate) fn opj_jp2_decoder_capture_boxes(jp2: &mut opj_jp2, capture: bool) {
+  jp2.capture_boxes = capture;
+}
+
+pub(crate) fn opj_jp2_get_boxes(jp2: &opj_jp2) -> Vec<RawBox> {
+  jp2.boxes.clone()
+}
+
+pub(crate) fn opj_jp2_encoder_preserve_boxes(
+  jp2: &mut opj_jp2,
+  boxes: Vec<RawBox>,
+  _p_manager: &mut opj_event_mgr,
+) -> bool {
+  let mut preserved = Vec::with_capacity(boxes.len());
+  for raw_box in boxes {
+    let ty: Jp2BoxType = u32::from_be_bytes(raw_box.ty).into();
+    /* These boxes are generated by the encoder */
+    if ty.is_file_header() || ty == Jp2BoxType::JP2C {
+      continue;
+    }
+    preserved.push(raw_box);
+  }
+  jp2.boxes = preserved;
+  true
+}
+
+pub(crate) fn opj_jp2_encoder_add_uuid_box(jp2: &mut opj_jp2, uuid: [u8; 16], data: Vec<u8>) {
+  jp2.uuid_boxes.push(RawBox::new_uuid(uuid, data));
+}
+
+pub(crate) fn opj_jp2_encoder_set_geo_metadata(jp2: &mut opj_jp2, data: &[u8]) {
+  /* Replace the GeoJP2 box set before */
+  jp2
+    .uuid_boxes
+    .retain(|b| b.uuid().is_none_or(|(uuid, _)| uuid != GEOJP2_UUID));
+  opj_jp2_encoder_add_uuid_box(jp2, GEOJP2_UUID, data.to_vec());
+}
+
+/// Decode a precinct, the JP2 colour transforms are not applied.
+pub(crate) fn opj_jp2_decode_precinct(
+  jp2: &mut opj_jp2,
+  p_stream: &mut Stream,
+  p_image: &mut opj_image,
+  precinct: PrecinctIndex,
+  p_manager: &mut opj_event_mgr,
+) -> Option<PrecinctData> {
+  opj_j2k_decode_precinct(&mut jp2.j2k, p_stream, p_image, precinct, p_manager)
+}
+
+pub(crate) fn opj_jp2_get_packet_order(
+  jp2: &mut opj_jp2,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Vec<PacketCoord>> {
+  opj_j2k_get_packet_order(&mut jp2.j2k, tile_index, p_manager)
+}
+
+pub(crate) fn opj_jp2_get_packet_count(
+  jp2: &mut opj_jp2,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<OPJ_UINT32> {
+  opj_j2k_get_packet_count(&mut jp2.j2k, tile_index, p_manager)
+}
+
+pub(crate) fn opj_jp2_get_layer_sizes(
+  jp2: &mut opj_jp2,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Vec<u64>> {
+  opj_j2k_get_layer_sizes(&mut jp2.j2k, tile_index, p_manager)
+}
+
+pub(crate) fn opj_jp2_build_packet_index(
+  jp2: &mut opj_jp2,
+  p_stream: &mut Stream,
+  p_manager: &mut opj_event_mgr,
+) -> Option<PacketIndex> {
+  opj_j2k_build_packet_index(&mut jp2.j2k, p_stream, p_manager)
+}
+
+pub(crate) fn opj_jp2_get_codestream_byte_ranges(
+  jp2: &opj_jp2,
+  p_manager: &mut opj_event_mgr,
+) -> Option<CodestreamByteRanges> {
+  opj_j2k_get_codestream_byte_ranges(&jp2.j2k, p_manager)
+}
+
+pub(crate) fn opj_jp2_get_decoded_region(jp2: &opj_jp2) -> DecodedRegion {
+  opj_j2k_get_decoded_region(&jp2.j2k)
+}
+
+pub(crate) fn opj_jp2_tile_at(jp2: &opj_jp2, x: OPJ_UINT32, y: OPJ_UINT32) -> Option<OPJ_UINT32> {
+  opj_j2k_tile_at(&jp2.j2k, x, y)
+}
+
+pub(crate) fn opj_jp2_tile_bounds(
+  jp2: &opj_jp2,
+  tile_index: OPJ_UINT32,
+) -> Option<(OPJ_UINT32, OPJ_UINT32, OPJ_UINT32, OPJ_UINT32)> {
+  opj_j2k_tile_bounds(&jp2.j2k, tile_index)
+}
+
 pub(crate) fn opj_jp2_set_threads(mut jp2: &mut opj_jp2, mut num_threads: OPJ_UINT32) -> OPJ_BOOL {
   opj_j2k_set_threads(&mut jp2.j2k, num_threads)
 }
@@ -1285,6 +1811,10 @@ pub(crate) fn opj_jp2_setup_encoder(
 
   /* Profile box */
 
+  /* The size of the codestream is only known once written, its box has a
+   * 64-bit length when it may not fit in 32 bits */
+  jp2.jp2c_xlbox = image.uncompressed_size() > u32::MAX as u64 / 2;
+
   jp2.brand = Jp2BoxType::JP2.to_u32().unwrap(); /* BR */
   jp2.minversion = 0 as OPJ_UINT32; /* MinV */
   jp2.cl = Vec::with_capacity(1);
@@ -1306,9 +1836,8 @@ pub(crate) fn opj_jp2_setup_encoder(
   let sign = comps[0].sgnd;
   jp2.bpc = depth_0.wrapping_add(sign << 7);
   for comp in &comps[1..] {
-    let mut depth = comp.prec.wrapping_sub(1);
-    //sign = comp.sgnd;
-    if depth_0 != depth {
+    /* Components differing in depth or sign are described by a BPCC box */
+    if comp.prec.wrapping_sub(1) != depth_0 || comp.sgnd != sign {
       jp2.bpc = 255 as OPJ_UINT32
     }
   }
@@ -1316,8 +1845,8 @@ pub(crate) fn opj_jp2_setup_encoder(
   jp2.C = 7 as OPJ_UINT32;
   /* UnkC, colorspace specified in colr box */
   jp2.UnkC = 0 as OPJ_UINT32;
-  /* IPR, no intellectual property */
-  jp2.IPR = 0 as OPJ_UINT32;
+  /* IPR, set if an intellectual property box is written */
+  jp2.IPR = jp2.ipr.is_some() as OPJ_UINT32;
 
   /* BitsPerComponent box */
   for (j_comp, comp) in jp2.comps.iter_mut().zip(&comps[..]) {
@@ -1404,9 +1933,11 @@ pub(crate) fn opj_jp2_setup_encoder(
       } else {
         if comps[i].alpha != 0 {
           /* we'll be here exactly once */
+          /* Opacity (1) or premultiplied opacity (2) channel */
+          let typ = if comps[i].alpha == 2 { 2 } else { 1 };
           cdef.info.push(opj_jp2_cdef_info {
             cn,
-            typ: 1,  /* Opacity channel */
+            typ,
             asoc: 0, /* Apply alpha channel to the whole image */
           })
         } else {
@@ -1478,11 +2009,14 @@ pub(crate) fn opj_jp2_end_compress(
  * Developers wanting to extend the library can add their own writing procedures.
  */
 fn opj_jp2_setup_end_header_writing(
-  _jp2: &mut opj_jp2,
+  jp2: &mut opj_jp2,
   list: &mut opj_jp2_proc_list_t,
   _p_manager: &mut opj_event_mgr,
 ) -> OPJ_BOOL {
   list.add(opj_jp2_write_jp2c);
+  if jp2.boxes.iter().any(|b| b.after_codestream) {
+    list.add(opj_jp2_write_boxes_after_jp2c);
+  }
   /* DEVELOPER CORNER, add your custom procedures */
   1i32
 }
@@ -1551,16 +2085,21 @@ fn opj_jp2_read_header_procedure(
   let mut data = Vec::<u8>::new();
 
   while let Some(header) = Jp2BoxHeader::from_stream(stream) {
+    /* Boxes read after the codestream box are past its start. jp2_state
+     * can't tell, JP2_STATE_UNKNOWN sets all of its bits */
+    let after_codestream =
+      jp2.j2k_codestream_offset > 0 && opj_stream_tell(stream) > jp2.j2k_codestream_offset;
     /* is it the codestream box ? */
     if header.ty == Jp2BoxType::JP2C {
       if jp2.jp2_state & JP2_STATE_HEADER as core::ffi::c_uint != 0 {
         jp2.jp2_state |= JP2_STATE_CODESTREAM as core::ffi::c_uint;
+        jp2.j2k_codestream_offset = opj_stream_tell(stream);
         return 1i32;
       } else {
         event_msg!(p_manager, EVT_ERROR, "bad placed jpeg codestream\n",);
         return 0i32;
       }
-    } else if header.length == 0u32 {
+    } else if header.length == 0 {
       event_msg!(
         p_manager,
         EVT_ERROR,
@@ -1580,7 +2119,7 @@ fn opj_jp2_read_header_procedure(
         return 0i32;
       }
     }
-    let data_size = header.content_length() as usize;
+    let data_size = header.content_length();
     if header.ty.is_file_header() || header.ty.is_image_header() {
       if !header.ty.is_file_header() {
         event_msg!(
@@ -1635,6 +2174,7 @@ fn opj_jp2_read_header_procedure(
       if header.read_content(jp2, &data, p_manager).is_err() {
         return 0;
       }
+      opj_jp2_capture_box(jp2, &header, &data, after_codestream);
     } else {
       if jp2.jp2_state & JP2_STATE_SIGNATURE == 0 {
         event_msg!(
@@ -1653,7 +2193,35 @@ fn opj_jp2_read_header_procedure(
         return 0i32;
       }
       jp2.jp2_state |= JP2_STATE_UNKNOWN;
-      if opj_stream_skip(stream, data_size as OPJ_OFF_T, p_manager) != data_size as i64 {
+      /* The IPR, reader requirements, uuid and association boxes are read,
+       * others are skipped */
+      let is_read = matches!(
+        header.ty,
+        Jp2BoxType::JP2I | Jp2BoxType::RREQ | Jp2BoxType::UUID | Jp2BoxType::ASOC
+      );
+      if (jp2.capture_boxes || is_read)
+        && data_size as OPJ_OFF_T <= opj_stream_get_number_byte_left(stream)
+      {
+        data.resize(data_size as usize, 0);
+        if stream.read_exact(data.as_mut_slice()).is_err() {
+          event_msg!(
+            p_manager,
+            EVT_ERROR,
+            "Problem with reading JPEG2000 box, stream error\n",
+          );
+          return 0i32;
+        }
+        match header.ty {
+          Jp2BoxType::JP2I => jp2.ipr = Some(data.clone()),
+          Jp2BoxType::RREQ => opj_jp2_read_rreq(jp2, &data, p_manager),
+          Jp2BoxType::ASOC => opj_jp2_read_asoc(jp2, &data, after_codestream, p_manager),
+          Jp2BoxType::UUID if data.starts_with(&GEOJP2_UUID) && jp2.geo_metadata.is_none() => {
+            jp2.geo_metadata = Some(data[16..].to_vec())
+          }
+          _ => (),
+        }
+        opj_jp2_capture_box(jp2, &header, &data, after_codestream);
+      } else if opj_stream_skip(stream, data_size as OPJ_OFF_T, p_manager) != data_size as i64 {
         if jp2.jp2_state & JP2_STATE_CODESTREAM != 0 {
           /* If we already read the codestream, do not error out */
           /* Needed for data/input/nonregression/issue254.jp2 */
@@ -1677,6 +2245,22 @@ fn opj_jp2_read_header_procedure(
   1i32
 }
 
+/// Keep a copy of a top-level box when capturing boxes.
+fn opj_jp2_capture_box(
+  jp2: &mut opj_jp2,
+  header: &Jp2BoxHeader,
+  data: &[u8],
+  after_codestream: bool,
+) {
+  if jp2.capture_boxes {
+    jp2.boxes.push(RawBox {
+      ty: header.ty_u32().to_be_bytes(),
+      data: data.to_vec(),
+      after_codestream,
+    });
+  }
+}
+
 /* *
  * Executes the given procedures on the given codec.
  *
@@ -1824,7 +2408,8 @@ fn opj_jp2_skip_jp2c(
   /* preconditions */
 
   jp2.j2k_codestream_offset = opj_stream_tell(stream);
-  if opj_stream_skip(stream, 8 as OPJ_OFF_T, p_manager) != 8i64 {
+  let header_length = if jp2.jp2c_xlbox { 16 } else { 8 };
+  if opj_stream_skip(stream, header_length, p_manager) != header_length {
     return 0i32;
   }
   1i32
@@ -1880,8 +2465,8 @@ fn opj_jp2_read_jp2h(jp2: &mut opj_jp2, mut buf: &[u8], p_manager: &mut opj_even
       event_msg!(p_manager, EVT_ERROR, "Box length is inconsistent.\n",);
       return 0;
     }
-    let content_length = header.content_length() as usize;
-    if buf.len() < content_length {
+    let content_length = header.content_length();
+    if (buf.len() as u64) < content_length {
       event_msg!(
         p_manager,
         EVT_ERROR,
@@ -1889,7 +2474,7 @@ fn opj_jp2_read_jp2h(jp2: &mut opj_jp2, mut buf: &[u8], p_manager: &mut opj_even
       );
       return 0;
     }
-    let (content, rest) = buf.split_at(content_length);
+    let (content, rest) = buf.split_at(content_length as usize);
     buf = rest;
     if header.ty.is_image_header() {
       if header.read_content(jp2, content, p_manager).is_err() {
@@ -1910,6 +2495,13 @@ fn opj_jp2_read_jp2h(jp2: &mut opj_jp2, mut buf: &[u8], p_manager: &mut opj_even
     );
     return 0;
   }
+  if jp2.bpc == 255 && jp2.has_bpcc == 0 {
+    event_msg!(
+      p_manager,
+      EVT_WARNING,
+      "JP2 IHDR box indicates components of different bit depths, but there is no BPCC box\n",
+    );
+  }
   jp2.jp2_state |= JP2_STATE_HEADER;
   jp2.has_jp2h = 1 as OPJ_BYTE;
   1
@@ -1959,20 +2551,15 @@ pub(crate) fn opj_jp2_read_header(
     }
   };
   if let Some(image) = image {
-    /* Set Image Color Space */
-    if jp2.enumcs == 16u32 {
-      image.color_space = OPJ_CLRSPC_SRGB
-    } else if jp2.enumcs == 17u32 {
-      image.color_space = OPJ_CLRSPC_GRAY
-    } else if jp2.enumcs == 18u32 {
-      image.color_space = OPJ_CLRSPC_SYCC
-    } else if jp2.enumcs == 24u32 {
-      image.color_space = OPJ_CLRSPC_EYCC
-    } else if jp2.enumcs == 12u32 {
-      image.color_space = OPJ_CLRSPC_CMYK
+    opj_jp2_check_bit_depths(jp2, image, p_manager);
+
+    /* Set Image Color Space, unless the file says its colour space is
+     * unknown (UnkC), in which case the colr box may be inaccurate */
+    image.color_space = if jp2.UnkC != 0 {
+      OPJ_CLRSPC_UNKNOWN
     } else {
-      image.color_space = OPJ_CLRSPC_UNKNOWN
-    }
+      Jp2ColorSpec::Enumerated(jp2.enumcs).color_space()
+    };
 
     if let Some(icc_profile) = &jp2.color.icc_profile {
       image.copy_icc_profile(icc_profile);
@@ -2019,7 +2606,21 @@ fn opj_jp2_setup_header_writing(
 ) -> OPJ_BOOL {
   list.add(opj_jp2_write_jp);
   list.add(opj_jp2_write_ftyp);
+  if jp2.reader_requirements.is_some() {
+    list.add(opj_jp2_write_rreq);
+  }
   list.add(opj_jp2_write_jp2h);
+  if jp2.ipr.is_some() {
+    list.add(opj_jp2_write_jp2i);
+  }
+  if jp2
+    .boxes
+    .iter()
+    .chain(&jp2.uuid_boxes)
+    .any(|b| !b.after_codestream)
+  {
+    list.add(opj_jp2_write_boxes_before_jp2c);
+  }
   if jp2.jpip_on != 0 {
     list.add(opj_jpip_skip_iptr);
   }
@@ -2071,6 +2672,14 @@ pub(crate) fn opj_jp2_decode_tile(
   opj_j2k_decode_tile(&mut p_jp2.j2k, p_tile_index, p_data, p_stream, p_manager)
 }
 
+pub(crate) fn opj_jp2_decoded_components(jp2: &opj_jp2) -> Option<Vec<OPJ_UINT32>> {
+  opj_j2k_decoded_components(&jp2.j2k)
+}
+
+pub(crate) fn opj_jp2_has_decode_area(jp2: &opj_jp2) -> bool {
+  opj_j2k_has_decode_area(&jp2.j2k)
+}
+
 pub(crate) fn opj_jp2_set_decoded_components(
   p_jp2: &mut opj_jp2,
   components: &[u32],
@@ -2123,6 +2732,19 @@ pub(crate) fn opj_jp2_get_tile(
   opj_jp2_apply_color_postprocessing(p_jp2, p_image, p_manager)
 }
 
+pub(crate) fn opj_jp2_decode_tile_image(
+  p_jp2: &mut opj_jp2,
+  p_stream: &mut Stream,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Box<opj_image>> {
+  let mut image = opj_j2k_create_tile_image(&p_jp2.j2k, tile_index, p_manager)?;
+  if opj_jp2_get_tile(p_jp2, p_stream, &mut image, p_manager, tile_index) == 0 {
+    return None;
+  }
+  Some(image)
+}
+
 /// JP2 encoder interface
 pub(crate) fn opj_jp2_create(mut p_is_decoder: OPJ_BOOL) -> Option<opj_jp2> {
   /* create the J2K codec */
@@ -2147,6 +2769,7 @@ pub(crate) fn opj_jp2_create(mut p_is_decoder: OPJ_BOOL) -> Option<opj_jp2> {
     cl: Vec::new(),
     comps: Vec::new(),
     j2k_codestream_offset: 0,
+    jp2c_xlbox: false,
     jpip_iptr_offset: 0,
     jpip_on: 0,
     jp2_state: 0,
@@ -2154,6 +2777,14 @@ pub(crate) fn opj_jp2_create(mut p_is_decoder: OPJ_BOOL) -> Option<opj_jp2> {
     ignore_pclr_cmap_cdef: 0,
     has_jp2h: 0,
     has_ihdr: 0,
+    has_bpcc: 0,
+    capture_boxes: false,
+    boxes: Vec::new(),
+    uuid_boxes: Vec::new(),
+    ipr: None,
+    geo_metadata: None,
+    reader_requirements: None,
+    associations: Vec::new(),
     /* Color structure */
     color: opj_jp2_color {
       icc_profile: None,
@@ -2180,6 +2811,23 @@ pub(crate) fn jp2_get_cstr_info(mut p_jp2: &mut opj_jp2) -> *mut opj_codestream_
   j2k_get_cstr_info(&mut p_jp2.j2k)
 }
 
+pub(crate) fn opj_jp2_set_component_reduce(
+  p_jp2: &mut opj_jp2,
+  factors: &[(OPJ_UINT32, OPJ_UINT32)],
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_set_component_reduce(&mut p_jp2.j2k, factors, p_manager)
+}
+
+pub(crate) fn opj_jp2_set_layer_range(
+  p_jp2: &mut opj_jp2,
+  start: OPJ_UINT32,
+  end: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_set_layer_range(&mut p_jp2.j2k, start, end, p_manager)
+}
+
 pub(crate) fn opj_jp2_set_decoded_resolution_factor(
   p_jp2: &mut opj_jp2,
   res_factor: OPJ_UINT32,
@@ -2193,5 +2841,262 @@ pub(crate) fn opj_jp2_encoder_set_extra_options(
   options: &[&str],
   p_manager: &mut opj_event_mgr,
 ) -> bool {
-  opj_j2k_encoder_set_extra_options(&mut p_jp2.j2k, options, p_manager)
+  let mut j2k_options = Vec::with_capacity(options.len());
+  for option in options {
+    if option.starts_with("JP2C_XLBOX=") {
+      if *option == "JP2C_XLBOX=YES" {
+        p_jp2.jp2c_xlbox = true;
+      } else if *option == "JP2C_XLBOX=NO" {
+        p_jp2.jp2c_xlbox = false;
+      } else {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Invalid value for option: %s.\n",
+          *option,
+        );
+        return false;
+      }
+    } else {
+      j2k_options.push(*option);
+    }
+  }
+  opj_j2k_encoder_set_extra_options(&mut p_jp2.j2k, &j2k_options, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_add_comment(
+  p_jp2: &mut opj_jp2,
+  comment: &str,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_add_comment(&mut p_jp2.j2k, comment, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_writer_signature(
+  p_jp2: &mut opj_jp2,
+  signature: Option<&str>,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_writer_signature(&mut p_jp2.j2k, signature, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_detach_image_data(p_jp2: &mut opj_jp2) {
+  opj_j2k_encoder_detach_image_data(&mut p_jp2.j2k)
+}
+
+pub(crate) fn opj_jp2_encoder_set_capability(
+  p_jp2: &mut opj_jp2,
+  part: OPJ_UINT32,
+  ccap: u16,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_capability(&mut p_jp2.j2k, part, ccap, p_manager)
+}
+
+pub(crate) fn opj_jp2_get_progression(
+  jp2: &opj_jp2,
+  tile_index: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> Option<Progression> {
+  opj_j2k_get_progression(&jp2.j2k, tile_index, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_progression(
+  p_jp2: &mut opj_jp2,
+  progression: Progression,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_progression(&mut p_jp2.j2k, progression, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_add_poc(
+  p_jp2: &mut opj_jp2,
+  poc: &PocSpec,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_add_poc(&mut p_jp2.j2k, poc, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_embed_md5(p_jp2: &mut opj_jp2, embed: bool) {
+  opj_j2k_encoder_embed_md5(&mut p_jp2.j2k, embed)
+}
+
+pub(crate) fn opj_jp2_encoder_set_codestream_layout(p_jp2: &mut opj_jp2, layout: CodestreamLayout) {
+  opj_j2k_encoder_set_codestream_layout(&mut p_jp2.j2k, layout)
+}
+
+pub(crate) fn opj_jp2_verify_embedded_md5(p_jp2: &opj_jp2, p_image: &opj_image) -> Option<bool> {
+  opj_j2k_verify_embedded_md5(&p_jp2.j2k, p_image)
+}
+
+pub(crate) fn opj_jp2_get_capabilities(p_jp2: &opj_jp2) -> Capabilities {
+  opj_j2k_get_capabilities(&p_jp2.j2k)
+}
+
+pub(crate) fn opj_jp2_get_required_features(p_jp2: &opj_jp2) -> FeatureSet {
+  opj_j2k_get_required_features(&p_jp2.j2k)
+}
+
+pub(crate) fn opj_jp2_get_comments(p_jp2: &opj_jp2) -> Vec<Comment> {
+  opj_j2k_get_comments(&p_jp2.j2k)
+}
+
+pub(crate) fn opj_jp2_encoder_set_resolutions(
+  p_jp2: &mut opj_jp2,
+  numresolutions: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_resolutions(&mut p_jp2.j2k, numresolutions, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_quantization(
+  p_jp2: &mut opj_jp2,
+  style: &QuantStyle,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_quantization(&mut p_jp2.j2k, style, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_near_lossless(
+  p_jp2: &mut opj_jp2,
+  max_error: OPJ_UINT32,
+  step_shift: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_near_lossless(&mut p_jp2.j2k, max_error, step_shift, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_guard_bits(
+  p_jp2: &mut opj_jp2,
+  numgbits: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_guard_bits(&mut p_jp2.j2k, numgbits, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_mode_switches(
+  p_jp2: &mut opj_jp2,
+  modes: ModeSwitches,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_mode_switches(&mut p_jp2.j2k, modes, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_coding_style(
+  p_jp2: &mut opj_jp2,
+  style: &CodingStyle,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_coding_style(&mut p_jp2.j2k, style, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_get_coding_style(p_jp2: &opj_jp2) -> Option<CodingStyle> {
+  opj_j2k_encoder_get_coding_style(&p_jp2.j2k)
+}
+
+pub(crate) fn opj_jp2_encoder_set_num_layers(
+  p_jp2: &mut opj_jp2,
+  numlayers: OPJ_UINT32,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_num_layers(&mut p_jp2.j2k, numlayers, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_layer_rates(
+  p_jp2: &mut opj_jp2,
+  rates: &[f32],
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_layer_rates(&mut p_jp2.j2k, rates, p_manager)
+}
+
+pub(crate) fn opj_jp2_encoder_set_layer_psnr(
+  p_jp2: &mut opj_jp2,
+  psnr: &[f32],
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  opj_j2k_encoder_set_layer_psnr(&mut p_jp2.j2k, psnr, p_manager)
+}
+
+pub(crate) fn opj_jp2_get_color_spec(p_jp2: &opj_jp2) -> Jp2ColorSpec {
+  if p_jp2.color.jp2_has_colr == 0 {
+    Jp2ColorSpec::Unspecified
+  } else if p_jp2.meth == 1 {
+    Jp2ColorSpec::Enumerated(p_jp2.enumcs)
+  } else {
+    Jp2ColorSpec::IccProfile
+  }
+}
+
+pub(crate) fn opj_jp2_get_ipr(p_jp2: &opj_jp2) -> Option<&[u8]> {
+  p_jp2.ipr.as_deref()
+}
+
+pub(crate) fn opj_jp2_get_geo_metadata(p_jp2: &opj_jp2) -> Option<&[u8]> {
+  p_jp2.geo_metadata.as_deref()
+}
+
+pub(crate) fn opj_jp2_get_associations(p_jp2: &opj_jp2) -> Vec<Association> {
+  p_jp2.associations.clone()
+}
+
+pub(crate) fn opj_jp2_get_reader_requirements(p_jp2: &opj_jp2) -> Option<ReaderRequirements> {
+  p_jp2.reader_requirements.clone()
+}
+
+pub(crate) fn opj_jp2_encoder_set_brands(
+  p_jp2: &mut opj_jp2,
+  brand: u32,
+  cl: Vec<u32>,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  if !cl.contains(&brand) {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "The brand of the file type box must be in its compatibility list\n",
+    );
+    return false;
+  }
+  if cl.len() > (u32::MAX as usize - 16) / 4 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "Too many brands in the file type box\n",
+    );
+    return false;
+  }
+  p_jp2.brand = brand;
+  p_jp2.cl = cl;
+  true
+}
+
+pub(crate) fn opj_jp2_encoder_set_colorspace_unknown(p_jp2: &mut opj_jp2, unknown: bool) {
+  p_jp2.UnkC = unknown as OPJ_UINT32;
+}
+
+pub(crate) fn opj_jp2_encoder_set_ipr(p_jp2: &mut opj_jp2, ipr: Option<Vec<u8>>) {
+  p_jp2.IPR = ipr.is_some() as OPJ_UINT32;
+  p_jp2.ipr = ipr;
+}
+
+pub(crate) fn opj_jp2_encoder_set_reader_requirements(
+  p_jp2: &mut opj_jp2,
+  rreq: Option<ReaderRequirements>,
+  p_manager: &mut opj_event_mgr,
+) -> bool {
+  if let Some(rreq) = &rreq {
+    if rreq.standard_features.len() > u16::MAX as usize
+      || rreq.vendor_features.len() > u16::MAX as usize
+    {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Too many features in the reader requirements\n",
+      );
+      return false;
+    }
+  }
+  p_jp2.reader_requirements = rreq;
+  true
 }
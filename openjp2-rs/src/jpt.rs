@@ -0,0 +1,267 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * Copyright (c) 2002-2014, Universite catholique de Louvain (UCL), Belgium
+ * Copyright (c) 2002-2014, Professor Benoit Macq
+ * Copyright (c) 2002-2003, Yannick Verschueren
+ * Copyright (c) 2005, Herve Drolon, FreeImage Team
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! JPT-stream (JPEG 2000 Part 9, JPIP) support.
+//!
+//! A JPT-stream is a sequence of JPIP messages carrying byte ranges of
+//! main header and tile data-bins. Only reconstruction of a plain
+//! codestream is supported: the main header data-bin must be complete and
+//! every tile data-bin must be received from its start without gaps. Only
+//! the trailing tile may be truncated. Precinct data-bins (JPP-streams),
+//! multiple codestreams and metadata are not supported.
+
+use std::collections::BTreeMap;
+
+use super::event::*;
+
+/// Data-bin class of precinct data-bins.
+const JPIP_CLASS_PRECINCT: u32 = 0;
+/// Data-bin class of extended precinct data-bins.
+const JPIP_CLASS_EXT_PRECINCT: u32 = 1;
+/// Data-bin class of tile header data-bins.
+const JPIP_CLASS_TILE_HEADER: u32 = 2;
+/// Data-bin class of tile data-bins.
+const JPIP_CLASS_TILE: u32 = 4;
+/// Data-bin class of extended tile data-bins.
+const JPIP_CLASS_EXT_TILE: u32 = 5;
+/// Data-bin class of the main header data-bin.
+const JPIP_CLASS_MAIN_HEADER: u32 = 6;
+/// Data-bin class of metadata-bins.
+const JPIP_CLASS_METADATA: u32 = 8;
+
+/// Header of a JPIP message.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct opj_jpt_msg_header {
+  /// In-class identifier of the data-bin.
+  pub id: u64,
+  /// Is this message the last byte range of its data-bin?
+  pub last_byte: bool,
+  /// Data-bin class.
+  pub class_id: u32,
+  /// Codestream index.
+  pub csn_id: u32,
+  /// Offset of the message body in the data-bin.
+  pub msg_offset: u64,
+  /// Length of the message body.
+  pub msg_length: u64,
+  /// Auxiliary value (number of quality layers for extended classes).
+  pub layer_nb: u64,
+}
+
+/// Read a variable-length byte-aligned segment (VBAS).
+///
+/// `first_bits` is the number of value bits in the first byte.
+fn opj_jpt_read_vbas(data: &[u8], pos: &mut usize, first_bits: u32) -> Option<u64> {
+  let mut byte = *data.get(*pos)?;
+  *pos += 1;
+  let mut value = (byte & ((1u8 << first_bits) - 1)) as u64;
+  while byte & 0x80 != 0 {
+    byte = *data.get(*pos)?;
+    *pos += 1;
+    if value >> 57 != 0 {
+      /* Value doesn't fit in 64 bits */
+      return None;
+    }
+    value = (value << 7) | (byte & 0x7f) as u64;
+  }
+  Some(value)
+}
+
+/// Read a JPIP message header.
+///
+/// The class and codestream index of the previous message are used when
+/// they are omitted from the header.
+pub(crate) fn opj_jpt_read_msg_header(
+  data: &[u8],
+  pos: &mut usize,
+  previous: &opj_jpt_msg_header,
+) -> Option<opj_jpt_msg_header> {
+  let mut header = opj_jpt_msg_header {
+    class_id: previous.class_id,
+    csn_id: previous.csn_id,
+    ..Default::default()
+  };
+  let first = *data.get(*pos)?;
+  /* Bin-ID: [ext:1][bin-ID indicator:2][completeness:1][in-class id:4] */
+  let indicator = (first >> 5) & 0x3;
+  header.last_byte = first & 0x10 != 0;
+  header.id = opj_jpt_read_vbas(data, pos, 4)?;
+  match indicator {
+    1 => (),
+    2 => {
+      header.class_id = opj_jpt_read_vbas(data, pos, 7)? as u32;
+    }
+    3 => {
+      header.class_id = opj_jpt_read_vbas(data, pos, 7)? as u32;
+      header.csn_id = opj_jpt_read_vbas(data, pos, 7)? as u32;
+    }
+    _ => return None,
+  }
+  header.msg_offset = opj_jpt_read_vbas(data, pos, 7)?;
+  header.msg_length = opj_jpt_read_vbas(data, pos, 7)?;
+  /* Extended classes have an auxiliary VBAS */
+  if header.class_id & 1 != 0 {
+    header.layer_nb = opj_jpt_read_vbas(data, pos, 7)?;
+  }
+  Some(header)
+}
+
+/// Byte ranges received for a data-bin.
+#[derive(Default)]
+struct DataBin {
+  ranges: BTreeMap<u64, Vec<u8>>,
+  /// Total length, known once the last byte range was received.
+  length: Option<u64>,
+}
+
+impl DataBin {
+  /// Return the contiguous data from the start of the data-bin and whether
+  /// it is the whole data-bin.
+  fn contiguous(&self) -> (Vec<u8>, bool) {
+    let mut data = Vec::new();
+    for (offset, range) in &self.ranges {
+      let end = *offset + range.len() as u64;
+      let len = data.len() as u64;
+      if *offset > len {
+        break;
+      }
+      if end > len {
+        data.extend_from_slice(&range[(len - *offset) as usize..]);
+      }
+    }
+    let complete = self.length.is_some_and(|l| data.len() as u64 >= l);
+    if let Some(length) = self.length {
+      data.truncate(length as usize);
+    }
+    (data, complete)
+  }
+}
+
+/// Reconstruct a plain codestream from a JPT-stream.
+pub(crate) fn opj_jpt_to_codestream(data: &[u8], p_manager: &mut opj_event_mgr) -> Option<Vec<u8>> {
+  let mut main_header = DataBin::default();
+  let mut tiles = BTreeMap::<u64, DataBin>::new();
+  let mut header = opj_jpt_msg_header::default();
+  let mut pos = 0;
+  while pos < data.len() {
+    if data[pos] == 0 {
+      /* EOR message: the end of the response */
+      break;
+    }
+    header = match opj_jpt_read_msg_header(data, &mut pos, &header) {
+      Some(header) => header,
+      None => {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Invalid JPT-stream message header at offset %d\n",
+          pos,
+        );
+        return None;
+      }
+    };
+    let body_end = match pos.checked_add(header.msg_length as usize) {
+      Some(end) if end <= data.len() => end,
+      _ => {
+        event_msg!(p_manager, EVT_ERROR, "Truncated JPT-stream message\n");
+        return None;
+      }
+    };
+    let body = &data[pos..body_end];
+    pos = body_end;
+    if header.csn_id != 0 {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Unsupported JPT-stream: multiple codestreams\n"
+      );
+      return None;
+    }
+    let bin = match header.class_id {
+      JPIP_CLASS_MAIN_HEADER => &mut main_header,
+      JPIP_CLASS_TILE | JPIP_CLASS_EXT_TILE => tiles.entry(header.id).or_default(),
+      JPIP_CLASS_METADATA => continue,
+      JPIP_CLASS_PRECINCT | JPIP_CLASS_EXT_PRECINCT | JPIP_CLASS_TILE_HEADER => {
+        event_msg!(
+          p_manager,
+          EVT_ERROR,
+          "Unsupported JPIP stream: precinct data-bins (JPP-stream)\n"
+        );
+        return None;
+      }
+      _ => {
+        event_msg!(
+          p_manager,
+          EVT_WARNING,
+          "Skipping JPT-stream message of unknown class %d\n",
+          header.class_id,
+        );
+        continue;
+      }
+    };
+    if header.last_byte {
+      bin.length = Some(header.msg_offset + header.msg_length);
+    }
+    if !body.is_empty() {
+      bin.ranges.insert(header.msg_offset, body.to_vec());
+    }
+  }
+
+  let (mut codestream, complete) = main_header.contiguous();
+  if !complete || codestream.len() < 2 {
+    event_msg!(
+      p_manager,
+      EVT_ERROR,
+      "JPT-stream doesn't contain the complete main header\n"
+    );
+    return None;
+  }
+  for (tileno, tile) in &tiles {
+    let (data, complete) = tile.contiguous();
+    codestream.extend_from_slice(&data);
+    if !complete {
+      /* Following tiles can't be located in a truncated codestream */
+      event_msg!(
+        p_manager,
+        EVT_WARNING,
+        "JPT-stream tile %d is incomplete, ignoring the following tiles\n",
+        *tileno,
+      );
+      break;
+    }
+  }
+  /* EOC */
+  codestream.extend_from_slice(&[0xff, 0xd9]);
+  Some(codestream)
+}
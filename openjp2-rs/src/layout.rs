@@ -0,0 +1,206 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! Layout of the pixels written by `opj_decode_into` into a caller provided
+//! buffer.
+
+/// How the channels of the output pixels are arranged.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Interleaving {
+  /// The channels of a pixel are next to each other (`RGBRGB...`).
+  #[default]
+  Interleaved,
+  /// Each channel is written as its own plane (`RR...GG...BB...`).
+  Planar,
+}
+
+/// Size of the output samples. Samples are scaled from the component
+/// precision to the full range of the output depth.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SampleDepth {
+  /// 8-bit samples.
+  #[default]
+  U8,
+  /// 16-bit samples in native byte order.
+  U16,
+}
+
+/// Rounding of the samples scaled to a lower precision.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum RoundingMode {
+  /// Round down, like `opj_decompress`.
+  Truncate,
+  /// Round to the nearest value, halves up.
+  #[default]
+  Nearest,
+  /// Round up with the probability of the fraction, a dithering keeping
+  /// the mean of the samples that reduces the banding of smooth areas. The
+  /// noise depends on the position of the sample only, so the output is
+  /// reproducible.
+  Stochastic,
+}
+
+impl RoundingMode {
+  /// `value * num / den` rounded, `value` being positive. `position` seeds
+  /// the noise of `Stochastic`.
+  pub(crate) fn scale(self, value: i64, num: i64, den: i64, position: u64) -> i64 {
+    // 128 bits: the product overflows 64 bits for 31-bit samples.
+    let (value, num, den) = (value as i128, num as i128, den as i128);
+    let product = value * num;
+    let rounded = match self {
+      RoundingMode::Truncate => product / den,
+      RoundingMode::Nearest => (product + den / 2) / den,
+      RoundingMode::Stochastic => {
+        let threshold = (noise(position) as i128 * den) >> 32;
+        product / den + (threshold < product % den) as i128
+      }
+    };
+    rounded as i64
+  }
+}
+
+/// Uniform 32-bit noise of `position`, the SplitMix64 finalizer.
+fn noise(position: u64) -> u32 {
+  let mut z = position.wrapping_add(0x9E37_79B9_7F4A_7C15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+  ((z ^ (z >> 31)) >> 32) as u32
+}
+
+/// Layout of the pixels decoded into a caller provided buffer.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PixelLayout {
+  pub interleaving: Interleaving,
+  /// Component index of each output channel. Empty means all components
+  /// in order.
+  pub channels: Vec<u32>,
+  pub depth: SampleDepth,
+  /// Number of bytes from the start of a row to the start of the next one.
+  /// `0` means the rows are tightly packed.
+  pub row_stride: usize,
+  /// Rounding of the samples deeper than the output depth.
+  pub rounding: RoundingMode,
+}
+
+impl PixelLayout {
+  pub(crate) fn channels(&self, numcomps: u32) -> Vec<u32> {
+    if self.channels.is_empty() {
+      (0..numcomps).collect()
+    } else {
+      self.channels.clone()
+    }
+  }
+
+  fn sample_bytes(&self) -> usize {
+    match self.depth {
+      SampleDepth::U8 => 1,
+      SampleDepth::U16 => 2,
+    }
+  }
+
+  fn row_bytes(&self, width: usize, nb_channels: usize) -> Option<usize> {
+    let samples = match self.interleaving {
+      Interleaving::Interleaved => width.checked_mul(nb_channels)?,
+      Interleaving::Planar => width,
+    };
+    samples.checked_mul(self.sample_bytes())
+  }
+
+  /// Row stride in bytes, `None` if `row_stride` is too small for a row.
+  pub(crate) fn stride(&self, width: usize, nb_channels: usize) -> Option<usize> {
+    let row_bytes = self.row_bytes(width, nb_channels)?;
+    match self.row_stride {
+      0 => Some(row_bytes),
+      stride if stride >= row_bytes => Some(stride),
+      _ => None,
+    }
+  }
+
+  /// Number of bytes needed to hold the whole image.
+  pub(crate) fn buffer_size(
+    &self,
+    width: usize,
+    height: usize,
+    nb_channels: usize,
+  ) -> Option<usize> {
+    let plane = self.stride(width, nb_channels)?.checked_mul(height)?;
+    match self.interleaving {
+      Interleaving::Interleaved => Some(plane),
+      Interleaving::Planar => plane.checked_mul(nb_channels),
+    }
+  }
+
+  /// Byte offset of a sample in the output buffer.
+  pub(crate) fn offset(
+    &self,
+    x: usize,
+    y: usize,
+    channel: usize,
+    stride: usize,
+    height: usize,
+    nb_channels: usize,
+  ) -> usize {
+    let bytes = self.sample_bytes();
+    match self.interleaving {
+      Interleaving::Interleaved => y * stride + (x * nb_channels + channel) * bytes,
+      Interleaving::Planar => (channel * height + y) * stride + x * bytes,
+    }
+  }
+
+  /// Scale a decoded sample to the output depth and write it at `offset`.
+  /// The index of the pixel seeds the noise of `RoundingMode::Stochastic`,
+  /// the same for all the channels of a pixel so grey stays grey.
+  pub(crate) fn write_sample(
+    &self,
+    out: &mut [u8],
+    offset: usize,
+    pixel: usize,
+    value: i32,
+    prec: u32,
+    sgnd: bool,
+  ) {
+    let max_in = (1i64 << prec) - 1;
+    let mut value = value as i64;
+    if sgnd {
+      value += 1i64 << (prec - 1);
+    }
+    let value = value.clamp(0, max_in);
+    let position = pixel as u64;
+    match self.depth {
+      SampleDepth::U8 => {
+        out[offset] = self.rounding.scale(value, 255, max_in, position) as u8;
+      }
+      SampleDepth::U16 => {
+        let value = self.rounding.scale(value, 65535, max_in, position) as u16;
+        out[offset..offset + 2].copy_from_slice(&value.to_ne_bytes());
+      }
+    }
+  }
+}
@@ -28,8 +28,10 @@ mod event;
 mod fprintf;
 
 // Public OpenJpeg interface.
+pub mod convert;
 pub mod image;
 pub mod openjpeg;
+pub mod safe;
 pub mod stream;
 
 mod bio;
@@ -41,11 +43,15 @@ mod ht_dec;
 mod invert;
 mod j2k;
 mod jp2;
+mod jpt;
+mod layout;
 mod malloc;
 mod math;
 mod mct;
+mod md5;
 mod mqc;
 mod pi;
+mod sha256;
 mod sparse_array;
 mod t1;
 mod t1_ht_luts;
@@ -53,3 +59,4 @@ mod t1_luts;
 mod t2;
 mod tcd;
 mod tgt;
+mod validate;
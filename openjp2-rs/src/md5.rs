@@ -0,0 +1,192 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * Copyright (c) 2002-2014, Universite catholique de Louvain (UCL), Belgium
+ * Copyright (c) 2002-2014, Professor Benoit Macq
+ * Copyright (c) 2002-2003, Yannick Verschueren
+ * Copyright (c) 2005, Herve Drolon, FreeImage Team
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! MD5 message digest (RFC 1321).
+//!
+//! Only used to embed a digest of the image samples in a COM marker, see
+//! `opj_encoder_embed_decoded_md5`. It isn't meant for security purposes.
+
+use core::convert::TryInto;
+
+use super::image::opj_image;
+
+/// Per-round shift amounts.
+const S: [u32; 64] = [
+  7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14,
+  20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15, 21, 6,
+  10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// `floor(abs(sin(i + 1)) * 2^32)`
+const K: [u32; 64] = [
+  0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+  0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+  0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+  0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+  0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+  0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+  0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+  0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+pub(crate) struct Md5 {
+  state: [u32; 4],
+  buffer: [u8; 64],
+  buffer_len: usize,
+  /// Length of the message, in bytes.
+  length: u64,
+}
+
+impl Md5 {
+  pub fn new() -> Self {
+    Self {
+      state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+      buffer: [0; 64],
+      buffer_len: 0,
+      length: 0,
+    }
+  }
+
+  pub fn update(&mut self, mut data: &[u8]) {
+    self.length = self.length.wrapping_add(data.len() as u64);
+    if self.buffer_len > 0 {
+      let n = (64 - self.buffer_len).min(data.len());
+      self.buffer[self.buffer_len..self.buffer_len + n].copy_from_slice(&data[..n]);
+      self.buffer_len += n;
+      data = &data[n..];
+      if self.buffer_len < 64 {
+        return;
+      }
+      let block = self.buffer;
+      self.process(&block);
+      self.buffer_len = 0;
+    }
+    let mut blocks = data.chunks_exact(64);
+    for block in &mut blocks {
+      self.process(block.try_into().unwrap());
+    }
+    let rest = blocks.remainder();
+    self.buffer[..rest.len()].copy_from_slice(rest);
+    self.buffer_len = rest.len();
+  }
+
+  pub fn finalize(mut self) -> [u8; 16] {
+    let bit_length = self.length.wrapping_mul(8);
+    /* Padding: a 1 bit, zeros up to 56 bytes modulo 64, then the length */
+    self.update(&[0x80]);
+    while self.buffer_len != 56 {
+      self.update(&[0]);
+    }
+    self.update(&bit_length.to_le_bytes());
+    let mut digest = [0u8; 16];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+      bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+  }
+
+  fn process(&mut self, block: &[u8; 64]) {
+    let mut m = [0u32; 16];
+    for (word, bytes) in m.iter_mut().zip(block.chunks_exact(4)) {
+      *word = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    let [mut a, mut b, mut c, mut d] = self.state;
+    for i in 0..64 {
+      let (f, g) = match i / 16 {
+        0 => ((b & c) | (!b & d), i),
+        1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+        2 => (b ^ c ^ d, (3 * i + 5) % 16),
+        _ => (c ^ (b | !d), (7 * i) % 16),
+      };
+      let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+      a = d;
+      d = c;
+      c = b;
+      b = b.wrapping_add(f.rotate_left(S[i]));
+    }
+    for (state, value) in self.state.iter_mut().zip([a, b, c, d]) {
+      *state = state.wrapping_add(value);
+    }
+  }
+}
+
+/// MD5 of the samples of `image`: the samples of each component in turn,
+/// row by row, as 32-bit little-endian integers.
+///
+/// Returns `None` if a component has no samples.
+pub(crate) fn opj_image_md5(image: &opj_image) -> Option<[u8; 16]> {
+  let mut md5 = Md5::new();
+  for comp in image.comps()? {
+    for sample in comp.data()? {
+      md5.update(&sample.to_le_bytes());
+    }
+  }
+  Some(md5.finalize())
+}
+
+/// Lowercase hexadecimal representation of a digest.
+pub(crate) fn md5_to_hex(digest: &[u8; 16]) -> String {
+  digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn md5_hex(data: &[u8]) -> String {
+    let mut md5 = Md5::new();
+    md5.update(data);
+    md5_to_hex(&md5.finalize())
+  }
+
+  #[test]
+  fn test_rfc1321_vectors() {
+    assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(
+      md5_hex(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"),
+      "57edf4a22be3c955ac49da2e2107b67a"
+    );
+  }
+
+  #[test]
+  fn test_split_updates() {
+    let data: Vec<u8> = (0..200u8).collect();
+    let mut md5 = Md5::new();
+    for chunk in data.chunks(7) {
+      md5.update(chunk);
+    }
+    assert_eq!(md5_to_hex(&md5.finalize()), md5_hex(&data));
+  }
+}
@@ -32,11 +32,21 @@
  */
 
 pub use super::c_api_types::*;
+pub use super::event::OPJ_LOG_TARGET;
 use super::j2k::*;
+pub use super::layout::{Interleaving, PixelLayout, RoundingMode, SampleDepth};
 pub(crate) use super::types::*;
+pub use super::types::{
+  Association, Capabilities, CodestreamByteRanges, CodestreamLayout, CodingStyle, Comment,
+  DecodeLimits, DecodedRegion, ErrorResilienceFlags, FeatureSet, Jp2ColorSpec, ModeSwitches,
+  PacketCoord, PacketIndex, PacketPosition, PocSpec, PrecinctData, Progression, QuantStyle, RawBox,
+  ReaderRequirements, StructureError, TileByteRange, TileStatus, Wavelet, GEOJP2_UUID,
+};
 
 use super::codec::*;
-pub use super::image::{opj_image_create, opj_image_destroy, opj_image_tile_create};
+pub use super::image::{
+  opj_image_create, opj_image_destroy, opj_image_tile_create, UpsampleFilter,
+};
 use super::malloc::*;
 
 #[cfg(feature = "file-io")]
@@ -83,6 +93,24 @@ pub unsafe fn opj_set_error_handler(
   l_codec.set_error_handler(p_callback, p_user_data)
 }
 
+/// Send the codec's error, warning and info messages to the `log` crate.
+///
+/// Messages are logged with the `"openjp2"` target (`OPJ_LOG_TARGET`) and
+/// replace any handler set with `opj_set_error_handler`,
+/// `opj_set_warning_handler` or `opj_set_info_handler`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_use_log_crate(mut p_codec: *mut opj_codec_t) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.use_log_crate();
+  1i32
+}
+
 /* ---------------------------------------------------------------------- */
 pub const OPJ_VERSION: &str = "2.5.2";
 pub const OPJ_VERSION_C: *const u8 = b"2.5.2\x00" as *const u8;
@@ -92,6 +120,41 @@ pub fn opj_version() -> *const core::ffi::c_char {
   OPJ_VERSION_C as *const core::ffi::c_char
 }
 
+/// Check the structure of a JP2 file or of a J2K codestream, without
+/// decoding it.
+///
+/// Box and marker segment lengths must stay within `p_data`, the mandatory
+/// boxes (`jP  `, `ftyp`, `jp2h` with `ihdr` first, `jp2c`) or markers (SOC,
+/// SIZ, COD, QCD, SOT, SOD, EOC) must be present and in order. Packet data
+/// isn't checked, so a valid structure doesn't guarantee that decoding
+/// succeeds.
+pub fn opj_validate_structure(p_data: &[u8]) -> Result<(), StructureError> {
+  super::validate::opj_validate_structure(p_data)
+}
+
+/// SHA-256 of the codestream of a JP2 file or of a J2K codestream.
+///
+/// For a JP2 file only the contents of the codestream box are hashed, the
+/// other boxes (metadata, colour specification...) are skipped, so files
+/// that only differ by their metadata have the same digest. Fails if no
+/// codestream can be located.
+pub fn opj_codestream_digest(p_data: &[u8]) -> Result<[u8; 32], StructureError> {
+  let codestream = super::validate::opj_locate_codestream(p_data)?;
+  let mut sha256 = super::sha256::Sha256::new();
+  sha256.update(codestream);
+  Ok(sha256.finalize())
+}
+
+/// List the reasons why this build may fail to decode a JP2 file or a J2K
+/// codestream, without decoding it.
+///
+/// The main and tile-part headers are walked to report the markers and
+/// parameters that aren't supported, e.g. the JPWL markers, more than 16384
+/// components or the mixed HT code-block style. An empty list doesn't guarantee that decoding succeeds.
+pub fn opj_explain_unsupported(p_data: &[u8]) -> Vec<String> {
+  Codec::explain_unsupported(p_data)
+}
+
 /* ---------------------------------------------------------------------- */
 /* DECOMPRESSION FUNCTIONS*/
 #[no_mangle]
@@ -113,6 +176,15 @@ pub unsafe fn opj_set_default_decoder_parameters(mut parameters: *mut opj_dparam
   parameters.set_defaults();
 }
 
+/// Set the number of threads used by the decoder to decode the code-blocks
+/// of a tile, needs the `threads` feature.
+///
+/// The inverse DWT stays single threaded.  Message handlers may be called
+/// from the worker threads.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
 #[no_mangle]
 pub unsafe fn opj_codec_set_threads(
   mut p_codec: *mut opj_codec_t,
@@ -150,6 +222,471 @@ pub unsafe fn opj_decoder_set_strict_mode(
   l_codec.decoder_set_strict_mode(strict)
 }
 
+/// Recover a partial image from a truncated or corrupted codestream.
+///
+/// When enabled, `opj_decode` stops at the first tile that can't be read or
+/// decoded and succeeds with the tiles decoded so far, the rest of the image
+/// is zero. Use `opj_get_decoded_region` to know which tiles were decoded.
+/// With strict mode disabled (see `opj_decoder_set_strict_mode`), the
+/// resolutions and layers received for a truncated tile are also decoded.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_partial(mut p_codec: *mut opj_codec_t, partial: bool) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decoder_set_partial(partial) as OPJ_BOOL
+}
+
+/// Return the components as they are coded in the codestream, for analysis
+/// or debugging.
+///
+/// When enabled, `opj_decode` skips the multiple component transform, the
+/// DC level shift and the clipping of the samples to their precision: the
+/// samples are the integer output of the inverse wavelet transform, those
+/// of the 9-7 wavelet being rounded. The JP2 palette and channel
+/// definitions aren't applied either, nor the conversions enabled on the
+/// decoder, and the decoded image has `raw_components` set.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_raw_components(mut p_codec: *mut opj_codec_t, raw: bool) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decoder_set_raw_components(raw) as OPJ_BOOL
+}
+
+/// Enable or disable the correction of the number of tile-parts, enabled by
+/// default.
+///
+/// Some encoders write a `TNsot` (number of tile-parts of the tile) one too
+/// small, as if it were the index `TPsot` of the last tile-part, e.g. the
+/// `textGBR.jp2` file of the conformance data. When the tile-parts of the
+/// first tile seem complete, the decoder looks ahead for another tile-part
+/// of that tile. If there is one, the `TNsot` of every tile is taken as one
+/// more than written, with the warning "Non conformant codestream
+/// TPsot==TNsot". `TNsot` values of 0, for an unknown number of
+/// tile-parts, are kept.
+///
+/// Without the correction, `TNsot` is trusted: a tile is decoded as soon as
+/// its announced tile-parts are read, and a further tile-part of that tile
+/// is an error, like with the reference decoder of the standard. This also
+/// saves the look ahead, which seeks in the stream.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_tile_parts_correction(
+  mut p_codec: *mut opj_codec_t,
+  enable: bool,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decoder_set_tile_parts_correction(enable) as OPJ_BOOL
+}
+
+/// Check that codestreams claiming a digital cinema or IMF profile in their
+/// `Rsiz` follow it: component count and precision, image and tile sizes,
+/// progression order, code-block size and style, and wavelet transform.
+///
+/// The check is done by `opj_read_header` on the main header. Violations are
+/// reported as errors that fail the header reading in strict mode (see
+/// `opj_decoder_set_strict_mode`), as warnings otherwise.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_validate_profile(
+  mut p_codec: *mut opj_codec_t,
+  validate: bool,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decoder_set_validate_profile(validate) as OPJ_BOOL
+}
+
+/// Limit the number of components, resolutions, quality layers and tiles
+/// of the codestreams accepted by the decoder, to reject files crafted to
+/// exhaust resources, e.g. on a server.
+///
+/// Must be called before `opj_read_header`. A codestream exceeding a limit
+/// fails to decode with an error naming the limit: the components and tiles
+/// are checked when reading the SIZ marker, the layers and resolutions when
+/// reading the COD and COC markers, including those of the tile-part
+/// headers. The default `DecodeLimits` accept all valid codestreams.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_limits(
+  mut p_codec: *mut opj_codec_t,
+  limits: DecodeLimits,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decoder_set_limits(limits) as OPJ_BOOL
+}
+
+/// Enable checks of the code-block data to detect corruption, e.g. for
+/// codestreams received over lossy channels.
+///
+/// The segmentation symbols are only present in code-blocks coded with the
+/// SEGMARK mode switch. The predictable termination of code-blocks coded
+/// with ERTERM is always checked when decoding all the quality layers. With
+/// `ErrorResilienceFlags::STOP_ON_ERROR`, a failed check fails the decoding
+/// of the tile, see `opj_decoder_set_partial` to keep the tiles decoded
+/// before it.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_error_resilience(
+  mut p_codec: *mut opj_codec_t,
+  flags: ErrorResilienceFlags,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decoder_set_error_resilience(flags) as OPJ_BOOL
+}
+
+/// Upsample the subsampled components (e.g. the chroma of a 4:2:0 YCC
+/// image) of the images returned by `opj_decode`, `opj_get_decoded_tile`
+/// and `opj_decode_tile` with `filter`, see `opj_image::upsample_to_match`.
+///
+/// `UpsampleFilter::Bilinear` gives smoother colour edges than
+/// `UpsampleFilter::Nearest`. With `None`, the default, components keep
+/// their subsampling.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_chroma_upsampling(
+  mut p_codec: *mut opj_codec_t,
+  filter: Option<UpsampleFilter>,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_chroma_upsampling(filter) as OPJ_BOOL
+}
+
+/// Upsample all the components of the images returned by `opj_decode`,
+/// `opj_get_decoded_tile` and `opj_decode_tile` to the full resolution of
+/// the image, with `dx = dy = 1`, see `opj_image::upsample_to_full`.
+///
+/// This is the `-upsample` option of `opj_decompress`. Unlike
+/// `opj_decoder_set_chroma_upsampling` alone, components are upsampled even
+/// when none has the full resolution. The filter set by
+/// `opj_decoder_set_chroma_upsampling` is used, `UpsampleFilter::Nearest`
+/// by default. With a resolution reduction factor, the components have
+/// the reduced image size.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_upsample(mut p_codec: *mut opj_codec_t, upsample: bool) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_upsample(upsample) as OPJ_BOOL
+}
+
+/// Convert the decoded images to sRGB, like the `-force-rgb` option of
+/// `opj_decompress`.
+///
+/// sYCC, e-sYCC and CMYK images are converted, grey images have their
+/// component copied to the 3 RGB components. sRGB images are unchanged, as
+/// are images of an unspecified colour space with at least 3 components.
+/// Components after the colour channels are kept: grey or CMYK images with
+/// an opacity component become RGBA. Subsampled components are upsampled
+/// first, with the filter of `opj_decoder_set_chroma_upsampling` or nearest
+/// neighbour. JP2 palettes are always expanded by the decoder. An image that
+/// can't be converted, e.g. CIELab, is returned as decoded with a warning.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_force_rgb(
+  mut p_codec: *mut opj_codec_t,
+  force_rgb: bool,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_force_rgb(force_rgb) as OPJ_BOOL
+}
+
+/// Warn through the warning handler when a component of a decoded image
+/// has all its samples at the minimum, or all at the maximum value of its
+/// precision, e.g. an all-black or all-white image. This is a common
+/// symptom of a wrong precision or DC level shift in the codestream.
+///
+/// Disabled by default. The check runs after `opj_decode` and
+/// `opj_decode_tile`, and stops at the first sample of a component that
+/// isn't saturated.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_saturation_warning(
+  mut p_codec: *mut opj_codec_t,
+  enable: bool,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_saturation_warning(enable) as OPJ_BOOL
+}
+
+/// Override the DC level shift of component `compno`, or restore the shift
+/// of the codestream with `None`.
+///
+/// Must be called after `opj_read_header`. The shift is added to the
+/// reconstructed samples of every tile, which are then clamped to the range
+/// of the component: `[0, 2^prec - 1]` for unsigned components and
+/// `[-2^(prec-1), 2^(prec-1) - 1]` for signed ones. The codestream shift is
+/// `2^(prec-1)` for unsigned components and 0 for signed ones, unless a
+/// Part 2 MCC marker defines another one.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_dc_shift(
+  mut p_codec: *mut opj_codec_t,
+  compno: OPJ_UINT32,
+  shift: Option<OPJ_INT32>,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decoder_set_dc_shift(compno, shift) as OPJ_BOOL
+}
+
+/// Get the DC level shift of component `compno` defined by the main header
+/// of the codestream, ignoring `opj_decoder_set_dc_shift`.
+///
+/// Returns `None` before `opj_read_header` or for an invalid component.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_dc_shift(
+  mut p_codec: *mut opj_codec_t,
+  compno: OPJ_UINT32,
+) -> Option<OPJ_INT32> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.dc_shift(compno)
+}
+
+/// Keep a copy of the top-level boxes of a JP2 file, see `opj_get_boxes`.
+///
+/// Must be called before `opj_read_header`. The boxes following the
+/// codestream box are read by `opj_end_decompress`.
+///
+/// # Safety
+///
+/// `p_codec` must be a JP2 decompressor handle.
+pub unsafe fn opj_decoder_capture_boxes(mut p_codec: *mut opj_codec_t, capture: bool) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decoder_capture_boxes(capture) as OPJ_BOOL
+}
+
+/// Get the top-level boxes read by a JP2 decompressor, in file order,
+/// without the codestream box.
+///
+/// Boxes are only kept when enabled with `opj_decoder_capture_boxes`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_boxes(mut p_codec: *mut opj_codec_t) -> Vec<RawBox> {
+  if p_codec.is_null() {
+    return Vec::new();
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.boxes()
+}
+
+/// Get the contents of the intellectual property box (`jp2i`) read by a JP2
+/// decompressor, or `None` if the file doesn't have one.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_ipr(mut p_codec: *mut opj_codec_t) -> Option<Vec<u8>> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.ipr().map(|ipr| ipr.to_vec())
+}
+
+/// Get the GeoTIFF blob of the GeoJP2 `uuid` box read by a JP2
+/// decompressor, or `None` if the file doesn't have one.
+///
+/// The blob isn't parsed, see `opj_encoder_set_geo_metadata` to write it
+/// back.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_geo_metadata(mut p_codec: *mut opj_codec_t) -> Option<Vec<u8>> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.geo_metadata().map(|data| data.to_vec())
+}
+
+/// Get the tree of top-level association boxes (`asoc`) read by a JP2
+/// decompressor, e.g. the labels and metadata of the regions of a JPX file.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_associations(mut p_codec: *mut opj_codec_t) -> Vec<Association> {
+  if p_codec.is_null() {
+    return Vec::new();
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.associations()
+}
+
+/// Get the reader requirements box (`rreq`) read by a JP2 decompressor, or
+/// `None` if the file doesn't have a valid one.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_reader_requirements(
+  mut p_codec: *mut opj_codec_t,
+) -> Option<ReaderRequirements> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.reader_requirements()
+}
+
+/// Get the tiles decoded by the last call to `opj_decode`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_decoded_region(mut p_codec: *mut opj_codec_t) -> DecodedRegion {
+  if p_codec.is_null() {
+    return DecodedRegion::default();
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.decoded_region()
+}
+
+/// Get the number of tiles decoded by the last call to `opj_decode`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_decoded_tile_count(mut p_codec: *mut opj_codec_t) -> OPJ_UINT32 {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.get_decoded_tile_count()
+}
+
+/// Get the status of each tile read by the last call to `opj_decode`: whether
+/// it was decoded, the size of its compressed data and why it failed.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_tile_status(mut p_codec: *mut opj_codec_t) -> Vec<TileStatus> {
+  if p_codec.is_null() {
+    return Vec::new();
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.tile_status()
+}
+
+/// Get the index of the tile containing the point `(x, y)` of the reference
+/// grid, e.g. to find the tiles under a region to decode. Returns `None`
+/// outside of the image or before `opj_read_header`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_tile_at(
+  mut p_codec: *mut opj_codec_t,
+  x: OPJ_UINT32,
+  y: OPJ_UINT32,
+) -> Option<OPJ_UINT32> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.tile_at(x, y)
+}
+
+/// Get the area `(x0, y0, x1, y1)` of a tile on the reference grid. The
+/// tiles at the right and bottom edges are clamped to the image.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_tile_bounds(
+  mut p_codec: *mut opj_codec_t,
+  tile_index: OPJ_UINT32,
+) -> Option<(OPJ_UINT32, OPJ_UINT32, OPJ_UINT32, OPJ_UINT32)> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.tile_bounds(tile_index)
+}
+
+/// Get the optional decoding paths the codestream needs: the HTJ2K block
+/// decoder, or the markers of JPWL or JPSPEC. Compare with
+/// `FeatureSet::supported()` to refuse codestreams that this build can't
+/// decode before calling `opj_decode`.
+///
+/// Must be called after `opj_read_header`, only the main header is checked.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_required_features(mut p_codec: *mut opj_codec_t) -> FeatureSet {
+  if p_codec.is_null() {
+    return FeatureSet::empty();
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.requires_features()
+}
+
 #[no_mangle]
 pub unsafe fn opj_read_header(
   mut p_stream: *mut opj_stream_t,
@@ -164,6 +701,46 @@ pub unsafe fn opj_read_header(
   l_codec.read_header(p_stream, p_image)
 }
 
+/// Reset a decompressor and read the header of the next codestream from
+/// `p_stream`, like `opj_read_header` on a new codec.
+///
+/// The decoder parameters and options set on the codec are kept. The tile
+/// buffers of the previous codestream are reused, which avoids most of the
+/// allocations when decoding many images of the same size, e.g. the tiles of
+/// a pyramid: they are reallocated when the number of components differs and
+/// grown when the tiles are larger. The previous image must still be
+/// destroyed with `opj_image_destroy`. JPT-streams aren't supported.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle and `p_stream` a valid stream.
+pub unsafe fn opj_decoder_reset_for_next(
+  p_codec: *mut opj_codec_t,
+  p_stream: *mut opj_stream_t,
+  p_image: *mut *mut opj_image_t,
+) -> OPJ_BOOL {
+  if p_codec.is_null() | p_stream.is_null() | p_image.is_null() {
+    return 0i32;
+  }
+  let p_stream = &mut *(p_stream as *mut opj_stream_private_t);
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.reset_for_next(p_stream, p_image)
+}
+
+/// Restrict decoding to a subset of the components.
+///
+/// Must be called after `opj_read_header`. The decoded image only contains
+/// the listed components, in the order of `comps_indices`. Passing no
+/// components restores decoding of all components.
+///
+/// The components that aren't listed are skipped by tile decoding, so the
+/// MCT and the JP2 colour transforms (palette, channel definitions) can't be
+/// applied: `apply_color_transforms` must be false.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle and `comps_indices` must point to
+/// `numcomps` indices.
 #[no_mangle]
 pub unsafe fn opj_set_decoded_components(
   mut p_codec: *mut opj_codec_t,
@@ -171,11 +748,15 @@ pub unsafe fn opj_set_decoded_components(
   mut comps_indices: *const OPJ_UINT32,
   mut apply_color_transforms: OPJ_BOOL,
 ) -> OPJ_BOOL {
-  if p_codec.is_null() {
+  if p_codec.is_null() || (numcomps != 0 && comps_indices.is_null()) {
     return 0i32;
   }
   let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
-  let components = unsafe { core::slice::from_raw_parts(comps_indices, numcomps as usize) };
+  let components: &[OPJ_UINT32] = if numcomps == 0 {
+    &[]
+  } else {
+    unsafe { core::slice::from_raw_parts(comps_indices, numcomps as usize) }
+  };
   l_codec.set_decoded_components(components, apply_color_transforms)
 }
 
@@ -304,6 +885,333 @@ pub unsafe fn opj_get_decoded_tile(
   l_codec.get_decoded_tile(p_stream, p_image, tile_index)
 }
 
+/// Decode a single tile into a new image.
+///
+/// Must be called after `opj_read_header`. The image is sized to the tile,
+/// clamped to the image area, at its offset on the reference grid, e.g. the
+/// whole image when a single tile is larger than it. The resolution factor
+/// and components set on the codec apply. Can be called repeatedly for
+/// different tiles, the stream must then be seekable.
+///
+/// Returns NULL on error, e.g. for an invalid tile index. The image must be
+/// freed with `opj_image_destroy`.
+///
+/// # Safety
+///
+/// `p_codec` and `p_stream` must be valid handles.
+pub unsafe fn opj_decode_tile(
+  mut p_codec: *mut opj_codec_t,
+  mut p_stream: *mut opj_stream_t,
+  tile_index: OPJ_UINT32,
+) -> *mut opj_image_t {
+  if p_codec.is_null() | p_stream.is_null() {
+    return std::ptr::null_mut();
+  }
+  let p_stream = unsafe { &mut *(p_stream as *mut opj_stream_private_t) };
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  match l_codec.decode_tile(p_stream, tile_index) {
+    Some(image) => Box::into_raw(image),
+    None => std::ptr::null_mut(),
+  }
+}
+
+/// Decode a JP2 file or a J2K codestream to 8-bit RGBA pixels, for callers
+/// that don't want to use the codec and stream API.
+///
+/// `data` is decoded as a JP2 file if it starts with a JPEG 2000 signature
+/// box, or as a codestream otherwise. The image is converted with
+/// `opj_image::as_rgba8`, after upsampling subsampled components.
+///
+/// On success, 0 is returned, `*w` and `*h` are the size of the image and
+/// `*out` points to the `*w * *h * 4` bytes of the pixels, row by row. The
+/// buffer is allocated with `malloc` and owned by the caller, who must free
+/// it with `free`. On failure, -1 is returned and `*out`, `*w` and `*h` are
+/// left unchanged.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, `out`, `w` and `h` must be
+/// valid for writes.
+#[cfg(feature = "c_api")]
+#[no_mangle]
+pub unsafe extern "C" fn opj_decode_to_rgba(
+  data: *const u8,
+  len: usize,
+  out: *mut *mut u8,
+  w: *mut u32,
+  h: *mut u32,
+) -> i32 {
+  if data.is_null() | out.is_null() | w.is_null() | h.is_null() {
+    return -1;
+  }
+  let data = core::slice::from_raw_parts(data, len);
+  let format = if data.starts_with(&[0x00, 0x00, 0x00, 0x0c, 0x6a, 0x50, 0x20, 0x20]) {
+    OPJ_CODEC_JP2
+  } else {
+    OPJ_CODEC_J2K
+  };
+  let mut l_codec = match opj_codec_private_t::new_decoder(format) {
+    Some(codec) => codec,
+    None => return -1,
+  };
+  let mut parameters = opj_dparameters_t::default();
+  if l_codec.setup_decoder(&mut parameters) == 0 {
+    return -1;
+  }
+  let mut l_stream = opj_stream_private::new_memory(data.to_vec());
+  let mut p_image = std::ptr::null_mut::<opj_image_t>();
+  if l_codec.read_header(&mut l_stream, &mut p_image) == 0 {
+    opj_image_destroy(p_image);
+    return -1;
+  }
+  let mut image = Box::from_raw(p_image);
+  if l_codec.decode(&mut l_stream, &mut image) == 0
+    || l_codec.end_decompress(&mut l_stream) == 0
+    || !image.upsample_to_match(UpsampleFilter::Nearest)
+  {
+    return -1;
+  }
+  let (width, height) = match image.comps() {
+    Some(comps) if !comps.is_empty() => (comps[0].w, comps[0].h),
+    _ => return -1,
+  };
+  let rgba = match image.as_rgba8() {
+    Some(rgba) => rgba,
+    None => return -1,
+  };
+  let buffer = ::libc::malloc(rgba.len().max(1)) as *mut u8;
+  if buffer.is_null() {
+    return -1;
+  }
+  core::ptr::copy_nonoverlapping(rgba.as_ptr(), buffer, rgba.len());
+  *out = buffer;
+  *w = width;
+  *h = height;
+  0
+}
+
+/// Decode the area covered by a single precinct.
+///
+/// Must be called after `opj_read_header`. Precinct `precno` of resolution
+/// `resno` of component `compno` of tile `tile_index` is located with the
+/// precinct partition of the tile, precincts being numbered in raster order.
+/// The area of the precinct is decoded into `p_image`, for `compno` only and
+/// up to resolution `resno`, and only the code-blocks contributing to that
+/// area are decoded: this changes the decoded components, the resolution
+/// factor and the decode area of the codec. The stream must be seekable.
+///
+/// Returns `None` on error, e.g. for invalid indices.
+///
+/// # Safety
+///
+/// `p_codec`, `p_stream` and `p_image` must be valid handles.
+pub unsafe fn opj_decode_precinct(
+  mut p_codec: *mut opj_codec_t,
+  mut p_stream: *mut opj_stream_t,
+  mut p_image: *mut opj_image_t,
+  tile_index: OPJ_UINT32,
+  compno: OPJ_UINT32,
+  resno: OPJ_UINT32,
+  precno: OPJ_UINT32,
+) -> Option<PrecinctData> {
+  if p_codec.is_null() | p_stream.is_null() | p_image.is_null() {
+    return None;
+  }
+  let p_image = unsafe { &mut *p_image };
+  let p_stream = unsafe { &mut *(p_stream as *mut opj_stream_private_t) };
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decode_precinct(p_stream, p_image, tile_index, compno, resno, precno)
+}
+
+/// Check the samples of a decoded image against the MD5 written by
+/// `opj_encoder_embed_decoded_md5`.
+///
+/// Returns `None` if the codestream has no such digest, or if `p_image`
+/// isn't the whole image at full resolution (decoded area, resolution
+/// factor or components restricted), otherwise whether the digests match.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle and `p_image` a decoded image.
+pub unsafe fn opj_verify_embedded_md5(
+  mut p_codec: *mut opj_codec_t,
+  p_image: *const opj_image_t,
+) -> Option<bool> {
+  if p_codec.is_null() || p_image.is_null() {
+    return None;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.verify_embedded_md5(&*p_image)
+}
+
+/// Get the packets of a tile in the order they appear in the codestream.
+///
+/// The order follows the progression order of the tile and its POC
+/// segments, each packet being listed once. After `opj_read_header` this is
+/// the order given by the main header: COD and POC markers of tile-part
+/// headers are only taken into account once the tile has been read, e.g.
+/// by `opj_decode` or `opj_read_tile_header`.
+///
+/// Returns `None` on error, e.g. for an invalid tile index.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_get_packet_order(
+  mut p_codec: *mut opj_codec_t,
+  tile_index: OPJ_UINT32,
+) -> Option<Vec<PacketCoord>> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.packet_order(tile_index)
+}
+
+/// Get the progression order of a tile.
+///
+/// Works with a decompressor after `opj_read_header`, where the order of a
+/// tile comes from the main header until its tile-part headers are read,
+/// and with a compressor after `opj_setup_encoder`.
+///
+/// Returns `None` on error, e.g. for an invalid tile index.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_progression(
+  mut p_codec: *mut opj_codec_t,
+  tile_index: OPJ_UINT32,
+) -> Option<Progression> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.progression(tile_index)
+}
+
+/// Get the number of packets of a tile, the length of the list returned by
+/// `opj_get_packet_order`.
+///
+/// Returns `None` on error, e.g. for an invalid tile index.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_get_packet_count(
+  mut p_codec: *mut opj_codec_t,
+  tile_index: OPJ_UINT32,
+) -> Option<OPJ_UINT32> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.packet_count(tile_index)
+}
+
+/// Get the cumulative size in bytes of the quality layers of a tile: entry
+/// `l` is the size of the packets of layers `0..=l`.
+///
+/// Sizes are computed from the packet lengths of the PLT markers, so the
+/// tile must have been read, e.g. by `opj_decode`, and must have PLT
+/// markers (see the `PLT=YES` option of `opj_encoder_set_extra_options`).
+/// Returns `None` otherwise or on error. With PPM or PPT markers, packet
+/// headers aren't included.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_get_layer_sizes(
+  mut p_codec: *mut opj_codec_t,
+  tile_index: OPJ_UINT32,
+) -> Option<Vec<u64>> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.layer_sizes(tile_index)
+}
+
+/// Build an index of the location of every packet in the stream, e.g. to
+/// fetch the packets of a layer or resolution with range requests.
+///
+/// Must be called after `opj_read_header`, instead of `opj_decode`: the
+/// tiles are read from the stream and only their packet headers are
+/// parsed, the code-blocks aren't decoded. PLT markers aren't needed.
+/// Tiles outside the area set by `opj_set_decode_area` are left out.
+///
+/// Returns `None` on error, e.g. for a truncated codestream.
+///
+/// # Safety
+///
+/// `p_codec` and `p_stream` must be valid handles.
+pub unsafe fn opj_build_packet_index(
+  mut p_codec: *mut opj_codec_t,
+  mut p_stream: *mut opj_stream_t,
+) -> Option<PacketIndex> {
+  if p_codec.is_null() | p_stream.is_null() {
+    return None;
+  }
+  let p_stream = unsafe { &mut *(p_stream as *mut opj_stream_private_t) };
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.build_packet_index(p_stream)
+}
+
+/// Get the location in the stream of the main header and of the tile-parts
+/// of each tile, e.g. to serve the tiles of a codestream with range
+/// requests.
+///
+/// Ranges come from the codestream index, so only the tiles read so far
+/// are listed: call it after `opj_decode`, or after `opj_read_header` for
+/// the main header alone. Positions are offsets in the stream, so in a JP2
+/// file they include the boxes before the codestream.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_get_codestream_byte_ranges(
+  mut p_codec: *mut opj_codec_t,
+) -> Option<CodestreamByteRanges> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.codestream_byte_ranges()
+}
+
+/// Decode the whole image straight into a caller provided buffer.
+///
+/// Must be called after `opj_read_header` (and optionally
+/// `opj_set_decoded_resolution_factor`) instead of `opj_decode`. Tiles are
+/// decoded one at a time and written into `out` as described by `layout`,
+/// so the image components are never allocated. `out` must be large enough
+/// for the image at the decoded resolution.
+///
+/// Subsampled components and decode areas aren't supported, and the JP2
+/// colour transforms (palette, channel definitions) aren't applied. After
+/// `opj_set_decoded_components`, the channels of `layout` index the decoded
+/// components, in the order they were selected.
+///
+/// # Safety
+///
+/// `p_codec`, `p_stream` and `p_image` must be the handles used with
+/// `opj_read_header`.
+pub unsafe fn opj_decode_into(
+  mut p_codec: *mut opj_codec_t,
+  mut p_stream: *mut opj_stream_t,
+  mut p_image: *const opj_image_t,
+  out: &mut [u8],
+  layout: &PixelLayout,
+) -> OPJ_BOOL {
+  if p_codec.is_null() | p_stream.is_null() | p_image.is_null() {
+    return 0i32;
+  }
+  let p_image = unsafe { &*p_image };
+  let p_stream = unsafe { &mut *(p_stream as *mut opj_stream_private_t) };
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.decode_into(p_stream, p_image, out, layout) as _
+}
+
 #[no_mangle]
 pub unsafe fn opj_set_decoded_resolution_factor(
   mut p_codec: *mut opj_codec_t,
@@ -316,6 +1224,54 @@ pub unsafe fn opj_set_decoded_resolution_factor(
   l_codec.set_decoded_resolution_factor(res_factor)
 }
 
+/// Set the number of highest resolution levels to discard for some
+/// components, as `(compno, factor)` pairs.
+///
+/// Must be called after `opj_read_header`, and after
+/// `opj_set_decoded_resolution_factor` which sets the factor of every
+/// component. Each factor must be lower than the number of resolutions of
+/// its component. The decoded components then have different dimensions,
+/// given by their `factor` member. When the codestream uses a multiple
+/// component transform, the first three components must keep the same
+/// factor.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_set_component_reduce(
+  mut p_codec: *mut opj_codec_t,
+  factors: &[(OPJ_UINT32, OPJ_UINT32)],
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_component_reduce(factors) as OPJ_BOOL
+}
+
+/// Decode the band of quality layers `[start, end)`, e.g. to refine an
+/// image whose layers `0..start` were already received.
+///
+/// The layers below `start` are the base the band refines, so the layers
+/// `0..end` are decoded: this is the same as setting `cp_layer` to `end` in
+/// `opj_dparameters_t`. Must be called after `opj_read_header`, with
+/// `start < end <= numlayers`, the number of layers of the main header.
+///
+/// # Safety
+///
+/// `p_codec` must be a decompressor handle.
+pub unsafe fn opj_decoder_set_layer_range(
+  mut p_codec: *mut opj_codec_t,
+  start: OPJ_UINT32,
+  end: OPJ_UINT32,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0i32;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_layer_range(start, end) as OPJ_BOOL
+}
+
 /* default decoding parameters */
 /* ---------------------------------------------------------------------- */
 /* COMPRESSION FUNCTIONS*/
@@ -384,6 +1340,535 @@ pub unsafe fn opj_encoder_set_extra_options(
   l_codec.encoder_set_extra_options(options.as_slice()) as _
 }
 
+/* ----------------------------------------------------------------------- */
+/// Set the number of resolutions (decomposition levels + 1) of all tiles.
+///
+/// Must be called after `opj_setup_encoder`. Fails if the lowest resolution
+/// of a tile would be empty or if custom precinct sizes can't be extended
+/// to the extra resolutions.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+#[no_mangle]
+pub unsafe fn opj_encoder_set_resolutions(
+  mut p_codec: *mut opj_codec_t,
+  mut numresolutions: OPJ_UINT32,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_resolutions(numresolutions) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the quantization step sizes of the irreversible wavelet subbands.
+///
+/// Must be called after `opj_setup_encoder` (with `irreversible` set) and
+/// after `opj_encoder_set_resolutions`, which resets the step sizes. Explicit
+/// step sizes must have one entry per subband: `3 * numresolutions - 2`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_quantization(
+  mut p_codec: *mut opj_codec_t,
+  style: &QuantStyle,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_quantization(style) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Encode with the irreversible wavelet and quantization step sizes that
+/// keep every decoded sample within `max_error` of the source, for users
+/// who need a bounded error rather than a lossless image.
+///
+/// Must be called after `opj_setup_encoder` and after any call replacing
+/// the step sizes, like `opj_encoder_set_resolutions`. The last quality
+/// layer must be lossless (rate 0). The bound is statistical, derived from
+/// the subband norms: exactly bounding the error would need step sizes
+/// computed per subband from the synthesis filters.
+/// `Jp2Codec::set_near_lossless` guarantees it by checking the decoded
+/// image and encoding it again when it doesn't hold.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_near_lossless(
+  mut p_codec: *mut opj_codec_t,
+  max_error: OPJ_UINT32,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_near_lossless(max_error, 0) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the mode switches of the code-block coding style of all tiles and
+/// components, replacing `opj_cparameters_t::mode`.
+///
+/// Must be called after `opj_setup_encoder`. `ModeSwitches::SEGMARK` and
+/// `ModeSwitches::ERTERM` let decoders detect corrupt code-blocks, see
+/// `opj_decoder_set_error_resilience`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_mode_switches(
+  mut p_codec: *mut opj_codec_t,
+  modes: ModeSwitches,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_mode_switches(modes) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the coding parameters of the COD marker of all tiles and components
+/// at once: resolutions, code-block and precinct sizes, mode switches,
+/// SOP/EPH markers, guard bits, multiple component transform and wavelet.
+///
+/// Must be called after `opj_setup_encoder`. The whole style is validated
+/// before any tile is changed, so a failed call leaves the encoder as it
+/// was. The quantization is reset to the default of the wavelet.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_coding_style(
+  mut p_codec: *mut opj_codec_t,
+  style: &CodingStyle,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_coding_style(style) as _
+}
+
+/// Get the coding style of the first component of the first tile of a
+/// compressor, or `None` before `opj_setup_encoder`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_get_coding_style(mut p_codec: *mut opj_codec_t) -> Option<CodingStyle> {
+  if p_codec.is_null() {
+    return None;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.coding_style()
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the number of guard bits, in `[0, 7]`, of all tiles and components.
+///
+/// Must be called after `opj_setup_encoder`. Guard bits add headroom to the
+/// wavelet coefficients: high-amplitude images can overflow the magnitude
+/// bit-planes with too few of them. The default is 2.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_guard_bits(
+  mut p_codec: *mut opj_codec_t,
+  mut numgbits: OPJ_UINT32,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_guard_bits(numgbits) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the number of quality layers of all tiles.
+///
+/// Must be called after `opj_setup_encoder`, and before
+/// `opj_encoder_set_layer_rates` or `opj_encoder_set_layer_psnr` which set
+/// the target of each layer. Until then, the first layer includes all the
+/// data. Not supported with `cp_fixed_alloc`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_num_layers(
+  mut p_codec: *mut opj_codec_t,
+  numlayers: OPJ_UINT32,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_num_layers(numlayers) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Allocate the quality layers by compression ratio, like `tcp_rates`.
+///
+/// One strictly decreasing ratio per layer, a last ratio of 0 (or up to 1)
+/// makes the last layer lossless. Replaces targets set by
+/// `opj_encoder_set_layer_psnr`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_layer_rates(
+  mut p_codec: *mut opj_codec_t,
+  rates: &[f32],
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_layer_rates(rates) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Allocate the quality layers by PSNR in dB, like `tcp_distoratio`.
+///
+/// One strictly increasing PSNR per layer, a last PSNR of 0 makes the last
+/// layer lossless. Replaces targets set by `opj_encoder_set_layer_rates`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_layer_psnr(mut p_codec: *mut opj_codec_t, psnr: &[f32]) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_layer_psnr(psnr) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Add a text comment to be written as a COM marker in the main header.
+///
+/// The comment must be representable as ISO/IEC 8859-15 (Latin-9) text.
+/// Can be called multiple times, each comment is written in its own marker
+/// after the comment from `opj_cparameters_t::cp_comment`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle and `comment` a NUL terminated string.
+#[no_mangle]
+pub unsafe fn opj_encoder_add_comment(
+  mut p_codec: *mut opj_codec_t,
+  mut comment: *const core::ffi::c_char,
+) -> OPJ_BOOL {
+  if p_codec.is_null() || comment.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  match core::ffi::CStr::from_ptr(comment).to_str() {
+    Ok(comment) => l_codec.add_comment(comment) as _,
+    Err(err) => {
+      log::error!("Failed to convert C string to Rust string: {err}");
+      0
+    }
+  }
+}
+
+/* ----------------------------------------------------------------------- */
+/// Replace the comment identifying the writer, "Created by OpenJPEG version
+/// ..." unless `opj_cparameters_t::cp_comment` is set.
+///
+/// With `None`, the comment is dropped: no COM marker is written unless
+/// comments are added with `opj_encoder_add_comment`, and the codestream no
+/// longer depends on the library version. The signature must be
+/// representable as ISO/IEC 8859-15 (Latin-9) text.
+///
+/// Must be called after `opj_setup_encoder`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_writer_signature(
+  mut p_codec: *mut opj_codec_t,
+  signature: Option<&str>,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_writer_signature(signature) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Write `boxes` in a JP2 file, e.g. the boxes read with `opj_get_boxes`.
+///
+/// The boxes are written in order, after the JP2 header box or after the
+/// codestream box depending on `RawBox::after_codestream`. The signature,
+/// file type, JP2 header and codestream boxes are generated by the encoder
+/// and are skipped.
+///
+/// # Safety
+///
+/// `p_codec` must be a JP2 compressor handle.
+pub unsafe fn opj_encoder_preserve_boxes(
+  mut p_codec: *mut opj_codec_t,
+  boxes: Vec<RawBox>,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.preserve_boxes(boxes) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Write a `uuid` box with application specific `data` in a JP2 file, e.g.
+/// the GeoJP2 metadata.
+///
+/// The boxes are written in the order they were added, after the JP2 header
+/// box and the boxes given to `opj_encoder_preserve_boxes`. A box larger
+/// than 4 GiB is written with an extended length. The decoder keeps them
+/// with `opj_decoder_capture_boxes`, see `RawBox::uuid`.
+///
+/// # Safety
+///
+/// `p_codec` must be a JP2 compressor handle.
+pub unsafe fn opj_encoder_add_uuid_box(
+  mut p_codec: *mut opj_codec_t,
+  uuid: [u8; 16],
+  data: Vec<u8>,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.add_uuid_box(uuid, data) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Write the georeferencing of a JP2 file: a GeoJP2 `uuid` box containing
+/// the GeoTIFF blob `data`, e.g. read with `opj_get_geo_metadata`.
+///
+/// The blob isn't checked. It replaces the one set before and is written
+/// like the boxes of `opj_encoder_add_uuid_box`.
+///
+/// # Safety
+///
+/// `p_codec` must be a JP2 compressor handle.
+pub unsafe fn opj_encoder_set_geo_metadata(mut p_codec: *mut opj_codec_t, data: &[u8]) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_geo_metadata(data) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the colour specification unknown flag (UnkC) of the image header box
+/// of a JP2 file, telling readers that the colour space given by the `colr`
+/// box may be inaccurate. Decoders then report `OPJ_CLRSPC_UNKNOWN`.
+///
+/// Call this after `opj_setup_encoder`, which clears the flag.
+///
+/// # Safety
+///
+/// `p_codec` must be a JP2 compressor handle.
+pub unsafe fn opj_encoder_set_colorspace_unknown(
+  mut p_codec: *mut opj_codec_t,
+  unknown: bool,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_colorspace_unknown(unknown) as _
+}
+
+/// Write an intellectual property box (`jp2i`) with the given contents
+/// after the JP2 header box, and set the IPR flag of the image header box.
+/// `None` removes it. A preserved `jp2i` box is replaced, see
+/// `opj_get_ipr` to read it back.
+///
+/// # Safety
+///
+/// `p_codec` must be a JP2 compressor handle.
+pub unsafe fn opj_encoder_set_ipr(mut p_codec: *mut opj_codec_t, ipr: Option<Vec<u8>>) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_ipr(ipr) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the brand and the compatibility list of the file type box (`ftyp`)
+/// of a JP2 file, e.g. `*b"jph "` for HTJ2K or `*b"jpx "` for JPX files.
+/// The default is the `jp2 ` brand alone.
+///
+/// The brand must be in the compatibility list. Call this after
+/// `opj_setup_encoder`, which resets the brands.
+///
+/// # Safety
+///
+/// `p_codec` must be a JP2 compressor handle.
+pub unsafe fn opj_encoder_set_brands(
+  mut p_codec: *mut opj_codec_t,
+  brand: [u8; 4],
+  compatible: Vec<[u8; 4]>,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_brands(brand, compatible) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Write a reader requirements box (`rreq`) after the file type box of a JP2
+/// file, or no box with `None`.
+///
+/// The box replaces a `rreq` box given to `opj_encoder_preserve_boxes`.
+///
+/// # Safety
+///
+/// `p_codec` must be a JP2 compressor handle.
+pub unsafe fn opj_encoder_set_reader_requirements(
+  mut p_codec: *mut opj_codec_t,
+  rreq: Option<ReaderRequirements>,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_reader_requirements(rreq) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the order of the tile-parts in the codestream, for serving
+/// progressively with range requests.
+///
+/// With `CodestreamLayout::LayerFirst`, each tile is split into one
+/// tile-part per quality layer, and the first tile-part of every tile is
+/// written, then the second one... The tile progression orders become LRCP.
+/// A prefix of the codestream ending after the tile-parts of a layer,
+/// followed by an EOC marker, decodes to the lower layers, like the whole
+/// codestream with `cp_layer` set. `CodestreamLayout::ResolutionFirst` does
+/// the same with resolution levels and the RLCP order (RPCL is kept), the
+/// prefix decoding like the whole codestream with `cp_reduce` set.
+///
+/// The tile-parts are kept in memory until `opj_end_compress`. Progression
+/// order changes must start with the same dimension as the layout.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_codestream_layout(
+  mut p_codec: *mut opj_codec_t,
+  layout: CodestreamLayout,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_codestream_layout(layout) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Write the MD5 of the decoded image samples in a COM marker, to check
+/// later that the codestream still decodes to them with
+/// `opj_verify_embedded_md5`.
+///
+/// This is not standard: other decoders see the digest as a plain text
+/// comment. The codestream is kept in memory and decoded by
+/// `opj_end_compress`, which writes it with the digest of the reconstructed
+/// samples, so the digest also holds for lossy codestreams.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_embed_decoded_md5(
+  mut p_codec: *mut opj_codec_t,
+  embed: bool,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.embed_decoded_md5(embed) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Append a progression order change to every tile.
+///
+/// Must be called after `opj_setup_encoder`, the changes being appended to
+/// the ones of `opj_cparameters_t::POC`. They are written in POC markers
+/// and must cover every packet of the tiles: `opj_start_compress` fails
+/// otherwise.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_add_progression_change(
+  mut p_codec: *mut opj_codec_t,
+  poc: &PocSpec,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.add_progression_change(poc) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Set the progression order of all tiles, written in their COD marker.
+///
+/// Must be called after `opj_setup_encoder`, which sets the order of
+/// `opj_cparameters_t::prog_order`. Progression order changes added by
+/// `opj_encoder_add_progression_change` keep their own order.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_progression(
+  mut p_codec: *mut opj_codec_t,
+  progression: Progression,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_progression(progression) as _
+}
+
+/* ----------------------------------------------------------------------- */
+/// Signal the use of Part `part` capabilities in the CAP marker, with the
+/// Ccap value `ccap`.
+///
+/// Must be called after `opj_setup_encoder`. The CAP marker is written after
+/// the SIZ marker and Rsiz signals it. With Part 15 (HTJ2K), a CPF marker
+/// follows with the profile of the corresponding Part 1 codestream. The
+/// encoder doesn't check that the codestream conforms to the signaled
+/// capabilities.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_encoder_set_capability(
+  mut p_codec: *mut opj_codec_t,
+  part: OPJ_UINT32,
+  ccap: u16,
+) -> OPJ_BOOL {
+  if p_codec.is_null() {
+    return 0;
+  }
+  let l_codec = &mut *(p_codec as *mut opj_codec_private_t);
+  l_codec.set_capability(part, ccap) as _
+}
+
 /* ----------------------------------------------------------------------- */
 #[no_mangle]
 pub unsafe fn opj_start_compress(
@@ -520,6 +2005,54 @@ pub unsafe fn opj_destroy_cstr_info(mut cstr_info: *mut *mut opj_codestream_info
   };
 }
 
+/// Get the colour specification read from the `colr` box of a JP2 file.
+///
+/// Call this after `opj_read_header`. Unlike `opj_image_t::color_space`,
+/// this tells an ICC profile apart from an unknown enumerated colour space.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_color_spec(mut p_codec: *mut opj_codec_t) -> Jp2ColorSpec {
+  if p_codec.is_null() {
+    return Jp2ColorSpec::Unspecified;
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.color_spec()
+}
+
+/// Get the comments read from the COM markers of the codestream.
+///
+/// Comments are collected while reading the main header and tile-part
+/// headers, so call this after `opj_read_header` or `opj_decode`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_comments(mut p_codec: *mut opj_codec_t) -> Vec<Comment> {
+  if p_codec.is_null() {
+    return Vec::new();
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.comments()
+}
+
+/// Get the capabilities signaled by the codestream: Rsiz and the CAP and CPF
+/// markers.
+///
+/// For a decoder, must be called after `opj_read_header`.
+///
+/// # Safety
+///
+/// `p_codec` must be a codec handle.
+pub unsafe fn opj_get_capabilities(mut p_codec: *mut opj_codec_t) -> Capabilities {
+  if p_codec.is_null() {
+    return Capabilities::default();
+  }
+  let l_codec = &*(p_codec as *mut opj_codec_private_t);
+  l_codec.capabilities()
+}
+
 #[no_mangle]
 pub unsafe fn opj_get_cstr_index(mut p_codec: *mut opj_codec_t) -> *mut opj_codestream_index_t {
   if p_codec.is_null() {
@@ -653,6 +2186,42 @@ pub unsafe extern "C" fn opj_stream_set_user_data_length(
   p_stream.set_stream_length(data_length);
 }
 
+/// Number of bytes read from the stream by the codec.
+///
+/// Bytes skipped, e.g. the data of tiles outside of the decoded area, aren't
+/// counted. Bytes buffered ahead of the codec's reads aren't counted either.
+///
+/// # Safety
+///
+/// `p_stream` must be a stream handle.
+#[no_mangle]
+pub unsafe extern "C" fn opj_stream_get_bytes_read(
+  mut p_stream: *const opj_stream_t,
+) -> OPJ_UINT64 {
+  if p_stream.is_null() {
+    return 0;
+  }
+  let p_stream = unsafe { &*(p_stream as *const opj_stream_private_t) };
+  p_stream.bytes_read()
+}
+
+/// Number of bytes written to the stream by the codec, including bytes
+/// written again after seeking back, e.g. to update box or marker lengths.
+///
+/// # Safety
+///
+/// `p_stream` must be a stream handle.
+#[no_mangle]
+pub unsafe extern "C" fn opj_stream_get_bytes_written(
+  mut p_stream: *const opj_stream_t,
+) -> OPJ_UINT64 {
+  if p_stream.is_null() {
+    return 0;
+  }
+  let p_stream = unsafe { &*(p_stream as *const opj_stream_private_t) };
+  p_stream.bytes_written()
+}
+
 #[cfg(feature = "file-io")]
 #[no_mangle]
 pub unsafe fn opj_stream_create_default_file_stream(
@@ -700,11 +2269,17 @@ pub unsafe fn opj_image_data_free(mut ptr: *mut core::ffi::c_void) {
   opj_aligned_free(ptr);
 }
 
-/* Stub implementation */
 #[no_mangle]
 pub fn opj_has_thread_support() -> OPJ_BOOL {
-  0i32
+  cfg!(feature = "threads") as OPJ_BOOL
 }
+#[cfg(feature = "threads")]
+#[no_mangle]
+pub fn opj_get_num_cpus() -> core::ffi::c_int {
+  std::thread::available_parallelism().map_or(1, |n| n.get() as core::ffi::c_int)
+}
+/* Stub implementation */
+#[cfg(not(feature = "threads"))]
 #[no_mangle]
 pub fn opj_get_num_cpus() -> core::ffi::c_int {
   1i32
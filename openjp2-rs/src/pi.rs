@@ -56,6 +56,9 @@ pub struct opj_pi_iterator {
   pub y: OPJ_UINT32,
   pub dx: OPJ_UINT32,
   pub dy: OPJ_UINT32,
+  /** origin of the precinct partitions, 0 or 1 */
+  pub ox: OPJ_UINT32,
+  pub oy: OPJ_UINT32,
   pub manager: opj_event_mgr,
 }
 pub type opj_pi_iterator_t = opj_pi_iterator;
@@ -67,6 +70,42 @@ unsafe fn opj_uint_ceildivpow2(mut a: OPJ_UINT32, mut b: OPJ_UINT32) -> OPJ_UINT
     >> b) as u32
 }
 
+/* *
+ * Index of the precinct of size 1 << pd holding position a, in the
+ * partition starting at origin. Plus one so it stays unsigned when a is
+ * before the origin: only differences of indices are meaningful.
+ */
+#[inline]
+fn opj_pi_prc_index(a: OPJ_UINT32, pd: OPJ_UINT32, origin: OPJ_UINT32) -> OPJ_UINT32 {
+  ((a as u64 + (1u64 << pd) - origin as u64) >> pd) as OPJ_UINT32
+}
+
+/* *
+ * Like opj_pi_prc_index(), for the end of the precinct ending at a.
+ */
+#[inline]
+fn opj_pi_prc_index_end(a: OPJ_UINT32, pd: OPJ_UINT32, origin: OPJ_UINT32) -> OPJ_UINT32 {
+  ((a as u64 + (2u64 << pd) - 1 - origin as u64) >> pd) as OPJ_UINT32
+}
+
+/* *
+ * Whether a is the start of a precinct of size step, in the partition
+ * starting at origin.
+ */
+#[inline]
+fn opj_pi_is_prc_start(a: OPJ_UINT32, step: u64, origin: OPJ_UINT32) -> bool {
+  (a as u64 + step - origin as u64).is_multiple_of(step)
+}
+
+/* *
+ * Next position after a that is at origin modulo step.
+ */
+#[inline]
+fn opj_pi_next_position(a: OPJ_UINT32, step: OPJ_UINT32, origin: OPJ_UINT32) -> OPJ_UINT32 {
+  let rem = (a as u64 + step as u64 - origin as u64) % step as u64;
+  a.wrapping_add(step.wrapping_sub(rem as OPJ_UINT32))
+}
+
 /*
  * The copyright in this software is being made available under the 2-clauses
  * BSD License, included below. This software may be subject to other third
@@ -520,9 +559,7 @@ unsafe fn opj_pi_next_rpcl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
             if (*pi).x < (*pi).poc.tx1 {
               (*pi).compno = (*pi).poc.compno0
             } else {
-              (*pi).y = ((*pi).y as core::ffi::c_uint)
-                .wrapping_add((*pi).dy.wrapping_sub((*pi).y.wrapping_rem((*pi).dy)))
-                as OPJ_UINT32;
+              (*pi).y = opj_pi_next_position((*pi).y, (*pi).dy, (*pi).oy);
               current_block = 6450636197030046351;
               break;
             }
@@ -567,14 +604,14 @@ unsafe fn opj_pi_next_rpcl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
           }
 
           /* See ISO-15441. B.12.1.3 Resolution level-position-component-layer progression */
-          if !(((*pi).y as u64 % (dy << rpy)) == 0
-            || ((*pi).y == (*pi).ty0 && (((try0 as u64) << levelno) % (1u64 << rpy)) != 0))
+          if !(opj_pi_is_prc_start((*pi).y, dy << rpy, (*pi).oy)
+            || ((*pi).y == (*pi).ty0 && !opj_pi_is_prc_start(try0, 1u64 << (*res).pdy, (*pi).oy)))
           {
             current_block = 10891380440665537214;
             continue;
           }
-          if !(((*pi).x as u64 % (dx << rpx)) == 0
-            || ((*pi).x == (*pi).tx0 && (((trx0 as u64) << levelno) % (1u64 << rpx)) != 0))
+          if !(opj_pi_is_prc_start((*pi).x, dx << rpx, (*pi).ox)
+            || ((*pi).x == (*pi).tx0 && !opj_pi_is_prc_start(trx0, 1u64 << (*res).pdx, (*pi).ox)))
           {
             current_block = 10891380440665537214;
             continue;
@@ -587,22 +624,22 @@ unsafe fn opj_pi_next_rpcl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
             current_block = 10891380440665537214;
             continue;
           }
-          prci = opj_uint_floordivpow2(
+          prci = opj_pi_prc_index(
             opj_uint64_ceildiv_res_uint32((*pi).x as u64, dx << levelno),
             (*res).pdx,
-          ) - opj_uint_floordivpow2(trx0, (*res).pdx);
-          prcj = opj_uint_floordivpow2(
+            (*pi).ox,
+          ) - opj_pi_prc_index(trx0, (*res).pdx, (*pi).ox);
+          prcj = opj_pi_prc_index(
             opj_uint64_ceildiv_res_uint32((*pi).y as u64, dy << levelno),
             (*res).pdy,
-          ) - opj_uint_floordivpow2(try0, (*res).pdy);
+            (*pi).oy,
+          ) - opj_pi_prc_index(try0, (*res).pdy, (*pi).oy);
           (*pi).precno = prci.wrapping_add(prcj.wrapping_mul((*res).pw));
           (*pi).layno = (*pi).poc.layno0;
           current_block = 2606304779496145856;
           break;
         } else {
-          (*pi).x = ((*pi).x as core::ffi::c_uint)
-            .wrapping_add((*pi).dx.wrapping_sub((*pi).x.wrapping_rem((*pi).dx)))
-            as OPJ_UINT32;
+          (*pi).x = opj_pi_next_position((*pi).x, (*pi).dx, (*pi).ox);
           current_block = 3123434771885419771;
         }
       }
@@ -756,9 +793,7 @@ unsafe fn opj_pi_next_pcrl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
           if (*pi).x < (*pi).poc.tx1 {
             (*pi).compno = (*pi).poc.compno0
           } else {
-            (*pi).y = ((*pi).y as core::ffi::c_uint)
-              .wrapping_add((*pi).dy.wrapping_sub((*pi).y.wrapping_rem((*pi).dy)))
-              as OPJ_UINT32;
+            (*pi).y = opj_pi_next_position((*pi).y, (*pi).dy, (*pi).oy);
             current_block = 7245201122033322888;
             break;
           }
@@ -795,9 +830,7 @@ unsafe fn opj_pi_next_pcrl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
               comp = &mut *(*pi).comps.offset((*pi).compno as isize) as *mut opj_pi_comp_t;
               (*pi).resno = (*pi).poc.resno0
             } else {
-              (*pi).x = ((*pi).x as core::ffi::c_uint)
-                .wrapping_add((*pi).dx.wrapping_sub((*pi).x.wrapping_rem((*pi).dx)))
-                as OPJ_UINT32;
+              (*pi).x = opj_pi_next_position((*pi).x, (*pi).dx, (*pi).ox);
               current_block = 8845338526596852646;
               break;
             }
@@ -837,14 +870,14 @@ unsafe fn opj_pi_next_pcrl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
             continue;
           }
           /* See ISO-15441. B.12.1.4 Position-component-resolution level-layer progression */
-          if !(((*pi).y as u64 % (((*comp).dy as u64) << rpy)) == 0
-            || ((*pi).y == (*pi).ty0 && (((try0 as u64) << levelno) % (1u64 << rpy)) != 0))
+          if !(opj_pi_is_prc_start((*pi).y, ((*comp).dy as u64) << rpy, (*pi).oy)
+            || ((*pi).y == (*pi).ty0 && !opj_pi_is_prc_start(try0, 1u64 << (*res).pdy, (*pi).oy)))
           {
             current_block = 15512526488502093901;
             continue;
           }
-          if !(((*pi).x as u64 % (((*comp).dx as u64) << rpx)) == 0
-            || ((*pi).x == (*pi).tx0 && (((trx0 as u64) << levelno) % (1u64 << rpx)) != 0))
+          if !(opj_pi_is_prc_start((*pi).x, ((*comp).dx as u64) << rpx, (*pi).ox)
+            || ((*pi).x == (*pi).tx0 && !opj_pi_is_prc_start(trx0, 1u64 << (*res).pdx, (*pi).ox)))
           {
             current_block = 15512526488502093901;
             continue;
@@ -857,14 +890,16 @@ unsafe fn opj_pi_next_pcrl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
             current_block = 15512526488502093901;
             continue;
           }
-          prci = opj_uint_floordivpow2(
+          prci = opj_pi_prc_index(
             opj_uint64_ceildiv_res_uint32((*pi).x as u64, ((*comp).dx as u64) << levelno),
             (*res).pdx,
-          ) - opj_uint_floordivpow2(trx0, (*res).pdx);
-          prcj = opj_uint_floordivpow2(
+            (*pi).ox,
+          ) - opj_pi_prc_index(trx0, (*res).pdx, (*pi).ox);
+          prcj = opj_pi_prc_index(
             opj_uint64_ceildiv_res_uint32((*pi).y as u64, ((*comp).dy as u64) << levelno),
             (*res).pdy,
-          ) - opj_uint_floordivpow2(try0, (*res).pdy);
+            (*pi).oy,
+          ) - opj_pi_prc_index(try0, (*res).pdy, (*pi).oy);
           (*pi).precno = prci.wrapping_add(prcj.wrapping_mul((*res).pw));
           (*pi).layno = (*pi).poc.layno0;
           current_block = 6281126495347172768;
@@ -1056,9 +1091,7 @@ unsafe fn opj_pi_next_cprl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
             if (*pi).x < (*pi).poc.tx1 {
               (*pi).resno = (*pi).poc.resno0
             } else {
-              (*pi).y = ((*pi).y as core::ffi::c_uint)
-                .wrapping_add((*pi).dy.wrapping_sub((*pi).y.wrapping_rem((*pi).dy)))
-                as OPJ_UINT32;
+              (*pi).y = opj_pi_next_position((*pi).y, (*pi).dy, (*pi).oy);
               current_block = 18153031941552419006;
               break;
             }
@@ -1098,14 +1131,14 @@ unsafe fn opj_pi_next_cprl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
             continue;
           }
           /* See ISO-15441. B.12.1.5 Component-position-resolution level-layer progression */
-          if !(((*pi).y as u64 % (((*comp).dy as u64) << rpy)) == 0
-            || ((*pi).y == (*pi).ty0 && (((try0 as u64) << levelno) % (1u64 << rpy)) != 0))
+          if !(opj_pi_is_prc_start((*pi).y, ((*comp).dy as u64) << rpy, (*pi).oy)
+            || ((*pi).y == (*pi).ty0 && !opj_pi_is_prc_start(try0, 1u64 << (*res).pdy, (*pi).oy)))
           {
             current_block = 3123434771885419771;
             continue;
           }
-          if !(((*pi).x as u64 % (((*comp).dx as u64) << rpx)) == 0
-            || ((*pi).x == (*pi).tx0 && (((trx0 as u64) << levelno) % (1u64 << rpx)) != 0))
+          if !(opj_pi_is_prc_start((*pi).x, ((*comp).dx as u64) << rpx, (*pi).ox)
+            || ((*pi).x == (*pi).tx0 && !opj_pi_is_prc_start(trx0, 1u64 << (*res).pdx, (*pi).ox)))
           {
             current_block = 3123434771885419771;
             continue;
@@ -1118,22 +1151,22 @@ unsafe fn opj_pi_next_cprl(mut pi: *mut opj_pi_iterator_t) -> OPJ_BOOL {
             current_block = 3123434771885419771;
             continue;
           }
-          prci = opj_uint_floordivpow2(
+          prci = opj_pi_prc_index(
             opj_uint64_ceildiv_res_uint32((*pi).x as u64, ((*comp).dx as u64) << levelno),
             (*res).pdx,
-          ) - opj_uint_floordivpow2(trx0, (*res).pdx);
-          prcj = opj_uint_floordivpow2(
+            (*pi).ox,
+          ) - opj_pi_prc_index(trx0, (*res).pdx, (*pi).ox);
+          prcj = opj_pi_prc_index(
             opj_uint64_ceildiv_res_uint32((*pi).y as u64, ((*comp).dy as u64) << levelno),
             (*res).pdy,
-          ) - opj_uint_floordivpow2(try0, (*res).pdy);
+            (*pi).oy,
+          ) - opj_pi_prc_index(try0, (*res).pdy, (*pi).oy);
           (*pi).precno = prci.wrapping_add(prcj.wrapping_mul((*res).pw));
           (*pi).layno = (*pi).poc.layno0;
           current_block = 15594839951440953787;
           break;
         } else {
-          (*pi).x = ((*pi).x as core::ffi::c_uint)
-            .wrapping_add((*pi).dx.wrapping_sub((*pi).x.wrapping_rem((*pi).dx)))
-            as OPJ_UINT32;
+          (*pi).x = opj_pi_next_position((*pi).x, (*pi).dx, (*pi).ox);
           current_block = 10692455896603418738;
         }
       }
@@ -1207,6 +1240,7 @@ unsafe fn opj_get_encoding_parameters(
   /* take the largest value for dx_min and dy_min */
   *p_dx_min = 0x7fffffff as OPJ_UINT32;
   *p_dy_min = 0x7fffffff as OPJ_UINT32;
+  let (l_ox, l_oy) = (*l_tcp).partition_origin();
   compno = 0 as OPJ_UINT32;
   while compno < (*p_image).numcomps {
     /* arithmetic variables to calculate */
@@ -1264,19 +1298,19 @@ unsafe fn opj_get_encoding_parameters(
       l_ry0 = opj_uint_ceildivpow2(l_tcy0, l_level_no);
       l_rx1 = opj_uint_ceildivpow2(l_tcx1, l_level_no);
       l_ry1 = opj_uint_ceildivpow2(l_tcy1, l_level_no);
-      l_px0 = opj_uint_floordivpow2(l_rx0, l_pdx) << l_pdx;
-      l_py0 = opj_uint_floordivpow2(l_ry0, l_pdy) << l_pdy;
-      l_px1 = opj_uint_ceildivpow2(l_rx1, l_pdx) << l_pdx;
-      py1 = opj_uint_ceildivpow2(l_ry1, l_pdy) << l_pdy;
+      l_px0 = opj_pi_prc_index(l_rx0, l_pdx, l_ox);
+      l_py0 = opj_pi_prc_index(l_ry0, l_pdy, l_oy);
+      l_px1 = opj_pi_prc_index_end(l_rx1, l_pdx, l_ox);
+      py1 = opj_pi_prc_index_end(l_ry1, l_pdy, l_oy);
       l_pw = if l_rx0 == l_rx1 {
         0u32
       } else {
-        (l_px1.wrapping_sub(l_px0)) >> l_pdx
+        l_px1.wrapping_sub(l_px0)
       };
       l_ph = if l_ry0 == l_ry1 {
         0u32
       } else {
-        (py1.wrapping_sub(l_py0)) >> l_pdy
+        py1.wrapping_sub(l_py0)
       };
       l_product = l_pw.wrapping_mul(l_ph);
       /* update precision */
@@ -1364,6 +1398,7 @@ unsafe fn opj_get_all_encoding_parameters(
   /* take the largest value for dx_min and dy_min*/
   *p_dx_min = 0x7fffffff as OPJ_UINT32;
   *p_dy_min = 0x7fffffff as OPJ_UINT32;
+  let (l_ox, l_oy) = (*tcp).partition_origin();
   compno = 0 as OPJ_UINT32;
   while compno < (*p_image).numcomps {
     /* arithmetic variables to calculate*/
@@ -1445,19 +1480,19 @@ unsafe fn opj_get_all_encoding_parameters(
       l_ry0 = opj_uint_ceildivpow2(l_tcy0, l_level_no);
       l_rx1 = opj_uint_ceildivpow2(l_tcx1, l_level_no);
       l_ry1 = opj_uint_ceildivpow2(l_tcy1, l_level_no);
-      l_px0 = opj_uint_floordivpow2(l_rx0, l_pdx) << l_pdx;
-      l_py0 = opj_uint_floordivpow2(l_ry0, l_pdy) << l_pdy;
-      l_px1 = opj_uint_ceildivpow2(l_rx1, l_pdx) << l_pdx;
-      py1 = opj_uint_ceildivpow2(l_ry1, l_pdy) << l_pdy;
+      l_px0 = opj_pi_prc_index(l_rx0, l_pdx, l_ox);
+      l_py0 = opj_pi_prc_index(l_ry0, l_pdy, l_oy);
+      l_px1 = opj_pi_prc_index_end(l_rx1, l_pdx, l_ox);
+      py1 = opj_pi_prc_index_end(l_ry1, l_pdy, l_oy);
       l_pw = if l_rx0 == l_rx1 {
         0u32
       } else {
-        (l_px1.wrapping_sub(l_px0)) >> l_pdx
+        l_px1.wrapping_sub(l_px0)
       };
       l_ph = if l_ry0 == l_ry1 {
         0u32
       } else {
-        (py1.wrapping_sub(l_py0)) >> l_pdy
+        py1.wrapping_sub(l_py0)
       };
       if !lResolutionPtr.is_null() {
         let fresh2 = lResolutionPtr;
@@ -1525,6 +1560,7 @@ unsafe fn opj_pi_create(
   pino = 0 as OPJ_UINT32;
   while pino < l_poc_bound {
     (*l_current_pi).manager = *manager;
+    ((*l_current_pi).ox, (*l_current_pi).oy) = (*tcp).partition_origin();
     (*l_current_pi).comps = opj_calloc(
       (*image).numcomps as size_t,
       core::mem::size_of::<opj_pi_comp_t>(),
@@ -0,0 +1,1349 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! Owned codec handles whose decoding and encoding steps are checked at
+//! compile time.
+//!
+//! A [`Jp2Codec`] owns the codec, its stream and its image. Each step of
+//! the decoding or encoding consumes the handle and returns it in the next
+//! state, so e.g. decoding before reading the header doesn't compile.
+//! Dropping the handle in any state destroys the codec, the stream and the
+//! image: there is no `opj_destroy_codec` to forget or to call twice.
+//!
+//! ```compile_fail
+//! use openjp2::openjpeg::OPJ_CODEC_J2K;
+//! use openjp2::safe::Jp2Codec;
+//!
+//! let codec = Jp2Codec::decoder(OPJ_CODEC_J2K, Vec::new()).unwrap();
+//! // The header must be read first.
+//! codec.decode();
+//! ```
+//!
+//! Messages of the codec are sent to the `log` crate.
+
+use core::marker::PhantomData;
+#[cfg(feature = "file-io")]
+use std::io::Read;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use super::codec::Codec;
+use super::image::{opj_image, ComponentRef, OwnedImage};
+use super::math::opj_uint_ceildiv;
+use super::openjpeg::*;
+use super::stream::MemoryWriter;
+use super::types::Stream;
+
+/// First bytes of a codestream: the SOC marker followed by the SIZ marker.
+pub const J2K_CODESTREAM_MAGIC: [u8; 4] = [0xff, 0x4f, 0xff, 0x51];
+
+/// First bytes of a JP2 file: the JPEG 2000 signature box.
+pub const JP2_MAGIC: [u8; 12] = [
+  0x00, 0x00, 0x00, 0x0c, 0x6a, 0x50, 0x20, 0x20, 0x0d, 0x0a, 0x87, 0x0a,
+];
+
+/// Output of an encoder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum J2KFormat {
+  /// A bare codestream, e.g. to embed in a container supplying its own
+  /// wrapper like PDF or DICOM.
+  J2K,
+  /// A JP2 file: the codestream wrapped in JP2 boxes.
+  JP2,
+}
+
+impl From<J2KFormat> for CODEC_FORMAT {
+  fn from(format: J2KFormat) -> Self {
+    match format {
+      J2KFormat::J2K => OPJ_CODEC_J2K,
+      J2KFormat::JP2 => OPJ_CODEC_JP2,
+    }
+  }
+}
+
+impl J2KFormat {
+  /// Detect the format from the first bytes of a file.
+  pub fn from_magic(data: &[u8]) -> Option<Self> {
+    if data.starts_with(&JP2_MAGIC) {
+      Some(J2KFormat::JP2)
+    } else if data.starts_with(&J2K_CODESTREAM_MAGIC) {
+      Some(J2KFormat::J2K)
+    } else {
+      None
+    }
+  }
+
+  /// Detect the format from the extension of `path`, ignoring case, with
+  /// the extensions of `opj_decompress`.
+  pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<Self> {
+    let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+      "j2k" | "j2c" | "jpc" => Some(J2KFormat::J2K),
+      "jp2" => Some(J2KFormat::JP2),
+      _ => None,
+    }
+  }
+}
+
+/// Detect the codec to decode the file `path` from its extension, ignoring
+/// case, with the extensions of `opj_decompress`: `.j2k`, `.j2c` and `.jpc`
+/// are codestreams, `.jp2` JP2 files and `.jpt` JPT-streams.
+pub fn decoder_format_from_extension<P: AsRef<Path>>(path: P) -> Option<CODEC_FORMAT> {
+  let path = path.as_ref();
+  match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+    "jpt" => Some(OPJ_CODEC_JPT),
+    _ => J2KFormat::from_extension(path).map(CODEC_FORMAT::from),
+  }
+}
+
+/// Extension of a file that doesn't match its content, see
+/// `detect_format_from_file`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FormatMismatch {
+  /// Format given by the extension.
+  pub extension: J2KFormat,
+  /// Format of the content, the one used.
+  pub content: J2KFormat,
+}
+
+/// Detect the format of the file `path` from its first bytes.
+///
+/// A known extension that disagrees with the content is returned as a
+/// `FormatMismatch` instead of being printed, so that applications can
+/// report it their own way. Fails if the file can't be read or isn't a
+/// codestream or JP2 file.
+#[cfg(feature = "file-io")]
+pub fn detect_format_from_file<P: AsRef<Path>>(
+  path: P,
+) -> Result<(J2KFormat, Option<FormatMismatch>)> {
+  let path = path.as_ref();
+  let mut magic = Vec::with_capacity(JP2_MAGIC.len());
+  std::fs::File::open(path)?
+    .take(JP2_MAGIC.len() as u64)
+    .read_to_end(&mut magic)?;
+  let content = J2KFormat::from_magic(&magic).ok_or_else(|| {
+    Error::new(
+      ErrorKind::InvalidData,
+      format!("Not a JPEG 2000 codestream or JP2 file: {}", path.display()),
+    )
+  })?;
+  let mismatch = J2KFormat::from_extension(path)
+    .filter(|extension| *extension != content)
+    .map(|extension| FormatMismatch { extension, content });
+  Ok((content, mismatch))
+}
+
+/// Decoder whose header hasn't been read yet.
+pub struct Uninit;
+
+/// Decoder whose header was read: the image has its dimensions and
+/// components but no samples.
+pub struct HeaderRead;
+
+/// Decoder whose image was decoded.
+pub struct Decoded;
+
+/// Encoder set up with its parameters and image.
+pub struct Configured;
+
+/// Position of an output tile of `Jp2Codec::decode_grid`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridCoord {
+  /// Column of the tile in the grid.
+  pub col: u32,
+  /// Row of the tile in the grid.
+  pub row: u32,
+  /// Area of the tile on the reference grid, smaller than the grid size at
+  /// the right and bottom edges of the image.
+  pub x0: u32,
+  pub y0: u32,
+  pub x1: u32,
+  pub y1: u32,
+}
+
+/// Iterator over the tiles of a grid laid over a decoded image, row by row,
+/// see `Jp2Codec::decode_grid`.
+pub struct GridTiles {
+  image: Box<opj_image>,
+  tile_w: u32,
+  tile_h: u32,
+  cols: u64,
+  count: u64,
+  next: u64,
+}
+
+impl GridTiles {
+  fn new(image: Box<opj_image>, tile_w: u32, tile_h: u32) -> Self {
+    let cols = ((image.x1 - image.x0) as u64).div_ceil(tile_w as u64);
+    let rows = ((image.y1 - image.y0) as u64).div_ceil(tile_h as u64);
+    Self {
+      image,
+      tile_w,
+      tile_h,
+      cols,
+      count: cols * rows,
+      next: 0,
+    }
+  }
+
+  /// Number of columns and rows of the grid.
+  pub fn grid_size(&self) -> (u32, u32) {
+    let rows = self.count.checked_div(self.cols).unwrap_or(0);
+    (self.cols as u32, rows as u32)
+  }
+}
+
+impl Iterator for GridTiles {
+  type Item = Result<(GridCoord, Box<opj_image>)>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.next >= self.count {
+      return None;
+    }
+    let (col, row) = (self.next % self.cols, self.next / self.cols);
+    self.next += 1;
+    let image = &self.image;
+    let x0 = image.x0 as u64 + col * self.tile_w as u64;
+    let y0 = image.y0 as u64 + row * self.tile_h as u64;
+    let coord = GridCoord {
+      col: col as u32,
+      row: row as u32,
+      x0: x0 as u32,
+      y0: y0 as u32,
+      x1: (x0 + self.tile_w as u64).min(image.x1 as u64) as u32,
+      y1: (y0 + self.tile_h as u64).min(image.y1 as u64) as u32,
+    };
+    let tile = image
+      .crop(coord.x0, coord.y0, coord.x1, coord.y1)
+      .ok_or_else(|| {
+        Error::other(format!(
+          "Failed to copy the grid tile {},{}",
+          coord.col, coord.row
+        ))
+      });
+    Some(tile.map(|tile| (coord, tile)))
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let left = (self.count - self.next) as usize;
+    (left, Some(left))
+  }
+}
+
+/// Encoder whose tiles are written one at a time, see
+/// `Jp2Codec::start_tiles`.
+pub struct TileEncoding;
+
+/// Encoder whose image was encoded.
+pub struct Encoded;
+
+/// Codec handle in the state `S`.
+pub struct Jp2Codec<S> {
+  /// Boxed, as the tile coder created by `read_header()` keeps pointers into
+  /// the codec while the handle moves from state to state.
+  codec: Box<Codec>,
+  stream: Stream,
+  image: Box<opj_image>,
+  output: Option<MemoryWriter>,
+  encoder: Option<Box<EncoderSetup>>,
+  /// Index of the next tile to write in the `TileEncoding` state.
+  next_tile: u32,
+  state: PhantomData<S>,
+}
+
+/// What an encoder was set up with, to set up the codec again for another
+/// format.
+struct EncoderSetup {
+  format: CODEC_FORMAT,
+  parameters: opj_cparameters_t,
+  extra_options: Vec<String>,
+  target_size: Option<u64>,
+  max_error: Option<u32>,
+  step_shift: u32,
+  auto_greyscale: bool,
+  brands: Option<([u8; 4], Vec<[u8; 4]>)>,
+  colorspace_unknown: bool,
+  ipr: Option<Vec<u8>>,
+  layout: CodestreamLayout,
+}
+
+/// Encoder whose image samples are borrowed from the caller. They are
+/// detached from the codec and the image before both are dropped, even on
+/// a panic, so the library never frees them.
+struct BorrowedSamples {
+  codec: Codec,
+  image: Box<opj_image>,
+}
+
+impl Drop for BorrowedSamples {
+  fn drop(&mut self) {
+    self.codec.detach_image_data();
+    for comp in self.image.comps_mut().unwrap_or_default() {
+      comp.data = core::ptr::null_mut();
+    }
+  }
+}
+
+/// Maximum number of encoding passes to reach a target size.
+const TARGET_SIZE_PASSES: usize = 4;
+
+/// Maximum number of near-lossless encoding passes, before falling back to
+/// the reversible wavelet.
+const NEAR_LOSSLESS_PASSES: u32 = 4;
+
+/// Largest difference between the samples of `source` and the samples
+/// decoded from `data`, `None` if they can't be decoded or compared.
+fn decoded_error(format: CODEC_FORMAT, data: Vec<u8>, source: &opj_image) -> Option<u32> {
+  let decoded = Jp2Codec::decoder(format, data)?
+    .read_header()?
+    .decode()?
+    .into_image();
+  let (comps, decoded_comps) = (source.comps()?, decoded.comps()?);
+  if comps.len() != decoded_comps.len() {
+    return None;
+  }
+  let mut worst = 0;
+  for (comp, decoded_comp) in comps.iter().zip(decoded_comps) {
+    let (samples, decoded_samples) = (comp.data()?, decoded_comp.data()?);
+    if samples.len() != decoded_samples.len() {
+      return None;
+    }
+    for (a, b) in samples.iter().zip(decoded_samples) {
+      worst = worst.max(a.abs_diff(*b));
+    }
+  }
+  Some(worst)
+}
+
+/// Size in bytes of the samples of `image`, computed like
+/// `opj_j2k_update_rates` from the first component.
+fn uncompressed_size(image: &opj_image) -> f64 {
+  let comp = match image.comps() {
+    Some([comp, ..]) => comp,
+    _ => return 0.0,
+  };
+  let size_pixel = image.numcomps as f64 * comp.prec as f64;
+  let area = (image.x1 - image.x0) as f64 * (image.y1 - image.y0) as f64;
+  size_pixel * area / (8 * comp.dx * comp.dy) as f64
+}
+
+impl EncoderSetup {
+  fn new_codec(&self, image: &mut opj_image) -> Option<Codec> {
+    let mut codec = Codec::new_encoder(self.format)?;
+    codec.use_log_crate();
+    let mut parameters = self.parameters;
+    if codec.setup_encoder(&mut parameters, image) == 0 {
+      return None;
+    }
+    let options: Vec<&str> = self.extra_options.iter().map(|o| o.as_str()).collect();
+    if !options.is_empty() && !codec.encoder_set_extra_options(&options) {
+      return None;
+    }
+    if self.layout != CodestreamLayout::TileFirst && !codec.set_codestream_layout(self.layout) {
+      return None;
+    }
+    if self.format == OPJ_CODEC_JP2 {
+      if let Some((brand, compatible)) = &self.brands {
+        if !codec.set_brands(*brand, compatible.clone()) {
+          return None;
+        }
+      }
+      if self.colorspace_unknown && !codec.set_colorspace_unknown(true) {
+        return None;
+      }
+      if self.ipr.is_some() && !codec.set_ipr(self.ipr.clone()) {
+        return None;
+      }
+    }
+    if let Some(max_error) = self.max_error {
+      if !codec.set_near_lossless(max_error, self.step_shift) {
+        return None;
+      }
+    }
+    Some(codec)
+  }
+}
+
+impl<S> Jp2Codec<S> {
+  /// Index of the tile containing the point `(x, y)` of the reference grid,
+  /// once the header was read, see `Codec::tile_at`.
+  pub fn tile_at(&self, x: u32, y: u32) -> Option<u32> {
+    self.codec.tile_at(x, y)
+  }
+
+  /// Area `(x0, y0, x1, y1)` of a tile on the reference grid, clamped to
+  /// the image at the right and bottom edges.
+  pub fn tile_bounds(&self, index: u32) -> Option<(u32, u32, u32, u32)> {
+    self.codec.tile_bounds(index)
+  }
+
+  fn into_state<T>(self) -> Jp2Codec<T> {
+    Jp2Codec {
+      codec: self.codec,
+      stream: self.stream,
+      image: self.image,
+      output: self.output,
+      encoder: self.encoder,
+      next_tile: self.next_tile,
+      state: PhantomData,
+    }
+  }
+}
+
+impl Jp2Codec<Uninit> {
+  /// Create a decoder of the `format` codestream or file in `data`, with
+  /// the default decoding parameters.
+  ///
+  /// Returns `None` for an unknown format.
+  ///
+  /// # Example
+  ///
+  /// ```no_run
+  /// use openjp2::openjpeg::OPJ_CODEC_JP2;
+  /// use openjp2::safe::Jp2Codec;
+  ///
+  /// let data = std::fs::read("image.jp2").unwrap();
+  /// let codec = Jp2Codec::decoder(OPJ_CODEC_JP2, data).expect("decoder");
+  /// let codec = codec.read_header().expect("valid header");
+  /// println!("{}x{}", codec.header().x1, codec.header().y1);
+  /// let image = codec.decode().expect("decoded image").into_image();
+  /// ```
+  pub fn decoder(format: CODEC_FORMAT, data: Vec<u8>) -> Option<Self> {
+    Self::decoder_with_parameters(format, data, &mut opj_dparameters_t::default())
+  }
+
+  /// Create a decoder of the file `path`, with the default decoding
+  /// parameters.
+  ///
+  /// Codestreams and JP2 files are detected from their content, whatever
+  /// their extension, see `detect_format_from_file`. JPT-streams have no
+  /// signature and are recognized by their `.jpt` extension. Fails if the
+  /// file can't be read or has none of these formats.
+  #[cfg(feature = "file-io")]
+  pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let path = path.as_ref();
+    let data = std::fs::read(path)?;
+    let format = match J2KFormat::from_magic(&data) {
+      Some(format) => format.into(),
+      None if decoder_format_from_extension(path) == Some(OPJ_CODEC_JPT) => OPJ_CODEC_JPT,
+      None => {
+        return Err(Error::new(
+          ErrorKind::InvalidData,
+          format!(
+            "Not a JPEG 2000 codestream, JP2 file or JPT-stream: {}",
+            path.display()
+          ),
+        ))
+      }
+    };
+    Self::decoder(format, data).ok_or_else(|| Error::other("Failed to create the decoder"))
+  }
+
+  /// Create a decoder of the `format` codestream or file in `data`, with
+  /// the given decoding parameters.
+  pub fn decoder_with_parameters(
+    format: CODEC_FORMAT,
+    data: Vec<u8>,
+    parameters: &mut opj_dparameters_t,
+  ) -> Option<Self> {
+    let mut codec = Codec::new_decoder(format)?;
+    codec.use_log_crate();
+    if codec.setup_decoder(parameters) == 0 {
+      return None;
+    }
+    Some(Self {
+      codec: Box::new(codec),
+      stream: Stream::new_memory(data),
+      image: opj_image::new(),
+      output: None,
+      encoder: None,
+      next_tile: 0,
+      state: PhantomData,
+    })
+  }
+
+  /// Check codestreams claiming a digital cinema or IMF profile against it
+  /// when reading the header, see `opj_decoder_set_validate_profile`. A
+  /// violation makes `read_header()` fail.
+  pub fn set_validate_profile(&mut self, validate: bool) -> bool {
+    self.codec.decoder_set_validate_profile(validate)
+  }
+
+  /// Enable or disable the correction of `TNsot` values one too small, see
+  /// `opj_decoder_set_tile_parts_correction`.
+  pub fn set_tile_parts_correction(&mut self, enable: bool) -> bool {
+    self.codec.decoder_set_tile_parts_correction(enable)
+  }
+
+  /// Return the components as they are coded, without the multiple
+  /// component transform and the DC level shift, see
+  /// `opj_decoder_set_raw_components`.
+  pub fn set_raw_components(&mut self, raw: bool) -> bool {
+    self.codec.decoder_set_raw_components(raw)
+  }
+
+  /// Read the main header, returns `None` if it is invalid.
+  pub fn read_header(mut self) -> Option<Jp2Codec<HeaderRead>> {
+    let mut p_image: *mut opj_image = std::ptr::null_mut();
+    let ret = self.codec.read_header(&mut self.stream, &mut p_image);
+    if p_image.is_null() {
+      return None;
+    }
+    self.image = unsafe { Box::from_raw(p_image) };
+    if ret == 0 {
+      return None;
+    }
+    Some(self.into_state())
+  }
+}
+
+impl Jp2Codec<HeaderRead> {
+  /// The image described by the header, without samples.
+  pub fn header(&self) -> &opj_image {
+    &self.image
+  }
+
+  /// Restrict decoding to an area of the reference grid, see
+  /// `opj_set_decode_area`.
+  pub fn set_decode_area(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) -> bool {
+    self.codec.set_decode_area(&mut self.image, x0, y0, x1, y1) != 0
+  }
+
+  /// Decode the band of quality layers `[start, end)` on top of the layers
+  /// below it, see `opj_decoder_set_layer_range`.
+  pub fn set_layer_range(&mut self, start: u32, end: u32) -> bool {
+    self.codec.set_layer_range(start, end)
+  }
+
+  /// Optional decoding paths the codestream needs, see
+  /// `opj_get_required_features`.
+  pub fn requires_features(&self) -> FeatureSet {
+    self.codec.requires_features()
+  }
+
+  /// Decode the image, returns `None` on error.
+  pub fn decode(self) -> Option<Jp2Codec<Decoded>> {
+    self.try_decode().ok()
+  }
+
+  /// Decode the image. Fails up front with `ErrorKind::Unsupported` if the
+  /// codestream needs a feature this build doesn't have, e.g. the JPWL
+  /// markers.
+  pub fn try_decode(mut self) -> Result<Jp2Codec<Decoded>> {
+    let missing = self.requires_features() - FeatureSet::supported();
+    if !missing.is_empty() {
+      return Err(Error::new(
+        ErrorKind::Unsupported,
+        format!("The codestream needs unsupported features: {:?}", missing),
+      ));
+    }
+    if self.codec.decode(&mut self.stream, &mut self.image) == 0
+      || self.codec.end_decompress(&mut self.stream) == 0
+    {
+      return Err(Error::new(
+        ErrorKind::InvalidData,
+        "Failed to decode the image",
+      ));
+    }
+    Ok(self.into_state())
+  }
+
+  /// Decode the image and cut it into tiles of `tile_w` x `tile_h` on the
+  /// reference grid, whatever the tiling of the codestream, e.g. for
+  /// display. The grid starts at the origin of the decoded area.
+  ///
+  /// Each codestream tile is decoded once, even when it spans several grid
+  /// tiles: the grid tiles are copied from the decoded image as they are
+  /// iterated.
+  ///
+  /// ```no_run
+  /// use openjp2::openjpeg::OPJ_CODEC_JP2;
+  /// use openjp2::safe::Jp2Codec;
+  ///
+  /// # fn display() -> std::io::Result<()> {
+  /// let data = std::fs::read("image.jp2")?;
+  /// let codec = Jp2Codec::decoder(OPJ_CODEC_JP2, data)
+  ///   .and_then(|codec| codec.read_header())
+  ///   .expect("valid header");
+  /// for tile in codec.decode_grid(256, 256)? {
+  ///   let (coord, image) = tile?;
+  ///   println!("tile {},{}: {} components", coord.col, coord.row, image.numcomps);
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn decode_grid(self, tile_w: u32, tile_h: u32) -> Result<GridTiles> {
+    if tile_w == 0 || tile_h == 0 {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("Invalid grid tile size {}x{}", tile_w, tile_h),
+      ));
+    }
+    let image = self.try_decode()?.into_image();
+    Ok(GridTiles::new(image, tile_w, tile_h))
+  }
+}
+
+impl Jp2Codec<Decoded> {
+  /// The decoded image.
+  pub fn image(&self) -> &opj_image {
+    &self.image
+  }
+
+  /// Take the decoded image, destroying the codec.
+  pub fn into_image(self) -> Box<opj_image> {
+    self.image
+  }
+
+  /// Take the decoded image with its samples copied in `Vec`s, destroying
+  /// the codec and the buffers of the library.
+  pub fn into_image_owned(self) -> OwnedImage {
+    self.image.into_owned()
+  }
+}
+
+impl Jp2Codec<Configured> {
+  /// Create an encoder of `image` to the `format` codestream or file, see
+  /// also `set_format()`.
+  ///
+  /// Returns `None` for an unknown format or invalid parameters.
+  ///
+  /// # Example
+  ///
+  /// ```no_run
+  /// use openjp2::image::opj_image;
+  /// use openjp2::openjpeg::{opj_cparameters_t, OPJ_CODEC_J2K};
+  /// use openjp2::safe::Jp2Codec;
+  ///
+  /// fn encode(image: Box<opj_image>) -> Option<Vec<u8>> {
+  ///   let mut params = opj_cparameters_t::default();
+  ///   params.numresolution = 3;
+  ///   let mut codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image)?;
+  ///   codec.set_extra_options(&["PLT=YES"]);
+  ///   Some(codec.encode()?.into_bytes())
+  /// }
+  /// ```
+  pub fn encoder(
+    format: CODEC_FORMAT,
+    parameters: &mut opj_cparameters_t,
+    mut image: Box<opj_image>,
+  ) -> Option<Self> {
+    let encoder = Box::new(EncoderSetup {
+      format,
+      parameters: *parameters,
+      extra_options: Vec::new(),
+      target_size: None,
+      max_error: None,
+      step_shift: 0,
+      auto_greyscale: false,
+      brands: None,
+      colorspace_unknown: false,
+      ipr: None,
+      layout: CodestreamLayout::TileFirst,
+    });
+    let codec = encoder.new_codec(&mut image)?;
+    let output = MemoryWriter::default();
+    Some(Self {
+      codec: Box::new(codec),
+      stream: Stream::new_memory_writer(output.clone()),
+      image,
+      output: Some(output),
+      encoder: Some(encoder),
+      next_tile: 0,
+      state: PhantomData,
+    })
+  }
+
+  /// Encode samples borrowed from the caller, without copying them into an
+  /// `opj_image`. They stay borrowed until the encoded bytes are returned.
+  ///
+  /// The samples are overwritten: when the image is a single tile, the DC
+  /// level shift, colour and wavelet transforms are done in place in the
+  /// caller's buffers. Their content is unspecified afterwards.
+  ///
+  /// One and three component images are tagged as greyscale and sRGB.
+  ///
+  /// ```no_run
+  /// # use openjp2::openjpeg::{opj_cparameters_t, OPJ_CODEC_J2K};
+  /// use openjp2::image::ComponentRef;
+  /// use openjp2::safe::Jp2Codec;
+  ///
+  /// # fn encode(grey: &mut [i32]) -> std::io::Result<Vec<u8>> {
+  /// let mut params = opj_cparameters_t::default();
+  /// let component = ComponentRef {
+  ///   data: grey,
+  ///   prec: 8,
+  ///   sgnd: false,
+  ///   dx: 1,
+  ///   dy: 1,
+  /// };
+  /// Jp2Codec::encode_borrowed(OPJ_CODEC_J2K, &mut params, 64, 64, &mut [component])
+  /// # }
+  /// ```
+  pub fn encode_borrowed(
+    format: CODEC_FORMAT,
+    parameters: &mut opj_cparameters_t,
+    width: u32,
+    height: u32,
+    components: &mut [ComponentRef<'_>],
+  ) -> Result<Vec<u8>> {
+    if width == 0 || height == 0 || components.is_empty() {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        "The image has no samples",
+      ));
+    }
+    let mut image = opj_image::new();
+    image.color_space = match components.len() {
+      1 => OPJ_CLRSPC_GRAY,
+      3 => OPJ_CLRSPC_SRGB,
+      _ => OPJ_CLRSPC_UNKNOWN,
+    };
+    image.x1 = width;
+    image.y1 = height;
+    if !image.alloc_comps(components.len() as u32, true) {
+      return Err(Error::new(
+        ErrorKind::OutOfMemory,
+        "Failed to allocate the image components",
+      ));
+    }
+    let comps = image.comps_mut().unwrap_or_default();
+    for (compno, (comp, component)) in comps.iter_mut().zip(components.iter()).enumerate() {
+      if component.dx == 0 || component.dy == 0 || component.prec == 0 || component.prec > 31 {
+        return Err(Error::new(
+          ErrorKind::InvalidInput,
+          format!("Invalid subsampling or precision of component {}", compno),
+        ));
+      }
+      comp.dx = component.dx;
+      comp.dy = component.dy;
+      comp.w = opj_uint_ceildiv(width, component.dx);
+      comp.h = opj_uint_ceildiv(height, component.dy);
+      comp.prec = component.prec;
+      comp.sgnd = component.sgnd as u32;
+      if component.data.len() as u64 != comp.w as u64 * comp.h as u64 {
+        return Err(Error::new(
+          ErrorKind::InvalidInput,
+          format!(
+            "Component {} has {} samples instead of {}x{}",
+            compno,
+            component.data.len(),
+            comp.w,
+            comp.h
+          ),
+        ));
+      }
+    }
+
+    let mut codec =
+      Codec::new_encoder(format).ok_or_else(|| Error::other("Failed to create the encoder"))?;
+    codec.use_log_crate();
+    if codec.setup_encoder(parameters, &mut image) == 0 {
+      return Err(Error::other("Failed to set up the encoder"));
+    }
+    let mut encoder = BorrowedSamples { codec, image };
+    let comps = encoder.image.comps_mut().unwrap_or_default();
+    for (comp, component) in comps.iter_mut().zip(components.iter_mut()) {
+      comp.data = component.data.as_mut_ptr();
+    }
+    let output = MemoryWriter::default();
+    let mut stream = Stream::new_memory_writer(output.clone());
+    let codec = &mut encoder.codec;
+    if codec.start_compress(&mut encoder.image, &mut stream) == 0
+      || codec.encode(&mut stream) == 0
+      || codec.end_compress(&mut stream) == 0
+    {
+      return Err(Error::other("Failed to encode the image"));
+    }
+    stream.flush()?;
+    drop(stream);
+    drop(encoder);
+    Ok(output.take())
+  }
+
+  /// Choose between a bare codestream and a JP2 file. The codec is set up
+  /// again with the same parameters and extra options.
+  ///
+  /// ```no_run
+  /// # use openjp2::image::opj_image;
+  /// # use openjp2::openjpeg::{opj_cparameters_t, OPJ_CODEC_JP2};
+  /// use openjp2::safe::{J2KFormat, Jp2Codec, J2K_CODESTREAM_MAGIC};
+  ///
+  /// # fn encode(image: Box<opj_image>) -> Option<()> {
+  /// let mut params = opj_cparameters_t::default();
+  /// let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image)?;
+  /// codec.set_format(J2KFormat::J2K);
+  /// let bytes = codec.encode()?.into_bytes();
+  /// assert!(bytes.starts_with(&J2K_CODESTREAM_MAGIC));
+  /// # Some(())
+  /// # }
+  /// ```
+  pub fn set_format(&mut self, format: J2KFormat) -> bool {
+    let parameters = match &self.encoder {
+      Some(encoder) => encoder.parameters,
+      None => return false,
+    };
+    self.setup_again(format.into(), parameters)
+  }
+
+  /// Encode a single quality layer whose size approaches `bytes`.
+  ///
+  /// The compression ratio is estimated from the size of the samples, like
+  /// the rates of `opj_cparameters_t::tcp_rates`.  The size is approximate:
+  /// when the output overshoots the target, the image is encoded again with
+  /// a higher ratio, up to 4 times.  A target larger than the samples gives
+  /// a lossless encoding.
+  ///
+  /// ```no_run
+  /// # use openjp2::image::opj_image;
+  /// # use openjp2::openjpeg::{opj_cparameters_t, OPJ_CODEC_JP2};
+  /// use openjp2::safe::Jp2Codec;
+  ///
+  /// # fn encode(image: Box<opj_image>) -> Option<()> {
+  /// let mut params = opj_cparameters_t::default();
+  /// let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image)?;
+  /// codec.set_target_size(50 * 1024);
+  /// let bytes = codec.encode()?.into_bytes();
+  /// # Some(())
+  /// # }
+  /// ```
+  pub fn set_target_size(&mut self, bytes: u64) -> bool {
+    let (format, mut parameters) = match &self.encoder {
+      Some(encoder) => (encoder.format, encoder.parameters),
+      None => return false,
+    };
+    if bytes == 0 || self.encoder.as_ref().is_some_and(|e| e.max_error.is_some()) {
+      return false;
+    }
+    let ratio = uncompressed_size(&self.image) / bytes as f64;
+    parameters.tcp_numlayers = 1;
+    parameters.tcp_rates[0] = if ratio > 1.0 { ratio as f32 } else { 0.0 };
+    parameters.cp_disto_alloc = 1;
+    parameters.cp_fixed_quality = 0;
+    parameters.cp_fixed_alloc = 0;
+    parameters.max_cs_size = 0;
+    if !self.setup_again(format, parameters) {
+      return false;
+    }
+    if let Some(encoder) = &mut self.encoder {
+      encoder.target_size = Some(bytes);
+    }
+    true
+  }
+
+  /// Encode with the irreversible wavelet, keeping every decoded sample
+  /// within `max_error` of the source sample.
+  ///
+  /// The quantization step sizes are derived from `max_error`, see
+  /// `opj_encoder_set_near_lossless`, which only bounds the error
+  /// statistically. `encode()` decodes the image to check the bound, and
+  /// encodes it again with halved step sizes when a sample exceeds it, up to
+  /// 4 times. If the bound still doesn't hold, or the coded step sizes
+  /// don't fit in the tile buffers, e.g. for noise, the image is encoded
+  /// losslessly with the reversible wavelet.
+  ///
+  /// Fails if the last quality layer isn't lossless, with a target size,
+  /// or if `max_error` is 0 or at least half the range of a component.
+  ///
+  /// ```no_run
+  /// # use openjp2::image::opj_image;
+  /// # use openjp2::openjpeg::{opj_cparameters_t, OPJ_CODEC_JP2};
+  /// use openjp2::safe::Jp2Codec;
+  ///
+  /// # fn encode(image: Box<opj_image>) -> Option<()> {
+  /// let mut params = opj_cparameters_t::default();
+  /// let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image)?;
+  /// codec.set_near_lossless(2);
+  /// let bytes = codec.encode()?.into_bytes();
+  /// # Some(())
+  /// # }
+  /// ```
+  pub fn set_near_lossless(&mut self, max_error: u32) -> bool {
+    let encoder = match &mut self.encoder {
+      Some(encoder) => encoder,
+      None => return false,
+    };
+    let parameters = &encoder.parameters;
+    let last = parameters.tcp_numlayers.max(1) as usize - 1;
+    if encoder.target_size.is_some()
+      || parameters.tcp_rates[last] > 0.0
+      || parameters.tcp_distoratio[last] > 0.0
+    {
+      return false;
+    }
+    if !self.codec.set_near_lossless(max_error, 0) {
+      return false;
+    }
+    encoder.max_error = Some(max_error);
+    encoder.step_shift = 0;
+    true
+  }
+
+  /// Set the brand and the compatibility list of the file type box of JP2
+  /// files, the default being the `jp2 ` brand alone. Returns `false` if
+  /// the brand isn't in the list.
+  ///
+  /// The brands are kept when the format changes, and only written to JP2
+  /// files.
+  ///
+  /// ```no_run
+  /// # use openjp2::image::opj_image;
+  /// # use openjp2::openjpeg::{opj_cparameters_t, OPJ_CODEC_JP2};
+  /// use openjp2::safe::Jp2Codec;
+  ///
+  /// # fn encode(image: Box<opj_image>) -> Option<()> {
+  /// let mut params = opj_cparameters_t::default();
+  /// let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image)?;
+  /// codec.set_brands(*b"jpx ", vec![*b"jpx ", *b"jp2 "]);
+  /// let bytes = codec.encode()?.into_bytes();
+  /// # Some(())
+  /// # }
+  /// ```
+  pub fn set_brands(&mut self, brand: [u8; 4], compatible: Vec<[u8; 4]>) -> bool {
+    let encoder = match &mut self.encoder {
+      Some(encoder) => encoder,
+      None => return false,
+    };
+    if !compatible.contains(&brand) {
+      return false;
+    }
+    if encoder.format == OPJ_CODEC_JP2 && !self.codec.set_brands(brand, compatible.clone()) {
+      return false;
+    }
+    encoder.brands = Some((brand, compatible));
+    true
+  }
+
+  /// Set the colour specification unknown flag of JP2 files, telling
+  /// readers not to trust the colour space of the `colr` box. Decoders then
+  /// report `OPJ_CLRSPC_UNKNOWN`.
+  pub fn set_colorspace_unknown(&mut self, unknown: bool) -> bool {
+    let encoder = match &mut self.encoder {
+      Some(encoder) => encoder,
+      None => return false,
+    };
+    if encoder.format == OPJ_CODEC_JP2 && !self.codec.set_colorspace_unknown(unknown) {
+      return false;
+    }
+    encoder.colorspace_unknown = unknown;
+    true
+  }
+
+  /// Write an intellectual property box with the given contents to JP2
+  /// files and set their IPR flag, or remove it with `None`.
+  pub fn set_ipr(&mut self, ipr: Option<Vec<u8>>) -> bool {
+    let encoder = match &mut self.encoder {
+      Some(encoder) => encoder,
+      None => return false,
+    };
+    if encoder.format == OPJ_CODEC_JP2 && !self.codec.set_ipr(ipr.clone()) {
+      return false;
+    }
+    encoder.ipr = ipr;
+    true
+  }
+
+  /// Set the order of the tile-parts, see
+  /// `opj_encoder_set_codestream_layout`. With `CodestreamLayout::LayerFirst`
+  /// a prefix of the output holds the lower quality layers.
+  pub fn set_codestream_layout(&mut self, layout: CodestreamLayout) -> bool {
+    let encoder = match &mut self.encoder {
+      Some(encoder) => encoder,
+      None => return false,
+    };
+    if !self.codec.set_codestream_layout(layout) {
+      return false;
+    }
+    encoder.layout = layout;
+    true
+  }
+
+  /// Encode a grey image stored as RGB, whose R, G and B components are
+  /// equal, as a single greyscale component. This shrinks e.g. greyscale
+  /// documents scanned as RGB.
+  ///
+  /// The check is done by `encode()` and stops at the first differing
+  /// sample. When it succeeds, the encoded image has a single component in
+  /// the `OPJ_CLRSPC_GRAY` colour space, and the multiple component
+  /// transform is disabled.
+  pub fn set_auto_greyscale(&mut self, enable: bool) -> bool {
+    match &mut self.encoder {
+      Some(encoder) => {
+        encoder.auto_greyscale = enable;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Collapse the image to greyscale if enabled and possible, and set the
+  /// codec up again for the single component.
+  fn apply_auto_greyscale(&mut self) -> Option<()> {
+    let encoder = self.encoder.as_ref()?;
+    if !encoder.auto_greyscale || !self.image.collapse_grey_rgb() {
+      return Some(());
+    }
+    let (format, mut parameters) = (encoder.format, encoder.parameters);
+    parameters.tcp_mct = 0;
+    if !self.setup_again(format, parameters) {
+      return None;
+    }
+    Some(())
+  }
+
+  /// Set the codec up again with new parameters and the same extra options.
+  fn setup_again(&mut self, format: CODEC_FORMAT, parameters: opj_cparameters_t) -> bool {
+    let encoder = match &mut self.encoder {
+      Some(encoder) => encoder,
+      None => return false,
+    };
+    let setup = EncoderSetup {
+      format,
+      parameters,
+      extra_options: encoder.extra_options.clone(),
+      target_size: encoder.target_size,
+      max_error: encoder.max_error,
+      step_shift: encoder.step_shift,
+      auto_greyscale: encoder.auto_greyscale,
+      brands: encoder.brands.clone(),
+      colorspace_unknown: encoder.colorspace_unknown,
+      ipr: encoder.ipr.clone(),
+      layout: encoder.layout,
+    };
+    match setup.new_codec(&mut self.image) {
+      Some(codec) => {
+        *self.codec = codec;
+        **encoder = setup;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Set extra encoding options, see `opj_encoder_set_extra_options`.
+  pub fn set_extra_options(&mut self, options: &[&str]) -> bool {
+    if !self.codec.encoder_set_extra_options(options) {
+      return false;
+    }
+    if let Some(encoder) = &mut self.encoder {
+      encoder
+        .extra_options
+        .extend(options.iter().map(|o| o.to_string()));
+    }
+    true
+  }
+
+  /// Encode the image, returns `None` on error.
+  pub fn encode(mut self) -> Option<Jp2Codec<Encoded>> {
+    self.apply_auto_greyscale()?;
+    if let Some(max_error) = self.encoder.as_ref().and_then(|e| e.max_error) {
+      return self.encode_near_lossless(max_error);
+    }
+    let target = match self.encoder.as_ref().and_then(|e| e.target_size) {
+      Some(target) => target,
+      None => {
+        self.encode_once()?;
+        return Some(self.into_state());
+      }
+    };
+    // Encoding moves the samples out of the image, each pass encodes a copy
+    // of the original.
+    let source = self.image.clone();
+    self.encode_once()?;
+    for _ in 1..TARGET_SIZE_PASSES {
+      let size = self.output.as_ref().map_or(0, |o| o.len()) as u64;
+      if size <= target {
+        break;
+      }
+      // Raise the ratio in proportion to the overshoot.
+      let encoder = self.encoder.as_ref()?;
+      let (format, mut parameters) = (encoder.format, encoder.parameters);
+      let ratio = parameters.tcp_rates[0].max(1.0);
+      parameters.tcp_rates[0] = ratio * size as f32 / target as f32;
+      self.encode_again(&source, format, parameters)?;
+    }
+    Some(self.into_state())
+  }
+
+  /// Start encoding the image one tile at a time, with `write_tile()`.
+  /// The samples of the image aren't used, only its header.
+  ///
+  /// ```no_run
+  /// # use openjp2::image::opj_image;
+  /// # use openjp2::openjpeg::{opj_cparameters_t, OPJ_CODEC_J2K};
+  /// use openjp2::safe::Jp2Codec;
+  ///
+  /// # fn encode(image: Box<opj_image>, tiles: &[Vec<i32>]) -> Option<()> {
+  /// let mut params = opj_cparameters_t::default();
+  /// params.tile_size_on = 1;
+  /// params.cp_tdx = 256;
+  /// params.cp_tdy = 256;
+  /// let codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image)?;
+  /// let mut codec = codec.start_tiles()?;
+  /// for (index, samples) in tiles.iter().enumerate() {
+  ///   codec.write_tile(index as u32, samples).ok()?;
+  /// }
+  /// let _bytes = codec.finish_tiles()?.into_bytes();
+  /// # Some(())
+  /// # }
+  /// ```
+  pub fn start_tiles(mut self) -> Option<Jp2Codec<TileEncoding>> {
+    if self.codec.start_compress(&mut self.image, &mut self.stream) == 0 {
+      return None;
+    }
+    self.next_tile = 0;
+    Some(self.into_state())
+  }
+
+  /// Encode the image again until the decoded samples are within
+  /// `max_error` of the source, see `set_near_lossless()`.
+  fn encode_near_lossless(mut self, max_error: u32) -> Option<Jp2Codec<Encoded>> {
+    // Encoding moves the samples out of the image, each pass encodes a copy
+    // of the original.
+    let source = self.image.clone();
+    for step_shift in 0..NEAR_LOSSLESS_PASSES {
+      let (format, parameters) = {
+        let encoder = self.encoder.as_mut()?;
+        encoder.step_shift = step_shift;
+        (encoder.format, encoder.parameters)
+      };
+      let encoded = if step_shift > 0 {
+        self.encode_again(&source, format, parameters)
+      } else {
+        self.encode_once()
+      };
+      // Small step sizes can overflow the tile buffer sized for the
+      // samples, e.g. with noise: stop tightening them.
+      if encoded.is_none() {
+        break;
+      }
+      let data = self.output.as_ref()?.to_vec();
+      if decoded_error(format, data, &source).is_some_and(|error| error <= max_error) {
+        return Some(self.into_state());
+      }
+    }
+    // The last quality layer is lossless, so is the reversible wavelet.
+    let (format, mut parameters) = {
+      let encoder = self.encoder.as_mut()?;
+      encoder.max_error = None;
+      (encoder.format, encoder.parameters)
+    };
+    parameters.irreversible = 0;
+    self.encode_again(&source, format, parameters)?;
+    Some(self.into_state())
+  }
+
+  /// Set the codec up again and encode a copy of `source` to a new output.
+  fn encode_again(
+    &mut self,
+    source: &opj_image,
+    format: CODEC_FORMAT,
+    parameters: opj_cparameters_t,
+  ) -> Option<()> {
+    *self.image = source.clone();
+    if !self.setup_again(format, parameters) {
+      return None;
+    }
+    let output = MemoryWriter::default();
+    self.stream = Stream::new_memory_writer(output.clone());
+    self.output = Some(output);
+    self.encode_once()
+  }
+
+  fn encode_once(&mut self) -> Option<()> {
+    if self.codec.start_compress(&mut self.image, &mut self.stream) == 0
+      || self.codec.encode(&mut self.stream) == 0
+      || self.codec.end_compress(&mut self.stream) == 0
+      || self.stream.flush().is_err()
+    {
+      return None;
+    }
+    Some(())
+  }
+}
+
+impl Jp2Codec<TileEncoding> {
+  /// Area of the tile `index` on the reference grid, `None` if there is no
+  /// such tile.
+  fn tile_area(&self, index: u32) -> Option<(u32, u32, u32, u32)> {
+    let parameters = &self.encoder.as_ref()?.parameters;
+    let image = &self.image;
+    let (tx0, ty0, tdx, tdy) = if parameters.tile_size_on != 0 {
+      (
+        parameters.cp_tx0 as u32,
+        parameters.cp_ty0 as u32,
+        parameters.cp_tdx as u32,
+        parameters.cp_tdy as u32,
+      )
+    } else {
+      (0, 0, image.x1, image.y1)
+    };
+    if tdx == 0 || tdy == 0 {
+      return None;
+    }
+    let tw = opj_uint_ceildiv(image.x1 - tx0, tdx);
+    let th = opj_uint_ceildiv(image.y1 - ty0, tdy);
+    if index >= tw * th {
+      return None;
+    }
+    let (p, q) = (index % tw, index / tw);
+    Some((
+      (tx0 + p * tdx).max(image.x0),
+      (ty0 + q * tdy).max(image.y0),
+      (tx0 + (p + 1) * tdx).min(image.x1),
+      (ty0 + (q + 1) * tdy).min(image.y1),
+    ))
+  }
+
+  /// Number of tiles of the image.
+  pub fn tile_count(&self) -> u32 {
+    let mut count = 0;
+    while self.tile_area(count).is_some() {
+      count += 1;
+    }
+    count
+  }
+
+  /// Index of the next tile to write.
+  pub fn next_tile(&self) -> u32 {
+    self.next_tile
+  }
+
+  /// Number of samples of each component in the tile `index`.
+  pub fn tile_sample_counts(&self, index: u32) -> Option<Vec<usize>> {
+    let (x0, y0, x1, y1) = self.tile_area(index)?;
+    let comps = self.image.comps()?;
+    Some(
+      comps
+        .iter()
+        .map(|comp| {
+          let w = opj_uint_ceildiv(x1, comp.dx) - opj_uint_ceildiv(x0, comp.dx);
+          let h = opj_uint_ceildiv(y1, comp.dy) - opj_uint_ceildiv(y0, comp.dy);
+          w as usize * h as usize
+        })
+        .collect(),
+    )
+  }
+
+  /// Encode the tile `index` and write it to the output. `samples` holds
+  /// the samples of each component of the tile in turn, row by row, see
+  /// `tile_sample_counts()`.
+  ///
+  /// Tiles must be written in index order.
+  pub fn write_tile(&mut self, index: u32, samples: &[i32]) -> Result<()> {
+    if index != self.next_tile {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("Expected tile {}, got tile {}", self.next_tile, index),
+      ));
+    }
+    let counts = self
+      .tile_sample_counts(index)
+      .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("No tile {}", index)))?;
+    let expected: usize = counts.iter().sum();
+    if samples.len() != expected {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+          "Tile {} has {} samples, got {}",
+          index,
+          expected,
+          samples.len()
+        ),
+      ));
+    }
+    /* The codec reads 1, 2 or 4 bytes per sample depending on the
+     * precision of the component, see opj_tcd_copy_tile_data() */
+    let mut data = Vec::with_capacity(samples.len() * 4);
+    let mut samples = samples;
+    for (comp, count) in self.image.comps().unwrap_or_default().iter().zip(counts) {
+      let (comp_samples, rest) = samples.split_at(count);
+      samples = rest;
+      match opj_uint_ceildiv(comp.prec, 8) {
+        1 => data.extend(comp_samples.iter().map(|&v| v as u8)),
+        2 => {
+          for &v in comp_samples {
+            data.extend_from_slice(&(v as u16).to_ne_bytes());
+          }
+        }
+        _ => {
+          for &v in comp_samples {
+            data.extend_from_slice(&v.to_ne_bytes());
+          }
+        }
+      }
+    }
+    if self.codec.write_tile(index, &data, &mut self.stream) == 0 {
+      return Err(Error::other(format!("Failed to encode tile {}", index)));
+    }
+    self.next_tile += 1;
+    Ok(())
+  }
+
+  /// End the codestream once all the tiles were written, returns `None` if
+  /// some are missing or on error.
+  pub fn finish_tiles(self) -> Option<Jp2Codec<Encoded>> {
+    self.try_finish_tiles().ok()
+  }
+
+  /// End the codestream and take the encoded bytes. This writes the EOC
+  /// marker and, when enabled, the updated TLM marker.
+  pub fn finish(self) -> Result<Vec<u8>> {
+    Ok(self.try_finish_tiles()?.into_bytes())
+  }
+
+  /// Like `finish_tiles()`, with the reason of the failure.
+  pub fn try_finish_tiles(mut self) -> Result<Jp2Codec<Encoded>> {
+    let count = self.tile_count();
+    if self.next_tile != count {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("Only {} of {} tiles were written", self.next_tile, count),
+      ));
+    }
+    if self.codec.end_compress(&mut self.stream) == 0 {
+      return Err(Error::other("Failed to end the codestream"));
+    }
+    self.stream.flush()?;
+    Ok(self.into_state())
+  }
+}
+
+impl Jp2Codec<Encoded> {
+  /// The encoded image.
+  pub fn image(&self) -> &opj_image {
+    &self.image
+  }
+
+  /// Take the encoded bytes, destroying the codec.
+  pub fn into_bytes(self) -> Vec<u8> {
+    self.output.map(|output| output.take()).unwrap_or_default()
+  }
+}
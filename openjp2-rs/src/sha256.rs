@@ -0,0 +1,197 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * Copyright (c) 2002-2014, Universite catholique de Louvain (UCL), Belgium
+ * Copyright (c) 2002-2014, Professor Benoit Macq
+ * Copyright (c) 2002-2003, Yannick Verschueren
+ * Copyright (c) 2005, Herve Drolon, FreeImage Team
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! SHA-256 message digest (FIPS 180-4).
+//!
+//! Used to hash the codestream of a file, see `opj_codestream_digest`.
+
+use core::convert::TryInto;
+
+/// First 32 bits of the fractional parts of the cube roots of the first 64
+/// primes.
+const K: [u32; 64] = [
+  0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+  0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+  0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+  0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+  0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+  0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+  0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+  0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub(crate) struct Sha256 {
+  state: [u32; 8],
+  buffer: [u8; 64],
+  buffer_len: usize,
+  /// Length of the message, in bytes.
+  length: u64,
+}
+
+impl Sha256 {
+  pub fn new() -> Self {
+    Self {
+      state: [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+      ],
+      buffer: [0; 64],
+      buffer_len: 0,
+      length: 0,
+    }
+  }
+
+  pub fn update(&mut self, mut data: &[u8]) {
+    self.length = self.length.wrapping_add(data.len() as u64);
+    if self.buffer_len > 0 {
+      let n = (64 - self.buffer_len).min(data.len());
+      self.buffer[self.buffer_len..self.buffer_len + n].copy_from_slice(&data[..n]);
+      self.buffer_len += n;
+      data = &data[n..];
+      if self.buffer_len < 64 {
+        return;
+      }
+      let block = self.buffer;
+      self.process(&block);
+      self.buffer_len = 0;
+    }
+    let mut blocks = data.chunks_exact(64);
+    for block in &mut blocks {
+      self.process(block.try_into().unwrap());
+    }
+    let rest = blocks.remainder();
+    self.buffer[..rest.len()].copy_from_slice(rest);
+    self.buffer_len = rest.len();
+  }
+
+  pub fn finalize(mut self) -> [u8; 32] {
+    let bit_length = self.length.wrapping_mul(8);
+    /* Padding: a 1 bit, zeros up to 56 bytes modulo 64, then the length */
+    self.update(&[0x80]);
+    while self.buffer_len != 56 {
+      self.update(&[0]);
+    }
+    self.update(&bit_length.to_be_bytes());
+    let mut digest = [0u8; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+      bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+  }
+
+  fn process(&mut self, block: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+      *word = u32::from_be_bytes(bytes.try_into().unwrap());
+    }
+    for i in 16..64 {
+      let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+      let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+      w[i] = w[i - 16]
+        .wrapping_add(s0)
+        .wrapping_add(w[i - 7])
+        .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+    for i in 0..64 {
+      let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+      let ch = (e & f) ^ (!e & g);
+      let t1 = h
+        .wrapping_add(s1)
+        .wrapping_add(ch)
+        .wrapping_add(K[i])
+        .wrapping_add(w[i]);
+      let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+      let maj = (a & b) ^ (a & c) ^ (b & c);
+      let t2 = s0.wrapping_add(maj);
+      h = g;
+      g = f;
+      f = e;
+      e = d.wrapping_add(t1);
+      d = c;
+      c = b;
+      b = a;
+      a = t1.wrapping_add(t2);
+    }
+    for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+      *state = state.wrapping_add(value);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sha256_hex(data: &[u8]) -> String {
+    let mut sha256 = Sha256::new();
+    sha256.update(data);
+    sha256
+      .finalize()
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect()
+  }
+
+  #[test]
+  fn test_fips180_vectors() {
+    assert_eq!(
+      sha256_hex(b""),
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+      sha256_hex(b"abc"),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+      sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+      "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+  }
+
+  #[test]
+  fn test_split_updates() {
+    let data: Vec<u8> = (0..200u8).collect();
+    let mut sha256 = Sha256::new();
+    for chunk in data.chunks(7) {
+      sha256.update(chunk);
+    }
+    let split: String = sha256
+      .finalize()
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect();
+    assert_eq!(split, sha256_hex(&data));
+  }
+}
@@ -37,11 +37,16 @@
  * POSSIBILITY OF SUCH DAMAGE.
  */
 
-use std::io::{BufReader, BufWriter, Error as IoError, Read, Seek, SeekFrom, Write};
+use std::cell::RefCell;
+use std::io::{
+  BufReader, BufWriter, Cursor, Error as IoError, ErrorKind, Read, Seek, SeekFrom, Write,
+};
+use std::rc::Rc;
 
 #[cfg(feature = "file-io")]
 use std::{fs::File, path::Path};
 
+use super::consts::opj::OPJ_J2K_STREAM_CHUNK_SIZE;
 use super::event::*;
 use super::openjpeg::*;
 
@@ -199,6 +204,43 @@ impl Seek for CustomStream {
   }
 }
 
+/// In-memory output of a stream, shared with the stream writing to it.
+#[derive(Clone, Default)]
+pub(crate) struct MemoryWriter(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+impl MemoryWriter {
+  /// Take the bytes written so far.
+  pub fn take(&self) -> Vec<u8> {
+    self.0.replace(Cursor::new(Vec::new())).into_inner()
+  }
+
+  /// Copy of the bytes written so far.
+  pub fn to_vec(&self) -> Vec<u8> {
+    self.0.borrow().get_ref().clone()
+  }
+
+  /// Number of bytes written so far.
+  pub fn len(&self) -> usize {
+    self.0.borrow().get_ref().len()
+  }
+}
+
+impl Write for MemoryWriter {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.borrow_mut().write(buf)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+impl Seek for MemoryWriter {
+  fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+    self.0.borrow_mut().seek(pos)
+  }
+}
+
 pub(crate) enum StreamInner {
   Reader(BufReader<Box<dyn ReadSeek>>),
   Writer(BufWriter<Box<dyn WriteSeek>>),
@@ -301,6 +343,10 @@ impl Stream {
         m_inner: super::stream::StreamInner::new_reader(buffer_size, file),
         m_stream_length,
         m_byte_offset: 0,
+        m_bytes_read: 0,
+        m_read_ranges: Vec::new(),
+        m_bytes_written: 0,
+        m_count_reads: true,
       })
     } else {
       let file = File::create(&path)?;
@@ -308,6 +354,10 @@ impl Stream {
         m_inner: super::stream::StreamInner::new_writer(buffer_size, file),
         m_stream_length: 0,
         m_byte_offset: 0,
+        m_bytes_read: 0,
+        m_read_ranges: Vec::new(),
+        m_bytes_written: 0,
+        m_count_reads: true,
       })
     }
   }
@@ -330,10 +380,50 @@ impl Stream {
       },
       m_stream_length: Default::default(),
       m_byte_offset: 0,
+      m_bytes_read: 0,
+      m_read_ranges: Vec::new(),
+      m_bytes_written: 0,
+      m_count_reads: true,
     };
     l_stream
   }
 
+  /// Create an input stream reading from an in-memory buffer.
+  pub fn new_memory(data: Vec<u8>) -> Self {
+    Self {
+      m_stream_length: data.len() as u64,
+      m_inner: StreamInner::new_reader(OPJ_J2K_STREAM_CHUNK_SIZE as usize, Cursor::new(data)),
+      m_byte_offset: 0,
+      m_bytes_read: 0,
+      m_read_ranges: Vec::new(),
+      m_bytes_written: 0,
+      m_count_reads: true,
+    }
+  }
+
+  /// Create an output stream writing to an in-memory buffer.
+  pub fn new_memory_writer(writer: MemoryWriter) -> Self {
+    Self {
+      m_inner: StreamInner::new_writer(OPJ_J2K_STREAM_CHUNK_SIZE as usize, writer),
+      m_stream_length: 0,
+      m_byte_offset: 0,
+      m_bytes_read: 0,
+      m_read_ranges: Vec::new(),
+      m_bytes_written: 0,
+      m_count_reads: true,
+    }
+  }
+
+  /// Create an output stream appending to the bytes already written to
+  /// `writer`.
+  pub fn new_memory_appender(writer: MemoryWriter) -> Self {
+    let len = writer.len();
+    writer.0.borrow_mut().set_position(len as u64);
+    let mut stream = Self::new_memory_writer(writer);
+    stream.m_byte_offset = len as OPJ_OFF_T;
+    stream
+  }
+
   pub fn is_input(&self) -> bool {
     match self.m_inner {
       StreamInner::Reader(_) => true,
@@ -356,27 +446,94 @@ impl Stream {
     self.m_stream_length = len;
   }
 
-  pub fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-    let len = buf.len();
-    match self.m_inner.read_exact(buf) {
-      Ok(_) => {
-        self.m_byte_offset += len as i64;
-        Ok(len)
+  /// Read all remaining bytes of an input stream.
+  pub fn read_remaining(&mut self) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+      // Custom streams report the end of the stream as a read error.
+      match self.m_inner.read(&mut chunk) {
+        Ok(0) | Err(_) => break,
+        Ok(nb) => {
+          data.extend_from_slice(&chunk[..nb]);
+          self.count_read(nb);
+          self.m_byte_offset += nb as i64;
+        }
       }
-      Err(_err) => {
-        // Maybe EOF, do a partial read.
-        match self.m_inner.read(buf) {
-          Ok(nb) => {
-            self.m_byte_offset += nb as i64;
-            Ok(nb)
-          }
-          Err(err) => {
-            log::trace!("Failed to read from stream: {err}");
-            Err(err)
-          }
+    }
+    data
+  }
+
+  /// Replace the input of the stream with an in-memory buffer.
+  ///
+  /// Reads from the new input aren't counted in `bytes_read()`.
+  pub fn replace_input(&mut self, data: Vec<u8>) {
+    self.m_stream_length = data.len() as u64;
+    self.m_byte_offset = 0;
+    self.m_count_reads = false;
+    self.m_inner = StreamInner::new_reader(OPJ_J2K_STREAM_CHUNK_SIZE as usize, Cursor::new(data));
+  }
+
+  /// Count the `nb` bytes read at the current offset, bytes already read
+  /// before a seek back are only counted once.
+  fn count_read(&mut self, nb: usize) {
+    if !self.m_count_reads || nb == 0 {
+      return;
+    }
+    let (start, end) = (self.m_byte_offset, self.m_byte_offset + nb as i64);
+    let ranges = &mut self.m_read_ranges;
+    // Ranges overlapping or adjacent to the read bytes.
+    let first = ranges.partition_point(|r| r.1 < start);
+    let last = ranges.partition_point(|r| r.0 <= end);
+    let mut new = end - start;
+    for &(s, e) in &ranges[first..last] {
+      new -= (e.min(end) - s.max(start)).max(0);
+    }
+    let merged = if first < last {
+      (ranges[first].0.min(start), ranges[last - 1].1.max(end))
+    } else {
+      (start, end)
+    };
+    ranges.splice(first..last, std::iter::once(merged));
+    self.m_bytes_read += new as u64;
+  }
+
+  /// Number of bytes read from the stream. Skipped bytes aren't counted and
+  /// bytes read again after a seek are counted once.
+  pub fn bytes_read(&self) -> u64 {
+    self.m_bytes_read
+  }
+
+  /// Number of bytes written to the stream, including rewritten ones.
+  pub fn bytes_written(&self) -> u64 {
+    self.m_bytes_written
+  }
+
+  /// Read up to `buf.len()` bytes, looping on short reads from the
+  /// underlying reader (e.g. a socket or a read callback returning partial
+  /// buffers). Less bytes are only returned at the end of the stream.
+  pub fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut nb = 0;
+    while nb < buf.len() {
+      match self.m_inner.read(&mut buf[nb..]) {
+        Ok(0) => break,
+        Ok(n) => nb += n,
+        Err(err) if err.kind() == ErrorKind::Interrupted => (),
+        // Custom streams report the end of the stream as a read error, keep
+        // the bytes read before it.
+        Err(err) if nb > 0 => {
+          log::trace!("Partial read from stream: {err}");
+          break;
+        }
+        Err(err) => {
+          log::trace!("Failed to read from stream: {err}");
+          return Err(err);
         }
       }
     }
+    self.count_read(nb);
+    self.m_byte_offset += nb as i64;
+    Ok(nb)
   }
 
   pub fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
@@ -385,6 +542,7 @@ impl Stream {
     match self.m_inner.write_all(buf) {
       Ok(_) => {
         self.m_byte_offset += len as i64;
+        self.m_bytes_written += len as u64;
         Ok(len)
       }
       Err(err) => {
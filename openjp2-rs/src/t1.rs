@@ -301,6 +301,7 @@ pub(crate) struct opj_t1_cblk_decode_processing_job_t {
   pub pret: *mut OPJ_BOOL,
   pub p_manager: opj_event_mgr,
   pub check_pterm: OPJ_BOOL,
+  pub error_resilience: ErrorResilienceFlags,
 }
 
 #[inline]
@@ -1682,7 +1683,12 @@ fn opj_t1_dec_clnpass_internal(
   }
 }
 
-fn opj_t1_dec_clnpass_check_segsym(mut t1: &mut opj_t1_t, mut cblksty: OPJ_INT32) {
+/// Decode the segmentation symbol of a cleanup pass, return the bad symbol
+/// if it isn't 0xa.
+fn opj_t1_dec_clnpass_check_segsym(
+  mut t1: &mut opj_t1_t,
+  mut cblksty: OPJ_INT32,
+) -> Option<OPJ_UINT32> {
   if (cblksty as u32 & J2K_CCP_CBLKSTY_SEGSYM) != 0 {
     let mqc = &mut t1.mqc;
     let mut v = 0;
@@ -1696,11 +1702,10 @@ fn opj_t1_dec_clnpass_check_segsym(mut t1: &mut opj_t1_t, mut cblksty: OPJ_INT32
     opj_mqc_decode_macro(&mut v2, mqc);
     v = (v << 1) | v2;
     if v != 0xa {
-      /*
-      event_msg!(t1->cinfo, EVT_WARNING, "Bad segmentation symbol %x\n", v);
-      */
+      return Some(v);
     }
   }
+  None
 }
 
 fn opj_t1_dec_clnpass_64x64_novsc(t1: &mut opj_t1_t, mut bpno: OPJ_INT32) {
@@ -1719,7 +1724,11 @@ fn opj_t1_dec_clnpass_generic_vsc(t1: &mut opj_t1_t, mut bpno: OPJ_INT32) {
   opj_t1_dec_clnpass_internal(t1, bpno, true, t1.w, t1.h, t1.w + 2);
 }
 
-fn opj_t1_dec_clnpass(mut t1: &mut opj_t1_t, mut bpno: OPJ_INT32, mut cblksty: OPJ_INT32) {
+fn opj_t1_dec_clnpass(
+  mut t1: &mut opj_t1_t,
+  mut bpno: OPJ_INT32,
+  mut cblksty: OPJ_INT32,
+) -> Option<OPJ_UINT32> {
   if t1.w == 64 && t1.h == 64 {
     if (cblksty as u32 & J2K_CCP_CBLKSTY_VSC) != 0 {
       opj_t1_dec_clnpass_64x64_vsc(t1, bpno);
@@ -1731,7 +1740,7 @@ fn opj_t1_dec_clnpass(mut t1: &mut opj_t1_t, mut bpno: OPJ_INT32, mut cblksty: O
   } else {
     opj_t1_dec_clnpass_generic_novsc(t1, bpno);
   }
-  opj_t1_dec_clnpass_check_segsym(t1, cblksty);
+  opj_t1_dec_clnpass_check_segsym(t1, cblksty)
 }
 
 fn opj_t1_getwmsedec(
@@ -1930,10 +1939,10 @@ fn opj_t1_clbl_decode_processor(mut user_data: *mut core::ffi::c_void) {
           t1,
           cblk,
           (*band).bandno,
-          (*tccp).roishift as OPJ_UINT32,
-          (*tccp).cblksty,
+          &*tccp,
           &mut (*job).p_manager,
           (*job).check_pterm,
+          (*job).error_resilience,
         )
       {
         core::ptr::write_volatile((*job).pret, 0i32);
@@ -2112,6 +2121,12 @@ pub(crate) fn opj_t1_decode_cblks(
     let mut bandno: OPJ_UINT32 = 0;
     let mut precno: OPJ_UINT32 = 0;
     let mut cblkno: OPJ_UINT32 = 0;
+    /* With several threads, the jobs are queued and decoded once all the */
+    /* code-blocks of the tile component are known. */
+    #[cfg(feature = "threads")]
+    let num_threads = (*(*tcd).cp).num_threads;
+    #[cfg(feature = "threads")]
+    let mut jobs = Vec::new();
     resno = 0 as OPJ_UINT32;
     while resno < (*tilec).minimum_num_resolutions {
       let mut res: *mut opj_tcd_resolution_t =
@@ -2190,6 +2205,10 @@ pub(crate) fn opj_t1_decode_cblks(
                     ) as *mut opj_t1_cblk_decode_processing_job_t;
                     if job.is_null() {
                       core::ptr::write_volatile(pret, 0i32);
+                      #[cfg(feature = "threads")]
+                      for job in jobs {
+                        opj_free(job as *mut core::ffi::c_void);
+                      }
                       return;
                     }
                     (*job).whole_tile_decoding = (*tcd).whole_tile_decoding;
@@ -2201,7 +2220,14 @@ pub(crate) fn opj_t1_decode_cblks(
                     (*job).pret = pret;
                     (*job).p_manager = *p_manager;
                     (*job).check_pterm = check_pterm;
+                    (*job).error_resilience = (*(*tcd).cp).error_resilience;
                     (*job).mustuse_cblkdatabuffer = 0;
+                    #[cfg(feature = "threads")]
+                    if num_threads > 1 {
+                      jobs.push(job);
+                      cblkno += 1;
+                      continue;
+                    }
                     opj_t1_clbl_decode_processor(job as _);
                     if *pret == 0 {
                       return;
@@ -2221,6 +2247,82 @@ pub(crate) fn opj_t1_decode_cblks(
       }
       resno += 1;
     }
+    #[cfg(feature = "threads")]
+    opj_t1_decode_cblks_parallel(jobs, num_threads, pret);
+  }
+}
+
+/// Decode the queued code-blocks of a tile component with up to `num_threads`
+/// threads, each one using its own thread local T1 instance.
+///
+/// The number of threads is capped by the available parallelism and the
+/// number of jobs, so a small tile component is decoded on the calling thread.
+#[cfg(feature = "threads")]
+fn opj_t1_decode_cblks_parallel(
+  jobs: Vec<*mut opj_t1_cblk_decode_processing_job_t>,
+  num_threads: OPJ_UINT32,
+  pret: *mut OPJ_BOOL,
+) {
+  unsafe {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    struct Job(*mut opj_t1_cblk_decode_processing_job_t);
+    // Each code-block only writes to its own decoded data or to its own area
+    // of the tile component.
+    unsafe impl Send for Job {}
+
+    let available = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let num_threads = (num_threads as usize).min(available).min(jobs.len());
+    if num_threads <= 1 {
+      for job in jobs {
+        opj_t1_clbl_decode_processor(job as _);
+        if *pret == 0 {
+          return;
+        }
+      }
+      return;
+    }
+
+    // One return value per job, the processor writes to it on error. The
+    // messages of each job are queued, and passed to the handlers in the
+    // order of the jobs once the threads are done.
+    let p_manager = (*jobs[0]).p_manager;
+    let mut rets = vec![1 as OPJ_BOOL; jobs.len()];
+    let mut queues = vec![Vec::new(); jobs.len()];
+    for (i, job) in jobs.iter().enumerate() {
+      (**job).pret = rets.as_mut_ptr().add(i);
+      (**job).p_manager = p_manager.queued(queues.as_mut_ptr().add(i));
+    }
+    let queue = Mutex::new(jobs.into_iter().map(Job).collect::<Vec<_>>().into_iter());
+    let failed = AtomicBool::new(false);
+    std::thread::scope(|s| {
+      for _ in 0..num_threads {
+        s.spawn(|| {
+          while !failed.load(Ordering::Relaxed) {
+            let job = match queue.lock().unwrap().next() {
+              Some(job) => job.0,
+              None => break,
+            };
+            let ret = (*job).pret;
+            opj_t1_clbl_decode_processor(job as _);
+            if *ret == 0 {
+              failed.store(true, Ordering::Relaxed);
+            }
+          }
+        });
+      }
+    });
+    // Jobs left after an error.
+    for job in queue.into_inner().unwrap() {
+      opj_free(job.0 as *mut core::ffi::c_void);
+    }
+    for queue in queues {
+      p_manager.replay(queue);
+    }
+    if rets.contains(&0) {
+      core::ptr::write_volatile(pret, 0i32);
+    }
   }
 }
 
@@ -2229,8 +2331,8 @@ Decode 1 code-block
 @param t1 T1 handle
 @param cblk Code-block coding parameters
 @param orient
-@param roishift Region of interest shifting value
-@param cblksty Code-block style
+@param tccp Tile-component coding parameters: region of interest shifting
+value and code-block style
 @param p_manager the event manager
 @param check_pterm whether PTERM correct termination should be checked
 */
@@ -2238,12 +2340,15 @@ fn opj_t1_decode_cblk(
   mut t1: &mut opj_t1_t,
   mut cblk: *mut opj_tcd_cblk_dec_t,
   mut orient: OPJ_UINT32,
-  mut roishift: OPJ_UINT32,
-  mut cblksty: OPJ_UINT32,
+  tccp: &opj_tccp_t,
   mut p_manager: &mut opj_event_mgr,
   mut check_pterm: OPJ_BOOL,
+  error_resilience: ErrorResilienceFlags,
 ) -> OPJ_BOOL {
+  let roishift = tccp.roishift as OPJ_UINT32;
+  let cblksty = tccp.cblksty;
   unsafe {
+    let mut corrupt = false;
     let mut bpno_plus_one: OPJ_INT32 = 0; /* BYPASS mode */
     let mut passtype: OPJ_UINT32 = 0;
     let mut segno: OPJ_UINT32 = 0;
@@ -2381,7 +2486,18 @@ fn opj_t1_decode_cblk(
             }
           }
           2 => {
-            opj_t1_dec_clnpass(t1, bpno_plus_one, cblksty as OPJ_INT32);
+            let bad_segsym = opj_t1_dec_clnpass(t1, bpno_plus_one, cblksty as OPJ_INT32);
+            if let Some(v) = bad_segsym {
+              if error_resilience.contains(ErrorResilienceFlags::CHECK_SEGMARK) && !corrupt {
+                event_msg!(
+                  p_manager,
+                  EVT_WARNING,
+                  "Bad segmentation symbol %x in code-block\n",
+                  v,
+                );
+                corrupt = true;
+              }
+            }
           }
           _ => {}
         }
@@ -2412,6 +2528,7 @@ fn opj_t1_decode_cblk(
           mqc.bp.offset_from(mqc.start) as core::ffi::c_int,
           mqc.end.offset_from(mqc.start) as core::ffi::c_int,
         );
+        corrupt = true;
       } else if mqc.end_of_byte_stream_counter > 2 {
         event_msg!(
           p_manager,
@@ -2419,7 +2536,19 @@ fn opj_t1_decode_cblk(
           "PTERM check failure: %d synthetized 0xFF markers read\n",
           mqc.end_of_byte_stream_counter,
         );
+        corrupt = true;
+      }
+    }
+    if corrupt && error_resilience.contains(ErrorResilienceFlags::STOP_ON_ERROR) {
+      event_msg!(
+        p_manager,
+        EVT_ERROR,
+        "Corrupt code-block, stopping decoding\n",
+      );
+      if !(*cblk).decoded_data.is_null() {
+        t1.reset_decoded_data();
       }
+      return 0i32;
     }
     /* Restore original t1->data is needed */
     if !(*cblk).decoded_data.is_null() {
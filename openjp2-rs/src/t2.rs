@@ -1,4 +1,5 @@
 use super::bio::*;
+use super::consts::j2k::{J2K_CP_CSTY_EPH, J2K_CP_CSTY_SOP};
 use super::event::*;
 use super::math::*;
 use super::openjpeg::*;
@@ -375,7 +376,10 @@ pub(crate) unsafe fn opj_t2_decode_packets(
       );
       /* INDEX >> */
       /* << INDEX */
-      if (*l_current_pi).layno >= (*l_tcp).num_layers_to_decode {
+      if !(*tcd).packet_index.is_null() {
+        /* Header-only parsing: the packet data is skipped */
+        skip_packet = 1i32
+      } else if (*l_current_pi).layno >= (*l_tcp).num_layers_to_decode {
         skip_packet = 1i32
       } else if (*l_current_pi).resno
         >= (*(*p_tile).comps.offset((*l_current_pi).compno as isize)).minimum_num_resolutions
@@ -471,6 +475,20 @@ pub(crate) unsafe fn opj_t2_decode_packets(
             .wrapping_sub(1u32)
         }
       }
+      if !(*tcd).packet_index.is_null() {
+        (*(*tcd).packet_index).push(PacketPosition {
+          tile: p_tile_no,
+          coord: PacketCoord {
+            layer: (*l_current_pi).layno,
+            resolution: (*l_current_pi).resno,
+            component: (*l_current_pi).compno,
+            precinct: (*l_current_pi).precno,
+          },
+          /* Offset in the tile data, see opj_j2k_build_packet_index() */
+          offset: l_current_data.offset_from(p_src) as u64,
+          length: l_nb_bytes_read,
+        });
+      }
       l_current_data = l_current_data.offset(l_nb_bytes_read as isize);
       p_max_len = (p_max_len as core::ffi::c_uint).wrapping_sub(l_nb_bytes_read) as OPJ_UINT32
     }
@@ -485,6 +503,51 @@ pub(crate) unsafe fn opj_t2_decode_packets(
   *p_data_read = l_current_data.offset_from(p_src) as OPJ_UINT32;
   1i32
 }
+
+/* *
+ * Decodes the packets of a tile made of a single packet per component, in
+ * component order, see opj_tcd_has_one_packet_per_component().
+ *
+ * The packets are the same as with opj_t2_decode_packets(), without
+ * creating a packet iterator.
+ */
+pub(crate) unsafe fn opj_t2_decode_component_packets(
+  mut p_t2: *mut opj_t2_t,
+  mut p_tile_no: OPJ_UINT32,
+  mut p_tile: *mut opj_tcd_tile_t,
+  mut p_src: *mut OPJ_BYTE,
+  mut p_data_read: *mut OPJ_UINT32,
+  mut p_max_len: OPJ_UINT32,
+  mut p_manager: &mut opj_event_mgr,
+) -> OPJ_BOOL {
+  let mut l_current_data = p_src;
+  let mut l_tcp: *mut opj_tcp_t =
+    &mut *(*(*p_t2).cp).tcps.offset(p_tile_no as isize) as *mut opj_tcp_t;
+  /* Only the packet identity is read from the iterator */
+  let mut l_pi: opj_pi_iterator_t = core::mem::zeroed();
+  for compno in 0..(*p_tile).numcomps {
+    let mut l_nb_bytes_read = 0 as OPJ_UINT32;
+    l_pi.compno = compno;
+    if opj_t2_decode_packet(
+      p_t2,
+      p_tile,
+      l_tcp,
+      &mut l_pi,
+      l_current_data,
+      &mut l_nb_bytes_read,
+      p_max_len,
+      std::ptr::null_mut(),
+      p_manager,
+    ) == 0
+    {
+      return 0i32;
+    }
+    l_current_data = l_current_data.offset(l_nb_bytes_read as isize);
+    p_max_len = p_max_len.wrapping_sub(l_nb_bytes_read)
+  }
+  *p_data_read = l_current_data.offset_from(p_src) as OPJ_UINT32;
+  1i32
+}
 /* ----------------------------------------------------------------------- */
 /* *
  * Creates a Tier 2 handle
@@ -988,6 +1051,103 @@ unsafe fn opj_t2_skip_packet(
   1i32
 }
 
+/* *
+ * Reads the SOP marker at the start of a packet.
+ *
+ * Packet data can't contain 0xFF90 to 0xFFFF, so a SOP marker is skipped
+ * even when the COD marker doesn't enable them, as some encoders write
+ * them anyway. A missing SOP marker is reported, nothing is consumed then.
+ *
+ * @return the number of bytes of the SOP marker segment read.
+ */
+unsafe fn opj_t2_read_sop(
+  mut p_tcp: *mut opj_tcp_t,
+  mut p_pi: *mut opj_pi_iterator_t,
+  mut p_data: *const OPJ_BYTE,
+  mut p_max_length: OPJ_UINT32,
+  mut p_manager: &mut opj_event_mgr,
+) -> OPJ_UINT32 {
+  let l_claimed = (*p_tcp).csty & J2K_CP_CSTY_SOP != 0;
+  let l_found = p_max_length >= 6
+    && *p_data == 0xff
+    && *p_data.offset(1) == 0x91
+    && *p_data.offset(2) == 0x00
+    && *p_data.offset(3) == 0x04;
+  if l_found {
+    if !l_claimed {
+      event_msg!(
+        p_manager,
+        EVT_INFO,
+        "Skipping a SOP marker not enabled by the COD marker\n",
+      );
+    }
+    /* * TODO : check the Nsop value */
+    return 6;
+  }
+  if l_claimed {
+    event_msg!(
+      p_manager,
+      EVT_WARNING,
+      "Expected SOP marker for the packet of component %d, resolution %d, precinct %d, layer %d%s\n",
+      (*p_pi).compno,
+      (*p_pi).resno,
+      (*p_pi).precno,
+      (*p_pi).layno,
+      if p_max_length < 6 {
+        ": not enough data left"
+      } else {
+        ""
+      },
+    );
+  }
+  0
+}
+
+/* *
+ * Reads the EPH marker at the end of a packet header, like
+ * opj_t2_read_sop(): a stray EPH marker is skipped and a missing one is
+ * reported.
+ *
+ * @return the number of bytes of the EPH marker read.
+ */
+unsafe fn opj_t2_read_eph(
+  mut p_tcp: *mut opj_tcp_t,
+  mut p_pi: *mut opj_pi_iterator_t,
+  mut p_data: *const OPJ_BYTE,
+  mut p_max_length: OPJ_UINT32,
+  mut p_manager: &mut opj_event_mgr,
+) -> OPJ_UINT32 {
+  let l_claimed = (*p_tcp).csty & J2K_CP_CSTY_EPH != 0;
+  let l_found = p_max_length >= 2 && *p_data == 0xff && *p_data.offset(1) == 0x92;
+  if l_found {
+    if !l_claimed {
+      event_msg!(
+        p_manager,
+        EVT_INFO,
+        "Skipping an EPH marker not enabled by the COD marker\n",
+      );
+    }
+    return 2;
+  }
+  if l_claimed {
+    event_msg!(
+      p_manager,
+      EVT_WARNING,
+      "Expected EPH marker for the packet of component %d, resolution %d, precinct %d, layer %d%s\n",
+      (*p_pi).compno,
+      (*p_pi).resno,
+      (*p_pi).precno,
+      (*p_pi).layno,
+      if p_max_length < 2 {
+        ": not enough data left"
+      } else {
+        ""
+      },
+    );
+  }
+  0
+}
+
 unsafe fn opj_t2_read_packet_header(
   mut p_t2: *mut opj_t2_t,
   mut p_tile: *mut opj_tcd_tile_t,
@@ -1052,22 +1212,9 @@ unsafe fn opj_t2_read_packet_header(
   }
 
   /* SOP markers */
-  if (*p_tcp).csty & 0x2u32 != 0 {
-    if p_max_length < 6u32 {
-      event_msg!(
-        p_manager,
-        EVT_WARNING,
-        "Not enough space for expected SOP marker\n",
-      );
-    } else if *l_current_data as core::ffi::c_int != 0xffi32
-      || *l_current_data.offset(1) as core::ffi::c_int != 0x91i32
-    {
-      event_msg!(p_manager, EVT_WARNING, "Expected SOP marker\n",);
-    } else {
-      l_current_data = l_current_data.offset(6)
-    }
-    /* * TODO : check the Nsop value */
-  }
+  l_current_data =
+    l_current_data
+      .offset(opj_t2_read_sop(p_tcp, p_pi, l_current_data, p_max_length, p_manager) as isize);
 
   /*
   When the marker PPT/PPM is used the packet header are store in PPT/PPM marker
@@ -1109,24 +1256,14 @@ unsafe fn opj_t2_read_packet_header(
     l_header_data = l_header_data.offset(opj_bio_numbytes(l_bio));
     opj_bio_destroy(l_bio);
     /* EPH markers */
-    if (*p_tcp).csty & 0x4u32 != 0 {
-      if (*l_modified_length_ptr)
-        .wrapping_sub(l_header_data.offset_from(*l_header_data_start) as OPJ_UINT32)
-        < 2u32
-      {
-        event_msg!(
-          p_manager,
-          EVT_WARNING,
-          "Not enough space for expected EPH marker\n",
-        );
-      } else if *l_header_data as core::ffi::c_int != 0xffi32
-        || *l_header_data.offset(1) as core::ffi::c_int != 0x92i32
-      {
-        event_msg!(p_manager, EVT_WARNING, "Expected EPH marker\n",);
-      } else {
-        l_header_data = l_header_data.offset(2)
-      }
-    }
+    l_header_data = l_header_data.offset(opj_t2_read_eph(
+      p_tcp,
+      p_pi,
+      l_header_data,
+      (*l_modified_length_ptr)
+        .wrapping_sub(l_header_data.offset_from(*l_header_data_start) as OPJ_UINT32),
+      p_manager,
+    ) as isize);
     l_header_length = l_header_data.offset_from(*l_header_data_start) as OPJ_UINT32;
     *l_modified_length_ptr =
       (*l_modified_length_ptr as core::ffi::c_uint).wrapping_sub(l_header_length) as OPJ_UINT32;
@@ -1346,24 +1483,14 @@ unsafe fn opj_t2_read_packet_header(
   l_header_data = l_header_data.offset(opj_bio_numbytes(l_bio));
   opj_bio_destroy(l_bio);
   /* EPH markers */
-  if (*p_tcp).csty & 0x4u32 != 0 {
-    if (*l_modified_length_ptr)
-      .wrapping_sub(l_header_data.offset_from(*l_header_data_start) as OPJ_UINT32)
-      < 2u32
-    {
-      event_msg!(
-        p_manager,
-        EVT_WARNING,
-        "Not enough space for expected EPH marker\n",
-      );
-    } else if *l_header_data as core::ffi::c_int != 0xffi32
-      || *l_header_data.offset(1) as core::ffi::c_int != 0x92i32
-    {
-      event_msg!(p_manager, EVT_WARNING, "Expected EPH marker\n",);
-    } else {
-      l_header_data = l_header_data.offset(2)
-    }
-  }
+  l_header_data = l_header_data.offset(opj_t2_read_eph(
+    p_tcp,
+    p_pi,
+    l_header_data,
+    (*l_modified_length_ptr)
+      .wrapping_sub(l_header_data.offset_from(*l_header_data_start) as OPJ_UINT32),
+    p_manager,
+  ) as isize);
   l_header_length = l_header_data.offset_from(*l_header_data_start) as OPJ_UINT32;
   log::debug!("hdrlen={}", l_header_length);
   log::debug!("packet body");
@@ -657,6 +657,25 @@ pub(crate) unsafe fn opj_tcd_init(
   (*p_tcd).tp_pos = (*p_cp).m_specific_param.m_enc.m_tp_pos;
   1i32
 }
+/// Attach a tile decoder to the image and coding parameters of a new
+/// codestream, keeping the buffers of its tile. Fails if the tile doesn't
+/// have as many components as `p_image`, the decoder must then be recreated.
+pub(crate) unsafe fn opj_tcd_reinit(
+  p_tcd: *mut opj_tcd_t,
+  p_image: *mut opj_image_t,
+  p_cp: *mut opj_cp_t,
+) -> bool {
+  let l_tile = (*(*p_tcd).tcd_image).tiles;
+  if l_tile.is_null() || (*l_tile).numcomps != (*p_image).numcomps {
+    return false;
+  }
+  (*p_tcd).image = p_image;
+  (*p_tcd).cp = p_cp;
+  (*p_tcd).tcp = std::ptr::null_mut();
+  (*p_tcd).tcd_tileno = 0;
+  (*p_tcd).tp_pos = (*p_cp).m_specific_param.m_enc.m_tp_pos;
+  true
+}
 /* *
 Destroy a previously created TCD handle
 */
@@ -672,6 +691,16 @@ pub(crate) unsafe fn opj_tcd_destroy(mut tcd: *mut opj_tcd_t) {
     opj_free(tcd as *mut core::ffi::c_void);
   };
 }
+/// Size in bytes of a buffer of `w * h` samples of `sample_size` bytes,
+/// `None` if it doesn't fit in a `usize`: wrapping would under-allocate
+/// the buffer, e.g. for large tiles on 32-bit targets.
+fn opj_tcd_buffer_size(
+  w: OPJ_SIZE_T,
+  h: OPJ_SIZE_T,
+  sample_size: OPJ_SIZE_T,
+) -> Option<OPJ_SIZE_T> {
+  w.checked_mul(h)?.checked_mul(sample_size)
+}
 #[no_mangle]
 pub(crate) unsafe fn opj_alloc_tile_component_data(
   mut l_tilec: *mut opj_tcd_tilecomp_t,
@@ -800,6 +829,8 @@ unsafe fn opj_tcd_init_tile(
   l_tcp = &mut *(*l_cp).tcps.offset(p_tile_no as isize) as *mut opj_tcp_t;
   l_tile = (*(*p_tcd).tcd_image).tiles;
   l_tccp = (*l_tcp).tccps;
+  let (l_ox, l_oy) = (*l_tcp).partition_origin();
+  let (l_ox, l_oy) = (l_ox as OPJ_INT32, l_oy as OPJ_INT32);
   l_tilec = (*l_tile).comps;
   l_image = (*p_tcd).image;
   l_image_comp = (*(*p_tcd).image).comps;
@@ -846,7 +877,21 @@ unsafe fn opj_tcd_init_tile(
     (*l_tilec).compno = compno;
     /*fprintf(stderr, "\tTile compo border = %d,%d,%d,%d\n", l_tilec->x0, l_tilec->y0,l_tilec->x1,l_tilec->y1);*/
     (*l_tilec).numresolutions = (*l_tccp).numresolutions;
-    if (*l_tccp).numresolutions < (*l_cp).m_specific_param.m_dec.m_reduce {
+    if isEncoder == 0 {
+      /* The decoder can remove a different number of resolutions in each component */
+      if (*l_image_comp).factor >= (*l_tccp).numresolutions {
+        event_msg!(
+          manager,
+          EVT_ERROR,
+          "The number of resolutions to remove (%d) of component %d is greater or equal than its number of resolutions (%d)\n",
+          (*l_image_comp).factor,
+          compno,
+          (*l_tccp).numresolutions,
+        );
+        return 0i32;
+      }
+      (*l_tilec).minimum_num_resolutions = (*l_tccp).numresolutions - (*l_image_comp).factor
+    } else if (*l_tccp).numresolutions < (*l_cp).m_specific_param.m_dec.m_reduce {
       (*l_tilec).minimum_num_resolutions = 1 as OPJ_UINT32
     } else {
       (*l_tilec).minimum_num_resolutions = (*l_tccp)
@@ -854,30 +899,21 @@ unsafe fn opj_tcd_init_tile(
         .wrapping_sub((*l_cp).m_specific_param.m_dec.m_reduce)
     }
     if isEncoder != 0 {
-      let mut l_tile_data_size: OPJ_SIZE_T = 0;
       /* compute l_data_size with overflow check */
       let mut w = ((*l_tilec).x1 - (*l_tilec).x0) as OPJ_SIZE_T;
       let mut h = ((*l_tilec).y1 - (*l_tilec).y0) as OPJ_SIZE_T;
       /* issue 733, l_data_size == 0U, probably something wrong should be checked before getting here */
-      if h > 0 && w > (usize::MAX).wrapping_div(h) {
-        event_msg!(
-          manager,
-          EVT_ERROR,
-          "Size of tile data exceeds system limits\n",
-        );
-        return 0i32;
-      }
-      l_tile_data_size = w.wrapping_mul(h);
-      if (usize::MAX).wrapping_div(core::mem::size_of::<OPJ_UINT32>()) < l_tile_data_size {
-        event_msg!(
-          manager,
-          EVT_ERROR,
-          "Size of tile data exceeds system limits\n",
-        );
-        return 0i32;
+      match opj_tcd_buffer_size(w, h, core::mem::size_of::<OPJ_UINT32>()) {
+        Some(l_tile_data_size) => (*l_tilec).data_size_needed = l_tile_data_size,
+        None => {
+          event_msg!(
+            manager,
+            EVT_ERROR,
+            "Size of tile data exceeds system limits\n",
+          );
+          return 0i32;
+        }
       }
-      l_tile_data_size = l_tile_data_size.wrapping_mul(core::mem::size_of::<OPJ_UINT32>());
-      (*l_tilec).data_size_needed = l_tile_data_size
     }
     l_data_size = (*l_tilec)
       .numresolutions
@@ -947,15 +983,22 @@ unsafe fn opj_tcd_init_tile(
       (*l_res).y1 = opj_int_ceildivpow2((*l_tilec).y1, l_level_no as OPJ_INT32);
       l_pdx = (*l_tccp).prcw[resno as usize];
       l_pdy = (*l_tccp).prch[resno as usize];
-      l_tl_prc_x_start = opj_int_floordivpow2((*l_res).x0, l_pdx as OPJ_INT32) << l_pdx;
-      l_tl_prc_y_start = opj_int_floordivpow2((*l_res).y0, l_pdy as OPJ_INT32) << l_pdy;
-      let mut tmp = (opj_int_ceildivpow2((*l_res).x1, l_pdx as OPJ_INT32) as OPJ_UINT32) << l_pdx;
+      /* The partitions start at the origin, 0 or 1 */
+      l_tl_prc_x_start =
+        (opj_int_floordivpow2((*l_res).x0 - l_ox, l_pdx as OPJ_INT32) << l_pdx) + l_ox;
+      l_tl_prc_y_start =
+        (opj_int_floordivpow2((*l_res).y0 - l_oy, l_pdy as OPJ_INT32) << l_pdy) + l_oy;
+      let mut tmp = ((opj_int_ceildivpow2((*l_res).x1 - l_ox, l_pdx as OPJ_INT32) as OPJ_UINT32)
+        << l_pdx)
+        .wrapping_add(l_ox as OPJ_UINT32);
       if tmp > 2147483647 as OPJ_UINT32 {
         event_msg!(manager, EVT_ERROR, "Integer overflow\n",);
         return 0i32;
       }
       l_br_prc_x_end = tmp as OPJ_INT32;
-      let mut tmp_0 = (opj_int_ceildivpow2((*l_res).y1, l_pdy as OPJ_INT32) as OPJ_UINT32) << l_pdy;
+      let mut tmp_0 = ((opj_int_ceildivpow2((*l_res).y1 - l_oy, l_pdy as OPJ_INT32) as OPJ_UINT32)
+        << l_pdy)
+        .wrapping_add(l_oy as OPJ_UINT32);
       if tmp_0 > 2147483647 as OPJ_UINT32 {
         event_msg!(manager, EVT_ERROR, "Integer overflow\n",);
         return 0i32;
@@ -1158,20 +1201,25 @@ unsafe fn opj_tcd_init_tile(
               (*l_current_precinct).y1 = opj_int_min(cbgyend, (*l_band).y1);
               /*fprintf(stderr, "\t prc_x0=%d; prc_y0=%d, prc_x1=%d; prc_y1=%d\n",l_current_precinct->x0, l_current_precinct->y0 ,l_current_precinct->x1, l_current_precinct->y1);*/
               tlcblkxstart =
-                opj_int_floordivpow2((*l_current_precinct).x0, cblkwidthexpn as OPJ_INT32)
-                  << cblkwidthexpn;
+                (opj_int_floordivpow2((*l_current_precinct).x0 - l_ox, cblkwidthexpn as OPJ_INT32)
+                  << cblkwidthexpn)
+                  + l_ox;
               /*fprintf(stderr, "\t tlcblkxstart =%d\n",tlcblkxstart );*/
-              tlcblkystart =
-                opj_int_floordivpow2((*l_current_precinct).y0, cblkheightexpn as OPJ_INT32)
-                  << cblkheightexpn;
+              tlcblkystart = (opj_int_floordivpow2(
+                (*l_current_precinct).y0 - l_oy,
+                cblkheightexpn as OPJ_INT32,
+              ) << cblkheightexpn)
+                + l_oy;
               /*fprintf(stderr, "\t tlcblkystart =%d\n",tlcblkystart );*/
               brcblkxend =
-                opj_int_ceildivpow2((*l_current_precinct).x1, cblkwidthexpn as OPJ_INT32)
-                  << cblkwidthexpn;
+                (opj_int_ceildivpow2((*l_current_precinct).x1 - l_ox, cblkwidthexpn as OPJ_INT32)
+                  << cblkwidthexpn)
+                  + l_ox;
               /*fprintf(stderr, "\t brcblkxend =%d\n",brcblkxend );*/
               brcblkyend =
-                opj_int_ceildivpow2((*l_current_precinct).y1, cblkheightexpn as OPJ_INT32)
-                  << cblkheightexpn;
+                (opj_int_ceildivpow2((*l_current_precinct).y1 - l_oy, cblkheightexpn as OPJ_INT32)
+                  << cblkheightexpn)
+                  + l_oy;
               /*fprintf(stderr, "\t brcblkyend =%d\n",brcblkyend );*/
               (*l_current_precinct).cw =
                 ((brcblkxend - tlcblkxstart) >> cblkwidthexpn) as OPJ_UINT32;
@@ -1478,6 +1526,13 @@ pub(crate) unsafe fn opj_tcd_get_decoded_tile_size(
   while i < (*(*p_tcd).image).numcomps {
     let mut w: OPJ_UINT32 = 0;
     let mut h: OPJ_UINT32 = 0;
+    if !(*p_tcd).used_component.is_null() && *(*p_tcd).used_component.offset(i as isize) == 0 {
+      /* Components that aren't decoded have no data */
+      l_img_comp = l_img_comp.offset(1);
+      l_tile_comp = l_tile_comp.offset(1);
+      i += 1;
+      continue;
+    }
     l_size_comp = (*l_img_comp).prec >> 3i32;
     l_remaining = (*l_img_comp).prec & 7u32;
     if l_remaining != 0 {
@@ -1654,6 +1709,17 @@ pub(crate) unsafe fn opj_tcd_decode_tile(
   let mut compno: OPJ_UINT32 = 0;
   (*p_tcd).tcd_tileno = p_tile_no;
   (*p_tcd).tcp = &mut *(*(*p_tcd).cp).tcps.offset(p_tile_no as isize) as *mut opj_tcp_t;
+  if !(*p_tcd).packet_index.is_null() {
+    /* Only the packet headers are read, see opj_j2k_build_packet_index() */
+    return opj_tcd_t2_decode(
+      p_tcd,
+      p_src,
+      &mut l_data_read,
+      p_max_length,
+      p_cstr_index,
+      p_manager,
+    );
+  }
   (*p_tcd).win_x0 = win_x0;
   (*p_tcd).win_y0 = win_y0;
   (*p_tcd).win_x1 = win_x1;
@@ -1697,7 +1763,6 @@ pub(crate) unsafe fn opj_tcd_decode_tile(
         .resolutions
         .offset((*tilec).minimum_num_resolutions.wrapping_sub(1u32) as isize)
         as *mut opj_tcd_resolution_t;
-      let mut l_data_size: OPJ_SIZE_T = 0;
       /* compute l_data_size with overflow check */
       let mut res_w = ((*l_res).x1 - (*l_res).x0) as OPJ_SIZE_T;
       let mut res_h = ((*l_res).y1 - (*l_res).y0) as OPJ_SIZE_T;
@@ -1705,26 +1770,17 @@ pub(crate) unsafe fn opj_tcd_decode_tile(
         && *(*p_tcd).used_component.offset(compno as isize) == 0)
       {
         /* issue 733, l_data_size == 0U, probably something wrong should be checked before getting here */
-        if res_h > 0 && res_w > (usize::MAX).wrapping_div(res_h) {
-          event_msg!(
-            p_manager,
-            EVT_ERROR,
-            "Size of tile data exceeds system limits\n",
-          );
-          return 0i32;
-        }
-        l_data_size = res_w.wrapping_mul(res_h);
-        if (usize::MAX).wrapping_div(core::mem::size_of::<OPJ_UINT32>()) < l_data_size {
-          event_msg!(
-            p_manager,
-            EVT_ERROR,
-            "Size of tile data exceeds system limits\n",
-          );
-          return 0i32;
+        match opj_tcd_buffer_size(res_w, res_h, core::mem::size_of::<OPJ_UINT32>()) {
+          Some(l_data_size) => (*tilec).data_size_needed = l_data_size,
+          None => {
+            event_msg!(
+              p_manager,
+              EVT_ERROR,
+              "Size of tile data exceeds system limits\n",
+            );
+            return 0i32;
+          }
         }
-        l_data_size = (l_data_size as usize).wrapping_mul(core::mem::size_of::<OPJ_UINT32>())
-          as OPJ_SIZE_T as OPJ_SIZE_T;
-        (*tilec).data_size_needed = l_data_size;
         if opj_alloc_tile_component_data(tilec) == 0 {
           event_msg!(
             p_manager,
@@ -1850,7 +1906,6 @@ pub(crate) unsafe fn opj_tcd_decode_tile(
         .offset((*image_comp_0).resno_decoded as isize);
       let mut w = (*res_0).win_x1.wrapping_sub((*res_0).win_x0) as OPJ_SIZE_T;
       let mut h = (*res_0).win_y1.wrapping_sub((*res_0).win_y0) as OPJ_SIZE_T;
-      let mut l_data_size_0: OPJ_SIZE_T = 0;
       opj_image_data_free((*tilec_1).data_win as *mut core::ffi::c_void);
       (*tilec_1).data_win = std::ptr::null_mut::<OPJ_INT32>();
       if !(!(*p_tcd).used_component.is_null()
@@ -1858,25 +1913,17 @@ pub(crate) unsafe fn opj_tcd_decode_tile(
         && w > 0
         && h > 0
       {
-        if w > (usize::MAX).wrapping_div(h) {
-          event_msg!(
-            p_manager,
-            EVT_ERROR,
-            "Size of tile data exceeds system limits\n",
-          );
-          return 0i32;
-        }
-        l_data_size_0 = w.wrapping_mul(h);
-        if l_data_size_0 > (usize::MAX).wrapping_div(core::mem::size_of::<OPJ_INT32>()) {
-          event_msg!(
-            p_manager,
-            EVT_ERROR,
-            "Size of tile data exceeds system limits\n",
-          );
-          return 0i32;
-        }
-        l_data_size_0 = (l_data_size_0 as usize).wrapping_mul(core::mem::size_of::<OPJ_INT32>())
-          as OPJ_SIZE_T as OPJ_SIZE_T;
+        let l_data_size_0 = match opj_tcd_buffer_size(w, h, core::mem::size_of::<OPJ_INT32>()) {
+          Some(l_data_size_0) => l_data_size_0,
+          None => {
+            event_msg!(
+              p_manager,
+              EVT_ERROR,
+              "Size of tile data exceeds system limits\n",
+            );
+            return 0i32;
+          }
+        };
         (*tilec_1).data_win = opj_image_data_alloc(l_data_size_0) as *mut OPJ_INT32;
         if (*tilec_1).data_win.is_null() {
           event_msg!(
@@ -1927,7 +1974,10 @@ pub(crate) fn opj_tcd_update_tile_data(p_tcd: &mut opj_tcd_t, mut p_dest: &mut [
     let numcomps = (*p_tcd.image).numcomps as usize;
     let mut l_tilec = std::slice::from_raw_parts((*(*p_tcd.tcd_image).tiles).comps, numcomps);
     let mut l_img_comp = std::slice::from_raw_parts((*p_tcd.image).comps, numcomps);
-    for (l_tilec, l_img_comp) in l_tilec.iter().zip(l_img_comp.iter()) {
+    for (compno, (l_tilec, l_img_comp)) in l_tilec.iter().zip(l_img_comp.iter()).enumerate() {
+      if !p_tcd.used_component.is_null() && *p_tcd.used_component.add(compno) == 0 {
+        continue;
+      }
       let mut l_size_comp = l_img_comp.prec >> 3i32;
       let l_remaining = l_img_comp.prec & 7u32;
       let l_res = l_tilec
@@ -2123,6 +2173,43 @@ unsafe fn opj_tcd_free_tile(mut p_tcd: *mut opj_tcd_t) {
   opj_free((*(*p_tcd).tcd_image).tiles as *mut core::ffi::c_void);
   (*(*p_tcd).tcd_image).tiles = std::ptr::null_mut::<opj_tcd_tile_t>();
 }
+/* *
+ * Is the whole tile made of one packet per component, in component order?
+ *
+ * This is the case of a single tile image with a single layer, a single
+ * resolution and a single precinct, coded reversibly without MCT: the
+ * packets can then be read without a packet iterator.
+ */
+unsafe fn opj_tcd_has_one_packet_per_component(mut p_tcd: *mut opj_tcd_t) -> bool {
+  let l_cp = &*(*p_tcd).cp;
+  let l_tcp = &*(*p_tcd).tcp;
+  let l_tile = &*(*(*p_tcd).tcd_image).tiles;
+  if !(*p_tcd).packet_index.is_null()
+    || (*p_tcd).whole_tile_decoding == 0
+    || l_cp.tw != 1
+    || l_cp.th != 1
+    || l_tcp.numlayers != 1
+    || l_tcp.num_layers_to_decode != 1
+    || l_tcp.POC
+    || l_tcp.mct != 0
+    || !matches!(l_tcp.prg, OPJ_LRCP | OPJ_RLCP | OPJ_CPRL)
+  {
+    return false;
+  }
+  for compno in 0..l_tile.numcomps as usize {
+    let l_tccp = &*l_tcp.tccps.add(compno);
+    let l_tilec = &*l_tile.comps.add(compno);
+    if l_tccp.numresolutions != 1
+      || l_tccp.qmfbid != 1
+      || l_tilec.minimum_num_resolutions != 1
+      || (*l_tilec.resolutions).pw * (*l_tilec.resolutions).ph != 1
+    {
+      return false;
+    }
+  }
+  true
+}
+
 unsafe fn opj_tcd_t2_decode(
   mut p_tcd: *mut opj_tcd_t,
   mut p_src_data: *mut OPJ_BYTE,
@@ -2136,18 +2223,30 @@ unsafe fn opj_tcd_t2_decode(
   if l_t2.is_null() {
     return 0i32;
   }
-  if opj_t2_decode_packets(
-    p_tcd,
-    l_t2,
-    (*p_tcd).tcd_tileno,
-    (*(*p_tcd).tcd_image).tiles,
-    p_src_data,
-    p_data_read,
-    p_max_src_size,
-    p_cstr_index,
-    p_manager,
-  ) == 0
-  {
+  let l_ok = if opj_tcd_has_one_packet_per_component(p_tcd) {
+    opj_t2_decode_component_packets(
+      l_t2,
+      (*p_tcd).tcd_tileno,
+      (*(*p_tcd).tcd_image).tiles,
+      p_src_data,
+      p_data_read,
+      p_max_src_size,
+      p_manager,
+    )
+  } else {
+    opj_t2_decode_packets(
+      p_tcd,
+      l_t2,
+      (*p_tcd).tcd_tileno,
+      (*(*p_tcd).tcd_image).tiles,
+      p_src_data,
+      p_data_read,
+      p_max_src_size,
+      p_cstr_index,
+      p_manager,
+    )
+  };
+  if l_ok == 0 {
     opj_t2_destroy(l_t2);
     return 0i32;
   }
@@ -2232,7 +2331,7 @@ unsafe fn opj_tcd_mct_decode(
   let mut l_tile_comp = (*l_tile).comps;
   let mut l_samples: OPJ_SIZE_T = 0;
   let mut i: OPJ_UINT32 = 0;
-  if (*l_tcp).mct == 0u32 || !(*p_tcd).used_component.is_null() {
+  if (*l_tcp).mct == 0u32 || !(*p_tcd).used_component.is_null() || (*(*p_tcd).cp).raw_components {
     return 1i32;
   }
   if (*p_tcd).whole_tile_decoding != 0 {
@@ -2413,6 +2512,7 @@ unsafe fn opj_tcd_dc_level_shift_decode(mut p_tcd: *mut opj_tcd_t) -> OPJ_BOOL {
   let mut l_min: OPJ_INT32 = 0;
   let mut l_max: OPJ_INT32 = 0;
   let mut l_stride: OPJ_UINT32 = 0;
+  let mut l_shift: OPJ_INT32 = 0;
   l_tile = (*(*p_tcd).tcd_image).tiles;
   l_tile_comp = (*l_tile).comps;
   l_tccp = (*(*p_tcd).tcp).tccps;
@@ -2452,7 +2552,14 @@ unsafe fn opj_tcd_dc_level_shift_decode(mut p_tcd: *mut opj_tcd_t) -> OPJ_BOOL {
       }
 
       if l_width != 0 && l_height != 0 {
-        if (*l_img_comp).sgnd != 0 {
+        l_shift = (*l_tccp).m_dc_level_shift;
+        if (*(*p_tcd).cp).raw_components {
+          /* Raw components are neither shifted nor clipped, the samples of */
+          /* the 9-7 wavelet are only rounded */
+          l_shift = 0;
+          l_min = OPJ_INT32::MIN;
+          l_max = OPJ_INT32::MAX
+        } else if (*l_img_comp).sgnd != 0 {
           l_min = -((1i32) << (*l_img_comp).prec.wrapping_sub(1u32));
           l_max = ((1i32) << (*l_img_comp).prec.wrapping_sub(1u32)) - 1i32
         } else {
@@ -2466,8 +2573,7 @@ unsafe fn opj_tcd_dc_level_shift_decode(mut p_tcd: *mut opj_tcd_t) -> OPJ_BOOL {
             i = 0 as OPJ_UINT32;
             while i < l_width {
               /* TODO: do addition on int64 ? */
-              *l_current_ptr =
-                opj_int_clamp(*l_current_ptr + (*l_tccp).m_dc_level_shift, l_min, l_max);
+              *l_current_ptr = opj_int_clamp(*l_current_ptr + l_shift, l_min, l_max);
               l_current_ptr = l_current_ptr.offset(1);
               i += 1;
             }
@@ -2488,7 +2594,7 @@ unsafe fn opj_tcd_dc_level_shift_decode(mut p_tcd: *mut opj_tcd_t) -> OPJ_BOOL {
                 /* Do addition on int64 to avoid overflows */
                 let mut l_value_int = opj_lrintf(l_value);
                 *l_current_ptr = opj_int64_clamp(
-                  l_value_int + (*l_tccp).m_dc_level_shift as i64,
+                  l_value_int + l_shift as i64,
                   l_min as OPJ_INT64,
                   l_max as OPJ_INT64,
                 ) as OPJ_INT32
@@ -2604,12 +2710,14 @@ pub(crate) fn opj_tcd_get_encoder_input_buffer_size(mut p_tcd: &mut opj_tcd_t) -
     if l_size_comp == 3u32 {
       l_size_comp = 4 as OPJ_UINT32
     }
-    l_data_size = (l_data_size as usize).wrapping_add(
-      (l_size_comp as usize).wrapping_mul(
-        ((l_tilec.x1 - l_tilec.x0) as OPJ_SIZE_T)
-          .wrapping_mul((l_tilec.y1 - l_tilec.y0) as OPJ_SIZE_T),
-      ),
-    ) as OPJ_SIZE_T as OPJ_SIZE_T;
+    /* Saturate on overflow, so that allocating the buffer fails */
+    l_data_size = opj_tcd_buffer_size(
+      (l_tilec.x1 - l_tilec.x0) as OPJ_SIZE_T,
+      (l_tilec.y1 - l_tilec.y0) as OPJ_SIZE_T,
+      l_size_comp as usize,
+    )
+    .and_then(|l_comp_size| l_data_size.checked_add(l_comp_size))
+    .unwrap_or(OPJ_SIZE_T::MAX);
   }
   l_data_size
 }
@@ -3093,3 +3201,16 @@ pub(crate) unsafe fn opj_tcd_marker_info_destroy(
   };
 }
 /* ----------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn buffer_size_overflow() {
+    assert_eq!(opj_tcd_buffer_size(16, 16, 4), Some(1024));
+    assert_eq!(opj_tcd_buffer_size(0, usize::MAX, 4), Some(0));
+    assert_eq!(opj_tcd_buffer_size(usize::MAX / 2, 3, 1), None);
+    assert_eq!(opj_tcd_buffer_size(usize::MAX / 4, 2, 4), None);
+  }
+}
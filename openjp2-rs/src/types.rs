@@ -32,9 +32,13 @@
  */
 
 use super::c_api_types::*;
+use super::consts::j2k::{J2K_CP_CSTY_ORIGIN_X, J2K_CP_CSTY_ORIGIN_Y};
 use super::j2k::*;
 
 use super::event::opj_event_mgr;
+use super::stream::MemoryWriter;
+use bitflags::bitflags;
+use core::ops::Range;
 
 #[derive(Clone)]
 pub(crate) struct EncoderParameters {
@@ -233,6 +237,15 @@ pub(crate) struct Stream {
   pub m_inner: super::stream::StreamInner,
   pub m_stream_length: OPJ_UINT64,
   pub m_byte_offset: OPJ_OFF_T,
+  /// Bytes read by the codec from the user's stream, skips and seeks
+  /// excluded.
+  pub m_bytes_read: OPJ_UINT64,
+  /// Sorted and disjoint ranges of the bytes counted in `m_bytes_read`.
+  pub m_read_ranges: Vec<(OPJ_OFF_T, OPJ_OFF_T)>,
+  /// Bytes written by the codec.
+  pub m_bytes_written: OPJ_UINT64,
+  /// Is the input the user's stream, not replaced by `replace_input`?
+  pub m_count_reads: bool,
 }
 pub(crate) type opj_stream_private = Stream;
 pub(crate) type opj_stream_private_t = Stream;
@@ -259,6 +272,8 @@ pub(crate) struct opj_jp2 {
   pub cl: Vec<u32>,
   pub comps: Vec<opj_jp2_comps>,
   pub j2k_codestream_offset: OPJ_OFF_T,
+  /// Write the codestream box with a 64-bit length (XLBox).
+  pub jp2c_xlbox: bool,
   pub jpip_iptr_offset: OPJ_OFF_T,
   pub jpip_on: OPJ_BOOL,
   pub jp2_state: OPJ_UINT32,
@@ -267,6 +282,23 @@ pub(crate) struct opj_jp2 {
   pub ignore_pclr_cmap_cdef: OPJ_BOOL,
   pub has_jp2h: OPJ_BYTE,
   pub has_ihdr: OPJ_BYTE,
+  pub has_bpcc: OPJ_BYTE,
+  /// Keep the top-level boxes read by the decoder in `boxes`.
+  pub capture_boxes: bool,
+  /// Top-level boxes read by the decoder, or to be written by the encoder.
+  pub boxes: Vec<RawBox>,
+  /// `uuid` boxes to be written by the encoder after the preserved boxes.
+  pub uuid_boxes: Vec<RawBox>,
+  /// Contents of the intellectual property box read by the decoder, or to
+  /// be written by the encoder.
+  pub ipr: Option<Vec<u8>>,
+  /// GeoTIFF blob of the GeoJP2 `uuid` box read by the decoder.
+  pub geo_metadata: Option<Vec<u8>>,
+  /// Reader requirements read by the decoder, or to be written by the
+  /// encoder.
+  pub reader_requirements: Option<ReaderRequirements>,
+  /// Top-level association boxes read by the decoder.
+  pub associations: Vec<Association>,
 }
 
 #[derive(Clone)]
@@ -337,6 +369,35 @@ pub(crate) struct opj_j2k {
   pub ihdr_w: OPJ_UINT32,
   pub ihdr_h: OPJ_UINT32,
   pub dump_state: core::ffi::c_uint,
+  /// COM marker contents: `(Rcom, Ccom)`.
+  pub m_comments: Vec<(u16, Vec<u8>)>,
+  /// Component precisions of the image the encoder was set up with.
+  pub m_comp_prec: Vec<OPJ_UINT32>,
+  /// Component subsampling `(dx, dy)` of the image the encoder was set up
+  /// with.
+  pub m_comp_subsampling: Vec<(OPJ_UINT32, OPJ_UINT32)>,
+  /// Tiles decoded by the last `opj_j2k_decode()`.
+  pub m_decoded_region: DecodedRegion,
+  /// Pcap and Ccap values of the CAP marker.
+  pub m_capabilities: Capabilities,
+  /// Features needed by the markers of the main header.
+  pub m_required_features: FeatureSet,
+  /// DC level shifts set with `opj_decoder_set_dc_shift()`, by component.
+  pub m_dc_shift_overrides: Vec<Option<OPJ_INT32>>,
+  /// Write the MD5 of the image samples in a COM marker.
+  pub m_embed_md5: bool,
+  /// Codestream written while embedding the MD5, copied to the output
+  /// stream by `opj_j2k_end_compress` once the digest is known.
+  pub m_md5_codestream: Option<MemoryWriter>,
+  /// Offset of the digest in `m_md5_codestream`.
+  pub m_md5_offset: usize,
+  /// Packet lengths read from the PLT markers, by tile.
+  pub m_packet_lengths: std::collections::BTreeMap<OPJ_UINT32, Vec<OPJ_UINT32>>,
+  /// Order of the tile-parts written by the encoder.
+  pub m_layout: CodestreamLayout,
+  /// Tile-parts of each encoded tile, `(tile index, tile-parts)`, kept
+  /// until the end of the encoding to be interleaved.
+  pub m_tile_parts: Vec<(OPJ_UINT32, Vec<Vec<u8>>)>,
 }
 
 #[derive(Copy, Clone)]
@@ -358,6 +419,9 @@ pub(crate) struct opj_tcd {
   pub win_y1: OPJ_UINT32,
   pub whole_tile_decoding: OPJ_BOOL,
   pub used_component: *mut OPJ_BOOL,
+  /// When set, only the packet headers are read and the packets are
+  /// recorded here, see `opj_j2k_build_packet_index`.
+  pub packet_index: *mut Vec<PacketPosition>,
 }
 pub(crate) type opj_tcd_t = opj_tcd;
 
@@ -398,6 +462,16 @@ pub(crate) struct opj_tcp {
 }
 pub(crate) type opj_tcp_t = opj_tcp;
 
+impl opj_tcp {
+  /// Origin of the precinct and code-block partitions, 0 or 1 on each axis.
+  pub(crate) fn partition_origin(&self) -> (OPJ_UINT32, OPJ_UINT32) {
+    (
+      (self.csty & J2K_CP_CSTY_ORIGIN_X != 0) as OPJ_UINT32,
+      (self.csty & J2K_CP_CSTY_ORIGIN_Y != 0) as OPJ_UINT32,
+    )
+  }
+}
+
 #[derive(Copy, Clone)]
 pub(crate) struct opj_simple_mcc_decorrelation_data {
   pub m_index: OPJ_UINT32,
@@ -480,9 +554,23 @@ pub(crate) struct opj_cp {
   pub tcps: *mut opj_tcp_t,
   pub m_specific_param: C2RustUnnamed_0,
   pub strict: OPJ_BOOL,
+  /// Return the decoded tiles instead of failing on a truncated codestream.
+  pub partial: bool,
+  /// Checks of the code-block data done by the decoder.
+  pub error_resilience: ErrorResilienceFlags,
+  /// Limits on the codestreams accepted by the decoder.
+  pub limits: DecodeLimits,
+  /// Check the main header against the cinema or IMF profile of `rsiz`.
+  pub validate_profile: bool,
+  /// Detect tiles with more tile-parts than their `TNsot` (issue 254).
+  pub tile_parts_correction: bool,
+  /// Skip the multiple component transform and the DC level shift of the
+  /// decoded tiles.
+  pub raw_components: bool,
+  /// Number of threads used to decode the code-blocks of a tile component.
+  pub num_threads: OPJ_UINT32,
   pub ppm: bool,
   pub m_is_decoder: bool,
-  pub allow_different_bit_depth_sign: bool,
 }
 pub(crate) type opj_cp_t = opj_cp;
 
@@ -546,6 +634,639 @@ pub(crate) struct opj_tcd_tile {
 }
 pub(crate) type opj_tcd_tile_t = opj_tcd_tile;
 
+/// Contents of a COM (comment) marker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comment {
+  /// Rcom = 1: ISO/IEC 8859-15 (Latin) text.
+  Text(String),
+  /// Rcom = 0 (binary) or a reserved registration value.
+  Binary(Vec<u8>),
+}
+
+/// Quantization of the irreversible (9-7) wavelet subbands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuantStyle {
+  /// Scalar derived: only the step size of the LL subband is signalled,
+  /// the others are derived from it.
+  Derived,
+  /// Scalar expounded: one `(mantissa, exponent)` step size per subband, in
+  /// codestream order (LL, then HL, LH, HH of each resolution). The mantissa
+  /// has 11 bits and the exponent 5 bits.
+  Explicit { steps: Vec<(u16, u8)> },
+}
+
+/// Colour specification from the `colr` box of a JP2 file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Jp2ColorSpec {
+  /// No `colr` box was read, or the codec isn't a JP2 decoder.
+  Unspecified,
+  /// METH = 1: enumerated colour space, with the `EnumCS` value.
+  Enumerated(u32),
+  /// METH = 2: restricted ICC profile, see `opj_image::icc_profile()`.
+  IccProfile,
+}
+
+impl Jp2ColorSpec {
+  /// The matching image colour space. ICC profiles and enumerated colour
+  /// spaces not supported by the library map to `OPJ_CLRSPC_UNKNOWN`.
+  pub fn color_space(&self) -> COLOR_SPACE {
+    match self {
+      Self::Unspecified => OPJ_CLRSPC_UNSPECIFIED,
+      Self::Enumerated(16) => OPJ_CLRSPC_SRGB,
+      Self::Enumerated(17) => OPJ_CLRSPC_GRAY,
+      Self::Enumerated(18) => OPJ_CLRSPC_SYCC,
+      Self::Enumerated(24) => OPJ_CLRSPC_EYCC,
+      Self::Enumerated(12) => OPJ_CLRSPC_CMYK,
+      Self::Enumerated(_) | Self::IccProfile => OPJ_CLRSPC_UNKNOWN,
+    }
+  }
+}
+
+/// Part of the image decoded by the last `opj_decode`, see
+/// `opj_decoder_set_partial`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodedRegion {
+  /// Number of tiles of the image.
+  pub nb_tiles: u32,
+  /// Indices of the decoded tiles, in decoding order.
+  pub tiles: Vec<u32>,
+  /// Did decoding stop early because the codestream is truncated or
+  /// corrupted? The samples of the tiles that weren't decoded are zero.
+  pub truncated: bool,
+  /// Status of each tile intersecting the decoded area, in index order.
+  pub tile_status: Vec<TileStatus>,
+}
+
+/// Decoding status of a tile, see `opj_get_tile_status`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TileStatus {
+  /// Index of the tile in the image.
+  pub index: u32,
+  /// Was the tile decoded into the image?
+  pub decoded: bool,
+  /// Number of bytes of compressed data read for the tile.
+  pub bytes: u64,
+  /// Why the tile wasn't decoded, if decoding stopped on it or before it.
+  pub error: Option<String>,
+}
+
+/// Limits on the codestreams accepted by a decoder, see
+/// `opj_decoder_set_limits`. The defaults are the maxima allowed by the
+/// standard, so that no valid codestream is rejected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecodeLimits {
+  /// Maximum number of components (Csiz of the SIZ marker).
+  pub max_components: u32,
+  /// Maximum number of resolutions of a tile component: the number of
+  /// decomposition levels of the COD and COC markers plus one.
+  pub max_resolutions: u32,
+  /// Maximum number of quality layers (SGcod of the COD markers).
+  pub max_layers: u32,
+  /// Maximum number of tiles of the image.
+  pub max_tiles: u32,
+}
+
+impl Default for DecodeLimits {
+  fn default() -> Self {
+    Self {
+      max_components: 16384,
+      max_resolutions: 33,
+      max_layers: 65535,
+      max_tiles: 65535,
+    }
+  }
+}
+
+impl DecodedRegion {
+  /// Was the whole image decoded?
+  pub fn is_complete(&self) -> bool {
+    !self.truncated
+  }
+}
+
+/// Capabilities signaled by a codestream in the Rsiz field of the SIZ
+/// marker and in the CAP and CPF markers, see `opj_get_capabilities`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+  /// Rsiz field of the SIZ marker.
+  pub rsiz: u16,
+  /// Pcap field of the CAP marker: the bit `32 - i` is set when the
+  /// codestream uses capabilities of Part i.
+  pub pcap: u32,
+  /// Ccap fields of the CAP marker, one for each part set in `pcap`, in
+  /// increasing part order.
+  pub ccap: Vec<u16>,
+  /// Pcpf fields of the CPF marker of a HTJ2K codestream, least significant
+  /// first, see `corresponding_profile()`.
+  pub cpf: Vec<u16>,
+}
+
+impl Capabilities {
+  /// Part 15 (HTJ2K).
+  pub const PART_HTJ2K: u32 = 15;
+
+  /// Does Rsiz signal a CAP marker?
+  pub fn has_extended_capabilities(&self) -> bool {
+    self.rsiz & 0x4000 != 0
+  }
+
+  /// Does Rsiz signal Part 2 extensions?
+  pub fn has_part2_extensions(&self) -> bool {
+    self.rsiz & 0x8000 != 0
+  }
+
+  /// Part 2 extensions signaled by Rsiz, e.g. `OPJ_EXTENSION_MCT`.
+  pub fn part2_extensions(&self) -> u16 {
+    if self.has_part2_extensions() {
+      self.rsiz & 0x3fff
+    } else {
+      0
+    }
+  }
+
+  /// Does the codestream use capabilities of Part `part`?
+  pub fn uses_part(&self, part: u32) -> bool {
+    (1..=32).contains(&part) && self.pcap & (1 << (32 - part)) != 0
+  }
+
+  /// Is the codestream a HTJ2K (Part 15) codestream?
+  pub fn is_htj2k(&self) -> bool {
+    self.uses_part(Self::PART_HTJ2K)
+  }
+
+  /// Profile of the codestream corresponding to a HTJ2K codestream, i.e.
+  /// its Rsiz once transcoded to the Part 1 block coder, from the CPF
+  /// marker. `None` without CPF marker or if the value exceeds 64 bits.
+  pub fn corresponding_profile(&self) -> Option<u64> {
+    if self.cpf.is_empty() || self.cpf.iter().skip(4).any(|&pcpf| pcpf != 0) {
+      return None;
+    }
+    Some(
+      self
+        .cpf
+        .iter()
+        .take(4)
+        .enumerate()
+        .map(|(i, &pcpf)| (pcpf as u64) << (16 * i))
+        .sum(),
+    )
+  }
+
+  /// Ccap value of Part `part`.
+  pub fn ccap(&self, part: u32) -> Option<u16> {
+    if !self.uses_part(part) {
+      return None;
+    }
+    self.ccap.get(self.ccap_index(part)).copied()
+  }
+
+  /// Set the Ccap value of Part `part`.
+  pub fn set(&mut self, part: u32, ccap: u16) -> bool {
+    if !(1..=32).contains(&part) {
+      return false;
+    }
+    let index = self.ccap_index(part);
+    if self.uses_part(part) {
+      self.ccap[index] = ccap;
+    } else {
+      self.pcap |= 1 << (32 - part);
+      self.ccap.insert(index, ccap);
+    }
+    true
+  }
+
+  /// Index in `ccap` of Part `part`: the number of lower parts used.
+  fn ccap_index(&self, part: u32) -> usize {
+    ((self.pcap as u64) >> (33 - part)).count_ones() as usize
+  }
+}
+
+/// UUID of the GeoJP2 `uuid` box, followed by a GeoTIFF blob.
+pub const GEOJP2_UUID: [u8; 16] = [
+  0xb1, 0x4b, 0xf8, 0xbd, 0x08, 0x3d, 0x4b, 0x43, 0xa5, 0xae, 0x8c, 0xd7, 0xd5, 0xa6, 0xce, 0x03,
+];
+
+/// A top-level JP2 box, see `opj_get_boxes` and `opj_encoder_preserve_boxes`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RawBox {
+  /// Box type, e.g. `*b"xml "`.
+  pub ty: [u8; 4],
+  /// Contents of the box, without its header.
+  pub data: Vec<u8>,
+  /// Does the box follow the codestream box?
+  pub after_codestream: bool,
+}
+
+/// A JPX association box (`asoc`), see `opj_get_associations`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Association {
+  /// Contents of the first label box (`lbl `) of the association.
+  pub label: Option<String>,
+  /// Nested association boxes, in file order.
+  pub children: Vec<Association>,
+  /// Other boxes of the association, in file order, e.g. `xml ` boxes or
+  /// number list boxes (`nlst`) referencing codestreams.
+  pub raw_boxes: Vec<RawBox>,
+}
+
+impl RawBox {
+  /// A `uuid` box: the 16 bytes of `uuid` followed by `data`, written
+  /// before the codestream box.
+  pub fn new_uuid(uuid: [u8; 16], mut data: Vec<u8>) -> Self {
+    data.splice(0..0, uuid);
+    Self {
+      ty: *b"uuid",
+      data,
+      after_codestream: false,
+    }
+  }
+
+  /// The UUID and the data of a `uuid` box, `None` for other boxes.
+  pub fn uuid(&self) -> Option<([u8; 16], &[u8])> {
+    if &self.ty != b"uuid" || self.data.len() < 16 {
+      return None;
+    }
+    let mut uuid = [0; 16];
+    uuid.copy_from_slice(&self.data[..16]);
+    Some((uuid, &self.data[16..]))
+  }
+}
+
+bitflags! {
+  /// Error resilience checks of the decoder, see
+  /// `opj_decoder_set_error_resilience`.
+  #[derive(Default)]
+  pub struct ErrorResilienceFlags: u32 {
+    /// Check the segmentation symbol ending each cleanup pass of the
+    /// code-blocks coded with the SEGMARK mode switch.
+    const CHECK_SEGMARK = 0x01;
+    /// Fail decoding at the first code-block failing a check, of its
+    /// segmentation symbols or of its predictable termination (ERTERM),
+    /// instead of decoding corrupt samples.
+    const STOP_ON_ERROR = 0x02;
+  }
+}
+
+bitflags! {
+  /// Optional decoding paths needed by a codestream, see
+  /// `opj_get_required_features`.
+  #[derive(Default)]
+  pub struct FeatureSet: u32 {
+    /// High throughput block coder (Part 15), signaled by the CAP marker.
+    const HTJ2K = 0x01;
+    /// JPEG 2000 for wireless (Part 11): EPC, EPB, ESD or RED markers.
+    const JPWL = 0x02;
+    /// Secure JPEG 2000 (Part 8): SEC or INSEC markers.
+    const JPSPEC = 0x04;
+  }
+}
+
+impl FeatureSet {
+  /// Features this build of the crate can decode. The markers of Part 11
+  /// and Part 8 aren't decoded.
+  pub fn supported() -> Self {
+    Self::HTJ2K
+  }
+
+  /// Features needed to read the marker `id`.
+  pub(crate) fn from_marker(id: u32) -> Self {
+    match id {
+      0xff66..=0xff69 => Self::JPWL,
+      0xff65 | 0xff94 => Self::JPSPEC,
+      _ => Self::empty(),
+    }
+  }
+}
+
+bitflags! {
+  /// Mode switches of the code-block coding style, see
+  /// `opj_encoder_set_mode_switches`.
+  #[derive(Default)]
+  pub struct ModeSwitches: u32 {
+    /// Selective arithmetic coding bypass (BYPASS or LAZY).
+    const BYPASS = 0x01;
+    /// Reset of the context probabilities on coding pass boundaries.
+    const RESET = 0x02;
+    /// Termination on each coding pass (RESTART or TERMALL).
+    const RESTART = 0x04;
+    /// Vertically causal context.
+    const VSC = 0x08;
+    /// Predictable termination (ERTERM or PTERM).
+    const ERTERM = 0x10;
+    /// Segmentation symbols (SEGMARK or SEGSYM).
+    const SEGMARK = 0x20;
+  }
+}
+
+/// Wavelet transform of the tile-components.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Wavelet {
+  /// Reversible 5-3 integer transform, for lossless coding.
+  #[default]
+  Reversible53,
+  /// Irreversible 9-7 floating point transform.
+  Irreversible97,
+}
+
+/// Coding parameters of the COD marker, set together by
+/// `opj_encoder_set_coding_style`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodingStyle {
+  /// Number of resolutions, in `[1, 33]`.
+  pub resolutions: u32,
+  /// Code-block width and height, powers of 2 in `[4, 1024]` whose product
+  /// is at most 4096.
+  pub codeblock: (u32, u32),
+  /// Precinct widths and heights, powers of 2 in `[2, 32768]`, from the
+  /// highest resolution down. The lower resolutions without a size get the
+  /// last one halved. Empty for a single precinct per resolution.
+  pub precincts: Vec<(u32, u32)>,
+  pub mode_switches: ModeSwitches,
+  /// Start of packet marker before each packet.
+  pub sop: bool,
+  /// End of packet header marker after each packet header.
+  pub eph: bool,
+  /// Origin of the precinct and code-block partitions, 0 or 1 on each
+  /// axis. A JPEG 2000 Part 2 extension, Part 1 decoders reject the
+  /// codestream when it isn't `(0, 0)`.
+  pub partition_origin: (u32, u32),
+  /// Number of guard bits, in `[0, 7]`.
+  pub guard_bits: u32,
+  /// Multiple component transform of the first 3 components: RCT with the
+  /// reversible wavelet, ICT with the irreversible one.
+  pub mct: bool,
+  pub wavelet: Wavelet,
+}
+
+impl Default for CodingStyle {
+  /// The defaults of `opj_cparameters_t`.
+  fn default() -> Self {
+    Self {
+      resolutions: 6,
+      codeblock: (64, 64),
+      precincts: Vec::new(),
+      mode_switches: ModeSwitches::empty(),
+      sop: false,
+      eph: false,
+      partition_origin: (0, 0),
+      guard_bits: 2,
+      mct: false,
+      wavelet: Wavelet::Reversible53,
+    }
+  }
+}
+
+/// Contents of a JP2 reader requirements box (`rreq`), see
+/// `opj_get_reader_requirements`.
+///
+/// Masks are stored in the low bits of a `u64`, bit 0 of the mask is the
+/// last bit of the box field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReaderRequirements {
+  /// Fully understand aspects mask (FUAM).
+  pub fuam: u64,
+  /// Decode completely mask (DCM).
+  pub dcm: u64,
+  /// Standard features (SF) and their masks (SM).
+  pub standard_features: Vec<(u16, u64)>,
+  /// Vendor features (VF) and their masks (VM).
+  pub vendor_features: Vec<([u8; 16], u64)>,
+}
+
+/// Precinct `precno` of resolution `resno` of component `compno` of tile
+/// `tile_index`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PrecinctIndex {
+  pub tile_index: OPJ_UINT32,
+  pub compno: OPJ_UINT32,
+  pub resno: OPJ_UINT32,
+  pub precno: OPJ_UINT32,
+}
+
+/// Samples of the area covered by a precinct, see `opj_decode_precinct`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrecinctData {
+  /// Area covered by the precinct, in coordinates of its resolution level.
+  pub x0: u32,
+  pub y0: u32,
+  pub x1: u32,
+  pub y1: u32,
+  /// Precision of the component.
+  pub prec: u32,
+  /// Are the samples signed?
+  pub sgnd: bool,
+  /// Samples of the area, row by row.
+  pub data: Vec<i32>,
+}
+
+impl PrecinctData {
+  pub fn width(&self) -> u32 {
+    self.x1 - self.x0
+  }
+
+  pub fn height(&self) -> u32 {
+    self.y1 - self.y0
+  }
+}
+
+/// Position of a packet in a tile, see `opj_get_packet_order`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PacketCoord {
+  pub layer: u32,
+  pub resolution: u32,
+  pub component: u32,
+  /// Precinct index in the resolution level, in raster order.
+  pub precinct: u32,
+}
+
+/// Location of a packet in the stream, see `opj_build_packet_index`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketPosition {
+  pub tile: u32,
+  pub coord: PacketCoord,
+  /// Offset of the first byte of the packet in the stream.
+  pub offset: u64,
+  /// Length of the packet in the stream. With PPM or PPT markers, the
+  /// packet header isn't included.
+  pub length: u32,
+}
+
+/// Location of the packets of a codestream, see `opj_build_packet_index`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketIndex {
+  /// Packets in the order they appear in the stream.
+  pub packets: Vec<PacketPosition>,
+}
+
+impl PacketIndex {
+  /// Packets of a tile, in the order they appear in the stream.
+  pub fn tile(&self, tile: u32) -> impl Iterator<Item = &PacketPosition> + '_ {
+    self
+      .packets
+      .iter()
+      .filter(move |packet| packet.tile == tile)
+  }
+}
+
+/// Location of the tile-parts of a tile in the stream, see
+/// `opj_get_codestream_byte_ranges`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TileByteRange {
+  pub tile: u32,
+  /// Tile-parts in index order, from their SOT marker to the end of their
+  /// data.
+  pub tile_parts: Vec<Range<u64>>,
+}
+
+/// Location of the main header and of the tiles of a codestream, see
+/// `opj_get_codestream_byte_ranges`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodestreamByteRanges {
+  /// From the SOC marker to the first SOT marker.
+  pub main_header: Range<u64>,
+  /// Tiles read from the stream, in index order.
+  pub tiles: Vec<TileByteRange>,
+}
+
+/// A progression order change, see `opj_encoder_add_progression_change`.
+///
+/// The packets of layers `0..layer_end`, resolutions `res_start..res_end`
+/// and components `comp_start..comp_end` not written by a previous change
+/// are written in the progression order `order`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PocSpec {
+  pub res_start: u32,
+  pub comp_start: u32,
+  pub layer_end: u32,
+  pub res_end: u32,
+  pub comp_end: u32,
+  pub order: OPJ_PROG_ORDER,
+}
+
+/// Progression order of the packets of a tile, from the COD marker, see
+/// `opj_get_progression` and `opj_encoder_set_progression`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Progression {
+  /// Layer-resolution-component-position.
+  #[default]
+  LRCP,
+  /// Resolution-layer-component-position.
+  RLCP,
+  /// Resolution-position-component-layer.
+  RPCL,
+  /// Position-component-resolution-layer.
+  PCRL,
+  /// Component-position-resolution-layer.
+  CPRL,
+}
+
+impl Progression {
+  /// The progression order of an `OPJ_PROG_ORDER`, `None` for
+  /// `OPJ_PROG_UNKNOWN`.
+  pub fn from_c_enum(order: OPJ_PROG_ORDER) -> Option<Self> {
+    match order {
+      OPJ_LRCP => Some(Self::LRCP),
+      OPJ_RLCP => Some(Self::RLCP),
+      OPJ_RPCL => Some(Self::RPCL),
+      OPJ_PCRL => Some(Self::PCRL),
+      OPJ_CPRL => Some(Self::CPRL),
+      _ => None,
+    }
+  }
+
+  pub fn to_c_enum(self) -> OPJ_PROG_ORDER {
+    match self {
+      Self::LRCP => OPJ_LRCP,
+      Self::RLCP => OPJ_RLCP,
+      Self::RPCL => OPJ_RPCL,
+      Self::PCRL => OPJ_PCRL,
+      Self::CPRL => OPJ_CPRL,
+    }
+  }
+}
+
+/// Order of the tile-parts in the codestream written by the encoder, see
+/// `opj_encoder_set_codestream_layout`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CodestreamLayout {
+  /// All the tile-parts of a tile, then those of the next tile.
+  #[default]
+  TileFirst,
+  /// One tile-part per quality layer, the first tile-part of every tile,
+  /// then the second one of every tile... A prefix of the codestream
+  /// ending after the tile-parts of a layer holds the lower layers.
+  LayerFirst,
+  /// Like `LayerFirst`, with one tile-part per resolution level: a prefix
+  /// holds the lower resolutions.
+  ResolutionFirst,
+}
+
+/// Structural error found by `opj_validate_structure`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StructureError {
+  /// Offset of the faulty box or marker in the data.
+  pub offset: usize,
+  pub message: &'static str,
+}
+
+impl core::fmt::Display for StructureError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{} at offset {}", self.message, self.offset)
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StructureError {}
+
+/// ISO/IEC 8859-15 differs from ISO/IEC 8859-1 in only these code points.
+const LATIN9_DIFF: [(u8, char); 8] = [
+  (0xA4, '\u{20AC}'),
+  (0xA6, '\u{0160}'),
+  (0xA8, '\u{0161}'),
+  (0xB4, '\u{017D}'),
+  (0xB8, '\u{017E}'),
+  (0xBC, '\u{0152}'),
+  (0xBD, '\u{0153}'),
+  (0xBE, '\u{0178}'),
+];
+
+impl Comment {
+  pub(crate) fn from_marker(rcom: u16, data: &[u8]) -> Self {
+    if rcom != 1 {
+      return Self::Binary(data.to_vec());
+    }
+    Self::Text(
+      data
+        .iter()
+        .map(|&b| {
+          LATIN9_DIFF
+            .iter()
+            .find(|(code, _)| *code == b)
+            .map_or(b as char, |(_, c)| *c)
+        })
+        .collect(),
+    )
+  }
+
+  /// Encode `text` as ISO/IEC 8859-15, returns `None` if a character
+  /// can't be represented.
+  pub(crate) fn encode_text(text: &str) -> Option<Vec<u8>> {
+    text
+      .chars()
+      .map(|c| {
+        if let Some((code, _)) = LATIN9_DIFF.iter().find(|(_, l)| *l == c) {
+          return Some(*code);
+        }
+        if (c as u32) < 0x100 && !LATIN9_DIFF.iter().any(|(code, _)| *code as u32 == c as u32) {
+          Some(c as u8)
+        } else {
+          None
+        }
+      })
+      .collect()
+  }
+}
+
 #[derive(Clone, Default)]
 pub struct TileInfo {
   pub index: u32,
@@ -780,5 +1501,7 @@ pub(crate) struct opj_j2k_dec {
   pub m_skip_data: bool,
   pub m_nb_tile_parts_correction_checked: bool,
   pub m_nb_tile_parts_correction: bool,
+  /** Marker ID read after an empty tile-part and not handled yet, 0 if none */
+  pub m_next_marker: OPJ_UINT32,
 }
 pub(crate) type opj_j2k_dec_t = opj_j2k_dec;
@@ -0,0 +1,501 @@
+/*
+ * The copyright in this software is being made available under the 2-clauses
+ * BSD License, included below. This software may be subject to other third
+ * party and contributor rights, including patent rights, and no such rights
+ * are granted under this license.
+ *
+ * Copyright (c) 2002-2014, Universite catholique de Louvain (UCL), Belgium
+ * Copyright (c) 2002-2014, Professor Benoit Macq
+ * Copyright (c) 2002-2003, Yannick Verschueren
+ * Copyright (c) 2005, Herve Drolon, FreeImage Team
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS `AS IS'
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE COPYRIGHT OWNER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! Structural validation of JP2 files and J2K codestreams.
+//!
+//! Boxes and marker segments are walked without decoding anything: their
+//! lengths must stay within the data, and the mandatory boxes and markers
+//! must be present in the right order. Nothing is allocated, so this can be
+//! used to reject malformed input early.
+
+use super::j2k::J2KMarker;
+use super::types::StructureError;
+
+/// Contents of the JPEG 2000 signature box.
+const JP2_SIGNATURE: [u8; 4] = [0x0d, 0x0a, 0x87, 0x0a];
+
+const J2K_MS_SOC: u16 = 0xff4f;
+const J2K_MS_SIZ: u16 = 0xff51;
+const J2K_MS_COD: u16 = 0xff52;
+const J2K_MS_COC: u16 = 0xff53;
+const J2K_MS_QCD: u16 = 0xff5c;
+const J2K_MS_SOT: u16 = 0xff90;
+const J2K_MS_SOD: u16 = 0xff93;
+const J2K_MS_EOC: u16 = 0xffd9;
+
+fn error<T>(offset: usize, message: &'static str) -> Result<T, StructureError> {
+  Err(StructureError { offset, message })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+  let bytes = data.get(offset..offset.checked_add(2)?)?;
+  Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+  let bytes = data.get(offset..offset.checked_add(4)?)?;
+  Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+  Some(((read_u32(data, offset)? as u64) << 32) | read_u32(data, offset + 4)? as u64)
+}
+
+/// A box: its type and the range of its contents.
+struct BoxRange {
+  ty: [u8; 4],
+  start: usize,
+  end: usize,
+}
+
+/// Read the header of the box at `offset`, which must end before `end`.
+fn opj_validate_box(data: &[u8], offset: usize, end: usize) -> Result<BoxRange, StructureError> {
+  let (lbox, tbox) = match (read_u32(data, offset), read_u32(data, offset + 4)) {
+    (Some(lbox), Some(tbox)) if offset + 8 <= end => (lbox, tbox),
+    _ => return error(offset, "Truncated box header"),
+  };
+  let ty = tbox.to_be_bytes();
+  let (length, header_length) = match lbox {
+    /* The box extends to the end of its container */
+    0 => ((end - offset) as u64, 8),
+    1 => match read_u64(data, offset + 8) {
+      Some(xlbox) if offset + 16 <= end => (xlbox, 16),
+      _ => return error(offset, "Truncated box header"),
+    },
+    lbox => (lbox as u64, 8),
+  };
+  if length < header_length as u64 {
+    return error(offset, "Box length is smaller than its header");
+  }
+  if length > (end - offset) as u64 {
+    return error(offset, "Box length exceeds the data");
+  }
+  Ok(BoxRange {
+    ty,
+    start: offset + header_length,
+    end: offset + length as usize,
+  })
+}
+
+/// Validate a JP2 file: signature, file type, JP2 header and codestream
+/// boxes, in this order.
+fn opj_validate_jp2(data: &[u8]) -> Result<(), StructureError> {
+  let signature = opj_validate_box(data, 0, data.len())?;
+  if &signature.ty != b"jP  " || data[signature.start..signature.end] != JP2_SIGNATURE {
+    return error(0, "Invalid JPEG 2000 signature box");
+  }
+  let ftyp = opj_validate_box(data, signature.end, data.len())?;
+  if &ftyp.ty != b"ftyp" {
+    return error(
+      signature.end,
+      "The file type box must follow the signature box",
+    );
+  }
+  /* BR(4), MinV(4) and CL(4 each) */
+  let ftyp_len = ftyp.end - ftyp.start;
+  if ftyp_len < 8 || ftyp_len % 4 != 0 {
+    return error(signature.end, "Invalid file type box length");
+  }
+
+  let mut has_jp2h = false;
+  let mut offset = ftyp.end;
+  while offset < data.len() {
+    let b = opj_validate_box(data, offset, data.len())?;
+    match &b.ty {
+      b"jp2h" => {
+        if has_jp2h {
+          return error(offset, "Duplicate JP2 header box");
+        }
+        opj_validate_jp2h(data, &b, offset)?;
+        has_jp2h = true;
+      }
+      b"jp2c" => {
+        if !has_jp2h {
+          return error(offset, "The JP2 header box must precede the codestream box");
+        }
+        return opj_validate_j2k(&data[b.start..b.end]).map_err(|e| StructureError {
+          offset: b.start + e.offset,
+          message: e.message,
+        });
+      }
+      b"jP  " | b"ftyp" => return error(offset, "Duplicate signature or file type box"),
+      _ => (),
+    }
+    offset = b.end;
+  }
+  if !has_jp2h {
+    return error(offset, "Missing JP2 header box");
+  }
+  error(offset, "Missing codestream box")
+}
+
+/// Validate the sub-boxes of the JP2 header box, starting with the image
+/// header box.
+fn opj_validate_jp2h(
+  data: &[u8],
+  jp2h: &BoxRange,
+  jp2h_offset: usize,
+) -> Result<(), StructureError> {
+  let mut offset = jp2h.start;
+  while offset < jp2h.end {
+    let b = opj_validate_box(data, offset, jp2h.end)?;
+    if offset == jp2h.start {
+      if &b.ty != b"ihdr" {
+        return error(
+          offset,
+          "The image header box must be the first box of the JP2 header box",
+        );
+      }
+      /* HEIGHT(4) WIDTH(4) NC(2) BPC(1) C(1) UnkC(1) IPR(1) */
+      if b.end - b.start != 14 {
+        return error(offset, "Invalid image header box length");
+      }
+    }
+    offset = b.end;
+  }
+  if offset == jp2h.start {
+    return error(jp2h_offset, "Empty JP2 header box");
+  }
+  Ok(())
+}
+
+/// Offset of the end of the marker segment at `offset`.
+fn opj_validate_marker_segment(data: &[u8], offset: usize) -> Result<usize, StructureError> {
+  let marker = match read_u16(data, offset) {
+    Some(marker) => marker,
+    None => return error(offset, "Truncated marker"),
+  };
+  if marker < 0xff00 {
+    return error(offset, "Expected a marker");
+  }
+  /* Markers without marker segment */
+  if (0xff30..=0xff3f).contains(&marker) {
+    return Ok(offset + 2);
+  }
+  let length = match read_u16(data, offset + 2) {
+    Some(length) => length as usize,
+    None => return error(offset, "Truncated marker segment"),
+  };
+  if length < 2 {
+    return error(offset, "Invalid marker segment length");
+  }
+  if offset + 2 + length > data.len() {
+    return error(offset, "Marker segment length exceeds the data");
+  }
+  Ok(offset + 2 + length)
+}
+
+/// Validate the SIZ marker segment at `offset`, returns the number of tiles.
+fn opj_validate_siz(data: &[u8], offset: usize) -> Result<u64, StructureError> {
+  let field = |pos: usize| read_u32(data, offset + pos).map(|v| v as u64).unwrap_or(0);
+  let lsiz = read_u16(data, offset + 2).unwrap_or(0) as usize;
+  let csiz = read_u16(data, offset + 38).unwrap_or(0) as usize;
+  if lsiz < 38 || csiz == 0 || csiz > 16384 || lsiz != 38 + 3 * csiz {
+    return error(offset, "Invalid SIZ marker segment length");
+  }
+  let (xsiz, ysiz, xosiz, yosiz) = (field(6), field(10), field(14), field(18));
+  let (xtsiz, ytsiz, xtosiz, ytosiz) = (field(22), field(26), field(30), field(34));
+  if xosiz >= xsiz || yosiz >= ysiz {
+    return error(offset, "Invalid image size");
+  }
+  if xtsiz == 0
+    || ytsiz == 0
+    || xtosiz > xosiz
+    || ytosiz > yosiz
+    || xtosiz + xtsiz <= xosiz
+    || ytosiz + ytsiz <= yosiz
+  {
+    return error(offset, "Invalid tile size or offset");
+  }
+  let tw = (xsiz - xtosiz).div_ceil(xtsiz);
+  let th = (ysiz - ytosiz).div_ceil(ytsiz);
+  Ok(tw * th)
+}
+
+/// Validate a J2K codestream: main header starting with SOC and SIZ and
+/// containing COD and QCD, tile-parts, then EOC.
+fn opj_validate_j2k(data: &[u8]) -> Result<(), StructureError> {
+  if read_u16(data, 0) != Some(J2K_MS_SOC) {
+    return error(0, "The codestream must start with a SOC marker");
+  }
+  if read_u16(data, 2) != Some(J2K_MS_SIZ) {
+    return error(2, "The SIZ marker must follow the SOC marker");
+  }
+  let mut offset = opj_validate_marker_segment(data, 2)?;
+  let nb_tiles = opj_validate_siz(data, 2)?;
+
+  /* Main header */
+  let (mut has_cod, mut has_qcd) = (false, false);
+  loop {
+    match read_u16(data, offset) {
+      Some(J2K_MS_SOT) => break,
+      Some(J2K_MS_COD) => has_cod = true,
+      Some(J2K_MS_QCD) => has_qcd = true,
+      Some(J2K_MS_SOC) | Some(J2K_MS_SIZ) => {
+        return error(offset, "Duplicate SOC or SIZ marker");
+      }
+      Some(J2K_MS_SOD) | Some(J2K_MS_EOC) | None => {
+        return error(offset, "Main header without tile-parts");
+      }
+      Some(_) => (),
+    }
+    offset = opj_validate_marker_segment(data, offset)?;
+  }
+  if !has_cod || !has_qcd {
+    return error(offset, "Missing COD or QCD marker in the main header");
+  }
+
+  /* Tile-parts */
+  while read_u16(data, offset) == Some(J2K_MS_SOT) {
+    let sot = offset;
+    /* Lsot(2) Isot(2) Psot(4) TPsot(1) TNsot(1) */
+    if read_u16(data, sot + 2) != Some(10) {
+      return error(sot, "Invalid SOT marker segment length");
+    }
+    let isot = read_u16(data, sot + 4).unwrap_or(0) as u64;
+    let psot = read_u32(data, sot + 6).unwrap_or(0) as usize;
+    if isot >= nb_tiles {
+      return error(sot, "Invalid tile index");
+    }
+    offset = opj_validate_marker_segment(data, sot)?;
+    loop {
+      match read_u16(data, offset) {
+        Some(J2K_MS_SOD) => break,
+        Some(J2K_MS_SOT) | Some(J2K_MS_EOC) | None => {
+          return error(offset, "Tile-part header without SOD marker");
+        }
+        Some(_) => offset = opj_validate_marker_segment(data, offset)?,
+      }
+    }
+    let tile_part_end = if psot == 0 {
+      /* The last tile-part extends to the EOC marker */
+      if read_u16(data, data.len().saturating_sub(2)) != Some(J2K_MS_EOC) {
+        return error(data.len(), "Missing EOC marker");
+      }
+      data.len() - 2
+    } else {
+      sot.saturating_add(psot)
+    };
+    if tile_part_end < offset + 2 || tile_part_end > data.len() {
+      return error(sot, "Tile-part length exceeds the data");
+    }
+    offset = tile_part_end;
+  }
+  if read_u16(data, offset) != Some(J2K_MS_EOC) {
+    return error(offset, "Missing EOC marker");
+  }
+  Ok(())
+}
+
+/// Validate the structure of a JP2 file or of a J2K codestream.
+pub(crate) fn opj_validate_structure(data: &[u8]) -> Result<(), StructureError> {
+  if data.len() >= 12 && data[4..8] == *b"jP  " {
+    opj_validate_jp2(data)
+  } else if read_u16(data, 0) == Some(J2K_MS_SOC) {
+    opj_validate_j2k(data)
+  } else {
+    error(0, "Neither a JP2 file nor a J2K codestream")
+  }
+}
+
+/// The codestream of a JP2 file, i.e. the contents of its first codestream
+/// box, or the whole data if it is a J2K codestream.
+///
+/// Only the top-level boxes are walked, the codestream itself isn't checked.
+pub(crate) fn opj_locate_codestream(data: &[u8]) -> Result<&[u8], StructureError> {
+  if data.len() >= 12 && data[4..8] == *b"jP  " {
+    let mut offset = 0;
+    while offset < data.len() {
+      let b = opj_validate_box(data, offset, data.len())?;
+      if &b.ty == b"jp2c" {
+        return Ok(&data[b.start..b.end]);
+      }
+      offset = b.end;
+    }
+    error(offset, "Missing codestream box")
+  } else if read_u16(data, 0) == Some(J2K_MS_SOC) {
+    Ok(data)
+  } else {
+    error(0, "Neither a JP2 file nor a J2K codestream")
+  }
+}
+
+/// Part 11 (JPWL) markers, only known with the `jpwl` feature.
+const JPWL_MARKERS: [(u16, &str); 4] = [
+  (0xff66, "EPB"),
+  (0xff67, "ESD"),
+  (0xff68, "EPC"),
+  (0xff69, "RED"),
+];
+
+/// Part 8 (JPSEC) markers, only known with the `jpspec` feature.
+const JPSEC_MARKERS: [(u16, &str); 2] = [(0xff65, "SEC"), (0xff94, "INSEC")];
+
+/// Report the unsupported code-block styles of the SPcod/SPcoc parameters
+/// at `offset`.
+fn opj_explain_cblksty(data: &[u8], offset: usize, reasons: &mut Vec<String>) {
+  /* Levels(1) xcb(1) ycb(1) Style(1) */
+  if let Some(&cblksty) = data.get(offset + 3) {
+    if cblksty & 0x80 != 0 {
+      reasons.push(format!(
+        "Mixed HT code-block style at offset {} is not supported",
+        offset
+      ));
+    }
+  }
+}
+
+/// Report the unsupported features of the marker segment at `offset`,
+/// `csiz` is the number of components given by the SIZ marker.
+fn opj_explain_marker(data: &[u8], offset: usize, csiz: usize, reasons: &mut Vec<String>) {
+  let marker = match read_u16(data, offset) {
+    Some(marker) => marker,
+    None => return,
+  };
+  match marker {
+    J2K_MS_SIZ => {
+      if csiz > 16384 {
+        reasons.push(format!(
+          "More than 16384 components ({}) in the SIZ marker",
+          csiz
+        ));
+      }
+      /* Ssiz(1) XRsiz(1) YRsiz(1) per component */
+      for compno in 0..csiz {
+        if let Some(&ssiz) = data.get(offset + 40 + 3 * compno) {
+          let prec = (ssiz & 0x7f) as u32 + 1;
+          if prec > 31 {
+            reasons.push(format!(
+              "Component {} has a precision of {} bits, only up to 31 are supported",
+              compno, prec
+            ));
+          }
+        }
+      }
+    }
+    /* Lcod(2) Scod(1) SGcod(4) */
+    J2K_MS_COD => opj_explain_cblksty(data, offset + 9, reasons),
+    /* Lcoc(2) Ccoc(1 or 2) Scoc(1) */
+    J2K_MS_COC => {
+      let ccoc_len = if csiz < 257 { 1 } else { 2 };
+      opj_explain_cblksty(data, offset + 5 + ccoc_len, reasons)
+    }
+    marker if J2KMarker::from(marker as u32).is_unknown() => {
+      let jpwl = JPWL_MARKERS.iter().find(|(m, _)| *m == marker);
+      let jpsec = JPSEC_MARKERS.iter().find(|(m, _)| *m == marker);
+      reasons.push(if let Some((_, name)) = jpwl {
+        format!(
+          "JPWL (Part 11) marker {} at offset {} requires the jpwl feature, it is skipped",
+          name, offset
+        )
+      } else if let Some((_, name)) = jpsec {
+        format!(
+          "JPSEC (Part 8) marker {} at offset {} requires the jpspec feature, it is skipped",
+          name, offset
+        )
+      } else {
+        format!(
+          "Unknown marker 0x{:04X} at offset {} is skipped",
+          marker, offset
+        )
+      });
+    }
+    _ => (),
+  }
+}
+
+/// Walk the main and tile-part headers of a J2K codestream.
+fn opj_explain_j2k(data: &[u8], reasons: &mut Vec<String>) -> Result<(), StructureError> {
+  if read_u16(data, 0) != Some(J2K_MS_SOC) {
+    return error(0, "The codestream must start with a SOC marker");
+  }
+  let csiz = read_u16(data, 40).unwrap_or(0) as usize;
+  let mut offset = 2;
+  loop {
+    match read_u16(data, offset) {
+      Some(J2K_MS_SOT) => {
+        let sot = offset;
+        let psot = read_u32(data, sot + 6).unwrap_or(0) as usize;
+        offset = opj_validate_marker_segment(data, sot)?;
+        while let Some(marker) = read_u16(data, offset) {
+          if marker == J2K_MS_SOD {
+            break;
+          }
+          opj_explain_marker(data, offset, csiz, reasons);
+          offset = opj_validate_marker_segment(data, offset)?;
+        }
+        /* The last tile-part extends to the EOC marker */
+        if psot == 0 {
+          return Ok(());
+        }
+        offset = sot.saturating_add(psot);
+      }
+      Some(J2K_MS_EOC) | None => return Ok(()),
+      Some(_) => {
+        opj_explain_marker(data, offset, csiz, reasons);
+        offset = opj_validate_marker_segment(data, offset)?;
+      }
+    }
+  }
+}
+
+/// List the features of a JP2 file or of a J2K codestream that this build
+/// can't decode, see `opj_explain_unsupported`.
+pub(crate) fn opj_explain_unsupported(data: &[u8]) -> Vec<String> {
+  let mut reasons = Vec::new();
+  let result = if data.len() >= 12 && data[4..8] == *b"jP  " {
+    let mut offset = 0;
+    loop {
+      match opj_validate_box(data, offset, data.len()) {
+        Ok(b) if &b.ty == b"jp2c" => {
+          break opj_explain_j2k(&data[b.start..b.end], &mut reasons).map_err(|e| StructureError {
+            offset: b.start + e.offset,
+            message: e.message,
+          });
+        }
+        Ok(b) if b.end < data.len() => offset = b.end,
+        Ok(_) => break error(data.len(), "Missing codestream box"),
+        Err(e) => break Err(e),
+      }
+    }
+  } else if read_u16(data, 0) == Some(J2K_MS_SOC) {
+    opj_explain_j2k(data, &mut reasons)
+  } else {
+    error(0, "Neither a JP2 file nor a J2K codestream")
+  };
+  if let Err(e) = result {
+    reasons.push(e.to_string());
+  }
+  reasons
+}
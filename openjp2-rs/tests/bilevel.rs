@@ -0,0 +1,72 @@
+use openjp2::image::opj_image;
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+/// A 1-bit checkerboard of 4x4 squares.
+fn bilevel_image(w: u32, h: u32) -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = w;
+  image.y1 = h;
+  image.color_space = OPJ_CLRSPC_GRAY;
+  assert!(image.alloc_comps(1, true));
+  let comp = &mut image.comps_mut().unwrap()[0];
+  comp.dx = 1;
+  comp.dy = 1;
+  comp.w = w;
+  comp.h = h;
+  comp.prec = 1;
+  assert!(comp.alloc_data());
+  for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+    let (x, y) = (i as u32 % w, i as u32 / w);
+    *v = ((x / 4 + y / 4) % 2) as i32;
+  }
+  image
+}
+
+fn round_trip(format: CODEC_FORMAT, image: Box<opj_image>) -> Box<opj_image> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  let codec = Jp2Codec::encoder(format, &mut params, image).expect("encoder");
+  let bytes = codec.encode().expect("encoded image").into_bytes();
+  let codec = Jp2Codec::decoder(format, bytes)
+    .and_then(|codec| codec.read_header())
+    .expect("valid header");
+  codec.decode().expect("decoded image").into_image()
+}
+
+#[test]
+fn decode_bilevel_image() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    let expected = bilevel_image(32, 32).comps().unwrap()[0]
+      .data()
+      .unwrap()
+      .to_vec();
+    let decoded = round_trip(format, bilevel_image(32, 32));
+    let comp = &decoded.comps().unwrap()[0];
+    assert_eq!((comp.prec, comp.sgnd), (1, 0));
+    assert_eq!(comp.data().unwrap(), &expected[..]);
+
+    // Samples are expanded to black and white.
+    let rgba = decoded.as_rgba8().expect("grey image");
+    for (pixel, &v) in rgba.chunks_exact(4).zip(&expected) {
+      let grey = if v == 0 { 0 } else { 255 };
+      assert_eq!(pixel, &[grey, grey, grey, 255]);
+    }
+    let planes = decoded.as_f32_planes(true);
+    for (&f, &v) in planes[0].iter().zip(&expected) {
+      assert_eq!(f, v as f32);
+    }
+  }
+}
+
+#[test]
+fn write_bilevel_pgm() {
+  let decoded = round_trip(OPJ_CODEC_J2K, bilevel_image(8, 4));
+  let mut buf = Vec::new();
+  decoded.write_pgm(&mut buf).expect("write pgm");
+  let header = b"P5\n8 4\n1\n";
+  assert_eq!(&buf[..header.len()], header);
+  for row in buf[header.len()..].chunks(8) {
+    assert_eq!(row, &[0, 0, 0, 0, 1, 1, 1, 1]);
+  }
+}
@@ -0,0 +1,282 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode_jp2(image: *mut opj_image_t, boxes: Vec<RawBox>) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_preserve_boxes(codec, boxes) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+fn read_boxes(bytes: Vec<u8>) -> Vec<RawBox> {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_JP2);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let boxes = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_decoder_capture_boxes(codec, true) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    opj_get_boxes(codec)
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  boxes
+}
+
+fn write_box(out: &mut Vec<u8>, ty: &[u8; 4], data: &[u8]) {
+  out.extend_from_slice(&(8 + data.len() as u32).to_be_bytes());
+  out.extend_from_slice(ty);
+  out.extend_from_slice(data);
+}
+
+/// Offset of the first top-level box of type `ty`.
+fn find_box(bytes: &[u8], ty: &[u8; 4]) -> Option<usize> {
+  let mut pos = 0;
+  while pos + 8 <= bytes.len() {
+    let len = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]);
+    if &bytes[pos + 4..pos + 8] == ty {
+      return Some(pos);
+    }
+    pos += len as usize;
+  }
+  None
+}
+
+#[test]
+fn preserve_unknown_boxes() {
+  let image = grey_image(16, 16);
+  let bytes = encode_jp2(image, Vec::new());
+
+  // Add a private box before the codestream and an XML box after it.
+  let jp2c = find_box(&bytes, b"jp2c").expect("jp2c box");
+  let mut file = bytes[..jp2c].to_vec();
+  write_box(&mut file, b"priv", &[1, 2, 3]);
+  file.extend_from_slice(&bytes[jp2c..]);
+  write_box(&mut file, b"xml ", b"<a/>");
+
+  let boxes = read_boxes(file);
+  let types: Vec<_> = boxes.iter().map(|b| &b.ty).collect();
+  assert_eq!(types, [b"jP  ", b"ftyp", b"jp2h", b"priv", b"xml "]);
+  assert!(!boxes[3].after_codestream);
+  assert!(boxes[4].after_codestream);
+  assert_eq!(boxes[3].data, [1, 2, 3]);
+
+  // The generated boxes are skipped and the others are written in place.
+  // Encoding consumes the samples, so encode a new image.
+  opj_image_destroy(image);
+  let image = grey_image(16, 16);
+  let bytes = encode_jp2(image, boxes.clone());
+  let priv_box = find_box(&bytes, b"priv").expect("priv box");
+  let jp2c = find_box(&bytes, b"jp2c").expect("jp2c box");
+  let xml = find_box(&bytes, b"xml ").expect("xml box");
+  assert!(find_box(&bytes, b"jp2h").unwrap() < priv_box);
+  assert!(priv_box < jp2c && jp2c < xml);
+  assert_eq!(read_boxes(bytes), boxes);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn uuid_boxes_round_trip() {
+  let image = grey_image(16, 16);
+  let vendor = [7; 16];
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_add_uuid_box(codec, GEOJP2_UUID, b"GeoTIFF".to_vec()) != 0);
+    assert!(opj_encoder_add_uuid_box(codec, vendor, vec![1, 2, 3]) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  let uuid = find_box(&bytes, b"uuid").expect("uuid box");
+  assert!(find_box(&bytes, b"jp2h").unwrap() < uuid);
+  assert!(uuid < find_box(&bytes, b"jp2c").unwrap());
+
+  // The boxes are read back in insertion order.
+  let boxes = read_boxes(bytes);
+  let uuids: Vec<_> = boxes.iter().filter_map(|b| b.uuid()).collect();
+  assert_eq!(
+    uuids,
+    [(GEOJP2_UUID, &b"GeoTIFF"[..]), (vendor, &[1, 2, 3][..])]
+  );
+  opj_image_destroy(image);
+}
+
+#[test]
+fn uuid_boxes_need_a_jp2_compressor() {
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    assert!(opj_encoder_add_uuid_box(codec, GEOJP2_UUID, Vec::new()) == 0);
+    opj_destroy_codec(codec);
+  }
+}
+
+#[test]
+fn geo_metadata_round_trip() {
+  let image = grey_image(16, 16);
+  // A little-endian TIFF header, the blob isn't parsed.
+  let geotiff = b"II*\x00\x08\x00\x00\x00geokeys".to_vec();
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_geo_metadata(codec, b"replaced") != 0);
+    assert!(opj_encoder_set_geo_metadata(codec, &geotiff) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_JP2);
+  let mut params = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  let geo_metadata = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    opj_get_geo_metadata(codec)
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+  assert_eq!(geo_metadata, Some(geotiff));
+}
+
+fn read_associations(bytes: Vec<u8>) -> Vec<Association> {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_JP2);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let associations = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    opj_get_associations(codec)
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  associations
+}
+
+/// Insert `boxes` before the codestream box of a JP2 file.
+fn jp2_with_boxes(boxes: &[u8]) -> Vec<u8> {
+  let image = grey_image(16, 16);
+  let bytes = encode_jp2(image, Vec::new());
+  opj_image_destroy(image);
+  let jp2c = find_box(&bytes, b"jp2c").expect("jp2c box");
+  let mut file = bytes[..jp2c].to_vec();
+  file.extend_from_slice(boxes);
+  file.extend_from_slice(&bytes[jp2c..]);
+  file
+}
+
+#[test]
+fn nested_associations() {
+  let mut child1 = Vec::new();
+  write_box(&mut child1, b"lbl ", b"lesion");
+  write_box(&mut child1, b"nlst", &[0x01, 0x00, 0x00, 0x00]);
+  let mut child2 = Vec::new();
+  write_box(&mut child2, b"lbl ", b"background");
+  let mut root = Vec::new();
+  write_box(&mut root, b"lbl ", b"regions");
+  write_box(&mut root, b"asoc", &child1);
+  write_box(&mut root, b"xml ", b"<a/>");
+  write_box(&mut root, b"asoc", &child2);
+  let mut boxes = Vec::new();
+  write_box(&mut boxes, b"asoc", &root);
+
+  let associations = read_associations(jp2_with_boxes(&boxes));
+  assert_eq!(
+    associations,
+    [Association {
+      label: Some("regions".into()),
+      children: vec![
+        Association {
+          label: Some("lesion".into()),
+          children: Vec::new(),
+          raw_boxes: vec![RawBox {
+            ty: *b"nlst",
+            data: vec![0x01, 0x00, 0x00, 0x00],
+            after_codestream: false,
+          }],
+        },
+        Association {
+          label: Some("background".into()),
+          ..Default::default()
+        },
+      ],
+      raw_boxes: vec![RawBox {
+        ty: *b"xml ",
+        data: b"<a/>".to_vec(),
+        after_codestream: false,
+      }],
+    }]
+  );
+}
+
+#[test]
+fn deeply_nested_associations_are_bounded() {
+  let mut boxes = Vec::new();
+  write_box(&mut boxes, b"lbl ", b"leaf");
+  for _ in 0..1000 {
+    let mut parent = Vec::new();
+    write_box(&mut parent, b"asoc", &boxes);
+    boxes = parent;
+  }
+  let associations = read_associations(jp2_with_boxes(&boxes));
+  let mut depth = 0;
+  let mut node = &associations[0];
+  while let Some(child) = node.children.first() {
+    node = child;
+    depth += 1;
+  }
+  assert!(depth < 1000);
+  assert_eq!(node.label, None);
+}
+
+#[test]
+fn invalid_association_lengths_are_ignored() {
+  // The label box claims more bytes than the association box holds.
+  let mut root = Vec::new();
+  root.extend_from_slice(&1000u32.to_be_bytes());
+  root.extend_from_slice(b"lbl ");
+  root.extend_from_slice(b"oops");
+  let mut boxes = Vec::new();
+  write_box(&mut boxes, b"asoc", &root);
+  let associations = read_associations(jp2_with_boxes(&boxes));
+  assert_eq!(associations, [Association::default()]);
+}
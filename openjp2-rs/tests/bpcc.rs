@@ -0,0 +1,142 @@
+use openjp2::image::opj_image_cmptparm_t;
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 16;
+
+/// Create an image with a component per `(prec, sgnd)` pair.
+fn new_image(depths: &[(u32, u32)]) -> *mut opj_image_t {
+  let mut cmptparm: Vec<_> = depths
+    .iter()
+    .map(|&(prec, sgnd)| opj_image_cmptparm_t {
+      dx: 1,
+      dy: 1,
+      w: W,
+      h: W,
+      prec,
+      sgnd,
+      ..Default::default()
+    })
+    .collect();
+  let image = opj_image_create(
+    depths.len() as u32,
+    cmptparm.as_mut_ptr(),
+    OPJ_CLRSPC_UNSPECIFIED,
+  );
+  unsafe {
+    (*image).x1 = W;
+    (*image).y1 = W;
+    for comp in (*image).comps_mut().unwrap() {
+      let (prec, sgnd) = (comp.prec, comp.sgnd);
+      for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+        let v_max = 1i64 << prec;
+        let mut sample = (i as i64 * 7919) % v_max;
+        if sgnd != 0 {
+          sample -= v_max / 2;
+        }
+        *v = sample as i32;
+      }
+    }
+  }
+  image
+}
+
+fn encode(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Content of the first box of type `ty`.
+fn find_box<'a>(bytes: &'a [u8], ty: &[u8]) -> Option<&'a [u8]> {
+  let pos = bytes.windows(4).position(|w| w == ty)?;
+  let len = u32::from_be_bytes([
+    bytes[pos - 4],
+    bytes[pos - 3],
+    bytes[pos - 2],
+    bytes[pos - 1],
+  ]) as usize;
+  Some(&bytes[pos + 4..pos + len - 4])
+}
+
+fn round_trip(depths: &[(u32, u32)]) -> Vec<u8> {
+  let image = new_image(depths);
+  let expected = samples(image);
+  let bytes = encode(image);
+  opj_image_destroy(image);
+  let decoded = decode(OPJ_CODEC_JP2, bytes.clone());
+  unsafe {
+    let actual = (*decoded).comps().unwrap();
+    assert_eq!(actual.len(), depths.len());
+    for ((&(prec, sgnd), e), a) in depths.iter().zip(&expected).zip(actual) {
+      assert_eq!((a.prec, a.sgnd), (prec, sgnd));
+      assert_eq!(a.data().unwrap(), &e[..]);
+    }
+  }
+  opj_image_destroy(decoded);
+  bytes
+}
+
+#[test]
+fn mixed_bit_depths_round_trip() {
+  let bytes = round_trip(&[(8, 0), (16, 0)]);
+  // IHDR: HEIGHT(4) WIDTH(4) NC(2) BPC(1)
+  assert_eq!(find_box(&bytes, b"ihdr").unwrap()[10], 255);
+  assert_eq!(find_box(&bytes, b"bpcc"), Some(&[7u8, 15][..]));
+}
+
+#[test]
+fn mixed_signedness_round_trip() {
+  let bytes = round_trip(&[(8, 0), (8, 1)]);
+  assert_eq!(find_box(&bytes, b"ihdr").unwrap()[10], 255);
+  assert_eq!(find_box(&bytes, b"bpcc"), Some(&[7u8, 0x87][..]));
+}
+
+#[test]
+fn same_bit_depths_without_bpcc() {
+  let bytes = round_trip(&[(12, 0), (12, 0)]);
+  assert_eq!(find_box(&bytes, b"ihdr").unwrap()[10], 11);
+  assert!(find_box(&bytes, b"bpcc").is_none());
+}
+
+#[test]
+fn codestream_bit_depths_take_precedence() {
+  let image = new_image(&[(12, 0), (12, 0), (12, 0)]);
+  let expected = samples(image);
+  let mut bytes = encode(image);
+  opj_image_destroy(image);
+  // Claim 8 bits unsigned components in the IHDR box.
+  let ihdr = bytes.windows(4).position(|w| w == b"ihdr").unwrap();
+  assert_eq!(bytes[ihdr + 14], 11);
+  bytes[ihdr + 14] = 7;
+
+  let mut decoder = TestDecoder::new(OPJ_CODEC_JP2, bytes);
+  let decoded = decoder.decode().expect("decoded image");
+  for (e, a) in expected.iter().zip(unsafe { &*decoded }.comps().unwrap()) {
+    assert_eq!((a.prec, a.sgnd), (12, 0));
+    assert_eq!(a.data().unwrap(), &e[..]);
+  }
+  let warnings = decoder.msgs();
+  let bit_depths: Vec<_> = warnings
+    .iter()
+    .filter(|msg| msg.contains("bit depth"))
+    .collect();
+  assert_eq!(bit_depths.len(), 1, "{:?}", warnings);
+  assert!(
+    bit_depths[0].contains("3 component(s)"),
+    "{}",
+    bit_depths[0]
+  );
+  opj_image_destroy(decoded);
+}
@@ -0,0 +1,73 @@
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+mod common;
+use common::*;
+
+/// Return the contents of the file type box, after the signature box.
+fn ftyp(bytes: &[u8]) -> &[u8] {
+  let len = u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) as usize;
+  assert_eq!(&bytes[16..20], b"ftyp");
+  &bytes[20..12 + len]
+}
+
+fn encode_jp2(brands: Option<([u8; 4], Vec<[u8; 4]>)>) -> Option<Vec<u8>> {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  let ok = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    match brands {
+      Some((brand, compatible)) => opj_encoder_set_brands(codec, brand, compatible) != 0,
+      None => true,
+    }
+  };
+  let bytes = if ok {
+    Some(encode_to_vec(codec, image))
+  } else {
+    None
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+#[test]
+fn default_brands() {
+  let bytes = encode_jp2(None).expect("encoded image");
+  assert_eq!(ftyp(&bytes), b"jp2 \0\0\0\0jp2 ");
+}
+
+#[test]
+fn custom_brands() {
+  let bytes = encode_jp2(Some((*b"jph ", vec![*b"jph ", *b"jp2 "]))).expect("encoded image");
+  assert_eq!(ftyp(&bytes), b"jph \0\0\0\0jph jp2 ");
+
+  // The file is still readable.
+  let codec = Jp2Codec::decoder(OPJ_CODEC_JP2, bytes).expect("decoder");
+  assert!(codec.read_header().and_then(|c| c.decode()).is_some());
+}
+
+#[test]
+fn brand_must_be_compatible() {
+  assert!(encode_jp2(Some((*b"jpx ", vec![*b"jp2 "]))).is_none());
+  assert!(encode_jp2(Some((*b"jpx ", vec![]))).is_none());
+}
+
+#[test]
+fn safe_codec_brands() {
+  let image = unsafe { Box::from_raw(grey_image(16, 16)) };
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image).expect("encoder");
+  assert!(!codec.set_brands(*b"jpx ", vec![*b"jp2 "]));
+  assert!(codec.set_brands(*b"jpx ", vec![*b"jpx ", *b"jp2 "]));
+  // Kept when the codec is set up again.
+  assert!(codec.set_target_size(1 << 20));
+  let bytes = codec.encode().expect("encoded image").into_bytes();
+  assert_eq!(ftyp(&bytes), b"jpx \0\0\0\0jpx jp2 ");
+}
@@ -0,0 +1,95 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Encode a 32x32 image with 4 tiles and 2 resolutions, each tile being
+/// split in a tile-part by resolution.
+fn encode() -> Vec<u8> {
+  let image = grey_image(32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = 16;
+  params.cp_tdy = 16;
+  params.numresolution = 2;
+  params.tp_on = 1;
+  params.tp_flag = b'R' as _;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    // Not a decompressor.
+    assert!(opj_get_codestream_byte_ranges(codec).is_none());
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Byte ranges after reading the header and after decoding.
+fn byte_ranges(bytes: Vec<u8>) -> (CodestreamByteRanges, CodestreamByteRanges) {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let ranges = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_get_codestream_byte_ranges(codec).is_none());
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    let header = opj_get_codestream_byte_ranges(codec).expect("byte ranges");
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    let decoded = opj_get_codestream_byte_ranges(codec).expect("byte ranges");
+    (header, decoded)
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  ranges
+}
+
+#[test]
+fn tile_byte_ranges() {
+  let bytes = encode();
+  let (header, ranges) = byte_ranges(bytes.clone());
+  assert!(header.tiles.is_empty());
+  assert_eq!(header.main_header, ranges.main_header);
+  assert_eq!(ranges.main_header.start, 0);
+
+  assert_eq!(
+    ranges
+      .tiles
+      .iter()
+      .map(|tile| tile.tile)
+      .collect::<Vec<_>>(),
+    [0, 1, 2, 3]
+  );
+  let mut parts = Vec::new();
+  for tile in &ranges.tiles {
+    assert_eq!(tile.tile_parts.len(), 2);
+    for part in &tile.tile_parts {
+      assert_eq!(&bytes[part.start as usize..][..2], &[0xFF, 0x90]);
+      // Psot: the length of the tile-part.
+      let psot = &bytes[part.start as usize + 6..][..4];
+      let psot = u32::from_be_bytes([psot[0], psot[1], psot[2], psot[3]]);
+      assert_eq!(psot as u64, part.end - part.start);
+    }
+    parts.extend(tile.tile_parts.iter().cloned());
+  }
+
+  // The main header and the tile-parts cover the codestream up to EOC.
+  parts.sort_by_key(|part| part.start);
+  let mut end = ranges.main_header.end;
+  for part in &parts {
+    assert_eq!(part.start, end);
+    end = part.end;
+  }
+  assert_eq!(end as usize, bytes.len() - 2);
+  assert_eq!(&bytes[end as usize..], &[0xFF, 0xD9]);
+}
@@ -0,0 +1,123 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(image: *mut opj_image_t, caps: &[(u32, u16)]) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    for (part, ccap) in caps {
+      assert!(opj_encoder_set_capability(codec, *part, *ccap) != 0);
+    }
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+fn read_capabilities(bytes: Vec<u8>) -> Capabilities {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let caps = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    opj_get_capabilities(codec)
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  caps
+}
+
+#[test]
+fn no_cap_marker() {
+  let image = grey_image(16, 16);
+  let bytes = encode(image, &[]);
+  opj_image_destroy(image);
+  assert!(find_marker(&bytes, 0xFF50).is_none());
+  let caps = read_capabilities(bytes);
+  assert!(!caps.has_extended_capabilities());
+  assert!(!caps.is_htj2k());
+  assert_eq!(caps.pcap, 0);
+  assert!(caps.ccap.is_empty());
+}
+
+#[test]
+fn cap_marker_round_trip() {
+  let image = grey_image(16, 16);
+  // Parts are stored in increasing order, whatever the order they are set in.
+  let bytes = encode(image, &[(15, 0x0003), (2, 0x1234)]);
+  opj_image_destroy(image);
+  // The CAP marker follows the SIZ marker.
+  let cap = find_marker(&bytes, 0xFF50).expect("CAP marker");
+  let lsiz = u16::from_be_bytes([bytes[4], bytes[5]]) as usize;
+  assert_eq!(cap, 4 + lsiz);
+  assert_eq!(
+    bytes[cap..cap + 12],
+    [0xFF, 0x50, 0x00, 0x0A, 0x40, 0x02, 0x00, 0x00, 0x12, 0x34, 0x00, 0x03]
+  );
+
+  let caps = read_capabilities(bytes);
+  assert!(caps.has_extended_capabilities());
+  assert!(caps.is_htj2k());
+  assert!(caps.uses_part(2));
+  assert!(!caps.uses_part(1));
+  assert_eq!(caps.ccap(Capabilities::PART_HTJ2K), Some(0x0003));
+  assert_eq!(caps.ccap(2), Some(0x1234));
+  assert_eq!(caps.ccap(3), None);
+}
+
+#[test]
+fn set_capability_checks_part() {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    // Must be called after the encoder setup.
+    assert!(opj_encoder_set_capability(codec, 15, 0) == 0);
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_capability(codec, 0, 0) == 0);
+    assert!(opj_encoder_set_capability(codec, 33, 0) == 0);
+    assert!(opj_encoder_set_capability(codec, 32, 0) != 0);
+    assert_eq!(opj_get_capabilities(codec).pcap, 1);
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
+
+#[test]
+fn cpf_marker() {
+  let image = grey_image(16, 16);
+  // Without Part 15, there is no CPF marker.
+  let bytes = encode(image, &[(2, 0)]);
+  opj_image_destroy(image);
+  assert!(find_marker(&bytes, 0xFF59).is_none());
+  assert_eq!(read_capabilities(bytes).corresponding_profile(), None);
+
+  // A HTJ2K codestream signals the profile of its Part 1 counterpart.
+  let image = grey_image(16, 16);
+  let mut bytes = encode(image, &[(15, 0)]);
+  opj_image_destroy(image);
+  let cap = find_marker(&bytes, 0xFF50).expect("CAP marker");
+  let cpf = find_marker(&bytes, 0xFF59).expect("CPF marker");
+  assert_eq!(cpf, cap + 2 + 8);
+  assert_eq!(bytes[cpf..cpf + 6], [0xFF, 0x59, 0x00, 0x04, 0x00, 0x00]);
+  let caps = read_capabilities(bytes.clone());
+  assert_eq!(caps.cpf, [0]);
+  assert_eq!(caps.corresponding_profile(), Some(0));
+
+  // e.g. a Cinema 2K profile.
+  bytes[cpf + 5] = 0x03;
+  assert_eq!(read_capabilities(bytes).corresponding_profile(), Some(3));
+}
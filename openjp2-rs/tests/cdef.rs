@@ -0,0 +1,95 @@
+use openjp2::image::opj_image_cmptparm_t;
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Create an 8-bit sRGB image with an opacity channel of type `alpha`.
+fn new_rgba8(w: u32, h: u32, alpha: u16) -> *mut opj_image_t {
+  let mut cmptparm = vec![
+    opj_image_cmptparm_t {
+      dx: 1,
+      dy: 1,
+      w,
+      h,
+      prec: 8,
+      ..Default::default()
+    };
+    4
+  ];
+  let image = opj_image_create(4, cmptparm.as_mut_ptr(), OPJ_CLRSPC_SRGB);
+  unsafe {
+    (*image).x1 = w;
+    (*image).y1 = h;
+    for (compno, comp) in (*image).comps_mut().unwrap().iter_mut().enumerate() {
+      for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+        *v = ((i * (compno + 1)) % 256) as i32;
+      }
+    }
+    (*image).comps_mut().unwrap()[3].alpha = alpha;
+  }
+  image
+}
+
+fn round_trip(alpha: u16) -> *mut opj_image_t {
+  let image = new_rgba8(16, 16, alpha);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  // The JP2 header contains a cdef box.
+  assert!(bytes.windows(4).any(|w| w == b"cdef"));
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_JP2);
+  let mut params = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  decoded
+}
+
+#[test]
+fn rgba_opacity_round_trip() {
+  let decoded = round_trip(1);
+  unsafe {
+    let alpha: Vec<u16> = (*decoded)
+      .comps()
+      .unwrap()
+      .iter()
+      .map(|c| c.alpha)
+      .collect();
+    assert_eq!(alpha, [0, 0, 0, 1]);
+  }
+  opj_image_destroy(decoded);
+}
+
+#[test]
+fn rgba_premultiplied_round_trip() {
+  let decoded = round_trip(2);
+  unsafe {
+    let alpha: Vec<u16> = (*decoded)
+      .comps()
+      .unwrap()
+      .iter()
+      .map(|c| c.alpha)
+      .collect();
+    assert_eq!(alpha, [0, 0, 0, 2]);
+  }
+  opj_image_destroy(decoded);
+}
@@ -0,0 +1,196 @@
+use openjp2::image::opj_image_cmptparm_t;
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 32;
+
+/// Smooth chroma gradient at full resolution.
+fn chroma(x: u32, y: u32) -> i32 {
+  (4 * x + 2 * y) as i32
+}
+
+/// Create a 4:2:0 YCC image: the chroma components are the average of each
+/// 2x2 block of the full resolution gradient.
+fn ycc420_image() -> *mut opj_image_t {
+  let mut cmptparm = [W, W / 2, W / 2].map(|w| opj_image_cmptparm_t {
+    dx: W / w,
+    dy: W / w,
+    w,
+    h: w,
+    prec: 8,
+    ..Default::default()
+  });
+  let image = opj_image_create(3, cmptparm.as_mut_ptr(), OPJ_CLRSPC_SYCC);
+  unsafe {
+    (*image).x1 = W;
+    (*image).y1 = W;
+    for comp in (*image).comps_mut().unwrap() {
+      let (w, dx) = (comp.w, comp.dx);
+      for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+        let (x, y) = (i as u32 % w * dx, i as u32 / w * dx);
+        *v = if dx == 1 {
+          chroma(x, y) / 2
+        } else {
+          (chroma(x, y) + chroma(x + 1, y) + chroma(x, y + 1) + chroma(x + 1, y + 1)) / 4
+        };
+      }
+    }
+  }
+  image
+}
+
+fn encode(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+fn decode_with_filter(bytes: Vec<u8>, filter: Option<UpsampleFilter>) -> *mut opj_image_t {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_decoder_set_chroma_upsampling(decoder.codec, filter) } != 0);
+  decoder.decode().expect("decoded image")
+}
+
+/// Mean absolute error of the upsampled chroma against the full resolution
+/// gradient.
+fn chroma_error(image: *mut opj_image_t) -> f64 {
+  let comps = unsafe { (*image).comps().unwrap() };
+  let mut error = 0;
+  for comp in &comps[1..] {
+    assert_eq!((comp.dx, comp.dy, comp.w, comp.h), (1, 1, W, W));
+    for (i, v) in comp.data().unwrap().iter().enumerate() {
+      let (x, y) = (i as u32 % W, i as u32 / W);
+      error += (v - chroma(x, y)).abs();
+    }
+  }
+  error as f64 / (2 * W * W) as f64
+}
+
+#[test]
+fn bilinear_chroma_upsampling() {
+  let image = ycc420_image();
+  let bytes = encode(image);
+  opj_image_destroy(image);
+
+  // Components keep their subsampling by default.
+  let decoded = decode(OPJ_CODEC_J2K, bytes.clone());
+  unsafe {
+    let comp = &(*decoded).comps().unwrap()[1];
+    assert_eq!((comp.dx, comp.dy, comp.w, comp.h), (2, 2, W / 2, W / 2));
+  }
+  opj_image_destroy(decoded);
+
+  let nearest = decode_with_filter(bytes.clone(), Some(UpsampleFilter::Nearest));
+  let bilinear = decode_with_filter(bytes, Some(UpsampleFilter::Bilinear));
+  let nearest_error = chroma_error(nearest);
+  let bilinear_error = chroma_error(bilinear);
+  assert!(
+    bilinear_error < nearest_error / 2.0,
+    "bilinear error {} vs nearest error {}",
+    bilinear_error,
+    nearest_error
+  );
+  opj_image_destroy(nearest);
+  opj_image_destroy(bilinear);
+}
+
+#[test]
+fn chroma_upsampling_needs_a_decompressor() {
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    assert!(opj_decoder_set_chroma_upsampling(codec, Some(UpsampleFilter::Bilinear)) == 0);
+    opj_destroy_codec(codec);
+  }
+}
+
+fn decode_upsampled(bytes: Vec<u8>, reduce: u32) -> *mut opj_image_t {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  params.cp_reduce = reduce;
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_decoder_set_upsample(codec, true) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  image
+}
+
+fn comp_sizes(image: *mut opj_image_t) -> Vec<(u32, u32, u32, u32)> {
+  unsafe {
+    (*image)
+      .comps()
+      .unwrap()
+      .iter()
+      .map(|comp| (comp.dx, comp.dy, comp.w, comp.h))
+      .collect()
+  }
+}
+
+#[test]
+fn upsample_to_full_resolution() {
+  let image = ycc420_image();
+  let bytes = encode(image);
+  opj_image_destroy(image);
+
+  let decoded = decode_upsampled(bytes.clone(), 0);
+  assert_eq!(comp_sizes(decoded), vec![(1, 1, W, W); 3]);
+  opj_image_destroy(decoded);
+  // With a reduction factor, the components have the reduced image size.
+  let decoded = decode_upsampled(bytes, 1);
+  assert_eq!(comp_sizes(decoded), vec![(1, 1, W / 2, W / 2); 3]);
+  opj_image_destroy(decoded);
+}
+
+#[test]
+fn upsample_when_all_components_are_subsampled() {
+  let mut cmptparm = [opj_image_cmptparm_t {
+    dx: 2,
+    dy: 2,
+    w: W / 2,
+    h: W / 2,
+    prec: 8,
+    ..Default::default()
+  }; 3];
+  let image = opj_image_create(3, cmptparm.as_mut_ptr(), OPJ_CLRSPC_SRGB);
+  unsafe {
+    (*image).x1 = W;
+    (*image).y1 = W;
+  }
+  let bytes = encode(image);
+  opj_image_destroy(image);
+
+  // The components match, chroma upsampling alone keeps them subsampled.
+  let decoded = decode_with_filter(bytes.clone(), Some(UpsampleFilter::Bilinear));
+  assert_eq!(comp_sizes(decoded), vec![(2, 2, W / 2, W / 2); 3]);
+  opj_image_destroy(decoded);
+  let decoded = decode_upsampled(bytes, 0);
+  assert_eq!(comp_sizes(decoded), vec![(1, 1, W, W); 3]);
+  opj_image_destroy(decoded);
+}
+
+#[test]
+fn upsample_needs_a_decompressor() {
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    assert!(opj_decoder_set_upsample(codec, true) == 0);
+    opj_destroy_codec(codec);
+  }
+}
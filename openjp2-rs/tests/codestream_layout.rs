@@ -0,0 +1,203 @@
+use std::ffi::CString;
+
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+mod common;
+use common::*;
+
+const W: u32 = 64;
+
+/// Grey image with a textured content, which doesn't compress well.
+fn textured_image() -> *mut opj_image_t {
+  let image = grey_image(W, W);
+  unsafe {
+    for (i, v) in (*image).comps_mut().unwrap()[0]
+      .data_mut()
+      .unwrap()
+      .iter_mut()
+      .enumerate()
+    {
+      *v = ((i * 7919 + (i * i) % 251) % 256) as i32;
+    }
+  }
+  image
+}
+
+fn tiled_params() -> opj_cparameters_t {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  params.tile_size_on = 1;
+  params.cp_tdx = 32;
+  params.cp_tdy = 32;
+  params
+}
+
+/// Encode 4 tiles with 3 quality layers.
+fn encode(layout: CodestreamLayout) -> Vec<u8> {
+  let image = textured_image();
+  let mut params = tiled_params();
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_num_layers(codec, 3) != 0);
+    assert!(opj_encoder_set_layer_rates(codec, &[20.0, 4.0, 0.0]) != 0);
+    assert!(opj_encoder_set_codestream_layout(codec, layout) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// The tile-parts of a codestream: `(offset, Isot, TPsot, TNsot)`.
+fn tile_parts(bytes: &[u8]) -> Vec<(usize, u16, u8, u8)> {
+  let mut sot = find_marker(bytes, 0xFF90).expect("SOT marker");
+  let mut parts = Vec::new();
+  while bytes[sot..sot + 2] == [0xFF, 0x90] {
+    let isot = u16::from_be_bytes([bytes[sot + 4], bytes[sot + 5]]);
+    let psot = u32::from_be_bytes([
+      bytes[sot + 6],
+      bytes[sot + 7],
+      bytes[sot + 8],
+      bytes[sot + 9],
+    ]);
+    parts.push((sot, isot, bytes[sot + 10], bytes[sot + 11]));
+    sot += psot as usize;
+  }
+  parts
+}
+
+/// Keep the codestream up to `end`, and terminate it with an EOC marker.
+fn truncate(bytes: &[u8], end: usize) -> Vec<u8> {
+  let mut truncated = bytes[..end].to_vec();
+  truncated.extend_from_slice(&[0xFF, 0xD9]);
+  truncated
+}
+
+fn decode_layers(bytes: Vec<u8>, layers: u32, reduce: u32) -> Vec<i32> {
+  let mut params = opj_dparameters_t::default();
+  params.cp_layer = layers;
+  params.cp_reduce = reduce;
+  let mut decoder = TestDecoder::with_params(OPJ_CODEC_J2K, read_stream(bytes), params);
+  let image = decoder.decode().expect("decoded image");
+  let samples = samples(image).remove(0);
+  opj_image_destroy(image);
+  samples
+}
+
+#[test]
+fn tile_first_layout() {
+  let parts: Vec<_> = tile_parts(&encode(CodestreamLayout::TileFirst))
+    .iter()
+    .map(|&(_, isot, tpsot, tnsot)| (isot, tpsot, tnsot))
+    .collect();
+  assert_eq!(parts, vec![(0, 0, 1), (1, 0, 1), (2, 0, 1), (3, 0, 1)]);
+}
+
+#[test]
+fn layer_first_layout() {
+  let bytes = encode(CodestreamLayout::LayerFirst);
+  let parts = tile_parts(&bytes);
+  let order: Vec<_> = parts
+    .iter()
+    .map(|&(_, isot, tpsot, tnsot)| (isot, tpsot, tnsot))
+    .collect();
+  let expected: Vec<_> = (0..3)
+    .flat_map(|layer| (0..4).map(move |tile| (tile, layer, 3)))
+    .collect();
+  assert_eq!(order, expected);
+
+  let full = decode_layers(bytes.clone(), 0, 0);
+  let first_layer = decode_layers(bytes.clone(), 1, 0);
+  assert_ne!(full, first_layer);
+
+  // The first layer of every tile precedes the first tile-part of layer 1.
+  let boundary = parts[4].0;
+  assert_eq!(decode_layers(truncate(&bytes, boundary), 1, 0), first_layer);
+  assert_eq!(decode_layers(truncate(&bytes, boundary), 0, 0), first_layer);
+  let boundary = parts[8].0;
+  assert_eq!(
+    decode_layers(truncate(&bytes, boundary), 0, 0),
+    decode_layers(bytes, 2, 0)
+  );
+}
+
+#[test]
+fn resolution_first_layout() {
+  let bytes = encode(CodestreamLayout::ResolutionFirst);
+  let parts = tile_parts(&bytes);
+  assert_eq!(parts.len(), 12);
+  assert!(parts
+    .iter()
+    .enumerate()
+    .all(|(i, &(_, isot, tpsot, _))| isot as usize == i % 4 && tpsot as usize == i / 4));
+
+  // The lowest resolution of every tile.
+  let boundary = parts[4].0;
+  assert_eq!(
+    decode_layers(truncate(&bytes, boundary), 0, 2),
+    decode_layers(bytes, 0, 2)
+  );
+}
+
+#[test]
+fn layout_with_tile_length_markers() {
+  let image = textured_image();
+  let mut params = tiled_params();
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    let option = CString::new("TLM=YES").unwrap();
+    let options = [option.as_ptr(), std::ptr::null()];
+    assert!(opj_encoder_set_extra_options(codec, options.as_ptr()) != 0);
+    assert!(opj_encoder_set_codestream_layout(codec, CodestreamLayout::ResolutionFirst) != 0);
+  }
+  let expected = samples(image).swap_remove(0);
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+
+  // Ttlm (1 byte, for 12 tile-parts) and Ptlm of each tile-part, in
+  // codestream order.
+  let tlm = find_marker(&bytes, 0xFF55).expect("TLM marker");
+  let parts = tile_parts(&bytes);
+  assert_eq!(parts.len(), 12);
+  for (i, &(offset, isot, _, _)) in parts.iter().enumerate() {
+    let entry = tlm + 6 + i * 5;
+    assert_eq!(bytes[entry] as u16, isot);
+    let ptlm = u32::from_be_bytes([
+      bytes[entry + 1],
+      bytes[entry + 2],
+      bytes[entry + 3],
+      bytes[entry + 4],
+    ]) as usize;
+    let next = parts.get(i + 1).map_or(bytes.len() - 2, |p| p.0);
+    assert_eq!(ptlm, next - offset);
+  }
+  assert_eq!(decode_layers(bytes, 0, 0), expected);
+}
+
+#[test]
+fn safe_codec_layout() {
+  let image = unsafe { Box::from_raw(textured_image()) };
+  let expected = image.comps().unwrap()[0].data().unwrap().to_vec();
+  let mut params = tiled_params();
+  let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image).expect("encoder");
+  assert!(codec.set_codestream_layout(CodestreamLayout::LayerFirst));
+  let bytes = codec.encode().expect("encoded image").into_bytes();
+  assert_eq!(opj_validate_structure(&bytes), Ok(()));
+
+  let decoded = Jp2Codec::decoder(OPJ_CODEC_JP2, bytes)
+    .and_then(|codec| codec.read_header())
+    .and_then(|codec| codec.decode())
+    .expect("decoded image")
+    .into_image();
+  assert_eq!(decoded.comps().unwrap()[0].data().unwrap(), &expected[..]);
+}
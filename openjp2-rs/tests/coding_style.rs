@@ -0,0 +1,139 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn style() -> CodingStyle {
+  CodingStyle {
+    resolutions: 4,
+    codeblock: (32, 16),
+    precincts: vec![(64, 64), (32, 32)],
+    mode_switches: ModeSwitches::RESET | ModeSwitches::VSC | ModeSwitches::SEGMARK,
+    sop: true,
+    eph: true,
+    partition_origin: (0, 0),
+    guard_bits: 3,
+    mct: true,
+    wavelet: Wavelet::Irreversible97,
+  }
+}
+
+fn setup_encoder(image: *mut opj_image_t) -> *mut opj_codec_t {
+  let mut params = opj_cparameters_t::default();
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  codec
+}
+
+#[test]
+fn coding_style_round_trip() {
+  let image = multi_component_image(3, 64, 64);
+  let codec = setup_encoder(image);
+  let style = style();
+  unsafe {
+    assert!(opj_encoder_set_coding_style(codec, &style) != 0);
+    // The lower resolutions get the last precinct size halved.
+    let expected = CodingStyle {
+      precincts: vec![(64, 64), (32, 32), (16, 16), (8, 8)],
+      ..style.clone()
+    };
+    assert_eq!(opj_encoder_get_coding_style(codec), Some(expected));
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut dparams = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut dparams) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    let mut info = opj_get_cstr_info(codec);
+    let tile = &(*info).m_default_tile_info;
+    assert_eq!(tile.csty, 0x01 | 0x02 | 0x04);
+    assert_eq!(tile.mct, 1);
+    for compno in 0..3 {
+      let tccp = &*tile.tccp_info.add(compno);
+      assert_eq!(tccp.csty, 0x01);
+      assert_eq!(tccp.numresolutions, 4);
+      assert_eq!((tccp.cblkw, tccp.cblkh), (5, 4));
+      assert_eq!(tccp.cblksty, style.mode_switches.bits());
+      assert_eq!(tccp.qmfbid, 0);
+      assert_eq!(tccp.qntsty, 2);
+      assert_eq!(tccp.numgbits, 3);
+      assert_eq!(&tccp.prcw[..4], &[3, 4, 5, 6]);
+      assert_eq!(&tccp.prch[..4], &[3, 4, 5, 6]);
+    }
+    opj_destroy_cstr_info(&mut info);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+}
+
+#[test]
+fn invalid_coding_style() {
+  let image = multi_component_image(3, 64, 64);
+  let codec = setup_encoder(image);
+  let invalid = [
+    CodingStyle {
+      resolutions: 8,
+      ..style()
+    },
+    CodingStyle {
+      resolutions: 1,
+      ..style()
+    },
+    CodingStyle {
+      codeblock: (128, 64),
+      ..style()
+    },
+    CodingStyle {
+      codeblock: (24, 24),
+      ..style()
+    },
+    CodingStyle {
+      precincts: vec![(64, 64), (1, 1)],
+      ..style()
+    },
+    CodingStyle {
+      guard_bits: 8,
+      ..style()
+    },
+  ];
+  unsafe {
+    let before = opj_encoder_get_coding_style(codec);
+    assert_eq!(before.as_ref().map(|style| style.resolutions), Some(6));
+    for style in &invalid {
+      assert!(opj_encoder_set_coding_style(codec, style) == 0);
+      // Nothing changed.
+      assert_eq!(opj_encoder_get_coding_style(codec), before);
+    }
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+
+  // The multiple component transform needs 3 components.
+  let image = grey_image(64, 64);
+  let codec = setup_encoder(image);
+  unsafe {
+    assert!(opj_encoder_set_coding_style(codec, &style()) == 0);
+    let style = CodingStyle {
+      mct: false,
+      ..style()
+    };
+    assert!(opj_encoder_set_coding_style(codec, &style) != 0);
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
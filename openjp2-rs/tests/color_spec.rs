@@ -0,0 +1,66 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode_jp2(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+#[test]
+fn jp2_sycc_color_spec() {
+  let image = multi_component_image(3, 16, 16);
+  unsafe {
+    (*image).color_space = OPJ_CLRSPC_SYCC;
+  }
+  let stream = read_stream(encode_jp2(image));
+  let codec = opj_create_decompress(OPJ_CODEC_JP2);
+  let mut params = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+
+    let spec = opj_get_color_spec(codec);
+    assert_eq!(spec, Jp2ColorSpec::Enumerated(18));
+    assert_eq!(spec.color_space(), OPJ_CLRSPC_SYCC);
+    assert_eq!((*decoded).color_space, OPJ_CLRSPC_SYCC);
+
+    // Correct a mislabeled image.
+    (*decoded).color_space = OPJ_CLRSPC_SRGB;
+    assert_eq!(opj_get_color_spec(codec), Jp2ColorSpec::Enumerated(18));
+
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn color_spec_kinds() {
+  assert_eq!(
+    Jp2ColorSpec::Unspecified.color_space(),
+    OPJ_CLRSPC_UNSPECIFIED
+  );
+  assert_eq!(Jp2ColorSpec::Enumerated(16).color_space(), OPJ_CLRSPC_SRGB);
+  // ICC profiles are kept apart from sRGB.
+  assert_eq!(Jp2ColorSpec::IccProfile.color_space(), OPJ_CLRSPC_UNKNOWN);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  unsafe {
+    assert_eq!(opj_get_color_spec(codec), Jp2ColorSpec::Unspecified);
+    opj_destroy_codec(codec);
+  }
+}
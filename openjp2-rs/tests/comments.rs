@@ -0,0 +1,58 @@
+use std::ffi::*;
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode_with_comments(comments: &[&str]) -> Vec<u8> {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    for comment in comments {
+      let comment = CString::new(*comment).unwrap();
+      assert!(opj_encoder_add_comment(codec, comment.as_ptr()) != 0);
+    }
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+#[test]
+fn com_marker_round_trip() {
+  let bytes = encode_with_comments(&["Hello", "café €5"]);
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+  }
+
+  let comments = unsafe { opj_get_comments(codec) };
+  assert_eq!(
+    comments,
+    vec![
+      Comment::Text(format!("Created by OpenJPEG version {}", OPJ_VERSION)),
+      Comment::Text("Hello".into()),
+      Comment::Text("café €5".into()),
+    ]
+  );
+
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+}
@@ -0,0 +1,321 @@
+#![allow(dead_code)]
+
+use std::ffi::*;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use openjp2::image::opj_image_cmptparm_t;
+use openjp2::openjpeg::*;
+
+pub extern "C" fn log_error(msg: *const c_char, _data: *mut c_void) {
+  unsafe {
+    eprintln!("[ERROR] {:?}", CStr::from_ptr(msg).to_string_lossy());
+  }
+}
+
+/// Collect the messages of a codec in the `Vec<String>` `data` points to.
+pub extern "C" fn collect_msg(msg: *const c_char, data: *mut c_void) {
+  let msgs = unsafe { &mut *(data as *mut Vec<String>) };
+  msgs.push(
+    unsafe { CStr::from_ptr(msg) }
+      .to_string_lossy()
+      .into_owned(),
+  );
+}
+
+extern "C" fn cursor_free_fn(p_data: *mut c_void) {
+  drop(unsafe { Box::from_raw(p_data as *mut Cursor<Vec<u8>>) })
+}
+
+extern "C" fn cursor_read_fn(p_buffer: *mut c_void, nb_bytes: usize, p_data: *mut c_void) -> usize {
+  let cursor = unsafe { &mut *(p_data as *mut Cursor<Vec<u8>>) };
+  let out_buf = unsafe { std::slice::from_raw_parts_mut(p_buffer as *mut u8, nb_bytes) };
+  match cursor.read(out_buf) {
+    Ok(0) | Err(_) => usize::MAX,
+    Ok(nb) => nb,
+  }
+}
+
+extern "C" fn cursor_write_fn(
+  p_buffer: *mut c_void,
+  nb_bytes: usize,
+  p_data: *mut c_void,
+) -> usize {
+  let cursor = unsafe { &mut *(p_data as *mut Cursor<Vec<u8>>) };
+  let buf = unsafe { std::slice::from_raw_parts(p_buffer as *const u8, nb_bytes) };
+  cursor.write(buf).unwrap_or(usize::MAX)
+}
+
+extern "C" fn cursor_skip_fn(nb_bytes: i64, p_data: *mut c_void) -> i64 {
+  let cursor = unsafe { &mut *(p_data as *mut Cursor<Vec<u8>>) };
+  match cursor.seek(SeekFrom::Current(nb_bytes)) {
+    Ok(_) => nb_bytes,
+    Err(_) => -1,
+  }
+}
+
+extern "C" fn cursor_seek_fn(nb_bytes: i64, p_data: *mut c_void) -> i32 {
+  let cursor = unsafe { &mut *(p_data as *mut Cursor<Vec<u8>>) };
+  cursor.seek(SeekFrom::Start(nb_bytes as u64)).is_ok() as i32
+}
+
+pub fn cursor_stream(
+  cursor: *mut Cursor<Vec<u8>>,
+  len: usize,
+  is_input: bool,
+) -> *mut opj_stream_t {
+  // Output streams don't own the cursor, so the encoded bytes can be taken
+  // back after the stream is destroyed.
+  let free_fn = if is_input {
+    Some(cursor_free_fn as unsafe extern "C" fn(*mut c_void))
+  } else {
+    None
+  };
+  unsafe {
+    let stream = opj_stream_default_create(is_input as i32);
+    opj_stream_set_read_function(stream, Some(cursor_read_fn));
+    opj_stream_set_write_function(stream, Some(cursor_write_fn));
+    opj_stream_set_skip_function(stream, Some(cursor_skip_fn));
+    opj_stream_set_seek_function(stream, Some(cursor_seek_fn));
+    opj_stream_set_user_data_length(stream, len as u64);
+    opj_stream_set_user_data(stream, cursor as *mut c_void, free_fn);
+    stream
+  }
+}
+
+/// Create a single component 8-bit greyscale test image.
+pub fn grey_image(w: u32, h: u32) -> *mut opj_image_t {
+  let mut cmptparm = opj_image_cmptparm_t {
+    dx: 1,
+    dy: 1,
+    w,
+    h,
+    prec: 8,
+    ..Default::default()
+  };
+  let image = opj_image_create(1, &mut cmptparm, OPJ_CLRSPC_GRAY);
+  unsafe {
+    (*image).x1 = w;
+    (*image).y1 = h;
+    let data = (*image).comps_mut().unwrap()[0].data_mut().unwrap();
+    for (i, v) in data.iter_mut().enumerate() {
+      *v = (i % 256) as i32;
+    }
+  }
+  image
+}
+
+/// Create an 8-bit test image where each component has different samples.
+pub fn multi_component_image(numcomps: u32, w: u32, h: u32) -> *mut opj_image_t {
+  let mut cmptparm = vec![
+    opj_image_cmptparm_t {
+      dx: 1,
+      dy: 1,
+      w,
+      h,
+      prec: 8,
+      ..Default::default()
+    };
+    numcomps as usize
+  ];
+  let image = opj_image_create(numcomps, cmptparm.as_mut_ptr(), OPJ_CLRSPC_UNSPECIFIED);
+  unsafe {
+    (*image).x1 = w;
+    (*image).y1 = h;
+    for (compno, comp) in (*image).comps_mut().unwrap().iter_mut().enumerate() {
+      for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+        *v = ((i * (compno + 1)) % 256) as i32;
+      }
+    }
+  }
+  image
+}
+
+/// Copy the samples of each component of `image`. Encoding moves the
+/// samples out of the image, so take this before `encode_to_vec`.
+pub fn samples(image: *const opj_image_t) -> Vec<Vec<i32>> {
+  unsafe {
+    (*image)
+      .comps()
+      .unwrap_or_default()
+      .iter()
+      .map(|comp| comp.data().unwrap_or_default().to_vec())
+      .collect()
+  }
+}
+
+/// Encode `image` with an already set up `codec` into memory.
+pub fn encode_to_vec(codec: *mut opj_codec_t, image: *mut opj_image_t) -> Vec<u8> {
+  let cursor = Box::into_raw(Box::new(Cursor::new(Vec::new())));
+  let stream = cursor_stream(cursor, 0, false);
+  unsafe {
+    assert!(opj_start_compress(codec, image, stream) != 0);
+    assert!(opj_encode(codec, stream) != 0);
+    assert!(opj_end_compress(codec, stream) != 0);
+  }
+  // Destroying the stream flushes any buffered bytes into the cursor.
+  unsafe {
+    opj_stream_destroy(stream);
+    Box::from_raw(cursor).into_inner()
+  }
+}
+
+/// Create an input stream reading from `bytes`.
+pub fn read_stream(bytes: Vec<u8>) -> *mut opj_stream_t {
+  let len = bytes.len();
+  let cursor = Box::into_raw(Box::new(Cursor::new(bytes)));
+  cursor_stream(cursor, len, true)
+}
+
+/// Create an input stream reading from `bytes` that can't seek.
+pub fn read_stream_without_seek(bytes: Vec<u8>) -> *mut opj_stream_t {
+  let stream = read_stream(bytes);
+  unsafe {
+    opj_stream_set_seek_function(stream, None);
+  }
+  stream
+}
+
+/// Decoder of a test codestream, its warnings and errors are collected in
+/// `msgs()`. The codec and the stream are destroyed with it.
+pub struct TestDecoder {
+  pub codec: *mut opj_codec_t,
+  pub stream: *mut opj_stream_t,
+  msgs: *mut Vec<String>,
+}
+
+impl TestDecoder {
+  /// Decoder of `bytes` with the default parameters.
+  pub fn new(format: OPJ_CODEC_FORMAT, bytes: Vec<u8>) -> Self {
+    Self::from_stream(format, read_stream(bytes))
+  }
+
+  /// Decoder reading `stream` with the default parameters.
+  pub fn from_stream(format: OPJ_CODEC_FORMAT, stream: *mut opj_stream_t) -> Self {
+    Self::with_params(format, stream, opj_dparameters_t::default())
+  }
+
+  /// Decoder reading `stream`, set up with `params`.
+  pub fn with_params(
+    format: OPJ_CODEC_FORMAT,
+    stream: *mut opj_stream_t,
+    mut params: opj_dparameters_t,
+  ) -> Self {
+    let codec = opj_create_decompress(format);
+    let msgs = Box::into_raw(Box::new(Vec::new()));
+    unsafe {
+      opj_set_error_handler(codec, Some(collect_msg), msgs as *mut c_void);
+      opj_set_warning_handler(codec, Some(collect_msg), msgs as *mut c_void);
+      assert!(opj_setup_decoder(codec, &mut params) != 0);
+    }
+    Self {
+      codec,
+      stream,
+      msgs,
+    }
+  }
+
+  /// Warnings and errors of the decoder so far.
+  pub fn msgs(&self) -> &[String] {
+    unsafe { &*self.msgs }
+  }
+
+  /// Read the header and decode the image.
+  pub fn decode(&mut self) -> Option<*mut opj_image_t> {
+    self.decode_area(None)
+  }
+
+  /// Read the header and decode the image, restricted to `area` if any.
+  /// Returns `None` if a step fails.
+  pub fn decode_area(&mut self, area: Option<(i32, i32, i32, i32)>) -> Option<*mut opj_image_t> {
+    let mut image = std::ptr::null_mut();
+    let ok = unsafe {
+      opj_read_header(self.stream, self.codec, &mut image) != 0
+        && match area {
+          Some((x0, y0, x1, y1)) => opj_set_decode_area(self.codec, image, x0, y0, x1, y1) != 0,
+          None => true,
+        }
+        && opj_decode(self.codec, self.stream, image) != 0
+        && opj_end_decompress(self.codec, self.stream) != 0
+    };
+    if ok {
+      Some(image)
+    } else {
+      opj_image_destroy(image);
+      None
+    }
+  }
+}
+
+impl Drop for TestDecoder {
+  fn drop(&mut self) {
+    unsafe {
+      opj_destroy_codec(self.codec);
+      opj_stream_destroy(self.stream);
+      drop(Box::from_raw(self.msgs));
+    }
+  }
+}
+
+/// Decode `bytes` with the default parameters, panics if decoding fails.
+pub fn decode(format: OPJ_CODEC_FORMAT, bytes: Vec<u8>) -> *mut opj_image_t {
+  let mut decoder = TestDecoder::new(format, bytes);
+  decoder
+    .decode()
+    .unwrap_or_else(|| panic!("decoding failed: {:?}", decoder.msgs()))
+}
+
+/// Find the offset of the first `marker` in a codestream.
+pub fn find_marker(bytes: &[u8], marker: u16) -> Option<usize> {
+  let marker = marker.to_be_bytes();
+  bytes.windows(2).position(|w| w == marker)
+}
+
+/// Classes of JPIP data-bins.
+pub const CLASS_PRECINCT: u8 = 0;
+pub const CLASS_TILE: u8 = 4;
+pub const CLASS_MAIN_HEADER: u8 = 6;
+
+/// Encode a VBAS, without the extension bit of the first byte.
+pub fn vbas(mut value: u64) -> Vec<u8> {
+  let mut out = vec![(value & 0x7f) as u8];
+  value >>= 7;
+  while value != 0 {
+    out.insert(0, 0x80 | (value & 0x7f) as u8);
+    value >>= 7;
+  }
+  out
+}
+
+/// Write a complete data-bin as a single JPIP message.
+pub fn write_msg(out: &mut Vec<u8>, class: u8, id: u8, body: &[u8]) {
+  assert!(id < 16);
+  // Bin-ID with class and CSn present, the message is the last byte range.
+  out.push(0x60 | 0x10 | id);
+  out.push(class);
+  out.push(0);
+  out.extend(vbas(0));
+  out.extend(vbas(body.len() as u64));
+  out.extend_from_slice(body);
+}
+
+/// Split a codestream into a JPT-stream with one tile data-bin per tile.
+pub fn codestream_to_jpt(bytes: &[u8]) -> Vec<u8> {
+  let mut out = Vec::new();
+  let mut sot = find_marker(bytes, 0xFF90).expect("SOT marker");
+  write_msg(&mut out, CLASS_MAIN_HEADER, 0, &bytes[..sot]);
+  // Each tile has a single tile-part: Isot(2) and Psot(4) follow Lsot.
+  while bytes[sot..sot + 2] == [0xFF, 0x90] {
+    let isot = u16::from_be_bytes([bytes[sot + 4], bytes[sot + 5]]);
+    let psot = u32::from_be_bytes([
+      bytes[sot + 6],
+      bytes[sot + 7],
+      bytes[sot + 8],
+      bytes[sot + 9],
+    ]) as usize;
+    write_msg(&mut out, CLASS_TILE, isot as u8, &bytes[sot..sot + psot]);
+    sot += psot;
+  }
+  // EOR message.
+  out.extend_from_slice(&[0x00, 0x01, 0x00]);
+  out
+}
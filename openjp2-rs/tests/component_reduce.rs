@@ -0,0 +1,52 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+#[test]
+fn reduce_one_component() {
+  let image = multi_component_image(2, 16, 16);
+  let expected = samples(image);
+  let stream = read_stream(encode(image));
+  opj_image_destroy(image);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_set_component_reduce(codec, &[(1, 1)]) == 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    // Invalid component or too many resolutions removed.
+    assert!(opj_set_component_reduce(codec, &[(2, 1)]) == 0);
+    assert!(opj_set_component_reduce(codec, &[(1, 3)]) == 0);
+    assert!(opj_set_component_reduce(codec, &[(1, 2)]) != 0);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+
+    let actual = (*decoded).comps().unwrap();
+    assert_eq!((actual[0].w, actual[0].h, actual[0].factor), (16, 16, 0));
+    assert_eq!(actual[0].data().unwrap(), expected[0]);
+    assert_eq!((actual[1].w, actual[1].h, actual[1].factor), (4, 4, 2));
+    assert_eq!(actual[1].data().unwrap().len(), 16);
+
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+}
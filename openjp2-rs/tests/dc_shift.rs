@@ -0,0 +1,56 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+#[test]
+fn override_dc_level_shift() {
+  let image = grey_image(16, 16);
+  let expected = samples(image).remove(0);
+  let stream = read_stream(encode(image));
+  opj_image_destroy(image);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    // The header must be read first.
+    assert_eq!(opj_get_dc_shift(codec, 0), None);
+    assert!(opj_decoder_set_dc_shift(codec, 0, Some(100)) == 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+
+    assert_eq!(opj_get_dc_shift(codec, 0), Some(128));
+    assert_eq!(opj_get_dc_shift(codec, 1), None);
+    assert!(opj_decoder_set_dc_shift(codec, 1, Some(100)) == 0);
+    assert!(opj_decoder_set_dc_shift(codec, 0, Some(100)) != 0);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    // The getter still reports the shift of the codestream.
+    assert_eq!(opj_get_dc_shift(codec, 0), Some(128));
+
+    let actual = (*decoded).comps().unwrap()[0].data().unwrap();
+    for (e, a) in expected.iter().zip(actual) {
+      assert_eq!(*a, (e - 28).max(0));
+    }
+
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+}
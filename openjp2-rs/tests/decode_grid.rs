@@ -0,0 +1,91 @@
+use std::io::ErrorKind;
+
+use openjp2::openjpeg::*;
+use openjp2::safe::{GridCoord, HeaderRead, Jp2Codec};
+
+mod common;
+use common::*;
+
+const SIZE: u32 = 512;
+
+/// Encode a lossless single-tile image, returns it with its samples.
+fn encoded_image() -> (Vec<u8>, Vec<i32>) {
+  let image = unsafe { Box::from_raw(grey_image(SIZE, SIZE)) };
+  let samples = image.comps().unwrap()[0].data().unwrap().to_vec();
+  let mut params = opj_cparameters_t::default();
+  let codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  (codec.encode().expect("encoded image").into_bytes(), samples)
+}
+
+fn header(bytes: Vec<u8>) -> Jp2Codec<HeaderRead> {
+  Jp2Codec::decoder(OPJ_CODEC_J2K, bytes)
+    .and_then(|codec| codec.read_header())
+    .expect("valid header")
+}
+
+#[test]
+fn retile_into_a_grid() {
+  let (bytes, samples) = encoded_image();
+  let tiles = header(bytes).decode_grid(256, 256).expect("decoded image");
+  assert_eq!(tiles.grid_size(), (2, 2));
+
+  // Reassemble the image from the tiles, which must match on the seams.
+  let mut assembled = vec![-1; samples.len()];
+  let mut count = 0;
+  for tile in tiles {
+    let (coord, image) = tile.expect("grid tile");
+    let (col, row) = (count % 2, count / 2);
+    assert_eq!(
+      coord,
+      GridCoord {
+        col,
+        row,
+        x0: col * 256,
+        y0: row * 256,
+        x1: col * 256 + 256,
+        y1: row * 256 + 256,
+      }
+    );
+    let comp = &image.comps().unwrap()[0];
+    assert_eq!((comp.w, comp.h), (256, 256));
+    for (y, line) in comp.data().unwrap().chunks(256).enumerate() {
+      let start = (coord.y0 as usize + y) * SIZE as usize + coord.x0 as usize;
+      assembled[start..start + 256].copy_from_slice(line);
+    }
+    count += 1;
+  }
+  assert_eq!(count, 4);
+  assert_eq!(assembled, samples);
+}
+
+#[test]
+fn partial_tiles_at_the_edges() {
+  let (bytes, samples) = encoded_image();
+  let tiles: Vec<_> = header(bytes)
+    .decode_grid(200, 300)
+    .expect("decoded image")
+    .map(|tile| tile.expect("grid tile"))
+    .collect();
+  assert_eq!(tiles.len(), 6);
+  let (coord, image) = &tiles[5];
+  assert_eq!((coord.col, coord.row), (2, 1));
+  assert_eq!(
+    (coord.x0, coord.y0, coord.x1, coord.y1),
+    (400, 300, 512, 512)
+  );
+  let comp = &image.comps().unwrap()[0];
+  assert_eq!((comp.w, comp.h), (112, 212));
+  let first = comp.data().unwrap()[0];
+  assert_eq!(first, samples[300 * SIZE as usize + 400]);
+}
+
+#[test]
+fn invalid_grid_size() {
+  let (bytes, _) = encoded_image();
+  let err = header(bytes.clone())
+    .decode_grid(0, 256)
+    .err()
+    .expect("error");
+  assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  assert!(header(bytes).decode_grid(256, 0).is_err());
+}
@@ -0,0 +1,144 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: usize = 40;
+const H: usize = 24;
+
+fn encode_tiled(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = 16;
+  params.cp_tdy = 16;
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+fn decode_into(bytes: Vec<u8>, out: &mut [u8], layout: &PixelLayout) -> bool {
+  decode_into_with(bytes, out, layout, |_, _| true)
+}
+
+/// Like `decode_into()`, calling `setup` with the codec and the header
+/// before decoding.
+fn decode_into_with(
+  bytes: Vec<u8>,
+  out: &mut [u8],
+  layout: &PixelLayout,
+  setup: impl FnOnce(*mut opj_codec_t, *mut opj_image_t) -> bool,
+) -> bool {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let ok = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    setup(codec, image) && opj_decode_into(codec, stream, image, out, layout) != 0
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  ok
+}
+
+#[test]
+fn decode_into_interleaved_u8() {
+  let image = multi_component_image(3, W as u32, H as u32);
+  let expected = samples(image);
+  let bytes = encode_tiled(image);
+  let layout = PixelLayout {
+    channels: vec![2, 0],
+    ..Default::default()
+  };
+  let mut out = vec![0u8; W * H * 2];
+  assert!(decode_into(bytes.clone(), &mut out, &layout));
+
+  for i in 0..W * H {
+    assert_eq!(out[i * 2] as i32, expected[2][i]);
+    assert_eq!(out[i * 2 + 1] as i32, expected[0][i]);
+  }
+
+  // The output buffer must hold the whole image.
+  let mut small = vec![0u8; W * H * 2 - 1];
+  assert!(!decode_into(bytes, &mut small, &layout));
+  opj_image_destroy(image);
+}
+
+#[test]
+fn decode_into_planar_u16_with_stride() {
+  let image = multi_component_image(3, W as u32, H as u32);
+  let expected = samples(image);
+  let bytes = encode_tiled(image);
+  let stride = W * 2 + 16;
+  let layout = PixelLayout {
+    interleaving: Interleaving::Planar,
+    depth: SampleDepth::U16,
+    row_stride: stride,
+    ..Default::default()
+  };
+  let mut out = vec![0u8; stride * H * 3];
+  assert!(decode_into(bytes, &mut out, &layout));
+
+  for (compno, data) in expected.iter().enumerate() {
+    for y in 0..H {
+      for x in 0..W {
+        let offset = (compno * H + y) * stride + x * 2;
+        let value = u16::from_ne_bytes([out[offset], out[offset + 1]]);
+        // 8-bit samples are scaled to the full 16-bit range.
+        assert_eq!(value as i32, data[y * W + x] * 257);
+      }
+    }
+  }
+  opj_image_destroy(image);
+}
+
+#[test]
+fn decode_into_selected_components() {
+  let image = multi_component_image(3, W as u32, H as u32);
+  let expected = samples(image);
+  let bytes = encode_tiled(image);
+  // The channels index the decoded components: 2, then 1.
+  let layout = PixelLayout {
+    channels: vec![1, 0],
+    ..Default::default()
+  };
+  let mut out = vec![0u8; W * H * 2];
+  assert!(decode_into_with(
+    bytes,
+    &mut out,
+    &layout,
+    |codec, _| unsafe { opj_set_decoded_components(codec, 2, [2, 1].as_ptr(), 0) != 0 }
+  ));
+  for i in 0..W * H {
+    assert_eq!(out[i * 2] as i32, expected[1][i]);
+    assert_eq!(out[i * 2 + 1] as i32, expected[2][i]);
+  }
+  opj_image_destroy(image);
+}
+
+#[test]
+fn decode_into_rejects_a_decode_area() {
+  let image = multi_component_image(3, W as u32, H as u32);
+  let bytes = encode_tiled(image);
+  let mut out = vec![0u8; W * H * 3];
+  assert!(!decode_into_with(
+    bytes,
+    &mut out,
+    &PixelLayout::default(),
+    |codec, header| unsafe { opj_set_decode_area(codec, header, 20, 4, 40, 24) != 0 }
+  ));
+  opj_image_destroy(image);
+}
@@ -0,0 +1,142 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Encode a 3 components image with 4 tiles, 3 layers and 3 resolutions.
+fn encode() -> Vec<u8> {
+  let image = multi_component_image(3, 32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = 16;
+  params.cp_tdy = 16;
+  params.numresolution = 3;
+  params.tcp_numlayers = 3;
+  params.tcp_rates[0] = 20.0;
+  params.tcp_rates[1] = 5.0;
+  params.tcp_rates[2] = 1.0;
+  params.cp_disto_alloc = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Decode with `limits`, returning the error messages on failure.
+fn decode_with(bytes: Vec<u8>, limits: DecodeLimits) -> Result<(), Vec<String>> {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_decoder_set_limits(decoder.codec, limits) } != 0);
+  match decoder.decode() {
+    Some(image) => {
+      opj_image_destroy(image);
+      Ok(())
+    }
+    None => Err(decoder.msgs().to_vec()),
+  }
+}
+
+/// Decoding must fail with an error naming `limit`.
+fn assert_exceeds(bytes: Vec<u8>, limits: DecodeLimits, limit: &str) {
+  let errors = decode_with(bytes, limits).expect_err(limit);
+  assert!(
+    errors.iter().any(|error| error.contains(limit)),
+    "{}: {:?}",
+    limit,
+    errors
+  );
+}
+
+#[test]
+fn default_limits_accept_valid_codestreams() {
+  assert_eq!(decode_with(encode(), DecodeLimits::default()), Ok(()));
+}
+
+#[test]
+fn each_limit_is_checked() {
+  let bytes = encode();
+  let exact = DecodeLimits {
+    max_components: 3,
+    max_resolutions: 3,
+    max_layers: 3,
+    max_tiles: 4,
+  };
+  assert_eq!(decode_with(bytes.clone(), exact), Ok(()));
+  let exceeded = [
+    (
+      DecodeLimits {
+        max_components: 2,
+        ..exact
+      },
+      "max_components",
+    ),
+    (
+      DecodeLimits {
+        max_resolutions: 2,
+        ..exact
+      },
+      "max_resolutions",
+    ),
+    (
+      DecodeLimits {
+        max_layers: 2,
+        ..exact
+      },
+      "max_layers",
+    ),
+    (
+      DecodeLimits {
+        max_tiles: 3,
+        ..exact
+      },
+      "max_tiles",
+    ),
+  ];
+  for (limits, limit) in exceeded {
+    assert_exceeds(bytes.clone(), limits, limit);
+  }
+}
+
+/// The resolutions of a COC marker in a tile-part header are checked too.
+#[test]
+fn limits_apply_to_coc_markers() {
+  let mut bytes = encode();
+  // Insert a COC marker for component 1 before the SOD marker of the first
+  // tile-part: Lcoc, Ccoc, Scoc, then 4 decomposition levels (5
+  // resolutions), 64x64 code-blocks, no mode switch and the 5-3 wavelet.
+  let sod = find_marker(&bytes, 0xFF93).expect("SOD marker");
+  let coc = [
+    0xFF, 0x53, 0x00, 0x09, 0x01, 0x00, 0x04, 0x04, 0x04, 0x00, 0x01,
+  ];
+  bytes.splice(sod..sod, coc);
+  // Psot of the first tile-part grows by the size of the marker.
+  let sot = find_marker(&bytes, 0xFF90).expect("SOT marker");
+  let psot = u32::from_be_bytes([
+    bytes[sot + 6],
+    bytes[sot + 7],
+    bytes[sot + 8],
+    bytes[sot + 9],
+  ]) + coc.len() as u32;
+  bytes[sot + 6..sot + 10].copy_from_slice(&psot.to_be_bytes());
+
+  let limits = DecodeLimits {
+    max_resolutions: 4,
+    ..Default::default()
+  };
+  assert_exceeds(bytes, limits, "max_resolutions");
+}
+
+#[test]
+fn limits_need_a_decompressor() {
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    assert!(opj_decoder_set_limits(codec, DecodeLimits::default()) == 0);
+    opj_destroy_codec(codec);
+  }
+}
@@ -0,0 +1,78 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 40;
+const H: u32 = 24;
+const TILE: u32 = 16;
+
+fn encode_tiled(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = TILE as i32;
+  params.cp_tdy = TILE as i32;
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+#[test]
+fn decode_tiles_by_index() {
+  let image = grey_image(W, H);
+  let expected = samples(image).remove(0);
+  let stream = read_stream(encode_tiled(image));
+  opj_image_destroy(image);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut header = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    // The header must be read first.
+    assert!(opj_decode_tile(codec, stream, 0).is_null());
+    assert!(opj_read_header(stream, codec, &mut header) != 0);
+    // 3x2 tiles.
+    assert!(opj_decode_tile(codec, stream, 6).is_null());
+
+    // Out of order and repeated tiles.
+    for tile_index in [5, 0, 2, 0] {
+      let tile = opj_decode_tile(codec, stream, tile_index);
+      assert!(!tile.is_null(), "tile {}", tile_index);
+      let (x0, y0) = ((tile_index % 3) * TILE, (tile_index / 3) * TILE);
+      let (x1, y1) = ((x0 + TILE).min(W), (y0 + TILE).min(H));
+      assert_eq!(
+        ((*tile).x0, (*tile).y0, (*tile).x1, (*tile).y1),
+        (x0, y0, x1, y1)
+      );
+      let comp = &(*tile).comps().unwrap()[0];
+      assert_eq!((comp.w, comp.h), (x1 - x0, y1 - y0));
+      let data = comp.data().unwrap();
+      for y in y0..y1 {
+        for x in x0..x1 {
+          assert_eq!(
+            data[((y - y0) * comp.w + x - x0) as usize],
+            expected[(y * W + x) as usize],
+            "sample ({}, {}) of tile {}",
+            x,
+            y,
+            tile_index
+          );
+        }
+      }
+      opj_image_destroy(tile);
+    }
+
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(header);
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "c_api")]
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Encode `image`, which gives its samples to the encoder.
+fn encode(image: *mut opj_image_t, format: CODEC_FORMAT) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(format) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Decode with `opj_decode_to_rgba` and copy the pixels out of the C buffer.
+fn decode_to_rgba(bytes: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+  let mut out = std::ptr::null_mut();
+  let (mut w, mut h) = (0, 0);
+  unsafe {
+    if opj_decode_to_rgba(bytes.as_ptr(), bytes.len(), &mut out, &mut w, &mut h) != 0 {
+      assert!(out.is_null());
+      return None;
+    }
+    let pixels = std::slice::from_raw_parts(out, (w * h * 4) as usize).to_vec();
+    libc::free(out as *mut libc::c_void);
+    Some((w, h, pixels))
+  }
+}
+
+#[test]
+fn decode_grey_codestream() {
+  let image = grey_image(12, 10);
+  let expected = samples(image).swap_remove(0);
+  let (w, h, pixels) = decode_to_rgba(&encode(image, OPJ_CODEC_J2K)).expect("decoded image");
+  assert_eq!((w, h), (12, 10));
+  for (pixel, v) in pixels.chunks_exact(4).zip(&expected) {
+    let v = *v as u8;
+    assert_eq!(pixel, [v, v, v, 255]);
+  }
+  opj_image_destroy(image);
+}
+
+#[test]
+fn decode_rgb_jp2() {
+  let image = multi_component_image(3, 8, 8);
+  unsafe {
+    (*image).color_space = OPJ_CLRSPC_SRGB;
+  }
+  let comps = samples(image);
+  let (w, h, pixels) = decode_to_rgba(&encode(image, OPJ_CODEC_JP2)).expect("decoded image");
+  assert_eq!((w, h), (8, 8));
+  for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+    let expected: Vec<u8> = comps.iter().map(|c| c[i] as u8).collect();
+    assert_eq!(pixel[..3], expected[..]);
+    assert_eq!(pixel[3], 255);
+  }
+  opj_image_destroy(image);
+}
+
+#[test]
+fn invalid_data() {
+  assert!(decode_to_rgba(&[0xff, 0x4f, 0xff, 0x51, 0x00]).is_none());
+  assert!(decode_to_rgba(&[]).is_none());
+}
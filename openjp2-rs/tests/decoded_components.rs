@@ -0,0 +1,50 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+#[test]
+fn decode_selected_components_in_order() {
+  let image = multi_component_image(4, 16, 16);
+  let expected = samples(image);
+  let stream = read_stream(encode(image));
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    // Invalid and duplicate indices are rejected.
+    assert!(opj_set_decoded_components(codec, 1, [4].as_ptr(), 0) == 0);
+    assert!(opj_set_decoded_components(codec, 2, [1, 1].as_ptr(), 0) == 0);
+    assert!(opj_set_decoded_components(codec, 2, [3, 1].as_ptr(), 0) != 0);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+
+    let actual = samples(decoded);
+    assert_eq!(actual.len(), 2);
+    assert_eq!(actual[0], expected[3]);
+    assert_eq!(actual[1], expected[1]);
+
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+  opj_image_destroy(image);
+}
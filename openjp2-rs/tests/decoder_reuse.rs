@@ -0,0 +1,109 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(format: CODEC_FORMAT, image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  let codec = unsafe { opj_create_compress(format) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Decode `bytes` with `codec`, reading the header with `opj_read_header`
+/// for the first codestream and `opj_decoder_reset_for_next` afterwards.
+fn decode_with(codec: *mut opj_codec_t, bytes: Vec<u8>, first: bool) -> *mut opj_image_t {
+  let stream = read_stream(bytes);
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    if first {
+      assert!(opj_read_header(stream, codec, &mut image) != 0);
+    } else {
+      assert!(opj_decoder_reset_for_next(codec, stream, &mut image) != 0);
+    }
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    opj_stream_destroy(stream);
+  }
+  image
+}
+
+fn new_decoder(format: CODEC_FORMAT, reduce: u32) -> *mut opj_codec_t {
+  let codec = opj_create_decompress(format);
+  let mut params = opj_dparameters_t::default();
+  params.cp_reduce = reduce;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+  }
+  codec
+}
+
+#[test]
+fn reset_between_images() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    // Same size, then more components and larger, then smaller again.
+    let images = [
+      grey_image(32, 32),
+      multi_component_image(1, 32, 32),
+      multi_component_image(3, 64, 48),
+      multi_component_image(3, 40, 40),
+      grey_image(16, 8),
+    ];
+    let codec = new_decoder(format, 0);
+    for (i, &image) in images.iter().enumerate() {
+      let size = unsafe { ((*image).x1, (*image).y1) };
+      let expected = samples(image);
+      let bytes = encode(format, image);
+      opj_image_destroy(image);
+      let decoded = decode_with(codec, bytes, i == 0);
+      unsafe {
+        assert_eq!(((*decoded).x1, (*decoded).y1), size, "image {}", i);
+      }
+      assert_eq!(samples(decoded), expected, "image {}", i);
+      opj_image_destroy(decoded);
+    }
+    unsafe {
+      opj_destroy_codec(codec);
+    }
+  }
+}
+
+#[test]
+fn reset_keeps_decoder_parameters() {
+  let image = grey_image(32, 32);
+  let bytes = encode(OPJ_CODEC_J2K, image);
+  let codec = new_decoder(OPJ_CODEC_J2K, 1);
+  for i in 0..2 {
+    let decoded = decode_with(codec, bytes.clone(), i == 0);
+    unsafe {
+      let comp = &(*decoded).comps().unwrap()[0];
+      assert_eq!((comp.w, comp.h, comp.factor), (16, 16, 1));
+    }
+    opj_image_destroy(decoded);
+  }
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
+
+#[test]
+fn reset_needs_a_decompressor() {
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    let stream = read_stream(Vec::new());
+    let mut image = std::ptr::null_mut() as *mut opj_image_t;
+    assert!(opj_decoder_reset_for_next(codec, stream, &mut image) == 0);
+    opj_stream_destroy(stream);
+    opj_destroy_codec(codec);
+  }
+}
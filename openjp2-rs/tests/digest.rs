@@ -0,0 +1,70 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(format: CODEC_FORMAT) -> Vec<u8> {
+  let image = grey_image(32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(format) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+fn xml_box(xml: &str) -> Vec<u8> {
+  let mut b = ((8 + xml.len()) as u32).to_be_bytes().to_vec();
+  b.extend_from_slice(b"xml ");
+  b.extend_from_slice(xml.as_bytes());
+  b
+}
+
+/// Insert an XML box after the file type box and another one at the end.
+fn with_metadata(jp2: &[u8], xml: &str) -> Vec<u8> {
+  let ftyp_end = 12 + u32::from_be_bytes([jp2[12], jp2[13], jp2[14], jp2[15]]) as usize;
+  let mut bytes = jp2[..ftyp_end].to_vec();
+  bytes.extend(xml_box(xml));
+  bytes.extend_from_slice(&jp2[ftyp_end..]);
+  bytes.extend(xml_box(xml));
+  bytes
+}
+
+#[test]
+fn same_codestream_same_digest() {
+  let jp2 = encode(OPJ_CODEC_JP2);
+  let first = with_metadata(&jp2, "<title>first</title>");
+  let second = with_metadata(&jp2, "<title>second, longer</title>");
+  assert_ne!(first, second);
+  assert_eq!(opj_validate_structure(&first), Ok(()));
+
+  let digest = opj_codestream_digest(&jp2).expect("digest");
+  assert_eq!(opj_codestream_digest(&first), Ok(digest));
+  assert_eq!(opj_codestream_digest(&second), Ok(digest));
+  /* The same codestream without a container */
+  assert_eq!(opj_codestream_digest(&encode(OPJ_CODEC_J2K)), Ok(digest));
+}
+
+#[test]
+fn different_codestream_different_digest() {
+  let mut j2k = encode(OPJ_CODEC_J2K);
+  let digest = opj_codestream_digest(&j2k).expect("digest");
+  let last = j2k.len() - 3;
+  j2k[last] ^= 1;
+  assert_ne!(opj_codestream_digest(&j2k), Ok(digest));
+}
+
+#[test]
+fn missing_codestream() {
+  let jp2 = encode(OPJ_CODEC_JP2);
+  let jp2c = find_marker(&jp2, 0xFF4F).expect("SOC marker") - 8;
+  assert!(opj_codestream_digest(&jp2[..jp2c]).is_err());
+  assert!(opj_codestream_digest(b"not a JPEG 2000 file").is_err());
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "dynamic-image")]
+
+use std::convert::TryFrom;
+
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Rgba};
+use openjp2::image::opj_image_cmptparm_t;
+use openjp2::openjpeg::*;
+
+#[test]
+fn rgba8_round_trip() {
+  let buffer = ImageBuffer::from_fn(7, 5, |x, y| Rgba([x as u8, y as u8, (x * y) as u8, 200]));
+  let dynamic = DynamicImage::ImageRgba8(buffer);
+  let image = Box::<opj_image>::try_from(&dynamic).expect("converted to opj_image");
+  assert_eq!(image.numcomps, 4);
+  assert_eq!(image.color_space, OPJ_CLRSPC_SRGB);
+  let comps = image.comps().unwrap();
+  assert_eq!((comps[0].w, comps[0].h, comps[0].prec), (7, 5, 8));
+  assert_eq!(comps[3].alpha, 1);
+  assert_eq!(comps[2].data().unwrap()[7 * 4 + 6], 24);
+
+  let converted = DynamicImage::try_from(&*image).expect("converted to DynamicImage");
+  assert_eq!(converted, dynamic);
+}
+
+#[test]
+fn luma16_round_trip() {
+  let buffer: ImageBuffer<Luma<u16>, _> =
+    ImageBuffer::from_fn(4, 4, |x, y| Luma([(x * 4 + y) as u16 * 4000]));
+  let dynamic = DynamicImage::ImageLuma16(buffer);
+  let image = Box::<opj_image>::try_from(dynamic.clone()).expect("converted to opj_image");
+  assert_eq!(image.color_space, OPJ_CLRSPC_GRAY);
+  assert_eq!(DynamicImage::try_from(&*image).unwrap(), dynamic);
+}
+
+#[test]
+fn rescale_and_reject() {
+  // 4 bits samples are rescaled to 8 bits.
+  let mut cmptparm = opj_image_cmptparm_t {
+    dx: 1,
+    dy: 1,
+    w: 2,
+    h: 1,
+    prec: 4,
+    ..Default::default()
+  };
+  let image = opj_image_create(1, &mut cmptparm, OPJ_CLRSPC_GRAY);
+  unsafe {
+    (*image).x1 = 2;
+    (*image).y1 = 1;
+    (*image).comps_mut().unwrap()[0]
+      .data_mut()
+      .unwrap()
+      .copy_from_slice(&[0, 15]);
+    let converted = DynamicImage::try_from(&*image).unwrap();
+    assert_eq!(
+      converted,
+      DynamicImage::ImageLuma8(GrayImage::from_raw(2, 1, vec![0, 255]).unwrap())
+    );
+    // YCC samples must be converted first.
+    (*image).color_space = OPJ_CLRSPC_SYCC;
+    assert!(DynamicImage::try_from(&*image).is_err());
+  }
+  opj_image_destroy(image);
+}
@@ -0,0 +1,243 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Read the number of decomposition levels from the COD marker.
+fn cod_numdecompos(bytes: &[u8]) -> u8 {
+  let cod = find_marker(bytes, 0xFF52).expect("COD marker");
+  // Marker(2), Lcod(2), Scod(1), SGcod(4), then SPcod starts with the
+  // number of decomposition levels.
+  bytes[cod + 9]
+}
+
+#[test]
+fn set_resolutions_writes_cod_numdecompos() {
+  let image = grey_image(64, 64);
+  let mut params = opj_cparameters_t::default();
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    // 64x64 can't have 8 resolutions, the lowest would be empty.
+    assert!(opj_encoder_set_resolutions(codec, 8) == 0);
+    assert!(opj_encoder_set_resolutions(codec, 0) == 0);
+    assert!(opj_encoder_set_resolutions(codec, 4) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  assert_eq!(cod_numdecompos(&bytes), 3);
+
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
+
+#[test]
+fn explicit_quantization_round_trip() {
+  let steps = vec![(100, 10), (200, 11), (300, 11), (400, 12)];
+  let image = grey_image(32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  params.irreversible = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    // 2 resolutions have 4 subbands.
+    let too_few = QuantStyle::Explicit {
+      steps: steps[..3].to_vec(),
+    };
+    assert!(opj_encoder_set_quantization(codec, &too_few) == 0);
+    let style = QuantStyle::Explicit {
+      steps: steps.clone(),
+    };
+    assert!(opj_encoder_set_quantization(codec, &style) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut dparams = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut dparams) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    let mut info = opj_get_cstr_info(codec);
+    let tccp = &*(*info).m_default_tile_info.tccp_info;
+    assert_eq!(tccp.qntsty, 2);
+    for (bandno, (mant, expn)) in steps.iter().enumerate() {
+      assert_eq!(tccp.stepsizes_mant[bandno], *mant as u32);
+      assert_eq!(tccp.stepsizes_expn[bandno], *expn as u32);
+    }
+    opj_destroy_cstr_info(&mut info);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+}
+
+fn setup_tiled(image: *mut opj_image_t, tx0: u32, ty0: u32) -> (*mut opj_codec_t, bool) {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  params.tile_size_on = 1;
+  params.cp_tdx = 16;
+  params.cp_tdy = 16;
+  assert!(params.set_tile_origin(tx0, ty0));
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  let ok = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    opj_setup_encoder(codec, &mut params, image) != 0
+  };
+  (codec, ok)
+}
+
+#[test]
+fn tile_origin_and_image_offset() {
+  let image = grey_image(32, 32);
+  unsafe {
+    assert!((*image).set_image_offset(5, 3));
+    assert_eq!(((*image).x1, (*image).y1), (37, 35));
+  }
+
+  // The tile origin can't be past the image origin.
+  let (codec, ok) = setup_tiled(image, 6, 0);
+  assert!(!ok);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+
+  let (codec, ok) = setup_tiled(image, 2, 1);
+  assert!(ok);
+  let expected = samples(image);
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut dparams = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut dparams) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    let mut info = opj_get_cstr_info(codec);
+    assert_eq!(((*info).tx0, (*info).ty0), (2, 1));
+    assert_eq!(((*info).tw, (*info).th), (3, 3));
+    opj_destroy_cstr_info(&mut info);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+
+    assert_eq!(((*decoded).x0, (*decoded).y0), (5, 3));
+    assert_eq!(samples(decoded), expected);
+
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn first_tile_must_intersect_image() {
+  let image = grey_image(32, 32);
+  unsafe {
+    assert!((*image).set_image_offset(20, 0));
+  }
+  let (codec, ok) = setup_tiled(image, 0, 0);
+  assert!(!ok);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
+
+/// Encode a noisy image with `opj_encoder_set_near_lossless` and return the
+/// largest difference between the decoded and the source samples.
+fn near_lossless_max_error(numcomps: u32, max_error: u32) -> i32 {
+  let image = multi_component_image(numcomps, 64, 64);
+  let mut seed = 0x2545_f491u32;
+  let mut source = Vec::new();
+  unsafe {
+    for comp in (*image).comps_mut().unwrap() {
+      for v in comp.data_mut().unwrap() {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        *v = (*v + (seed >> 27) as i32).min(255);
+      }
+      source.push(comp.data().unwrap().to_vec());
+    }
+  }
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 4;
+  params.tcp_mct = (numcomps == 3) as _;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_near_lossless(codec, max_error) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  // The wavelet transformation of SPcod is the 9-7 irreversible one.
+  let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+  assert_eq!(bytes[cod + 13], 0);
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut dparams = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  let mut worst = 0;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut dparams) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    for (comp, source) in (*decoded).comps().unwrap().iter().zip(&source) {
+      for (a, b) in comp.data().unwrap().iter().zip(source) {
+        worst = worst.max((a - b).abs());
+      }
+    }
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+  worst
+}
+
+/// The bound of the step sizes alone is statistical, it holds for noise.
+#[test]
+fn near_lossless_error_bound() {
+  for max_error in [1, 2, 8] {
+    assert!(near_lossless_max_error(1, max_error) <= max_error as i32);
+    assert!(near_lossless_max_error(3, max_error) <= max_error as i32);
+  }
+}
+
+#[test]
+fn near_lossless_rejects_invalid_errors() {
+  let image = grey_image(32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    // Not set up yet.
+    assert!(opj_encoder_set_near_lossless(codec, 1) == 0);
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_near_lossless(codec, 0) == 0);
+    // Half the range of an 8-bit component.
+    assert!(opj_encoder_set_near_lossless(codec, 128) == 0);
+    assert!(opj_encoder_set_near_lossless(codec, 127) != 0);
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
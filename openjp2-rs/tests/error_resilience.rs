@@ -0,0 +1,85 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 64;
+
+/// Encode a single code-block with the given mode switches.
+fn encode(image: *mut opj_image_t, modes: ModeSwitches) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_mode_switches(codec, modes) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+fn decode_with_checks(bytes: Vec<u8>, flags: ErrorResilienceFlags) -> Option<*mut opj_image_t> {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_decoder_set_error_resilience(decoder.codec, flags) } != 0);
+  decoder.decode()
+}
+
+/// Scramble a few bytes in the middle of the code-block data.
+fn corrupt(bytes: &[u8]) -> Vec<u8> {
+  let mut bytes = bytes.to_vec();
+  let sod = find_marker(&bytes, 0xFF93).expect("SOD marker");
+  let mid = sod + (bytes.len() - sod) / 2;
+  for b in &mut bytes[mid..mid + 4] {
+    *b ^= 0x55;
+    if *b == 0xFF {
+      *b = 0x7F;
+    }
+  }
+  bytes
+}
+
+#[test]
+fn segmentation_symbols_detect_corruption() {
+  let image = grey_image(W, W);
+  let expected = samples(image).remove(0);
+  let bytes = encode(image, ModeSwitches::SEGMARK);
+  opj_image_destroy(image);
+  // The COD marker segment signals the SEGMARK mode switch.
+  let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+  assert_eq!(bytes[cod + 12], 0x20);
+
+  let checks = ErrorResilienceFlags::CHECK_SEGMARK | ErrorResilienceFlags::STOP_ON_ERROR;
+  let decoded =
+    decode_with_checks(bytes.clone(), checks).expect("valid code-blocks pass the checks");
+  unsafe {
+    assert_eq!(
+      (*decoded).comps().unwrap()[0].data().unwrap(),
+      &expected[..]
+    );
+  }
+  opj_image_destroy(decoded);
+
+  let corrupted = corrupt(&bytes);
+  // Without the checks, the corrupt code-block is decoded.
+  let decoded =
+    decode_with_checks(corrupted.clone(), ErrorResilienceFlags::empty()).expect("decoded");
+  opj_image_destroy(decoded);
+  // Without STOP_ON_ERROR, a bad segmentation symbol is only a warning.
+  let decoded =
+    decode_with_checks(corrupted.clone(), ErrorResilienceFlags::CHECK_SEGMARK).expect("decoded");
+  opj_image_destroy(decoded);
+  assert!(decode_with_checks(corrupted, checks).is_none());
+}
+
+#[test]
+fn mode_switches_need_a_compressor() {
+  unsafe {
+    let codec = opj_create_decompress(OPJ_CODEC_J2K);
+    assert!(opj_encoder_set_mode_switches(codec, ModeSwitches::SEGMARK) == 0);
+    opj_destroy_codec(codec);
+  }
+}
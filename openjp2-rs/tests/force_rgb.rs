@@ -0,0 +1,188 @@
+use openjp2::image::opj_image_cmptparm_t;
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 16;
+
+fn encode(format: CODEC_FORMAT, image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(format) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Encode losslessly and decode `image` with the conversion to sRGB.
+fn round_trip(format: CODEC_FORMAT, image: *mut opj_image_t) -> *mut opj_image_t {
+  let mut decoder = TestDecoder::new(format, encode(format, image));
+  assert!(unsafe { opj_decoder_set_force_rgb(decoder.codec, true) } != 0);
+  decoder.decode().expect("decoded image")
+}
+
+/// Create an 8-bit image with `numcomps` components of the given samples.
+fn image_with(
+  numcomps: u32,
+  color_space: COLOR_SPACE,
+  sample: impl Fn(usize, usize) -> i32,
+) -> *mut opj_image_t {
+  let mut cmptparm = vec![
+    opj_image_cmptparm_t {
+      dx: 1,
+      dy: 1,
+      w: W,
+      h: W,
+      prec: 8,
+      ..Default::default()
+    };
+    numcomps as usize
+  ];
+  let image = opj_image_create(numcomps, cmptparm.as_mut_ptr(), color_space);
+  unsafe {
+    (*image).x1 = W;
+    (*image).y1 = W;
+    for (compno, comp) in (*image).comps_mut().unwrap().iter_mut().enumerate() {
+      for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+        *v = sample(compno, i);
+      }
+    }
+  }
+  image
+}
+
+#[test]
+fn grey_to_rgb() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    let image = grey_image(W, W);
+    let grey = samples(image);
+    let decoded = round_trip(format, image);
+    opj_image_destroy(image);
+    unsafe {
+      assert_eq!((*decoded).color_space, OPJ_CLRSPC_SRGB);
+    }
+    assert_eq!(samples(decoded), vec![grey[0].clone(); 3]);
+    opj_image_destroy(decoded);
+  }
+}
+
+#[test]
+fn grey_with_alpha_to_rgba() {
+  let image = multi_component_image(2, W, W);
+  let planes = samples(image);
+  let decoded = round_trip(OPJ_CODEC_J2K, image);
+  let expected = vec![
+    planes[0].clone(),
+    planes[0].clone(),
+    planes[0].clone(),
+    planes[1].clone(),
+  ];
+  assert_eq!(samples(decoded), expected);
+  opj_image_destroy(decoded);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn srgb_is_unchanged() {
+  let image = multi_component_image(3, W, W);
+  unsafe {
+    (*image).color_space = OPJ_CLRSPC_SRGB;
+  }
+  let planes = samples(image);
+  let decoded = round_trip(OPJ_CODEC_JP2, image);
+  opj_image_destroy(image);
+  unsafe {
+    assert_eq!((*decoded).color_space, OPJ_CLRSPC_SRGB);
+  }
+  assert_eq!(samples(decoded), planes);
+  opj_image_destroy(decoded);
+}
+
+#[test]
+fn sycc_to_rgb() {
+  // Grey levels have neutral chroma, the other samples are coloured.
+  let image = image_with(3, OPJ_CLRSPC_SYCC, |compno, i| match (compno, i % 2) {
+    (0, _) => (i % 256) as i32,
+    (_, 0) => 128,
+    (1, _) => 100,
+    _ => 200,
+  });
+  let decoded = round_trip(OPJ_CODEC_JP2, image);
+  unsafe {
+    assert_eq!((*decoded).color_space, OPJ_CLRSPC_SRGB);
+    assert_eq!((*decoded).numcomps, 3);
+  }
+  let rgb = samples(decoded);
+  for i in 0..(W * W) as usize {
+    let y = (i % 256) as i32;
+    let (r, g, b) = if i % 2 == 0 {
+      (y, y, y)
+    } else {
+      // Cb = -28, Cr = 72
+      (
+        (y + 100).clamp(0, 255),
+        (y - 41).clamp(0, 255),
+        (y - 49).clamp(0, 255),
+      )
+    };
+    assert_eq!((rgb[0][i], rgb[1][i], rgb[2][i]), (r, g, b), "sample {}", i);
+  }
+  opj_image_destroy(decoded);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn eycc_to_rgb() {
+  let image = image_with(3, OPJ_CLRSPC_EYCC, |compno, _| [100, 128, 128][compno]);
+  unsafe {
+    assert!((*image).convert_to_srgb(UpsampleFilter::Nearest));
+    assert_eq!((*image).color_space, OPJ_CLRSPC_SRGB);
+  }
+  assert_eq!(samples(image), vec![vec![100; (W * W) as usize]; 3]);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn cmyk_with_alpha_to_rgba() {
+  // Cyan, with an opacity component.
+  let image = image_with(5, OPJ_CLRSPC_CMYK, |compno, _| [255, 0, 0, 0, 42][compno]);
+  unsafe {
+    assert!((*image).convert_to_srgb(UpsampleFilter::Nearest));
+    assert_eq!((*image).color_space, OPJ_CLRSPC_SRGB);
+    assert_eq!((*image).numcomps, 4);
+  }
+  let len = (W * W) as usize;
+  assert_eq!(
+    samples(image),
+    vec![vec![0; len], vec![255; len], vec![255; len], vec![42; len]]
+  );
+  opj_image_destroy(image);
+}
+
+#[test]
+fn unknown_color_space_is_not_converted() {
+  let image = image_with(3, OPJ_CLRSPC_UNKNOWN, |_, i| (i % 256) as i32);
+  let planes = samples(image);
+  unsafe {
+    assert!(!(*image).convert_to_srgb(UpsampleFilter::Nearest));
+    assert_eq!((*image).color_space, OPJ_CLRSPC_UNKNOWN);
+  }
+  assert_eq!(samples(image), planes);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn force_rgb_needs_a_decompressor() {
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    assert!(opj_decoder_set_force_rgb(codec, true) == 0);
+    opj_destroy_codec(codec);
+  }
+}
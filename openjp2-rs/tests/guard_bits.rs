@@ -0,0 +1,112 @@
+use openjp2::image::opj_image_cmptparm_t;
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 16;
+
+/// 8-bit grey image with black and white halves: after the level shift the
+/// low-pass coefficients reach -128, which needs 8 magnitude bit-planes.
+fn high_amplitude_image() -> *mut opj_image_t {
+  let mut cmptparm = opj_image_cmptparm_t {
+    dx: 1,
+    dy: 1,
+    w: W,
+    h: W,
+    prec: 8,
+    ..Default::default()
+  };
+  let image = opj_image_create(1, &mut cmptparm, OPJ_CLRSPC_GRAY);
+  unsafe {
+    (*image).x1 = W;
+    (*image).y1 = W;
+    for (i, v) in (*image).comps_mut().unwrap()[0]
+      .data_mut()
+      .unwrap()
+      .iter_mut()
+      .enumerate()
+    {
+      *v = if (i as u32 % W) < W / 2 { 0 } else { 255 };
+    }
+  }
+  image
+}
+
+/// Encode a new `high_amplitude_image()`, encoding consumes the samples.
+fn encode(numgbits: Option<u32>) -> Vec<u8> {
+  let image = high_amplitude_image();
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    if let Some(numgbits) = numgbits {
+      assert!(opj_encoder_set_guard_bits(codec, numgbits) != 0);
+    }
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+fn decode_samples(bytes: Vec<u8>) -> Option<Vec<i32>> {
+  let image = TestDecoder::new(OPJ_CODEC_J2K, bytes).decode()?;
+  let samples = samples(image).remove(0);
+  opj_image_destroy(image);
+  Some(samples)
+}
+
+/// Number of guard bits written in the Sqcd field of the QCD marker.
+fn qcd_guard_bits(bytes: &[u8]) -> u8 {
+  let qcd = find_marker(bytes, 0xFF5C).expect("QCD marker");
+  bytes[qcd + 4] >> 5
+}
+
+#[test]
+fn guard_bits_prevent_overflow() {
+  let image = high_amplitude_image();
+  let expected = samples(image).remove(0);
+  opj_image_destroy(image);
+
+  let bytes = encode(None);
+  assert_eq!(qcd_guard_bits(&bytes), 2);
+  assert_eq!(decode_samples(bytes).as_ref(), Some(&expected));
+
+  // Without guard bits, the low-pass band overflows its bit-planes.
+  let bytes = encode(Some(0));
+  assert_eq!(qcd_guard_bits(&bytes), 0);
+  assert_ne!(decode_samples(bytes).as_ref(), Some(&expected));
+
+  // A single guard bit is enough headroom for this lossless round trip.
+  for numgbits in [1, 7] {
+    let bytes = encode(Some(numgbits));
+    assert_eq!(qcd_guard_bits(&bytes), numgbits as u8);
+    assert_eq!(
+      decode_samples(bytes).as_ref(),
+      Some(&expected),
+      "{} guard bits",
+      numgbits
+    );
+  }
+}
+
+#[test]
+fn invalid_guard_bits() {
+  let image = high_amplitude_image();
+  let mut params = opj_cparameters_t::default();
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    // Guard bits are per tile, the encoder must be set up first.
+    assert!(opj_encoder_set_guard_bits(codec, 2) == 0);
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_guard_bits(codec, 8) == 0);
+    assert!(opj_encoder_set_guard_bits(codec, 7) != 0);
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
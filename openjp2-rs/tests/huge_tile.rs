@@ -0,0 +1,56 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Encode a small image in a single tile, then claim a `w` x `h` image and
+/// tile in the SIZ marker.
+fn crafted_codestream(w: u32, h: u32) -> Vec<u8> {
+  let image = grey_image(64, 64);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let mut bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  let siz = find_marker(&bytes, 0xFF51).expect("SIZ marker");
+  // Xsiz, Ysiz, then XTsiz, YTsiz after the image offset.
+  for (offset, value) in [(6, w), (10, h), (22, w), (26, h)] {
+    bytes[siz + offset..siz + offset + 4].copy_from_slice(&value.to_be_bytes());
+  }
+  bytes
+}
+
+/// Read the header and decode, which must fail cleanly.
+fn decode_fails(bytes: Vec<u8>) {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) == 0);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+}
+
+#[test]
+fn huge_tile_is_an_error() {
+  decode_fails(crafted_codestream(1 << 30, 1 << 30));
+}
+
+/// The 2^32 bytes of the tile data don't fit in a `usize`.
+#[cfg(target_pointer_width = "32")]
+#[test]
+fn tile_data_size_overflow_is_an_error() {
+  decode_fails(crafted_codestream(1 << 20, 1 << 10));
+}
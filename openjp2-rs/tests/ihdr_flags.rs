@@ -0,0 +1,98 @@
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+mod common;
+use common::*;
+
+/// Return the UnkC and IPR flags of the image header box.
+fn ihdr_flags(bytes: &[u8]) -> (u8, u8) {
+  let ihdr = bytes
+    .windows(4)
+    .position(|w| w == b"ihdr")
+    .expect("ihdr box");
+  /* HEIGHT(4) WIDTH(4) NC(2) BPC(1) C(1) UnkC(1) IPR(1) */
+  (bytes[ihdr + 16], bytes[ihdr + 17])
+}
+
+fn encode_jp2(unknown: bool, ipr: Option<&[u8]>) -> Vec<u8> {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_colorspace_unknown(codec, unknown) != 0);
+    assert!(opj_encoder_set_ipr(codec, ipr.map(|ipr| ipr.to_vec())) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Read the header, returns the colour space of the image and the
+/// contents of the intellectual property box.
+fn read_header(bytes: Vec<u8>) -> (COLOR_SPACE, Option<Vec<u8>>) {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_JP2);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let (color_space, ipr) = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    let ipr = opj_get_ipr(codec);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+    ((*image).color_space, ipr)
+  };
+  opj_image_destroy(image);
+  (color_space, ipr)
+}
+
+#[test]
+fn default_flags() {
+  let bytes = encode_jp2(false, None);
+  assert_eq!(ihdr_flags(&bytes), (0, 0));
+  assert_eq!(read_header(bytes), (OPJ_CLRSPC_GRAY, None));
+}
+
+#[test]
+fn colorspace_unknown() {
+  let bytes = encode_jp2(true, None);
+  assert_eq!(ihdr_flags(&bytes), (1, 0));
+  assert_eq!(read_header(bytes), (OPJ_CLRSPC_UNKNOWN, None));
+}
+
+#[test]
+fn intellectual_property() {
+  let rights = b"<rights>All rights reserved</rights>";
+  let bytes = encode_jp2(false, Some(rights));
+  assert_eq!(ihdr_flags(&bytes), (0, 1));
+  assert_eq!(opj_validate_structure(&bytes), Ok(()));
+  assert_eq!(read_header(bytes), (OPJ_CLRSPC_GRAY, Some(rights.to_vec())));
+}
+
+#[test]
+fn safe_codec_flags() {
+  let image = unsafe { Box::from_raw(grey_image(16, 16)) };
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image).expect("encoder");
+  assert!(codec.set_colorspace_unknown(true));
+  assert!(codec.set_ipr(Some(b"rights".to_vec())));
+  // Kept when the codec is set up again.
+  assert!(codec.set_target_size(1 << 20));
+  let bytes = codec.encode().expect("encoded image").into_bytes();
+  assert_eq!(ihdr_flags(&bytes), (1, 1));
+
+  let decoded = Jp2Codec::decoder(OPJ_CODEC_JP2, bytes)
+    .and_then(|codec| codec.read_header())
+    .and_then(|codec| codec.decode())
+    .expect("decoded image")
+    .into_image();
+  assert_eq!(decoded.color_space, OPJ_CLRSPC_UNKNOWN);
+}
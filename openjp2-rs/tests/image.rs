@@ -0,0 +1,512 @@
+use openjp2::image::UpsampleFilter;
+use openjp2::openjpeg::*;
+
+fn two_component_image() -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = 4;
+  image.y1 = 2;
+  assert!(image.alloc_comps(2, true));
+  for (compno, comp) in image.comps_mut().unwrap().iter_mut().enumerate() {
+    comp.dx = compno as u32 + 1;
+    comp.dy = 1;
+    comp.w = 4 / comp.dx;
+    comp.h = 2;
+    comp.prec = 8;
+    comp.alpha = compno as u16;
+    assert!(comp.alloc_data());
+  }
+  image
+}
+
+#[test]
+fn component_views() {
+  let mut image = two_component_image();
+  for (compno, comp) in image.components_mut().enumerate() {
+    for v in comp.data.iter_mut() {
+      *v = compno as i32 + 1;
+    }
+  }
+
+  let views = image.components().collect::<Vec<_>>();
+  assert_eq!(views.len(), 2);
+  assert_eq!((views[0].width, views[0].height, views[0].dx), (4, 2, 1));
+  assert_eq!((views[1].width, views[1].height, views[1].dx), (2, 2, 2));
+  assert_eq!(views[1].alpha, 1);
+  assert!(!views[0].sgnd);
+  assert_eq!(views[0].data, &[1; 8]);
+  assert_eq!(views[1].data, &[2; 4]);
+}
+
+fn upsampled(filter: UpsampleFilter) -> Box<opj_image> {
+  let mut image = two_component_image();
+  image.comps_mut().unwrap()[1]
+    .data_mut()
+    .unwrap()
+    .copy_from_slice(&[0, 100, 40, 80]);
+  assert!(!image.comps_match());
+  assert!(image.upsample_to_match(filter));
+  assert!(image.comps_match());
+  image
+}
+
+#[test]
+fn upsample_nearest() {
+  let image = upsampled(UpsampleFilter::Nearest);
+  let comp = &image.comps().unwrap()[1];
+  assert_eq!((comp.w, comp.h, comp.dx, comp.dy), (4, 2, 1, 1));
+  assert_eq!((comp.prec, comp.alpha), (8, 1));
+  assert_eq!(comp.data().unwrap(), &[0, 0, 100, 100, 40, 40, 80, 80]);
+}
+
+#[test]
+fn upsample_bilinear() {
+  let image = upsampled(UpsampleFilter::Bilinear);
+  let comp = &image.comps().unwrap()[1];
+  assert_eq!(comp.data().unwrap(), &[0, 25, 75, 100, 40, 50, 70, 80]);
+}
+
+#[test]
+fn into_planes() {
+  let mut image = opj_image::new();
+  image.x1 = 3;
+  image.y1 = 2;
+  assert!(image.alloc_comps(6, true));
+  for (compno, comp) in image.comps_mut().unwrap().iter_mut().enumerate() {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = 3;
+    comp.h = 2;
+    comp.prec = 12;
+    comp.sgnd = (compno % 2) as u32;
+    assert!(comp.alloc_data());
+    for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+      *v = (compno * 10 + i) as i32;
+    }
+  }
+  // The last component has no samples.
+  image.comps_mut().unwrap()[5].clear_data();
+
+  let planes = (*image).into_planes();
+  assert_eq!(planes.len(), 6);
+  for (compno, plane) in planes.iter().take(5).enumerate() {
+    assert_eq!((plane.width, plane.height, plane.prec), (3, 2, 12));
+    assert_eq!(plane.sgnd, compno % 2 == 1);
+    let expected: Vec<i32> = (0..6).map(|i| (compno * 10 + i) as i32).collect();
+    assert_eq!(plane.data, expected);
+  }
+  assert!(planes[5].data.is_empty());
+}
+
+#[test]
+fn as_rgba8() {
+  // Grey with a 4 bits opacity.
+  let mut image = upsampled(UpsampleFilter::Nearest);
+  let comps = image.comps_mut().unwrap();
+  comps[0]
+    .data_mut()
+    .unwrap()
+    .copy_from_slice(&[0, 10, 20, 30, 40, 50, 60, 255]);
+  comps[1].prec = 4;
+  comps[1]
+    .data_mut()
+    .unwrap()
+    .copy_from_slice(&[0, 0, 15, 15, 0, 0, 15, 15]);
+  let rgba = image.as_rgba8().unwrap();
+  assert_eq!(rgba.len(), 4 * 8);
+  assert_eq!(&rgba[4..8], &[10, 10, 10, 0]);
+  assert_eq!(&rgba[28..32], &[255, 255, 255, 255]);
+
+  // YCC images aren't converted.
+  image.color_space = OPJ_CLRSPC_SYCC;
+  assert_eq!(image.as_rgba8(), None);
+
+  // Nor are components of different sizes or without samples.
+  let image = two_component_image();
+  assert_eq!(image.as_rgba8(), None);
+  let mut image = upsampled(UpsampleFilter::Nearest);
+  image.comps_mut().unwrap()[1].clear_data();
+  assert_eq!(image.as_rgba8(), None);
+}
+
+/// A 16-bit grey image, a gradient of `w` samples by row.
+fn grey16_gradient(w: u32, h: u32, sample: impl Fn(u32, u32) -> i32) -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = w;
+  image.y1 = h;
+  image.color_space = OPJ_CLRSPC_GRAY;
+  assert!(image.alloc_comps(1, true));
+  let comp = &mut image.comps_mut().unwrap()[0];
+  comp.dx = 1;
+  comp.dy = 1;
+  comp.w = w;
+  comp.h = h;
+  comp.prec = 16;
+  assert!(comp.alloc_data());
+  for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+    *v = sample(i as u32 % w, i as u32 / w);
+  }
+  image
+}
+
+#[test]
+fn as_rgba8_rounding() {
+  // 127.5019... and 0.4980... in 8 bits.
+  let image = grey16_gradient(2, 1, |x, _| [32768, 128][x as usize]);
+  let grey = |rounding| {
+    let rgba = image.as_rgba8_with_rounding(rounding).unwrap();
+    (rgba[0], rgba[4])
+  };
+  assert_eq!(grey(RoundingMode::Nearest), (128, 0));
+  assert_eq!(grey(RoundingMode::Truncate), (127, 0));
+  assert_eq!(
+    image.as_rgba8(),
+    image.as_rgba8_with_rounding(RoundingMode::Nearest)
+  );
+  // Exact values are kept.
+  let image = grey16_gradient(3, 1, |x, _| [0, 257 * 100, 65535][x as usize]);
+  for rounding in [RoundingMode::Truncate, RoundingMode::Stochastic] {
+    let rgba = image.as_rgba8_with_rounding(rounding).unwrap();
+    assert_eq!((rgba[0], rgba[4], rgba[8]), (0, 100, 255));
+  }
+}
+
+#[test]
+fn stochastic_rounding_keeps_the_mean() {
+  // A slow gradient, each 8-bit step spanning many rows.
+  let (w, h) = (64, 512);
+  let image = grey16_gradient(w, h, |_, y| (y * 37) as i32);
+  let exact = |y: u32| (y * 37) as f64 * 255.0 / 65535.0;
+  let row_means = |rounding| {
+    let rgba = image.as_rgba8_with_rounding(rounding).unwrap();
+    (0..h as usize)
+      .map(|y| {
+        let row = &rgba[y * w as usize * 4..][..w as usize * 4];
+        row.iter().step_by(4).map(|&v| v as f64).sum::<f64>() / w as f64
+      })
+      .collect::<Vec<_>>()
+  };
+  let mean_error = |means: &[f64]| {
+    means
+      .iter()
+      .enumerate()
+      .map(|(y, mean)| mean - exact(y as u32))
+      .sum::<f64>()
+      / h as f64
+  };
+  let stochastic = row_means(RoundingMode::Stochastic);
+  let truncate = row_means(RoundingMode::Truncate);
+  // Truncation loses half a step on average, the dithering nothing.
+  assert!((mean_error(&truncate) + 0.5).abs() < 0.05);
+  assert!(mean_error(&stochastic).abs() < 0.02);
+  // Each row is dithered between the 2 nearest values.
+  for (y, mean) in stochastic.iter().enumerate() {
+    assert!((mean - exact(y as u32)).abs() < 0.3, "row {}", y);
+  }
+  // The noise only depends on the position of the samples.
+  assert_eq!(
+    image.as_rgba8_with_rounding(RoundingMode::Stochastic),
+    image.as_rgba8_with_rounding(RoundingMode::Stochastic)
+  );
+}
+
+#[test]
+fn as_f32_planes() {
+  let mut image = upsampled(UpsampleFilter::Nearest);
+  let comps = image.comps_mut().unwrap();
+  comps[0]
+    .data_mut()
+    .unwrap()
+    .copy_from_slice(&[0, 51, 102, 255, 0, 0, 0, 0]);
+  comps[1].prec = 12;
+  comps[1].sgnd = 1;
+  comps[1]
+    .data_mut()
+    .unwrap()
+    .copy_from_slice(&[-2048, -2047, 0, 2047, 0, 0, 0, 0]);
+
+  let planes = image.as_f32_planes(true);
+  assert_eq!(planes.len(), 2);
+  assert_eq!(&planes[0][..4], &[0.0, 0.2, 0.4, 1.0]);
+  assert_eq!(&planes[1][..4], &[-1.0, -1.0, 0.0, 1.0]);
+
+  // Without normalization, the values are kept.
+  let planes = image.as_f32_planes(false);
+  assert_eq!(&planes[0][..4], &[0.0, 51.0, 102.0, 255.0]);
+  assert_eq!(&planes[1][..4], &[-2048.0, -2047.0, 0.0, 2047.0]);
+
+  image.comps_mut().unwrap()[1].clear_data();
+  assert!(image.as_f32_planes(true)[1].is_empty());
+}
+
+#[test]
+fn as_rgba8_is_opaque_without_opacity() {
+  let mut image = opj_image::new();
+  image.x1 = 2;
+  image.y1 = 1;
+  assert!(image.alloc_comps(3, true));
+  for (compno, comp) in image.comps_mut().unwrap().iter_mut().enumerate() {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = 2;
+    comp.h = 1;
+    comp.prec = 8;
+    comp.sgnd = (compno == 2) as u32;
+    assert!(comp.alloc_data());
+    comp
+      .data_mut()
+      .unwrap()
+      .copy_from_slice(&[compno as i32, -128]);
+  }
+  // The signed component is shifted to unsigned samples.
+  assert_eq!(image.as_rgba8().unwrap(), [0, 1, 130, 255, 0, 0, 0, 255]);
+}
+
+#[test]
+fn zero_size_components() {
+  let mut image = opj_image::new();
+  assert!(image.alloc_comps(2, true));
+  for (compno, comp) in image.comps_mut().unwrap().iter_mut().enumerate() {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = compno as u32;
+    comp.h = 0;
+    comp.prec = 8;
+    // Nothing to allocate.
+    assert!(comp.alloc_data());
+    assert_eq!(comp.data(), Some(&[][..]));
+    assert_eq!(comp.data_mut(), Some(&mut [][..]));
+  }
+  assert!(image.components().all(|comp| comp.data.is_empty()));
+  assert!(!image.upsample_to_match(UpsampleFilter::Bilinear));
+  image.comps_mut().unwrap()[1].w = 0;
+  assert_eq!(image.as_rgba8(), Some(Vec::new()));
+  let planes = image.into_planes();
+  assert!(planes.iter().all(|plane| plane.data.is_empty()));
+}
+
+/// Parameters and samples of each component.
+fn comp_fields(image: &opj_image) -> Vec<(Vec<u32>, Vec<i32>)> {
+  image
+    .comps()
+    .unwrap()
+    .iter()
+    .map(|c| {
+      (
+        vec![
+          c.dx,
+          c.dy,
+          c.w,
+          c.h,
+          c.x0,
+          c.y0,
+          c.prec,
+          c.sgnd,
+          c.alpha as u32,
+        ],
+        c.data().unwrap().to_vec(),
+      )
+    })
+    .collect()
+}
+
+#[test]
+fn cache_bytes_round_trip() {
+  // A signed full resolution component, a subsampled one and an opacity.
+  let mut image = opj_image::new();
+  image.x0 = 1;
+  image.y0 = 2;
+  image.x1 = 9;
+  image.y1 = 6;
+  image.color_space = OPJ_CLRSPC_SYCC;
+  assert!(image.copy_icc_profile(b"not really a profile"));
+  assert!(image.alloc_comps(3, true));
+  for (compno, comp) in image.comps_mut().unwrap().iter_mut().enumerate() {
+    comp.dx = if compno == 1 { 2 } else { 1 };
+    comp.dy = comp.dx;
+    comp.w = 8 / comp.dx;
+    comp.h = 4 / comp.dy;
+    comp.x0 = 1;
+    comp.y0 = 1;
+    comp.prec = [12, 8, 1][compno];
+    comp.sgnd = (compno == 0) as u32;
+    comp.alpha = (compno == 2) as u16;
+    assert!(comp.alloc_data());
+    for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+      *v = match compno {
+        0 => i as i32 * 97 - 2048,
+        1 => i as i32 * 31,
+        _ => i as i32 % 2,
+      };
+    }
+  }
+
+  let bytes = image.to_cache_bytes();
+  let loaded = opj_image::from_cache_bytes(&bytes).expect("valid cache");
+  assert_eq!(
+    (loaded.x0, loaded.y0, loaded.x1, loaded.y1),
+    (image.x0, image.y0, image.x1, image.y1)
+  );
+  assert_eq!(loaded.color_space, OPJ_CLRSPC_SYCC);
+  assert_eq!(loaded.icc_profile(), image.icc_profile());
+  assert_eq!(comp_fields(&loaded), comp_fields(&image));
+  // The reloaded image serializes to the same bytes.
+  assert_eq!(loaded.to_cache_bytes(), bytes);
+}
+
+#[test]
+fn invalid_cache_bytes() {
+  let bytes = two_component_image().to_cache_bytes();
+  assert!(opj_image::from_cache_bytes(&bytes).is_ok());
+
+  let mut bad_magic = bytes.clone();
+  bad_magic[0] ^= 1;
+  assert!(opj_image::from_cache_bytes(&bad_magic).is_err());
+  // Truncated anywhere, or with trailing bytes.
+  for len in 0..bytes.len() {
+    assert!(
+      opj_image::from_cache_bytes(&bytes[..len]).is_err(),
+      "{}",
+      len
+    );
+  }
+  let mut trailing = bytes.clone();
+  trailing.push(0);
+  assert!(opj_image::from_cache_bytes(&trailing).is_err());
+  // A huge component must not be allocated: the height of the first one
+  // follows the magic, the image area, colour space, empty ICC profile,
+  // number of components and dx, dy, w.
+  let mut huge = bytes;
+  let h = 8 + 16 + 4 + 4 + 4 + 12;
+  huge[h..h + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+  assert!(opj_image::from_cache_bytes(&huge).is_err());
+}
+
+#[test]
+fn uncompressed_size_of_subsampled_image() {
+  let mut image = opj_image::new();
+  image.x0 = 1;
+  image.x1 = 9;
+  image.y1 = 5;
+  assert!(image.alloc_comps(3, true));
+  for (comp, (dx, dy, prec)) in
+    image
+      .comps_mut()
+      .unwrap()
+      .iter_mut()
+      .zip([(1, 1, 8), (2, 2, 12), (2, 1, 1)])
+  {
+    comp.dx = dx;
+    comp.dy = dy;
+    comp.prec = prec;
+  }
+  // 8x5x8 + 4x3x12 + 4x5x1 = 484 bits.
+  assert_eq!(image.uncompressed_size(), 61);
+  assert_eq!(image.max_compression_ratio(), 61.0);
+
+  assert_eq!(
+    image.recommended_rates(&[2.0, 10.0, 40.0]),
+    Some(vec![40.0, 10.0, 2.0])
+  );
+  assert_eq!(image.recommended_rates(&[]), None);
+  assert_eq!(image.recommended_rates(&[1.0, 10.0]), None);
+  assert_eq!(image.recommended_rates(&[10.0, 2.0]), None);
+  assert_eq!(image.recommended_rates(&[10.0, 10.0]), None);
+  assert_eq!(image.recommended_rates(&[2.0, 100.0]), None);
+}
+
+fn rotation_image() -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x0 = 1;
+  image.x1 = 7;
+  image.y1 = 4;
+  assert!(image.alloc_comps(2, true));
+  for (compno, comp) in image.comps_mut().unwrap().iter_mut().enumerate() {
+    comp.dx = compno as u32 + 1;
+    comp.dy = 1;
+    comp.x0 = (1 + comp.dx - 1) / comp.dx;
+    comp.w = (7 + comp.dx - 1) / comp.dx - comp.x0;
+    comp.h = 4;
+    comp.prec = 8;
+    assert!(comp.alloc_data());
+    for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+      *v = (compno * 100 + i) as i32;
+    }
+  }
+  image
+}
+
+fn comp_geometry(image: &opj_image) -> Vec<(u32, u32, u32, u32, u32, u32, Vec<i32>)> {
+  image
+    .comps()
+    .unwrap()
+    .iter()
+    .map(|c| {
+      let data = c.data().unwrap().to_vec();
+      (c.dx, c.dy, c.x0, c.y0, c.w, c.h, data)
+    })
+    .collect()
+}
+
+#[test]
+fn rotate90_swaps_axes() {
+  let mut image = rotation_image();
+  image.rotate90(1);
+  assert_eq!((image.x0, image.y0, image.x1, image.y1), (0, 1, 4, 7));
+  let comps = comp_geometry(&image);
+  // 6x4 component, now 4x6: the first row is the first column read upwards.
+  assert_eq!(
+    (comps[0].0, comps[0].1, comps[0].2, comps[0].3, comps[0].4, comps[0].5),
+    (1, 1, 0, 1, 4, 6)
+  );
+  assert_eq!(&comps[0].6[..4], &[18, 12, 6, 0]);
+  // The horizontally subsampled 3x4 component becomes vertically subsampled.
+  assert_eq!(
+    (comps[1].0, comps[1].1, comps[1].2, comps[1].3, comps[1].4, comps[1].5),
+    (1, 2, 0, 1, 4, 3)
+  );
+  assert_eq!(&comps[1].6[..4], &[109, 106, 103, 100]);
+
+  let mut ccw = rotation_image();
+  ccw.rotate90(3);
+  assert_eq!(&comp_geometry(&ccw)[0].6[..4], &[5, 11, 17, 23]);
+}
+
+#[test]
+fn rotate90_round_trip() {
+  let original = rotation_image();
+  let mut image = rotation_image();
+  image.rotate90(1);
+  image.rotate90(3);
+  assert_eq!(comp_geometry(&image), comp_geometry(&original));
+  assert_eq!((image.x0, image.y0, image.x1, image.y1), (1, 0, 7, 4));
+
+  for turns in [0u8, 4, 8, 252] {
+    let mut image = rotation_image();
+    image.rotate90(turns);
+    assert_eq!(comp_geometry(&image), comp_geometry(&original));
+  }
+
+  let mut image = rotation_image();
+  image.rotate90(2);
+  image.rotate90(2);
+  assert_eq!(comp_geometry(&image), comp_geometry(&original));
+}
+
+#[test]
+fn flip_components() {
+  let original = rotation_image();
+  let mut image = rotation_image();
+  image.flip_horizontal();
+  let comps = comp_geometry(&image);
+  assert_eq!(&comps[0].6[..6], &[5, 4, 3, 2, 1, 0]);
+  assert_eq!(&comps[1].6[..3], &[102, 101, 100]);
+  image.flip_horizontal();
+  assert_eq!(comp_geometry(&image), comp_geometry(&original));
+
+  image.flip_vertical();
+  let comps = comp_geometry(&image);
+  assert_eq!(&comps[0].6[..6], &[18, 19, 20, 21, 22, 23]);
+  assert_eq!(&comps[1].6[..3], &[109, 110, 111]);
+  image.flip_vertical();
+  assert_eq!(comp_geometry(&image), comp_geometry(&original));
+}
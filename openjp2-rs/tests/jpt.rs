@@ -0,0 +1,42 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode_tiled(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = 16;
+  params.cp_tdy = 16;
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+#[test]
+fn jpt_stream_tile_data_bins() {
+  let image = grey_image(32, 32);
+  let expected = samples(image);
+  let bytes = encode_tiled(image);
+  let decoded = TestDecoder::new(OPJ_CODEC_JPT, codestream_to_jpt(&bytes))
+    .decode()
+    .expect("decoded JPT-stream");
+  assert_eq!(samples(decoded), expected);
+  opj_image_destroy(decoded);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn jpp_stream_is_rejected() {
+  let mut jpp = Vec::new();
+  write_msg(&mut jpp, CLASS_PRECINCT, 0, &[0xFF, 0x4F]);
+  assert!(TestDecoder::new(OPJ_CODEC_JPT, jpp).decode().is_none());
+}
@@ -0,0 +1,182 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 37;
+const H: u32 = 21;
+/// Offset of the image on the reference grid, the tiles start at 0.
+const X0: u32 = 5;
+const Y0: u32 = 3;
+
+/// Encode a greyscale image at `(X0, Y0)` with `tdx`x`tdy` tiles. Returns
+/// the codestream and the samples.
+fn encode(tdx: u32, tdy: u32) -> (Vec<u8>, Vec<i32>) {
+  let image = grey_image(W, H);
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = tdx as i32;
+  params.cp_tdy = tdy as i32;
+  params.numresolution = 3;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  let samples = unsafe {
+    assert!((*image).set_image_offset(X0, Y0));
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    (*image).comps().unwrap()[0].data().unwrap().to_vec()
+  };
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  (bytes, samples)
+}
+
+/// Check that `image` is the area `(x0, y0, x1, y1)` of the encoded
+/// samples.
+unsafe fn check_area(image: *const opj_image_t, area: (u32, u32, u32, u32), samples: &[i32]) {
+  let (x0, y0, x1, y1) = area;
+  assert_eq!(((*image).x0, (*image).y0, (*image).x1, (*image).y1), area);
+  let comp = &(*image).comps().unwrap()[0];
+  assert_eq!(
+    (comp.x0, comp.y0, comp.w, comp.h),
+    (x0, y0, x1 - x0, y1 - y0)
+  );
+  let data = comp.data().unwrap();
+  assert_eq!(data.len(), (comp.w * comp.h) as usize);
+  for y in y0..y1 {
+    for x in x0..x1 {
+      assert_eq!(
+        data[((y - y0) * comp.w + x - x0) as usize],
+        samples[((y - Y0) * W + x - X0) as usize],
+        "sample ({}, {})",
+        x,
+        y
+      );
+    }
+  }
+}
+
+fn decoder(bytes: Vec<u8>) -> (*mut opj_codec_t, *mut opj_stream_t, *mut opj_image_t) {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+  }
+  (codec, stream, image)
+}
+
+unsafe fn destroy(codec: *mut opj_codec_t, stream: *mut opj_stream_t, image: *mut opj_image_t) {
+  opj_destroy_codec(codec);
+  opj_stream_destroy(stream);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn tile_larger_than_image() {
+  let (bytes, samples) = encode(4096, 4096);
+  let image_area = (X0, Y0, X0 + W, Y0 + H);
+
+  // The whole image.
+  let (codec, stream, image) = decoder(bytes.clone());
+  unsafe {
+    let mut info = opj_get_cstr_info(codec);
+    assert_eq!(((*info).tw, (*info).th), (1, 1));
+    assert_eq!(((*info).tdx, (*info).tdy), (4096, 4096));
+    opj_destroy_cstr_info(&mut info);
+    assert!(opj_decode(codec, stream, image) != 0);
+    check_area(image, image_area, &samples);
+    destroy(codec, stream, image);
+  }
+
+  // The single tile is clamped to the image.
+  let (codec, stream, image) = decoder(bytes.clone());
+  unsafe {
+    assert!(opj_decode_tile(codec, stream, 1).is_null());
+    let tile = opj_decode_tile(codec, stream, 0);
+    assert!(!tile.is_null());
+    check_area(tile, image_area, &samples);
+    opj_image_destroy(tile);
+    destroy(codec, stream, image);
+  }
+
+  // Same with the tile-by-tile API.
+  let (codec, stream, image) = decoder(bytes);
+  unsafe {
+    let (mut index, mut size, mut nb_comps, mut go_on) = (0, 0, 0, 0);
+    let (mut x0, mut y0, mut x1, mut y1) = (0, 0, 0, 0);
+    assert!(
+      opj_read_tile_header(
+        codec,
+        stream,
+        &mut index,
+        &mut size,
+        &mut x0,
+        &mut y0,
+        &mut x1,
+        &mut y1,
+        &mut nb_comps,
+        &mut go_on,
+      ) != 0
+    );
+    assert_eq!(go_on, 1);
+    assert_eq!((index, nb_comps), (0, 1));
+    assert_eq!(
+      (x0, y0, x1, y1),
+      (X0 as i32, Y0 as i32, (X0 + W) as i32, (Y0 + H) as i32)
+    );
+    // One byte by 8-bit sample.
+    assert_eq!(size, W * H);
+    let mut data = vec![0u8; size as usize];
+    assert!(opj_decode_tile_data(codec, index, data.as_mut_ptr(), size, stream) != 0);
+    let expected = samples.iter().map(|&v| v as u8).collect::<Vec<_>>();
+    assert_eq!(data, expected);
+    destroy(codec, stream, image);
+  }
+}
+
+#[test]
+fn reduced_tile_larger_than_image() {
+  let (bytes, _) = encode(4096, 4096);
+  let (codec, stream, image) = decoder(bytes);
+  unsafe {
+    assert!(opj_set_decoded_resolution_factor(codec, 1) != 0);
+    let tile = opj_decode_tile(codec, stream, 0);
+    assert!(!tile.is_null());
+    let comp = &(*tile).comps().unwrap()[0];
+    // ceil((X0 + W) / 2) - ceil(X0 / 2)
+    assert_eq!((comp.w, comp.h), (21 - 3, 12 - 2));
+    assert_eq!(comp.data().unwrap().len(), (comp.w * comp.h) as usize);
+    opj_image_destroy(tile);
+    destroy(codec, stream, image);
+  }
+}
+
+#[test]
+fn partial_last_tile() {
+  // 2 tiles: (0, 0)-(32, 4096) and (32, 0)-(64, 4096), both clamped to the
+  // image, the last one being 10 samples wide.
+  let (bytes, samples) = encode(32, 4096);
+  let (codec, stream, image) = decoder(bytes.clone());
+  unsafe {
+    for (tile_index, area) in [(1, (32, Y0, X0 + W, Y0 + H)), (0, (X0, Y0, 32, Y0 + H))] {
+      let tile = opj_decode_tile(codec, stream, tile_index);
+      assert!(!tile.is_null(), "tile {}", tile_index);
+      check_area(tile, area, &samples);
+      opj_image_destroy(tile);
+    }
+    destroy(codec, stream, image);
+  }
+
+  let (codec, stream, image) = decoder(bytes);
+  unsafe {
+    assert!(opj_decode(codec, stream, image) != 0);
+    check_area(image, (X0, Y0, X0 + W, Y0 + H), &samples);
+    destroy(codec, stream, image);
+  }
+}
@@ -0,0 +1,100 @@
+use std::ffi::CString;
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const NUMCOMPS: u32 = 2;
+
+/// Encode a 2 components image with 3 layers and 2 resolutions.
+fn encode(plt: bool) -> Vec<u8> {
+  let image = multi_component_image(NUMCOMPS, 32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  params.tcp_numlayers = 3;
+  params.tcp_rates[0] = 20.0;
+  params.tcp_rates[1] = 5.0;
+  params.tcp_rates[2] = 1.0;
+  params.cp_disto_alloc = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    if plt {
+      let option = CString::new("PLT=YES").unwrap();
+      let options = [option.as_ptr(), std::ptr::null()];
+      assert!(opj_encoder_set_extra_options(codec, options.as_ptr()) != 0);
+    }
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Decode the codestream and return the packet count and layer sizes of the
+/// single tile.
+fn layer_sizes(bytes: Vec<u8>) -> (u32, usize, Option<Vec<u64>>) {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let result = unsafe {
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    assert!(opj_get_packet_count(codec, 1).is_none());
+    assert!(opj_get_layer_sizes(codec, 1).is_none());
+    let count = opj_get_packet_count(codec, 0).expect("packet count");
+    let packets = opj_get_packet_order(codec, 0).expect("packet order");
+    (count, packets.len(), opj_get_layer_sizes(codec, 0))
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  result
+}
+
+/// Length of the packet data of the single tile-part.
+fn tile_data_len(bytes: &[u8]) -> u64 {
+  let sot = find_marker(bytes, 0xFF90).expect("SOT marker");
+  let sod = find_marker(bytes, 0xFF93).expect("SOD marker");
+  let psot = u32::from_be_bytes([
+    bytes[sot + 6],
+    bytes[sot + 7],
+    bytes[sot + 8],
+    bytes[sot + 9],
+  ]);
+  (psot as usize - (sod + 2 - sot)) as u64
+}
+
+#[test]
+fn cumulative_layer_sizes() {
+  let bytes = encode(true);
+  assert!(find_marker(&bytes, 0xFF58).is_some());
+  let data_len = tile_data_len(&bytes);
+  let (count, nb_packets, sizes) = layer_sizes(bytes);
+  // 3 layers, 2 resolutions and 2 components with a single precinct.
+  assert_eq!(count, 3 * 2 * NUMCOMPS);
+  assert_eq!(count as usize, nb_packets);
+  let sizes = sizes.expect("layer sizes");
+  assert_eq!(sizes.len(), 3);
+  assert!(sizes[0] > 0);
+  assert!(sizes.windows(2).all(|w| w[0] < w[1]), "{:?}", sizes);
+  assert_eq!(sizes[2], data_len);
+}
+
+#[test]
+fn layer_sizes_need_plt_markers() {
+  let bytes = encode(false);
+  assert!(find_marker(&bytes, 0xFF58).is_none());
+  let (count, _, sizes) = layer_sizes(bytes);
+  assert_eq!(count, 3 * 2 * NUMCOMPS);
+  assert_eq!(sizes, None);
+}
@@ -0,0 +1,215 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 64;
+
+/// Grey image with a textured content, which doesn't compress well.
+fn textured_image() -> *mut opj_image_t {
+  let image = grey_image(W, W);
+  unsafe {
+    for (i, v) in (*image).comps_mut().unwrap()[0]
+      .data_mut()
+      .unwrap()
+      .iter_mut()
+      .enumerate()
+    {
+      *v = ((i * 7919 + (i * i) % 251) % 256) as i32;
+    }
+  }
+  image
+}
+
+fn encode(image: *mut opj_image_t, setup: impl FnOnce(*mut opj_codec_t)) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  setup(codec);
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Mean squared error of the image decoded with the first `layers` layers.
+fn decoded_mse(bytes: &[u8], layers: u32, expected: &[i32]) -> f64 {
+  let stream = read_stream(bytes.to_vec());
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  params.cp_layer = layers;
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let mse = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    let actual = (*image).comps().unwrap()[0].data().unwrap();
+    let sum: f64 = expected
+      .iter()
+      .zip(actual)
+      .map(|(e, a)| ((e - a) as f64).powi(2))
+      .sum();
+    sum / expected.len() as f64
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  mse
+}
+
+/// Number of layers written in the COD marker.
+fn cod_layers(bytes: &[u8]) -> u16 {
+  let cod = find_marker(bytes, 0xFF52).expect("COD marker");
+  // Lcod(2) Scod(1) progression order(1) number of layers(2)
+  u16::from_be_bytes([bytes[cod + 6], bytes[cod + 7]])
+}
+
+#[test]
+fn layers_by_rate_improve_quality() {
+  let image = textured_image();
+  let expected = samples(image).remove(0);
+  let bytes = encode(image, |codec| unsafe {
+    assert!(opj_encoder_set_num_layers(codec, 3) != 0);
+    assert!(opj_encoder_set_layer_rates(codec, &[20.0, 4.0, 0.0]) != 0);
+  });
+  opj_image_destroy(image);
+  assert_eq!(cod_layers(&bytes), 3);
+  let mse: Vec<f64> = (1..=3).map(|l| decoded_mse(&bytes, l, &expected)).collect();
+  assert!(mse[0] > mse[1], "{:?}", mse);
+  assert!(mse[1] > mse[2], "{:?}", mse);
+  // The last layer is lossless.
+  assert_eq!(mse[2], 0.0);
+}
+
+#[test]
+fn layers_by_psnr_improve_quality() {
+  let image = textured_image();
+  let expected = samples(image).remove(0);
+  let bytes = encode(image, |codec| unsafe {
+    assert!(opj_encoder_set_num_layers(codec, 4) != 0);
+    assert!(opj_encoder_set_layer_psnr(codec, &[20.0, 30.0, 40.0, 0.0]) != 0);
+  });
+  opj_image_destroy(image);
+  assert_eq!(cod_layers(&bytes), 4);
+  let mse: Vec<f64> = (1..=4).map(|l| decoded_mse(&bytes, l, &expected)).collect();
+  for pair in mse.windows(2) {
+    assert!(pair[0] >= pair[1], "{:?}", mse);
+  }
+  assert!(mse[0] > 0.0);
+  assert_eq!(mse[3], 0.0);
+}
+
+#[test]
+fn invalid_layer_targets() {
+  let image = textured_image();
+  let mut params = opj_cparameters_t::default();
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    // The layers are set on the tiles, created by opj_setup_encoder.
+    assert!(opj_encoder_set_num_layers(codec, 2) == 0);
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_num_layers(codec, 0) == 0);
+    assert!(opj_encoder_set_num_layers(codec, 101) == 0);
+    assert!(opj_encoder_set_num_layers(codec, 2) != 0);
+    // One target per layer.
+    assert!(opj_encoder_set_layer_rates(codec, &[10.0]) == 0);
+    assert!(opj_encoder_set_layer_psnr(codec, &[30.0, 40.0, 50.0]) == 0);
+    // Rates must decrease and PSNR increase.
+    assert!(opj_encoder_set_layer_rates(codec, &[10.0, 20.0]) == 0);
+    assert!(opj_encoder_set_layer_psnr(codec, &[40.0, 30.0]) == 0);
+    assert!(opj_encoder_set_layer_rates(codec, &[-1.0, 0.0]) == 0);
+    assert!(opj_encoder_set_layer_rates(codec, &[10.0, 0.0]) != 0);
+    assert!(opj_encoder_set_layer_psnr(codec, &[30.0, 0.0]) != 0);
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
+
+/// Samples decoded with `cp_layer` set to `max_layers`, then the layer range
+/// `range` if any.
+fn decode_layers(bytes: &[u8], max_layers: u32, range: Option<(u32, u32)>) -> Vec<i32> {
+  let stream = read_stream(bytes.to_vec());
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  params.cp_layer = max_layers;
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let samples = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    if let Some((start, end)) = range {
+      assert!(opj_decoder_set_layer_range(codec, start, end) != 0);
+    }
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    (*image).comps().unwrap()[0].data().unwrap().to_vec()
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  samples
+}
+
+#[test]
+fn layer_range() {
+  let image = textured_image();
+  let bytes = encode(image, |codec| unsafe {
+    assert!(opj_encoder_set_num_layers(codec, 3) != 0);
+    assert!(opj_encoder_set_layer_rates(codec, &[20.0, 4.0, 0.0]) != 0);
+  });
+  let by_layers = (1..=3)
+    .map(|k| decode_layers(&bytes, k, None))
+    .collect::<Vec<_>>();
+  assert_ne!(by_layers[0], by_layers[1]);
+  for k in 1..=3 {
+    let expected = &by_layers[k as usize - 1];
+    assert_eq!(&decode_layers(&bytes, 0, Some((0, k))), expected);
+    // The lower layers are decoded as the base of the band.
+    assert_eq!(&decode_layers(&bytes, 0, Some((k - 1, k))), expected);
+    // The range replaces the maximum set in the parameters.
+    assert_eq!(&decode_layers(&bytes, 3, Some((0, k))), expected);
+  }
+  assert_eq!(decode_layers(&bytes, 1, Some((0, 3))), by_layers[2]);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn invalid_layer_range() {
+  let image = textured_image();
+  let bytes = encode(image, |codec| unsafe {
+    assert!(opj_encoder_set_num_layers(codec, 3) != 0);
+    assert!(opj_encoder_set_layer_rates(codec, &[20.0, 4.0, 0.0]) != 0);
+    // Not a decompressor.
+    assert!(opj_decoder_set_layer_range(codec, 0, 1) == 0);
+  });
+  opj_image_destroy(image);
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    // The number of layers is read from the main header.
+    assert!(opj_decoder_set_layer_range(codec, 0, 1) == 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decoder_set_layer_range(codec, 1, 1) == 0);
+    assert!(opj_decoder_set_layer_range(codec, 2, 1) == 0);
+    assert!(opj_decoder_set_layer_range(codec, 0, 4) == 0);
+    assert!(opj_decoder_set_layer_range(codec, 2, 3) != 0);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+}
@@ -0,0 +1,75 @@
+use std::sync::Mutex;
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+static RECORDS: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+
+struct TestLogger;
+
+impl log::Log for TestLogger {
+  fn enabled(&self, _metadata: &log::Metadata) -> bool {
+    true
+  }
+
+  fn log(&self, record: &log::Record) {
+    if record.target() == OPJ_LOG_TARGET {
+      let msg = record.args().to_string();
+      RECORDS.lock().unwrap().push((record.level(), msg));
+    }
+  }
+
+  fn flush(&self) {}
+}
+
+static LOGGER: TestLogger = TestLogger;
+
+#[test]
+fn messages_are_routed_to_log() {
+  log::set_logger(&LOGGER).unwrap();
+  log::set_max_level(log::LevelFilter::Info);
+
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    assert!(opj_use_log_crate(codec) != 0);
+    // Setting the resolutions before the encoder setup is an error.
+    assert!(opj_encoder_set_resolutions(codec, 2) == 0);
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut dparams = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    assert!(opj_use_log_crate(codec) != 0);
+    assert!(opj_setup_decoder(codec, &mut dparams) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(decoded);
+
+  let records = RECORDS.lock().unwrap();
+  assert!(records.contains(&(
+    log::Level::Error,
+    "opj_encoder_set_resolutions must be called after opj_setup_encoder".to_string()
+  )));
+  assert!(records.contains(&(
+    log::Level::Info,
+    "Header of tile 1 / 1 has been read.".to_string()
+  )));
+  // The trailing newline of the messages is removed.
+  assert!(records.iter().all(|(_, msg)| !msg.ends_with('\n')));
+}
@@ -0,0 +1,79 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Encode a lossless codestream of `numcomps` components, with the
+/// multiple component transform flag of the COD marker set to `mct`.
+/// Returns the codestream and the samples of the components.
+fn encode(numcomps: u32, mct: u8) -> (Vec<u8>, Vec<Vec<i32>>) {
+  let image = multi_component_image(numcomps, 16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  params.tcp_mct = (numcomps >= 3) as _;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let samples = samples(image);
+  let mut bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  // Lcod, Scod, progression order and number of layers come first.
+  let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+  bytes[cod + 8] = mct;
+  (bytes, samples)
+}
+
+/// Decode the codestream, returns the samples of the components if it
+/// succeeded and the errors and warnings.
+fn decode_strict(bytes: Vec<u8>, strict: bool) -> (Option<Vec<Vec<i32>>>, Vec<String>) {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_decoder_set_strict_mode(decoder.codec, strict as i32) } != 0);
+  let decoded = decoder.decode().map(|image| {
+    let decoded = samples(image);
+    opj_image_destroy(image);
+    decoded
+  });
+  (decoded, decoder.msgs().to_vec())
+}
+
+fn mct_msgs(msgs: &[String]) -> Vec<&String> {
+  msgs
+    .iter()
+    .filter(|msg| msg.contains("multiple component transform"))
+    .collect()
+}
+
+#[test]
+fn mct_with_4_components() {
+  // The transform applies to the first 3 components, e.g. of an RGBA image.
+  let (bytes, samples) = encode(4, 1);
+  let (decoded, msgs) = decode_strict(bytes, true);
+  assert_eq!(decoded, Some(samples));
+  assert!(mct_msgs(&msgs).is_empty());
+}
+
+#[test]
+fn mct_with_2_components() {
+  let (bytes, samples) = encode(2, 1);
+
+  // An error in strict mode.
+  let (decoded, msgs) = decode_strict(bytes.clone(), true);
+  assert!(decoded.is_none());
+  assert_eq!(
+    mct_msgs(&msgs),
+    ["Tile 0 uses a multiple component transform, but the image has 2 components\n"]
+  );
+
+  // Otherwise the transform is skipped.
+  let (decoded, msgs) = decode_strict(bytes, false);
+  assert_eq!(decoded, Some(samples));
+  assert_eq!(
+    mct_msgs(&msgs),
+    ["Tile 0 uses a multiple component transform, but the image has 2 components. Skipping it.\n"]
+  );
+}
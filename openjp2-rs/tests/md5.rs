@@ -0,0 +1,113 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(
+  format: OPJ_CODEC_FORMAT,
+  image: *mut opj_image_t,
+  embed: bool,
+  irreversible: bool,
+) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  if irreversible {
+    params.irreversible = 1;
+    params.tcp_numlayers = 1;
+    params.tcp_rates[0] = 8.0;
+    params.cp_disto_alloc = 1;
+  }
+  let codec = unsafe { opj_create_compress(format) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_embed_decoded_md5(codec, embed) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Decode `bytes` and verify the embedded MD5, after altering the decoded
+/// image with `alter`.
+fn decode_and_verify(
+  format: OPJ_CODEC_FORMAT,
+  bytes: Vec<u8>,
+  reduce: u32,
+  alter: fn(&mut [i32]),
+) -> Option<bool> {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(format);
+  let mut params = opj_dparameters_t::default();
+  params.cp_reduce = reduce;
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let verified = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    alter((*image).comps_mut().unwrap()[0].data_mut().unwrap());
+    let verified = opj_verify_embedded_md5(codec, image);
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+    verified
+  };
+  opj_image_destroy(image);
+  verified
+}
+
+#[test]
+fn embedded_md5() {
+  let image = grey_image(16, 16);
+  let bytes = encode(OPJ_CODEC_J2K, image, true, false);
+  opj_image_destroy(image);
+  assert!(bytes.windows(21).any(|w| w == b"openjp2 samples MD5: "));
+
+  assert_eq!(
+    decode_and_verify(OPJ_CODEC_J2K, bytes.clone(), 0, |_| ()),
+    Some(true)
+  );
+  assert_eq!(
+    decode_and_verify(OPJ_CODEC_J2K, bytes.clone(), 0, |data| data[5] ^= 1),
+    Some(false)
+  );
+  // The digest is the one of the full resolution image.
+  assert_eq!(decode_and_verify(OPJ_CODEC_J2K, bytes, 1, |_| ()), None);
+}
+
+#[test]
+fn no_embedded_md5() {
+  let image = grey_image(16, 16);
+  let bytes = encode(OPJ_CODEC_J2K, image, false, false);
+  opj_image_destroy(image);
+  assert_eq!(decode_and_verify(OPJ_CODEC_J2K, bytes, 0, |_| ()), None);
+}
+
+#[test]
+fn embedded_md5_of_lossy_codestream() {
+  let image = grey_image(16, 16);
+  let bytes = encode(OPJ_CODEC_J2K, image, true, true);
+  opj_image_destroy(image);
+  assert!(!bytes
+    .windows(32)
+    .any(|w| w == b"00000000000000000000000000000000"));
+  // The digest is the one of the reconstructed samples, not the originals.
+  assert_eq!(
+    decode_and_verify(OPJ_CODEC_J2K, bytes, 0, |_| ()),
+    Some(true)
+  );
+}
+
+#[test]
+fn embedded_md5_in_jp2() {
+  let image = grey_image(16, 16);
+  let bytes = encode(OPJ_CODEC_JP2, image, true, false);
+  opj_image_destroy(image);
+  assert_eq!(
+    decode_and_verify(OPJ_CODEC_JP2, bytes, 0, |_| ()),
+    Some(true)
+  );
+}
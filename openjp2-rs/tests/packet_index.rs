@@ -0,0 +1,153 @@
+use std::ffi::CString;
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const NUMCOMPS: u32 = 2;
+const NUMTILES: u32 = 4;
+
+/// Encode a 2 components image with 4 tiles, 3 layers and 2 resolutions,
+/// each tile being split in a tile-part by resolution.
+fn encode(plt: bool) -> Vec<u8> {
+  let image = multi_component_image(NUMCOMPS, 32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = 16;
+  params.cp_tdy = 16;
+  params.numresolution = 2;
+  params.tcp_numlayers = 3;
+  params.tcp_rates[0] = 20.0;
+  params.tcp_rates[1] = 5.0;
+  params.tcp_rates[2] = 1.0;
+  params.cp_disto_alloc = 1;
+  params.tp_on = 1;
+  params.tp_flag = b'R' as _;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    if plt {
+      let option = CString::new("PLT=YES").unwrap();
+      let options = [option.as_ptr(), std::ptr::null()];
+      assert!(opj_encoder_set_extra_options(codec, options.as_ptr()) != 0);
+    }
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+fn packet_index(bytes: Vec<u8>) -> PacketIndex {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let index = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    opj_build_packet_index(codec, stream).expect("packet index")
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  index
+}
+
+/// Decode the codestream and return the packet order and the layer sizes
+/// from the PLT markers of each tile.
+fn decoded_tiles(bytes: Vec<u8>) -> Vec<(Vec<PacketCoord>, Vec<u64>)> {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let tiles = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    (0..NUMTILES)
+      .map(|tile| {
+        (
+          opj_get_packet_order(codec, tile).expect("packet order"),
+          opj_get_layer_sizes(codec, tile).expect("layer sizes"),
+        )
+      })
+      .collect()
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  tiles
+}
+
+#[test]
+fn packet_index_matches_plt_lengths() {
+  let bytes = encode(true);
+  assert!(find_marker(&bytes, 0xFF58).is_some());
+  let index = packet_index(bytes.clone());
+  assert_eq!(index.packets.len() as u32, NUMTILES * 3 * 2 * NUMCOMPS);
+
+  for (tile, (order, sizes)) in decoded_tiles(bytes.clone()).into_iter().enumerate() {
+    let packets: Vec<_> = index.tile(tile as u32).collect();
+    let coords: Vec<_> = packets.iter().map(|packet| packet.coord).collect();
+    assert_eq!(coords, order);
+    let mut layer_sizes = vec![0u64; 3];
+    for packet in &packets {
+      layer_sizes[packet.coord.layer as usize] += packet.length as u64;
+    }
+    for layno in 1..3 {
+      layer_sizes[layno] += layer_sizes[layno - 1];
+    }
+    assert_eq!(layer_sizes, sizes, "tile {}", tile);
+  }
+
+  // The first packet follows the first SOD marker.
+  let sod = find_marker(&bytes, 0xFF93).expect("SOD marker");
+  assert_eq!(index.packets[0].offset, sod as u64 + 2);
+  // Packets don't overlap and are either contiguous or separated by a
+  // tile-part header.
+  for w in index.packets.windows(2) {
+    let end = w[0].offset + w[0].length as u64;
+    assert!(w[1].offset >= end);
+    if w[1].offset > end {
+      assert_eq!(&bytes[end as usize..end as usize + 2], [0xFF, 0x90]);
+    }
+  }
+}
+
+#[test]
+fn packet_index_without_plt_markers() {
+  let with_plt = packet_index(encode(true));
+  let bytes = encode(false);
+  assert!(find_marker(&bytes, 0xFF58).is_none());
+  let index = packet_index(bytes);
+  assert_eq!(index.packets.len(), with_plt.packets.len());
+  for (packet, expected) in index.packets.iter().zip(&with_plt.packets) {
+    assert_eq!(
+      (packet.tile, packet.coord, packet.length),
+      (expected.tile, expected.coord, expected.length)
+    );
+  }
+}
+
+#[test]
+fn packet_index_needs_a_decompressor() {
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    let stream = read_stream(Vec::new());
+    assert!(opj_build_packet_index(codec, stream).is_none());
+    opj_stream_destroy(stream);
+    opj_destroy_codec(codec);
+  }
+}
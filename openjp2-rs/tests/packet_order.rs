@@ -0,0 +1,109 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const NUMCOMPS: u32 = 2;
+
+/// Encode a 2 components image with 2 layers and 2 resolutions.
+fn encode(pocs: &[opj_poc_t]) -> Vec<u8> {
+  let image = multi_component_image(NUMCOMPS, 16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  params.tcp_numlayers = 2;
+  params.tcp_rates[0] = 10.0;
+  params.tcp_rates[1] = 1.0;
+  params.cp_disto_alloc = 1;
+  params.POC[..pocs.len()].copy_from_slice(pocs);
+  params.numpocs = pocs.len() as u32;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+fn packet_order(bytes: Vec<u8>) -> Vec<(u32, u32, u32)> {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let packets = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    // The POC markers are in the tile-part header, read when decoding.
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    assert!(opj_get_packet_order(codec, 1).is_none());
+    let packets = opj_get_packet_order(codec, 0).expect("packet order");
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+    packets
+  };
+  opj_image_destroy(image);
+  // A single precinct per resolution.
+  assert!(packets.iter().all(|p| p.precinct == 0));
+  packets
+    .iter()
+    .map(|p| (p.layer, p.resolution, p.component))
+    .collect()
+}
+
+#[test]
+fn lrcp_packet_order() {
+  let mut expected = Vec::new();
+  for layer in 0..2 {
+    for resolution in 0..2 {
+      for component in 0..NUMCOMPS {
+        expected.push((layer, resolution, component));
+      }
+    }
+  }
+  assert_eq!(packet_order(encode(&[])), expected);
+}
+
+#[test]
+fn poc_packet_order() {
+  let pocs = [
+    // RLCP for the lowest resolution.
+    opj_poc_t {
+      resno0: 0,
+      compno0: 0,
+      layno1: 2,
+      resno1: 1,
+      compno1: NUMCOMPS,
+      prg1: OPJ_RLCP,
+      tile: 1,
+      ..Default::default()
+    },
+    // Then CPRL for the other one.
+    opj_poc_t {
+      resno0: 1,
+      compno0: 0,
+      layno1: 2,
+      resno1: 2,
+      compno1: NUMCOMPS,
+      prg1: OPJ_CPRL,
+      tile: 1,
+      ..Default::default()
+    },
+  ];
+  let expected = [
+    (0, 0, 0),
+    (0, 0, 1),
+    (1, 0, 0),
+    (1, 0, 1),
+    (0, 1, 0),
+    (1, 1, 0),
+    (0, 1, 1),
+    (1, 1, 1),
+  ];
+  assert_eq!(packet_order(encode(&pocs)), expected);
+}
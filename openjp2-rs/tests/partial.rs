@@ -0,0 +1,149 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 32;
+const TILE: u32 = 16;
+
+fn encode_tiled(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = TILE as i32;
+  params.cp_tdy = TILE as i32;
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Offsets of the SOT marker of each tile, each tile has a single tile-part.
+fn tile_offsets(bytes: &[u8]) -> Vec<usize> {
+  let mut offsets = Vec::new();
+  let mut sot = find_marker(bytes, 0xFF90).expect("SOT marker");
+  while bytes[sot..sot + 2] == [0xFF, 0x90] {
+    offsets.push(sot);
+    let psot = u32::from_be_bytes([
+      bytes[sot + 6],
+      bytes[sot + 7],
+      bytes[sot + 8],
+      bytes[sot + 9],
+    ]);
+    sot += psot as usize;
+  }
+  offsets
+}
+
+fn decode_partial(bytes: Vec<u8>, partial: bool) -> Option<(*mut opj_image_t, DecodedRegion)> {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_decoder_set_partial(decoder.codec, partial) } != 0);
+  let image = decoder.decode()?;
+  Some((image, unsafe { opj_get_decoded_region(decoder.codec) }))
+}
+
+/// Check that decoded tiles match `expected` and the others are zero.
+fn check_tiles(expected: &[i32], actual: &[i32], tiles: &[u32]) {
+  let tiles_per_row = W / TILE;
+  for (i, (e, a)) in expected.iter().zip(actual).enumerate() {
+    let (x, y) = (i as u32 % W, i as u32 / W);
+    let tileno = (y / TILE) * tiles_per_row + x / TILE;
+    if tiles.contains(&tileno) {
+      assert_eq!(a, e, "sample ({}, {}) of decoded tile {}", x, y, tileno);
+    } else {
+      assert_eq!(*a, 0, "sample ({}, {}) of missing tile {}", x, y, tileno);
+    }
+  }
+}
+
+#[test]
+fn truncated_codestream_returns_decoded_tiles() {
+  let image = grey_image(W, W);
+  let expected = samples(image).remove(0);
+  let bytes = encode_tiled(image);
+  let sots = tile_offsets(&bytes);
+  assert_eq!(sots.len(), 4);
+
+  let cases = [
+    // Inside the data of the second tile.
+    (sots[1] + 20, vec![0]),
+    // At the start of the third tile.
+    (sots[2], vec![0, 1]),
+    // Inside the SOT marker segment of the fourth tile.
+    (sots[3] + 5, vec![0, 1, 2]),
+    // Inside the data of the last tile.
+    (bytes.len() - 10, vec![0, 1, 2]),
+  ];
+  for (len, tiles) in cases {
+    let (decoded, region) =
+      decode_partial(bytes[..len].to_vec(), true).expect("partial decoding succeeds");
+    assert!(region.truncated, "truncated at {}", len);
+    assert_eq!(region.nb_tiles, 4);
+    assert_eq!(region.tiles, tiles, "truncated at {}", len);
+    unsafe {
+      let actual = (*decoded).comps().unwrap()[0].data().unwrap();
+      check_tiles(&expected, actual, &tiles);
+    }
+    opj_image_destroy(decoded);
+  }
+
+  // Without partial decoding, a truncated tile is an error.
+  assert!(decode_partial(bytes[..sots[1] + 20].to_vec(), false).is_none());
+
+  // A complete codestream is reported as such.
+  let (decoded, region) = decode_partial(bytes, true).expect("complete decoding");
+  assert!(region.is_complete());
+  assert_eq!(region.tiles, [0, 1, 2, 3]);
+  opj_image_destroy(decoded);
+  opj_image_destroy(image);
+}
+
+#[test]
+fn tile_status_of_truncated_codestream() {
+  let image = grey_image(W, W);
+  let bytes = encode_tiled(image);
+  opj_image_destroy(image);
+  let sots = tile_offsets(&bytes);
+  // Truncate inside the data of the third tile.
+  let stream = read_stream(bytes[..sots[2] + 20].to_vec());
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let (count, status) = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_decoder_set_partial(codec, true) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    (
+      opj_get_decoded_tile_count(codec),
+      opj_get_tile_status(codec),
+    )
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+
+  assert_eq!(count, 2);
+  assert_eq!(
+    status.iter().map(|tile| tile.index).collect::<Vec<_>>(),
+    [0, 1, 2, 3]
+  );
+  for tile in &status[..2] {
+    assert!(tile.decoded, "tile {}", tile.index);
+    assert!(tile.bytes > 0, "tile {}", tile.index);
+    assert_eq!(tile.error, None);
+  }
+  for tile in &status[2..] {
+    assert!(!tile.decoded, "tile {}", tile.index);
+    assert!(tile.error.is_some(), "tile {}", tile.index);
+  }
+}
@@ -0,0 +1,125 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 45;
+const H: u32 = 37;
+
+/// Encode a new 3 component image with 8x8 precincts, 4x4 code-blocks and
+/// the partitions starting at `origin`, and its samples.
+fn encode(prog_order: OPJ_PROG_ORDER, origin: (u32, u32), sop: bool) -> (Vec<u8>, Vec<Vec<i32>>) {
+  let image = multi_component_image(3, W, H);
+  let expected = samples(image);
+  let mut params = opj_cparameters_t::default();
+  params.prog_order = prog_order;
+  params.numresolution = 3;
+  let style = CodingStyle {
+    resolutions: 3,
+    codeblock: (4, 4),
+    precincts: vec![(8, 8)],
+    sop,
+    partition_origin: origin,
+    ..CodingStyle::default()
+  };
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_set_coding_style(codec, &style) != 0);
+    assert_eq!(
+      opj_encoder_get_coding_style(codec).map(|style| style.partition_origin),
+      Some(origin)
+    );
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  (bytes, expected)
+}
+
+/// Decode `area` of `bytes`, or the whole image.
+fn decode_origin(bytes: Vec<u8>, area: Option<(u32, u32, u32, u32)>) -> Vec<Vec<i32>> {
+  let area = area.map(|(x0, y0, x1, y1)| (x0 as i32, y0 as i32, x1 as i32, y1 as i32));
+  let image = TestDecoder::new(OPJ_CODEC_J2K, bytes)
+    .decode_area(area)
+    .expect("decoded image");
+  let decoded = samples(image);
+  opj_image_destroy(image);
+  decoded
+}
+
+#[test]
+fn every_origin_and_progression() {
+  for &origin in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+    for &prog_order in &[OPJ_LRCP, OPJ_RLCP, OPJ_RPCL, OPJ_PCRL, OPJ_CPRL] {
+      let (bytes, expected) = encode(prog_order, origin, false);
+      // Bits 3 and 4 of Scod in the COD marker.
+      let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+      assert_eq!(
+        bytes[cod + 4] & 0x18,
+        (origin.0 << 3 | origin.1 << 4) as u8,
+        "{:?} {:?}",
+        origin,
+        prog_order
+      );
+      assert_eq!(
+        decode_origin(bytes, None),
+        expected,
+        "{:?} {:?}",
+        origin,
+        prog_order
+      );
+    }
+  }
+}
+
+/// Moving the origin by one adds a column of precincts here.
+#[test]
+fn origin_moves_the_precincts() {
+  let count_sop = |origin| {
+    let (bytes, _) = encode(OPJ_LRCP, origin, true);
+    bytes.windows(2).filter(|w| w == &[0xFF, 0x91]).count()
+  };
+  // 6x5 precincts in each resolution of each component.
+  assert_eq!(count_sop((0, 0)), 3 * 3 * 6 * 5);
+  // 7x5 precincts with the partitions starting at x = 1.
+  assert_eq!(count_sop((1, 0)), 3 * 3 * 7 * 5);
+}
+
+#[test]
+fn decode_area() {
+  let (x0, y0, x1, y1) = (7, 5, 30, 22);
+  for &prog_order in &[OPJ_LRCP, OPJ_PCRL] {
+    let (bytes, expected) = encode(prog_order, (1, 1), false);
+    let decoded = decode_origin(bytes, Some((x0, y0, x1, y1)));
+    for (comp, full) in decoded.iter().zip(&expected) {
+      let crop: Vec<i32> = (y0..y1)
+        .flat_map(|y| (x0..x1).map(move |x| full[(y * W + x) as usize]))
+        .collect();
+      assert_eq!(comp, &crop, "{:?}", prog_order);
+    }
+  }
+}
+
+#[test]
+fn invalid_origin() {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    let style = CodingStyle {
+      resolutions: 2,
+      partition_origin: (2, 0),
+      ..CodingStyle::default()
+    };
+    assert!(opj_encoder_set_coding_style(codec, &style) == 0);
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
@@ -0,0 +1,69 @@
+use std::io::Cursor;
+
+use openjp2::convert::pgx::*;
+use openjp2::openjpeg::*;
+
+fn signed_12bit_image() -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = 7;
+  image.y1 = 3;
+  assert!(image.alloc_comps(1, true));
+  let comp = &mut image.comps_mut().unwrap()[0];
+  comp.dx = 1;
+  comp.dy = 1;
+  comp.w = 7;
+  comp.h = 3;
+  comp.prec = 12;
+  comp.sgnd = 1;
+  assert!(comp.alloc_data());
+  let data = comp.data_mut().unwrap();
+  for (i, v) in data.iter_mut().enumerate() {
+    *v = (i as i32 * 195) - 2048;
+  }
+  image
+}
+
+#[test]
+fn pgx_signed_12bit_round_trip() {
+  let image = signed_12bit_image();
+  let comp = &image.comps().unwrap()[0];
+
+  for order in [PgxByteOrder::BigEndian, PgxByteOrder::LittleEndian] {
+    let mut buf = Vec::new();
+    write_pgx_component(comp, order, &mut buf).expect("write pgx");
+    let token = match order {
+      PgxByteOrder::BigEndian => "ML",
+      PgxByteOrder::LittleEndian => "LM",
+    };
+    let header = format!("PG {token} - 12 7 3\n");
+    assert!(buf.starts_with(header.as_bytes()));
+    assert_eq!(buf.len(), header.len() + 7 * 3 * 2);
+
+    let decoded = read_pgx(&mut Cursor::new(buf)).expect("read pgx");
+    let decoded_comp = &decoded.comps().unwrap()[0];
+    assert_eq!(decoded_comp.prec, 12);
+    assert_eq!(decoded_comp.sgnd, 1);
+    assert_eq!((decoded_comp.w, decoded_comp.h), (7, 3));
+    assert_eq!(decoded_comp.data(), comp.data());
+  }
+}
+
+#[test]
+fn pgx_samples_clamped_to_depth() {
+  let mut image = signed_12bit_image();
+  let comp = &mut image.comps_mut().unwrap()[0];
+  comp.data_mut().unwrap()[..2].copy_from_slice(&[-5000, 5000]);
+  let mut buf = Vec::new();
+  write_pgx_component(comp, PgxByteOrder::BigEndian, &mut buf).expect("write pgx");
+  let decoded = read_pgx(&mut Cursor::new(buf)).expect("read pgx");
+  let data = decoded.comps().unwrap()[0].data().unwrap();
+  assert_eq!(&data[..2], &[-2048, 2047]);
+
+  comp.sgnd = 0;
+  comp.data_mut().unwrap()[..2].copy_from_slice(&[-1, 5000]);
+  let mut buf = Vec::new();
+  write_pgx_component(comp, PgxByteOrder::BigEndian, &mut buf).expect("write pgx");
+  let decoded = read_pgx(&mut Cursor::new(buf)).expect("read pgx");
+  let data = decoded.comps().unwrap()[0].data().unwrap();
+  assert_eq!(&data[..2], &[0, 4095]);
+}
@@ -0,0 +1,268 @@
+use std::io::{BufWriter, Cursor, ErrorKind, Read, Write};
+
+use openjp2::convert::pnm::{load_pnm_image, read_pnm};
+use openjp2::convert::{SaveOptions, SignedHandling};
+use openjp2::openjpeg::*;
+
+fn image(numcomps: u32, prec: u32, sgnd: bool, samples: &[&[i32]]) -> Box<opj_image> {
+  let w = samples[0].len() as u32;
+  let mut image = opj_image::new();
+  image.x1 = w;
+  image.y1 = 1;
+  assert!(image.alloc_comps(numcomps, true));
+  for (comp, samples) in image.comps_mut().unwrap().iter_mut().zip(samples) {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = w;
+    comp.h = 1;
+    comp.prec = prec;
+    comp.sgnd = sgnd as u32;
+    assert!(comp.alloc_data());
+    comp.data_mut().unwrap().copy_from_slice(samples);
+  }
+  image
+}
+
+#[test]
+fn ppm_16bit_samples() {
+  let image = image(3, 12, false, &[&[0, 4095], &[1, 256], &[2, 5000]]);
+  let mut buf = Vec::new();
+  image.write_ppm(&mut buf).expect("write ppm");
+  let header = b"P6\n2 1\n4095\n";
+  assert_eq!(&buf[..header.len()], header);
+  assert_eq!(
+    &buf[header.len()..],
+    &[0, 0, 0, 1, 0, 2, 0x0f, 0xff, 0x01, 0x00, 0x0f, 0xff]
+  );
+}
+
+#[test]
+fn pgm_signed_8bit_samples() {
+  let image = image(1, 8, true, &[&[-128, 0, 127]]);
+  let mut buf = Vec::new();
+  image.write_pgm(&mut buf).expect("write pgm");
+  assert_eq!(buf, b"P5\n3 1\n255\n\x00\x80\xff");
+  assert!(image.write_ppm(&mut Vec::new()).is_err());
+}
+
+fn samples(image: &opj_image) -> Vec<Vec<i32>> {
+  image
+    .comps()
+    .unwrap()
+    .iter()
+    .map(|comp| comp.data().unwrap().to_vec())
+    .collect()
+}
+
+#[test]
+fn read_written_ppm() {
+  let image = image(3, 12, false, &[&[0, 4095], &[1, 256], &[2, 4000]]);
+  let mut buf = Vec::new();
+  image.write_ppm(&mut buf).expect("write ppm");
+  let read = read_pnm(&mut Cursor::new(buf)).expect("read ppm");
+  assert_eq!(read.color_space, OPJ_CLRSPC_SRGB);
+  assert_eq!((read.x1, read.y1), (2, 1));
+  assert!(read.comps().unwrap().iter().all(|comp| comp.prec == 12));
+  assert_eq!(samples(&read), samples(&image));
+}
+
+#[test]
+fn pgm_header_with_comments() {
+  let mut reader =
+    Cursor::new(b"P5\n# created by hand\n 3  2\n# maxval\n255\n\x01\x02\x03\x04\x05\x06".to_vec());
+  let image = read_pnm(&mut reader).expect("read pgm");
+  assert_eq!(image.color_space, OPJ_CLRSPC_GRAY);
+  assert_eq!((image.numcomps, image.x1, image.y1), (1, 3, 2));
+  assert_eq!(image.comps().unwrap()[0].prec, 8);
+  assert_eq!(samples(&image), vec![vec![1, 2, 3, 4, 5, 6]]);
+}
+
+#[test]
+fn reader_stops_at_the_end_of_the_raster() {
+  let mut data = b"P5 2 1 15\n\x0a\x0b".to_vec();
+  data.extend_from_slice(b"P5 1 1 255 \xff");
+  let mut reader = Cursor::new(data);
+  let first = read_pnm(&mut reader).expect("first image");
+  assert_eq!(first.comps().unwrap()[0].prec, 4);
+  assert_eq!(samples(&first), vec![vec![10, 11]]);
+  let second = read_pnm(&mut reader).expect("second image");
+  assert_eq!(samples(&second), vec![vec![255]]);
+}
+
+#[test]
+fn invalid_pnm() {
+  let truncated = read_pnm(&mut Cursor::new(b"P5 2 2 255\n\x00\x00\x00".to_vec()));
+  assert_eq!(truncated.err().unwrap().kind(), ErrorKind::UnexpectedEof);
+  for data in [
+    &b"P2 1 1 255\n0"[..],
+    b"P5 0 1 255\n",
+    b"P5 1 1 65536\n",
+    b"P5 1 1",
+  ] {
+    let err = read_pnm(&mut Cursor::new(data.to_vec())).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData, "{:?}", data);
+  }
+}
+
+/// Write the first component of `image` as a PGM and read its samples back.
+fn pgm_samples(image: &opj_image, signed_handling: SignedHandling) -> Vec<i32> {
+  let options = SaveOptions {
+    signed_handling,
+    ..Default::default()
+  };
+  let mut buf = Vec::new();
+  image
+    .write_pgm_with_options(&options, &mut buf)
+    .expect("write pgm");
+  assert!(buf.starts_with(b"P5\n5 1\n4095\n"));
+  let read = read_pnm(&mut Cursor::new(buf)).expect("read pgm");
+  samples(&read).remove(0)
+}
+
+#[test]
+fn signed_12bit_handling() {
+  let full = image(1, 12, true, &[&[-2048, -100, 0, 100, 2047]]);
+  assert_eq!(
+    pgm_samples(&full, SignedHandling::Offset),
+    vec![0, 1948, 2048, 2148, 4095]
+  );
+  assert_eq!(
+    pgm_samples(&full, SignedHandling::ClampToZero),
+    vec![0, 0, 0, 100, 2047]
+  );
+  assert_eq!(
+    pgm_samples(&full, SignedHandling::Rescale),
+    vec![0, 1948, 2048, 2148, 4095]
+  );
+  // Offset is the default.
+  let mut buf = Vec::new();
+  full.write_pgm(&mut buf).unwrap();
+  let read = read_pnm(&mut Cursor::new(buf)).unwrap();
+  assert_eq!(
+    samples(&read)[0],
+    pgm_samples(&full, SignedHandling::Offset)
+  );
+
+  // A narrow range of values is stretched to the whole output range.
+  let narrow = image(1, 12, true, &[&[-200, -100, 0, 100, 200]]);
+  assert_eq!(
+    pgm_samples(&narrow, SignedHandling::Offset),
+    vec![1848, 1948, 2048, 2148, 2248]
+  );
+  assert_eq!(
+    pgm_samples(&narrow, SignedHandling::Rescale),
+    vec![0, 1024, 2048, 3071, 4095]
+  );
+  let flat = image(1, 12, true, &[&[-7; 5]]);
+  assert_eq!(pgm_samples(&flat, SignedHandling::Rescale), vec![0; 5]);
+}
+
+#[test]
+fn rescale_shares_the_range_of_the_components() {
+  let image = image(3, 12, true, &[&[-100, 100], &[0, 0], &[-50, 50]]);
+  let options = SaveOptions {
+    signed_handling: SignedHandling::Rescale,
+    ..Default::default()
+  };
+  let mut buf = Vec::new();
+  image.write_ppm_with_options(&options, &mut buf).unwrap();
+  let read = read_pnm(&mut Cursor::new(buf)).unwrap();
+  assert_eq!(
+    samples(&read),
+    vec![vec![0, 4095], vec![2048, 2048], vec![1024, 3071]]
+  );
+}
+
+/// Write a 17-bit component as a PGM, scaled down to 16 bits.
+fn pgm_17bit_samples(data: &[i32], rounding: RoundingMode) -> Vec<i32> {
+  let image = image(1, 17, false, &[data]);
+  let options = SaveOptions {
+    rounding,
+    ..Default::default()
+  };
+  let mut buf = Vec::new();
+  image
+    .write_pgm_with_options(&options, &mut buf)
+    .expect("write pgm");
+  let read = read_pnm(&mut Cursor::new(buf)).expect("read pgm");
+  assert_eq!(read.comps().unwrap()[0].prec, 16);
+  samples(&read).remove(0)
+}
+
+#[test]
+fn deep_samples_rounding() {
+  let deep = [0, 1, 2, 3, 131070, 131071];
+  // Halves are rounded up, and the maximum value kept.
+  assert_eq!(
+    pgm_17bit_samples(&deep, RoundingMode::Nearest),
+    vec![0, 1, 1, 2, 65535, 65535]
+  );
+  assert_eq!(
+    pgm_17bit_samples(&deep, RoundingMode::Truncate),
+    vec![0, 0, 1, 1, 65535, 65535]
+  );
+  // Nearest is the default.
+  let mut buf = Vec::new();
+  image(1, 17, false, &[&deep]).write_pgm(&mut buf).unwrap();
+  let read = read_pnm(&mut Cursor::new(buf)).unwrap();
+  assert_eq!(samples(&read)[0], vec![0, 1, 1, 2, 65535, 65535]);
+
+  // Dithering keeps the mean of a flat area of halves.
+  let flat = pgm_17bit_samples(&[3; 4000], RoundingMode::Stochastic);
+  assert!(flat.iter().all(|&v| v == 1 || v == 2));
+  let mean = flat.iter().sum::<i32>() as f64 / flat.len() as f64;
+  assert!((mean - 1.5).abs() < 0.05, "mean {}", mean);
+}
+
+/// Rows of a large synthetic PGM, generated without holding the file.
+struct SyntheticPgm {
+  header: Vec<u8>,
+  width: usize,
+  size: usize,
+  pos: usize,
+}
+
+impl Read for SyntheticPgm {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() && self.pos < self.size {
+      buf[n] = match self.pos.checked_sub(self.header.len()) {
+        None => self.header[self.pos],
+        Some(i) => (i % self.width + i / self.width) as u8,
+      };
+      n += 1;
+      self.pos += 1;
+    }
+    Ok(n)
+  }
+}
+
+/// Load a 256 MB PGM: the image needs 1 GB for its 32-bit samples, the
+/// reader only one row more. Run with `cargo test --release -- --ignored`.
+#[test]
+#[ignore]
+fn load_large_pgm() {
+  const W: usize = 16384;
+  const H: usize = 16384;
+  let header = format!("P5\n{} {}\n255\n", W, H).into_bytes();
+  let size = header.len() + W * H;
+  let mut pgm = SyntheticPgm {
+    header,
+    width: W,
+    size,
+    pos: 0,
+  };
+  let path = std::env::temp_dir().join(format!("openjp2-large-{}.pgm", std::process::id()));
+  let mut out = BufWriter::new(std::fs::File::create(&path).unwrap());
+  std::io::copy(&mut pgm, &mut out).unwrap();
+  out.flush().unwrap();
+  drop(out);
+
+  let image = load_pnm_image(&path).expect("load pgm");
+  std::fs::remove_file(&path).unwrap();
+  let data = image.comps().unwrap()[0].data().unwrap();
+  assert_eq!(data.len(), W * H);
+  for (x, y) in [(0, 0), (W - 1, 0), (5, 7), (W - 1, H - 1)] {
+    assert_eq!(data[y * W + x], ((x + y) % 256) as i32);
+  }
+}
@@ -0,0 +1,108 @@
+use std::io::Cursor;
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const NUMCOMPS: u32 = 2;
+
+/// Set up an encoder of a 2 components image with 2 layers and 2
+/// resolutions.
+fn setup_encoder(image: *mut opj_image_t) -> *mut opj_codec_t {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  params.tcp_numlayers = 2;
+  params.tcp_rates[0] = 10.0;
+  params.tcp_rates[1] = 1.0;
+  params.cp_disto_alloc = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  codec
+}
+
+fn poc(res_start: u32, res_end: u32, order: OPJ_PROG_ORDER) -> PocSpec {
+  PocSpec {
+    res_start,
+    comp_start: 0,
+    layer_end: 2,
+    res_end,
+    comp_end: NUMCOMPS,
+    order,
+  }
+}
+
+#[test]
+fn resolution_then_quality_progression() {
+  let image = multi_component_image(NUMCOMPS, 16, 16);
+  let codec = setup_encoder(image);
+  unsafe {
+    assert!(opj_encoder_add_progression_change(codec, &poc(0, 1, OPJ_RLCP)) != 0);
+    assert!(opj_encoder_add_progression_change(codec, &poc(1, 2, OPJ_CPRL)) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  assert!(find_marker(&bytes, 0xFF5F).is_some(), "POC marker");
+
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut decoded = std::ptr::null_mut() as *mut opj_image_t;
+  let packets = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut decoded) != 0);
+    assert!(opj_decode(codec, stream, decoded) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    let packets = opj_get_packet_order(codec, 0).expect("packet order");
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+    packets
+  };
+  opj_image_destroy(decoded);
+  let packets: Vec<_> = packets
+    .iter()
+    .map(|p| (p.layer, p.resolution, p.component))
+    .collect();
+  assert_eq!(
+    packets,
+    [
+      (0, 0, 0),
+      (0, 0, 1),
+      (1, 0, 0),
+      (1, 0, 1),
+      (0, 1, 0),
+      (1, 1, 0),
+      (0, 1, 1),
+      (1, 1, 1),
+    ]
+  );
+}
+
+#[test]
+fn invalid_progression_changes() {
+  let image = multi_component_image(NUMCOMPS, 16, 16);
+  let codec = setup_encoder(image);
+  unsafe {
+    // Out of range or empty.
+    assert!(opj_encoder_add_progression_change(codec, &poc(0, 3, OPJ_RLCP)) == 0);
+    assert!(opj_encoder_add_progression_change(codec, &poc(1, 1, OPJ_RLCP)) == 0);
+    assert!(opj_encoder_add_progression_change(codec, &poc(0, 1, OPJ_PROG_UNKNOWN)) == 0);
+
+    // The packets of the second resolution are missing.
+    assert!(opj_encoder_add_progression_change(codec, &poc(0, 1, OPJ_RLCP)) != 0);
+    let cursor = Box::into_raw(Box::new(Cursor::new(Vec::new())));
+    let stream = cursor_stream(cursor, 0, false);
+    assert!(opj_start_compress(codec, image, stream) == 0);
+    opj_stream_destroy(stream);
+    drop(Box::from_raw(cursor));
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+}
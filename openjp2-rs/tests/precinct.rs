@@ -0,0 +1,122 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 64;
+
+/// Encode with 3 resolutions and 32x32 precincts at the highest resolution,
+/// halved at each lower resolution, in `tile` x `tile` tiles if not 0.
+fn encode_precincts(image: *mut opj_image_t, tile: i32) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  if tile != 0 {
+    params.tile_size_on = 1;
+    params.cp_tdx = tile;
+    params.cp_tdy = tile;
+  }
+  params.numresolution = 3;
+  params.csty |= 0x01;
+  params.res_spec = 1;
+  params.prcw_init[0] = 32;
+  params.prch_init[0] = 32;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+fn decode_precinct(
+  stream: *mut opj_stream_t,
+  tile_index: u32,
+  resno: u32,
+  precno: u32,
+) -> Option<PrecinctData> {
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let data = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    opj_decode_precinct(codec, stream, image, tile_index, 0, resno, precno)
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  data
+}
+
+#[test]
+fn decode_single_precinct() {
+  let image = grey_image(W, W);
+  let expected = samples(image).remove(0);
+  let bytes = encode_precincts(image, 0);
+  opj_image_destroy(image);
+
+  // Bottom right precinct of the full resolution.
+  let prc = decode_precinct(read_stream(bytes.clone()), 0, 2, 3).expect("precinct");
+  assert_eq!((prc.x0, prc.y0, prc.x1, prc.y1), (32, 32, 64, 64));
+  assert_eq!(prc.prec, 8);
+  for y in 0..prc.height() {
+    let row = ((prc.y0 + y) * W + prc.x0) as usize;
+    let prc_row = (y * prc.width()) as usize;
+    assert_eq!(
+      prc.data[prc_row..prc_row + prc.width() as usize],
+      expected[row..row + prc.width() as usize]
+    );
+  }
+
+  // Top right precinct of the 32x32 resolution.
+  let prc = decode_precinct(read_stream(bytes.clone()), 0, 1, 1).expect("precinct");
+  assert_eq!((prc.x0, prc.y0, prc.x1, prc.y1), (16, 0, 32, 16));
+  assert_eq!(prc.data.len(), 16 * 16);
+
+  // Resolution 1 has 2x2 precincts.
+  assert!(decode_precinct(read_stream(bytes.clone()), 0, 1, 4).is_none());
+  assert!(decode_precinct(read_stream(bytes.clone()), 0, 3, 0).is_none());
+  // Tiles can't be located without seeking.
+  assert!(decode_precinct(read_stream_without_seek(bytes), 0, 2, 0).is_none());
+}
+
+#[test]
+fn decode_precinct_of_offset_tile() {
+  // 64x64 tiles on an image at (8, 4): tile 3 is the bottom right one,
+  // starting at (64, 64) on the reference grid.
+  let image = grey_image(2 * W - 8, 2 * W - 4);
+  unsafe {
+    assert!((*image).set_image_offset(8, 4));
+  }
+  let expected = samples(image).remove(0);
+  let bytes = encode_precincts(image, W as i32);
+  let (x0, y0, w) = unsafe { ((*image).x0, (*image).y0, (*image).x1 - (*image).x0) };
+  opj_image_destroy(image);
+
+  // Top left precinct of the full resolution of tile 3.
+  let prc = decode_precinct(read_stream(bytes.clone()), 3, 2, 0).expect("precinct");
+  assert_eq!((prc.x0, prc.y0, prc.x1, prc.y1), (64, 64, 96, 96));
+  for y in prc.y0..prc.y1 {
+    let row = ((y - y0) * w + prc.x0 - x0) as usize;
+    let prc_row = ((y - prc.y0) * prc.width()) as usize;
+    assert_eq!(
+      prc.data[prc_row..prc_row + prc.width() as usize],
+      expected[row..row + prc.width() as usize],
+      "row {}",
+      y
+    );
+  }
+
+  // Tile 1 starts at (64, 4), its first precinct of resolution 1 is
+  // clipped to the tile.
+  let prc = decode_precinct(read_stream(bytes.clone()), 1, 1, 0).expect("precinct");
+  assert_eq!((prc.x0, prc.y0, prc.x1, prc.y1), (32, 2, 48, 16));
+
+  assert!(decode_precinct(read_stream(bytes), 4, 2, 0).is_none());
+}
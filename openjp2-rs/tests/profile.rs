@@ -0,0 +1,84 @@
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+mod common;
+use common::*;
+
+/// `Rsiz` of the 2K digital cinema profile.
+const CINEMA_2K: u16 = 0x0003;
+
+/// Encode a lossless greyscale codestream and set its `Rsiz`, which the
+/// coding parameters don't follow.
+fn mislabeled_codestream(rsiz: u16) -> Vec<u8> {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let mut bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  let siz = find_marker(&bytes, 0xFF51).expect("SIZ marker");
+  bytes[siz + 4..siz + 6].copy_from_slice(&rsiz.to_be_bytes());
+  bytes
+}
+
+/// Read the header, returns whether it succeeded and the errors and
+/// warnings.
+fn read_header(bytes: Vec<u8>, validate: bool, strict: bool) -> (bool, Vec<String>) {
+  let decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let ok = unsafe {
+    assert!(opj_decoder_set_strict_mode(decoder.codec, strict as i32) != 0);
+    assert!(opj_decoder_set_validate_profile(decoder.codec, validate) != 0);
+    opj_read_header(decoder.stream, decoder.codec, &mut image) != 0
+  };
+  opj_image_destroy(image);
+  (ok, decoder.msgs().to_vec())
+}
+
+fn profile_msgs(msgs: &[String]) -> Vec<&String> {
+  msgs
+    .iter()
+    .filter(|msg| msg.starts_with("2K digital cinema profile"))
+    .collect()
+}
+
+#[test]
+fn mislabeled_cinema_codestream() {
+  let bytes = mislabeled_codestream(CINEMA_2K);
+
+  let (ok, msgs) = read_header(bytes.clone(), true, true);
+  assert!(!ok);
+  let violations = profile_msgs(&msgs);
+  assert!(violations.iter().any(|msg| msg.contains("3 components")));
+  assert!(violations.iter().any(|msg| msg.contains("precision")));
+  assert!(violations.iter().any(|msg| msg.contains("CPRL")));
+  assert!(violations.iter().any(|msg| msg.contains("9-7")));
+
+  // Warnings only without strict mode.
+  let (ok, msgs) = read_header(bytes.clone(), true, false);
+  assert!(ok);
+  assert_eq!(profile_msgs(&msgs).len(), violations.len());
+
+  // No check by default.
+  let (ok, msgs) = read_header(bytes.clone(), false, true);
+  assert!(ok);
+  assert!(profile_msgs(&msgs).is_empty());
+
+  let mut codec = Jp2Codec::decoder(OPJ_CODEC_J2K, bytes).expect("decoder");
+  assert!(codec.set_validate_profile(true));
+  assert!(codec.read_header().is_none());
+}
+
+#[test]
+fn codestream_without_profile() {
+  let (ok, msgs) = read_header(mislabeled_codestream(0), true, true);
+  assert!(ok);
+  assert!(msgs.iter().all(|msg| !msg.contains("profile")));
+}
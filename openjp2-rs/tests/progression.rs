@@ -0,0 +1,198 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const PROGRESSIONS: [Progression; 5] = [
+  Progression::LRCP,
+  Progression::RLCP,
+  Progression::RPCL,
+  Progression::PCRL,
+  Progression::CPRL,
+];
+
+/// Encode a new lossless RGB image with 3 resolutions and 2 layers, in the
+/// progression order `progression`, optionally split in a tile-part by
+/// resolution.
+fn encode(progression: Progression, tile_parts: bool) -> Vec<u8> {
+  let image = multi_component_image(3, 32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  params.tcp_numlayers = 2;
+  params.tcp_rates[0] = 4.0;
+  params.tcp_rates[1] = 0.0;
+  params.cp_disto_alloc = 1;
+  if tile_parts {
+    params.tp_on = 1;
+    params.tp_flag = b'R' as _;
+  }
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert_eq!(opj_get_progression(codec, 0), Some(Progression::LRCP));
+    assert!(opj_encoder_set_progression(codec, progression) != 0);
+    assert_eq!(opj_get_progression(codec, 0), Some(progression));
+    assert_eq!(opj_get_progression(codec, 1), None);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Decode `bytes`, returns the progression order of the tile, its packets
+/// and the samples.
+fn decode_progression(bytes: Vec<u8>) -> (Option<Progression>, Vec<PacketCoord>, Vec<Vec<i32>>) {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  unsafe {
+    assert!(opj_encoder_set_progression(decoder.codec, Progression::LRCP) == 0);
+    assert!(opj_get_progression(decoder.codec, 0).is_none());
+  }
+  let image = decoder.decode().expect("decoded image");
+  let decoded = unsafe {
+    (
+      opj_get_progression(decoder.codec, 0),
+      opj_get_packet_order(decoder.codec, 0).expect("packet order"),
+      samples(image),
+    )
+  };
+  opj_image_destroy(image);
+  decoded
+}
+
+#[test]
+fn progression_round_trip() {
+  let image = multi_component_image(3, 32, 32);
+  let expected = samples(image);
+  opj_image_destroy(image);
+  for progression in PROGRESSIONS {
+    assert_eq!(
+      Progression::from_c_enum(progression.to_c_enum()),
+      Some(progression)
+    );
+    for tile_parts in [false, true] {
+      let bytes = encode(progression, tile_parts);
+      // Lcod and Scod, then the progression order.
+      let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+      assert_eq!(bytes[cod + 5] as OPJ_PROG_ORDER, progression.to_c_enum());
+
+      let (decoded, packets, samples) = decode_progression(bytes);
+      assert_eq!(decoded, Some(progression));
+      assert_eq!(samples, expected, "{:?}", progression);
+      // 2 layers, 3 resolutions and 3 components with a precinct each.
+      assert_eq!(packets.len(), 18);
+      // The outermost step of the progression order.
+      let outer = |p: &PacketCoord| match progression {
+        Progression::LRCP => p.layer,
+        Progression::RLCP | Progression::RPCL => p.resolution,
+        Progression::PCRL => p.precinct,
+        Progression::CPRL => p.component,
+      };
+      assert!(packets.windows(2).all(|w| outer(&w[0]) <= outer(&w[1])));
+      // The innermost step.
+      let second = &packets[1];
+      assert_eq!(
+        (second.layer, second.resolution, second.component),
+        match progression {
+          Progression::LRCP | Progression::RLCP => (0, 0, 1),
+          Progression::RPCL | Progression::PCRL | Progression::CPRL => (1, 0, 0),
+        },
+        "{:?}",
+        progression
+      );
+    }
+  }
+}
+
+#[test]
+fn unknown_progression_order() {
+  assert_eq!(Progression::from_c_enum(OPJ_PROG_UNKNOWN), None);
+  assert_eq!(Progression::from_c_enum(5), None);
+  assert_eq!(Progression::default(), Progression::LRCP);
+}
+
+/// Encode a new image in 4 tiles of 16x16 in RPCL with the C parameters, in
+/// a tile-part by resolution.
+fn encode_rpcl_tiles(layout: CodestreamLayout) -> Vec<u8> {
+  let image = multi_component_image(3, 32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  params.tile_size_on = 1;
+  params.cp_tdx = 16;
+  params.cp_tdy = 16;
+  params.prog_order = OPJ_RPCL;
+  params.tp_on = 1;
+  params.tp_flag = b'R' as _;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert_eq!(opj_get_progression(codec, 3), Some(Progression::RPCL));
+    assert!(opj_encoder_set_codestream_layout(codec, layout) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// The tiles of the tile-parts in codestream order.
+fn tile_part_order(bytes: Vec<u8>) -> Vec<u32> {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let ranges = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert!(opj_decode(codec, stream, image) != 0);
+    assert!(opj_end_decompress(codec, stream) != 0);
+    opj_get_codestream_byte_ranges(codec).expect("byte ranges")
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  let mut parts = ranges
+    .tiles
+    .iter()
+    .flat_map(|tile| {
+      tile
+        .tile_parts
+        .iter()
+        .map(move |part| (part.start, tile.tile))
+    })
+    .collect::<Vec<_>>();
+  parts.sort();
+  parts.into_iter().map(|(_, tile)| tile).collect()
+}
+
+#[test]
+fn rpcl_tile_parts() {
+  let image = multi_component_image(3, 32, 32);
+  let expected = samples(image);
+  opj_image_destroy(image);
+
+  // A tile-part by resolution, the tiles one after the other.
+  let bytes = encode_rpcl_tiles(CodestreamLayout::TileFirst);
+  let (progression, packets, samples) = decode_progression(bytes.clone());
+  assert_eq!(progression, Some(Progression::RPCL));
+  assert_eq!(samples, expected);
+  assert_eq!(packets.len(), 9);
+  assert_eq!(tile_part_order(bytes), [0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3]);
+
+  // RPCL starts with the resolutions: the resolution-first layout keeps it
+  // and interleaves the tile-parts of the tiles.
+  let bytes = encode_rpcl_tiles(CodestreamLayout::ResolutionFirst);
+  let (progression, _, samples) = decode_progression(bytes.clone());
+  assert_eq!(progression, Some(Progression::RPCL));
+  assert_eq!(samples, expected);
+  assert_eq!(tile_part_order(bytes), [0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3]);
+}
@@ -0,0 +1,76 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Encode an 8-bit RGB image with the multiple component transform.
+fn encode(irreversible: bool) -> Vec<u8> {
+  let image = multi_component_image(3, 32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  params.tcp_mct = 1;
+  params.irreversible = irreversible as _;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Decode the codestream, returns the samples of the components and
+/// whether the image is flagged as raw.
+fn decode_raw(bytes: Vec<u8>, raw: bool) -> (Vec<Vec<i32>>, bool) {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_decoder_set_raw_components(decoder.codec, raw) } != 0);
+  let image = decoder.decode().expect("decoded image");
+  let decoded = (samples(image), unsafe { (*image).raw_components });
+  opj_image_destroy(image);
+  decoded
+}
+
+#[test]
+fn raw_reversible_components() {
+  let bytes = encode(false);
+  let (expected, raw) = decode_raw(bytes.clone(), false);
+  assert!(!raw);
+  let (comps, raw) = decode_raw(bytes, true);
+  assert!(raw);
+  assert_ne!(comps, expected);
+
+  // Inverse reversible component transform and DC level shift.
+  let mut rgb = vec![Vec::new(); 3];
+  for i in 0..comps[0].len() {
+    let (y, u, v) = (comps[0][i], comps[1][i], comps[2][i]);
+    let g = y - ((u + v) >> 2);
+    for (c, value) in [v + g, g, u + g].iter().enumerate() {
+      rgb[c].push((value + 128).clamp(0, 255));
+    }
+  }
+  assert_eq!(rgb, expected);
+}
+
+#[test]
+fn raw_irreversible_components() {
+  let bytes = encode(true);
+  let (expected, _) = decode_raw(bytes.clone(), false);
+  let (comps, raw) = decode_raw(bytes, true);
+  assert!(raw);
+
+  // Inverse irreversible component transform and DC level shift. The raw
+  // samples are rounded before the transform, so the result may differ by
+  // 2 at most.
+  for i in 0..comps[0].len() {
+    let (y, u, v) = (comps[0][i] as f32, comps[1][i] as f32, comps[2][i] as f32);
+    let rgb = [y + 1.402 * v, y - 0.34413 * u - 0.71414 * v, y + 1.772 * u];
+    for (c, value) in rgb.iter().enumerate() {
+      let value = (value.round() as i32 + 128).clamp(0, 255);
+      assert!((value - expected[c][i]).abs() <= 2);
+    }
+  }
+}
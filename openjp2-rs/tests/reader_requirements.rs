@@ -0,0 +1,99 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode_jp2(
+  image: *mut opj_image_t,
+  rreq: Option<ReaderRequirements>,
+  boxes: Vec<RawBox>,
+) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_encoder_preserve_boxes(codec, boxes) != 0);
+    assert!(opj_encoder_set_reader_requirements(codec, rreq) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Read the header and return the IPR and reader requirements boxes.
+fn read_boxes(bytes: Vec<u8>) -> (Option<Vec<u8>>, Option<ReaderRequirements>) {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_JP2);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let boxes = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    (opj_get_ipr(codec), opj_get_reader_requirements(codec))
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  boxes
+}
+
+#[test]
+fn reader_requirements_round_trip() {
+  let image = grey_image(16, 16);
+  let rreq = ReaderRequirements {
+    fuam: 0x8000,
+    dcm: 0x4000,
+    standard_features: vec![(5, 0x8000), (18, 0x4000)],
+    vendor_features: vec![([7; 16], 0xc000)],
+  };
+  let bytes = encode_jp2(image, Some(rreq.clone()), Vec::new());
+  // The box follows the file type box, with 2 bytes masks.
+  let ftyp_len = u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) as usize;
+  let rreq_box = 12 + ftyp_len;
+  assert_eq!(&bytes[rreq_box + 4..rreq_box + 8], b"rreq");
+  assert_eq!(bytes[rreq_box + 8], 2);
+
+  let (ipr, decoded) = read_boxes(bytes);
+  assert_eq!(ipr, None);
+  assert_eq!(decoded, Some(rreq));
+  opj_image_destroy(image);
+}
+
+#[test]
+fn missing_boxes() {
+  let image = grey_image(16, 16);
+  let bytes = encode_jp2(image, None, Vec::new());
+  assert!(!bytes.windows(4).any(|w| w == b"rreq"));
+  assert_eq!(read_boxes(bytes), (None, None));
+  opj_image_destroy(image);
+}
+
+#[test]
+fn intellectual_property_box() {
+  let image = grey_image(16, 16);
+  let ipr = RawBox {
+    ty: *b"jp2i",
+    data: b"<rights/>".to_vec(),
+    after_codestream: false,
+  };
+  let bytes = encode_jp2(image, None, vec![ipr]);
+  assert_eq!(read_boxes(bytes), (Some(b"<rights/>".to_vec()), None));
+  opj_image_destroy(image);
+}
+
+#[test]
+fn reader_requirements_need_a_jp2_compressor() {
+  unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    let rreq = ReaderRequirements::default();
+    assert!(opj_encoder_set_reader_requirements(codec, Some(rreq)) == 0);
+    opj_destroy_codec(codec);
+  }
+}
@@ -0,0 +1,79 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode() -> Vec<u8> {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Insert a JPWL EPC marker after the SIZ marker: Lepc, Pcrc, DL and Pepc,
+/// padded to an even length.
+fn with_epc(mut bytes: Vec<u8>) -> Vec<u8> {
+  let siz = find_marker(&bytes, 0xFF51).expect("SIZ marker");
+  let lsiz = u16::from_be_bytes([bytes[siz + 2], bytes[siz + 3]]) as usize;
+  let epc = [0xFF, 0x68, 0x00, 0x0A, 0, 0, 0, 0, 0, 0, 0, 0];
+  let end = siz + 2 + lsiz;
+  bytes.splice(end..end, epc);
+  bytes
+}
+
+fn required_features(bytes: Vec<u8>) -> FeatureSet {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let features = unsafe {
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    opj_get_required_features(codec)
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  features
+}
+
+#[test]
+fn plain_codestream_needs_no_feature() {
+  assert_eq!(required_features(encode()), FeatureSet::empty());
+  assert!(FeatureSet::supported().contains(FeatureSet::HTJ2K));
+}
+
+#[test]
+fn jpwl_codestream_needs_jpwl() {
+  assert_eq!(required_features(with_epc(encode())), FeatureSet::JPWL);
+}
+
+#[test]
+fn jpwl_codestream_is_refused() {
+  use openjp2::safe::Jp2Codec;
+  use std::io::ErrorKind;
+
+  let codec = Jp2Codec::decoder(OPJ_CODEC_J2K, with_epc(encode())).expect("decoder");
+  let codec = codec.read_header().expect("valid header");
+  assert_eq!(codec.requires_features(), FeatureSet::JPWL);
+  let err = codec.try_decode().err().expect("unsupported codestream");
+  assert_eq!(err.kind(), ErrorKind::Unsupported);
+  assert!(err.to_string().contains("JPWL"), "{}", err);
+
+  // Without the EPC marker, the same codestream decodes.
+  let codec = Jp2Codec::decoder(OPJ_CODEC_J2K, encode()).expect("decoder");
+  let codec = codec.read_header().expect("valid header");
+  assert!(codec.try_decode().is_ok());
+}
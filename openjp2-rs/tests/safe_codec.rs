@@ -0,0 +1,461 @@
+use openjp2::image::{opj_image, ComponentRef};
+use openjp2::openjpeg::*;
+use openjp2::safe::{
+  decoder_format_from_extension, detect_format_from_file, FormatMismatch, J2KFormat, Jp2Codec,
+  J2K_CODESTREAM_MAGIC, JP2_MAGIC,
+};
+
+mod common;
+use common::*;
+
+/// Encode `image`, returns the bytes and a copy of the image taken before
+/// encoding, as the encoder consumes its samples.
+fn encode(format: CODEC_FORMAT, image: Box<opj_image>) -> (Vec<u8>, Box<opj_image>) {
+  let source = image.clone();
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let mut codec = Jp2Codec::encoder(format, &mut params, image).expect("encoder");
+  assert!(codec.set_extra_options(&["PLT=YES"]));
+  let codec = codec.encode().expect("encoded image");
+  (codec.into_bytes(), source)
+}
+
+#[test]
+fn encode_and_decode() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    let image = unsafe { Box::from_raw(grey_image(16, 8)) };
+    let (bytes, image) = encode(format, image);
+    assert!(find_marker(&bytes, 0xFF58).is_some());
+
+    let codec = Jp2Codec::decoder(format, bytes).expect("decoder");
+    let codec = codec.read_header().expect("valid header");
+    assert_eq!((codec.header().x1, codec.header().y1), (16, 8));
+    assert_eq!(codec.header().numcomps, 1);
+    let decoded = codec.decode().expect("decoded image").into_image();
+    assert_eq!(
+      decoded.comps().unwrap()[0].data(),
+      image.comps().unwrap()[0].data()
+    );
+  }
+}
+
+#[test]
+fn owned_image_outlives_codec() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    let image = unsafe { Box::from_raw(multi_component_image(3, 16, 8)) };
+    let (bytes, image) = encode(format, image);
+    let decoded = Jp2Codec::decoder(format, bytes.clone())
+      .and_then(|codec| codec.read_header())
+      .and_then(|codec| codec.decode())
+      .expect("decoded image")
+      .into_image();
+
+    let owned = {
+      let codec = Jp2Codec::decoder(format, bytes)
+        .and_then(|codec| codec.read_header())
+        .and_then(|codec| codec.decode())
+        .expect("decoded image");
+      codec.into_image_owned()
+    };
+    assert_eq!(
+      (owned.x0, owned.y0, owned.width, owned.height),
+      (0, 0, 16, 8)
+    );
+    assert_eq!(owned.color_space, decoded.color_space);
+    assert!(owned.icc_profile.is_empty());
+    assert_eq!(owned.components.len(), 3);
+    for (plane, comp) in owned.components.iter().zip(image.comps().unwrap()) {
+      assert_eq!((plane.width, plane.height, plane.prec), (16, 8, 8));
+      assert_eq!(Some(plane.data.as_slice()), comp.data());
+    }
+    // The samples are plain `Vec`s, usable without the library.
+    drop(image);
+    drop(decoded);
+    let sum = owned.components[0]
+      .data
+      .iter()
+      .map(|&v| v as i64)
+      .sum::<i64>();
+    assert!(sum > 0);
+  }
+}
+
+#[test]
+fn encode_borrowed_matches_a_copied_image() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    let mut planes: Vec<Vec<i32>> = (0..3)
+      .map(|compno| (0..16 * 8).map(|i| (i * (compno + 1)) % 256).collect())
+      .collect();
+    let mut components: Vec<ComponentRef> = planes
+      .iter_mut()
+      .map(|data| ComponentRef {
+        data,
+        prec: 8,
+        sgnd: false,
+        dx: 1,
+        dy: 1,
+      })
+      .collect();
+    let mut params = opj_cparameters_t::default();
+    params.numresolution = 2;
+    let bytes = Jp2Codec::encode_borrowed(format, &mut params, 16, 8, &mut components)
+      .expect("encoded borrowed samples");
+
+    let mut image = unsafe { Box::from_raw(multi_component_image(3, 16, 8)) };
+    image.color_space = OPJ_CLRSPC_SRGB;
+    let mut params = opj_cparameters_t::default();
+    params.numresolution = 2;
+    let codec = Jp2Codec::encoder(format, &mut params, image).expect("encoder");
+    let copied = codec.encode().expect("encoded image").into_bytes();
+    assert_eq!(bytes, copied);
+  }
+}
+
+#[test]
+fn encode_borrowed_checks_the_sample_count() {
+  let mut data = vec![0i32; 16 * 8 - 1];
+  let component = ComponentRef {
+    data: &mut data,
+    prec: 8,
+    sgnd: false,
+    dx: 1,
+    dy: 1,
+  };
+  let mut params = opj_cparameters_t::default();
+  let err = Jp2Codec::encode_borrowed(OPJ_CODEC_J2K, &mut params, 16, 8, &mut [component])
+    .expect_err("too few samples");
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn decode_area() {
+  let image = unsafe { Box::from_raw(grey_image(16, 16)) };
+  let (bytes, _) = encode(OPJ_CODEC_J2K, image);
+  let mut codec = Jp2Codec::decoder(OPJ_CODEC_J2K, bytes)
+    .and_then(|codec| codec.read_header())
+    .expect("valid header");
+  assert!(codec.set_decode_area(4, 4, 12, 8));
+  let decoded = codec.decode().expect("decoded image");
+  let comp = &decoded.image().comps().unwrap()[0];
+  assert_eq!((comp.w, comp.h), (8, 4));
+}
+
+#[test]
+fn invalid_header() {
+  let codec = Jp2Codec::decoder(OPJ_CODEC_J2K, vec![0; 16]).expect("decoder");
+  assert!(codec.read_header().is_none());
+}
+
+#[test]
+fn choose_the_output_format() {
+  let encode_as = |format: J2KFormat| {
+    let image = unsafe { Box::from_raw(grey_image(16, 8)) };
+    let mut params = opj_cparameters_t::default();
+    params.numresolution = 2;
+    let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image).expect("encoder");
+    assert!(codec.set_extra_options(&["PLT=YES"]));
+    assert!(codec.set_format(format));
+    codec.encode().expect("encoded image").into_bytes()
+  };
+
+  let bytes = encode_as(J2KFormat::J2K);
+  assert!(bytes.starts_with(&J2K_CODESTREAM_MAGIC));
+  assert!(!bytes.windows(4).any(|w| w == b"jP  " || w == b"jp2c"));
+  // Extra options are kept by the new codec.
+  assert!(find_marker(&bytes, 0xFF58).is_some());
+  let codestream = bytes;
+
+  let bytes = encode_as(J2KFormat::JP2);
+  assert!(bytes.starts_with(&JP2_MAGIC));
+  assert!(bytes.windows(codestream.len()).any(|w| w == codestream));
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+  std::env::temp_dir().join(format!("openjp2-safe-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn detect_format_mismatch() {
+  let image = unsafe { Box::from_raw(grey_image(8, 8)) };
+  let (codestream, _) = encode(OPJ_CODEC_J2K, image);
+
+  // A codestream saved with the extension of a JP2 file.
+  let path = temp_path("codestream.jp2");
+  std::fs::write(&path, &codestream).unwrap();
+  let mismatch = FormatMismatch {
+    extension: J2KFormat::JP2,
+    content: J2KFormat::J2K,
+  };
+  assert_eq!(
+    detect_format_from_file(&path).unwrap(),
+    (J2KFormat::J2K, Some(mismatch))
+  );
+  std::fs::remove_file(&path).unwrap();
+
+  let path = temp_path("codestream.J2C");
+  std::fs::write(&path, &codestream).unwrap();
+  assert_eq!(
+    detect_format_from_file(&path).unwrap(),
+    (J2KFormat::J2K, None)
+  );
+  std::fs::remove_file(&path).unwrap();
+
+  let path = temp_path("not-jpeg2000.jp2");
+  std::fs::write(&path, b"P5 8 8 255").unwrap();
+  assert!(detect_format_from_file(&path).is_err());
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn decoder_format_aliases() {
+  for (path, format) in [
+    ("a.j2k", OPJ_CODEC_J2K),
+    ("a.J2C", OPJ_CODEC_J2K),
+    ("a.jpc", OPJ_CODEC_J2K),
+    ("a.jp2", OPJ_CODEC_JP2),
+    ("a.JPT", OPJ_CODEC_JPT),
+  ] {
+    assert_eq!(
+      decoder_format_from_extension(path),
+      Some(format),
+      "{}",
+      path
+    );
+  }
+  assert_eq!(decoder_format_from_extension("a.jpx"), None);
+  assert_eq!(J2KFormat::from_extension("a.jpt"), None);
+}
+
+#[test]
+fn decode_file_under_each_extension() {
+  let image = unsafe { Box::from_raw(grey_image(32, 32)) };
+  let (codestream, image) = encode(OPJ_CODEC_J2K, image);
+  let (jp2, _) = encode(OPJ_CODEC_JP2, image.clone());
+  let jpt = codestream_to_jpt(&codestream);
+  let files = [
+    ("codestream.j2k", &codestream),
+    ("codestream.j2c", &codestream),
+    ("codestream.jpc", &codestream),
+    ("codestream.JPC", &codestream),
+    // The content wins over the extension.
+    ("codestream.jp2", &codestream),
+    ("file.jp2", &jp2),
+    ("file.j2k", &jp2),
+    ("stream.jpt", &jpt),
+  ];
+  for (name, data) in files {
+    let path = temp_path(name);
+    std::fs::write(&path, data).unwrap();
+    let decoded = Jp2Codec::from_file(&path)
+      .expect("decoder")
+      .read_header()
+      .and_then(|codec| codec.decode())
+      .unwrap_or_else(|| panic!("decoded {}", name))
+      .into_image();
+    assert_eq!(
+      decoded.comps().unwrap()[0].data(),
+      image.comps().unwrap()[0].data(),
+      "{}",
+      name
+    );
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  // A JPT-stream is only recognized by its extension.
+  let path = temp_path("stream.j2k");
+  std::fs::write(&path, &jpt).unwrap();
+  let err = Jp2Codec::from_file(&path).err().expect("unknown format");
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+  std::fs::remove_file(&path).unwrap();
+}
+
+/// Smooth shading with some noise, compressing like a photograph.
+fn natural_image(w: u32, h: u32) -> Box<opj_image> {
+  let mut image = unsafe { Box::from_raw(grey_image(w, h)) };
+  let mut seed = 0x2545_f491u32;
+  for (i, v) in image.comps_mut().unwrap()[0]
+    .data_mut()
+    .unwrap()
+    .iter_mut()
+    .enumerate()
+  {
+    let (x, y) = ((i as u32 % w) as f64, (i as u32 / w) as f64);
+    seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+    let noise = (seed >> 28) as f64 - 8.0;
+    let shade = 128.0 + 60.0 * (x / 23.0).sin() * (y / 17.0).cos() + 0.1 * x;
+    *v = (shade + noise).clamp(0.0, 255.0) as i32;
+  }
+  image
+}
+
+#[test]
+fn target_size() {
+  let target = 8 * 1024;
+  for format in [J2KFormat::J2K, J2KFormat::JP2] {
+    let mut params = opj_cparameters_t::default();
+    let image = natural_image(256, 256);
+    let mut codec = Jp2Codec::encoder(format.into(), &mut params, image).expect("encoder");
+    assert!(codec.set_target_size(target));
+    let bytes = codec.encode().expect("encoded image").into_bytes();
+    let size = bytes.len() as u64;
+    assert!(
+      size <= target && size * 10 >= target * 9,
+      "{:?}: {} bytes for a target of {}",
+      format,
+      size,
+      target
+    );
+  }
+  // The headers make the first pass overshoot a small target, the image is
+  // encoded again from its original samples.
+  let mut params = opj_cparameters_t::default();
+  let image = natural_image(256, 256);
+  let mut codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  assert!(codec.set_target_size(300));
+  let bytes = codec.encode().expect("encoded image").into_bytes();
+  assert!(bytes.len() <= 300, "{} bytes", bytes.len());
+  let codec = Jp2Codec::decoder(OPJ_CODEC_J2K, bytes).expect("decoder");
+  let decoded = codec.read_header().unwrap().decode().unwrap().into_image();
+  let mean = decoded.comps().unwrap()[0]
+    .data()
+    .unwrap()
+    .iter()
+    .map(|&v| v as f64)
+    .sum::<f64>()
+    / (256.0 * 256.0);
+  assert!((mean - 128.0).abs() < 16.0, "mean {}", mean);
+
+  // A target larger than the samples is a lossless encoding.
+  let mut params = opj_cparameters_t::default();
+  let image = natural_image(64, 64);
+  let expected = image.comps().unwrap()[0].data().unwrap().to_vec();
+  let mut codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  assert!(codec.set_target_size(1 << 20));
+  let bytes = codec.encode().expect("encoded image").into_bytes();
+  let codec = Jp2Codec::decoder(OPJ_CODEC_J2K, bytes).expect("decoder");
+  let decoded = codec.read_header().unwrap().decode().unwrap().into_image();
+  assert_eq!(decoded.comps().unwrap()[0].data().unwrap(), expected);
+}
+
+/// An sRGB image of black and white noise, which the step sizes of the
+/// smallest near-lossless errors can't code in the tile buffers.
+fn rgb_noise(w: u32, h: u32) -> Box<opj_image> {
+  let mut image = unsafe { Box::from_raw(multi_component_image(3, w, h)) };
+  image.color_space = OPJ_CLRSPC_SRGB;
+  let mut seed = 0x1234_5678u32;
+  for comp in image.comps_mut().unwrap() {
+    for v in comp.data_mut().unwrap() {
+      seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+      *v = if seed >> 31 == 0 { 0 } else { 255 };
+    }
+  }
+  image
+}
+
+/// Encode `image` with `set_near_lossless(max_error)`, returns the largest
+/// difference of the decoded samples and whether the irreversible wavelet
+/// was used.
+fn near_lossless_error(format: CODEC_FORMAT, image: Box<opj_image>, max_error: u32) -> (u32, bool) {
+  let source = samples(&*image);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 6;
+  params.tcp_mct = (image.numcomps == 3) as _;
+  let mut codec = Jp2Codec::encoder(format, &mut params, image).expect("encoder");
+  assert!(codec.set_near_lossless(max_error));
+  let bytes = codec.encode().expect("encoded image").into_bytes();
+  // The wavelet transformation of SPcod.
+  let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+  let irreversible = bytes[cod + 13] == 0;
+  let codec = Jp2Codec::decoder(format, bytes).expect("decoder");
+  let decoded = codec.read_header().unwrap().decode().unwrap().into_image();
+  let worst = samples(&*decoded)
+    .iter()
+    .flatten()
+    .zip(source.iter().flatten())
+    .map(|(a, b)| a.abs_diff(*b))
+    .max()
+    .unwrap();
+  (worst, irreversible)
+}
+
+#[test]
+fn near_lossless() {
+  for max_error in [1, 3, 20, 100] {
+    for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+      for image in [natural_image(64, 64), rgb_noise(64, 64)] {
+        let (worst, _) = near_lossless_error(format, image, max_error);
+        assert!(worst <= max_error, "{} > {}", worst, max_error);
+      }
+    }
+  }
+  assert!(near_lossless_error(OPJ_CODEC_J2K, natural_image(64, 64), 1).1);
+  // The noise is encoded losslessly.
+  assert_eq!(
+    near_lossless_error(OPJ_CODEC_J2K, rgb_noise(64, 64), 1),
+    (0, false)
+  );
+
+  // The last quality layer must be lossless.
+  let mut params = opj_cparameters_t::default();
+  params.tcp_numlayers = 1;
+  params.tcp_rates[0] = 20.0;
+  params.cp_disto_alloc = 1;
+  let image = natural_image(64, 64);
+  let mut codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  assert!(!codec.set_near_lossless(2));
+  let mut params = opj_cparameters_t::default();
+  let image = natural_image(64, 64);
+  let mut codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  assert!(!codec.set_near_lossless(0));
+  assert!(codec.set_target_size(1024));
+  assert!(!codec.set_near_lossless(2));
+}
+
+/// An sRGB image whose 3 components are equal, unless `differ`.
+fn grey_as_rgb(w: u32, h: u32, differ: bool) -> Box<opj_image> {
+  let mut image = unsafe { Box::from_raw(multi_component_image(3, w, h)) };
+  image.color_space = OPJ_CLRSPC_SRGB;
+  let comps = image.comps_mut().unwrap();
+  let grey = comps[0].data().unwrap().to_vec();
+  for comp in &mut comps[1..] {
+    comp.data_mut().unwrap().copy_from_slice(&grey);
+  }
+  if differ {
+    // A single sample in the last row.
+    comps[2].data_mut().unwrap()[(w * h - 1) as usize] ^= 1;
+  }
+  image
+}
+
+#[test]
+fn auto_greyscale() {
+  let encode_rgb = |image: Box<opj_image>| {
+    let mut params = opj_cparameters_t::default();
+    params.numresolution = 2;
+    params.tcp_mct = 1;
+    let mut codec = Jp2Codec::encoder(OPJ_CODEC_JP2, &mut params, image).expect("encoder");
+    assert!(codec.set_auto_greyscale(true));
+    let codec = codec.encode().expect("encoded image");
+    assert!(!codec.image().is_grey_rgb());
+    codec.into_bytes()
+  };
+
+  let image = grey_as_rgb(16, 8, false);
+  assert!(image.is_grey_rgb());
+  let expected = image.comps().unwrap()[0].data().unwrap().to_vec();
+  let bytes = encode_rgb(image);
+  let codec = Jp2Codec::decoder(OPJ_CODEC_JP2, bytes).expect("decoder");
+  let decoded = codec.read_header().unwrap().decode().unwrap().into_image();
+  assert_eq!(decoded.numcomps, 1);
+  assert_eq!(decoded.color_space, OPJ_CLRSPC_GRAY);
+  assert_eq!(decoded.comps().unwrap()[0].data().unwrap(), expected);
+
+  // A single differing sample keeps the 3 components.
+  let image = grey_as_rgb(16, 8, true);
+  assert!(!image.is_grey_rgb());
+  let bytes = encode_rgb(image);
+  let codec = Jp2Codec::decoder(OPJ_CODEC_JP2, bytes).expect("decoder");
+  let decoded = codec.read_header().unwrap().decode().unwrap().into_image();
+  assert_eq!(decoded.numcomps, 3);
+  assert_eq!(decoded.color_space, OPJ_CLRSPC_SRGB);
+}
@@ -0,0 +1,95 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// An 8-bit RGB image with smooth shading and noise, and the components
+/// of `flat` set to a single value.
+fn encode(flat: &[(usize, i32)]) -> Vec<u8> {
+  let (w, h) = (48, 32);
+  let image = multi_component_image(3, w, h);
+  let mut seed = 0x2545_f491u32;
+  for (compno, comp) in unsafe { &mut *image }
+    .comps_mut()
+    .unwrap()
+    .iter_mut()
+    .enumerate()
+  {
+    for (i, v) in comp.data_mut().unwrap().iter_mut().enumerate() {
+      let (x, y) = ((i as u32 % w) as f64, (i as u32 / w) as f64);
+      seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+      let noise = (seed >> 28) as f64 - 8.0;
+      let shade = 128.0 + 60.0 * (x / 11.0 + compno as f64).sin() * (y / 7.0).cos();
+      *v = (shade + noise).clamp(0.0, 255.0) as i32;
+    }
+    if let Some((_, value)) = flat.iter().find(|(flat, _)| *flat == compno) {
+      comp.data_mut().unwrap().fill(*value);
+    }
+  }
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    // Only for decompressors.
+    assert!(opj_decoder_set_saturation_warning(codec, true) == 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Decode the codestream, returns the saturation warnings.
+fn saturation_warnings(bytes: Vec<u8>, enable: bool) -> Vec<String> {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_decoder_set_saturation_warning(decoder.codec, enable) } != 0);
+  let image = decoder.decode().expect("decoded image");
+  opj_image_destroy(image);
+  let mut msgs = decoder.msgs().to_vec();
+  msgs.retain(|msg| msg.starts_with("All the samples"));
+  msgs
+}
+
+#[test]
+fn natural_image_is_not_saturated() {
+  let bytes = encode(&[]);
+  assert!(saturation_warnings(bytes, true).is_empty());
+}
+
+#[test]
+fn saturated_components() {
+  let bytes = encode(&[(0, 0), (2, 255)]);
+  let msgs = saturation_warnings(bytes.clone(), true);
+  assert_eq!(msgs.len(), 2, "{:?}", msgs);
+  assert!(msgs[0].starts_with("All the samples of component 0 are 0,"));
+  assert!(msgs[1].starts_with("All the samples of component 2 are 255,"));
+  // Opt-in.
+  assert!(saturation_warnings(bytes, false).is_empty());
+
+  // A flat component within the range is fine.
+  let bytes = encode(&[(1, 128)]);
+  assert!(saturation_warnings(bytes, true).is_empty());
+}
+
+#[test]
+fn saturated_value() {
+  let image = multi_component_image(1, 4, 1);
+  let comp = &mut unsafe { &mut *image }.comps_mut().unwrap()[0];
+  comp.data_mut().unwrap().copy_from_slice(&[0, 255, 0, 255]);
+  // Bilevel content uses both ends of the range.
+  assert_eq!(comp.saturated_value(), None);
+  comp.data_mut().unwrap().fill(255);
+  assert_eq!(comp.saturated_value(), Some(255));
+  comp.prec = 12;
+  assert_eq!(comp.saturated_value(), None);
+  comp.sgnd = 1;
+  comp.data_mut().unwrap().fill(-2048);
+  assert_eq!(comp.saturated_value(), Some(-2048));
+  comp.data_mut().unwrap().fill(2047);
+  assert_eq!(comp.saturated_value(), Some(2047));
+  opj_image_destroy(image);
+}
@@ -0,0 +1,169 @@
+use openjp2::convert::raw::{read_raw, RawByteOrder, RawImageInfo};
+use openjp2::convert::{detect_format_from_extension, ImageFormat};
+use openjp2::openjpeg::*;
+
+fn test_image(numcomps: u32, prec: u32, sgnd: bool, samples: &[&[i32]]) -> Box<opj_image> {
+  let w = samples[0].len() as u32;
+  let mut image = opj_image::new();
+  image.x1 = w;
+  image.y1 = 1;
+  assert!(image.alloc_comps(numcomps, true));
+  for (comp, samples) in image.comps_mut().unwrap().iter_mut().zip(samples) {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = w;
+    comp.h = 1;
+    comp.prec = prec;
+    comp.sgnd = sgnd as u32;
+    assert!(comp.alloc_data());
+    comp.data_mut().unwrap().copy_from_slice(samples);
+  }
+  image
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+  std::env::temp_dir().join(format!("openjp2-save-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn format_from_extension() {
+  assert_eq!(
+    detect_format_from_extension("a.PGX"),
+    Some(ImageFormat::Pgx)
+  );
+  assert_eq!(
+    detect_format_from_extension("a.b.ppm"),
+    Some(ImageFormat::Ppm)
+  );
+  assert_eq!(
+    detect_format_from_extension("a.yuv"),
+    Some(ImageFormat::Raw)
+  );
+  assert_eq!(
+    detect_format_from_extension("a.rawl"),
+    Some(ImageFormat::RawL)
+  );
+  #[cfg(feature = "tiff")]
+  assert_eq!(
+    detect_format_from_extension("a.TIFF"),
+    Some(ImageFormat::Tif)
+  );
+  #[cfg(not(feature = "tiff"))]
+  assert_eq!(detect_format_from_extension("a.TIFF"), None);
+  assert_eq!(detect_format_from_extension("a.png"), None);
+  assert_eq!(detect_format_from_extension("pgm"), None);
+}
+
+#[test]
+fn save_single_component_pgx() {
+  let image = test_image(1, 8, false, &[&[1, 2]]);
+  let path = temp_path("grey.pgx");
+  image.save(&path).expect("save pgx");
+  assert_eq!(std::fs::read(&path).unwrap(), b"PG ML + 8 2 1\n\x01\x02");
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn raw_samples() {
+  let image = test_image(2, 12, false, &[&[0, 4095], &[-1, 5000]]);
+  let mut buf = Vec::new();
+  image
+    .write_raw(RawByteOrder::LittleEndian, &mut buf)
+    .expect("write raw");
+  // Samples are clamped to the range of their depth.
+  assert_eq!(buf, [0x00, 0x00, 0xff, 0x0f, 0x00, 0x00, 0xff, 0x0f]);
+
+  let image = test_image(1, 8, true, &[&[-128, -1, 127]]);
+  let mut buf = Vec::new();
+  image
+    .write_raw(RawByteOrder::BigEndian, &mut buf)
+    .expect("write raw");
+  assert_eq!(buf, [0x80, 0xff, 0x7f]);
+}
+
+#[test]
+fn raw_24bit_round_trip() {
+  let info = RawImageInfo {
+    width: 3,
+    height: 1,
+    numcomps: 1,
+    prec: 24,
+    sgnd: true,
+  };
+  let samples: &[i32] = &[-8388608, -2, 0x123456];
+  let image = test_image(1, 24, true, &[samples]);
+  for (order, expected) in [
+    (
+      RawByteOrder::BigEndian,
+      [0x80, 0x00, 0x00, 0xff, 0xff, 0xfe, 0x12, 0x34, 0x56],
+    ),
+    (
+      RawByteOrder::LittleEndian,
+      [0x00, 0x00, 0x80, 0xfe, 0xff, 0xff, 0x56, 0x34, 0x12],
+    ),
+  ] {
+    let mut buf = Vec::new();
+    image.write_raw(order, &mut buf).expect("write raw");
+    assert_eq!(buf, expected);
+    let decoded = read_raw(&mut buf.as_slice(), &info, order).expect("read raw");
+    let comp = &decoded.comps().unwrap()[0];
+    assert_eq!((comp.w, comp.h, comp.prec, comp.sgnd), (3, 1, 24, 1));
+    assert_eq!(comp.data().unwrap(), samples);
+  }
+  // The file is too short for the layout.
+  assert!(read_raw(&mut [0u8; 8].as_slice(), &info, RawByteOrder::BigEndian).is_err());
+}
+
+#[test]
+fn save_by_extension() {
+  let image = test_image(3, 8, false, &[&[1, 2], &[3, 4], &[5, 6]]);
+
+  let path = temp_path("rgb.ppm");
+  image.save(&path).expect("save ppm");
+  assert_eq!(
+    std::fs::read(&path).unwrap(),
+    b"P6\n2 1\n255\n\x01\x03\x05\x02\x04\x06"
+  );
+  std::fs::remove_file(&path).unwrap();
+
+  let path = temp_path("grey.PGM");
+  image.save(&path).expect("save pgm");
+  assert_eq!(std::fs::read(&path).unwrap(), b"P5\n2 1\n255\n\x01\x02");
+  std::fs::remove_file(&path).unwrap();
+
+  let path = temp_path("planes.raw");
+  image.save(&path).expect("save raw");
+  assert_eq!(std::fs::read(&path).unwrap(), [1, 2, 3, 4, 5, 6]);
+  std::fs::remove_file(&path).unwrap();
+
+  #[cfg(feature = "tiff")]
+  {
+    let path = temp_path("rgb.tif");
+    image.save(&path).expect("save tif");
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(&bytes[..4], b"II\x2a\x00");
+    // Two strips of one row.
+    assert_eq!(&bytes[8..14], &[1, 3, 5, 2, 4, 6]);
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  // A file per component, like imagetopgx.
+  let path = temp_path("comps.pgx");
+  image.save(&path).expect("save pgx");
+  assert!(!path.exists());
+  for compno in 0..3 {
+    let path = temp_path(&format!("comps_{}.pgx", compno));
+    assert_eq!(
+      std::fs::read(&path).unwrap(),
+      b"PG ML + 8 2 1\n"
+        .iter()
+        .chain(&[1 + 2 * compno as u8, 2 + 2 * compno as u8])
+        .copied()
+        .collect::<Vec<_>>()
+    );
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  let err = image.save(temp_path("image.png")).unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
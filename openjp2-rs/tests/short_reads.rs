@@ -0,0 +1,137 @@
+use std::ffi::*;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// A source returning at most `chunk` bytes by read, like a network socket.
+struct ChunkedReader {
+  cursor: Cursor<Vec<u8>>,
+  chunk: usize,
+  reads: usize,
+}
+
+extern "C" fn chunked_free_fn(p_data: *mut c_void) {
+  drop(unsafe { Box::from_raw(p_data as *mut ChunkedReader) })
+}
+
+extern "C" fn chunked_read_fn(
+  p_buffer: *mut c_void,
+  nb_bytes: usize,
+  p_data: *mut c_void,
+) -> usize {
+  let reader = unsafe { &mut *(p_data as *mut ChunkedReader) };
+  let nb_bytes = nb_bytes.min(reader.chunk);
+  let out_buf = unsafe { std::slice::from_raw_parts_mut(p_buffer as *mut u8, nb_bytes) };
+  reader.reads += 1;
+  match reader.cursor.read(out_buf) {
+    Ok(0) | Err(_) => usize::MAX,
+    Ok(nb) => nb,
+  }
+}
+
+extern "C" fn chunked_skip_fn(nb_bytes: i64, p_data: *mut c_void) -> i64 {
+  let reader = unsafe { &mut *(p_data as *mut ChunkedReader) };
+  match reader.cursor.seek(SeekFrom::Current(nb_bytes)) {
+    Ok(_) => nb_bytes,
+    Err(_) => -1,
+  }
+}
+
+extern "C" fn chunked_seek_fn(nb_bytes: i64, p_data: *mut c_void) -> i32 {
+  let reader = unsafe { &mut *(p_data as *mut ChunkedReader) };
+  reader.cursor.seek(SeekFrom::Start(nb_bytes as u64)).is_ok() as i32
+}
+
+/// Create an input stream reading `bytes` by `chunk` bytes at most.
+fn chunked_stream(
+  bytes: Vec<u8>,
+  chunk: usize,
+  seek: bool,
+) -> (*mut opj_stream_t, *const ChunkedReader) {
+  let len = bytes.len();
+  let reader = Box::into_raw(Box::new(ChunkedReader {
+    cursor: Cursor::new(bytes),
+    chunk,
+    reads: 0,
+  }));
+  unsafe {
+    let stream = opj_stream_default_create(1);
+    opj_stream_set_read_function(stream, Some(chunked_read_fn));
+    opj_stream_set_skip_function(stream, Some(chunked_skip_fn));
+    if seek {
+      opj_stream_set_seek_function(stream, Some(chunked_seek_fn));
+    }
+    opj_stream_set_user_data_length(stream, len as u64);
+    opj_stream_set_user_data(stream, reader as *mut c_void, Some(chunked_free_fn));
+    (stream, reader)
+  }
+}
+
+fn encode(format: OPJ_CODEC_FORMAT) -> Vec<u8> {
+  let image = multi_component_image(3, 48, 40);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  params.tile_size_on = 1;
+  params.cp_tdx = 32;
+  params.cp_tdy = 32;
+  let codec = unsafe { opj_create_compress(format) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Decode the whole image from `stream`, returns the samples if it succeeds.
+fn decode_samples(decoder: &mut TestDecoder) -> Option<Vec<Vec<i32>>> {
+  let image = decoder.decode()?;
+  let decoded = samples(image);
+  opj_image_destroy(image);
+  Some(decoded)
+}
+
+#[test]
+fn byte_by_byte_decode() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    let bytes = encode(format);
+    let expected =
+      decode_samples(&mut TestDecoder::new(format, bytes.clone())).expect("decoded image");
+
+    for (chunk, seek) in [(1, true), (1, false), (7, true)] {
+      let (stream, reader) = chunked_stream(bytes.clone(), chunk, seek);
+      let mut decoder = TestDecoder::from_stream(format, stream);
+      let samples = decode_samples(&mut decoder);
+      assert_eq!(samples.as_ref(), Some(&expected), "{} bytes by read", chunk);
+      // The stream buffer doesn't hide the short reads.
+      let reads = unsafe { (*reader).reads };
+      assert!(reads * chunk >= bytes.len() / 2, "{} reads", reads);
+    }
+  }
+}
+
+#[test]
+fn byte_by_byte_truncated_decode() {
+  let bytes = encode(OPJ_CODEC_J2K);
+  // Truncated in the data of the last tile.
+  let sot = bytes
+    .windows(2)
+    .rposition(|w| w == [0xFF, 0x90])
+    .expect("SOT marker");
+  let truncated = bytes[..sot + 20].to_vec();
+
+  let expected = decode_samples(&mut TestDecoder::new(OPJ_CODEC_J2K, truncated.clone()));
+  // The bytes read before the end of the stream are kept.
+  let (stream, _) = chunked_stream(truncated, 1, true);
+  assert_eq!(
+    decode_samples(&mut TestDecoder::from_stream(OPJ_CODEC_J2K, stream)),
+    expected
+  );
+}
@@ -0,0 +1,84 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Encode losslessly with a single resolution, so that each component is a
+/// single packet when there is a single layer.
+fn encode(image: *mut opj_image_t, prog_order: OPJ_PROG_ORDER, layers: i32) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 1;
+  params.prog_order = prog_order;
+  params.tcp_mct = 0;
+  params.tcp_numlayers = layers;
+  for layno in 0..layers as usize {
+    params.tcp_rates[layno] = (layers as usize - 1 - layno) as f32 * 4.0;
+  }
+  params.cp_disto_alloc = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Decode the samples of `bytes`, restricted to `area` if given.
+fn decode_area(bytes: Vec<u8>, area: Option<(i32, i32, i32, i32)>) -> Vec<Vec<i32>> {
+  let image = TestDecoder::new(OPJ_CODEC_J2K, bytes)
+    .decode_area(area)
+    .expect("decoded image");
+  let decoded = samples(image);
+  opj_image_destroy(image);
+  decoded
+}
+
+#[test]
+fn single_packet_tiles_decode_losslessly() {
+  let new_images: [fn() -> *mut opj_image_t; 3] = [
+    || grey_image(32, 32),
+    || multi_component_image(3, 17, 33),
+    || multi_component_image(4, 40, 24),
+  ];
+  for new_image in &new_images {
+    // LRCP, RLCP and CPRL read one packet per component, RPCL and PCRL
+    // go through the packet iterator.
+    for prog_order in [OPJ_LRCP, OPJ_RLCP, OPJ_RPCL, OPJ_PCRL, OPJ_CPRL] {
+      let image = new_image();
+      let expected = samples(image);
+      let bytes = encode(image, prog_order, 1);
+      opj_image_destroy(image);
+      assert_eq!(decode_area(bytes, None), expected, "order {}", prog_order);
+    }
+  }
+}
+
+/// Decoding an area goes through the packet iterator, the samples must be
+/// the same as those of the whole image.
+#[test]
+fn single_packet_tiles_match_the_general_path() {
+  const W: i32 = 40;
+  for layers in [1, 2] {
+    let image = multi_component_image(3, W as u32, W as u32);
+    let bytes = encode(image, OPJ_LRCP, layers);
+    opj_image_destroy(image);
+    let whole = decode_area(bytes.clone(), None);
+    let (x0, y0, x1, y1) = (3, 5, 29, 31);
+    let area = decode_area(bytes, Some((x0, y0, x1, y1)));
+    for (compno, comp) in area.iter().enumerate() {
+      let expected: Vec<i32> = (y0..y1)
+        .flat_map(|y| (x0..x1).map(move |x| (y * W + x) as usize))
+        .map(|i| whole[compno][i])
+        .collect();
+      assert_eq!(
+        comp, &expected,
+        "component {} with {} layers",
+        compno, layers
+      );
+    }
+  }
+}
@@ -0,0 +1,114 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const SOP: i32 = 0x02;
+const EPH: i32 = 0x04;
+
+/// Encode losslessly with 2 layers, 3 resolutions and the SOP/EPH `csty`.
+fn encode(image: *mut opj_image_t, csty: i32) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 3;
+  params.csty = csty;
+  params.tcp_numlayers = 2;
+  params.tcp_rates[0] = 8.0;
+  params.tcp_rates[1] = 0.0;
+  params.cp_disto_alloc = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+/// Decode the samples of `bytes` and the warnings.
+fn decode_with_msgs(bytes: Vec<u8>) -> (Vec<i32>, Vec<String>) {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  let image = decoder
+    .decode()
+    .unwrap_or_else(|| panic!("decoding failed: {:?}", decoder.msgs()));
+  let decoded = samples(image).remove(0);
+  opj_image_destroy(image);
+  (decoded, decoder.msgs().to_vec())
+}
+
+fn count_marker(bytes: &[u8], marker: u8) -> usize {
+  bytes.windows(2).filter(|w| w == &[0xFF, marker]).count()
+}
+
+/// Override the SOP/EPH bits of the Scod field of the COD marker.
+fn set_cod_csty(bytes: &mut [u8], csty: i32) {
+  let cod = find_marker(bytes, 0xFF52).expect("COD marker");
+  bytes[cod + 4] = (bytes[cod + 4] & !((SOP | EPH) as u8)) | csty as u8;
+}
+
+/// Encode a new 32x32 image with `csty`, and its samples.
+fn encode_grey(csty: i32) -> (Vec<u8>, Vec<i32>) {
+  let image = grey_image(32, 32);
+  let expected = samples(image).swap_remove(0);
+  let bytes = encode(image, csty);
+  opj_image_destroy(image);
+  (bytes, expected)
+}
+
+#[test]
+fn every_sop_eph_combination() {
+  for csty in [0, SOP, EPH, SOP | EPH] {
+    let (bytes, expected) = encode_grey(csty);
+    assert_eq!(
+      count_marker(&bytes, 0x91) > 0,
+      csty & SOP != 0,
+      "csty {}",
+      csty
+    );
+    assert_eq!(
+      count_marker(&bytes, 0x92) > 0,
+      csty & EPH != 0,
+      "csty {}",
+      csty
+    );
+    let (samples, warnings) = decode_with_msgs(bytes);
+    assert_eq!(samples, expected, "csty {}", csty);
+    assert!(warnings.is_empty(), "csty {}: {:?}", csty, warnings);
+  }
+}
+
+/// Markers written without being enabled in the COD marker are skipped.
+#[test]
+fn markers_not_enabled_by_cod() {
+  for csty in [SOP, EPH, SOP | EPH] {
+    let (mut bytes, expected) = encode_grey(csty);
+    set_cod_csty(&mut bytes, 0);
+    let (samples, warnings) = decode_with_msgs(bytes);
+    assert_eq!(samples, expected, "csty {}", csty);
+    assert!(warnings.is_empty(), "csty {}: {:?}", csty, warnings);
+  }
+}
+
+/// Missing markers enabled by the COD marker are reported with the packet
+/// they are missing from, and the packets are still read.
+#[test]
+fn missing_markers_are_reported() {
+  for (csty, name) in [(SOP, "SOP"), (EPH, "EPH")] {
+    let (mut bytes, expected) = encode_grey(0);
+    set_cod_csty(&mut bytes, csty);
+    let (samples, warnings) = decode_with_msgs(bytes);
+    assert_eq!(samples, expected, "{}", name);
+    let first = format!(
+      "Expected {} marker for the packet of component 0, resolution 0, precinct 0, layer 0",
+      name
+    );
+    assert!(
+      warnings.iter().any(|w| w.starts_with(&first)),
+      "{}: {:?}",
+      name,
+      warnings
+    );
+  }
+}
@@ -0,0 +1,67 @@
+use std::io::Cursor;
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 64;
+const TILE: u32 = 16;
+
+/// Encode a tiled image, returns the codestream and the bytes written.
+fn encode_tiled() -> (Vec<u8>, u64) {
+  let image = grey_image(W, W);
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = TILE as i32;
+  params.cp_tdy = TILE as i32;
+  params.numresolution = 2;
+  let cursor = Box::into_raw(Box::new(Cursor::new(Vec::new())));
+  let stream = cursor_stream(cursor, 0, false);
+  let written = unsafe {
+    let codec = opj_create_compress(OPJ_CODEC_J2K);
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    assert!(opj_start_compress(codec, image, stream) != 0);
+    assert!(opj_encode(codec, stream) != 0);
+    assert!(opj_end_compress(codec, stream) != 0);
+    opj_destroy_codec(codec);
+    // Nothing is read from an output stream.
+    assert_eq!(opj_stream_get_bytes_read(stream), 0);
+    opj_stream_get_bytes_written(stream)
+  };
+  let bytes = unsafe {
+    opj_stream_destroy(stream);
+    Box::from_raw(cursor).into_inner()
+  };
+  opj_image_destroy(image);
+  (bytes, written)
+}
+
+/// Decode `area` of the image, or the whole image, returns the bytes read.
+fn bytes_read(bytes: Vec<u8>, area: Option<(i32, i32, i32, i32)>) -> u64 {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  let image = decoder.decode_area(area).expect("decoded image");
+  opj_image_destroy(image);
+  unsafe { opj_stream_get_bytes_read(decoder.stream) }
+}
+
+#[test]
+fn bytes_written_by_the_encoder() {
+  let (bytes, written) = encode_tiled();
+  // A J2K codestream without TLM markers is written sequentially.
+  assert_eq!(written, bytes.len() as u64);
+}
+
+#[test]
+fn region_decoding_reads_less() {
+  let (bytes, _) = encode_tiled();
+  let len = bytes.len() as u64;
+  let full = bytes_read(bytes.clone(), None);
+  assert!(full > len * 9 / 10 && full <= len, "{} of {}", full, len);
+
+  // A single tile of the 4x4 tiles: the others are skipped.
+  let tile = TILE as i32;
+  let region = bytes_read(bytes, Some((0, 0, tile, tile)));
+  assert!(region < full / 2, "{} of {}", region, full);
+}
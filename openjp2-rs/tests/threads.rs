@@ -0,0 +1,66 @@
+#![cfg(feature = "threads")]
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+const W: u32 = 1024;
+
+fn encode(image: *mut opj_image_t) -> Vec<u8> {
+  let mut params = opj_cparameters_t::default();
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  bytes
+}
+
+fn decode_threaded(bytes: Vec<u8>, num_threads: i32) -> *mut opj_image_t {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_codec_set_threads(decoder.codec, num_threads) } != 0);
+  decoder.decode().expect("decoded image")
+}
+
+#[test]
+fn threaded_decoding_is_bit_exact() {
+  assert_eq!(opj_has_thread_support(), 1);
+  // A single tile with 256 code-blocks in the highest resolution.
+  let image = grey_image(W, W);
+  let expected = samples(image).remove(0);
+  let bytes = encode(image);
+  opj_image_destroy(image);
+
+  let serial = decode_threaded(bytes.clone(), 1);
+  let threaded = decode_threaded(bytes, opj_get_num_cpus());
+  unsafe {
+    assert_eq!((*serial).comps().unwrap()[0].data().unwrap(), &expected[..]);
+    assert_eq!(
+      (*threaded).comps().unwrap()[0].data().unwrap(),
+      &expected[..]
+    );
+  }
+  opj_image_destroy(serial);
+  opj_image_destroy(threaded);
+}
+
+#[test]
+fn more_threads_than_code_blocks() {
+  let image = grey_image(64, 64);
+  let expected = samples(image).remove(0);
+  let bytes = encode(image);
+  opj_image_destroy(image);
+  let decoded = decode_threaded(bytes, 64);
+  unsafe {
+    assert_eq!(
+      (*decoded).comps().unwrap()[0].data().unwrap(),
+      &expected[..]
+    );
+  }
+  opj_image_destroy(decoded);
+}
@@ -0,0 +1,264 @@
+#![cfg(feature = "tiff")]
+
+use std::collections::BTreeMap;
+
+use openjp2::convert::tiff::{TiffCompression, TiffOptions};
+use openjp2::openjpeg::*;
+
+fn test_image(w: u32, h: u32, prec: u32, sgnd: bool, samples: &[&[i32]]) -> Box<opj_image> {
+  let mut image = opj_image::new();
+  image.x1 = w;
+  image.y1 = h;
+  image.color_space = if samples.len() >= 3 {
+    OPJ_CLRSPC_SRGB
+  } else {
+    OPJ_CLRSPC_GRAY
+  };
+  assert!(image.alloc_comps(samples.len() as u32, true));
+  for (comp, samples) in image.comps_mut().unwrap().iter_mut().zip(samples) {
+    comp.dx = 1;
+    comp.dy = 1;
+    comp.w = w;
+    comp.h = h;
+    comp.prec = prec;
+    comp.sgnd = sgnd as u32;
+    assert!(comp.alloc_data());
+    comp.data_mut().unwrap().copy_from_slice(samples);
+  }
+  image
+}
+
+fn write(image: &opj_image, options: &TiffOptions) -> Vec<u8> {
+  let mut buf = Vec::new();
+  image.write_tiff(options, &mut buf).expect("write tiff");
+  buf
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+  u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes([
+    bytes[offset],
+    bytes[offset + 1],
+    bytes[offset + 2],
+    bytes[offset + 3],
+  ])
+}
+
+/// The tags of the first directory, with their type and values.
+fn read_tags(bytes: &[u8]) -> BTreeMap<u16, (u16, Vec<u32>)> {
+  assert_eq!(&bytes[..4], b"II\x2a\x00");
+  let dir = u32_at(bytes, 4) as usize;
+  assert_eq!(dir % 2, 0);
+  let count = u16_at(bytes, dir) as usize;
+  let mut tags = BTreeMap::new();
+  let mut last_tag = 0;
+  for i in 0..count {
+    let entry = dir + 2 + i * 12;
+    let (tag, kind, n) = (
+      u16_at(bytes, entry),
+      u16_at(bytes, entry + 2),
+      u32_at(bytes, entry + 4) as usize,
+    );
+    // Sorted in ascending order.
+    assert!(tag > last_tag);
+    last_tag = tag;
+    let size = match kind {
+      3 => 2,
+      4 => 4,
+      _ => panic!("unexpected type {}", kind),
+    };
+    let values = if n * size <= 4 {
+      entry + 8
+    } else {
+      u32_at(bytes, entry + 8) as usize
+    };
+    let values = (0..n)
+      .map(|j| match size {
+        2 => u16_at(bytes, values + j * 2) as u32,
+        _ => u32_at(bytes, values + j * 4),
+      })
+      .collect();
+    tags.insert(tag, (kind, values));
+  }
+  // No next directory.
+  assert_eq!(u32_at(bytes, dir + 2 + count * 12), 0);
+  tags
+}
+
+fn tag(tags: &BTreeMap<u16, (u16, Vec<u32>)>, tag: u16) -> &[u32] {
+  &tags.get(&tag).expect("tag").1
+}
+
+/// The concatenated strips.
+fn strips(bytes: &[u8], tags: &BTreeMap<u16, (u16, Vec<u32>)>) -> Vec<u8> {
+  tag(tags, 273)
+    .iter()
+    .zip(tag(tags, 279))
+    .flat_map(|(&offset, &count)| bytes[offset as usize..][..count as usize].to_vec())
+    .collect()
+}
+
+#[test]
+fn grey_8bit() {
+  let image = test_image(3, 2, 8, false, &[&[1, 2, 3, 4, 5, 300]]);
+  let bytes = write(&image, &TiffOptions::default());
+  let tags = read_tags(&bytes);
+  assert_eq!(
+    tags.keys().copied().collect::<Vec<_>>(),
+    [256, 257, 258, 259, 262, 273, 274, 277, 278, 279, 284]
+  );
+  assert_eq!(tags[&256], (3, vec![3]));
+  assert_eq!(tags[&257], (3, vec![2]));
+  assert_eq!(tag(&tags, 258), [8]);
+  // No compression, min-is-black, top-left, contiguous samples.
+  assert_eq!(tag(&tags, 259), [1]);
+  assert_eq!(tag(&tags, 262), [1]);
+  assert_eq!(tag(&tags, 274), [1]);
+  assert_eq!(tag(&tags, 284), [1]);
+  assert_eq!(tag(&tags, 277), [1]);
+  // A strip by row, right after the header.
+  assert_eq!(tag(&tags, 278), [1]);
+  assert_eq!(tags[&273], (4, vec![8, 11]));
+  assert_eq!(tags[&279], (3, vec![3, 3]));
+  // Samples are clipped to their depth.
+  assert_eq!(&bytes[8..14], &[1, 2, 3, 4, 5, 255]);
+  // The directory follows on a word boundary.
+  assert_eq!(u32_at(&bytes, 4), 14);
+}
+
+#[test]
+fn rgba_16bit() {
+  let r: &[i32] = &[0, 65535];
+  let g: &[i32] = &[0x1234, 2];
+  let b: &[i32] = &[3, 4];
+  let a: &[i32] = &[65535, 0];
+  let image = test_image(2, 1, 16, false, &[r, g, b, a, r]);
+  let bytes = write(&image, &TiffOptions::default());
+  let tags = read_tags(&bytes);
+  // RGB, the fifth component is dropped.
+  assert_eq!(tag(&tags, 262), [2]);
+  assert_eq!(tag(&tags, 277), [4]);
+  assert_eq!(tag(&tags, 258), [16, 16, 16, 16]);
+  // Interleaved little-endian samples.
+  assert_eq!(
+    strips(&bytes, &tags),
+    [
+      0x00, 0x00, 0x34, 0x12, 0x03, 0x00, 0xff, 0xff, 0xff, 0xff, 0x02, 0x00, 0x04, 0x00, 0x00,
+      0x00
+    ]
+  );
+}
+
+#[test]
+fn packed_samples() {
+  // 1-bit rows of 10 samples are padded to 2 bytes.
+  let row: &[i32] = &[1, 0, 1, 1, 0, 0, 0, 1, 1, 1];
+  let image = test_image(10, 2, 1, false, &[&[row, row].concat()]);
+  let bytes = write(&image, &TiffOptions::default());
+  let tags = read_tags(&bytes);
+  assert_eq!(tag(&tags, 258), [1]);
+  assert_eq!(
+    strips(&bytes, &tags),
+    [0b1011_0001, 0b1100_0000, 0b1011_0001, 0b1100_0000]
+  );
+
+  // 12-bit RGB samples.
+  let image = test_image(1, 1, 12, false, &[&[0xABC], &[0x123], &[0xFFF]]);
+  let bytes = write(&image, &TiffOptions::default());
+  let tags = read_tags(&bytes);
+  assert_eq!(tag(&tags, 258), [12, 12, 12]);
+  assert_eq!(strips(&bytes, &tags), [0xAB, 0xC1, 0x23, 0xFF, 0xF0]);
+}
+
+#[test]
+fn signed_samples() {
+  let image = test_image(3, 1, 8, true, &[&[-128, -1, 200]]);
+  let bytes = write(&image, &TiffOptions::default());
+  let tags = read_tags(&bytes);
+  // Shifted to the unsigned range, like `opj_decompress`.
+  assert!(!tags.contains_key(&339));
+  assert_eq!(strips(&bytes, &tags), [0, 127, 255]);
+
+  let options = TiffOptions {
+    keep_signed: true,
+    ..Default::default()
+  };
+  let bytes = write(&image, &options);
+  let tags = read_tags(&bytes);
+  assert_eq!(tag(&tags, 339), [2]);
+  assert_eq!(strips(&bytes, &tags), [0x80, 0xff, 0x7f]);
+
+  // Unsigned components are not affected.
+  let image = test_image(1, 1, 8, false, &[&[7]]);
+  let tags = read_tags(&write(&image, &options));
+  assert!(!tags.contains_key(&339));
+}
+
+#[test]
+fn deflate_strips() {
+  let (w, h) = (64, 10);
+  let samples = (0..w * h).map(|i| (i % w) as i32).collect::<Vec<_>>();
+  let image = test_image(w, h, 8, false, &[&samples]);
+  let options = TiffOptions {
+    compression: TiffCompression::Deflate,
+    rows_per_strip: 4,
+    ..Default::default()
+  };
+  let bytes = write(&image, &options);
+  let tags = read_tags(&bytes);
+  assert_eq!(tag(&tags, 259), [8]);
+  assert_eq!(tag(&tags, 278), [4]);
+  let offsets = tag(&tags, 273);
+  let counts = tag(&tags, 279);
+  assert_eq!(offsets.len(), 3);
+  assert_eq!(offsets[0], 8);
+  for i in 0..3 {
+    let strip = &bytes[offsets[i] as usize..][..counts[i] as usize];
+    // zlib streams of the rows, smaller than them.
+    assert_eq!(&strip[..2], &[0x78, 0x9C]);
+    assert!(strip.len() < 4 * w as usize);
+    let rows = miniz_oxide::inflate::decompress_to_vec_zlib(strip).expect("zlib stream");
+    let first = i * 4 * w as usize;
+    let expected = samples[first..(first + 4 * w as usize).min(samples.len())]
+      .iter()
+      .map(|&v| v as u8)
+      .collect::<Vec<_>>();
+    assert_eq!(rows, expected);
+    if i > 0 {
+      assert_eq!(offsets[i], offsets[i - 1] + counts[i - 1]);
+    }
+  }
+
+  // Uncompressed strips of 4 rows, the last one of 2.
+  let options = TiffOptions {
+    rows_per_strip: 4,
+    ..Default::default()
+  };
+  let bytes = write(&image, &options);
+  let tags = read_tags(&bytes);
+  assert_eq!(tag(&tags, 279), [4 * w, 4 * w, 2 * w]);
+  assert_eq!(
+    strips(&bytes, &tags),
+    samples.iter().map(|&v| v as u8).collect::<Vec<_>>()
+  );
+}
+
+#[test]
+fn unsupported_images() {
+  let options = TiffOptions::default();
+  let mut buf = Vec::new();
+  let image = test_image(1, 1, 17, false, &[&[0]]);
+  assert!(image.write_tiff(&options, &mut buf).is_err());
+
+  let mut image = test_image(1, 1, 8, false, &[&[0], &[0], &[0]]);
+  image.comps_mut().unwrap()[1].prec = 10;
+  assert!(image.write_tiff(&options, &mut buf).is_err());
+
+  image.comps_mut().unwrap()[1].prec = 8;
+  image.color_space = OPJ_CLRSPC_CMYK;
+  assert!(image.write_tiff(&options, &mut buf).is_err());
+  assert!(buf.is_empty());
+}
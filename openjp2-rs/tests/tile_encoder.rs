@@ -0,0 +1,101 @@
+use std::io::ErrorKind;
+
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+mod common;
+use common::*;
+
+const SIZE: u32 = 32;
+const TILE: u32 = 16;
+
+fn tile_samples(samples: &[i32], index: u32) -> Vec<i32> {
+  let (tx, ty) = ((index % 2) * TILE, (index / 2) * TILE);
+  let mut tile = Vec::new();
+  for y in ty..ty + TILE {
+    let row = (y * SIZE + tx) as usize;
+    tile.extend_from_slice(&samples[row..row + TILE as usize]);
+  }
+  tile
+}
+
+fn tiled_params() -> opj_cparameters_t {
+  let mut params = opj_cparameters_t::default();
+  params.tile_size_on = 1;
+  params.cp_tdx = TILE as i32;
+  params.cp_tdy = TILE as i32;
+  params.numresolution = 2;
+  params
+}
+
+#[test]
+fn encode_one_tile_at_a_time() {
+  let image = unsafe { Box::from_raw(grey_image(SIZE, SIZE)) };
+  let samples = image.comps().unwrap()[0].data().unwrap().to_vec();
+  let mut params = tiled_params();
+  let codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  let mut codec = codec.start_tiles().expect("started tiles");
+  assert_eq!(codec.tile_count(), 4);
+  assert_eq!(codec.tile_sample_counts(3), Some(vec![256]));
+
+  let err = codec.write_tile(1, &tile_samples(&samples, 1)).unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  let err = codec.write_tile(0, &[0; 10]).unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  assert_eq!(codec.next_tile(), 0);
+
+  for index in 0..4 {
+    codec
+      .write_tile(index, &tile_samples(&samples, index))
+      .expect("tile written");
+  }
+  let err = codec.write_tile(4, &[]).unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  let bytes = codec.finish_tiles().expect("encoded image").into_bytes();
+
+  let decoded = Jp2Codec::decoder(OPJ_CODEC_J2K, bytes)
+    .and_then(|codec| codec.read_header())
+    .and_then(|codec| codec.decode())
+    .expect("decoded image")
+    .into_image();
+  assert_eq!(decoded.comps().unwrap()[0].data().unwrap(), &samples[..]);
+}
+
+#[test]
+fn missing_tiles() {
+  let image = unsafe { Box::from_raw(grey_image(SIZE, SIZE)) };
+  let samples = image.comps().unwrap()[0].data().unwrap().to_vec();
+  let mut params = tiled_params();
+  let codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  let mut codec = codec.start_tiles().expect("started tiles");
+  codec
+    .write_tile(0, &tile_samples(&samples, 0))
+    .expect("tile written");
+  let err = codec.finish().unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn finish_with_tlm() {
+  let image = unsafe { Box::from_raw(grey_image(SIZE, SIZE)) };
+  let samples = image.comps().unwrap()[0].data().unwrap().to_vec();
+  let mut params = tiled_params();
+  let mut codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  assert!(codec.set_extra_options(&["TLM=YES"]));
+  let mut codec = codec.start_tiles().expect("started tiles");
+  for index in 0..4 {
+    codec
+      .write_tile(index, &tile_samples(&samples, index))
+      .expect("tile written");
+  }
+  let bytes = codec.finish().expect("encoded bytes");
+  assert!(bytes.ends_with(&[0xFF, 0xD9]));
+  assert!(find_marker(&bytes, 0xFF55).is_some());
+
+  let decoded = Jp2Codec::decoder(OPJ_CODEC_J2K, bytes)
+    .and_then(|codec| codec.read_header())
+    .and_then(|codec| codec.decode())
+    .expect("decoded image")
+    .into_image();
+  assert_eq!(decoded.comps().unwrap()[0].data().unwrap(), &samples[..]);
+}
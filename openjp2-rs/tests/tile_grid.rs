@@ -0,0 +1,73 @@
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+mod common;
+use common::*;
+
+/// An 80x70 image at (8, 4) on a grid of 32x32 tiles starting at the
+/// origin: 3x3 tiles, clamped to the image on all the edges.
+fn tiled_codestream() -> Vec<u8> {
+  let mut image = unsafe { Box::from_raw(grey_image(80, 70)) };
+  assert!(image.set_image_offset(8, 4));
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  params.tile_size_on = 1;
+  params.cp_tdx = 32;
+  params.cp_tdy = 32;
+  let codec = Jp2Codec::encoder(OPJ_CODEC_J2K, &mut params, image).expect("encoder");
+  codec.encode().expect("encoded image").into_bytes()
+}
+
+#[test]
+fn tile_grid_mapping() {
+  let codec = Jp2Codec::decoder(OPJ_CODEC_J2K, tiled_codestream()).expect("decoder");
+  // The grid is unknown before reading the header.
+  assert_eq!(codec.tile_at(8, 4), None);
+  assert_eq!(codec.tile_bounds(0), None);
+  let codec = codec.read_header().expect("valid header");
+
+  let expected = [
+    (8, 4, 32, 32),
+    (32, 4, 64, 32),
+    (64, 4, 88, 32),
+    (8, 32, 32, 64),
+    (32, 32, 64, 64),
+    (64, 32, 88, 64),
+    (8, 64, 32, 74),
+    (32, 64, 64, 74),
+    (64, 64, 88, 74),
+  ];
+  for (index, bounds) in expected.iter().enumerate() {
+    assert_eq!(codec.tile_bounds(index as u32), Some(*bounds));
+    let (x0, y0, x1, y1) = *bounds;
+    // Every corner of the tile maps back to it.
+    for (x, y) in [(x0, y0), (x1 - 1, y0), (x0, y1 - 1), (x1 - 1, y1 - 1)] {
+      assert_eq!(codec.tile_at(x, y), Some(index as u32));
+    }
+  }
+  assert_eq!(codec.tile_bounds(9), None);
+
+  // Outside of the image, including the parts of the edge tiles cut off.
+  assert_eq!(codec.tile_at(7, 10), None);
+  assert_eq!(codec.tile_at(10, 3), None);
+  assert_eq!(codec.tile_at(88, 10), None);
+  assert_eq!(codec.tile_at(10, 74), None);
+}
+
+#[test]
+fn tile_grid_c_api() {
+  let stream = read_stream(tiled_codestream());
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut dparams = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut dparams) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    assert_eq!(opj_get_tile_at(codec, 40, 70), Some(7));
+    assert_eq!(opj_get_tile_bounds(codec, 7), Some((32, 64, 64, 74)));
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+}
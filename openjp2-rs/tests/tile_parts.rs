@@ -0,0 +1,184 @@
+use openjp2::openjpeg::*;
+use openjp2::safe::Jp2Codec;
+
+mod common;
+use common::*;
+
+/// Encode 2 tiles of 2 tile-parts each, one per resolution.
+fn encode() -> (Vec<u8>, Vec<i32>) {
+  let image = grey_image(32, 16);
+  let samples = unsafe { (*image).comps().unwrap()[0].data().unwrap().to_vec() };
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  params.tile_size_on = 1;
+  params.cp_tdx = 16;
+  params.cp_tdy = 16;
+  params.tp_on = 1;
+  params.tp_flag = b'R' as _;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  (bytes, samples)
+}
+
+/// Offsets of the SOT markers, following the `Psot` lengths.
+fn sot_offsets(bytes: &[u8]) -> Vec<usize> {
+  let mut sot = find_marker(bytes, 0xFF90).expect("SOT marker");
+  let mut offsets = Vec::new();
+  while bytes[sot..sot + 2] == [0xFF, 0x90] {
+    offsets.push(sot);
+    let psot = u32::from_be_bytes([
+      bytes[sot + 6],
+      bytes[sot + 7],
+      bytes[sot + 8],
+      bytes[sot + 9],
+    ]);
+    sot += psot as usize;
+  }
+  offsets
+}
+
+/// Like some encoders, write a `TNsot` one too small in each tile-part, so
+/// the last tile-part of each tile has `TPsot == TNsot` (issue 254).
+fn mislabel_tile_parts(bytes: &mut [u8]) {
+  let offsets = sot_offsets(bytes);
+  for &sot in &offsets {
+    assert_eq!(bytes[sot + 11], 2);
+    bytes[sot + 11] = 1;
+  }
+  assert_eq!(offsets.len(), 4);
+}
+
+/// Decode `bytes`, returns the samples, or `None`, and the messages.
+fn decode_tile_parts(bytes: Vec<u8>, correction: bool) -> (Option<Vec<i32>>, Vec<String>) {
+  decode_area(bytes, correction, None)
+}
+
+fn decode_area(
+  bytes: Vec<u8>,
+  correction: bool,
+  area: Option<(i32, i32, i32, i32)>,
+) -> (Option<Vec<i32>>, Vec<String>) {
+  let mut decoder = TestDecoder::new(OPJ_CODEC_J2K, bytes);
+  assert!(unsafe { opj_decoder_set_tile_parts_correction(decoder.codec, correction) } != 0);
+  let decoded = decoder.decode_area(area).map(|image| {
+    let decoded = samples(image).remove(0);
+    opj_image_destroy(image);
+    decoded
+  });
+  (decoded, decoder.msgs().to_vec())
+}
+
+#[test]
+fn correct_mislabeled_tile_parts() {
+  let (mut bytes, samples) = encode();
+  mislabel_tile_parts(&mut bytes);
+  let (decoded, msgs) = decode_tile_parts(bytes, true);
+  assert_eq!(decoded, Some(samples));
+  assert!(msgs
+    .iter()
+    .any(|msg| msg.contains("Non conformant codestream TPsot==TNsot")));
+}
+
+#[test]
+fn trust_mislabeled_tile_parts() {
+  let (mut bytes, _) = encode();
+  mislabel_tile_parts(&mut bytes);
+  let (decoded, msgs) = decode_tile_parts(bytes.clone(), false);
+  assert_eq!(decoded, None);
+  assert!(msgs
+    .iter()
+    .any(|msg| msg.contains("TPSot (1) is not valid")));
+  assert!(!msgs.iter().any(|msg| msg.contains("TPsot==TNsot")));
+
+  let mut codec = Jp2Codec::decoder(OPJ_CODEC_J2K, bytes).expect("decoder");
+  assert!(codec.set_tile_parts_correction(false));
+  assert!(codec
+    .read_header()
+    .and_then(|codec| codec.decode())
+    .is_none());
+}
+
+#[test]
+fn well_formed_tile_parts() {
+  let (bytes, samples) = encode();
+  for correction in [true, false] {
+    let (decoded, msgs) = decode_tile_parts(bytes.clone(), correction);
+    assert_eq!(decoded.as_ref(), Some(&samples));
+    assert!(!msgs.iter().any(|msg| msg.contains("TPsot==TNsot")));
+  }
+}
+
+fn set_psot(bytes: &mut [u8], sot: usize, psot: u32) {
+  bytes[sot + 6..sot + 10].copy_from_slice(&psot.to_be_bytes());
+}
+
+#[test]
+fn zero_psot_final_tile_part() {
+  let (mut bytes, samples) = encode();
+  let last = *sot_offsets(&bytes).last().unwrap();
+  set_psot(&mut bytes, last, 0);
+  let (decoded, msgs) = decode_tile_parts(bytes.clone(), true);
+  assert_eq!(decoded, Some(samples.clone()));
+  assert!(!msgs.iter().any(|msg| msg.contains("last tile-part")));
+
+  // The tile-part with Psot=0 is skipped up to the EOC marker.
+  let (decoded, msgs) = decode_area(bytes, true, Some((0, 0, 16, 16)));
+  let tile0 = samples
+    .chunks_exact(32)
+    .flat_map(|row| row[..16].iter().copied())
+    .collect::<Vec<_>>();
+  assert_eq!(decoded, Some(tile0), "{:?}", msgs);
+}
+
+#[test]
+fn zero_psot_before_other_tile_parts() {
+  let (mut bytes, _) = encode();
+  let offsets = sot_offsets(&bytes);
+  set_psot(&mut bytes, offsets[1], 0);
+  let (decoded, msgs) = decode_tile_parts(bytes, true);
+  assert_eq!(decoded, None);
+  assert!(msgs.iter().any(|msg| msg.contains(
+    "Psot=0 in tile-part 1 of tile 0, but it isn't the last tile-part of the codestream"
+  )));
+}
+
+/// Like kakadu, end the first tile with an empty tile-part: a SOT marker
+/// segment with Psot=12, without SOD marker.
+#[test]
+fn empty_tile_part() {
+  let (mut bytes, samples) = encode();
+  let offsets = sot_offsets(&bytes);
+  for &sot in &offsets[..2] {
+    bytes[sot + 11] = 3;
+  }
+  let empty = [0xFF, 0x90, 0, 10, 0, 0, 0, 0, 0, 12, 2, 3];
+  let bytes = [&bytes[..offsets[2]], &empty, &bytes[offsets[2]..]].concat();
+  assert_eq!(sot_offsets(&bytes).len(), 5);
+  for correction in [true, false] {
+    let (decoded, msgs) = decode_tile_parts(bytes.clone(), correction);
+    assert_eq!(decoded.as_ref(), Some(&samples), "{:?}", msgs);
+    assert!(msgs
+      .iter()
+      .any(|msg| msg.contains("Empty SOT marker detected: Psot=12.")));
+  }
+
+  // The empty tile-part can also end the codestream.
+  let (mut bytes, samples) = encode();
+  let offsets = sot_offsets(&bytes);
+  for &sot in &offsets[2..] {
+    bytes[sot + 11] = 3;
+  }
+  let empty = [0xFF, 0x90, 0, 10, 0, 1, 0, 0, 0, 12, 2, 3];
+  let eoc = bytes.len() - 2;
+  let bytes = [&bytes[..eoc], &empty, &bytes[eoc..]].concat();
+  let (decoded, msgs) = decode_tile_parts(bytes, true);
+  assert_eq!(decoded, Some(samples), "{:?}", msgs);
+}
@@ -0,0 +1,134 @@
+use std::convert::TryInto;
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(format: CODEC_FORMAT, tiled: bool) -> Vec<u8> {
+  let image = grey_image(32, 32);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  if tiled {
+    params.tile_size_on = 1;
+    params.cp_tdx = 16;
+    params.cp_tdy = 16;
+  }
+  let codec = unsafe { opj_create_compress(format) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+#[test]
+fn valid_structures() {
+  for tiled in [false, true] {
+    assert_eq!(
+      opj_validate_structure(&encode(OPJ_CODEC_J2K, tiled)),
+      Ok(())
+    );
+    assert_eq!(
+      opj_validate_structure(&encode(OPJ_CODEC_JP2, tiled)),
+      Ok(())
+    );
+  }
+}
+
+#[test]
+fn truncated_data() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    let bytes = encode(format, true);
+    for len in [0, 1, 10, 40, bytes.len() / 2, bytes.len() - 1] {
+      assert!(
+        opj_validate_structure(&bytes[..len]).is_err(),
+        "truncated at {}",
+        len
+      );
+    }
+  }
+  assert!(opj_validate_structure(b"not a JPEG 2000 file").is_err());
+}
+
+#[test]
+fn invalid_lengths() {
+  let bytes = encode(OPJ_CODEC_J2K, true);
+
+  // COD marker segment overrunning the data.
+  let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+  let mut corrupted = bytes.clone();
+  corrupted[cod + 2..cod + 4].copy_from_slice(&[0xFF, 0xFF]);
+  let err = opj_validate_structure(&corrupted).unwrap_err();
+  assert_eq!(err.offset, cod);
+
+  // Tile-part length overrunning the data.
+  let sot = find_marker(&bytes, 0xFF90).expect("SOT marker");
+  let mut corrupted = bytes.clone();
+  corrupted[sot + 6..sot + 10].copy_from_slice(&u32::MAX.to_be_bytes());
+  assert_eq!(opj_validate_structure(&corrupted).unwrap_err().offset, sot);
+
+  // Tile index out of the 2x2 tile grid.
+  let mut corrupted = bytes.clone();
+  corrupted[sot + 4..sot + 6].copy_from_slice(&4u16.to_be_bytes());
+  assert_eq!(opj_validate_structure(&corrupted).unwrap_err().offset, sot);
+
+  // Missing QCD marker: replaced by a COM marker of the same length.
+  let qcd = find_marker(&bytes, 0xFF5C).expect("QCD marker");
+  let mut corrupted = bytes;
+  corrupted[qcd + 1] = 0x64;
+  assert!(opj_validate_structure(&corrupted).is_err());
+}
+
+#[test]
+fn jp2_box_order() {
+  let bytes = encode(OPJ_CODEC_JP2, false);
+  let jp2h = bytes.windows(4).position(|w| w == b"jp2h").unwrap() - 4;
+  let jp2h_len = u32::from_be_bytes(bytes[jp2h..jp2h + 4].try_into().unwrap()) as usize;
+
+  // The codestream box before the JP2 header box.
+  let mut reordered = bytes[..jp2h].to_vec();
+  reordered.extend_from_slice(&bytes[jp2h + jp2h_len..]);
+  reordered.extend_from_slice(&bytes[jp2h..jp2h + jp2h_len]);
+  assert!(opj_validate_structure(&reordered).is_err());
+
+  // Box length overrunning the data.
+  let mut corrupted = bytes;
+  corrupted[jp2h..jp2h + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+  assert_eq!(opj_validate_structure(&corrupted).unwrap_err().offset, jp2h);
+}
+
+#[test]
+fn explain_unsupported() {
+  for format in [OPJ_CODEC_J2K, OPJ_CODEC_JP2] {
+    assert_eq!(
+      opj_explain_unsupported(&encode(format, true)),
+      Vec::<String>::new()
+    );
+  }
+
+  // Insert an EPC marker segment before the COD marker.
+  let bytes = encode(OPJ_CODEC_J2K, false);
+  let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+  let epc = [0xFF, 0x68, 0x00, 0x04, 0x00, 0x00];
+  let mut bytes = [&bytes[..cod], &epc, &bytes[cod..]].concat();
+  let reasons = opj_explain_unsupported(&bytes);
+  assert_eq!(reasons.len(), 1, "{:?}", reasons);
+  assert!(reasons[0].contains("EPC"), "{}", reasons[0]);
+  assert!(reasons[0].contains("jpwl feature"), "{}", reasons[0]);
+
+  // Mixed HT code-block style.
+  let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+  bytes[cod + 12] |= 0x80;
+  assert_eq!(opj_explain_unsupported(&bytes).len(), 2);
+
+  assert_eq!(
+    opj_explain_unsupported(b"not a JPEG 2000 file"),
+    ["Neither a JP2 file nor a J2K codestream at offset 0"]
+  );
+}
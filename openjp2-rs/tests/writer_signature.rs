@@ -0,0 +1,105 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode(signature: Option<Option<&str>>) -> Vec<u8> {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    if let Some(signature) = signature {
+      assert!(opj_encoder_set_writer_signature(codec, signature) != 0);
+    }
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+fn comments(bytes: Vec<u8>) -> Vec<Comment> {
+  let stream = read_stream(bytes);
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  let comments = unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    opj_get_comments(codec)
+  };
+  unsafe {
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+  comments
+}
+
+#[test]
+fn default_signature() {
+  let bytes = encode(None);
+  assert!(find_marker(&bytes, 0xFF64).is_some());
+  assert_eq!(
+    comments(bytes),
+    [Comment::Text(format!(
+      "Created by OpenJPEG version {}",
+      OPJ_VERSION
+    ))]
+  );
+}
+
+#[test]
+fn custom_signature() {
+  let bytes = encode(Some(Some("Encoded by café €5")));
+  assert_eq!(
+    comments(bytes),
+    [Comment::Text("Encoded by café €5".into())]
+  );
+}
+
+#[test]
+fn no_signature() {
+  let bytes = encode(Some(None));
+  assert_eq!(find_marker(&bytes, 0xFF64), None);
+  assert!(comments(bytes.clone()).is_empty());
+  // Without the version stamp the codestream is reproducible.
+  assert_eq!(bytes, encode(Some(None)));
+  // Only the COM marker is dropped.
+  let with_signature = encode(None);
+  let com = find_marker(&with_signature, 0xFF64).unwrap();
+  let lcom = u16::from_be_bytes([with_signature[com + 2], with_signature[com + 3]]) as usize;
+  assert_eq!(bytes.len(), with_signature.len() - 2 - lcom);
+}
+
+#[test]
+fn invalid_signature() {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    // Before the encoder setup.
+    assert_eq!(opj_encoder_set_writer_signature(codec, None), 0);
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    // Not Latin-9 text.
+    assert_eq!(opj_encoder_set_writer_signature(codec, Some("日本")), 0);
+    assert_eq!(opj_encoder_set_writer_signature(codec, Some("a\0b")), 0);
+    let long = "x".repeat(65532);
+    assert_eq!(opj_encoder_set_writer_signature(codec, Some(&long)), 0);
+    assert!(opj_encoder_set_writer_signature(codec, Some(&long[1..])) != 0);
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  unsafe {
+    assert_eq!(opj_encoder_set_writer_signature(codec, None), 0);
+    opj_destroy_codec(codec);
+  }
+}
@@ -0,0 +1,157 @@
+use std::ffi::{c_void, CString};
+
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+fn encode_jp2(options: &[&str]) -> Vec<u8> {
+  let image = grey_image(16, 16);
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 2;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_JP2) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+    let options: Vec<CString> = options.iter().map(|o| CString::new(*o).unwrap()).collect();
+    let mut ptrs: Vec<_> = options.iter().map(|o| o.as_ptr()).collect();
+    ptrs.push(std::ptr::null());
+    assert!(opj_encoder_set_extra_options(codec, ptrs.as_ptr()) != 0);
+  }
+  let bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  bytes
+}
+
+/// Offset of the box of type `ty`, from its LBox.
+fn find_box(bytes: &[u8], ty: &[u8; 4]) -> usize {
+  bytes.windows(4).position(|w| w == ty).expect("box") - 4
+}
+
+fn decode_stream(stream: *mut opj_stream_t) -> Vec<i32> {
+  let image = TestDecoder::from_stream(OPJ_CODEC_JP2, stream)
+    .decode()
+    .expect("decoded image");
+  let samples = samples(image).remove(0);
+  opj_image_destroy(image);
+  samples
+}
+
+fn grey_samples() -> Vec<i32> {
+  (0..256).collect()
+}
+
+#[test]
+fn codestream_box_with_xlbox() {
+  let bytes = encode_jp2(&["JP2C_XLBOX=YES"]);
+  let jp2c = find_box(&bytes, b"jp2c");
+  assert_eq!(&bytes[jp2c..jp2c + 4], &[0, 0, 0, 1]);
+  let mut xl_length = [0; 8];
+  xl_length.copy_from_slice(&bytes[jp2c + 8..jp2c + 16]);
+  assert_eq!(u64::from_be_bytes(xl_length), (bytes.len() - jp2c) as u64);
+  assert_eq!(&bytes[jp2c + 16..jp2c + 18], &[0xFF, 0x4F]);
+
+  assert_eq!(decode_stream(read_stream(bytes)), grey_samples());
+}
+
+#[test]
+fn small_codestream_box() {
+  let bytes = encode_jp2(&[]);
+  let jp2c = find_box(&bytes, b"jp2c");
+  assert_eq!(
+    &bytes[jp2c..jp2c + 4],
+    &((bytes.len() - jp2c) as u32).to_be_bytes()
+  );
+  assert_eq!(&bytes[jp2c + 8..jp2c + 10], &[0xFF, 0x4F]);
+}
+
+/// A file whose `gap` bytes after `prefix` are zeros that aren't stored.
+struct SparseFile {
+  prefix: Vec<u8>,
+  gap: u64,
+  suffix: Vec<u8>,
+  pos: u64,
+}
+
+impl SparseFile {
+  fn len(&self) -> u64 {
+    self.prefix.len() as u64 + self.gap + self.suffix.len() as u64
+  }
+
+  fn byte(&self, pos: u64) -> u8 {
+    let prefix = self.prefix.len() as u64;
+    if pos < prefix {
+      self.prefix[pos as usize]
+    } else if pos < prefix + self.gap {
+      0
+    } else {
+      self.suffix[(pos - prefix - self.gap) as usize]
+    }
+  }
+}
+
+extern "C" fn sparse_read(p_buffer: *mut c_void, nb_bytes: usize, p_data: *mut c_void) -> usize {
+  let file = unsafe { &mut *(p_data as *mut SparseFile) };
+  let buf = unsafe { std::slice::from_raw_parts_mut(p_buffer as *mut u8, nb_bytes) };
+  let count = (file.len().saturating_sub(file.pos)).min(nb_bytes as u64) as usize;
+  if count == 0 {
+    return usize::MAX;
+  }
+  for (i, b) in buf[..count].iter_mut().enumerate() {
+    *b = file.byte(file.pos + i as u64);
+  }
+  file.pos += count as u64;
+  count
+}
+
+extern "C" fn sparse_skip(nb_bytes: i64, p_data: *mut c_void) -> i64 {
+  let file = unsafe { &mut *(p_data as *mut SparseFile) };
+  file.pos = (file.pos as i64 + nb_bytes) as u64;
+  nb_bytes
+}
+
+extern "C" fn sparse_seek(nb_bytes: i64, p_data: *mut c_void) -> i32 {
+  let file = unsafe { &mut *(p_data as *mut SparseFile) };
+  file.pos = nb_bytes as u64;
+  1
+}
+
+extern "C" fn sparse_free(p_data: *mut c_void) {
+  drop(unsafe { Box::from_raw(p_data as *mut SparseFile) })
+}
+
+#[test]
+fn read_after_a_box_larger_than_4gb() {
+  let bytes = encode_jp2(&["JP2C_XLBOX=YES"]);
+  let jp2c = find_box(&bytes, b"jp2c");
+  // A free box of 5 GiB with an XLBox, before the codestream box.
+  let gap = 5u64 << 30;
+  let mut prefix = bytes[..jp2c].to_vec();
+  prefix.extend_from_slice(&1u32.to_be_bytes());
+  prefix.extend_from_slice(b"free");
+  prefix.extend_from_slice(&(16 + gap).to_be_bytes());
+  let file = Box::new(SparseFile {
+    prefix,
+    gap,
+    suffix: bytes[jp2c..].to_vec(),
+    pos: 0,
+  });
+  let len = file.len();
+  let stream = unsafe {
+    let stream = opj_stream_default_create(1);
+    opj_stream_set_read_function(stream, Some(sparse_read));
+    opj_stream_set_skip_function(stream, Some(sparse_skip));
+    opj_stream_set_seek_function(stream, Some(sparse_seek));
+    opj_stream_set_user_data_length(stream, len);
+    opj_stream_set_user_data(
+      stream,
+      Box::into_raw(file) as *mut c_void,
+      Some(sparse_free as unsafe extern "C" fn(*mut c_void)),
+    );
+    stream
+  };
+  assert_eq!(decode_stream(stream), grey_samples());
+}
@@ -0,0 +1,61 @@
+use openjp2::openjpeg::*;
+
+mod common;
+use common::*;
+
+/// Encode a 1x1 image at (1, 1) on the reference grid, then claim 5
+/// decomposition levels in the COD marker so that it can be reduced.
+fn crafted_codestream() -> Vec<u8> {
+  let image = grey_image(1, 1);
+  unsafe {
+    assert!((*image).set_image_offset(1, 1));
+  }
+  let mut params = opj_cparameters_t::default();
+  params.numresolution = 1;
+  let codec = unsafe { opj_create_compress(OPJ_CODEC_J2K) };
+  unsafe {
+    opj_set_error_handler(codec, Some(log_error), std::ptr::null_mut());
+    assert!(opj_setup_encoder(codec, &mut params, image) != 0);
+  }
+  let mut bytes = encode_to_vec(codec, image);
+  unsafe {
+    opj_destroy_codec(codec);
+  }
+  opj_image_destroy(image);
+  let cod = find_marker(&bytes, 0xFF52).expect("COD marker");
+  assert_eq!(bytes[cod + 9], 0);
+  bytes[cod + 9] = 5;
+  bytes
+}
+
+/// The safe accessors of an image whose components are empty.
+fn check_empty(image: &opj_image) {
+  let comp = &image.comps().unwrap()[0];
+  assert_eq!((comp.w, comp.h), (0, 0));
+  assert_eq!(comp.data(), Some(&[][..]));
+  assert!(image.components().all(|view| view.data.is_empty()));
+  assert_eq!(image.as_rgba8(), Some(Vec::new()));
+}
+
+#[test]
+fn reduced_1x1_image() {
+  let stream = read_stream(crafted_codestream());
+  let codec = opj_create_decompress(OPJ_CODEC_J2K);
+  let mut params = opj_dparameters_t::default();
+  params.cp_reduce = 5;
+  let mut image = std::ptr::null_mut() as *mut opj_image_t;
+  unsafe {
+    assert!(opj_setup_decoder(codec, &mut params) != 0);
+    assert!(opj_read_header(stream, codec, &mut image) != 0);
+    // Applies the reduction to the dimensions of the components.
+    assert!(opj_set_decode_area(codec, image, 0, 0, 0, 0) != 0);
+    check_empty(&*image);
+    // The crafted code-blocks may or may not decode, but without panicking.
+    if opj_decode(codec, stream, image) != 0 {
+      check_empty(&*image);
+    }
+    opj_destroy_codec(codec);
+    opj_stream_destroy(stream);
+  }
+  opj_image_destroy(image);
+}